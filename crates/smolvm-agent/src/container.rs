@@ -13,7 +13,7 @@
 //! - Enable future schema migrations
 //! - Track when state was last modified
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File, OpenOptions};
 use std::io::Write;
 use std::os::unix::io::AsRawFd;
@@ -22,12 +22,16 @@ use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use smolvm_protocol::{
+    ContainerStatus, ExitReason, HealthCheck, HealthStatus, RestartPolicy, SecurityOptions,
+};
 use tracing::{debug, info, warn};
 
 use crate::crun::CrunCommand;
 use crate::oci::{generate_container_id, OciSpec};
 use crate::paths;
-use crate::process::{wait_with_timeout, WaitResult, TIMEOUT_EXIT_CODE};
+use crate::process::{oom_kill_count, wait_with_timeout, WaitResult, TIMEOUT_EXIT_CODE};
+use crate::restart::MainProcess;
 use crate::storage;
 
 /// Error type for container operations (reuses storage error).
@@ -208,6 +212,36 @@ pub struct ContainerInfo {
     pub created_at: u64,
     /// Command the container is running.
     pub command: Vec<String>,
+    /// User labels set at creation.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Whether the container is restarted when its main process exits.
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+    /// How many times the container has been restarted.
+    #[serde(default)]
+    pub restart_count: u32,
+    /// When the main process last started (Unix epoch seconds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<u64>,
+    /// When the main process last exited (Unix epoch seconds), if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    /// Exit code of the main process's last run, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Why the last start failed, until the container starts again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Command run periodically to check the container is working.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub healthcheck: Option<HealthCheck>,
+    /// Outcome of the health checks so far this run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<HealthStatus>,
+    /// Health checks failed in a row.
+    #[serde(default)]
+    pub health_failures: u32,
 
     /// Path to the container PID file.
     #[serde(default, skip_serializing_if = "Option::is_none")]
@@ -223,6 +257,43 @@ pub struct ContainerInfo {
     pub attach_socket: Option<PathBuf>,
 }
 
+impl ContainerInfo {
+    /// Move the container to `state` at `now`, recording when its main
+    /// process started or finished.
+    fn transition(&mut self, state: ContainerState, now: u64) {
+        match state {
+            ContainerState::Running if self.state != ContainerState::Running => {
+                self.started_at = Some(now);
+                self.finished_at = None;
+                self.exit_code = None;
+                self.error = None;
+                self.health = self.healthcheck.as_ref().map(|_| HealthStatus::Starting);
+                self.health_failures = 0;
+            }
+            ContainerState::Stopped if self.state != ContainerState::Stopped => {
+                self.finished_at = Some(now);
+                self.health = None;
+            }
+            _ => {}
+        }
+        self.state = state;
+    }
+
+    /// The container's lifecycle status, as reported to the host.
+    pub fn status(&self) -> ContainerStatus {
+        match (&self.error, self.state) {
+            (_, ContainerState::Running) => ContainerStatus::Running,
+            (Some(message), _) => ContainerStatus::Error {
+                message: message.clone(),
+            },
+            (None, ContainerState::Created) => ContainerStatus::Created,
+            (None, ContainerState::Stopped) => ContainerStatus::Exited {
+                code: self.exit_code,
+            },
+        }
+    }
+}
+
 /// Global container registry.
 pub struct ContainerRegistry {
     containers: RwLock<HashMap<String, ContainerInfo>>,
@@ -257,7 +328,6 @@ impl ContainerRegistry {
     }
 
     /// Get a container by ID.
-    #[allow(dead_code)] // Used in tests
     pub fn get(&self, id: &str) -> Option<ContainerInfo> {
         let containers = self.containers.read();
         containers.get(id).cloned()
@@ -267,11 +337,73 @@ impl ContainerRegistry {
     pub fn update_state(&self, id: &str, state: ContainerState) {
         let mut containers = self.containers.write();
         if let Some(info) = containers.get_mut(id) {
-            info.state = state;
+            info.transition(state, current_timestamp());
             debug!(container_id = %id, state = %state, "updated container state");
         }
     }
 
+    /// Record that a container's main process exited with `exit_code`.
+    pub fn record_exit(&self, id: &str, exit_code: Option<i32>) {
+        let mut containers = self.containers.write();
+        if let Some(info) = containers.get_mut(id) {
+            info.transition(ContainerState::Stopped, current_timestamp());
+            info.exit_code = exit_code;
+            debug!(container_id = %id, exit_code = ?exit_code, "recorded container exit");
+        }
+    }
+
+    /// Record that a container failed to start.
+    pub fn record_error(&self, id: &str, message: String) {
+        let mut containers = self.containers.write();
+        if let Some(info) = containers.get_mut(id) {
+            debug!(container_id = %id, error = %message, "recorded container start failure");
+            info.error = Some(message);
+        }
+    }
+
+    /// Record whether a running container passed a health check run
+    /// against its run started at `started_at`. A result for an earlier run,
+    /// which has since exited or been restarted, is dropped. Returns the
+    /// container's health if this changed it.
+    pub fn record_health(
+        &self,
+        id: &str,
+        started_at: Option<u64>,
+        passed: bool,
+    ) -> Option<HealthStatus> {
+        let mut containers = self.containers.write();
+        let info = containers.get_mut(id)?;
+        let retries = info.healthcheck.as_ref()?.retries;
+        if info.state != ContainerState::Running || info.started_at != started_at {
+            return None;
+        }
+        let health = if passed {
+            info.health_failures = 0;
+            HealthStatus::Healthy
+        } else {
+            info.health_failures += 1;
+            if info.health_failures < retries {
+                return None;
+            }
+            HealthStatus::Unhealthy
+        };
+        debug!(container_id = %id, passed, failures = info.health_failures, "recorded health check");
+        if info.health == Some(health) {
+            return None;
+        }
+        info.health = Some(health);
+        Some(health)
+    }
+
+    /// Count a restart of a container.
+    pub fn record_restart(&self, id: &str) {
+        let mut containers = self.containers.write();
+        if let Some(info) = containers.get_mut(id) {
+            info.restart_count += 1;
+            debug!(container_id = %id, restart_count = info.restart_count, "recorded container restart");
+        }
+    }
+
     /// List all containers.
     pub fn list(&self) -> Vec<ContainerInfo> {
         let containers = self.containers.read();
@@ -508,9 +640,13 @@ impl ContainerRegistry {
         // Apply updates
         {
             let mut containers = self.containers.write();
+            let now = current_timestamp();
             for (id, state) in to_update {
                 if let Some(info) = containers.get_mut(&id) {
-                    info.state = state;
+                    if state == ContainerState::Stopped {
+                        observe_exit(info);
+                    }
+                    info.transition(state, now);
                 }
             }
             for id in to_remove {
@@ -543,6 +679,9 @@ pub struct ExecResult {
     pub exit_code: i32,
     pub stdout: String,
     pub stderr: String,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    pub reason: ExitReason,
 }
 
 /// Validate container creation parameters.
@@ -604,20 +743,48 @@ fn validate_exec_params(command: &[String]) -> Result<(), StorageError> {
     Ok(())
 }
 
+/// How the agent looks after a container once it is running.
+#[derive(Debug, Clone, Default)]
+pub struct ContainerOptions {
+    /// Whether the [restart supervisor](crate::restart) restarts it when it
+    /// exits.
+    pub restart_policy: RestartPolicy,
+    /// User labels stored with it.
+    pub labels: BTreeMap<String, String>,
+    /// Command the [health monitor](crate::health) runs to check it.
+    pub healthcheck: Option<HealthCheck>,
+}
+
 /// Create a long-running container and start it immediately.
 ///
 /// This creates the overlay, OCI bundle, and calls `crun run --detach`.
-/// The container starts running immediately in the background.
+/// The container starts running immediately in the background, and is then
+/// looked after as `options` say.
 pub fn create_container(
     image: &str,
     command: &[String],
     env: &[(String, String)],
     workdir: Option<&str>,
     mounts: &[(String, String, bool)],
+    options: ContainerOptions,
 ) -> Result<ContainerInfo, StorageError> {
+    let ContainerOptions {
+        restart_policy,
+        labels,
+        healthcheck,
+    } = options;
+
     // Validate inputs before proceeding
     validate_container_params(image, command, workdir)?;
     validate_env_vars(env)?;
+    if let Some(check) = &healthcheck {
+        check
+            .validate()
+            .map_err(|reason| StorageError::ValidationFailed {
+                context: "healthcheck".into(),
+                reason,
+            })?;
+    }
 
     // Generate unique container ID
     let container_id = generate_container_id();
@@ -640,7 +807,7 @@ pub fn create_container(
 
     // Add bind mounts for virtiofs volumes
     for (tag, container_path, read_only) in mounts {
-        let virtiofs_mount = Path::new(paths::VIRTIOFS_MOUNT_ROOT).join(tag);
+        let virtiofs_mount = storage::mount_source_path(tag)?;
         spec.add_bind_mount(
             &virtiofs_mount.to_string_lossy(),
             container_path,
@@ -742,10 +909,7 @@ pub fn create_container(
     }
 
     // Get current timestamp
-    let created_at = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
+    let created_at = current_timestamp();
 
     let info = ContainerInfo {
         id: container_id,
@@ -754,6 +918,16 @@ pub fn create_container(
         state: ContainerState::Created, // Container is created but NOT running
         created_at,
         command: command.to_vec(),
+        labels,
+        restart_policy,
+        restart_count: 0,
+        started_at: None,
+        finished_at: None,
+        exit_code: None,
+        error: None,
+        healthcheck,
+        health: None,
+        health_failures: 0,
         // Runtime state fields (populated when container is started)
         pid_file: None,
         exit_file: None,
@@ -774,12 +948,26 @@ pub fn create_container(
 ///
 /// This calls `crun start` to start the container.
 /// For stopped containers, it cleans up stale state and recreates before starting.
+/// A failed start leaves the container in the error status until it next
+/// starts.
 pub fn start_container(container_id: &str) -> Result<(), StorageError> {
     // Find container
     let info = REGISTRY
         .find_by_prefix(container_id)
         .ok_or_else(|| StorageError::new(format!("container not found: {}", container_id)))?;
 
+    let result = start_registered(&info);
+    if let Err(e) = &result {
+        REGISTRY.record_error(&info.id, e.to_string());
+        if let Err(e) = REGISTRY.persist() {
+            warn!(error = %e, "failed to persist registry after failed start");
+        }
+    }
+    result
+}
+
+/// Start `info`, a container in the registry.
+fn start_registered(info: &ContainerInfo) -> Result<(), StorageError> {
     // Check actual state from crun
     if let Ok(state) = get_crun_state(&info.id) {
         if state == "running" {
@@ -808,7 +996,9 @@ pub fn start_container(container_id: &str) -> Result<(), StorageError> {
                 .map_err(|e| StorageError::new(format!("failed to wait for crun start: {}", e)))?;
 
             match result {
-                WaitResult::Completed { exit_code, output } => {
+                WaitResult::Completed {
+                    exit_code, output, ..
+                } => {
                     if exit_code != 0 {
                         warn!(
                             container_id = %info.id,
@@ -985,12 +1175,17 @@ pub fn start_container(container_id: &str) -> Result<(), StorageError> {
 }
 
 /// Execute a command in a running container.
+///
+/// Non-empty `security` restricts only the exec'd process, and `user`
+/// overrides the container's user for it.
 pub fn exec_in_container(
     container_id: &str,
     command: &[String],
     env: &[(String, String)],
     workdir: Option<&str>,
     timeout_ms: Option<u64>,
+    security: &SecurityOptions,
+    user: Option<&str>,
 ) -> Result<ExecResult, StorageError> {
     // Validate inputs
     validate_exec_params(command)?;
@@ -1016,22 +1211,102 @@ pub fn exec_in_container(
         "executing command in container"
     );
 
-    let mut child = CrunCommand::exec(&info.id, env, command, workdir, false)
+    let oom_kills_before = oom_kill_count();
+    let (crun, _process_file) = crun_exec(&info, command, env, workdir, false, security, user)?;
+    let mut child = crun
         .capture_output()
         .spawn()
         .map_err(|e| StorageError::new(format!("failed to spawn crun exec: {}", e)))?;
 
     let result = wait_with_timeout(&mut child, timeout_ms, None)?;
-    convert_wait_result_to_exec(&info.id, result)
+    convert_wait_result_to_exec(&info.id, result, oom_kills_before)
+}
+
+/// Build the `crun exec` command for `command` in container `info`.
+///
+/// With non-empty `security` or a `user` the process is described in a
+/// `--process` file derived from the container's own config, since crun's
+/// exec flags can add capabilities but not drop them, and can't set `HOME`
+/// to match the user. The returned file must be kept until crun has started
+/// the process.
+fn crun_exec(
+    info: &ContainerInfo,
+    command: &[String],
+    env: &[(String, String)],
+    workdir: Option<&str>,
+    tty: bool,
+    security: &SecurityOptions,
+    user: Option<&str>,
+) -> Result<(CrunCommand, Option<tempfile::TempPath>), StorageError> {
+    if security.is_empty() && user.is_none() {
+        return Ok((
+            CrunCommand::exec(&info.id, env, command, workdir, tty),
+            None,
+        ));
+    }
+    if security.seccomp_allow.is_some() {
+        return Err(StorageError::ValidationFailed {
+            context: "exec".into(),
+            reason: "a seccomp profile applies to the whole container and can't be set \
+                     for a single exec"
+                .into(),
+        });
+    }
+
+    let config_path = info.bundle_path.join("config.json");
+    let config = fs::read(&config_path)
+        .map_err(|e| StorageError::read_error(config_path.display().to_string(), e))?;
+    let spec: OciSpec = serde_json::from_slice(&config)
+        .map_err(|e| StorageError::parse_error("container config.json", e))?;
+
+    let mut process = spec.process;
+    if let Some(user) = user {
+        let rootfs = info.bundle_path.join(&spec.root.path);
+        process.set_user(&crate::user::resolve(user, &rootfs)?);
+    }
+    process.args = command.to_vec();
+    process.terminal = tty;
+    for (key, value) in env {
+        let prefix = format!("{}=", key);
+        process.env.retain(|var| !var.starts_with(&prefix));
+        process.env.push(format!("{}={}", key, value));
+    }
+    if let Some(wd) = workdir {
+        process.cwd = wd.to_string();
+    }
+    process
+        .restrict(security)
+        .map_err(|reason| StorageError::ValidationFailed {
+            context: "security options".into(),
+            reason,
+        })?;
+
+    let mut file = tempfile::Builder::new()
+        .prefix("exec-")
+        .suffix(".json")
+        .tempfile_in(&info.bundle_path)
+        .map_err(|e| StorageError::write_error(info.bundle_path.display().to_string(), e))?;
+    serde_json::to_writer(&mut file, &process)
+        .map_err(|e| StorageError::write_error(file.path().display().to_string(), e))?;
+    let process_file = file.into_temp_path();
+
+    Ok((
+        CrunCommand::exec_process(&info.id, &process_file),
+        Some(process_file),
+    ))
 }
 
 /// Convert WaitResult to ExecResult.
 fn convert_wait_result_to_exec(
     container_id: &str,
     result: WaitResult,
+    oom_kills_before: Option<u64>,
 ) -> Result<ExecResult, StorageError> {
+    let reason = result.exit_reason(oom_kills_before);
     match result {
-        WaitResult::Completed { exit_code, output } => {
+        WaitResult::Completed {
+            exit_code, output, ..
+        } => {
             debug!(
                 container_id = %container_id,
                 exit_code = exit_code,
@@ -1041,6 +1316,9 @@ fn convert_wait_result_to_exec(
                 exit_code,
                 stdout: output.stdout,
                 stderr: output.stderr,
+                stdout_truncated: output.stdout_truncated,
+                stderr_truncated: output.stderr_truncated,
+                reason,
             })
         }
         WaitResult::TimedOut { output, timeout_ms } => {
@@ -1048,7 +1326,10 @@ fn convert_wait_result_to_exec(
             Ok(ExecResult {
                 exit_code: TIMEOUT_EXIT_CODE,
                 stdout: output.stdout,
+                stdout_truncated: output.stdout_truncated,
+                stderr_truncated: output.stderr_truncated,
                 stderr: format!("{}\nexec timed out after {}ms", output.stderr, timeout_ms),
+                reason,
             })
         }
     }
@@ -1056,15 +1337,19 @@ fn convert_wait_result_to_exec(
 
 /// Spawn an interactive exec in a running container.
 ///
-/// Returns a Child process that the caller can use to handle I/O streaming.
-/// The caller is responsible for managing stdin/stdout/stderr and waiting for exit.
+/// Returns a Child process that the caller can use to handle I/O streaming,
+/// and the `--process` file it was started with, which must be kept until
+/// the process exits. The caller is responsible for managing
+/// stdin/stdout/stderr and waiting for exit.
 pub fn spawn_interactive_exec(
     container_id: &str,
     command: &[String],
     env: &[(String, String)],
     workdir: Option<&str>,
     tty: bool,
-) -> Result<std::process::Child, StorageError> {
+    security: &SecurityOptions,
+    user: Option<&str>,
+) -> Result<(std::process::Child, Option<tempfile::TempPath>), StorageError> {
     // Validate command
     validate_exec_params(command)?;
 
@@ -1090,7 +1375,8 @@ pub fn spawn_interactive_exec(
     );
 
     // Spawn crun exec with piped stdio for streaming
-    let child = CrunCommand::exec(&info.id, env, command, workdir, tty)
+    let (crun, process_file) = crun_exec(&info, command, env, workdir, tty, security, user)?;
+    let child = crun
         .stdin_piped()
         .capture_output()
         .spawn()
@@ -1098,7 +1384,7 @@ pub fn spawn_interactive_exec(
 
     debug!(container_id = %info.id, "interactive exec spawned");
 
-    Ok(child)
+    Ok((child, process_file))
 }
 
 /// Stop a running container.
@@ -1109,6 +1395,9 @@ pub fn stop_container(container_id: &str, timeout_secs: u64) -> Result<(), Stora
 
     info!(container_id = %info.id, timeout_secs = timeout_secs, "stopping container");
 
+    // Mark it stopped up front so the restart supervisor leaves it alone
+    REGISTRY.update_state(&info.id, ContainerState::Stopped);
+
     // Send SIGTERM first
     let _ = CrunCommand::kill(&info.id, "SIGTERM").status();
 
@@ -1143,8 +1432,28 @@ pub fn stop_container(container_id: &str, timeout_secs: u64) -> Result<(), Stora
     Ok(())
 }
 
-/// Delete a container (must be stopped).
-pub fn delete_container(container_id: &str, force: bool) -> Result<(), StorageError> {
+/// Mark every running container stopped ahead of the guest being stopped,
+/// so the restart supervisor doesn't start them again once their processes
+/// are terminated. Returns how many were running.
+pub fn mark_all_stopped() -> usize {
+    let running: Vec<String> = REGISTRY
+        .list()
+        .into_iter()
+        .filter(|c| c.state == ContainerState::Running)
+        .map(|c| c.id)
+        .collect();
+    for id in &running {
+        REGISTRY.update_state(id, ContainerState::Stopped);
+    }
+    if let Err(e) = REGISTRY.persist() {
+        warn!(error = %e, "failed to persist registry after stopping containers");
+    }
+    running.len()
+}
+
+/// Delete a container (must be stopped), returning the bytes its overlay
+/// freed.
+pub fn delete_container(container_id: &str, force: bool) -> Result<u64, StorageError> {
     let info = REGISTRY
         .find_by_prefix(container_id)
         .ok_or_else(|| StorageError::new(format!("container not found: {}", container_id)))?;
@@ -1183,9 +1492,14 @@ pub fn delete_container(container_id: &str, force: bool) -> Result<(), StorageEr
 
     // Clean up overlay
     let workload_id = format!("container-{}", &info.id);
-    if let Err(e) = storage::cleanup_overlay(&workload_id) {
-        warn!(container_id = %info.id, error = %e, "failed to cleanup overlay");
-    }
+    let overlay_size = storage::workload_overlay_size(&workload_id);
+    let freed = match storage::cleanup_overlay(&workload_id) {
+        Ok(()) => overlay_size,
+        Err(e) => {
+            warn!(container_id = %info.id, error = %e, "failed to cleanup overlay");
+            0
+        }
+    };
 
     // Unregister from registry and persist
     REGISTRY.unregister(&info.id);
@@ -1193,10 +1507,15 @@ pub fn delete_container(container_id: &str, force: bool) -> Result<(), StorageEr
         warn!(error = %e, "failed to persist registry after delete");
     }
 
-    Ok(())
+    Ok(freed)
 }
 
 /// List all containers with their current state.
+///
+/// A container whose main process exited since the registry last saw it
+/// is reported as exited, with the exit code and time from its exit file
+/// if there is one. The registry itself is left for the restart
+/// supervisor to update.
 pub fn list_containers() -> Vec<ContainerInfo> {
     let mut containers = REGISTRY.list();
 
@@ -1210,11 +1529,75 @@ pub fn list_containers() -> Vec<ContainerInfo> {
                 _ => container.state,
             };
         }
+        if container.state == ContainerState::Stopped {
+            observe_exit(container);
+        }
     }
 
     containers
 }
 
+/// Fill in a stopped container's exit code and finish time from its exit
+/// file, where the registry doesn't have them.
+fn observe_exit(container: &mut ContainerInfo) {
+    if container.exit_code.is_none() {
+        container.exit_code = read_exit_code(&container.id);
+    }
+    if container.finished_at.is_none() {
+        container.finished_at = fs::metadata(paths::container_exit_path(&container.id))
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+    }
+}
+
+/// Whether a container's main process is still running.
+pub fn main_process(container_id: &str) -> MainProcess {
+    match get_crun_state(container_id).as_deref() {
+        Ok("running" | "created") => MainProcess::Running,
+        _ => MainProcess::Exited(read_exit_code(container_id)),
+    }
+}
+
+/// Run a container's health check once, returning whether it passed.
+pub fn run_health_check(container_id: &str, check: &HealthCheck) -> bool {
+    let spawned = CrunCommand::exec(container_id, &[], &check.command, None, false)
+        .stdin_null()
+        .capture_output()
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            warn!(container_id = %container_id, error = %e, "failed to spawn health check");
+            return false;
+        }
+    };
+    match wait_with_timeout(&mut child, Some(check.timeout_ms), None) {
+        Ok(WaitResult::Completed { exit_code: 0, .. }) => true,
+        Ok(WaitResult::Completed { exit_code, .. }) => {
+            debug!(container_id = %container_id, exit_code, "health check failed");
+            false
+        }
+        Ok(WaitResult::TimedOut { timeout_ms, .. }) => {
+            debug!(container_id = %container_id, timeout_ms, "health check timed out");
+            false
+        }
+        Err(e) => {
+            warn!(container_id = %container_id, error = %e, "failed to wait for health check");
+            false
+        }
+    }
+}
+
+/// Kill a container's main process so the restart supervisor can restart
+/// it. Unlike [`stop_container`], the container is left marked running.
+pub fn kill_container(container_id: &str) {
+    if let Err(e) = CrunCommand::kill(container_id, "SIGKILL").status() {
+        warn!(container_id = %container_id, error = %e, "failed to kill container");
+    }
+}
+
 /// Check if the overlay is mounted at the given path.
 fn is_overlay_mounted(merged_path: &Path) -> bool {
     paths::is_mount_point(merged_path)
@@ -1243,6 +1626,31 @@ fn get_crun_state(container_id: &str) -> Result<String, StorageError> {
         })
 }
 
+/// Resolve a container ID prefix to its full ID and init process PID.
+pub fn container_pid(container_id: &str) -> Result<(String, u32), StorageError> {
+    let info = REGISTRY
+        .find_by_prefix(container_id)
+        .ok_or_else(|| StorageError::new(format!("container not found: {}", container_id)))?;
+
+    let output = CrunCommand::state(&info.id)
+        .output()
+        .map_err(|e| StorageError::new(format!("failed to run crun state: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(StorageError::new(format!("crun state failed: {}", stderr)));
+    }
+    let state_json: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .map_err(|e| StorageError::new(format!("failed to parse crun state: {}", e)))?;
+
+    match state_json["pid"].as_u64() {
+        Some(pid) if pid > 0 && state_json["status"] == "running" => Ok((info.id, pid as u32)),
+        _ => Err(StorageError::new(format!(
+            "container is not running: {}",
+            info.id
+        ))),
+    }
+}
+
 /// Read exit code from the exit file for a container.
 fn read_exit_code(container_id: &str) -> Option<i32> {
     let exit_path = paths::container_exit_path(container_id);
@@ -1281,6 +1689,33 @@ fn cleanup_container_state(container_id: &str) {
     debug!(container_id = %container_id, "cleaned up container state");
 }
 
+/// A created `alpine:latest` container running `command`, for tests.
+#[cfg(test)]
+pub(crate) fn test_container(id: &str, command: &[&str]) -> ContainerInfo {
+    ContainerInfo {
+        id: id.to_string(),
+        image: "alpine:latest".to_string(),
+        bundle_path: PathBuf::from("/tmp/bundle"),
+        state: ContainerState::Created,
+        created_at: 0,
+        command: command.iter().map(|s| s.to_string()).collect(),
+        labels: BTreeMap::new(),
+        restart_policy: RestartPolicy::No,
+        restart_count: 0,
+        started_at: None,
+        finished_at: None,
+        exit_code: None,
+        error: None,
+        healthcheck: None,
+        health: None,
+        health_failures: 0,
+        pid_file: None,
+        exit_file: None,
+        log_file: None,
+        attach_socket: None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1290,16 +1725,8 @@ mod tests {
         let registry = ContainerRegistry::new();
 
         let info = ContainerInfo {
-            id: "test-123".to_string(),
-            image: "alpine:latest".to_string(),
-            bundle_path: PathBuf::from("/tmp/bundle"),
-            state: ContainerState::Created,
-            created_at: 12345,
-            command: vec!["sleep".to_string(), "infinity".to_string()],
-            pid_file: None,
-            exit_file: None,
-            log_file: None,
-            attach_socket: None,
+            labels: BTreeMap::from([("project".to_string(), "billing".to_string())]),
+            ..test_container("test-123", &["sleep", "infinity"])
         };
 
         registry.register(info.clone());
@@ -1307,6 +1734,11 @@ mod tests {
         assert!(registry.get("test-123").is_some());
         assert!(registry.get("nonexistent").is_none());
 
+        // Labels survive being persisted and reloaded
+        let reloaded: ContainerInfo =
+            serde_json::from_str(&serde_json::to_string(&info).unwrap()).unwrap();
+        assert_eq!(reloaded.labels, info.labels);
+
         registry.update_state("test-123", ContainerState::Running);
         assert_eq!(
             registry.get("test-123").unwrap().state,
@@ -1317,21 +1749,59 @@ mod tests {
         assert!(registry.get("test-123").is_none());
     }
 
+    #[test]
+    fn test_status_lifecycle() {
+        let mut info = test_container("test-lifecycle", &["sh"]);
+        assert_eq!(info.status(), ContainerStatus::Created);
+
+        // A failed start is an error until the container next starts
+        info.error = Some("crun start failed".to_string());
+        assert!(matches!(info.status(), ContainerStatus::Error { .. }));
+
+        info.transition(ContainerState::Running, 200);
+        assert_eq!(info.status(), ContainerStatus::Running);
+        assert_eq!(info.started_at, Some(200));
+        assert_eq!(info.error, None);
+        // Already running: the start time stands
+        info.transition(ContainerState::Running, 250);
+        assert_eq!(info.started_at, Some(200));
+
+        info.transition(ContainerState::Stopped, 300);
+        info.exit_code = Some(137);
+        assert_eq!(info.status(), ContainerStatus::Exited { code: Some(137) });
+        assert_eq!(info.finished_at, Some(300));
+        info.transition(ContainerState::Stopped, 350);
+        assert_eq!(info.finished_at, Some(300));
+
+        // Restarting clears the previous run's exit
+        info.transition(ContainerState::Running, 400);
+        assert_eq!(info.started_at, Some(400));
+        assert_eq!(info.finished_at, None);
+        assert_eq!(info.exit_code, None);
+
+        // The registry records exits and failures the same way
+        let registry = ContainerRegistry::new();
+        registry.register(info);
+        registry.record_exit("test-lifecycle", Some(2));
+        let info = registry.get("test-lifecycle").unwrap();
+        assert_eq!(info.status(), ContainerStatus::Exited { code: Some(2) });
+        assert!(info.finished_at.is_some());
+        registry.record_error("test-lifecycle", "no space".to_string());
+        assert_eq!(
+            registry.get("test-lifecycle").unwrap().status(),
+            ContainerStatus::Error {
+                message: "no space".to_string()
+            }
+        );
+    }
+
     #[test]
     fn test_find_by_prefix() {
         let registry = ContainerRegistry::new();
 
         let info = ContainerInfo {
-            id: "smolvm-abc123def456".to_string(),
-            image: "alpine:latest".to_string(),
-            bundle_path: PathBuf::from("/tmp/bundle"),
             state: ContainerState::Running,
-            created_at: 12345,
-            command: vec!["sh".to_string()],
-            pid_file: None,
-            exit_file: None,
-            log_file: None,
-            attach_socket: None,
+            ..test_container("smolvm-abc123def456", &["sh"])
         };
 
         registry.register(info);
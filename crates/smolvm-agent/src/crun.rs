@@ -7,6 +7,7 @@ use std::path::Path;
 use std::process::{Command, Stdio};
 
 use crate::paths;
+use crate::tools;
 
 /// Default PATH for container execution.
 ///
@@ -120,6 +121,18 @@ impl CrunCommand {
         c
     }
 
+    /// Execute the process described by an OCI process file:
+    /// `crun exec --process <file> <id>`
+    ///
+    /// Used instead of [`exec`](Self::exec) when the process needs settings
+    /// crun's exec flags can't express, such as a reduced capability set.
+    pub fn exec_process(container_id: &str, process_file: &Path) -> Self {
+        let mut c = Self::new();
+        c.cmd.arg("exec").arg("--process").arg(process_file);
+        c.cmd.arg(container_id);
+        c
+    }
+
     /// Kill a container: `crun kill <id> <signal>`
     pub fn kill(container_id: &str, signal: &str) -> Self {
         let mut c = Self::new();
@@ -183,20 +196,25 @@ impl CrunCommand {
 
     /// Spawn the command.
     pub fn spawn(mut self) -> std::io::Result<std::process::Child> {
-        self.cmd.spawn()
+        self.cmd.spawn().map_err(spawn_error)
     }
 
     /// Run and wait for output.
     pub fn output(mut self) -> std::io::Result<std::process::Output> {
-        self.cmd.output()
+        self.cmd.output().map_err(spawn_error)
     }
 
     /// Run and wait for status.
     pub fn status(mut self) -> std::io::Result<std::process::ExitStatus> {
-        self.cmd.status()
+        self.cmd.status().map_err(spawn_error)
     }
 }
 
+/// Name the crun path (and how to install crun) in spawn failures.
+fn spawn_error(err: std::io::Error) -> std::io::Error {
+    std::io::Error::new(err.kind(), tools::CRUN.spawn_error(&err))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,259 @@
+//! Deduplication of identical files across extracted layers.
+//!
+//! Images often ship byte-identical files (the same libc, the same CA
+//! bundle) in unrelated layers. When the host's `layer_dedup` setting is on
+//! (passed in [`LAYER_DEDUP_ENV`]), each freshly extracted layer is scanned
+//! and every regular file is hardlinked to an entry in a content store keyed
+//! by its SHA-256, mode and owner, so identical files share one inode.
+//!
+//! Only layer directories are deduplicated. They are read-only lower
+//! layers of every overlay, and a write through an overlay copies the file
+//! up into the writable upper layer first, so a shared inode is never
+//! modified. Hardlinked files share a modification time; the first copy
+//! stored wins. Files with extended attributes are left alone, since those
+//! would be shared too.
+
+use sha2::{Digest, Sha256};
+use smolvm_protocol::agent_env::LAYER_DEDUP_ENV;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Whether deduplication was enabled with [`LAYER_DEDUP_ENV`].
+pub fn enabled() -> bool {
+    std::env::var(LAYER_DEDUP_ENV).is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+}
+
+/// What a deduplication pass did.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DedupStats {
+    /// Files replaced by a link to an identical stored file.
+    pub files_linked: u64,
+    /// Bytes no longer stored twice.
+    pub bytes_saved: u64,
+}
+
+/// Hardlink the regular files under `layer_dir` to the content store at
+/// `store`, sharing inodes with identical files from other layers.
+///
+/// Does nothing if `store` is on another filesystem, since hardlinks can't
+/// cross it.
+pub fn dedup_layer(layer_dir: &Path, store: &Path) -> io::Result<DedupStats> {
+    std::fs::create_dir_all(store)?;
+    let mut stats = DedupStats::default();
+    let store_dev = std::fs::metadata(store)?.dev();
+    if std::fs::metadata(layer_dir)?.dev() != store_dev {
+        debug!(
+            layer = %layer_dir.display(),
+            store = %store.display(),
+            "content store is on another filesystem, not deduplicating"
+        );
+        return Ok(stats);
+    }
+    dedup_dir(layer_dir, store, store_dev, &mut stats)?;
+    Ok(stats)
+}
+
+fn dedup_dir(dir: &Path, store: &Path, dev: u64, stats: &mut DedupStats) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            // Mount points inside a layer would be another filesystem
+            if meta.dev() == dev {
+                dedup_dir(&path, store, dev, stats)?;
+            }
+        } else if meta.is_file() && meta.len() > 0 && meta.nlink() == 1 && meta.dev() == dev {
+            if let Err(e) = dedup_file(&path, &meta, store, stats) {
+                warn!(path = %path.display(), error = %e, "failed to deduplicate file");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn dedup_file(
+    path: &Path,
+    meta: &std::fs::Metadata,
+    store: &Path,
+    stats: &mut DedupStats,
+) -> io::Result<()> {
+    if has_xattrs(path) {
+        return Ok(());
+    }
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    let key = format!(
+        "{:x}-{:o}-{}-{}",
+        hasher.finalize(),
+        meta.mode() & 0o7777,
+        meta.uid(),
+        meta.gid()
+    );
+    let stored = store.join(key);
+
+    match std::fs::hard_link(path, &stored) {
+        // First copy: it becomes the stored one
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+
+    // Link the stored copy next to the file, then swap it in atomically
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{}.dedup", file_name));
+    std::fs::hard_link(&stored, &temp)?;
+    if let Err(e) = std::fs::rename(&temp, path) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+    stats.files_linked += 1;
+    stats.bytes_saved += meta.len();
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn has_xattrs(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return true;
+    };
+    // Size query: the length of the attribute name list, 0 if there are none
+    let len = unsafe { libc::llistxattr(path.as_ptr(), std::ptr::null_mut(), 0) };
+    len != 0
+}
+
+#[cfg(not(target_os = "linux"))]
+fn has_xattrs(_path: &Path) -> bool {
+    false
+}
+
+/// Remove stored files no layer links to any more. Returns the bytes freed.
+pub fn prune_store(store: &Path) -> io::Result<u64> {
+    let mut freed = 0;
+    if !store.exists() {
+        return Ok(0);
+    }
+    for entry in std::fs::read_dir(store)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.nlink() == 1 {
+            std::fs::remove_file(entry.path())?;
+            freed += meta.len();
+        }
+    }
+    Ok(freed)
+}
+
+/// Bytes that removing `dirs` and then pruning `store` would free.
+///
+/// A file counts once per inode, and only if every link to it is under
+/// `dirs`, or under `dirs` plus the one in `store` that pruning removes.
+/// Files another layer still links to free nothing.
+pub fn reclaimable(dirs: &[PathBuf], store: &Path) -> io::Result<u64> {
+    // Links under `dirs` per inode, with the inode's size and link count
+    let mut inodes: HashMap<u64, (u64, u64, u64)> = HashMap::new();
+    for dir in dirs {
+        count_links(dir, &mut inodes)?;
+    }
+
+    let mut stored = HashSet::new();
+    if store.exists() {
+        for entry in std::fs::read_dir(store)? {
+            let meta = entry?.metadata()?;
+            if inodes.contains_key(&meta.ino()) {
+                stored.insert(meta.ino());
+            }
+        }
+    }
+
+    Ok(inodes
+        .iter()
+        .filter(|(ino, &(_, nlink, links))| {
+            nlink == links || (nlink == links + 1 && stored.contains(ino))
+        })
+        .map(|(_, &(len, _, _))| len)
+        .sum())
+}
+
+fn count_links(dir: &Path, inodes: &mut HashMap<u64, (u64, u64, u64)>) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_dir() {
+            count_links(&entry.path(), inodes)?;
+        } else if meta.is_file() {
+            inodes
+                .entry(meta.ino())
+                .or_insert((meta.len(), meta.nlink(), 0))
+                .2 += 1;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_identical_files_share_an_inode() {
+        let root = tempfile::tempdir().unwrap();
+        let store = root.path().join("content");
+        let layer_a = root.path().join("layers/aaa");
+        let layer_b = root.path().join("layers/bbb");
+        for layer in [&layer_a, &layer_b] {
+            std::fs::create_dir_all(layer.join("lib")).unwrap();
+            std::fs::write(layer.join("lib/libc.so"), "the same libc").unwrap();
+        }
+        std::fs::write(layer_a.join("own.txt"), "only in a").unwrap();
+        // Same bytes, different mode: linking would change its permissions
+        std::fs::write(layer_b.join("script"), "the same libc").unwrap();
+        std::fs::set_permissions(
+            layer_b.join("script"),
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+
+        assert_eq!(
+            dedup_layer(&layer_a, &store).unwrap(),
+            DedupStats::default()
+        );
+        let stats = dedup_layer(&layer_b, &store).unwrap();
+        assert_eq!(stats.files_linked, 1);
+        assert_eq!(stats.bytes_saved, "the same libc".len() as u64);
+
+        let a = std::fs::metadata(layer_a.join("lib/libc.so")).unwrap();
+        let b = std::fs::metadata(layer_b.join("lib/libc.so")).unwrap();
+        assert_eq!(a.ino(), b.ino());
+        assert_eq!(
+            std::fs::read_to_string(layer_b.join("lib/libc.so")).unwrap(),
+            "the same libc"
+        );
+        let script = std::fs::metadata(layer_b.join("script")).unwrap();
+        assert_ne!(script.ino(), a.ino());
+        assert_eq!(script.mode() & 0o777, 0o755);
+
+        // Removing a layer frees only what no other layer links to
+        assert_eq!(
+            reclaimable(std::slice::from_ref(&layer_a), &store).unwrap(),
+            "only in a".len() as u64
+        );
+        assert_eq!(
+            reclaimable(&[layer_a.clone(), layer_b.clone()], &store).unwrap(),
+            ("only in a".len() + 2 * "the same libc".len()) as u64
+        );
+
+        // Stored files outlive one layer, and go once no layer uses them
+        std::fs::remove_dir_all(&layer_a).unwrap();
+        assert_eq!(prune_store(&store).unwrap(), "only in a".len() as u64);
+        std::fs::remove_dir_all(&layer_b).unwrap();
+        prune_store(&store).unwrap();
+        assert_eq!(std::fs::read_dir(&store).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,188 @@
+//! Interactive exec sessions that outlive their connection.
+//!
+//! A resumable `Exec` is given an ID by [`new_session_id`], which the host
+//! is told in `ExecSession`. If its connection drops, it is parked here,
+//! its process still running, until the host reconnects and sends
+//! `ResumeExec` with that ID. Output the process writes meanwhile waits in its output
+//! queue and, once that is full, its pipes. A session not resumed within [`RESUME_GRACE`] is killed.
+
+use std::collections::HashMap;
+use std::process::{Child, ChildStdin};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use smolvm_protocol::{AgentResponse, HeartbeatConfig};
+use tracing::{debug, info, warn};
+
+use crate::output_queue::OutputQueue;
+
+/// How long a parked session waits to be resumed before it is killed.
+pub const RESUME_GRACE: Duration = Duration::from_secs(30);
+
+/// A child's stdio as an interactive session uses it, kept apart from the
+/// session's connection so another connection can carry on with it.
+pub struct SessionIo {
+    pub output: OutputQueue,
+    pub stdin: Option<ChildStdin>,
+    /// Output read from the child that couldn't be sent, to go first once
+    /// the session continues.
+    pub pending: Option<AgentResponse>,
+    /// When the session times out.
+    pub deadline: Option<Instant>,
+}
+
+impl SessionIo {
+    /// Take `child`'s stdio, starting to queue its output, for a session that
+    /// times out after `timeout_ms`.
+    pub fn take(child: &mut Child, timeout_ms: Option<u64>) -> std::io::Result<Self> {
+        Ok(Self {
+            output: OutputQueue::spawn(child.stdout.take(), child.stderr.take())?,
+            stdin: child.stdin.take(),
+            pending: None,
+            deadline: timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms)),
+        })
+    }
+}
+
+/// An exec waiting to be resumed.
+pub struct ParkedExec {
+    pub child: Child,
+    pub io: SessionIo,
+    pub heartbeat: Option<HeartbeatConfig>,
+    /// Process file `crun exec` reads; deleted on drop.
+    pub _process_file: Option<tempfile::TempPath>,
+}
+
+struct Parked {
+    generation: u64,
+    exec: ParkedExec,
+}
+
+static PARKED: Mutex<Option<HashMap<u64, Parked>>> = Mutex::new(None);
+
+/// Tells a reaper whether the session it is waiting on was resumed and then
+/// parked again under the same ID.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// An ID for a new session: unique while the agent runs, and with random
+/// upper bits so one client can't guess another's.
+pub fn new_session_id() -> u64 {
+    use std::io::Read;
+
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    let mut random = [0u8; 4];
+    if let Err(e) = std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut random))
+    {
+        warn!(error = %e, "failed to read /dev/urandom for exec session ID");
+    }
+    let sequence = NEXT.fetch_add(1, Ordering::Relaxed) & u64::from(u32::MAX);
+    u64::from(u32::from_ne_bytes(random)) << 32 | sequence
+}
+
+/// Park `exec` as session `id` until it is resumed with [`take`], killing it
+/// if that doesn't happen within `grace`.
+pub fn park(id: u64, exec: ParkedExec, grace: Duration) {
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed);
+    PARKED
+        .lock()
+        .get_or_insert_with(HashMap::new)
+        .insert(id, Parked { generation, exec });
+    info!(
+        session_id = id,
+        grace_secs = grace.as_secs(),
+        "exec session parked"
+    );
+
+    let reaper = std::thread::Builder::new()
+        .name("exec-session-reaper".into())
+        .spawn(move || {
+            std::thread::sleep(grace);
+            let expired = {
+                let mut parked = PARKED.lock();
+                let sessions = parked.get_or_insert_with(HashMap::new);
+                match sessions.get(&id) {
+                    Some(p) if p.generation == generation => sessions.remove(&id),
+                    _ => None,
+                }
+            };
+            if let Some(expired) = expired {
+                warn!(session_id = id, "exec session was not resumed, killing it");
+                kill(expired.exec);
+            }
+        });
+    if let Err(e) = reaper {
+        warn!(error = %e, "failed to start exec session reaper");
+    }
+}
+
+/// Take parked session `id` to resume it.
+pub fn take(id: u64) -> Option<ParkedExec> {
+    let parked = PARKED.lock().as_mut()?.remove(&id)?;
+    debug!(session_id = id, "exec session resumed");
+    Some(parked.exec)
+}
+
+/// Kill and reap a parked exec's process.
+fn kill(mut exec: ParkedExec) {
+    if let Ok(None) = exec.child.try_wait() {
+        let _ = exec.child.kill();
+    }
+    let _ = exec.child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    fn spawn_parked() -> ParkedExec {
+        let mut child = Command::new("sleep")
+            .arg("30")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let io = SessionIo::take(&mut child, None).unwrap();
+        ParkedExec {
+            child,
+            io,
+            heartbeat: None,
+            _process_file: None,
+        }
+    }
+
+    #[test]
+    fn test_session_ids_are_unique() {
+        let a = new_session_id();
+        let b = new_session_id();
+        assert_ne!(a, b);
+        assert_ne!(a & u64::from(u32::MAX), b & u64::from(u32::MAX));
+    }
+
+    #[test]
+    fn test_unresumed_session_is_killed_after_grace() {
+        let exec = spawn_parked();
+        let pid = exec.child.id();
+        park(1001, exec, Duration::from_millis(100));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while std::path::Path::new(&format!("/proc/{}", pid)).exists() {
+            assert!(Instant::now() < deadline, "parked exec was never killed");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(take(1001).is_none());
+    }
+
+    #[test]
+    fn test_resumed_session_outlives_grace() {
+        park(1002, spawn_parked(), Duration::from_millis(50));
+        let mut exec = take(1002).expect("session should be parked");
+        assert!(take(1002).is_none());
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(exec.child.try_wait().unwrap().is_none());
+        kill(exec);
+    }
+}
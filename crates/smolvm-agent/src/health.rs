@@ -0,0 +1,202 @@
+//! Health checks for long-running containers.
+//!
+//! A background thread runs each running container's
+//! [`HealthCheck`](smolvm_protocol::HealthCheck) inside it every
+//! `interval_ms`, starting one interval after the container starts. A
+//! container is [`Starting`](HealthStatus::Starting) until a check passes
+//! and [`Unhealthy`](HealthStatus::Unhealthy) after `retries` failures in a
+//! row. Checks run one at a time, so a slow check delays the others by up
+//! to its `timeout_ms`.
+//!
+//! The [restart supervisor](crate::restart) kills unhealthy containers whose
+//! restart policy would restart them, so they are restarted like any other
+//! failed container.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use smolvm_protocol::{HealthCheck, HealthStatus};
+use tracing::{info, warn};
+
+use crate::container::{self, ContainerRegistry, ContainerState, REGISTRY};
+
+/// How often the registry is checked for containers due a health check.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Start the health monitor thread.
+pub fn spawn() {
+    let result = std::thread::Builder::new()
+        .name("health-monitor".into())
+        .spawn(|| {
+            let mut monitor = Monitor::default();
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                if monitor.tick(&REGISTRY, Instant::now(), container::run_health_check) {
+                    if let Err(e) = REGISTRY.persist() {
+                        warn!(error = %e, "failed to persist registry after health check");
+                    }
+                }
+            }
+        });
+    if let Err(e) = result {
+        warn!(error = %e, "failed to start health monitor, containers will not be health checked");
+    }
+}
+
+/// When each running container is next due a check.
+#[derive(Debug, Default)]
+struct Monitor {
+    /// The run a check is scheduled for (by start time), and when it is due.
+    due: HashMap<String, (Option<u64>, Instant)>,
+}
+
+impl Monitor {
+    /// Check every running container in `registry` that is due a check.
+    ///
+    /// `check` runs a container's health check and reports whether it
+    /// passed. Returns whether any container's health changed.
+    fn tick(
+        &mut self,
+        registry: &ContainerRegistry,
+        now: Instant,
+        mut check: impl FnMut(&str, &HealthCheck) -> bool,
+    ) -> bool {
+        let containers = registry.list();
+        self.due.retain(|id, _| {
+            containers
+                .iter()
+                .any(|c| &c.id == id && c.state == ContainerState::Running)
+        });
+
+        let mut changed = false;
+        for info in containers {
+            let (ContainerState::Running, Some(healthcheck)) = (info.state, &info.healthcheck)
+            else {
+                continue;
+            };
+            let interval = Duration::from_millis(healthcheck.interval_ms);
+            let (run, due) = self
+                .due
+                .entry(info.id.clone())
+                .or_insert((info.started_at, now + interval));
+            // Restarted since the check was scheduled
+            if *run != info.started_at {
+                *run = info.started_at;
+                *due = now + interval;
+            }
+            if now < *due {
+                continue;
+            }
+            *due = now + interval;
+
+            let passed = check(&info.id, healthcheck);
+            if let Some(health) = registry.record_health(&info.id, info.started_at, passed) {
+                if health == HealthStatus::Unhealthy {
+                    warn!(container_id = %info.id, retries = healthcheck.retries, "container is unhealthy");
+                } else {
+                    info!(container_id = %info.id, health = %health, "container health changed");
+                }
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container::ContainerInfo;
+
+    fn registry_with(healthcheck: HealthCheck) -> ContainerRegistry {
+        let registry = ContainerRegistry::new();
+        registry.register(ContainerInfo {
+            healthcheck: Some(healthcheck),
+            ..container::test_container("c1", &["sleep", "infinity"])
+        });
+        registry.update_state("c1", ContainerState::Running);
+        registry
+    }
+
+    fn healthcheck(retries: u32) -> HealthCheck {
+        HealthCheck {
+            interval_ms: 1000,
+            retries,
+            ..HealthCheck::new(vec!["true".to_string()])
+        }
+    }
+
+    fn health(registry: &ContainerRegistry) -> Option<HealthStatus> {
+        registry.get("c1").unwrap().health
+    }
+
+    #[test]
+    fn test_failing_check_turns_unhealthy_after_retries() {
+        let registry = registry_with(healthcheck(3));
+        let mut monitor = Monitor::default();
+        let start = Instant::now();
+        let mut tick =
+            |secs: u64| monitor.tick(&registry, start + Duration::from_secs(secs), |_, _| false);
+
+        // Nothing is checked until an interval has passed
+        assert!(!tick(0));
+        assert_eq!(health(&registry), Some(HealthStatus::Starting));
+
+        // Failures below the threshold leave it starting
+        assert!(!tick(1));
+        assert!(!tick(2));
+        assert_eq!(health(&registry), Some(HealthStatus::Starting));
+
+        assert!(tick(3));
+        assert_eq!(health(&registry), Some(HealthStatus::Unhealthy));
+        assert_eq!(registry.get("c1").unwrap().health_failures, 3);
+
+        // Still unhealthy is no change
+        assert!(!tick(4));
+    }
+
+    #[test]
+    fn test_result_for_an_earlier_run_is_dropped() {
+        let registry = registry_with(healthcheck(1));
+        let mut monitor = Monitor::default();
+        let start = Instant::now();
+        let changed = monitor.tick(&registry, start + Duration::from_secs(1), |id, _| {
+            // The container is restarted while its check runs
+            let mut info = registry.get(id).unwrap();
+            info.started_at = info.started_at.map(|t| t + 1);
+            registry.register(info);
+            false
+        });
+        assert!(!changed);
+        assert_eq!(health(&registry), Some(HealthStatus::Starting));
+        assert_eq!(registry.get("c1").unwrap().health_failures, 0);
+    }
+
+    #[test]
+    fn test_passing_check_reports_healthy() {
+        let registry = registry_with(healthcheck(2));
+        let mut monitor = Monitor::default();
+        let start = Instant::now();
+        let mut tick = |secs: u64, passing: bool| {
+            monitor.tick(&registry, start + Duration::from_secs(secs), |_, _| passing)
+        };
+
+        tick(0, true);
+        assert!(tick(1, true));
+        assert_eq!(health(&registry), Some(HealthStatus::Healthy));
+
+        // A single failure under the threshold doesn't change it
+        assert!(!tick(2, false));
+        assert_eq!(health(&registry), Some(HealthStatus::Healthy));
+        assert!(tick(3, false));
+        assert_eq!(health(&registry), Some(HealthStatus::Unhealthy));
+
+        // Passing again recovers, and a restart starts over
+        assert!(tick(4, true));
+        assert_eq!(health(&registry), Some(HealthStatus::Healthy));
+        registry.record_exit("c1", Some(1));
+        assert_eq!(health(&registry), None);
+        registry.update_state("c1", ContainerState::Running);
+        assert_eq!(health(&registry), Some(HealthStatus::Starting));
+    }
+}
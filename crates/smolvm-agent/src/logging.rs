@@ -0,0 +1,127 @@
+//! Log subscriber setup.
+//!
+//! Logs are text by default; `--log-format json` or `SMOLVM_LOG_FORMAT=json`
+//! switches to one JSON object per line with event and span fields as
+//! separate keys, for shipping to a log collector.
+
+use smolvm_protocol::log_format::LogFormat;
+use smolvm_protocol::AgentRequest;
+use tracing::Level;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// Install the global subscriber, in the format chosen by `args` or the
+/// environment.
+pub fn init(args: impl Iterator<Item = String>) {
+    let format = match format_from_args(args).or_else(LogFormat::from_env) {
+        Some(Ok(format)) => format,
+        Some(Err(e)) => {
+            eprintln!("smolvm-agent: {}, using text", e);
+            LogFormat::Text
+        }
+        None => LogFormat::Text,
+    };
+    subscriber(format, std::io::stdout).init();
+}
+
+/// The value of `--log-format`, if given.
+fn format_from_args(
+    mut args: impl Iterator<Item = String>,
+) -> Option<Result<LogFormat, smolvm_protocol::log_format::ParseLogFormatError>> {
+    while let Some(arg) = args.next() {
+        if arg == "--log-format" {
+            return args.next().map(|v| v.parse());
+        }
+        if let Some(value) = arg.strip_prefix("--log-format=") {
+            return Some(value.parse());
+        }
+    }
+    None
+}
+
+/// Build the agent's subscriber, writing lines in `format` to `writer`.
+fn subscriber<W>(format: LogFormat, writer: W) -> Box<dyn tracing::Subscriber + Send + Sync>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::from_default_env()
+                .add_directive("smolvm_agent=warn".parse().expect("valid directive")),
+        )
+        .with_writer(writer);
+    match format {
+        LogFormat::Text => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(builder.json().flatten_event(true).finish()),
+    }
+}
+
+/// Span covering the handling of `request`, so everything logged while
+/// serving it carries the request method.
+///
+/// At WARN level so the default filter keeps it for the warnings and errors
+/// that most need the context.
+pub fn request_span(request: &AgentRequest) -> tracing::Span {
+    tracing::span!(Level::WARN, "request", method = request.method())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_lines_have_structured_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = subscriber(LogFormat::Json, move || writer.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let request = AgentRequest::CleanupOverlay {
+                workload_id: "wl-123".to_string(),
+            };
+            let _span = request_span(&request).entered();
+            tracing::warn!(workload_id = "wl-123", "failed to clean up overlay");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["message"], "failed to clean up overlay");
+        assert_eq!(line["workload_id"], "wl-123");
+        assert_eq!(line["span"]["method"], "cleanup_overlay");
+    }
+
+    #[test]
+    fn test_format_from_args() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            format_from_args(args(&["init", "--log-format", "json"]).into_iter()),
+            Some(Ok(LogFormat::Json))
+        );
+        assert_eq!(
+            format_from_args(args(&["init", "--log-format=text"]).into_iter()),
+            Some(Ok(LogFormat::Text))
+        );
+        assert_eq!(format_from_args(args(&["init"]).into_iter()), None);
+        assert!(matches!(
+            format_from_args(args(&["--log-format", "xml"]).into_iter()),
+            Some(Err(_))
+        ));
+    }
+}
@@ -8,25 +8,45 @@
 //!
 //! Communication is via vsock on port 6000.
 
+use smolvm_protocol::agent_env::{MAX_CONNECTION_SECS_ENV, MAX_REQUESTS_ENV};
+use smolvm_protocol::heartbeat::HeartbeatTracker;
+use smolvm_protocol::scratch;
+use smolvm_protocol::vsock;
 use smolvm_protocol::{
-    error_codes, ports, AgentRequest, AgentResponse, ContainerInfo, RegistryAuth, LAYER_CHUNK_SIZE,
-    PROTOCOL_VERSION,
+    capabilities, chunked, decode_json, error_codes, ports, AgentRequest, AgentResponse,
+    ContainerInfo, ExitReason, HeartbeatConfig, ImageInfo, ImagePage, RegistryAuth, RequestFrame,
+    ResourceLimits, ResponseFrame, SecurityOptions, LAYER_CHUNK_SIZE, PROTOCOL_VERSION,
 };
 use std::io::{Read, Write};
 use std::os::unix::io::AsRawFd;
+use std::os::unix::process::ExitStatusExt;
 use std::process::{Child, Command, Stdio};
 use tracing::{debug, error, info, warn};
 
 mod container;
 mod crun;
+mod dedup;
+mod exec_session;
+mod health;
+mod logging;
 mod oci;
+mod output_queue;
+mod overlay_lru;
 mod paths;
 mod process;
 #[cfg(target_os = "linux")]
 mod pty;
+mod pull_cancel;
+mod pull_limit;
+mod puller;
+mod restart;
 mod retry;
+mod stats;
 mod storage;
-mod vsock;
+mod tools;
+mod user;
+mod volume;
+mod workload;
 
 // ============================================================================
 // Configuration Constants
@@ -38,6 +58,15 @@ const REQUEST_BUFFER_SIZE: usize = 64 * 1024; // 64KB
 /// Maximum allowed message size to prevent DoS via memory exhaustion.
 const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024; // 16MB
 
+/// Time allowed for the rest of a request's length header once its first
+/// byte has arrived. Waiting for that first byte is unbounded, since pooled
+/// connections sit idle between requests.
+const FRAME_HEADER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Time allowed for a request body once its header has been read. Generous
+/// enough for a full [`MAX_MESSAGE_SIZE`] request on a busy VM.
+const FRAME_BODY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
 /// Buffer size for streaming stdout/stderr in interactive mode.
 const IO_BUFFER_SIZE: usize = 4096;
 
@@ -63,6 +92,9 @@ fn uptime_ms() -> u64 {
     0
 }
 
+/// When the agent started, for `Status`.
+static STARTED_AT: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+
 fn main() {
     // Quick --version check (used by init script to detect rootfs updates)
     if std::env::args().any(|a| a == "--version") {
@@ -91,14 +123,10 @@ fn main() {
     };
 
     let start_uptime = uptime_ms();
+    STARTED_AT.get_or_init(std::time::Instant::now);
 
     // Initialize logging (after vsock listener is ready)
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::from_default_env()
-                .add_directive("smolvm_agent=warn".parse().expect("valid directive")),
-        )
-        .init();
+    logging::init(std::env::args());
 
     info!(
         version = env!("CARGO_PKG_VERSION"),
@@ -114,6 +142,12 @@ fn main() {
     mount_storage_disk();
     info!(duration_ms = uptime_ms() - t0, "storage disk mounted");
 
+    // Mount the scratch disk for overlay upper layers, if the VM has one
+    mount_scratch_disk();
+    if let Err(e) = storage::overlay_upper_root() {
+        error!(error = %e, "overlay upper directory unusable, containers will fail to start");
+    }
+
     // Now do initialization - the vsock listener is already accepting at kernel level
     let t0 = uptime_ms();
     if let Err(e) = storage::init() {
@@ -141,6 +175,13 @@ fn main() {
         warn!(error = %e, "failed to reconcile container registry");
     }
     info!(duration_ms = uptime_ms() - t0, "registry reconciled");
+    restart::spawn(&REQUEST_LOCK);
+    health::spawn();
+    tools::spawn_startup_check();
+    workload::spawn(|request| {
+        let _serialized = REQUEST_LOCK.lock();
+        handle_request(request)
+    });
 
     info!(
         total_startup_ms = uptime_ms() - start_uptime,
@@ -148,7 +189,10 @@ fn main() {
         "agent startup complete, entering accept loop"
     );
 
-    // Start accepting connections (listener already bound)
+    // Start accepting connections (listener already bound). The host's
+    // ready handshake has been waiting in the accept queue since boot, so
+    // its Ping is answered now that storage is usable — that Pong is the
+    // ready signal.
     if let Err(e) = run_server_with_listener(listener) {
         error!(error = %e, "server error");
         std::process::exit(1);
@@ -615,6 +659,48 @@ fn mount_storage_disk() {
     }
 }
 
+/// Mount the scratch disk where the host said to keep overlay upper layers.
+///
+/// The host sets [`scratch::OVERLAY_UPPER_DIR_ENV`] only when it attaches
+/// one. It arrives as a blank sparse file, so a disk that won't mount is formatted;
+/// one that still won't mount is left for [`storage::overlay_upper_root`]
+/// to reject.
+fn mount_scratch_disk() {
+    let Some(mount_point) = std::env::var_os(scratch::OVERLAY_UPPER_DIR_ENV) else {
+        return;
+    };
+    if !std::path::Path::new(scratch::SCRATCH_DEVICE).exists() {
+        warn!(
+            device = scratch::SCRATCH_DEVICE,
+            "scratch disk not attached"
+        );
+        return;
+    }
+    let _ = std::fs::create_dir_all(&mount_point);
+
+    let mount = || {
+        Command::new("mount")
+            .arg(scratch::SCRATCH_DEVICE)
+            .arg(&mount_point)
+            .status()
+            .is_ok_and(|status| status.success())
+    };
+    if mount() {
+        debug!("scratch disk mounted");
+        return;
+    }
+    info!("formatting scratch disk");
+    let _ = Command::new("mkfs.ext4")
+        .args(["-F", "-q", scratch::SCRATCH_DEVICE])
+        .status();
+    if !mount() {
+        warn!(
+            device = scratch::SCRATCH_DEVICE,
+            "failed to mount scratch disk"
+        );
+    }
+}
+
 /// Run the vsock server with a pre-created listener.
 /// The listener is created early (before initialization) to ensure the kernel
 /// has a listener ready when the host connects.
@@ -623,6 +709,7 @@ fn run_server_with_listener(
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut first_connection = true;
     let listen_start = uptime_ms();
+    let limits = ConnectionLimits::from_env();
 
     info!(uptime_ms = uptime_ms(), "entering vsock accept loop");
 
@@ -639,9 +726,12 @@ fn run_server_with_listener(
                 }
                 info!("accepted connection");
 
-                if let Err(e) = handle_connection(&mut stream) {
-                    warn!(error = %e, "connection error");
-                }
+                std::thread::spawn(move || {
+                    if let Err(e) = handle_connection(&mut stream, &FrameTimeouts::DEFAULT, &limits)
+                    {
+                        warn!(error = %e, "connection error");
+                    }
+                });
             }
             Err(e) => {
                 warn!(error = %e, "accept error");
@@ -650,20 +740,166 @@ fn run_server_with_listener(
     }
 }
 
+/// Serializes request handling across connections.
+///
+/// Each connection is served on its own thread so that an idle one (e.g. a
+/// connection pooled by the API server) never keeps others waiting, but
+/// requests still run one at a time as they did when connections were
+/// served serially. Pings, `CancelPull` and `Stop` skip the lock so liveness
+/// checks are answered, pulls can be cancelled, and the guest can be stopped,
+/// even during a long pull. Interactive sessions hold it only while setting
+/// up (see [`Serialized`]). The restart supervisor takes it too before
+/// acting on a container.
+static REQUEST_LOCK: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+
+/// A request's hold on [`REQUEST_LOCK`], if it takes it.
+///
+/// Interactive sessions release it once their command has started, so an
+/// open `exec -it` doesn't hold up every other request, and take it back to
+/// clean up afterwards.
+struct Serialized(Option<parking_lot::MutexGuard<'static, ()>>);
+
+impl Serialized {
+    /// Hold the lock for `request`, unless it is one that skips it.
+    fn for_request(request: &AgentRequest) -> Self {
+        let exempt = matches!(
+            request,
+            AgentRequest::Ping
+                | AgentRequest::Status
+                | AgentRequest::CancelPull { .. }
+                | AgentRequest::Stop { .. }
+        );
+        Self((!exempt).then(|| REQUEST_LOCK.lock()))
+    }
+
+    /// Let other requests run.
+    fn release(&mut self) {
+        self.0 = None;
+    }
+
+    /// Take the lock again after [`release`](Self::release).
+    fn reacquire(&mut self) {
+        if self.0.is_none() {
+            self.0 = Some(REQUEST_LOCK.lock());
+        }
+    }
+}
+
+thread_local! {
+    /// `request_id` of the request this connection thread is answering,
+    /// echoed on every frame [`send_response`] writes.
+    static RESPONSE_REQUEST_ID: std::cell::Cell<Option<u64>> = const { std::cell::Cell::new(None) };
+}
+
+/// How long a client may take to send each part of a request frame.
+struct FrameTimeouts {
+    /// Rest of the length header, after its first byte.
+    header: std::time::Duration,
+    /// Request body.
+    body: std::time::Duration,
+}
+
+impl FrameTimeouts {
+    const DEFAULT: Self = Self {
+        header: FRAME_HEADER_TIMEOUT,
+        body: FRAME_BODY_TIMEOUT,
+    };
+}
+
+/// How much one connection may be used before the agent closes it.
+///
+/// Limits are checked between requests, never interrupting one, and the
+/// connection is closed cleanly; clients open a new one.
+#[derive(Debug, Clone, Copy, Default)]
+struct ConnectionLimits {
+    /// Requests served before closing. Pings don't count, so a client's
+    /// liveness checks don't use up its connection.
+    max_requests: Option<u64>,
+    /// Time after accepting the connection before closing it.
+    max_lifetime: Option<std::time::Duration>,
+}
+
+impl ConnectionLimits {
+    /// Limits from [`MAX_REQUESTS_ENV`] and [`MAX_CONNECTION_SECS_ENV`].
+    /// Unset, zero or invalid values leave that limit off.
+    fn from_env() -> Self {
+        fn positive(name: &str) -> Option<u64> {
+            let value = std::env::var(name).ok()?;
+            match value.trim().parse::<u64>() {
+                Ok(0) => None,
+                Ok(n) => Some(n),
+                Err(_) => {
+                    warn!(name = name, value = %value, "ignoring invalid connection limit");
+                    None
+                }
+            }
+        }
+        let limits = Self {
+            max_requests: positive(MAX_REQUESTS_ENV),
+            max_lifetime: positive(MAX_CONNECTION_SECS_ENV).map(std::time::Duration::from_secs),
+        };
+        if limits.max_requests.is_some() || limits.max_lifetime.is_some() {
+            info!(
+                max_requests = ?limits.max_requests,
+                max_lifetime_secs = ?limits.max_lifetime.map(|d| d.as_secs()),
+                "connection limits set"
+            );
+        }
+        limits
+    }
+}
+
 /// Handle a single connection.
-fn handle_connection(stream: &mut impl ReadWrite) -> Result<(), Box<dyn std::error::Error>> {
+///
+/// A client that stalls part-way through a request frame is disconnected
+/// once `timeouts` run out, so it can't pin the connection's thread. The
+/// connection is closed once it reaches `limits`.
+fn handle_connection(
+    stream: &mut impl ReadWrite,
+    timeouts: &FrameTimeouts,
+    limits: &ConnectionLimits,
+) -> Result<(), Box<dyn std::error::Error>> {
     let mut buf = vec![0u8; REQUEST_BUFFER_SIZE];
+    let opened = std::time::Instant::now();
+    let mut served = 0u64;
 
     loop {
-        // Read length header
+        RESPONSE_REQUEST_ID.set(None);
+
+        if limits.max_requests.is_some_and(|max| served >= max) {
+            info!(
+                requests = served,
+                "connection reached its request limit, closing"
+            );
+            return Ok(());
+        }
+
+        // Wait for the next request, however long the connection stays idle,
+        // unless its lifetime runs out first
+        if let Some(lifetime) = limits.max_lifetime {
+            let remaining = lifetime.saturating_sub(opened.elapsed());
+            if !pull_cancel::wait_readable(stream, remaining)? {
+                info!(
+                    requests = served,
+                    "connection reached its lifetime, closing"
+                );
+                return Ok(());
+            }
+        }
         let mut header = [0u8; 4];
-        match stream.read_exact(&mut header) {
-            Ok(_) => {}
-            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+        let started = match stream.read(&mut header) {
+            Ok(0) => {
                 debug!("connection closed");
                 return Ok(());
             }
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
             Err(e) => return Err(e.into()),
+        };
+
+        // Once a frame has started, the rest of it must follow promptly
+        if let Err(e) = read_exact_within(stream, &mut header[started..], timeouts.header) {
+            return Err(stalled_frame("header", e));
         }
 
         let len = u32::from_be_bytes(header) as usize;
@@ -690,11 +926,16 @@ fn handle_connection(stream: &mut impl ReadWrite) -> Result<(), Box<dyn std::err
         }
 
         // Read payload
-        stream.read_exact(&mut buf[..len])?;
+        if let Err(e) = read_exact_within(stream, &mut buf[..len], timeouts.body) {
+            return Err(stalled_frame("body", e));
+        }
 
-        // Parse request
-        let request: AgentRequest = match serde_json::from_slice(&buf[..len]) {
-            Ok(req) => req,
+        // Parse request, tagging its responses with its ID if it has one
+        let request = match decode_json::<RequestFrame>(&buf[..len]) {
+            Ok(frame) => {
+                RESPONSE_REQUEST_ID.set(frame.request_id);
+                frame.request
+            }
             Err(e) => {
                 warn!(error = %e, "invalid request");
                 send_response(
@@ -708,8 +949,37 @@ fn handle_connection(stream: &mut impl ReadWrite) -> Result<(), Box<dyn std::err
             }
         };
 
+        let _span = logging::request_span(&request).entered();
+        if !matches!(request, AgentRequest::Ping) {
+            served += 1;
+        }
         debug!(?request, "received request");
 
+        // Exec'd processes join the container's cgroup, and crun runs
+        // without a cgroup manager (see `paths::CRUN_CGROUP_MANAGER`), so an
+        // exec can't have limits of its own. They are set when the
+        // container is run.
+        if let AgentRequest::Exec {
+            memory_mib,
+            cpu_quota,
+            ..
+        } = &request
+        {
+            if memory_mib.is_some() || cpu_quota.is_some() {
+                send_response(
+                    stream,
+                    &AgentResponse::error(
+                        "memory and CPU limits apply to the whole container and can't be \
+                         set for a single exec",
+                        error_codes::INVALID_REQUEST,
+                    ),
+                )?;
+                continue;
+            }
+        }
+
+        let mut serialized = Serialized::for_request(&request);
+
         // Check if this is an interactive run request
         if let AgentRequest::Run {
             interactive: true, ..
@@ -717,7 +987,7 @@ fn handle_connection(stream: &mut impl ReadWrite) -> Result<(), Box<dyn std::err
         | AgentRequest::Run { tty: true, .. } = &request
         {
             // Handle interactive session
-            handle_interactive_run(stream, request)?;
+            handle_interactive_run(stream, request, &mut serialized)?;
             continue;
         }
 
@@ -728,7 +998,7 @@ fn handle_connection(stream: &mut impl ReadWrite) -> Result<(), Box<dyn std::err
         | AgentRequest::VmExec { tty: true, .. } = &request
         {
             // Handle interactive VM exec session
-            handle_interactive_vm_exec(stream, request)?;
+            handle_interactive_vm_exec(stream, request, &mut serialized)?;
             continue;
         }
 
@@ -739,7 +1009,13 @@ fn handle_connection(stream: &mut impl ReadWrite) -> Result<(), Box<dyn std::err
         | AgentRequest::Exec { tty: true, .. } = &request
         {
             // Handle interactive container exec session
-            handle_interactive_container_exec(stream, request)?;
+            handle_interactive_container_exec(stream, request, &mut serialized)?;
+            continue;
+        }
+
+        // Pick up an interactive container exec whose connection dropped
+        if let AgentRequest::ResumeExec { session_id } = request {
+            handle_resume_exec(stream, session_id, &mut serialized)?;
             continue;
         }
 
@@ -748,9 +1024,21 @@ fn handle_connection(stream: &mut impl ReadWrite) -> Result<(), Box<dyn std::err
             ref image,
             ref oci_platform,
             ref auth,
+            no_cache,
+            max_bytes_per_sec,
+            heartbeat,
         } = request
         {
-            handle_streaming_pull(stream, image, oci_platform.as_deref(), auth.as_ref())?;
+            handle_streaming_pull(
+                stream,
+                timeouts,
+                image,
+                oci_platform.as_deref(),
+                auth.as_ref(),
+                no_cache,
+                max_bytes_per_sec,
+                heartbeat,
+            )?;
             continue;
         }
 
@@ -764,6 +1052,17 @@ fn handle_connection(stream: &mut impl ReadWrite) -> Result<(), Box<dyn std::err
             continue;
         }
 
+        // Image archives travel as chunked transfers after the request or
+        // response frame
+        if let AgentRequest::SaveImage { ref image } = request {
+            handle_streaming_save_image(stream, image)?;
+            continue;
+        }
+        if let AgentRequest::LoadImage = request {
+            handle_streaming_load_image(stream, timeouts)?;
+            continue;
+        }
+
         // Handle regular request
         let response = handle_request(request);
         send_response(stream, &response)?;
@@ -785,26 +1084,41 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
     match request {
         AgentRequest::Ping => AgentResponse::Pong {
             version: PROTOCOL_VERSION,
+            capabilities: capabilities::ALL.iter().map(|c| c.to_string()).collect(),
         },
+        AgentRequest::Status => handle_status(),
 
         // Pull is handled separately in handle_streaming_pull for progress streaming
         AgentRequest::Pull { .. } => unreachable!("Pull handled before match"),
+        AgentRequest::CancelPull { image } => handle_cancel_pull(&image),
 
         AgentRequest::Query { image } => handle_query(&image),
+        AgentRequest::QueryDigest { digest } => handle_query_digest(&digest),
+        AgentRequest::Tag { source, target } => handle_tag(&source, &target),
 
-        AgentRequest::ListImages => handle_list_images(),
+        AgentRequest::ListImages { offset, limit } => handle_list_images(offset, limit),
 
         AgentRequest::GarbageCollect { dry_run } => handle_gc(dry_run),
+        AgentRequest::RemoveImage { image } => handle_remove_image(&image),
 
         AgentRequest::PrepareOverlay { image, workload_id } => {
             handle_prepare_overlay(&image, &workload_id)
         }
 
         AgentRequest::CleanupOverlay { workload_id } => handle_cleanup_overlay(&workload_id),
+        AgentRequest::DiffOverlay { container_id } => handle_diff_overlay(&container_id),
+        AgentRequest::PruneOverlays { dry_run } => handle_prune_overlays(dry_run),
 
-        AgentRequest::FormatStorage => handle_format_storage(),
+        AgentRequest::FormatStorage { force } => handle_format_storage(force),
 
         AgentRequest::StorageStatus => handle_storage_status(),
+        AgentRequest::DiskUsage => handle_disk_usage(),
+        AgentRequest::Verify {
+            image,
+            remove_corrupt,
+        } => handle_verify(image.as_deref(), remove_corrupt),
+        AgentRequest::ToolCheck => AgentResponse::ok_with_data(tools::status()),
+        AgentRequest::Stats { container_id } => handle_stats(container_id.as_deref()),
 
         AgentRequest::NetworkTest { url } => {
             info!(url = %url, "testing network connectivity directly from agent");
@@ -876,6 +1190,8 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
             }
         }
 
+        AgentRequest::Stop { timeout_ms } => handle_stop(timeout_ms),
+
         // VM-level exec (direct command execution in VM, not container)
         AgentRequest::VmExec {
             command,
@@ -884,6 +1200,7 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
             timeout_ms,
             interactive: false,
             tty: false,
+            ..
         } => handle_vm_exec(&command, &env, workdir.as_deref(), timeout_ms),
 
         AgentRequest::VmExec { .. } => {
@@ -894,23 +1211,14 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
             )
         }
 
-        AgentRequest::Run {
-            image,
-            command,
-            env,
-            workdir,
-            mounts,
-            timeout_ms,
+        request @ AgentRequest::Run {
             interactive: false,
             tty: false,
-        } => handle_run(
-            &image,
-            &command,
-            &env,
-            workdir.as_deref(),
-            &mounts,
-            timeout_ms,
-        ),
+            ..
+        } => match RunRequest::from_request(request) {
+            Some(run) => handle_run(run),
+            None => AgentResponse::error("expected Run request", error_codes::INVALID_REQUEST),
+        },
 
         AgentRequest::Run { .. } => {
             // Interactive mode should be handled by handle_interactive_run
@@ -920,10 +1228,16 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
             )
         }
 
-        AgentRequest::Stdin { .. } | AgentRequest::Resize { .. } => AgentResponse::error(
-            "stdin/resize only valid during interactive session",
-            error_codes::INVALID_REQUEST,
-        ),
+        AgentRequest::Stdin { .. } | AgentRequest::Resize { .. } | AgentRequest::Signal { .. } => {
+            AgentResponse::error(
+                "stdin/resize/signal only valid during interactive session",
+                error_codes::INVALID_REQUEST,
+            )
+        }
+
+        // Stray keepalive outside a session (e.g. arriving just after one
+        // ended) - answer in kind so the host doesn't see an error
+        AgentRequest::Heartbeat => AgentResponse::Heartbeat,
 
         // Container lifecycle
         AgentRequest::CreateContainer {
@@ -932,7 +1246,21 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
             env,
             workdir,
             mounts,
-        } => handle_create_container(&image, &command, &env, workdir.as_deref(), &mounts),
+            restart_policy,
+            labels,
+            healthcheck,
+        } => handle_create_container(
+            &image,
+            &command,
+            &env,
+            workdir.as_deref(),
+            &mounts,
+            container::ContainerOptions {
+                restart_policy,
+                labels,
+                healthcheck,
+            },
+        ),
 
         AgentRequest::StartContainer { container_id } => handle_start_container(&container_id),
 
@@ -948,6 +1276,12 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
 
         AgentRequest::ListContainers => handle_list_containers(),
 
+        AgentRequest::CreateVolume { name, size_mib } => handle_create_volume(&name, size_mib),
+
+        AgentRequest::ListVolumes => handle_list_volumes(),
+
+        AgentRequest::RemoveVolume { name } => handle_remove_volume(&name),
+
         AgentRequest::Exec {
             container_id,
             command,
@@ -956,12 +1290,17 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
             timeout_ms,
             interactive: false,
             tty: false,
+            security,
+            user,
+            ..
         } => handle_exec(
             &container_id,
             &command,
             &env,
             workdir.as_deref(),
             timeout_ms,
+            &security,
+            user.as_deref(),
         ),
 
         AgentRequest::Exec { .. } => {
@@ -972,10 +1311,23 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
             )
         }
 
+        AgentRequest::ResumeExec { .. } => {
+            // Should be handled by handle_resume_exec
+            AgentResponse::error("resume exec not handled here", error_codes::INTERNAL_ERROR)
+        }
+
         AgentRequest::ExportLayer { .. } => {
             // Streaming export is handled by handle_streaming_export_layer
             AgentResponse::error("export layer not handled here", error_codes::INTERNAL_ERROR)
         }
+
+        AgentRequest::SaveImage { .. } | AgentRequest::LoadImage => {
+            // Handled by handle_streaming_save_image / handle_streaming_load_image
+            AgentResponse::error(
+                "image archive not handled here",
+                error_codes::INTERNAL_ERROR,
+            )
+        }
     }
 }
 
@@ -983,31 +1335,68 @@ fn handle_request(request: AgentRequest) -> AgentResponse {
 fn handle_interactive_run(
     stream: &mut impl ReadWrite,
     request: AgentRequest,
+    serialized: &mut Serialized,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let (image, command, env, workdir, mounts, timeout_ms, tty) = match request {
-        AgentRequest::Run {
-            image,
-            command,
-            env,
-            workdir,
-            mounts,
-            timeout_ms,
-            tty,
-            ..
-        } => (image, command, env, workdir, mounts, timeout_ms, tty),
-        _ => {
-            send_response(
-                stream,
-                &AgentResponse::error("expected Run request", error_codes::INVALID_REQUEST),
-            )?;
+    let Some(run) = RunRequest::from_request(request) else {
+        send_response(
+            stream,
+            &AgentResponse::error("expected Run request", error_codes::INVALID_REQUEST),
+        )?;
+        return Ok(());
+    };
+
+    if let Err(e) = run.options.validate(&run.image) {
+        send_response(stream, &run_error_response(e))?;
+        return Ok(());
+    }
+    let command = match storage::run_argv(&run.image, run.entrypoint.as_deref(), &run.command) {
+        Ok(argv) => argv,
+        Err(e) => {
+            send_response(stream, &run_error_response(e))?;
             return Ok(());
         }
     };
 
-    info!(image = %image, command = ?command, tty = tty, "starting interactive run");
+    info!(image = %run.image, command = ?command, tty = run.tty, ephemeral = run.options.ephemeral, read_only = run.options.read_only, "starting interactive run");
+
+    let workload_id = run.options.workload_id(&run.image);
+    // Ephemeral overlays are removed even if the session failed part-way
+    storage::with_run_overlay(
+        &workload_id,
+        run.options.cleans_up_overlay(),
+        storage::cleanup_run_overlay,
+        || {
+            let result = run_interactive_in_overlay(
+                stream,
+                serialized,
+                &workload_id,
+                &run.image,
+                &command,
+                &run.options,
+                run.heartbeat,
+            );
+            // Clean up under the lock
+            serialized.reacquire();
+            result
+        },
+    )
+}
+
+/// Run an interactive session in the overlay identified by `workload_id`.
+fn run_interactive_in_overlay(
+    stream: &mut impl ReadWrite,
+    serialized: &mut Serialized,
+    workload_id: &str,
+    image: &str,
+    command: &[String],
+    options: &storage::RunOptions,
+    heartbeat: Option<HeartbeatConfig>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Keep the overlay from being evicted for the length of the session
+    let _lease = overlay_lru::OverlayLease::acquire(workload_id);
 
     // Prepare the overlay and get the rootfs path
-    let rootfs = match storage::prepare_for_run(&image) {
+    let rootfs = match storage::prepare_for_run(image, workload_id) {
         Ok(path) => path,
         Err(e) => {
             send_response(stream, &AgentResponse::from_err(e, error_codes::RUN_FAILED))?;
@@ -1015,8 +1404,19 @@ fn handle_interactive_run(
         }
     };
 
+    if options.read_only {
+        if let Err(e) = storage::check_read_only_targets(
+            std::path::Path::new(&rootfs),
+            &options.mounts,
+            &options.tmpfs,
+        ) {
+            send_response(stream, &run_error_response(e))?;
+            return Ok(());
+        }
+    }
+
     // Setup virtiofs mounts at staging area (crun will bind-mount them via OCI spec)
-    if let Err(e) = storage::setup_mounts(&rootfs, &mounts) {
+    if let Err(e) = storage::setup_mounts(&rootfs, &options.mounts) {
         send_response(
             stream,
             &AgentResponse::from_err(e, error_codes::MOUNT_FAILED),
@@ -1024,16 +1424,21 @@ fn handle_interactive_run(
         return Ok(());
     }
 
+    if let Some(workdir) = &options.workdir {
+        if let Err(e) = storage::ensure_workdir(
+            std::path::Path::new(&rootfs),
+            workdir,
+            &options.mounts,
+            options.create_workdir && !options.read_only,
+        ) {
+            send_response(stream, &run_error_response(e))?;
+            return Ok(());
+        }
+    }
+
     // Spawn the command with crun
-    let mut child = match spawn_interactive_command(
-        &rootfs,
-        &command,
-        &env,
-        workdir.as_deref(),
-        &mounts,
-        tty,
-    ) {
-        Ok(child) => child,
+    let (mut child, container_id) = match spawn_interactive_command(&rootfs, command, options) {
+        Ok(spawned) => spawned,
         Err(e) => {
             send_response(
                 stream,
@@ -1044,10 +1449,20 @@ fn handle_interactive_run(
     };
 
     // Send Started response
+    serialized.release();
     send_response(stream, &AgentResponse::Started)?;
 
     // Run the interactive I/O loop
-    let exit_code = run_interactive_loop(stream, &mut child, timeout_ms)?;
+    let exit_code = kill_on_error(
+        run_interactive_loop(
+            stream,
+            &mut child,
+            SignalTarget::Container(&container_id),
+            options.timeout_ms,
+            heartbeat,
+        ),
+        &mut child,
+    )?;
 
     // Send Exited response
     send_response(stream, &AgentResponse::Exited { exit_code })?;
@@ -1056,14 +1471,13 @@ fn handle_interactive_run(
 }
 
 /// Spawn a command for interactive execution using crun OCI runtime.
+///
+/// Returns the `crun run` process and the container ID.
 fn spawn_interactive_command(
     rootfs: &str,
     command: &[String],
-    env: &[(String, String)],
-    workdir: Option<&str>,
-    mounts: &[(String, String, bool)],
-    _tty: bool,
-) -> Result<Child, Box<dyn std::error::Error>> {
+    options: &storage::RunOptions,
+) -> Result<(Child, String), Box<dyn std::error::Error>> {
     use std::path::Path;
 
     if command.is_empty() {
@@ -1083,21 +1497,8 @@ fn spawn_interactive_command(
         return Err(format!("bundle directory not found: {}", bundle_path.display()).into());
     }
 
-    // Generate OCI spec for this command
-    let workdir_str = workdir.unwrap_or("/");
-    let mut spec = oci::OciSpec::new(command, env, workdir_str, false);
-
-    // Add virtiofs bind mounts to OCI spec
-    for (tag, container_path, read_only) in mounts {
-        let virtiofs_mount = Path::new(paths::VIRTIOFS_MOUNT_ROOT).join(tag);
-        spec.add_bind_mount(
-            &virtiofs_mount.to_string_lossy(),
-            container_path,
-            *read_only,
-        );
-    }
-
-    // Write config.json to bundle
+    // Generate OCI spec for this command and write config.json to bundle
+    let spec = options.oci_spec(command, rootfs_path)?;
     spec.write_to(&bundle_path)
         .map_err(|e| format!("failed to write OCI spec: {}", e))?;
 
@@ -1110,7 +1511,7 @@ fn spawn_interactive_command(
         command = ?command,
         container_id = %container_id,
         bundle = %bundle_path.display(),
-        mounts = mounts.len(),
+        mounts = options.mounts.len(),
         "spawning interactive container with crun"
     );
 
@@ -1120,58 +1521,108 @@ fn spawn_interactive_command(
         .capture_output()
         .spawn()?;
 
-    Ok(child)
+    Ok((child, container_id))
+}
+
+/// Where a `Signal` from the host is delivered during an interactive session.
+#[derive(Clone, Copy)]
+enum SignalTarget<'a> {
+    /// The spawned child process itself.
+    Child,
+    /// The main process of a crun container, via `crun kill`.
+    Container(&'a str),
+}
+
+/// Deliver a signal forwarded by the host to the interactive command.
+fn forward_signal(child: &Child, target: SignalTarget<'_>, signal: i32) {
+    debug!(signal, "forwarding signal from host");
+    match target {
+        SignalTarget::Child => {
+            // SAFETY: kill has no memory-safety preconditions, and the child
+            // hasn't been reaped yet so its pid can't have been reused.
+            if unsafe { libc::kill(child.id() as libc::pid_t, signal) } != 0 {
+                let err = std::io::Error::last_os_error();
+                warn!(signal, error = %err, "failed to signal process");
+            }
+        }
+        SignalTarget::Container(id) => {
+            match crun::CrunCommand::kill(id, &signal.to_string()).status() {
+                Ok(status) if status.success() => {}
+                Ok(status) => warn!(container_id = %id, signal, %status, "crun kill failed"),
+                Err(e) => warn!(container_id = %id, signal, error = %e, "failed to run crun kill"),
+            }
+        }
+    }
 }
 
 /// Run the interactive I/O loop using poll() for efficient I/O multiplexing.
+///
+/// `Signal` requests from the host are delivered to `signals`. If
+/// `heartbeat` is set, sends `Heartbeat` frames to the host and fails
+/// with an error (after killing the child) once the host misses too many.
 fn run_interactive_loop(
     stream: &mut impl ReadWrite,
     child: &mut Child,
+    signals: SignalTarget<'_>,
     timeout_ms: Option<u64>,
+    heartbeat: Option<HeartbeatConfig>,
 ) -> Result<i32, Box<dyn std::error::Error>> {
-    use std::io::Read as _;
-    use std::time::{Duration, Instant};
+    let mut io = exec_session::SessionIo::take(child, timeout_ms)?;
+    run_session_loop(stream, child, &mut io, signals, heartbeat, true)
+}
 
-    let start = Instant::now();
-    let deadline = timeout_ms.map(|ms| start + Duration::from_millis(ms));
+/// The loop behind [`run_interactive_loop`], on stdio already taken from
+/// `child` so that it can be picked up again by another connection.
+///
+/// A lost heartbeat only kills the child if `kill_on_heartbeat_loss` is
+/// set. Output that couldn't be sent when the connection failed is left in
+/// `io` to be sent first next time.
+fn run_session_loop(
+    stream: &mut impl ReadWrite,
+    child: &mut Child,
+    io: &mut exec_session::SessionIo,
+    signals: SignalTarget<'_>,
+    heartbeat: Option<HeartbeatConfig>,
+    kill_on_heartbeat_loss: bool,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    use std::time::Instant;
 
-    // Get handles to child's stdio
-    let mut child_stdout = child.stdout.take();
-    let mut child_stderr = child.stderr.take();
-    let mut child_stdin = child.stdin.take();
+    let mut heartbeat = start_heartbeat(stream, heartbeat, Instant::now());
 
-    // Set non-blocking mode on stdout/stderr
-    if let Some(ref stdout) = child_stdout {
-        if !set_nonblocking(stdout.as_raw_fd()) {
-            warn!("failed to set stdout to non-blocking mode");
-        }
-    }
-    if let Some(ref stderr) = child_stderr {
-        if !set_nonblocking(stderr.as_raw_fd()) {
-            warn!("failed to set stderr to non-blocking mode");
+    if let Some(pending) = io.pending.take() {
+        if let Err(e) = send_response(stream, &pending) {
+            io.pending = Some(pending);
+            return Err(e);
         }
     }
 
-    let mut stdout_buf = [0u8; IO_BUFFER_SIZE];
-    let mut stderr_buf = [0u8; IO_BUFFER_SIZE];
+    let mut exit_code = None;
 
     loop {
         // Check if child has exited
-        if let Some(status) = child.try_wait()? {
-            // Drain any remaining output
-            drain_remaining_output(
-                stream,
-                &mut child_stdout,
-                &mut child_stderr,
-                &mut stdout_buf,
-                &mut stderr_buf,
-            )?;
-            return Ok(status.code().unwrap_or(-1));
+        if exit_code.is_none() {
+            exit_code = child.try_wait()?.map(|status| status.code().unwrap_or(-1));
+        }
+
+        // Once it has, finish when its output reaches EOF. Anything it left
+        // behind holding the output open keeps the session going, as it
+        // would on a terminal.
+        if let Some(code) = exit_code {
+            while let Some(chunk) = io.output.try_next() {
+                send_output(stream, io, chunk)?;
+            }
+            if io.output.ended() {
+                return Ok(code);
+            }
         }
 
         // Check timeout
-        if let Some(deadline) = deadline {
+        if let Some(deadline) = io.deadline {
             if Instant::now() >= deadline {
+                if let Some(code) = exit_code {
+                    warn!("interactive command timed out with its output still open");
+                    return Ok(code);
+                }
                 warn!("interactive command timed out, killing process");
                 if let Err(e) = child.kill() {
                     warn!(error = %e, "failed to kill timed out process");
@@ -1184,8 +1635,14 @@ fn run_interactive_loop(
             }
         }
 
+        check_heartbeat(
+            stream,
+            kill_on_heartbeat_loss.then_some(&mut *child),
+            &mut heartbeat,
+        )?;
+
         // Calculate poll timeout: either remaining time until deadline, or 100ms default
-        let poll_timeout_ms = match deadline {
+        let poll_timeout_ms = match io.deadline {
             Some(dl) => {
                 let remaining = dl.saturating_duration_since(Instant::now());
                 // Cap at 100ms to periodically check child exit status
@@ -1196,31 +1653,22 @@ fn run_interactive_loop(
             None => INTERACTIVE_POLL_TIMEOUT_MS,
         };
 
-        // Build poll fds array for stdout, stderr, and vsock stream
-        let stdout_fd = child_stdout.as_ref().map(|s| s.as_raw_fd()).unwrap_or(-1);
-        let stderr_fd = child_stderr.as_ref().map(|s| s.as_raw_fd()).unwrap_or(-1);
-        let stream_fd = stream.as_raw_fd();
-
+        // Build poll fds array for queued output and vsock stream
         let mut poll_fds = [
             libc::pollfd {
-                fd: stdout_fd,
-                events: if stdout_fd >= 0 { libc::POLLIN } else { 0 },
-                revents: 0,
-            },
-            libc::pollfd {
-                fd: stderr_fd,
-                events: if stderr_fd >= 0 { libc::POLLIN } else { 0 },
+                fd: io.output.wake_fd(),
+                events: libc::POLLIN,
                 revents: 0,
             },
             libc::pollfd {
-                fd: stream_fd,
+                fd: stream.as_raw_fd(),
                 events: libc::POLLIN,
                 revents: 0,
             },
         ];
 
         // Wait for I/O or timeout using poll()
-        let poll_result = unsafe { libc::poll(poll_fds.as_mut_ptr(), 3, poll_timeout_ms) };
+        let poll_result = unsafe { libc::poll(poll_fds.as_mut_ptr(), 2, poll_timeout_ms) };
 
         if poll_result < 0 {
             let err = std::io::Error::last_os_error();
@@ -1230,58 +1678,17 @@ fn run_interactive_loop(
             continue;
         }
 
-        // Read available stdout
+        // Send queued output
         if poll_fds[0].revents & libc::POLLIN != 0 {
-            if let Some(ref mut stdout) = child_stdout {
-                loop {
-                    match stdout.read(&mut stdout_buf) {
-                        Ok(0) => break,
-                        Ok(n) => {
-                            send_response(
-                                stream,
-                                &AgentResponse::Stdout {
-                                    data: stdout_buf[..n].to_vec(),
-                                },
-                            )?;
-                        }
-                        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
-                        Err(e) => {
-                            debug!(error = %e, "stdout read error");
-                            break;
-                        }
-                    }
-                }
-            }
-        }
-
-        // Read available stderr
-        if poll_fds[1].revents & libc::POLLIN != 0 {
-            if let Some(ref mut stderr) = child_stderr {
-                loop {
-                    match stderr.read(&mut stderr_buf) {
-                        Ok(0) => break,
-                        Ok(n) => {
-                            send_response(
-                                stream,
-                                &AgentResponse::Stderr {
-                                    data: stderr_buf[..n].to_vec(),
-                                },
-                            )?;
-                        }
-                        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
-                        Err(e) => {
-                            debug!(error = %e, "stderr read error");
-                            break;
-                        }
-                    }
-                }
+            while let Some(chunk) = io.output.try_next() {
+                send_output(stream, io, chunk)?;
             }
         }
 
         // Read incoming request from host (stdin data, resize) — only when
         // poll confirms data is available, then use blocking read_exact which
         // is safe because the data is already in the kernel buffer.
-        if poll_fds[2].revents & libc::POLLIN != 0 {
+        if poll_fds[1].revents & libc::POLLIN != 0 {
             let mut header = [0u8; 4];
             stream.read_exact(&mut header)?;
             let len = u32::from_be_bytes(header) as usize;
@@ -1290,13 +1697,16 @@ fn run_interactive_loop(
             }
             let mut buf = vec![0u8; len];
             stream.read_exact(&mut buf)?;
-            let request: AgentRequest = serde_json::from_slice(&buf)?;
+            let request: AgentRequest = decode_json(&buf)?;
+            if let Some(ref mut hb) = heartbeat {
+                hb.on_receive(Instant::now());
+            }
 
             match request {
                 AgentRequest::Stdin { data } => {
                     if data.is_empty() {
-                        drop(child_stdin.take());
-                    } else if let Some(ref mut stdin) = child_stdin {
+                        drop(io.stdin.take());
+                    } else if let Some(ref mut stdin) = io.stdin {
                         let _ = stdin.write_all(&data);
                         let _ = stdin.flush();
                     }
@@ -1304,6 +1714,8 @@ fn run_interactive_loop(
                 AgentRequest::Resize { cols, rows } => {
                     debug!(cols, rows, "resize requested (no PTY in pipe mode)");
                 }
+                AgentRequest::Signal { signal } => forward_signal(child, signals, signal),
+                AgentRequest::Heartbeat => {}
                 _ => {
                     warn!("unexpected request during interactive session");
                 }
@@ -1322,11 +1734,13 @@ fn run_interactive_loop_pty(
     child: &mut Child,
     pty_master: pty::PtyMaster,
     timeout_ms: Option<u64>,
+    heartbeat: Option<HeartbeatConfig>,
 ) -> Result<i32, Box<dyn std::error::Error>> {
     use std::time::{Duration, Instant};
 
     let start = Instant::now();
     let deadline = timeout_ms.map(|ms| start + Duration::from_millis(ms));
+    let mut heartbeat = start_heartbeat(stream, heartbeat, start);
 
     // Set the master fd to non-blocking so we can poll it.
     if !set_nonblocking(pty_master.as_raw_fd()) {
@@ -1377,6 +1791,8 @@ fn run_interactive_loop_pty(
             }
         }
 
+        check_heartbeat(stream, Some(child), &mut heartbeat)?;
+
         // Poll the PTY master fd for readable data.
         let poll_timeout_ms = match deadline {
             Some(dl) => {
@@ -1450,7 +1866,10 @@ fn run_interactive_loop_pty(
             }
             let mut msg_buf = vec![0u8; len];
             stream.read_exact(&mut msg_buf)?;
-            let request: AgentRequest = serde_json::from_slice(&msg_buf)?;
+            let request: AgentRequest = decode_json(&msg_buf)?;
+            if let Some(ref mut hb) = heartbeat {
+                hb.on_receive(Instant::now());
+            }
 
             match request {
                 AgentRequest::Stdin { data } => {
@@ -1464,6 +1883,10 @@ fn run_interactive_loop_pty(
                         debug!(error = %e, cols, rows, "failed to set PTY window size");
                     }
                 }
+                AgentRequest::Signal { signal } => {
+                    forward_signal(child, SignalTarget::Child, signal)
+                }
+                AgentRequest::Heartbeat => {}
                 _ => {
                     warn!("unexpected request during interactive PTY session");
                 }
@@ -1472,50 +1895,107 @@ fn run_interactive_loop_pty(
     }
 }
 
-/// Drain any remaining output from stdout/stderr after child exits.
-fn drain_remaining_output(
+/// Start heartbeat tracking for an interactive session.
+///
+/// Also bounds socket writes by the heartbeat timeout, so a host that stops
+/// reading can't wedge the loop in a blocking send.
+fn start_heartbeat(
+    stream: &impl AsRawFd,
+    config: Option<HeartbeatConfig>,
+    now: std::time::Instant,
+) -> Option<HeartbeatTracker> {
+    let config = config?;
+    if !set_send_timeout(stream.as_raw_fd(), config.timeout()) {
+        warn!("failed to set send timeout for heartbeat session");
+    }
+    Some(HeartbeatTracker::new(config, now))
+}
+
+/// Send a heartbeat if one is due, and fail, killing `child` if given, if
+/// the host has gone silent for longer than the heartbeat threshold.
+fn check_heartbeat(
     stream: &mut impl Write,
-    child_stdout: &mut Option<std::process::ChildStdout>,
-    child_stderr: &mut Option<std::process::ChildStderr>,
-    stdout_buf: &mut [u8],
-    stderr_buf: &mut [u8],
+    child: Option<&mut Child>,
+    heartbeat: &mut Option<HeartbeatTracker>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use std::io::Read as _;
+    let Some(hb) = heartbeat else {
+        return Ok(());
+    };
 
-    if let Some(ref mut stdout) = child_stdout {
-        loop {
-            match stdout.read(stdout_buf) {
-                Ok(0) => break,
-                Ok(n) => {
-                    send_response(
-                        stream,
-                        &AgentResponse::Stdout {
-                            data: stdout_buf[..n].to_vec(),
-                        },
-                    )?;
-                }
-                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
-                Err(_) => break,
-            }
+    let now = std::time::Instant::now();
+    if hb.peer_lost(now) {
+        warn!(
+            timeout_ms = hb.config().timeout().as_millis() as u64,
+            "host heartbeat lost"
+        );
+        if let Some(child) = child {
+            kill_and_reap(child);
         }
+        return Err("host heartbeat lost".into());
     }
-    if let Some(ref mut stderr) = child_stderr {
-        loop {
-            match stderr.read(stderr_buf) {
-                Ok(0) => break,
-                Ok(n) => {
-                    send_response(
-                        stream,
-                        &AgentResponse::Stderr {
-                            data: stderr_buf[..n].to_vec(),
-                        },
-                    )?;
-                }
-                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
-                Err(_) => break,
-            }
+    if hb.send_due(now) {
+        send_response(stream, &AgentResponse::Heartbeat)?;
+    }
+    Ok(())
+}
+
+/// Kill and reap `child` if an interactive loop failed (host disconnected,
+/// heartbeat lost, ...) so the command doesn't outlive its session.
+fn kill_on_error<T>(
+    result: Result<T, Box<dyn std::error::Error>>,
+    child: &mut Child,
+) -> Result<T, Box<dyn std::error::Error>> {
+    if let Err(ref e) = result {
+        warn!(error = %e, "interactive session failed, killing process");
+        kill_and_reap(child);
+    }
+    result
+}
+
+/// Kill a child process (if still running) and wait for it to avoid zombies.
+fn kill_and_reap(child: &mut Child) {
+    if let Ok(None) = child.try_wait() {
+        if let Err(e) = child.kill() {
+            warn!(error = %e, "failed to kill process");
         }
     }
+    if let Err(e) = child.wait() {
+        debug!(error = %e, "failed to wait for killed process");
+    }
+}
+
+/// Set `SO_SNDTIMEO` on a socket.
+fn set_send_timeout(fd: i32, timeout: std::time::Duration) -> bool {
+    let tv = libc::timeval {
+        tv_sec: timeout.as_secs() as libc::time_t,
+        tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+    };
+    // SAFETY: fd is a valid socket owned by the caller, and tv outlives the call
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_SNDTIMEO,
+            &tv as *const libc::timeval as *const libc::c_void,
+            std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+        )
+    };
+    result == 0
+}
+
+/// Send the host a chunk of the child's output.
+///
+/// A chunk that can't be sent is kept as `io.pending` before the error is
+/// returned.
+fn send_output(
+    stream: &mut impl Write,
+    io: &mut exec_session::SessionIo,
+    chunk: AgentResponse,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Err(e) = send_response(stream, &chunk) {
+        io.pending = Some(chunk);
+        return Err(e);
+    }
     Ok(())
 }
 
@@ -1787,63 +2267,338 @@ fn test_tcp_syscall(target: &str) -> serde_json::Value {
     })
 }
 
+/// A `Run` request, split into what picks the command and how to run it.
+struct RunRequest {
+    image: String,
+    entrypoint: Option<Vec<String>>,
+    command: Vec<String>,
+    tty: bool,
+    max_output_bytes: Option<u64>,
+    heartbeat: Option<HeartbeatConfig>,
+    options: storage::RunOptions,
+}
+
+impl RunRequest {
+    /// Split `request`; `None` if it isn't a `Run`.
+    fn from_request(request: AgentRequest) -> Option<Self> {
+        let AgentRequest::Run {
+            image,
+            command,
+            env,
+            workdir,
+            mounts,
+            timeout_ms,
+            interactive: _,
+            tty,
+            ephemeral,
+            max_output_bytes,
+            heartbeat,
+            memory_mib,
+            cpu_quota,
+            security,
+            user,
+            no_create_workdir,
+            platform,
+            entrypoint,
+            tmpfs,
+            read_only,
+            extra_hosts,
+        } = request
+        else {
+            return None;
+        };
+        Some(Self {
+            image,
+            entrypoint,
+            command,
+            tty,
+            max_output_bytes,
+            heartbeat,
+            options: storage::RunOptions {
+                env,
+                workdir,
+                mounts,
+                tmpfs,
+                extra_hosts,
+                timeout_ms,
+                ephemeral,
+                read_only,
+                limits: ResourceLimits::new(memory_mib, cpu_quota),
+                security,
+                user,
+                create_workdir: !no_create_workdir,
+                platform,
+            },
+        })
+    }
+}
+
 /// Handle command execution request (non-interactive).
-fn handle_run(
-    image: &str,
-    command: &[String],
-    env: &[(String, String)],
-    workdir: Option<&str>,
-    mounts: &[(String, String, bool)],
-    timeout_ms: Option<u64>,
-) -> AgentResponse {
-    info!(image = %image, command = ?command, mounts = ?mounts, timeout_ms = ?timeout_ms, "running command");
+fn handle_run(run: RunRequest) -> AgentResponse {
+    let command = match storage::run_argv(&run.image, run.entrypoint.as_deref(), &run.command) {
+        Ok(argv) => argv,
+        Err(e) => return run_error_response(e),
+    };
+    let options = &run.options;
+    info!(image = %run.image, command = ?command, mounts = ?options.mounts, timeout_ms = ?options.timeout_ms, ephemeral = options.ephemeral, read_only = options.read_only, "running command");
+
+    let output_limit = process::output_limit(run.max_output_bytes);
 
-    match storage::run_command(image, command, env, workdir, mounts, timeout_ms) {
+    match storage::run_command(&run.image, &command, options, output_limit) {
         Ok(result) => AgentResponse::Completed {
             exit_code: result.exit_code,
             stdout: result.stdout,
             stderr: result.stderr,
+            stdout_truncated: result.stdout_truncated,
+            stderr_truncated: result.stderr_truncated,
+            reason: result.reason,
         },
-        Err(e) => AgentResponse::from_err(e, error_codes::RUN_FAILED),
+        Err(e) => run_error_response(e),
+    }
+}
+
+/// Response for a failed run, distinguishing a missing working directory and
+/// an image built for another architecture.
+fn run_error_response(e: storage::StorageError) -> AgentResponse {
+    match e {
+        storage::StorageError::WorkdirNotFound { .. } => {
+            AgentResponse::error(e.to_string(), error_codes::WORKDIR_NOT_FOUND)
+        }
+        storage::StorageError::ArchMismatch { .. } => {
+            AgentResponse::error(e.to_string(), error_codes::ARCH_MISMATCH)
+        }
+        storage::StorageError::ValidationFailed { .. } => {
+            AgentResponse::error(e.to_string(), error_codes::INVALID_REQUEST)
+        }
+        e => AgentResponse::from_err(e, error_codes::RUN_FAILED),
     }
 }
 
 /// Handle image pull request with progress streaming.
-fn handle_streaming_pull<S: Read + Write>(
-    stream: &mut S,
+///
+/// The pull runs on its own thread while this one forwards its progress
+/// and watches the connection: a `CancelPull` frame, a disconnect or a lost
+/// heartbeat cancels the pull, which then ends with
+/// [`error_codes::PULL_CANCELLED`].
+#[allow(clippy::too_many_arguments)]
+fn handle_streaming_pull(
+    stream: &mut impl ReadWrite,
+    timeouts: &FrameTimeouts,
     image: &str,
     oci_platform: Option<&str>,
     auth: Option<&RegistryAuth>,
+    no_cache: bool,
+    max_bytes_per_sec: Option<u64>,
+    heartbeat: Option<HeartbeatConfig>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     info!(
         image = %image,
         ?oci_platform,
         has_auth = auth.is_some(),
+        no_cache,
+        ?max_bytes_per_sec,
         "pulling image with progress"
     );
 
-    // Create a progress callback that sends updates over the stream
-    let progress_callback = |current: usize, total: usize, layer: &str| {
-        let percent = if total > 0 {
-            ((current as f64 / total as f64) * 100.0) as u8
-        } else {
-            0
-        };
-        let response = AgentResponse::Progress {
-            message: format!("Pulling layer {}/{}", current, total),
-            percent: Some(percent),
-            layer: Some(layer.to_string()),
-        };
-        // Ignore errors from progress updates - non-critical
-        let _ = send_response(stream, &response);
+    stream_pull(stream, timeouts, image, heartbeat, |cancel, progress| {
+        storage::pull_image_with_progress_and_auth(
+            image,
+            oci_platform,
+            auth,
+            no_cache,
+            max_bytes_per_sec,
+            cancel,
+            progress,
+        )
+    })
+}
+
+/// Run `pull` on its own thread, streaming its progress to the client and
+/// cancelling it on a `CancelPull` frame or a disconnect.
+///
+/// With `heartbeat` set, a `Heartbeat` is sent every interval so a long
+/// download or extraction doesn't look dead, and the pull is cancelled if
+/// the host goes silent for longer than the heartbeat threshold.
+fn stream_pull<F>(
+    stream: &mut impl ReadWrite,
+    timeouts: &FrameTimeouts,
+    image: &str,
+    heartbeat: Option<HeartbeatConfig>,
+    pull: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(
+            &pull_cancel::PullCancel,
+            &dyn Fn(usize, usize, &str),
+        ) -> std::result::Result<ImageInfo, storage::StorageError>
+        + Send,
+{
+    let registration = pull_cancel::register(image);
+    let cancel = registration.token();
+    let (progress_tx, progress_rx) = std::sync::mpsc::channel();
+    let mut heartbeat = start_heartbeat(stream, heartbeat, std::time::Instant::now());
+
+    let result = std::thread::scope(|s| {
+        let pull = s.spawn(move || {
+            pull(cancel, &|current, total, layer| {
+                let _ = progress_tx.send(pull_progress(current, total, layer));
+            })
+        });
+
+        while !pull.is_finished() {
+            // Ignore errors from progress updates - non-critical
+            for progress in progress_rx.try_iter() {
+                let _ = send_response(stream, &progress);
+            }
+            if cancel.is_cancelled() {
+                break;
+            }
+            let keep_pulling = match pull_cancel::wait_readable(stream, pull_cancel::POLL_INTERVAL)
+            {
+                Ok(true) => {
+                    if let Some(ref mut hb) = heartbeat {
+                        hb.on_receive(std::time::Instant::now());
+                    }
+                    read_during_pull(stream, timeouts, image)
+                }
+                Ok(false) => true,
+                Err(_) => false,
+            } && check_heartbeat(stream, None, &mut heartbeat).is_ok();
+            if !keep_pulling {
+                info!(image = %image, "cancelling pull");
+                cancel.cancel();
+            }
+        }
+
+        pull.join()
+            .unwrap_or_else(|_| Err(storage::StorageError::new("pull thread panicked")))
+    });
+    for progress in progress_rx.try_iter() {
+        let _ = send_response(stream, &progress);
+    }
+
+    let response = match result {
+        Err(
+            e @ (storage::StorageError::InsufficientSpace { .. }
+            | storage::StorageError::InsufficientInodes { .. }),
+        ) => AgentResponse::error(e.to_string(), error_codes::NO_SPACE),
+        Err(
+            ref e @ storage::StorageError::PlatformUnavailable {
+                ref requested,
+                ref available,
+            },
+        ) => AgentResponse::error_with_data(
+            e.to_string(),
+            error_codes::PLATFORM_UNAVAILABLE,
+            serde_json::json!({
+                "platform": requested,
+                "available": available,
+            }),
+        ),
+        Err(e @ storage::StorageError::PullCancelled { .. }) => {
+            AgentResponse::error(e.to_string(), error_codes::PULL_CANCELLED)
+        }
+        result => AgentResponse::from_result(result, error_codes::PULL_FAILED),
+    };
+
+    // A disconnected client won't see the response
+    match send_response(stream, &response) {
+        Err(_) if cancel.is_cancelled() => Ok(()),
+        sent => sent,
+    }
+}
+
+/// Progress update for layer `current` of `total`.
+fn pull_progress(current: usize, total: usize, layer: &str) -> AgentResponse {
+    let percent = if total > 0 {
+        ((current as f64 / total as f64) * 100.0) as u8
+    } else {
+        0
+    };
+    AgentResponse::Progress {
+        message: format!("Pulling layer {}/{}", current, total),
+        percent: Some(percent),
+        layer: Some(layer.to_string()),
+    }
+}
+
+/// Read a frame the client sent while `image` is pulling. Returns whether
+/// the pull should go on: `false` on a `CancelPull`, a disconnect or an
+/// unreadable frame. A `Heartbeat` needs no answer. Any other request is
+/// answered with an
+/// [`error_codes::INVALID_REQUEST`] error, tagged with its own
+/// `request_id`, and the pull goes on.
+fn read_during_pull(stream: &mut impl ReadWrite, timeouts: &FrameTimeouts, image: &str) -> bool {
+    let mut header = [0u8; 4];
+    let started = match stream.read(&mut header) {
+        Ok(0) => {
+            info!(image = %image, "client disconnected during pull");
+            return false;
+        }
+        Ok(n) => n,
+        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => return true,
+        Err(e) => {
+            warn!(image = %image, error = %e, "connection failed during pull");
+            return false;
+        }
     };
+    if let Err(e) = read_exact_within(stream, &mut header[started..], timeouts.header) {
+        warn!(image = %image, error = %e, "incomplete frame during pull");
+        return false;
+    }
 
-    let response = AgentResponse::from_result(
-        storage::pull_image_with_progress_and_auth(image, oci_platform, auth, progress_callback),
-        error_codes::PULL_FAILED,
+    let len = u32::from_be_bytes(header) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        warn!(image = %image, len = len, "oversized frame during pull");
+        return false;
+    }
+    let mut buf = vec![0u8; len];
+    if let Err(e) = read_exact_within(stream, &mut buf, timeouts.body) {
+        warn!(image = %image, error = %e, "incomplete frame during pull");
+        return false;
+    }
+
+    let (request_id, message) = match decode_json::<RequestFrame>(&buf) {
+        Ok(RequestFrame {
+            request: AgentRequest::CancelPull { .. },
+            ..
+        }) => return false,
+        Ok(RequestFrame {
+            request: AgentRequest::Heartbeat,
+            ..
+        }) => return true,
+        Ok(frame) => {
+            warn!(
+                image = %image,
+                method = frame.request.method(),
+                "rejecting request sent during pull"
+            );
+            (
+                frame.request_id,
+                format!("only cancel_pull can be sent while {} is pulling", image),
+            )
+        }
+        Err(e) => {
+            warn!(image = %image, error = %e, "invalid request sent during pull");
+            (None, format!("invalid request: {}", e))
+        }
+    };
+
+    // Answer it under its own ID, not the pull's
+    let pull_request_id = RESPONSE_REQUEST_ID.replace(request_id);
+    let sent = send_response(
+        stream,
+        &AgentResponse::error(message, error_codes::INVALID_REQUEST),
     );
+    RESPONSE_REQUEST_ID.set(pull_request_id);
+    sent.is_ok()
+}
 
-    send_response(stream, &response)
+/// Cancel pulls of `image` running on other connections.
+fn handle_cancel_pull(image: &str) -> AgentResponse {
+    let cancelled = pull_cancel::cancel(image);
+    info!(image = %image, cancelled, "cancel pull requested");
+    AgentResponse::ok_with_data(serde_json::json!({ "cancelled": cancelled }))
 }
 
 /// Handle image query request.
@@ -1858,9 +2613,44 @@ fn handle_query(image: &str) -> AgentResponse {
     }
 }
 
+/// Handle query-by-digest request.
+fn handle_query_digest(digest: &str) -> AgentResponse {
+    AgentResponse::from_result(storage::query_digest(digest), error_codes::QUERY_FAILED)
+}
+
+/// Handle image tag request.
+fn handle_tag(source: &str, target: &str) -> AgentResponse {
+    info!(source = %source, target = %target, "tagging image");
+    match storage::tag_image(source, target) {
+        Ok(Some(info)) => AgentResponse::ok_with_data(info),
+        Ok(None) => AgentResponse::error(
+            format!("image not found: {}", source),
+            error_codes::NOT_FOUND,
+        ),
+        Err(e) => AgentResponse::from_err(e, error_codes::TAG_FAILED),
+    }
+}
+
 /// Handle list images request.
-fn handle_list_images() -> AgentResponse {
-    AgentResponse::from_result(storage::list_images(), error_codes::LIST_FAILED)
+///
+/// With a `limit`, answers with one [`ImagePage`].
+fn handle_list_images(offset: usize, limit: Option<usize>) -> AgentResponse {
+    let images = match storage::list_images() {
+        Ok(images) => images,
+        Err(e) => return AgentResponse::from_err(e, error_codes::LIST_FAILED),
+    };
+    let total = images.len();
+    let page = images
+        .into_iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX));
+    match limit {
+        Some(_) => AgentResponse::ok_with_data(ImagePage {
+            images: page.collect(),
+            total,
+        }),
+        None => AgentResponse::ok_with_data(page.collect::<Vec<_>>()),
+    }
 }
 
 /// Handle garbage collection request.
@@ -1874,6 +2664,27 @@ fn handle_gc(dry_run: bool) -> AgentResponse {
     }
 }
 
+/// Handle image removal request.
+fn handle_remove_image(image: &str) -> AgentResponse {
+    info!(image = %image, "removing image");
+    let container_images: Vec<String> = container::REGISTRY
+        .list()
+        .into_iter()
+        .map(|c| c.image)
+        .collect();
+    match storage::remove_image(image, &container_images) {
+        Ok(true) => AgentResponse::ok(None),
+        Ok(false) => AgentResponse::error(
+            format!("image not found: {}", image),
+            error_codes::NOT_FOUND,
+        ),
+        Err(e @ storage::StorageError::ImageInUse { .. }) => {
+            AgentResponse::error(e.to_string(), error_codes::IMAGE_IN_USE)
+        }
+        Err(e) => AgentResponse::from_err(e, error_codes::GC_FAILED),
+    }
+}
+
 /// Handle overlay preparation request.
 fn handle_prepare_overlay(image: &str, workload_id: &str) -> AgentResponse {
     info!(image = %image, workload_id = %workload_id, "preparing overlay");
@@ -1892,11 +2703,66 @@ fn handle_cleanup_overlay(workload_id: &str) -> AgentResponse {
     }
 }
 
+/// Handle overlay diff request.
+fn handle_diff_overlay(container_id: &str) -> AgentResponse {
+    let Some(info) = container::REGISTRY.find_by_prefix(container_id) else {
+        return AgentResponse::error(
+            format!("container not found: {}", container_id),
+            error_codes::NOT_FOUND,
+        );
+    };
+    match storage::diff_overlay(&format!("container-{}", info.id)) {
+        Ok(entries) => AgentResponse::ok_with_data(entries),
+        Err(e @ storage::StorageError::OverlayNotFound { .. }) => {
+            AgentResponse::error(e.to_string(), error_codes::NOT_FOUND)
+        }
+        Err(e @ storage::StorageError::ValidationFailed { .. }) => {
+            AgentResponse::error(e.to_string(), error_codes::INVALID_REQUEST)
+        }
+        Err(e) => AgentResponse::from_err(e, error_codes::OVERLAY_FAILED),
+    }
+}
+
+/// Handle orphaned overlay pruning request.
+fn handle_prune_overlays(dry_run: bool) -> AgentResponse {
+    let exists = |id: &str| container::REGISTRY.get(id).is_some();
+    AgentResponse::from_result(
+        storage::prune_overlays(dry_run, exists),
+        error_codes::OVERLAY_FAILED,
+    )
+}
+
+/// Handle named volume creation request.
+fn handle_create_volume(name: &str, size_mib: Option<u64>) -> AgentResponse {
+    info!(name = %name, size_mib = ?size_mib, "creating volume");
+    AgentResponse::from_result(
+        volume::create_volume(name, size_mib),
+        error_codes::VOLUME_FAILED,
+    )
+}
+
+/// Handle list volumes request.
+fn handle_list_volumes() -> AgentResponse {
+    AgentResponse::from_result(volume::list_volumes(), error_codes::LIST_FAILED)
+}
+
+/// Handle named volume removal request.
+fn handle_remove_volume(name: &str) -> AgentResponse {
+    info!(name = %name, "removing volume");
+    match volume::remove_volume(name) {
+        Ok(()) => AgentResponse::ok(None),
+        Err(e) => AgentResponse::from_err(e, error_codes::VOLUME_FAILED),
+    }
+}
+
 /// Handle storage format request.
-fn handle_format_storage() -> AgentResponse {
-    info!("formatting storage");
-    match storage::format() {
+fn handle_format_storage(force: bool) -> AgentResponse {
+    info!(force, "formatting storage");
+    match storage::format(force) {
         Ok(_) => AgentResponse::ok(None),
+        Err(e @ storage::StorageError::AlreadyFormatted { .. }) => {
+            AgentResponse::from_err(e, error_codes::ALREADY_FORMATTED)
+        }
         Err(e) => AgentResponse::from_err(e, error_codes::FORMAT_FAILED),
     }
 }
@@ -2011,11 +2877,212 @@ fn handle_streaming_export_layer(
     Ok(())
 }
 
+/// Handle save image request.
+///
+/// Builds the archive, answers `Ok` with its size, then streams it as a
+/// chunked transfer. A failure mid-stream can't be reported in-band, so it
+/// closes the connection.
+fn handle_streaming_save_image(
+    stream: &mut impl Write,
+    image: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    info!(image = %image, "saving image");
+    let archive = match storage::save_image(image) {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            send_response(
+                stream,
+                &AgentResponse::error(
+                    format!("image not found: {}", image),
+                    error_codes::NOT_FOUND,
+                ),
+            )?;
+            return Ok(());
+        }
+        Err(e) => {
+            send_response(
+                stream,
+                &AgentResponse::from_err(e, error_codes::EXPORT_FAILED),
+            )?;
+            return Ok(());
+        }
+    };
+
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = std::fs::File::open(&archive)?;
+        let size = file.metadata()?.len();
+        send_response(
+            stream,
+            &AgentResponse::ok(Some(serde_json::json!({ "size": size }))),
+        )?;
+        chunked::copy_to_chunked(&mut file, stream, size)?;
+        Ok(())
+    })();
+    let _ = std::fs::remove_file(&archive);
+    result
+}
+
+/// Handle load image request.
+///
+/// Receives the archive that follows the request into the storage disk's
+/// scratch space, then imports it. A transfer that breaks off leaves the
+/// stream out of step, so the connection is closed after reporting it.
+fn handle_streaming_load_image(
+    stream: &mut impl ReadWrite,
+    timeouts: &FrameTimeouts,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let archive = match storage::archive_path("load") {
+        Ok(path) => path,
+        Err(e) => {
+            send_response(
+                stream,
+                &AgentResponse::from_err(e, error_codes::IMPORT_FAILED),
+            )?;
+            // The archive is already on its way; skipping it would leave
+            // the stream out of step
+            return Err("could not receive image archive".into());
+        }
+    };
+
+    let received = std::fs::File::create(&archive)
+        .map_err(chunked::ChunkError::from)
+        .and_then(|mut file| {
+            let mut reader = TimedReader {
+                stream: &mut *stream,
+                timeout: timeouts.body,
+            };
+            let max_len = storage::max_load_archive_bytes().unwrap_or(u64::MAX);
+            chunked::copy_from_chunked(&mut reader, &mut file, max_len)
+        });
+    let size = match received {
+        Ok(size) => size,
+        // Refused on the header, before any of the archive is stored
+        Err(chunked::ChunkError::TooLarge { total_len, max_len }) => {
+            let _ = std::fs::remove_file(&archive);
+            warn!(
+                size = total_len,
+                max_len, "image archive too large for free space, closing connection"
+            );
+            send_response(
+                stream,
+                &AgentResponse::error(
+                    format!(
+                        "image archive is {} bytes, but loading it needs twice that \
+                         free on the storage disk ({} bytes free)",
+                        total_len,
+                        max_len.saturating_mul(2)
+                    ),
+                    error_codes::NO_SPACE,
+                ),
+            )?;
+            return Err("image archive too large".into());
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&archive);
+            warn!(error = %e, "image archive transfer failed, closing connection");
+            send_response(
+                stream,
+                &AgentResponse::error(
+                    format!("failed to receive image archive: {}", e),
+                    error_codes::IMPORT_FAILED,
+                ),
+            )?;
+            return Err(e.into());
+        }
+    };
+
+    info!(size, "loading image archive");
+    let response = match storage::load_image(&archive) {
+        Err(
+            e @ (storage::StorageError::InsufficientSpace { .. }
+            | storage::StorageError::InsufficientInodes { .. }),
+        ) => AgentResponse::error(e.to_string(), error_codes::NO_SPACE),
+        result => AgentResponse::from_result(result, error_codes::IMPORT_FAILED),
+    };
+    let _ = std::fs::remove_file(&archive);
+    send_response(stream, &response)
+}
+
+/// Reads from the connection, failing with `TimedOut` if the client sends
+/// nothing for `timeout`.
+struct TimedReader<'a, S> {
+    stream: &'a mut S,
+    timeout: std::time::Duration,
+}
+
+impl<S: ReadWrite> Read for TimedReader<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if !pull_cancel::wait_readable(self.stream, self.timeout)? {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "client stopped sending",
+            ));
+        }
+        self.stream.read(buf)
+    }
+}
+
+/// Handle agent status request.
+fn handle_status() -> AgentResponse {
+    let uptime = STARTED_AT.get_or_init(std::time::Instant::now).elapsed();
+    AgentResponse::from_result(storage::agent_status(uptime), error_codes::STATUS_FAILED)
+}
+
 /// Handle storage status request.
 fn handle_storage_status() -> AgentResponse {
     AgentResponse::from_result(storage::status(), error_codes::STATUS_FAILED)
 }
 
+/// Handle per-layer disk usage request.
+fn handle_disk_usage() -> AgentResponse {
+    AgentResponse::from_result(storage::disk_usage(), error_codes::STATUS_FAILED)
+}
+
+/// Handle image verification request.
+fn handle_verify(image: Option<&str>, remove_corrupt: bool) -> AgentResponse {
+    info!(image = ?image, remove_corrupt = remove_corrupt, "verifying images");
+    match storage::verify_images(image, remove_corrupt) {
+        Ok(Some(reports)) => AgentResponse::ok_with_data(reports),
+        Ok(None) => AgentResponse::error(
+            format!("image not found: {}", image.unwrap_or_default()),
+            error_codes::NOT_FOUND,
+        ),
+        Err(e) => AgentResponse::from_err(e, error_codes::VERIFY_FAILED),
+    }
+}
+
+/// Handle guest stop request: terminate everything running, then sync.
+///
+/// Stop doesn't wait for the request lock, so requests may still be using
+/// storage; it is only synced here, and left mounted for `Shutdown`.
+fn handle_stop(timeout_ms: u64) -> AgentResponse {
+    info!(timeout_ms, "stop requested");
+    let containers = container::mark_all_stopped();
+    let outcome = process::terminate_all(
+        &process::guest_processes(),
+        std::time::Duration::from_millis(timeout_ms),
+    );
+    info!(
+        containers,
+        terminated = outcome.terminated,
+        killed = outcome.killed,
+        "guest processes stopped"
+    );
+    // SAFETY: sync() is always safe to call
+    unsafe {
+        libc::sync();
+    }
+    AgentResponse::ok_with_data(serde_json::json!({
+        "terminated": outcome.terminated,
+        "killed": outcome.killed,
+    }))
+}
+
+/// Handle resource usage sample request.
+fn handle_stats(container_id: Option<&str>) -> AgentResponse {
+    AgentResponse::from_result(stats::sample(container_id), error_codes::STATUS_FAILED)
+}
+
 // ============================================================================
 // VM-Level Exec Handlers (Direct Execution in VM)
 // ============================================================================
@@ -2069,22 +3136,18 @@ fn handle_vm_exec(
         // Check if process has exited
         match child.try_wait() {
             Ok(Some(status)) => {
-                // Process exited, collect output (capped at 16 MiB to prevent OOM)
-                const MAX_OUTPUT: usize = 16 * 1024 * 1024;
-                let mut stdout = String::new();
-                let mut stderr = String::new();
-
-                if let Some(mut out) = child.stdout.take() {
-                    let _ = out.take(MAX_OUTPUT as u64).read_to_string(&mut stdout);
-                }
-                if let Some(mut err) = child.stderr.take() {
-                    let _ = err.take(MAX_OUTPUT as u64).read_to_string(&mut stderr);
-                }
+                // Process exited, collect output (capped to prevent OOM)
+                let output = process::capture_child_output(&mut child);
 
                 return AgentResponse::Completed {
                     exit_code: status.code().unwrap_or(-1),
-                    stdout,
-                    stderr,
+                    stdout: output.stdout,
+                    stderr: output.stderr,
+                    stdout_truncated: output.stdout_truncated,
+                    stderr_truncated: output.stderr_truncated,
+                    reason: status
+                        .signal()
+                        .map_or(ExitReason::Exited, |signal| ExitReason::Signaled { signal }),
                 };
             }
             Ok(None) => {
@@ -2104,6 +3167,9 @@ fn handle_vm_exec(
                             exit_code: 124, // Standard timeout exit code
                             stdout: String::new(),
                             stderr: "command timed out".to_string(),
+                            stdout_truncated: false,
+                            stderr_truncated: false,
+                            reason: ExitReason::TimedOut,
                         };
                     }
                 }
@@ -2123,16 +3189,18 @@ fn handle_vm_exec(
 fn handle_interactive_vm_exec(
     stream: &mut impl ReadWrite,
     request: AgentRequest,
+    serialized: &mut Serialized,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let (command, env, workdir, timeout_ms, tty) = match request {
+    let (command, env, workdir, timeout_ms, tty, heartbeat) = match request {
         AgentRequest::VmExec {
             command,
             env,
             workdir,
             timeout_ms,
             tty,
+            heartbeat,
             ..
-        } => (command, env, workdir, timeout_ms, tty),
+        } => (command, env, workdir, timeout_ms, tty, heartbeat),
         _ => {
             send_response(
                 stream,
@@ -2166,14 +3234,22 @@ fn handle_interactive_vm_exec(
         };
 
     // Send Started response
+    serialized.release();
     send_response(stream, &AgentResponse::Started)?;
 
     // Run the appropriate interactive I/O loop
-    let exit_code = match pty_master {
+    let result = match pty_master {
         #[cfg(target_os = "linux")]
-        Some(pty) => run_interactive_loop_pty(stream, &mut child, pty, timeout_ms)?,
-        _ => run_interactive_loop(stream, &mut child, timeout_ms)?,
+        Some(pty) => run_interactive_loop_pty(stream, &mut child, pty, timeout_ms, heartbeat),
+        _ => run_interactive_loop(
+            stream,
+            &mut child,
+            SignalTarget::Child,
+            timeout_ms,
+            heartbeat,
+        ),
     };
+    let exit_code = kill_on_error(result, &mut child)?;
 
     // Send Exited response
     send_response(stream, &AgentResponse::Exited { exit_code })?;
@@ -2276,10 +3352,11 @@ fn handle_create_container(
     env: &[(String, String)],
     workdir: Option<&str>,
     mounts: &[(String, String, bool)],
+    options: container::ContainerOptions,
 ) -> AgentResponse {
-    info!(image = %image, command = ?command, "creating container");
+    info!(image = %image, command = ?command, restart_policy = %options.restart_policy, "creating container");
 
-    match container::create_container(image, command, env, workdir, mounts) {
+    match container::create_container(image, command, env, workdir, mounts, options) {
         Ok(info) => {
             // Also start the container immediately
             if let Err(e) = container::start_container(&info.id) {
@@ -2290,15 +3367,9 @@ fn handle_create_container(
                 );
             }
 
-            let container_info = ContainerInfo {
-                id: info.id,
-                image: info.image,
-                state: "running".to_string(),
-                created_at: info.created_at,
-                command: info.command,
-            };
-
-            AgentResponse::ok_with_data(container_info)
+            // Report the state start_container left it in
+            let info = container::REGISTRY.get(&info.id).unwrap_or(info);
+            AgentResponse::ok_with_data(container_info(info))
         }
         Err(e) => AgentResponse::from_err(e, error_codes::CREATE_FAILED),
     }
@@ -2323,41 +3394,62 @@ fn handle_stop_container(container_id: &str, timeout_secs: u64) -> AgentResponse
 fn handle_delete_container(container_id: &str, force: bool) -> AgentResponse {
     info!(container_id = %container_id, force = force, "deleting container");
     match container::delete_container(container_id, force) {
-        Ok(()) => AgentResponse::ok(None),
+        Ok(freed) => AgentResponse::ok_with_data(serde_json::json!({ "freed_bytes": freed })),
         Err(e) => AgentResponse::from_err(e, error_codes::DELETE_FAILED),
     }
 }
 
 fn handle_list_containers() -> AgentResponse {
     let containers = container::list_containers();
-    let infos: Vec<ContainerInfo> = containers
-        .into_iter()
-        .map(|c| ContainerInfo {
-            id: c.id,
-            image: c.image,
-            state: c.state.to_string(),
-            created_at: c.created_at,
-            command: c.command,
-        })
-        .collect();
+    let infos: Vec<ContainerInfo> = containers.into_iter().map(container_info).collect();
 
     AgentResponse::ok_with_data(infos)
 }
 
+/// The host's view of a registered container.
+fn container_info(c: container::ContainerInfo) -> ContainerInfo {
+    ContainerInfo {
+        status: c.status(),
+        id: c.id,
+        image: c.image,
+        state: c.state.to_string(),
+        created_at: c.created_at,
+        command: c.command,
+        restart_count: c.restart_count,
+        started_at: c.started_at,
+        finished_at: c.finished_at,
+        labels: c.labels,
+        health: c.health,
+    }
+}
+
 fn handle_exec(
     container_id: &str,
     command: &[String],
     env: &[(String, String)],
     workdir: Option<&str>,
     timeout_ms: Option<u64>,
+    security: &SecurityOptions,
+    user: Option<&str>,
 ) -> AgentResponse {
     info!(container_id = %container_id, command = ?command, "executing in container");
 
-    match container::exec_in_container(container_id, command, env, workdir, timeout_ms) {
+    match container::exec_in_container(
+        container_id,
+        command,
+        env,
+        workdir,
+        timeout_ms,
+        security,
+        user,
+    ) {
         Ok(result) => AgentResponse::Completed {
             exit_code: result.exit_code,
             stdout: result.stdout,
             stderr: result.stderr,
+            stdout_truncated: result.stdout_truncated,
+            stderr_truncated: result.stderr_truncated,
+            reason: result.reason,
         },
         Err(e) => AgentResponse::from_err(e, error_codes::EXEC_FAILED),
     }
@@ -2367,8 +3459,20 @@ fn handle_exec(
 fn handle_interactive_container_exec(
     stream: &mut impl ReadWrite,
     request: AgentRequest,
+    serialized: &mut Serialized,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let (container_id, command, env, workdir, timeout_ms, tty) = match request {
+    let (
+        container_id,
+        command,
+        env,
+        workdir,
+        timeout_ms,
+        tty,
+        heartbeat,
+        security,
+        user,
+        resumable,
+    ) = match request {
         AgentRequest::Exec {
             container_id,
             command,
@@ -2376,8 +3480,23 @@ fn handle_interactive_container_exec(
             workdir,
             timeout_ms,
             tty,
+            heartbeat,
+            security,
+            user,
+            resumable,
             ..
-        } => (container_id, command, env, workdir, timeout_ms, tty),
+        } => (
+            container_id,
+            command,
+            env,
+            workdir,
+            timeout_ms,
+            tty,
+            heartbeat,
+            security,
+            user,
+            resumable,
+        ),
         _ => {
             send_response(
                 stream,
@@ -2389,15 +3508,18 @@ fn handle_interactive_container_exec(
 
     info!(container_id = %container_id, command = ?command, tty = tty, "starting interactive container exec");
 
-    // Spawn the interactive exec process
-    let mut child = match container::spawn_interactive_exec(
+    // Spawn the interactive exec process. The process file, if any, must
+    // outlive `crun exec`.
+    let (mut child, process_file) = match container::spawn_interactive_exec(
         &container_id,
         &command,
         &env,
         workdir.as_deref(),
         tty,
+        &security,
+        user.as_deref(),
     ) {
-        Ok(child) => child,
+        Ok(spawned) => spawned,
         Err(e) => {
             send_response(
                 stream,
@@ -2407,11 +3529,100 @@ fn handle_interactive_container_exec(
         }
     };
 
-    // Send Started response
-    send_response(stream, &AgentResponse::Started)?;
+    let io = match exec_session::SessionIo::take(&mut child, timeout_ms) {
+        Ok(io) => io,
+        Err(e) => {
+            kill_and_reap(&mut child);
+            send_response(
+                stream,
+                &AgentResponse::from_err(e, error_codes::EXEC_FAILED),
+            )?;
+            return Ok(());
+        }
+    };
 
-    // Run the interactive I/O loop
-    let exit_code = run_interactive_loop(stream, &mut child, timeout_ms)?;
+    // A resumable exec gets an ID the host can ask for it by from a new
+    // connection
+    let session_id = resumable.then(exec_session::new_session_id);
+    serialized.release();
+    let started = match session_id {
+        Some(session_id) => send_response(stream, &AgentResponse::ExecSession { session_id })
+            .and_then(|()| send_response(stream, &AgentResponse::Started)),
+        None => send_response(stream, &AgentResponse::Started),
+    };
+    if let Err(e) = started {
+        kill_and_reap(&mut child);
+        return Err(e);
+    }
+
+    run_exec_session(
+        stream,
+        exec_session::ParkedExec {
+            child,
+            io,
+            heartbeat,
+            _process_file: process_file,
+        },
+        session_id,
+    )
+}
+
+/// Handle `ResumeExec`: carry on with a parked exec session on this
+/// connection.
+fn handle_resume_exec(
+    stream: &mut impl ReadWrite,
+    session_id: u64,
+    serialized: &mut Serialized,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(session) = exec_session::take(session_id) else {
+        send_response(
+            stream,
+            &AgentResponse::error(
+                format!("no exec session {} to resume", session_id),
+                error_codes::NOT_FOUND,
+            ),
+        )?;
+        return Ok(());
+    };
+
+    info!(session_id, "resuming interactive container exec");
+    serialized.release();
+    if let Err(e) = send_response(stream, &AgentResponse::Started) {
+        exec_session::park(session_id, session, exec_session::RESUME_GRACE);
+        return Err(e);
+    }
+    run_exec_session(stream, session, Some(session_id))
+}
+
+/// Run an interactive container exec's I/O loop until it exits.
+///
+/// If the connection fails, a session with an ID is parked to be resumed
+/// rather than killed.
+fn run_exec_session(
+    stream: &mut impl ReadWrite,
+    mut session: exec_session::ParkedExec,
+    session_id: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // `crun kill` targets the container's init rather than the exec'd
+    // command, so signals go to the `crun exec` process itself.
+    let result = run_session_loop(
+        stream,
+        &mut session.child,
+        &mut session.io,
+        SignalTarget::Child,
+        session.heartbeat,
+        session_id.is_none(),
+    );
+
+    let exit_code = match (result, session_id) {
+        (Ok(exit_code), _) => exit_code,
+        (Err(e), Some(id)) => {
+            warn!(error = %e, session_id = id, "interactive session disconnected, parking it");
+            exec_session::park(id, session, exec_session::RESUME_GRACE);
+            return Ok(());
+        }
+        (Err(e), None) => return kill_on_error(Err(e), &mut session.child),
+    };
 
     // Send Exited response
     send_response(stream, &AgentResponse::Exited { exit_code })?;
@@ -2424,7 +3635,11 @@ fn send_response(
     stream: &mut impl Write,
     response: &AgentResponse,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let json = serde_json::to_vec(response)?;
+    let frame = ResponseFrame {
+        request_id: RESPONSE_REQUEST_ID.get(),
+        response,
+    };
+    let json = serde_json::to_vec(&frame)?;
     let len = json.len() as u32;
 
     stream.write_all(&len.to_be_bytes())?;
@@ -2435,6 +3650,788 @@ fn send_response(
     Ok(())
 }
 
+/// Fill `buf` from `stream`, failing with `TimedOut` if that takes longer
+/// than `timeout`.
+///
+/// The stream stays blocking; each read waits for [`pull_cancel::wait_readable`] to
+/// report data, so it can't block past the deadline.
+fn read_exact_within(
+    stream: &mut impl ReadWrite,
+    buf: &mut [u8],
+    timeout: std::time::Duration,
+) -> std::io::Result<()> {
+    let deadline = std::time::Instant::now() + timeout;
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("read {} of {} bytes before timing out", filled, buf.len()),
+            ));
+        }
+
+        if !pull_cancel::wait_readable(stream, remaining)? {
+            continue;
+        }
+
+        match stream.read(&mut buf[filled..]) {
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Log and describe a request frame the client didn't finish sending.
+fn stalled_frame(part: &str, e: std::io::Error) -> Box<dyn std::error::Error> {
+    warn!(part = part, error = %e, "incomplete request frame, closing connection");
+    format!("incomplete request {}: {}", part, e).into()
+}
+
 /// Trait for read+write streams with raw fd access.
 trait ReadWrite: Read + Write + AsRawFd {}
 impl<T: Read + Write + AsRawFd> ReadWrite for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use std::time::{Duration, Instant};
+
+    const TEST_TIMEOUTS: FrameTimeouts = FrameTimeouts {
+        header: Duration::from_millis(100),
+        body: Duration::from_millis(200),
+    };
+
+    /// Serve `agent_end` on a thread, returning how the connection ended.
+    fn serve(
+        agent_end: UnixStream,
+        limits: ConnectionLimits,
+    ) -> std::thread::JoinHandle<(Result<(), String>, Duration)> {
+        std::thread::spawn(move || {
+            let mut agent_end = agent_end;
+            let start = Instant::now();
+            let result = handle_connection(&mut agent_end, &TEST_TIMEOUTS, &limits)
+                .map_err(|e| e.to_string());
+            (result, start.elapsed())
+        })
+    }
+
+    /// Host end of a connection served by [`handle_connection`] over an
+    /// in-memory socket pair.
+    struct TestHost {
+        stream: UnixStream,
+        server: std::thread::JoinHandle<(Result<(), String>, Duration)>,
+    }
+
+    impl TestHost {
+        fn connect() -> Self {
+            Self::connect_with_limits(ConnectionLimits::default())
+        }
+
+        fn connect_with_limits(limits: ConnectionLimits) -> Self {
+            let (agent_end, stream) = UnixStream::pair().unwrap();
+            Self {
+                stream,
+                server: serve(agent_end, limits),
+            }
+        }
+
+        /// Host end of a pull of `image` that streams one progress update
+        /// and then runs until it is cancelled.
+        fn pulling(image: &'static str, heartbeat: Option<HeartbeatConfig>) -> Self {
+            let (mut agent_end, stream) = UnixStream::pair().unwrap();
+            let server = std::thread::spawn(move || {
+                let start = Instant::now();
+                let result = stream_pull(
+                    &mut agent_end,
+                    &TEST_TIMEOUTS,
+                    image,
+                    heartbeat,
+                    |cancel, progress| {
+                        progress(1, 2, "sha256:aaa");
+                        let deadline = Instant::now() + Duration::from_secs(5);
+                        while Instant::now() < deadline {
+                            cancel.check(image)?;
+                            std::thread::sleep(Duration::from_millis(10));
+                        }
+                        Err(storage::StorageError::new("pull was never cancelled"))
+                    },
+                )
+                .map_err(|e| e.to_string());
+                (result, start.elapsed())
+            });
+            Self { stream, server }
+        }
+
+        fn send(&mut self, request: &AgentRequest) {
+            let frame = smolvm_protocol::encode_message(request).unwrap();
+            self.stream.write_all(&frame).unwrap();
+        }
+
+        fn send_raw(&mut self, payload: &[u8]) {
+            self.stream
+                .write_all(&(payload.len() as u32).to_be_bytes())
+                .unwrap();
+            self.stream.write_all(payload).unwrap();
+        }
+
+        fn recv(&mut self) -> AgentResponse {
+            self.recv_frame().response
+        }
+
+        fn recv_frame(&mut self) -> ResponseFrame {
+            let mut header = [0u8; 4];
+            self.stream.read_exact(&mut header).unwrap();
+            let mut buf = vec![0u8; u32::from_be_bytes(header) as usize];
+            self.stream.read_exact(&mut buf).unwrap();
+            serde_json::from_slice(&buf).unwrap()
+        }
+
+        /// Whether the agent has closed its end.
+        fn is_closed(&mut self) -> bool {
+            let mut byte = [0u8; 1];
+            self.stream.read(&mut byte).unwrap() == 0
+        }
+
+        /// Close the host end and wait for the handler to return.
+        fn finish(self) -> Result<(), String> {
+            drop(self.stream);
+            self.server.join().unwrap().0
+        }
+    }
+
+    #[test]
+    fn test_connection_answers_ping() {
+        let mut host = TestHost::connect();
+        host.send(&AgentRequest::Ping);
+        let AgentResponse::Pong {
+            version,
+            capabilities,
+        } = host.recv()
+        else {
+            panic!("expected Pong");
+        };
+        assert_eq!(version, PROTOCOL_VERSION);
+        assert_eq!(capabilities.len(), capabilities::ALL.len());
+        assert!(host.finish().is_ok());
+    }
+
+    #[test]
+    fn test_connection_survives_invalid_request() {
+        let mut host = TestHost::connect();
+        for payload in [&b"not json"[..], br#"{"method": "no_such_method"}"#] {
+            host.send_raw(payload);
+            let AgentResponse::Error { message, code, .. } = host.recv() else {
+                panic!("expected an error for {:?}", payload);
+            };
+            assert_eq!(code.as_deref(), Some(error_codes::INVALID_REQUEST));
+            assert!(message.starts_with("invalid request"), "{}", message);
+        }
+
+        // The connection is still usable
+        host.send(&AgentRequest::Ping);
+        assert!(matches!(host.recv(), AgentResponse::Pong { .. }));
+        assert!(host.finish().is_ok());
+    }
+
+    #[test]
+    fn test_connection_ends_after_shutdown() {
+        let mut host = TestHost::connect();
+        host.send(&AgentRequest::Shutdown);
+        let AgentResponse::Ok { data: Some(data) } = host.recv() else {
+            panic!("expected Ok with data");
+        };
+        assert_eq!(data["shutdown"], true);
+
+        // The agent closes the connection without waiting for the host
+        assert!(host.is_closed());
+        assert!(host.finish().is_ok());
+    }
+
+    #[test]
+    fn test_connection_closed_after_max_requests() {
+        let mut host = TestHost::connect_with_limits(ConnectionLimits {
+            max_requests: Some(2),
+            max_lifetime: None,
+        });
+
+        // Pings don't count towards the limit
+        host.send(&AgentRequest::Ping);
+        assert!(matches!(host.recv(), AgentResponse::Pong { .. }));
+        for _ in 0..2 {
+            host.send(&AgentRequest::ListVolumes);
+            host.recv();
+        }
+
+        assert!(host.is_closed());
+        assert!(host.finish().is_ok());
+    }
+
+    #[test]
+    fn test_idle_connection_closed_after_max_lifetime() {
+        let mut host = TestHost::connect_with_limits(ConnectionLimits {
+            max_requests: None,
+            max_lifetime: Some(Duration::from_millis(200)),
+        });
+        host.send(&AgentRequest::Ping);
+        assert!(matches!(host.recv(), AgentResponse::Pong { .. }));
+
+        // Nothing more is sent; the agent closes once the lifetime is up
+        assert!(host.is_closed());
+        let (result, elapsed) = host.server.join().unwrap();
+        assert!(result.is_ok());
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn test_exec_rejects_resource_limits() {
+        let exec = |memory_mib, cpu_quota, interactive| AgentRequest::Exec {
+            container_id: "no-such-container".into(),
+            command: vec!["true".into()],
+            env: Vec::new(),
+            workdir: None,
+            timeout_ms: None,
+            interactive,
+            tty: false,
+            heartbeat: None,
+            memory_mib,
+            cpu_quota,
+            security: SecurityOptions::default(),
+            user: None,
+            resumable: false,
+        };
+
+        // Refused before the container is looked up or touched, so its own
+        // limits stay as they were
+        let mut host = TestHost::connect();
+        for request in [
+            exec(Some(64), None, false),
+            exec(None, Some(0.5), false),
+            exec(Some(64), Some(0.5), true),
+        ] {
+            host.send(&request);
+            let AgentResponse::Error { message, code, .. } = host.recv() else {
+                panic!("expected an error for {:?}", request);
+            };
+            assert_eq!(code.as_deref(), Some(error_codes::INVALID_REQUEST));
+            assert!(message.contains("whole container"), "{}", message);
+        }
+
+        // Without limits the exec gets as far as looking for the container
+        host.send(&exec(None, None, false));
+        let AgentResponse::Error { message, code, .. } = host.recv() else {
+            panic!("expected an error");
+        };
+        assert_eq!(code.as_deref(), Some(error_codes::EXEC_FAILED));
+        assert!(message.contains("not found"), "{}", message);
+        assert!(host.finish().is_ok());
+    }
+
+    #[test]
+    fn test_connection_dispatches_interactive_requests() {
+        let mut host = TestHost::connect();
+        host.send(&AgentRequest::VmExec {
+            command: vec!["sh".into(), "-c".into(), "echo hello; exit 3".into()],
+            env: Vec::new(),
+            workdir: None,
+            timeout_ms: Some(5000),
+            interactive: true,
+            tty: false,
+            heartbeat: None,
+        });
+        assert!(matches!(host.recv(), AgentResponse::Started));
+        let mut stdout = Vec::new();
+        let exit_code = loop {
+            match host.recv() {
+                AgentResponse::Stdout { data } => stdout.extend_from_slice(&data),
+                AgentResponse::Stderr { .. } => {}
+                AgentResponse::Exited { exit_code } => break exit_code,
+                other => panic!("unexpected response: {:?}", other),
+            }
+        };
+        assert_eq!(stdout, b"hello\n");
+        assert_eq!(exit_code, 3);
+
+        // Back to request/response once the session ends
+        host.send(&AgentRequest::Ping);
+        assert!(matches!(host.recv(), AgentResponse::Pong { .. }));
+        assert!(host.finish().is_ok());
+    }
+
+    #[test]
+    fn test_requests_served_while_interactive_session_open() {
+        let mut session = TestHost::connect();
+        session.send(&AgentRequest::VmExec {
+            command: vec!["cat".into()],
+            env: Vec::new(),
+            workdir: None,
+            timeout_ms: Some(10_000),
+            interactive: true,
+            tty: false,
+            heartbeat: None,
+        });
+        assert!(matches!(session.recv(), AgentResponse::Started));
+
+        // A request that takes the request lock is answered on another
+        // connection while the session is still open
+        let mut other = TestHost::connect();
+        other
+            .stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        other.send(&AgentRequest::ListVolumes);
+        other.recv();
+        assert!(other.finish().is_ok());
+
+        // Closing stdin ends the session
+
+        session.send(&AgentRequest::Stdin { data: Vec::new() });
+        loop {
+            match session.recv() {
+                AgentResponse::Exited { exit_code } => break assert_eq!(exit_code, 0),
+                AgentResponse::Stdout { .. } | AgentResponse::Stderr { .. } => {}
+                other => panic!("unexpected response: {:?}", other),
+            }
+        }
+        assert!(session.finish().is_ok());
+    }
+
+    #[test]
+    fn test_connection_multiplexes_tagged_execs() {
+        let exec = |request_id, script: &str, interactive| RequestFrame {
+            request_id: Some(request_id),
+            request: AgentRequest::VmExec {
+                command: vec!["sh".into(), "-c".into(), script.into()],
+                env: Vec::new(),
+                workdir: None,
+                timeout_ms: Some(5000),
+                interactive,
+                tty: false,
+                heartbeat: None,
+            },
+        };
+
+        // Two execs sent back to back on one connection
+        let mut host = TestHost::connect();
+        for frame in [
+            exec(1, "echo one", false),
+            exec(2, "echo two; exit 2", false),
+        ] {
+            host.send_raw(&serde_json::to_vec(&frame).unwrap());
+        }
+
+        // Frames route by ID whichever order they are asked for in
+        let mut demux = smolvm_protocol::Demultiplexer::new();
+        for (id, stdout, exit) in [(2, "two\n", 2), (1, "one\n", 0)] {
+            let response = demux
+                .recv(Some(id), || Ok::<_, ()>(host.recv_frame()))
+                .unwrap();
+            let AgentResponse::Completed {
+                stdout: out,
+                exit_code,
+                ..
+            } = response
+            else {
+                panic!("expected Completed for {}, got {:?}", id, response);
+            };
+            assert_eq!((out.as_str(), exit_code), (stdout, exit));
+        }
+
+        // Every streaming frame of a tagged interactive exec carries its ID
+        host.send_raw(&serde_json::to_vec(&exec(3, "echo three", true)).unwrap());
+        loop {
+            let frame = host.recv_frame();
+            assert_eq!(frame.request_id, Some(3), "{:?}", frame.response);
+            if matches!(frame.response, AgentResponse::Exited { .. }) {
+                break;
+            }
+        }
+
+        // Untagged requests get untagged responses
+        host.send(&AgentRequest::Ping);
+        assert_eq!(host.recv_frame().request_id, None);
+        assert!(host.finish().is_ok());
+    }
+
+    #[test]
+    fn test_pull_rejects_other_requests_until_cancelled() {
+        let image = "test-pull-frames:latest";
+        let mut host = TestHost::pulling(image, None);
+        assert!(matches!(host.recv(), AgentResponse::Progress { .. }));
+
+        // Another request is refused under its own ID and the pull goes on
+        let ping = RequestFrame {
+            request_id: Some(9),
+            request: AgentRequest::Ping,
+        };
+        host.send_raw(&serde_json::to_vec(&ping).unwrap());
+        let frame = host.recv_frame();
+        assert_eq!(frame.request_id, Some(9));
+        let AgentResponse::Error { code, .. } = frame.response else {
+            panic!("expected an error, got {:?}", frame.response);
+        };
+        assert_eq!(code.as_deref(), Some(error_codes::INVALID_REQUEST));
+
+        host.send_raw(b"not json");
+        let AgentResponse::Error { code, .. } = host.recv() else {
+            panic!("expected an error for an invalid frame");
+        };
+        assert_eq!(code.as_deref(), Some(error_codes::INVALID_REQUEST));
+
+        // CancelPull ends the pull with only the cancellation error
+        host.send(&AgentRequest::CancelPull {
+            image: image.to_string(),
+        });
+        let frame = host.recv_frame();
+        assert_eq!(frame.request_id, None);
+        let AgentResponse::Error { code, .. } = frame.response else {
+            panic!("expected an error, got {:?}", frame.response);
+        };
+        assert_eq!(code.as_deref(), Some(error_codes::PULL_CANCELLED));
+        assert!(host.finish().is_ok());
+    }
+
+    #[test]
+    fn test_pull_cancelled_by_disconnect() {
+        let mut host = TestHost::pulling("test-pull-disconnect:latest", None);
+        assert!(matches!(host.recv(), AgentResponse::Progress { .. }));
+
+        // Hanging up cancels the pull, and the unsent result isn't an error
+        drop(host.stream);
+        let (result, elapsed) = host.server.join().unwrap();
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(
+            elapsed < Duration::from_secs(2),
+            "cancelled after {:?}",
+            elapsed
+        );
+    }
+
+    const PULL_HEARTBEAT: HeartbeatConfig = HeartbeatConfig {
+        interval_ms: 50,
+        missed_threshold: 3,
+    };
+
+    #[test]
+    fn test_pull_sends_heartbeats() {
+        let image = "test-pull-heartbeat:latest";
+        let mut host = TestHost::pulling(image, Some(PULL_HEARTBEAT));
+
+        // Heartbeats keep coming while the pull is quiet, and answering
+        // them keeps it going well past the threshold
+        let mut heartbeats = 0;
+        while heartbeats < 6 {
+            match host.recv() {
+                AgentResponse::Progress { .. } => {}
+                AgentResponse::Heartbeat => {
+                    heartbeats += 1;
+                    host.send(&AgentRequest::Heartbeat);
+                }
+                other => panic!("expected a heartbeat, got {:?}", other),
+            }
+        }
+
+        host.send(&AgentRequest::CancelPull {
+            image: image.to_string(),
+        });
+        let code = loop {
+            match host.recv() {
+                AgentResponse::Progress { .. } | AgentResponse::Heartbeat => {}
+                AgentResponse::Error { code, .. } => break code,
+                other => panic!("expected an error, got {:?}", other),
+            }
+        };
+        assert_eq!(code.as_deref(), Some(error_codes::PULL_CANCELLED));
+        assert!(host.finish().is_ok());
+    }
+
+    #[test]
+    fn test_pull_cancelled_when_host_heartbeat_lost() {
+        let mut host = TestHost::pulling("test-pull-heartbeat-lost:latest", Some(PULL_HEARTBEAT));
+
+        // The host never answers, so the pull is cancelled once the
+        // threshold passes
+        let code = loop {
+            match host.recv() {
+                AgentResponse::Progress { .. } | AgentResponse::Heartbeat => {}
+                AgentResponse::Error { code, .. } => break code,
+                other => panic!("expected an error, got {:?}", other),
+            }
+        };
+        assert_eq!(code.as_deref(), Some(error_codes::PULL_CANCELLED));
+        let (result, elapsed) = host.server.join().unwrap();
+        assert!(result.is_ok(), "{:?}", result);
+        assert!(
+            elapsed >= PULL_HEARTBEAT.timeout() && elapsed < Duration::from_secs(2),
+            "cancelled after {:?}",
+            elapsed
+        );
+    }
+
+    #[test]
+    fn test_connection_reaps_client_stalled_mid_frame() {
+        // Header sent, body never follows
+        let (agent_end, mut host_end) = UnixStream::pair().unwrap();
+        let server = serve(agent_end, ConnectionLimits::default());
+        host_end.write_all(&64u32.to_be_bytes()).unwrap();
+        host_end.write_all(b"{\"method\"").unwrap();
+
+        let (result, elapsed) = server.join().unwrap();
+        let err = result.unwrap_err();
+        assert!(err.contains("incomplete request body"), "{}", err);
+        assert!(
+            elapsed < Duration::from_secs(2),
+            "reaped after {:?}",
+            elapsed
+        );
+        // The connection was closed
+        let mut byte = [0u8; 1];
+        assert_eq!(host_end.read(&mut byte).unwrap(), 0);
+
+        // Half a header
+        let (agent_end, mut host_end) = UnixStream::pair().unwrap();
+        let server = serve(agent_end, ConnectionLimits::default());
+        host_end.write_all(&[0, 0]).unwrap();
+        let (result, _) = server.join().unwrap();
+        assert!(result.unwrap_err().contains("incomplete request header"));
+    }
+
+    #[test]
+    fn test_idle_connection_is_not_reaped() {
+        let (agent_end, mut host_end) = UnixStream::pair().unwrap();
+        let server = serve(agent_end, ConnectionLimits::default());
+
+        // Idle well past both timeouts, then send a request in pieces
+        std::thread::sleep(TEST_TIMEOUTS.body * 2);
+        let json = serde_json::to_vec(&AgentRequest::Ping).unwrap();
+        host_end.write_all(&[0]).unwrap();
+        std::thread::sleep(TEST_TIMEOUTS.header / 4);
+        host_end
+            .write_all(&(json.len() as u32).to_be_bytes()[1..])
+            .unwrap();
+        host_end.write_all(&json).unwrap();
+
+        let mut header = [0u8; 4];
+        host_end.read_exact(&mut header).unwrap();
+        let mut buf = vec![0u8; u32::from_be_bytes(header) as usize];
+        host_end.read_exact(&mut buf).unwrap();
+        assert!(matches!(
+            serde_json::from_slice(&buf).unwrap(),
+            AgentResponse::Pong { .. }
+        ));
+
+        drop(host_end);
+        let (result, _) = server.join().unwrap();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_arch_mismatch_run_error_code() {
+        let response = run_error_response(storage::StorageError::ArchMismatch {
+            image_arch: "amd64".into(),
+            target_arch: "arm64".into(),
+        });
+        let AgentResponse::Error { message, code, .. } = response else {
+            panic!("expected an error response, got {:?}", response);
+        };
+        assert_eq!(code.as_deref(), Some(error_codes::ARCH_MISMATCH));
+        assert!(message.contains("amd64"), "{}", message);
+    }
+
+    #[test]
+    fn test_interactive_loop_tears_down_when_host_goes_silent() {
+        // The host end stays open but never sends anything, like a host
+        // process that hung or died without the connection being closed.
+        let (mut agent_end, _host_end) = UnixStream::pair().unwrap();
+
+        let mut child = Command::new("sleep")
+            .arg("30")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let config = HeartbeatConfig {
+            interval_ms: 50,
+            missed_threshold: 3,
+        };
+        let start = Instant::now();
+        let result = run_interactive_loop(
+            &mut agent_end,
+            &mut child,
+            SignalTarget::Child,
+            None,
+            Some(config),
+        );
+
+        assert!(result.is_err(), "session should fail when host is silent");
+        assert!(
+            start.elapsed() < config.timeout() + Duration::from_secs(1),
+            "teardown took {:?}",
+            start.elapsed()
+        );
+        // The child was killed and reaped
+        assert!(child.try_wait().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_interactive_loop_survives_with_heartbeats() {
+        let (mut agent_end, host_end) = UnixStream::pair().unwrap();
+
+        // Host answers every heartbeat until the command exits
+        let host = std::thread::spawn(move || {
+            let mut host_end = host_end;
+            loop {
+                let mut header = [0u8; 4];
+                if host_end.read_exact(&mut header).is_err() {
+                    break;
+                }
+                let mut buf = vec![0u8; u32::from_be_bytes(header) as usize];
+                host_end.read_exact(&mut buf).unwrap();
+                match serde_json::from_slice(&buf).unwrap() {
+                    AgentResponse::Heartbeat => {
+                        let json = serde_json::to_vec(&AgentRequest::Heartbeat).unwrap();
+                        let _ = host_end.write_all(&(json.len() as u32).to_be_bytes());
+                        let _ = host_end.write_all(&json);
+                    }
+                    _ => break,
+                }
+            }
+        });
+
+        // Runs well past the heartbeat timeout
+        let mut child = Command::new("sleep")
+            .arg("0.5")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let config = HeartbeatConfig {
+            interval_ms: 50,
+            missed_threshold: 3,
+        };
+        let exit_code = run_interactive_loop(
+            &mut agent_end,
+            &mut child,
+            SignalTarget::Child,
+            None,
+            Some(config),
+        )
+        .unwrap();
+        assert_eq!(exit_code, 0);
+
+        drop(agent_end);
+        host.join().unwrap();
+    }
+
+    #[test]
+    fn test_interactive_loop_forwards_signal() {
+        let (mut agent_end, mut host_end) = UnixStream::pair().unwrap();
+
+        // Exits with a distinctive code once it sees SIGINT
+        let mut child = Command::new("sh")
+            .args([
+                "-c",
+                "trap 'echo got INT; exit 42' INT; echo ready; while :; do sleep 0.05; done",
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        // Host sends Ctrl-C's SIGINT once the command is running
+        let host = std::thread::spawn(move || {
+            let mut stdout = Vec::new();
+            loop {
+                let mut header = [0u8; 4];
+                if host_end.read_exact(&mut header).is_err() {
+                    break;
+                }
+                let mut buf = vec![0u8; u32::from_be_bytes(header) as usize];
+                host_end.read_exact(&mut buf).unwrap();
+                if let AgentResponse::Stdout { data } = serde_json::from_slice(&buf).unwrap() {
+                    stdout.extend_from_slice(&data);
+                    if data.starts_with(b"ready") {
+                        let json = serde_json::to_vec(&AgentRequest::Signal {
+                            signal: libc::SIGINT,
+                        })
+                        .unwrap();
+                        host_end
+                            .write_all(&(json.len() as u32).to_be_bytes())
+                            .unwrap();
+                        host_end.write_all(&json).unwrap();
+                    }
+                }
+            }
+            stdout
+        });
+
+        let exit_code = run_interactive_loop(
+            &mut agent_end,
+            &mut child,
+            SignalTarget::Child,
+            Some(5000),
+            None,
+        )
+        .unwrap();
+        assert_eq!(exit_code, 42);
+
+        drop(agent_end);
+        let stdout = String::from_utf8(host.join().unwrap()).unwrap();
+        assert!(stdout.contains("got INT"), "stdout: {:?}", stdout);
+    }
+
+    #[test]
+    fn test_interactive_loop_sends_output_written_after_exit() {
+        let (mut agent_end, mut host_end) = UnixStream::pair().unwrap();
+
+        // Exits straight away, leaving a background job that writes later
+        let mut child = Command::new("sh")
+            .args(["-c", "(sleep 0.3; echo late) & echo early"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let host = std::thread::spawn(move || {
+            let mut stdout = Vec::new();
+            loop {
+                let mut header = [0u8; 4];
+                if host_end.read_exact(&mut header).is_err() {
+                    break;
+                }
+                let mut buf = vec![0u8; u32::from_be_bytes(header) as usize];
+                host_end.read_exact(&mut buf).unwrap();
+                if let AgentResponse::Stdout { data } = serde_json::from_slice(&buf).unwrap() {
+                    stdout.extend_from_slice(&data);
+                }
+            }
+            stdout
+        });
+
+        let exit_code = run_interactive_loop(
+            &mut agent_end,
+            &mut child,
+            SignalTarget::Child,
+            Some(5000),
+            None,
+        )
+        .unwrap();
+        assert_eq!(exit_code, 0);
+
+        drop(agent_end);
+        let stdout = String::from_utf8(host.join().unwrap()).unwrap();
+        assert_eq!(stdout, "early\nlate\n");
+    }
+}
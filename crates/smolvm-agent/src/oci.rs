@@ -4,7 +4,12 @@
 //! config.json files used by crun to execute containers.
 
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use smolvm_protocol::agent_env::{MAX_ENV_BYTES_ENV, MAX_ENV_VALUE_BYTES_ENV};
+use smolvm_protocol::{ResourceLimits, SecurityOptions, TmpfsMount};
+use std::path::{Component, Path};
+use std::sync::OnceLock;
+
+use crate::user::User;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 /// OCI Runtime Specification (subset for container execution).
@@ -30,6 +35,9 @@ pub struct OciRoot {
     pub readonly: bool,
 }
 
+/// The `HOME` a process gets unless the caller or its user sets another.
+const DEFAULT_HOME: &str = "HOME=/root";
+
 /// Process configuration for the container.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OciProcess {
@@ -56,6 +64,34 @@ pub struct OciProcess {
     pub no_new_privileges: bool,
 }
 
+impl OciProcess {
+    /// Apply the capability and `noNewPrivileges` parts of `security`.
+    ///
+    /// Empty options leave the process unchanged.
+    pub fn restrict(&mut self, security: &SecurityOptions) -> Result<(), String> {
+        if security.is_empty() {
+            return Ok(());
+        }
+        let caps = capability_set(&security.cap_drop, &security.cap_add)?;
+        self.capabilities = Some(OciCapabilities::only(caps));
+        self.no_new_privileges = security.no_new_privileges;
+        Ok(())
+    }
+
+    /// Run the process as `user`.
+    ///
+    /// `HOME` is pointed at the user's home directory (`/` if they have no
+    /// passwd entry) unless the caller overrode the default.
+    pub fn set_user(&mut self, user: &User) {
+        self.user.uid = user.uid;
+        self.user.gid = user.gid;
+
+        if let Some(home) = self.env.iter_mut().find(|var| *var == DEFAULT_HOME) {
+            *home = format!("HOME={}", user.home.as_deref().unwrap_or("/"));
+        }
+    }
+}
+
 /// User configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OciUser {
@@ -66,20 +102,36 @@ pub struct OciUser {
 }
 
 /// Linux capabilities configuration.
+///
+/// Empty sets are written out rather than omitted so that dropping every
+/// capability is explicit in `config.json`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OciCapabilities {
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
     pub bounding: Vec<String>,
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
     pub effective: Vec<String>,
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
     pub inheritable: Vec<String>,
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
     pub permitted: Vec<String>,
-    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
     pub ambient: Vec<String>,
 }
 
+impl OciCapabilities {
+    /// Grant exactly `caps` (bounding, effective and permitted).
+    fn only(caps: Vec<String>) -> Self {
+        Self {
+            bounding: caps.clone(),
+            effective: caps.clone(),
+            inheritable: vec![],
+            permitted: caps,
+            ambient: vec![],
+        }
+    }
+}
+
 /// Resource limit configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OciRlimit {
@@ -107,6 +159,97 @@ pub struct OciLinux {
         skip_serializing_if = "Vec::is_empty"
     )]
     pub readonly_paths: Vec<String>,
+    /// cgroup resource limits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<OciResources>,
+    /// Seccomp syscall filter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seccomp: Option<OciSeccomp>,
+}
+
+/// Seccomp configuration (`linux.seccomp`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OciSeccomp {
+    /// Action for syscalls no rule matches.
+    #[serde(rename = "defaultAction")]
+    pub default_action: String,
+    /// errno returned by `SCMP_ACT_ERRNO` default actions.
+    #[serde(rename = "defaultErrnoRet", skip_serializing_if = "Option::is_none")]
+    pub default_errno_ret: Option<u32>,
+    /// Syscall rules.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub syscalls: Vec<OciSyscall>,
+}
+
+/// A seccomp rule applying `action` to the named syscalls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OciSyscall {
+    pub names: Vec<String>,
+    pub action: String,
+}
+
+/// errno for syscalls a seccomp allowlist denies.
+const EPERM: u32 = 1;
+
+impl OciSeccomp {
+    /// A default-deny filter allowing only `syscalls`; everything else fails
+    /// with `EPERM`.
+    pub fn allowlist(syscalls: &[String]) -> Result<Self, String> {
+        if syscalls.is_empty() {
+            return Err("seccomp allowlist must not be empty".into());
+        }
+        if let Some(bad) = syscalls.iter().find(|name| {
+            name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        }) {
+            return Err(format!(
+                "invalid syscall name in seccomp allowlist: '{}'",
+                bad
+            ));
+        }
+
+        let mut names = syscalls.to_vec();
+        names.sort();
+        names.dedup();
+        Ok(Self {
+            default_action: "SCMP_ACT_ERRNO".to_string(),
+            default_errno_ret: Some(EPERM),
+            syscalls: vec![OciSyscall {
+                names,
+                action: "SCMP_ACT_ALLOW".to_string(),
+            }],
+        })
+    }
+}
+
+/// cgroup resource limits (`linux.resources`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OciResources {
+    /// Memory limits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<OciMemory>,
+    /// CPU limits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<OciCpu>,
+}
+
+/// Memory cgroup limits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OciMemory {
+    /// Memory limit in bytes.
+    pub limit: i64,
+}
+
+/// CPU cgroup limits. The container may use `quota` microseconds of CPU
+/// time every `period` microseconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OciCpu {
+    /// CPU time allowed per period, in microseconds.
+    pub quota: i64,
+    /// Length of the accounting period, in microseconds.
+    pub period: u64,
 }
 
 /// Device node configuration for OCI runtime.
@@ -167,15 +310,17 @@ impl OciSpec {
     /// * `workdir` - Working directory inside the container
     /// * `tty` - Whether to allocate a pseudo-terminal
     pub fn new(command: &[String], env: &[(String, String)], workdir: &str, tty: bool) -> Self {
-        // Build environment variables
-        let env_strings: Vec<String> = [
+        // Build environment variables; the caller's replace the defaults
+        let mut env_strings: Vec<String> = vec![
             "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_string(),
-            "HOME=/root".to_string(),
+            DEFAULT_HOME.to_string(),
             "TERM=xterm-256color".to_string(),
-        ]
-        .into_iter()
-        .chain(env.iter().map(|(k, v)| format!("{}={}", k, v)))
-        .collect();
+        ];
+        for (key, value) in env {
+            let prefix = format!("{}=", key);
+            env_strings.retain(|var| !var.starts_with(&prefix));
+            env_strings.push(format!("{}={}", key, value));
+        }
 
         // Default capabilities for root containers
         let capabilities = OciCapabilities {
@@ -249,6 +394,8 @@ impl OciSpec {
                     "/proc/sys".to_string(),
                     "/proc/sysrq-trigger".to_string(),
                 ],
+                resources: None,
+                seccomp: None,
             },
             mounts: default_mounts(),
             hostname: Some("container".to_string()),
@@ -274,6 +421,47 @@ impl OciSpec {
         });
     }
 
+    /// Add a tmpfs mount to the spec, capped at `size_bytes` if given.
+    pub fn add_tmpfs_mount(&mut self, destination: &str, size_bytes: Option<u64>) {
+        let mut options = vec![
+            "nosuid".to_string(),
+            "nodev".to_string(),
+            "mode=1777".to_string(),
+        ];
+        if let Some(size) = size_bytes {
+            options.push(format!("size={}", size));
+        }
+        self.mounts.push(OciMount {
+            destination: destination.to_string(),
+            mount_type: Some("tmpfs".to_string()),
+            source: "tmpfs".to_string(),
+            options,
+        });
+    }
+
+    /// Apply cgroup memory/CPU limits to the container.
+    pub fn set_resources(&mut self, limits: &ResourceLimits) {
+        self.linux.resources = (!limits.is_empty()).then(|| OciResources {
+            memory: limits.memory_bytes().map(|limit| OciMemory { limit }),
+            cpu: limits.cpu_quota_us().map(|quota| OciCpu {
+                quota,
+                period: ResourceLimits::CPU_PERIOD_US,
+            }),
+        });
+    }
+
+    /// Restrict the container's privileges: capabilities and
+    /// `noNewPrivileges` on the process, plus an optional seccomp filter.
+    pub fn set_security(&mut self, security: &SecurityOptions) -> Result<(), String> {
+        self.process.restrict(security)?;
+        self.linux.seccomp = security
+            .seccomp_allow
+            .as_deref()
+            .map(OciSeccomp::allowlist)
+            .transpose()?;
+        Ok(())
+    }
+
     /// Write the OCI spec to a config.json file in the bundle directory.
     pub fn write_to(&self, bundle_dir: &Path) -> std::io::Result<()> {
         let config_path = bundle_dir.join("config.json");
@@ -374,6 +562,58 @@ pub fn validate_image_reference(image: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Limits on the environment given to a workload.
+///
+/// The environment ends up in the request frame, the OCI spec and the
+/// process's `envp`, so a huge one is refused up front rather than failing
+/// (or stalling the agent) somewhere along the way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnvLimits {
+    /// Maximum length of one value, in bytes.
+    pub max_value_bytes: usize,
+    /// Maximum size of the whole environment, counting each variable as
+    /// `KEY=VALUE` plus its NUL terminator, as in `envp`.
+    pub max_total_bytes: usize,
+}
+
+impl Default for EnvLimits {
+    fn default() -> Self {
+        Self {
+            max_value_bytes: 32 * 1024,
+            // Well under the kernel's combined argv and envp limit
+            max_total_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl EnvLimits {
+    /// The limits, configured once from [`MAX_ENV_VALUE_BYTES_ENV`] and
+    /// [`MAX_ENV_BYTES_ENV`]. Unset, zero or malformed values keep the
+    /// default.
+    pub fn global() -> Self {
+        static LIMITS: OnceLock<EnvLimits> = OnceLock::new();
+        *LIMITS.get_or_init(|| {
+            let from_env = |name: &str| {
+                std::env::var(name)
+                    .ok()
+                    .and_then(|v| v.trim().parse::<usize>().ok())
+                    .filter(|&n| n > 0)
+            };
+            let default = Self::default();
+            Self {
+                max_value_bytes: from_env(MAX_ENV_VALUE_BYTES_ENV)
+                    .unwrap_or(default.max_value_bytes),
+                max_total_bytes: from_env(MAX_ENV_BYTES_ENV).unwrap_or(default.max_total_bytes),
+            }
+        })
+    }
+}
+
+/// Validate environment variables against [`EnvLimits::global`].
+pub fn validate_env_vars(env: &[(String, String)]) -> Result<(), String> {
+    validate_env_vars_with(env, EnvLimits::global())
+}
+
 /// Validate environment variables.
 ///
 /// Environment variable keys must:
@@ -382,11 +622,13 @@ pub fn validate_image_reference(image: &str) -> Result<(), String> {
 /// - Contain only alphanumeric characters and underscores
 /// - Not exceed 256 characters
 ///
-/// Values can be any string but must not exceed 32KB.
-pub fn validate_env_vars(env: &[(String, String)]) -> Result<(), String> {
+/// Values can be any string without NUL bytes, up to
+/// `limits.max_value_bytes`, and the whole environment must fit in
+/// `limits.max_total_bytes`. Errors name the offending variable.
+pub fn validate_env_vars_with(env: &[(String, String)], limits: EnvLimits) -> Result<(), String> {
     const MAX_KEY_LEN: usize = 256;
-    const MAX_VALUE_LEN: usize = 32 * 1024; // 32KB
 
+    let mut total_bytes = 0;
     for (key, value) in env {
         // Key validation
         if key.is_empty() {
@@ -396,7 +638,7 @@ pub fn validate_env_vars(env: &[(String, String)]) -> Result<(), String> {
         if key.len() > MAX_KEY_LEN {
             return Err(format!(
                 "environment variable key '{}...' exceeds {} character limit",
-                &key[..32.min(key.len())],
+                key.chars().take(32).collect::<String>(),
                 MAX_KEY_LEN
             ));
         }
@@ -419,11 +661,25 @@ pub fn validate_env_vars(env: &[(String, String)]) -> Result<(), String> {
             ));
         }
 
-        // Value length validation
-        if value.len() > MAX_VALUE_LEN {
+        // Value validation
+        if value.len() > limits.max_value_bytes {
             return Err(format!(
                 "environment variable '{}' value exceeds {} byte limit",
-                key, MAX_VALUE_LEN
+                key, limits.max_value_bytes
+            ));
+        }
+        if value.contains('\0') {
+            return Err(format!(
+                "environment variable '{}' value contains a NUL byte",
+                key
+            ));
+        }
+
+        total_bytes += key.len() + value.len() + 2;
+        if total_bytes > limits.max_total_bytes {
+            return Err(format!(
+                "environment exceeds {} byte limit at variable '{}'",
+                limits.max_total_bytes, key
             ));
         }
     }
@@ -431,6 +687,53 @@ pub fn validate_env_vars(env: &[(String, String)]) -> Result<(), String> {
     Ok(())
 }
 
+/// Build the process argv for a `Run` request.
+///
+/// With an entrypoint override the argv is `entrypoint + command`, so
+/// `command` becomes the entrypoint's arguments; an empty override leaves
+/// just `command`. Without one, `command` is the whole argv.
+pub fn process_args(entrypoint: Option<&[String]>, command: &[String]) -> Vec<String> {
+    entrypoint
+        .unwrap_or_default()
+        .iter()
+        .chain(command)
+        .cloned()
+        .collect()
+}
+
+/// Validate a process argv: it must be non-empty and no argument may
+/// contain a NUL byte, which can't be passed to `execve`.
+pub fn validate_command(command: &[String]) -> Result<(), String> {
+    if command.is_empty() {
+        return Err("empty command".to_string());
+    }
+    if let Some(i) = command.iter().position(|arg| arg.contains('\0')) {
+        return Err(format!("argument {} contains a NUL byte", i));
+    }
+    Ok(())
+}
+
+/// Validate the tmpfs mounts of a `Run` request: each path must be
+/// absolute, free of `..` and not `/`, and a size, if given, non-zero.
+pub fn validate_tmpfs_mounts(mounts: &[TmpfsMount]) -> Result<(), String> {
+    for mount in mounts {
+        let path = Path::new(&mount.path);
+        if !path.is_absolute() {
+            return Err(format!("tmpfs path '{}' must be absolute", mount.path));
+        }
+        if path.components().any(|c| c == Component::ParentDir) {
+            return Err(format!("tmpfs path '{}' must not contain '..'", mount.path));
+        }
+        if path.parent().is_none() {
+            return Err("tmpfs cannot be mounted over /".to_string());
+        }
+        if mount.size_bytes == Some(0) {
+            return Err(format!("tmpfs size for '{}' must be non-zero", mount.path));
+        }
+    }
+    Ok(())
+}
+
 /// Generate a unique container ID.
 ///
 /// Uses a combination of timestamp and random bytes to ensure uniqueness
@@ -464,6 +767,93 @@ pub fn generate_container_id() -> String {
     )
 }
 
+/// Every Linux capability, as of kernel 6.x.
+const ALL_CAPABILITIES: &[&str] = &[
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_DAC_READ_SEARCH",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETPCAP",
+    "CAP_LINUX_IMMUTABLE",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST",
+    "CAP_NET_ADMIN",
+    "CAP_NET_RAW",
+    "CAP_IPC_LOCK",
+    "CAP_IPC_OWNER",
+    "CAP_SYS_MODULE",
+    "CAP_SYS_RAWIO",
+    "CAP_SYS_CHROOT",
+    "CAP_SYS_PTRACE",
+    "CAP_SYS_PACCT",
+    "CAP_SYS_ADMIN",
+    "CAP_SYS_BOOT",
+    "CAP_SYS_NICE",
+    "CAP_SYS_RESOURCE",
+    "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG",
+    "CAP_MKNOD",
+    "CAP_LEASE",
+    "CAP_AUDIT_WRITE",
+    "CAP_AUDIT_CONTROL",
+    "CAP_SETFCAP",
+    "CAP_MAC_OVERRIDE",
+    "CAP_MAC_ADMIN",
+    "CAP_SYSLOG",
+    "CAP_WAKE_ALARM",
+    "CAP_BLOCK_SUSPEND",
+    "CAP_AUDIT_READ",
+    "CAP_PERFMON",
+    "CAP_BPF",
+    "CAP_CHECKPOINT_RESTORE",
+];
+
+/// Normalize a capability name to its `CAP_`-prefixed upper-case form.
+///
+/// Returns `None` for `ALL`, and an error for unknown names.
+fn normalize_capability(name: &str) -> Result<Option<String>, String> {
+    let upper = name.trim().to_ascii_uppercase();
+    if upper == "ALL" {
+        return Ok(None);
+    }
+    let cap = if upper.starts_with("CAP_") {
+        upper
+    } else {
+        format!("CAP_{}", upper)
+    };
+    if ALL_CAPABILITIES.contains(&cap.as_str()) {
+        Ok(Some(cap))
+    } else {
+        Err(format!("unknown capability: '{}'", name))
+    }
+}
+
+/// The default capability set with `drop` removed and then `add` granted.
+///
+/// `ALL` in `drop` starts from an empty set; `ALL` in `add` grants every
+/// capability.
+pub fn capability_set(drop: &[String], add: &[String]) -> Result<Vec<String>, String> {
+    let mut caps = default_capabilities();
+    for name in drop {
+        match normalize_capability(name)? {
+            None => caps.clear(),
+            Some(cap) => caps.retain(|c| *c != cap),
+        }
+    }
+    for name in add {
+        match normalize_capability(name)? {
+            None => caps = ALL_CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+            Some(cap) if !caps.contains(&cap) => caps.push(cap),
+            Some(_) => {}
+        }
+    }
+    Ok(caps)
+}
+
 /// Default Linux capabilities for root containers.
 fn default_capabilities() -> Vec<String> {
     vec![
@@ -645,6 +1035,27 @@ fn default_mounts() -> Vec<OciMount> {
 mod tests {
     use super::*;
 
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_process_args_with_entrypoint_override() {
+        let entrypoint = strings(&["/bin/sh"]);
+        assert_eq!(
+            process_args(Some(&entrypoint), &strings(&["-c", "echo hi"])),
+            ["/bin/sh", "-c", "echo hi"]
+        );
+        assert_eq!(process_args(Some(&entrypoint), &[]), ["/bin/sh"]);
+
+        // A cleared entrypoint runs the command as given
+        assert_eq!(
+            process_args(Some(&[]), &strings(&["/app", "--port", "80"])),
+            ["/app", "--port", "80"]
+        );
+        assert_eq!(process_args(None, &strings(&["ls", "/"])), ["ls", "/"]);
+    }
+
     #[test]
     fn test_validate_image_reference_valid() {
         // Valid references should pass
@@ -732,6 +1143,178 @@ mod tests {
         assert!(mount.options.contains(&"ro".to_string()));
     }
 
+    #[test]
+    fn test_add_tmpfs_mount() {
+        let mut spec = OciSpec::new(&["sh".to_string()], &[], "/", false);
+        spec.add_tmpfs_mount("/scratch", Some(64 * 1024 * 1024));
+        spec.add_tmpfs_mount("/cache", None);
+
+        let json = serde_json::to_value(&spec).unwrap();
+        let mounts = json["mounts"].as_array().unwrap();
+        let scratch = &mounts[mounts.len() - 2];
+        assert_eq!(scratch["destination"], "/scratch");
+        assert_eq!(scratch["type"], "tmpfs");
+        assert_eq!(scratch["source"], "tmpfs");
+        assert_eq!(
+            scratch["options"],
+            serde_json::json!(["nosuid", "nodev", "mode=1777", "size=67108864"])
+        );
+        let cache = &mounts[mounts.len() - 1];
+        assert_eq!(cache["destination"], "/cache");
+        assert_eq!(
+            cache["options"],
+            serde_json::json!(["nosuid", "nodev", "mode=1777"])
+        );
+    }
+
+    #[test]
+    fn test_validate_tmpfs_mounts() {
+        let mount = |path: &str, size_bytes| TmpfsMount {
+            path: path.to_string(),
+            size_bytes,
+        };
+        assert!(validate_tmpfs_mounts(&[]).is_ok());
+        assert!(
+            validate_tmpfs_mounts(&[mount("/tmp", None), mount("/run/app", Some(4096))]).is_ok()
+        );
+
+        for (bad, reason) in [
+            (mount("tmp", None), "absolute"),
+            (mount("/tmp/../etc", None), "'..'"),
+            (mount("/", None), "over /"),
+            (mount("/tmp", Some(0)), "non-zero"),
+        ] {
+            let err = validate_tmpfs_mounts(std::slice::from_ref(&bad))
+                .expect_err(&format!("{:?} accepted", bad));
+            assert!(err.contains(reason), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_resource_limits_in_spec() {
+        let mut spec = OciSpec::new(&["sh".to_string()], &[], "/", false);
+        let json = serde_json::to_value(&spec).unwrap();
+        assert!(json["linux"].get("resources").is_none());
+
+        spec.set_resources(&ResourceLimits::new(Some(64), Some(1.5)));
+        let json = serde_json::to_value(&spec).unwrap();
+        let resources = &json["linux"]["resources"];
+        assert_eq!(resources["memory"]["limit"], 64 * 1024 * 1024);
+        assert_eq!(resources["cpu"]["quota"], 150_000);
+        assert_eq!(resources["cpu"]["period"], ResourceLimits::CPU_PERIOD_US);
+
+        // Only the limits that were requested are emitted
+        spec.set_resources(&ResourceLimits::new(Some(64), None));
+        let json = serde_json::to_value(&spec).unwrap();
+        assert!(json["linux"]["resources"].get("cpu").is_none());
+    }
+
+    #[test]
+    fn test_security_options_in_config_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spec = OciSpec::new(&["sh".to_string()], &[], "/", false);
+        spec.set_security(&SecurityOptions {
+            cap_drop: vec!["ALL".to_string()],
+            cap_add: vec!["net_bind_service".to_string(), "CAP_CHOWN".to_string()],
+            no_new_privileges: true,
+            seccomp_allow: Some(vec![
+                "write".to_string(),
+                "execve".to_string(),
+                "write".to_string(),
+            ]),
+        })
+        .unwrap();
+        spec.write_to(dir.path()).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.path().join("config.json")).unwrap())
+                .unwrap();
+        let caps = &json["process"]["capabilities"];
+        for set in ["bounding", "effective", "permitted"] {
+            assert_eq!(
+                caps[set],
+                serde_json::json!(["CAP_NET_BIND_SERVICE", "CAP_CHOWN"])
+            );
+        }
+        assert_eq!(caps["ambient"], serde_json::json!([]));
+        assert_eq!(json["process"]["noNewPrivileges"], true);
+
+        let seccomp = &json["linux"]["seccomp"];
+        assert_eq!(seccomp["defaultAction"], "SCMP_ACT_ERRNO");
+        assert_eq!(seccomp["defaultErrnoRet"], 1);
+        assert_eq!(
+            seccomp["syscalls"],
+            serde_json::json!([{ "names": ["execve", "write"], "action": "SCMP_ACT_ALLOW" }])
+        );
+    }
+
+    #[test]
+    fn test_user_in_config_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spec = OciSpec::new(&["id".to_string()], &[], "/", false);
+        spec.process.set_user(&User {
+            uid: 1000,
+            gid: 1001,
+            home: Some("/home/app".to_string()),
+        });
+        spec.write_to(dir.path()).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(dir.path().join("config.json")).unwrap())
+                .unwrap();
+        assert_eq!(json["process"]["user"]["uid"], 1000);
+        assert_eq!(json["process"]["user"]["gid"], 1001);
+        let env = json["process"]["env"].as_array().unwrap();
+        assert!(env.contains(&"HOME=/home/app".into()));
+        assert!(!env.contains(&"HOME=/root".into()));
+
+        // A HOME set by the caller is kept
+        let mut spec = OciSpec::new(
+            &["id".to_string()],
+            &[("HOME".to_string(), "/data".to_string())],
+            "/",
+            false,
+        );
+        spec.process.set_user(&User {
+            uid: 4242,
+            gid: 0,
+            home: None,
+        });
+        let homes: Vec<_> = spec
+            .process
+            .env
+            .iter()
+            .filter(|var| var.starts_with("HOME="))
+            .collect();
+        assert_eq!(homes, ["HOME=/data"]);
+    }
+
+    #[test]
+    fn test_security_options_defaults_and_errors() {
+        // No options keep the default capabilities and no seccomp filter
+        let mut spec = OciSpec::new(&["sh".to_string()], &[], "/", false);
+        spec.set_security(&SecurityOptions::default()).unwrap();
+        let json = serde_json::to_value(&spec).unwrap();
+        assert!(json["linux"].get("seccomp").is_none());
+        assert_eq!(json["process"]["noNewPrivileges"], false);
+        assert_eq!(
+            json["process"]["capabilities"]["bounding"]
+                .as_array()
+                .unwrap()
+                .len(),
+            default_capabilities().len()
+        );
+
+        // Dropping a single capability keeps the rest
+        let caps = capability_set(&["NET_RAW".to_string()], &[]).unwrap();
+        assert!(!caps.contains(&"CAP_NET_RAW".to_string()));
+        assert_eq!(caps.len(), default_capabilities().len() - 1);
+
+        assert!(capability_set(&["CAP_BOGUS".to_string()], &[]).is_err());
+        assert!(OciSeccomp::allowlist(&[]).is_err());
+        assert!(OciSeccomp::allowlist(&["rm -rf".to_string()]).is_err());
+    }
+
     #[test]
     fn test_validate_env_vars_valid() {
         // Valid env vars should pass
@@ -774,4 +1357,38 @@ mod tests {
         let ok_value = "x".repeat(32 * 1024);
         assert!(validate_env_vars(&[("KEY".to_string(), ok_value)]).is_ok());
     }
+
+    #[test]
+    fn test_validate_env_vars_total_size() {
+        // Each variable is within limits, but not all of them together
+        let env: Vec<(String, String)> = (0..64)
+            .map(|i| (format!("VAR_{}", i), "x".repeat(32 * 1024)))
+            .collect();
+        let err = validate_env_vars(&env).unwrap_err();
+        assert!(err.contains("exceeds 1048576 byte limit"), "{}", err);
+        assert!(err.contains("'VAR_31'"), "{}", err);
+        assert!(validate_env_vars(&env[..30]).is_ok());
+
+        // Both limits are configurable
+        let limits = EnvLimits {
+            max_value_bytes: 8,
+            max_total_bytes: 15,
+        };
+        let err =
+            validate_env_vars_with(&[("LONG".to_string(), "x".repeat(9))], limits).unwrap_err();
+        assert!(err.contains("'LONG' value exceeds 8 byte limit"), "{}", err);
+        let env = [
+            ("A".to_string(), "12345".to_string()),
+            ("B".to_string(), "12345".to_string()),
+        ];
+        assert!(validate_env_vars_with(&env[..1], limits).is_ok());
+        let err = validate_env_vars_with(&env, limits).unwrap_err();
+        assert!(err.contains("at variable 'B'"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_env_vars_rejects_nul_values() {
+        let err = validate_env_vars(&[("TOKEN".to_string(), "abc\0def".to_string())]).unwrap_err();
+        assert!(err.contains("'TOKEN'") && err.contains("NUL"), "{}", err);
+    }
 }
@@ -0,0 +1,244 @@
+//! Child output queued for the host.
+//!
+//! A reader thread per output stream feeds a bounded queue that the
+//! session's writer drains onto the connection. Once [`QUEUE_CAPACITY`]
+//! chunks are waiting, the readers block until the writer catches up. The
+//! child's pipe then fills and the child stalls on its next write. Nothing
+//! is dropped, and how far a child can get ahead of a slow host is fixed.
+//!
+//! The readers run until their stream's EOF, which may come after the child
+//! has exited if something it left behind still holds the stream open.
+
+use std::io::Read;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use smolvm_protocol::AgentResponse;
+use tracing::{debug, warn};
+
+/// Chunks of output that may wait for the writer before the readers block.
+pub const QUEUE_CAPACITY: usize = 64;
+
+/// Bytes a reader reads from the child at a time.
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// Output from a child's stdout and stderr, in the order it was read.
+pub struct OutputQueue {
+    rx: Receiver<AgentResponse>,
+    /// Becomes readable when a chunk is queued, so the writer can poll it
+    /// alongside its connection.
+    wake: OwnedFd,
+    /// Reader threads not yet joined.
+    readers: Vec<JoinHandle<()>>,
+    ended: bool,
+}
+
+impl OutputQueue {
+    /// Start a reader thread for each of `stdout` and `stderr` that is set.
+    pub fn spawn(
+        stdout: Option<impl Read + Send + 'static>,
+        stderr: Option<impl Read + Send + 'static>,
+    ) -> std::io::Result<Self> {
+        let (wake, notify) = wake_pipe()?;
+        let notify = Arc::new(notify);
+        let (tx, rx) = sync_channel(QUEUE_CAPACITY);
+        let mut readers = Vec::new();
+
+        if let Some(stdout) = stdout {
+            readers.push(spawn_reader(
+                "exec-stdout",
+                stdout,
+                tx.clone(),
+                notify.clone(),
+                |data| AgentResponse::Stdout { data },
+            )?);
+        }
+        if let Some(stderr) = stderr {
+            readers.push(spawn_reader("exec-stderr", stderr, tx, notify, |data| {
+                AgentResponse::Stderr { data }
+            })?);
+        }
+
+        Ok(Self {
+            rx,
+            wake,
+            readers,
+            ended: false,
+        })
+    }
+
+    /// The fd to poll for readability before calling [`try_next`](Self::try_next).
+    pub fn wake_fd(&self) -> RawFd {
+        self.wake.as_raw_fd()
+    }
+
+    /// The next queued chunk, if one is waiting.
+    pub fn try_next(&mut self) -> Option<AgentResponse> {
+        match self.rx.try_recv() {
+            Ok(chunk) => return Some(chunk),
+            Err(TryRecvError::Empty) => {
+                self.clear_wake();
+                // A chunk queued between the failed receive and clearing the
+                // wake pipe would otherwise go unnoticed until the next one.
+                match self.rx.try_recv() {
+                    Ok(chunk) => return Some(chunk),
+                    Err(TryRecvError::Empty) => return None,
+                    Err(TryRecvError::Disconnected) => {}
+                }
+            }
+            Err(TryRecvError::Disconnected) => self.clear_wake(),
+        }
+        // Each reader holds a sender until it returns, so they have all
+        // finished and joining doesn't block.
+        for reader in self.readers.drain(..) {
+            if reader.join().is_err() {
+                warn!("output reader panicked");
+            }
+        }
+        self.ended = true;
+        None
+    }
+
+    /// Whether both streams have reached EOF and [`try_next`](Self::try_next)
+    /// has taken every chunk. The reader threads have been joined by then.
+    pub fn ended(&self) -> bool {
+        self.ended
+    }
+
+    fn clear_wake(&self) {
+        let mut buf = [0u8; 64];
+        // SAFETY: `buf` is valid for writes of `buf.len()` bytes, and the
+        // wake fd is open for as long as `self` is.
+        while unsafe { libc::read(self.wake_fd(), buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+    }
+}
+
+/// Read `source` to its end, queueing each chunk as built by `chunk`.
+fn spawn_reader(
+    name: &str,
+    mut source: impl Read + Send + 'static,
+    tx: SyncSender<AgentResponse>,
+    notify: Arc<OwnedFd>,
+    chunk: fn(Vec<u8>) -> AgentResponse,
+) -> std::io::Result<JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name(name.into())
+        .spawn(move || {
+            let mut buf = [0u8; READ_CHUNK_SIZE];
+            loop {
+                let n = match source.read(&mut buf) {
+                    Ok(0) => return,
+                    Ok(n) => n,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        debug!(error = %e, "output read error");
+                        return;
+                    }
+                };
+                // Blocks while the queue is full; fails once the session
+                // has gone.
+                if tx.send(chunk(buf[..n].to_vec())).is_err() {
+                    return;
+                }
+                // A full wake pipe already has the writer's attention.
+                // SAFETY: the one-byte buffer is valid for reads, and
+                // `notify` keeps the fd open.
+                unsafe { libc::write(notify.as_raw_fd(), [1u8].as_ptr().cast(), 1) };
+            }
+        })
+}
+
+/// A non-blocking pipe, as its read and write ends.
+fn wake_pipe() -> std::io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for the two fds pipe2 writes.
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+        let err = std::io::Error::last_os_error();
+        warn!(error = %err, "failed to create output wake pipe");
+        return Err(err);
+    }
+    // SAFETY: pipe2 succeeded, so both fds are open and owned by nobody else.
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_slow_consumer_blocks_child_without_losing_output() {
+        // Far more than the queue and the pipe can hold together
+        const TOTAL: usize = 4 * 1024 * 1024;
+        let mut child = Command::new("head")
+            .args(["-c", &TOTAL.to_string(), "/dev/zero"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut queue = OutputQueue::spawn(child.stdout.take(), None::<std::fs::File>).unwrap();
+
+        // Nobody is draining the queue, so the child can't finish
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(
+            child.try_wait().unwrap().is_none(),
+            "child should be blocked on a full pipe"
+        );
+
+        let mut received = 0;
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !queue.ended() {
+            assert!(Instant::now() < deadline, "output never finished");
+            match queue.try_next() {
+                Some(AgentResponse::Stdout { data }) => {
+                    assert!(data.len() <= READ_CHUNK_SIZE);
+                    received += data.len();
+                }
+                Some(other) => panic!("unexpected chunk {:?}", other),
+                None => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+
+        assert_eq!(received, TOTAL);
+        assert!(queue.readers.is_empty(), "readers weren't joined");
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_wake_fd_signals_queued_output() {
+        let mut child = Command::new("sh")
+            .args(["-c", "echo out; echo err >&2"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut queue = OutputQueue::spawn(child.stdout.take(), child.stderr.take()).unwrap();
+        child.wait().unwrap();
+
+        let mut pollfd = libc::pollfd {
+            fd: queue.wake_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `pollfd` is a valid pollfd and we pass a count of 1
+        assert_eq!(unsafe { libc::poll(&mut pollfd, 1, 2000) }, 1);
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stdout.is_empty() || stderr.is_empty() {
+            assert!(Instant::now() < deadline, "output never arrived");
+            match queue.try_next() {
+                Some(AgentResponse::Stdout { data }) => stdout.extend(data),
+                Some(AgentResponse::Stderr { data }) => stderr.extend(data),
+                Some(other) => panic!("unexpected chunk {:?}", other),
+                None => std::thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        assert_eq!(stdout, b"out\n");
+        assert_eq!(stderr, b"err\n");
+    }
+}
@@ -0,0 +1,247 @@
+//! Least-recently-used bookkeeping for persistent run overlays.
+//!
+//! Every image run without `ephemeral` gets a `persistent-<image>` overlay
+//! that stays mounted for reuse. To keep a VM that runs many different
+//! images from accumulating mounts forever, the number of persistent
+//! overlays is capped and the least recently used idle one is evicted when a
+//! new one would exceed the cap.
+//!
+//! Last-use times live in a small JSON index next to the overlays so they
+//! survive agent restarts. Overlays backing an in-flight run are held by an
+//! [`OverlayLease`] and are never picked for eviction.
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default maximum number of persistent overlays kept mounted.
+pub const DEFAULT_MAX_PERSISTENT_OVERLAYS: usize = 16;
+
+/// Environment variable overriding [`DEFAULT_MAX_PERSISTENT_OVERLAYS`].
+pub const MAX_PERSISTENT_OVERLAYS_ENV: &str = "SMOLVM_MAX_PERSISTENT_OVERLAYS";
+
+/// Index file name, stored in the overlays directory.
+pub const INDEX_FILE: &str = "persistent-index.json";
+
+/// Maximum number of persistent overlays, from the environment or the default.
+pub fn max_persistent_overlays() -> usize {
+    std::env::var(MAX_PERSISTENT_OVERLAYS_ENV)
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(DEFAULT_MAX_PERSISTENT_OVERLAYS)
+}
+
+/// Current time in milliseconds since the Unix epoch.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Persistent overlay last-use times, keyed by workload ID.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct OverlayIndex {
+    /// Workload ID to last use, in milliseconds since the Unix epoch.
+    last_used: BTreeMap<String, u64>,
+}
+
+impl OverlayIndex {
+    /// Load the index, starting empty if it is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        std::fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the index atomically.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, json)?;
+        std::fs::rename(&temp_path, path)
+    }
+
+    /// Make the index match the overlays that exist on disk.
+    ///
+    /// Overlays the index doesn't know about (e.g. created before it
+    /// existed) are treated as the least recently used.
+    pub fn sync_with(&mut self, existing: &[String]) {
+        self.last_used.retain(|id, _| existing.contains(id));
+        for id in existing {
+            self.last_used.entry(id.clone()).or_insert(0);
+        }
+    }
+
+    /// Record a use of `workload_id` at `now`.
+    pub fn touch(&mut self, workload_id: &str, now: u64) {
+        self.last_used.insert(workload_id.to_string(), now);
+    }
+
+    /// Forget `workload_id`.
+    pub fn remove(&mut self, workload_id: &str) {
+        self.last_used.remove(workload_id);
+    }
+
+    /// Last use of `workload_id`, if tracked.
+    #[cfg(test)]
+    pub fn last_used(&self, workload_id: &str) -> Option<u64> {
+        self.last_used.get(workload_id).copied()
+    }
+
+    /// Overlays to evict so that at most `max` remain once `incoming` is
+    /// added, oldest first. `incoming` and busy overlays are never chosen, so
+    /// fewer may be returned if too many overlays are in use.
+    pub fn eviction_candidates(
+        &self,
+        incoming: &str,
+        max: usize,
+        is_busy: impl Fn(&str) -> bool,
+    ) -> Vec<String> {
+        let total = self.last_used.len() + usize::from(!self.last_used.contains_key(incoming));
+        let excess = total.saturating_sub(max);
+        if excess == 0 {
+            return Vec::new();
+        }
+
+        let mut idle: Vec<(&String, u64)> = self
+            .last_used
+            .iter()
+            .filter(|(id, _)| id.as_str() != incoming && !is_busy(id))
+            .map(|(id, &t)| (id, t))
+            .collect();
+        idle.sort_by_key(|&(id, t)| (t, id.clone()));
+        idle.into_iter()
+            .take(excess)
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+}
+
+/// In-flight users per workload ID.
+fn leases() -> &'static Mutex<HashMap<String, usize>> {
+    static LEASES: OnceLock<Mutex<HashMap<String, usize>>> = OnceLock::new();
+    LEASES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Marks an overlay as in use until dropped, protecting it from eviction.
+#[derive(Debug)]
+pub struct OverlayLease {
+    workload_id: String,
+}
+
+impl OverlayLease {
+    /// Take a lease on `workload_id`.
+    pub fn acquire(workload_id: &str) -> Self {
+        *leases().lock().entry(workload_id.to_string()).or_insert(0) += 1;
+        Self {
+            workload_id: workload_id.to_string(),
+        }
+    }
+}
+
+impl Drop for OverlayLease {
+    fn drop(&mut self) {
+        let mut leases = leases().lock();
+        if let Some(count) = leases.get_mut(&self.workload_id) {
+            *count -= 1;
+            if *count == 0 {
+                leases.remove(&self.workload_id);
+            }
+        }
+    }
+}
+
+/// Whether any [`OverlayLease`] is held on `workload_id`.
+pub fn is_leased(workload_id: &str) -> bool {
+    leases().lock().contains_key(workload_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(entries: &[(&str, u64)]) -> OverlayIndex {
+        let mut index = OverlayIndex::default();
+        for (id, t) in entries {
+            index.touch(id, *t);
+        }
+        index
+    }
+
+    #[test]
+    fn test_no_eviction_under_limit() {
+        let index = index(&[("persistent-a", 1), ("persistent-b", 2)]);
+        assert!(index
+            .eviction_candidates("persistent-c", 3, |_| false)
+            .is_empty());
+        // Reusing a tracked overlay doesn't add one
+        assert!(index
+            .eviction_candidates("persistent-a", 2, |_| false)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_evicts_oldest_idle() {
+        let index = index(&[
+            ("persistent-a", 30),
+            ("persistent-b", 10),
+            ("persistent-c", 20),
+        ]);
+        assert_eq!(
+            index.eviction_candidates("persistent-d", 3, |_| false),
+            ["persistent-b"]
+        );
+        assert_eq!(
+            index.eviction_candidates("persistent-d", 2, |_| false),
+            ["persistent-b", "persistent-c"]
+        );
+        // A busy overlay is skipped even if it is the oldest
+        assert_eq!(
+            index.eviction_candidates("persistent-d", 3, |id| id == "persistent-b"),
+            ["persistent-c"]
+        );
+    }
+
+    #[test]
+    fn test_sync_with_disk() {
+        let mut index = index(&[("persistent-gone", 5), ("persistent-a", 7)]);
+        index.sync_with(&["persistent-a".to_string(), "persistent-old".to_string()]);
+        assert_eq!(index.last_used("persistent-gone"), None);
+        assert_eq!(index.last_used("persistent-a"), Some(7));
+        assert_eq!(index.last_used("persistent-old"), Some(0));
+    }
+
+    #[test]
+    fn test_lease_refcount() {
+        let id = "persistent-lease-test";
+        let first = OverlayLease::acquire(id);
+        let second = OverlayLease::acquire(id);
+        drop(first);
+        assert!(is_leased(id));
+        drop(second);
+        assert!(!is_leased(id));
+    }
+
+    #[test]
+    fn test_index_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(INDEX_FILE);
+        assert_eq!(OverlayIndex::load(&path).last_used("persistent-a"), None);
+
+        index(&[("persistent-a", 42)]).save(&path).unwrap();
+        assert_eq!(
+            OverlayIndex::load(&path).last_used("persistent-a"),
+            Some(42)
+        );
+
+        std::fs::write(&path, "not json").unwrap();
+        assert_eq!(OverlayIndex::load(&path).last_used("persistent-a"), None);
+    }
+}
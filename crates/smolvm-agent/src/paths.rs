@@ -29,6 +29,10 @@ pub const CRUN_CGROUP_MANAGER: &str = "disabled";
 /// Root directory for virtiofs mounts from the host.
 pub const VIRTIOFS_MOUNT_ROOT: &str = "/mnt/virtiofs";
 
+/// binfmt_misc entry present when the host registered Rosetta for x86_64
+/// binaries (see the host's `vm::rosetta`).
+pub const ROSETTA_BINFMT_ENTRY: &str = "/proc/sys/fs/binfmt_misc/rosetta";
+
 // =============================================================================
 // Storage Paths
 // =============================================================================
@@ -39,6 +43,9 @@ pub const STORAGE_ROOT: &str = "/storage";
 /// Directory for overlay filesystems.
 pub const OVERLAYS_DIR: &str = "/storage/overlays";
 
+/// Directory for named volumes.
+pub const VOLUMES_DIR: &str = "/storage/volumes";
+
 // =============================================================================
 // Container Runtime Paths
 // =============================================================================
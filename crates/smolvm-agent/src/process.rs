@@ -3,25 +3,67 @@
 //! This module provides common helpers for spawning and managing child processes,
 //! including timeout handling and output capture.
 
+use smolvm_protocol::{ExitReason, MAX_FRAME_SIZE, MAX_JSON_STRING_LEN};
 use std::io::Read;
+use std::os::unix::process::ExitStatusExt;
 use std::process::Child;
+use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 
 /// Exit code used when a command is killed due to timeout.
 pub const TIMEOUT_EXIT_CODE: i32 = 124;
 
+/// Offset added to the signal number when a shell or crun reports a
+/// signal death as an exit code (e.g. 137 = 128 + SIGKILL).
+const SIGNAL_EXIT_CODE_BASE: i32 = 128;
+
+/// Highest signal number decoded from a 128+N exit code.
+const MAX_SIGNAL: i32 = 64;
+
+/// Default per-stream cap on captured output (10 MiB).
+///
+/// Both streams at the cap still fit comfortably in a single
+/// `MAX_FRAME_SIZE` (32 MiB) response frame.
+pub const DEFAULT_OUTPUT_LIMIT: usize = 10 * 1024 * 1024;
+
+/// Largest per-stream cap a request may ask for; larger ones are clamped.
+///
+/// Both streams at this cap still fit in one response frame, and each in
+/// a single JSON string.
+pub const MAX_OUTPUT_LIMIT: usize = 12 * 1024 * 1024;
+
+const _: () = assert!(2 * MAX_OUTPUT_LIMIT < MAX_FRAME_SIZE as usize);
+const _: () = assert!(MAX_OUTPUT_LIMIT < MAX_JSON_STRING_LEN);
+
+/// The per-stream output cap for a request's `max_output_bytes`: the
+/// default if unset, and at most [`MAX_OUTPUT_LIMIT`].
+pub fn output_limit(max_output_bytes: Option<u64>) -> usize {
+    max_output_bytes.map_or(DEFAULT_OUTPUT_LIMIT, |n| {
+        usize::try_from(n).map_or(MAX_OUTPUT_LIMIT, |n| n.min(MAX_OUTPUT_LIMIT))
+    })
+}
+
 /// Captured output from a child process.
 #[derive(Debug, Default)]
 pub struct ChildOutput {
     pub stdout: String,
     pub stderr: String,
+    /// Stdout exceeded the capture limit and was cut off.
+    pub stdout_truncated: bool,
+    /// Stderr exceeded the capture limit and was cut off.
+    pub stderr_truncated: bool,
 }
 
 /// Result of waiting for a child process.
 #[derive(Debug)]
 pub enum WaitResult {
-    /// Process completed with the given exit code.
-    Completed { exit_code: i32, output: ChildOutput },
+    /// Process completed with the given exit code. `signal` is set if the
+    /// process was killed by a signal (`exit_code` is then -1).
+    Completed {
+        exit_code: i32,
+        signal: Option<i32>,
+        output: ChildOutput,
+    },
     /// Process was killed due to timeout.
     TimedOut {
         output: ChildOutput,
@@ -29,21 +71,130 @@ pub enum WaitResult {
     },
 }
 
-/// Capture stdout and stderr from a child process.
+impl WaitResult {
+    /// Classify how the process ended.
+    ///
+    /// `oom_kills_before` is the [`oom_kill_count`] sampled before the
+    /// process was started; pass `None` to skip OOM detection.
+    pub fn exit_reason(&self, oom_kills_before: Option<u64>) -> ExitReason {
+        match self {
+            WaitResult::Completed {
+                exit_code, signal, ..
+            } => {
+                let oom_kills_after = oom_kills_before.and_then(|_| oom_kill_count());
+                classify_exit(*exit_code, *signal, oom_kills_before, oom_kills_after)
+            }
+            WaitResult::TimedOut { .. } => ExitReason::TimedOut,
+        }
+    }
+}
+
+/// Number of processes the kernel OOM killer has killed since boot.
 ///
-/// This takes ownership of the stdout/stderr handles from the child
-/// and reads them to strings.
-pub fn capture_child_output(child: &mut Child) -> ChildOutput {
-    let mut output = ChildOutput::default();
+/// crun runs with the cgroup manager disabled, so containers have no cgroup
+/// of their own (and no `memory.events`); the VM-wide `oom_kill` counter in
+/// `/proc/vmstat` is the best available evidence that a SIGKILL came from
+/// the OOM killer.
+pub fn oom_kill_count() -> Option<u64> {
+    std::fs::read_to_string("/proc/vmstat")
+        .ok()
+        .and_then(|vmstat| parse_oom_kill(&vmstat))
+}
+
+fn parse_oom_kill(vmstat: &str) -> Option<u64> {
+    vmstat.lines().find_map(|line| {
+        line.strip_prefix("oom_kill ")
+            .and_then(|count| count.trim().parse().ok())
+    })
+}
 
-    if let Some(mut stdout) = child.stdout.take() {
-        let _ = stdout.read_to_string(&mut output.stdout);
+/// Classify a completed process's exit.
+///
+/// crun exits with 128+N when the container's init dies from signal N, so
+/// that convention is decoded in addition to a direct signal death. A
+/// SIGKILL is reported as an OOM kill if the OOM counter moved meanwhile.
+fn classify_exit(
+    exit_code: i32,
+    signal: Option<i32>,
+    oom_kills_before: Option<u64>,
+    oom_kills_after: Option<u64>,
+) -> ExitReason {
+    let signal = signal.or_else(|| {
+        let n = exit_code - SIGNAL_EXIT_CODE_BASE;
+        (1..=MAX_SIGNAL).contains(&n).then_some(n)
+    });
+
+    match signal {
+        None => ExitReason::Exited,
+        Some(libc::SIGKILL)
+            if matches!(
+                (oom_kills_before, oom_kills_after),
+                (Some(before), Some(after)) if after > before
+            ) =>
+        {
+            ExitReason::OomKilled
+        }
+        Some(signal) => ExitReason::Signaled { signal },
     }
-    if let Some(mut stderr) = child.stderr.take() {
-        let _ = stderr.read_to_string(&mut output.stderr);
+}
+
+/// Read a stream to the end, keeping at most `limit` bytes.
+///
+/// Everything past the limit is drained and discarded so the writer never
+/// blocks on a full pipe. Returns the kept text and whether anything was cut.
+pub fn read_capped<R: Read>(reader: R, limit: usize) -> (String, bool) {
+    let mut buf = Vec::new();
+    let mut reader = reader;
+    let _ = (&mut reader).take(limit as u64).read_to_end(&mut buf);
+    let dropped = std::io::copy(&mut reader, &mut std::io::sink()).unwrap_or(0);
+    (String::from_utf8_lossy(&buf).into_owned(), dropped > 0)
+}
+
+/// Background readers draining a child's stdout and stderr.
+///
+/// Started before waiting on the child so a chatty process can't fill the
+/// pipe and stall while we poll for its exit.
+struct OutputCapture {
+    stdout: Option<JoinHandle<(String, bool)>>,
+    stderr: Option<JoinHandle<(String, bool)>>,
+}
+
+impl OutputCapture {
+    fn start(child: &mut Child, limit: usize) -> Self {
+        Self {
+            stdout: child
+                .stdout
+                .take()
+                .map(|out| std::thread::spawn(move || read_capped(out, limit))),
+            stderr: child
+                .stderr
+                .take()
+                .map(|err| std::thread::spawn(move || read_capped(err, limit))),
+        }
+    }
+
+    /// Wait for both readers to hit EOF and collect the output.
+    fn finish(self) -> ChildOutput {
+        let join = |handle: Option<JoinHandle<(String, bool)>>| {
+            handle.and_then(|h| h.join().ok()).unwrap_or_default()
+        };
+        let (stdout, stdout_truncated) = join(self.stdout);
+        let (stderr, stderr_truncated) = join(self.stderr);
+        ChildOutput {
+            stdout,
+            stderr,
+            stdout_truncated,
+            stderr_truncated,
+        }
     }
+}
 
-    output
+/// Capture stdout and stderr from a child process.
+///
+/// This takes ownership of the stdout/stderr handles from the child
+/// and reads them to strings, up to [`DEFAULT_OUTPUT_LIMIT`] each.
+pub fn capture_child_output(child: &mut Child) -> ChildOutput {
+    OutputCapture::start(child, DEFAULT_OUTPUT_LIMIT).finish()
 }
 
 /// Wait for a child process with optional timeout.
@@ -62,14 +213,19 @@ pub fn wait_with_timeout(
 ) -> std::io::Result<WaitResult> {
     let poll_interval = Duration::from_millis(poll_interval_ms.unwrap_or(10));
     let deadline = timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms));
+    let capture = OutputCapture::start(child, DEFAULT_OUTPUT_LIMIT);
 
     loop {
         match try_wait_with_eintr(child) {
             Ok(Some(status)) => {
                 // Process completed
-                let output = capture_child_output(child);
+                let output = capture.finish();
                 let exit_code = status.code().unwrap_or(-1);
-                return Ok(WaitResult::Completed { exit_code, output });
+                return Ok(WaitResult::Completed {
+                    exit_code,
+                    signal: status.signal(),
+                    output,
+                });
             }
             Ok(None) => {
                 // Still running - check timeout
@@ -80,7 +236,7 @@ pub fn wait_with_timeout(
                         let _ = child.wait();
 
                         // Capture any partial output
-                        let output = capture_child_output(child);
+                        let output = capture.finish();
 
                         return Ok(WaitResult::TimedOut {
                             output,
@@ -119,10 +275,15 @@ fn try_wait_with_eintr(child: &mut Child) -> std::io::Result<Option<std::process
 /// The on_timeout callback is called when the process times out, before
 /// killing it. This allows for custom cleanup (e.g., killing containers).
 ///
+/// At most `output_limit` bytes of each of stdout and stderr are kept; the
+/// `*_truncated` flags on the returned output record whether more was
+/// produced.
+///
 /// Handles EINTR (interrupted system call) by retrying the wait.
 pub fn wait_with_timeout_and_cleanup<F>(
     child: &mut Child,
     timeout_ms: Option<u64>,
+    output_limit: usize,
     on_timeout: F,
 ) -> std::io::Result<WaitResult>
 where
@@ -130,13 +291,18 @@ where
 {
     let poll_interval = Duration::from_millis(10);
     let deadline = timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms));
+    let capture = OutputCapture::start(child, output_limit);
 
     loop {
         match try_wait_with_eintr(child) {
             Ok(Some(status)) => {
-                let output = capture_child_output(child);
+                let output = capture.finish();
                 let exit_code = status.code().unwrap_or(-1);
-                return Ok(WaitResult::Completed { exit_code, output });
+                return Ok(WaitResult::Completed {
+                    exit_code,
+                    signal: status.signal(),
+                    output,
+                });
             }
             Ok(None) => {
                 if let Some(deadline) = deadline {
@@ -148,7 +314,7 @@ where
                         let _ = child.kill();
                         let _ = child.wait();
 
-                        let output = capture_child_output(child);
+                        let output = capture.finish();
 
                         return Ok(WaitResult::TimedOut {
                             output,
@@ -164,6 +330,88 @@ where
     }
 }
 
+/// How often [`terminate_all`] checks whether the processes it signalled
+/// have exited.
+const TERMINATE_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// What [`terminate_all`] had to do to stop a set of processes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Terminated {
+    /// Processes that exited after SIGTERM.
+    pub terminated: usize,
+    /// Processes still running at the timeout, and sent SIGKILL.
+    pub killed: usize,
+}
+
+/// IDs of every user process in the guest other than the agent itself.
+///
+/// Kernel threads, which have no command line, are skipped.
+pub fn guest_processes() -> Vec<i32> {
+    let own = std::process::id() as i32;
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<i32>().ok())
+        .filter(|&pid| pid != own)
+        .filter(|pid| {
+            std::fs::read(format!("/proc/{}/cmdline", pid)).is_ok_and(|cmdline| !cmdline.is_empty())
+        })
+        .collect()
+}
+
+/// Send SIGTERM to each of `pids`, wait up to `timeout` for them to exit,
+/// then SIGKILL any still running.
+pub fn terminate_all(pids: &[i32], timeout: Duration) -> Terminated {
+    let mut running: Vec<i32> = pids
+        .iter()
+        .copied()
+        .filter(|&pid| is_running(pid))
+        .collect();
+    for &pid in &running {
+        // SAFETY: kill() has no memory safety requirements
+        unsafe {
+            libc::kill(pid, libc::SIGTERM);
+        }
+    }
+    let signalled = running.len();
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        running.retain(|&pid| is_running(pid));
+        if running.is_empty() || Instant::now() >= deadline {
+            break;
+        }
+        std::thread::sleep(TERMINATE_POLL_INTERVAL);
+    }
+
+    for &pid in &running {
+        // SAFETY: kill() has no memory safety requirements
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+        }
+    }
+
+    Terminated {
+        terminated: signalled - running.len(),
+        killed: running.len(),
+    }
+}
+
+/// Whether `pid` is a live process. Zombies, which have exited but not been
+/// reaped yet, don't count.
+fn is_running(pid: i32) -> bool {
+    let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+        return false;
+    };
+    // The state follows the parenthesised command name, which may itself
+    // contain spaces or parentheses
+    stat.rsplit_once(')')
+        .and_then(|(_, rest)| rest.split_whitespace().next())
+        .is_some_and(|state| state != "Z" && state != "X")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -228,7 +476,9 @@ mod tests {
         let result = wait_with_timeout(&mut child, Some(5000), None).unwrap();
 
         match result {
-            WaitResult::Completed { exit_code, output } => {
+            WaitResult::Completed {
+                exit_code, output, ..
+            } => {
                 assert_eq!(exit_code, 0);
                 assert!(output.stdout.contains("hello"));
             }
@@ -268,7 +518,9 @@ mod tests {
         let result = wait_with_timeout(&mut child, None, None).unwrap();
 
         match result {
-            WaitResult::Completed { exit_code, output } => {
+            WaitResult::Completed {
+                exit_code, output, ..
+            } => {
                 assert_eq!(exit_code, 0);
                 assert!(output.stdout.contains("quick"));
             }
@@ -295,6 +547,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_output_limit_is_clamped() {
+        assert_eq!(output_limit(None), DEFAULT_OUTPUT_LIMIT);
+        assert_eq!(output_limit(Some(1024)), 1024);
+        assert_eq!(output_limit(Some(1 << 30)), MAX_OUTPUT_LIMIT);
+        assert_eq!(output_limit(Some(u64::MAX)), MAX_OUTPUT_LIMIT);
+    }
+
     #[test]
     fn test_wait_custom_poll_interval() {
         let mut child = Command::new("echo")
@@ -322,10 +582,11 @@ mod tests {
             .spawn()
             .unwrap();
 
-        let result = wait_with_timeout_and_cleanup(&mut child, Some(50), || {
-            callback_called_clone.store(true, Ordering::SeqCst);
-        })
-        .unwrap();
+        let result =
+            wait_with_timeout_and_cleanup(&mut child, Some(50), DEFAULT_OUTPUT_LIMIT, || {
+                callback_called_clone.store(true, Ordering::SeqCst);
+            })
+            .unwrap();
 
         assert!(matches!(result, WaitResult::TimedOut { .. }));
         assert!(
@@ -346,10 +607,11 @@ mod tests {
             .spawn()
             .unwrap();
 
-        let result = wait_with_timeout_and_cleanup(&mut child, Some(5000), || {
-            callback_called_clone.store(true, Ordering::SeqCst);
-        })
-        .unwrap();
+        let result =
+            wait_with_timeout_and_cleanup(&mut child, Some(5000), DEFAULT_OUTPUT_LIMIT, || {
+                callback_called_clone.store(true, Ordering::SeqCst);
+            })
+            .unwrap();
 
         assert!(matches!(result, WaitResult::Completed { .. }));
         assert!(
@@ -357,4 +619,148 @@ mod tests {
             "cleanup callback should not be called on success"
         );
     }
+
+    #[test]
+    fn test_parse_oom_kill() {
+        let vmstat = "pgfault 1234\noom_kill 3\npgmajfault 5\n";
+        assert_eq!(parse_oom_kill(vmstat), Some(3));
+        assert_eq!(parse_oom_kill("pgfault 1\n"), None);
+    }
+
+    #[test]
+    fn test_classify_exit() {
+        assert_eq!(classify_exit(0, None, None, None), ExitReason::Exited);
+        assert_eq!(classify_exit(42, None, None, None), ExitReason::Exited);
+        assert_eq!(
+            classify_exit(-1, Some(15), None, None),
+            ExitReason::Signaled { signal: 15 }
+        );
+        // crun's 128+N convention
+        assert_eq!(
+            classify_exit(137, None, Some(2), Some(2)),
+            ExitReason::Signaled { signal: 9 }
+        );
+        // SIGKILL while the OOM counter moved
+        assert_eq!(
+            classify_exit(137, None, Some(2), Some(3)),
+            ExitReason::OomKilled
+        );
+        // Only SIGKILL can be an OOM kill
+        assert_eq!(
+            classify_exit(143, None, Some(2), Some(3)),
+            ExitReason::Signaled { signal: 15 }
+        );
+    }
+
+    #[test]
+    fn test_exit_reason_normal_exit() {
+        let mut child = Command::new("sh")
+            .args(["-c", "exit 3"])
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let result = wait_with_timeout(&mut child, Some(5000), None).unwrap();
+        assert_eq!(result.exit_reason(None), ExitReason::Exited);
+    }
+
+    #[test]
+    fn test_exit_reason_signaled() {
+        let mut child = Command::new("sh")
+            .args(["-c", "kill -9 $$"])
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let result = wait_with_timeout(&mut child, Some(5000), None).unwrap();
+        assert_eq!(result.exit_reason(None), ExitReason::Signaled { signal: 9 });
+    }
+
+    #[test]
+    fn test_exit_reason_timeout() {
+        let mut child = Command::new("sleep")
+            .arg("10")
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let result = wait_with_timeout(&mut child, Some(50), None).unwrap();
+        assert_eq!(result.exit_reason(oom_kill_count()), ExitReason::TimedOut);
+    }
+
+    #[test]
+    fn test_read_capped() {
+        let (text, truncated) = read_capped(&b"hello"[..], 10);
+        assert_eq!(text, "hello");
+        assert!(!truncated);
+
+        let (text, truncated) = read_capped(&b"hello"[..], 5);
+        assert_eq!(text, "hello");
+        assert!(!truncated);
+
+        let (text, truncated) = read_capped(&b"hello world"[..], 5);
+        assert_eq!(text, "hello");
+        assert!(truncated);
+    }
+
+    #[test]
+    fn test_wait_truncates_output_over_limit() {
+        // 256 KiB is well past both the cap and the pipe buffer size, so this
+        // also checks that output is drained while the process is running.
+        let mut child = Command::new("sh")
+            .args(["-c", "head -c 262144 /dev/zero | tr '\\0' a; echo err >&2"])
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let result = wait_with_timeout_and_cleanup(&mut child, Some(10_000), 1024, || {}).unwrap();
+
+        match result {
+            WaitResult::Completed {
+                exit_code, output, ..
+            } => {
+                assert_eq!(exit_code, 0);
+                assert_eq!(output.stdout.len(), 1024);
+                assert!(output.stdout_truncated);
+                assert_eq!(output.stderr, "err\n");
+                assert!(!output.stderr_truncated);
+            }
+            WaitResult::TimedOut { .. } => panic!("unexpected timeout"),
+        }
+    }
+
+    #[test]
+    fn test_terminate_all_kills_processes_ignoring_sigterm() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("signals");
+        let script = format!(
+            "trap 'echo term >> {0}' TERM; echo ready >> {0}; while :; do sleep 0.05; done",
+            marker.display()
+        );
+        let mut stubborn = Command::new("sh").args(["-c", &script]).spawn().unwrap();
+        let mut sleeper = Command::new("sleep").arg("30").spawn().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !std::fs::read_to_string(&marker).is_ok_and(|s| s.contains("ready")) {
+            assert!(Instant::now() < deadline, "trap was never installed");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let pids = [stubborn.id() as i32, sleeper.id() as i32];
+        let outcome = terminate_all(&pids, Duration::from_millis(300));
+
+        assert_eq!(
+            outcome,
+            Terminated {
+                terminated: 1,
+                killed: 1
+            }
+        );
+        assert_eq!(sleeper.wait().unwrap().signal(), Some(libc::SIGTERM));
+        assert_eq!(stubborn.wait().unwrap().signal(), Some(libc::SIGKILL));
+        assert!(std::fs::read_to_string(&marker).unwrap().contains("term"));
+    }
 }
@@ -0,0 +1,266 @@
+//! Cancelling image pulls part-way.
+//!
+//! Every pull registers a [`PullCancel`] token under its image for as long
+//! as it runs. A client cancels it by sending `CancelPull` (on the pull's
+//! own connection or another one) or by disconnecting mid-pull. The pull
+//! checks the token between layers and on every read of a layer blob, so
+//! a cancelled download stops the puller's child (crane) and tar straight
+//! away, and the partial layer directory is removed. Blob streams that can
+//! stall wait for data with [`PullCancel::wait_readable`], or are read on
+//! their own thread with [`PullCancel::interruptible`] when there is no fd to
+//! wait on, so a stalled download is cancelled too.
+
+use crate::storage::StorageError;
+use parking_lot::Mutex;
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often a waiting pull checks whether it was cancelled.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Pulls in progress, by image.
+static ACTIVE: Mutex<Vec<(String, PullCancel)>> = Mutex::new(Vec::new());
+
+/// Cancellation flag shared between a pull and whoever may cancel it.
+#[derive(Debug, Clone, Default)]
+pub struct PullCancel(Arc<AtomicBool>);
+
+impl PullCancel {
+    /// Ask the pull to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the pull was asked to stop.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Fail with [`StorageError::PullCancelled`] if the pull of `image` was
+    /// asked to stop.
+    pub fn check(&self, image: &str) -> Result<(), StorageError> {
+        if self.is_cancelled() {
+            return Err(StorageError::PullCancelled {
+                image: image.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Wait until `source` has data (or hits end of file), failing if the
+    /// pull is cancelled first.
+    pub fn wait_readable(&self, source: &impl AsRawFd) -> io::Result<()> {
+        loop {
+            if self.is_cancelled() {
+                return Err(cancelled());
+            }
+            if wait_readable(source, POLL_INTERVAL)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Wrap a blob stream so reads fail once the pull is cancelled.
+    pub fn guard<R: Read>(&self, inner: R) -> Cancellable<R> {
+        Cancellable {
+            inner,
+            cancel: self.clone(),
+        }
+    }
+
+    /// Read `inner` on its own thread so reads fail promptly once the pull
+    /// is cancelled, even while `inner` is stalled.
+    ///
+    /// A stalled read keeps the thread until it returns; it then finds the
+    /// stream dropped and exits.
+    pub fn interruptible<R: Read + Send + 'static>(&self, mut inner: R) -> Interruptible {
+        let (tx, rx) = mpsc::sync_channel(INTERRUPTIBLE_CHUNKS);
+        std::thread::spawn(move || {
+            let mut buf = vec![0u8; INTERRUPTIBLE_CHUNK_SIZE];
+            loop {
+                let chunk = match inner.read(&mut buf) {
+                    Ok(0) => return,
+                    Ok(n) => Ok(buf[..n].to_vec()),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => Err(e),
+                };
+                let failed = chunk.is_err();
+                if tx.send(chunk).is_err() || failed {
+                    return;
+                }
+            }
+        });
+        Interruptible {
+            chunks: rx,
+            pending: Vec::new(),
+            offset: 0,
+            cancel: self.clone(),
+        }
+    }
+}
+
+/// Largest chunk an [`Interruptible`] stream's thread reads at once.
+const INTERRUPTIBLE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Chunks an [`Interruptible`] stream's thread may read ahead.
+const INTERRUPTIBLE_CHUNKS: usize = 4;
+
+/// A pull's entry in the registry, removed when dropped.
+pub struct Registration {
+    cancel: PullCancel,
+}
+
+impl Registration {
+    /// The token the pull should check.
+    pub fn token(&self) -> &PullCancel {
+        &self.cancel
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        ACTIVE
+            .lock()
+            .retain(|(_, cancel)| !Arc::ptr_eq(&cancel.0, &self.cancel.0));
+    }
+}
+
+/// Register a pull of `image` so [`cancel`] can reach it.
+pub fn register(image: &str) -> Registration {
+    let cancel = PullCancel::default();
+    ACTIVE.lock().push((image.to_string(), cancel.clone()));
+    Registration { cancel }
+}
+
+/// Cancel every pull of `image` in progress, returning how many there were.
+pub fn cancel(image: &str) -> usize {
+    let active = ACTIVE.lock();
+    let mut cancelled = 0;
+    for (_, pull) in active.iter().filter(|(pulling, _)| pulling == image) {
+        pull.cancel();
+        cancelled += 1;
+    }
+    cancelled
+}
+
+/// Wait up to `timeout` for `source` to become readable (or hung up).
+/// Returns `false` if the time ran out first.
+pub fn wait_readable(source: &impl AsRawFd, timeout: Duration) -> io::Result<bool> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(false);
+        }
+
+        let mut poll_fd = libc::pollfd {
+            fd: source.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = remaining.as_millis().clamp(1, i32::MAX as u128) as i32;
+        // SAFETY: poll_fd is a valid pollfd and we pass a count of 1
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) };
+        if ready < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        if ready > 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Reader that fails once its pull is cancelled.
+pub struct Cancellable<R> {
+    inner: R,
+    cancel: PullCancel,
+}
+
+impl<R: Read> Read for Cancellable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cancel.is_cancelled() {
+            return Err(cancelled());
+        }
+        self.inner.read(buf)
+    }
+}
+
+/// A blob stream read on its own thread; see [`PullCancel::interruptible`].
+pub struct Interruptible {
+    chunks: Receiver<io::Result<Vec<u8>>>,
+    pending: Vec<u8>,
+    offset: usize,
+    cancel: PullCancel,
+}
+
+impl Read for Interruptible {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset == self.pending.len() {
+            if self.cancel.is_cancelled() {
+                return Err(cancelled());
+            }
+            match self.chunks.recv_timeout(POLL_INTERVAL) {
+                Ok(chunk) => {
+                    self.pending = chunk?;
+                    self.offset = 0;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.pending.len() - self.offset);
+        buf[..n].copy_from_slice(&self.pending[self.offset..self.offset + n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+/// The read error of a cancelled pull. Not `Interrupted`, which
+/// `io::copy` would retry.
+fn cancelled() -> io::Error {
+    io::Error::other("pull cancelled")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_reaches_registered_pull() {
+        let first = register("cancel-test:1");
+        let other = register("cancel-test:2");
+        assert!(!first.token().is_cancelled());
+
+        assert_eq!(cancel("cancel-test:1"), 1);
+        assert!(first.token().is_cancelled());
+        assert!(!other.token().is_cancelled());
+        assert!(matches!(
+            first.token().check("cancel-test:1"),
+            Err(StorageError::PullCancelled { .. })
+        ));
+
+        // A finished pull can no longer be cancelled
+        drop(other);
+        assert_eq!(cancel("cancel-test:2"), 0);
+    }
+
+    #[test]
+    fn test_cancelled_stream_stops_reading() {
+        let cancel = PullCancel::default();
+        let mut stream = cancel.guard(io::repeat(0));
+        let mut buf = [0u8; 16];
+        assert_eq!(stream.read(&mut buf).unwrap(), 16);
+
+        cancel.cancel();
+        let err = stream.read(&mut buf).unwrap_err();
+        assert_ne!(err.kind(), io::ErrorKind::Interrupted);
+    }
+}
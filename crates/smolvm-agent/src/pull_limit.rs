@@ -0,0 +1,225 @@
+//! Download rate limit for a pull.
+//!
+//! On a metered or slow link a pull can saturate the connection. A `Pull`
+//! request may cap its download rate with `max_bytes_per_sec`; the pull then
+//! goes through a [`RateLimited`] puller, whose blob downloads all draw from
+//! one token bucket.
+//!
+//! There is no cap on concurrent downloads: requests are serialized by the
+//! agent's request lock and a pull fetches its layers one at a time, so only
+//! one blob is ever downloading.
+
+use crate::pull_cancel::PullCancel;
+use crate::puller::OciPuller;
+use crate::storage::StorageError;
+use parking_lot::Mutex;
+use smolvm_protocol::RegistryAuth;
+use std::io::Read;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A puller whose blob downloads share a rate limit. Manifests and configs
+/// are small and fetched at full speed.
+pub struct RateLimited<'a> {
+    inner: &'a dyn OciPuller,
+    rate: Arc<RateLimiter>,
+}
+
+impl<'a> RateLimited<'a> {
+    /// Limit `inner`'s blob downloads to `bytes_per_sec` combined.
+    pub fn new(inner: &'a dyn OciPuller, bytes_per_sec: u64) -> Self {
+        Self {
+            inner,
+            rate: Arc::new(RateLimiter::new(bytes_per_sec.max(1))),
+        }
+    }
+}
+
+impl OciPuller for RateLimited<'_> {
+    fn manifest(
+        &self,
+        image: &str,
+        oci_platform: Option<&str>,
+        auth: Option<&RegistryAuth>,
+    ) -> Result<String, StorageError> {
+        self.inner.manifest(image, oci_platform, auth)
+    }
+
+    fn config(
+        &self,
+        image: &str,
+        digest: &str,
+        auth: Option<&RegistryAuth>,
+    ) -> Result<String, StorageError> {
+        self.inner.config(image, digest, auth)
+    }
+
+    fn blob_stream(
+        &self,
+        image: &str,
+        digest: &str,
+        oci_platform: Option<&str>,
+        auth: Option<&RegistryAuth>,
+        cancel: &PullCancel,
+    ) -> Result<Box<dyn Read + Send>, StorageError> {
+        let inner = self
+            .inner
+            .blob_stream(image, digest, oci_platform, auth, cancel)?;
+        Ok(Box::new(Throttled {
+            inner,
+            rate: Arc::clone(&self.rate),
+        }))
+    }
+}
+
+/// Token bucket holding up to a second's worth of bytes.
+///
+/// The bucket starts empty and readers may overdraw it; a reader that
+/// leaves it in debt sleeps until the debt is repaid, so the combined rate
+/// stays at `bytes_per_sec` however many readers share it.
+struct RateLimiter {
+    bytes_per_sec: u64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec,
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take `bytes` from the bucket, sleeping if that leaves it in debt.
+    fn consume(&self, bytes: usize) {
+        let rate = self.bytes_per_sec as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refill).min(rate) - bytes as f64;
+            bucket.refilled = now;
+            (bucket.tokens < 0.0).then(|| Duration::from_secs_f64(-bucket.tokens / rate))
+        };
+        if let Some(wait) = wait {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// Reader that draws what it reads from a [`RateLimiter`].
+struct Throttled<R> {
+    inner: R,
+    rate: Arc<RateLimiter>,
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Read at most a tenth of a second's worth, so sleeps stay short
+        // and the rate is smooth
+        let chunk = (self.rate.bytes_per_sec / 10).max(1) as usize;
+        let len = buf.len().min(chunk);
+        let n = self.inner.read(&mut buf[..len])?;
+        self.rate.consume(n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Puller serving every blob as `len` zero bytes, trickled out a
+    /// millisecond per read.
+    struct SlowPuller {
+        len: usize,
+    }
+
+    struct SlowStream {
+        remaining: usize,
+    }
+
+    impl Read for SlowStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            std::thread::sleep(Duration::from_millis(1));
+            let n = buf.len().min(self.remaining);
+            buf[..n].fill(0);
+            self.remaining -= n;
+            Ok(n)
+        }
+    }
+
+    impl OciPuller for SlowPuller {
+        fn manifest(
+            &self,
+            _image: &str,
+            _oci_platform: Option<&str>,
+            _auth: Option<&RegistryAuth>,
+        ) -> Result<String, StorageError> {
+            Ok("{}".to_string())
+        }
+
+        fn config(
+            &self,
+            _image: &str,
+            _digest: &str,
+            _auth: Option<&RegistryAuth>,
+        ) -> Result<String, StorageError> {
+            Ok("{}".to_string())
+        }
+
+        fn blob_stream(
+            &self,
+            _image: &str,
+            _digest: &str,
+            _oci_platform: Option<&str>,
+            _auth: Option<&RegistryAuth>,
+            _cancel: &PullCancel,
+        ) -> Result<Box<dyn Read + Send>, StorageError> {
+            Ok(Box::new(SlowStream {
+                remaining: self.len,
+            }))
+        }
+    }
+
+    #[test]
+    fn test_rate_limit_bounds_throughput() {
+        const RATE: u64 = 200_000;
+        const LEN: usize = 50_000;
+
+        let inner = SlowPuller { len: LEN };
+        let puller = RateLimited::new(&inner, RATE);
+        let cancel = PullCancel::default();
+        let start = Instant::now();
+        // Two blobs of one pull share the limit
+        let mut copied = 0;
+        for digest in ["sha256:aaa", "sha256:bbb"] {
+            let mut blob = puller
+                .blob_stream("alpine", digest, None, None, &cancel)
+                .unwrap();
+            copied += std::io::copy(&mut blob, &mut std::io::sink()).unwrap();
+        }
+        let elapsed = start.elapsed();
+
+        assert_eq!(copied, 2 * LEN as u64);
+        // 100 KB at 200 KB/s takes half a second, less the last read's sleep
+        assert!(
+            elapsed >= Duration::from_millis(400),
+            "{:?} is too fast",
+            elapsed
+        );
+        assert!(
+            elapsed < Duration::from_secs(5),
+            "{:?} is too slow",
+            elapsed
+        );
+    }
+}
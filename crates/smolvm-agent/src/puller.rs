@@ -0,0 +1,937 @@
+//! Fetching image manifests, configs and layer blobs from a registry.
+//!
+//! Pulls go through the [`OciPuller`] trait so the transport can be swapped
+//! without touching the storage layout logic in `storage`. Two
+//! implementations exist:
+//!
+//! - [`CranePuller`] shells out to the `crane` binary (the default)
+//! - [`RegistryPuller`] speaks the registry HTTP API in-process, reusing
+//!   pooled connections and bearer tokens across requests, and needs no
+//!   external binary
+//!
+//! The implementation is chosen with `SMOLVM_OCI_PULLER` (`crane` or
+//! `registry`).
+
+use crate::pull_cancel::PullCancel;
+use crate::storage::StorageError;
+use crate::tools;
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+use smolvm_protocol::{ImageRef, RegistryAuth};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+type Result<T> = std::result::Result<T, StorageError>;
+
+/// Environment variable selecting the [`OciPuller`] implementation.
+pub const OCI_PULLER_ENV: &str = "SMOLVM_OCI_PULLER";
+
+/// Name of the crane binary (resolved via `PATH`).
+const CRANE_BIN: &str = "crane";
+
+/// Media types accepted when fetching manifests.
+const MANIFEST_ACCEPT: &str = "application/vnd.oci.image.index.v1+json, \
+     application/vnd.docker.distribution.manifest.list.v2+json, \
+     application/vnd.oci.image.manifest.v1+json, \
+     application/vnd.docker.distribution.manifest.v2+json";
+
+/// Timeout for establishing a registry connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeout for a single read from a registry connection.
+const READ_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Source of image manifests, configs and layer blobs.
+///
+/// Errors should carry the registry's message so transient failures can be
+/// told apart from permanent ones (see `crate::retry`). Callers handle
+/// retries.
+pub trait OciPuller: Send + Sync {
+    /// Fetch the image manifest for `oci_platform` as raw JSON.
+    ///
+    /// Without a platform a multi-arch image yields its manifest list.
+    fn manifest(
+        &self,
+        image: &str,
+        oci_platform: Option<&str>,
+        auth: Option<&RegistryAuth>,
+    ) -> Result<String>;
+
+    /// Fetch the image config blob `digest`, named by the manifest, as raw
+    /// JSON. Content that doesn't match `digest` is an error.
+    fn config(&self, image: &str, digest: &str, auth: Option<&RegistryAuth>) -> Result<String>;
+
+    /// Open a stream of the blob `digest` in `image`'s repository, as stored
+    /// in the registry (layers are usually gzip-compressed tarballs).
+    ///
+    /// Failures partway through surface as read errors, as does content
+    /// that doesn't match `digest` once the end of the stream is read. Once
+    /// `cancel` is set, reads should fail promptly even if the download has
+    /// stalled.
+    fn blob_stream(
+        &self,
+        image: &str,
+        digest: &str,
+        oci_platform: Option<&str>,
+        auth: Option<&RegistryAuth>,
+        cancel: &PullCancel,
+    ) -> Result<Box<dyn Read + Send>>;
+}
+
+/// The puller selected by `SMOLVM_OCI_PULLER`, shared across pulls.
+pub fn default_puller() -> &'static dyn OciPuller {
+    static PULLER: OnceLock<Box<dyn OciPuller>> = OnceLock::new();
+    PULLER
+        .get_or_init(|| match std::env::var(OCI_PULLER_ENV).as_deref() {
+            Ok("registry") => Box::new(RegistryPuller::new()),
+            Ok("crane") | Err(_) => Box::new(CranePuller::default()),
+            Ok(other) => {
+                warn!(
+                    value = %other,
+                    "unknown {}, expected 'crane' or 'registry'; using crane",
+                    OCI_PULLER_ENV
+                );
+                Box::new(CranePuller::default())
+            }
+        })
+        .as_ref()
+}
+
+// ============================================================================
+// crane
+// ============================================================================
+
+/// Puller that runs the `crane` binary for each operation.
+pub struct CranePuller {
+    bin: String,
+}
+
+impl Default for CranePuller {
+    fn default() -> Self {
+        Self::with_bin(CRANE_BIN)
+    }
+}
+
+impl CranePuller {
+    /// Use the crane binary at `bin`.
+    pub fn with_bin(bin: impl Into<String>) -> Self {
+        Self { bin: bin.into() }
+    }
+
+    /// Run a crane command and return its stdout.
+    ///
+    /// If auth is provided, creates a temporary Docker config for crane to use.
+    fn run(
+        &self,
+        operation: &str,
+        image: &str,
+        oci_platform: Option<&str>,
+        auth: Option<&RegistryAuth>,
+    ) -> Result<String> {
+        let mut cmd = Command::new(&self.bin);
+        cmd.arg(operation).arg(image);
+
+        if let Some(p) = oci_platform {
+            cmd.arg("--platform").arg(p);
+        }
+
+        // Set up auth if provided (temp_dir must stay alive until command completes)
+        let _temp_dir = setup_docker_auth(image, auth)?;
+        if let Some(ref td) = _temp_dir {
+            cmd.env("DOCKER_CONFIG", td.path());
+        }
+
+        let output = cmd
+            .output()
+            .map_err(|e| StorageError::new(tools::CRANE.spawn_error_at(&self.bin, &e)))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(StorageError::new(format!(
+                "crane {} failed: {}",
+                operation, stderr
+            )));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+impl OciPuller for CranePuller {
+    fn manifest(
+        &self,
+        image: &str,
+        oci_platform: Option<&str>,
+        auth: Option<&RegistryAuth>,
+    ) -> Result<String> {
+        self.run("manifest", image, oci_platform, auth)
+    }
+
+    fn config(&self, image: &str, digest: &str, auth: Option<&RegistryAuth>) -> Result<String> {
+        // crane checks blobs against their digest itself
+        self.run("blob", &blob_ref(image, digest), None, auth)
+    }
+
+    fn blob_stream(
+        &self,
+        image: &str,
+        digest: &str,
+        oci_platform: Option<&str>,
+        auth: Option<&RegistryAuth>,
+        cancel: &PullCancel,
+    ) -> Result<Box<dyn Read + Send>> {
+        // Set up auth if provided (temp_dir must stay alive until crane exits)
+        let auth_dir = setup_docker_auth(image, auth)?;
+
+        let mut cmd = Command::new(&self.bin);
+        cmd.arg("blob").arg(blob_ref(image, digest));
+        if let Some(p) = oci_platform {
+            cmd.arg("--platform").arg(p);
+        }
+        cmd.stdout(Stdio::piped());
+        // Send stderr to a file rather than a pipe: a pipe could fill up and
+        // deadlock while the reader is blocked downstream, but we still need
+        // the message to decide whether the failure is retryable.
+        let stderr = tempfile::tempfile()
+            .map_err(|e| StorageError::new(format!("failed to create crane stderr file: {}", e)))?;
+        cmd.stderr(
+            stderr.try_clone().map_err(|e| {
+                StorageError::new(format!("failed to clone crane stderr file: {}", e))
+            })?,
+        );
+        if let Some(ref td) = auth_dir {
+            cmd.env("DOCKER_CONFIG", td.path());
+        }
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| StorageError::new(tools::CRANE.spawn_error_at(&self.bin, &e)))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| StorageError::new("failed to capture crane stdout".to_string()))?;
+
+        Ok(Box::new(CraneBlob {
+            child,
+            stdout,
+            stderr,
+            finished: false,
+            cancel: cancel.clone(),
+            _auth_dir: auth_dir,
+        }))
+    }
+}
+
+/// Stdout of a running `crane blob`.
+///
+/// At end of stream the process is reaped, and a non-zero exit becomes a
+/// read error carrying crane's stderr. Dropping the stream early kills
+/// crane, and reads fail once the pull is cancelled, even while crane is
+/// stalled.
+struct CraneBlob {
+    child: Child,
+    stdout: ChildStdout,
+    stderr: File,
+    finished: bool,
+    cancel: PullCancel,
+    _auth_dir: Option<tempfile::TempDir>,
+}
+
+impl Read for CraneBlob {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.cancel.wait_readable(&self.stdout)?;
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() && !self.finished {
+            self.finished = true;
+            let status = self.child.wait()?;
+            if !status.success() {
+                let mut message = String::new();
+                let _ = self.stderr.seek(std::io::SeekFrom::Start(0));
+                let _ = self.stderr.read_to_string(&mut message);
+                return Err(std::io::Error::other(format!(
+                    "crane blob failed: {}",
+                    message.trim()
+                )));
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Drop for CraneBlob {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Reference to the blob `digest` in `image`'s repository, for crane.
+fn blob_ref(image: &str, digest: &str) -> String {
+    let repository = image
+        .split_once('@')
+        .map_or(image, |(repository, _)| repository);
+    format!("{}@{}", repository, digest)
+}
+
+/// Extract the registry hostname from an image reference.
+/// e.g., "alpine:latest" -> "https://index.docker.io/v1/"
+/// e.g., "ghcr.io/owner/repo" -> "ghcr.io"
+fn extract_registry_from_image(image: &str) -> String {
+    if let Some(slash_pos) = image.find('/') {
+        let potential_registry = &image[..slash_pos];
+        if potential_registry.contains('.') || potential_registry.contains(':') {
+            return potential_registry.to_string();
+        }
+    }
+    // Docker Hub uses this URL in config.json
+    "https://index.docker.io/v1/".to_string()
+}
+
+/// Simple base64 encoding for auth string.
+fn base64_encode(input: &str) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let bytes = input.as_bytes();
+    let mut result = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let b0 = chunk[0] as usize;
+        let b1 = chunk.get(1).copied().unwrap_or(0) as usize;
+        let b2 = chunk.get(2).copied().unwrap_or(0) as usize;
+
+        result.push(ALPHABET[b0 >> 2] as char);
+        result.push(ALPHABET[((b0 & 0x03) << 4) | (b1 >> 4)] as char);
+
+        if chunk.len() > 1 {
+            result.push(ALPHABET[((b1 & 0x0f) << 2) | (b2 >> 6)] as char);
+        } else {
+            result.push('=');
+        }
+
+        if chunk.len() > 2 {
+            result.push(ALPHABET[b2 & 0x3f] as char);
+        } else {
+            result.push('=');
+        }
+    }
+
+    result
+}
+
+/// Set up Docker auth configuration for crane commands.
+///
+/// Creates a temporary directory with a Docker config.json file containing
+/// registry credentials. The returned TempDir must be kept alive for the
+/// duration of the command execution.
+///
+/// Returns `Ok(None)` if no auth is provided.
+fn setup_docker_auth(
+    image: &str,
+    auth: Option<&RegistryAuth>,
+) -> Result<Option<tempfile::TempDir>> {
+    let Some(a) = auth else {
+        return Ok(None);
+    };
+
+    let registry = extract_registry_from_image(image);
+
+    let temp_dir = tempfile::TempDir::new().map_err(|e| {
+        StorageError::new(format!("failed to create temp directory for auth: {}", e))
+    })?;
+
+    let auth_b64 = base64_encode(&format!("{}:{}", a.username, a.password));
+    let config_json = format!(
+        r#"{{"auths":{{"{}":{{"auth":"{}"}}}}}}"#,
+        registry, auth_b64
+    );
+
+    let config_path = temp_dir.path().join("config.json");
+    std::fs::write(&config_path, &config_json)
+        .map_err(|e| StorageError::new(format!("failed to write docker auth config: {}", e)))?;
+
+    debug!(
+        registry = %registry,
+        username = %a.username,
+        "using registry credentials via docker config"
+    );
+
+    Ok(Some(temp_dir))
+}
+
+// ============================================================================
+// In-process registry client
+// ============================================================================
+
+/// Puller speaking the OCI distribution HTTP API directly.
+///
+/// Connections are pooled and bearer tokens are cached per repository, so
+/// the manifest, config and layer requests of a pull share one TLS session
+/// and one token exchange.
+pub struct RegistryPuller {
+    agent: ureq::Agent,
+    /// `Authorization` header values by (registry, repository).
+    tokens: Mutex<HashMap<(String, String), String>>,
+}
+
+impl Default for RegistryPuller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegistryPuller {
+    /// Create a puller with an empty connection pool.
+    pub fn new() -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new()
+                .timeout_connect(CONNECT_TIMEOUT)
+                .timeout_read(READ_TIMEOUT)
+                .build(),
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fetch the image manifest, resolving a manifest list to the entry for
+    /// `oci_platform`.
+    fn resolve_manifest(
+        &self,
+        image_ref: &ImageRef,
+        oci_platform: Option<&str>,
+        auth: Option<&RegistryAuth>,
+    ) -> Result<String> {
+        let reference = image_ref
+            .digest()
+            .or(image_ref.tag())
+            .unwrap_or("latest")
+            .to_string();
+        let manifest = self.get_manifest(image_ref, &reference, auth)?;
+
+        let Some(platform) = oci_platform else {
+            return Ok(manifest);
+        };
+        let json: serde_json::Value = serde_json::from_str(&manifest)
+            .map_err(|e| StorageError::parse_error("manifest", e))?;
+        match select_platform_manifest(&json, platform) {
+            Some(digest) => self.get_manifest(image_ref, &digest, auth),
+            // A plain image manifest, or a list without this platform (the
+            // caller reports the available platforms)
+            None => Ok(manifest),
+        }
+    }
+
+    fn get_manifest(
+        &self,
+        image_ref: &ImageRef,
+        reference: &str,
+        auth: Option<&RegistryAuth>,
+    ) -> Result<String> {
+        let path = format!("manifests/{}", reference);
+        read_text(self.get(image_ref, &path, Some(MANIFEST_ACCEPT), auth)?)
+    }
+
+    /// GET `/v2/<repository>/<path>`, authenticating if challenged.
+    fn get(
+        &self,
+        image_ref: &ImageRef,
+        path: &str,
+        accept: Option<&str>,
+        auth: Option<&RegistryAuth>,
+    ) -> Result<ureq::Response> {
+        let url = format!(
+            "{}/v2/{}/{}",
+            registry_base_url(image_ref.registry()),
+            image_ref.repository(),
+            path
+        );
+        let key = (
+            image_ref.registry().to_string(),
+            image_ref.repository().to_string(),
+        );
+        let cached = self.tokens.lock().get(&key).cloned();
+
+        match self.request(&url, accept, cached.as_deref()).call() {
+            Err(ureq::Error::Status(401, resp)) => {
+                let challenge = resp.header("www-authenticate").unwrap_or("").to_string();
+                let authorization = self.authorize(&challenge, image_ref, auth)?;
+                let resp = self
+                    .request(&url, accept, Some(&authorization))
+                    .call()
+                    .map_err(|e| request_error(&url, e))?;
+                self.tokens.lock().insert(key, authorization);
+                Ok(resp)
+            }
+            result => result.map_err(|e| request_error(&url, e)),
+        }
+    }
+
+    fn request(
+        &self,
+        url: &str,
+        accept: Option<&str>,
+        authorization: Option<&str>,
+    ) -> ureq::Request {
+        let mut request = self.agent.get(url);
+        if let Some(accept) = accept {
+            request = request.set("Accept", accept);
+        }
+        if let Some(authorization) = authorization {
+            request = request.set("Authorization", authorization);
+        }
+        request
+    }
+
+    /// Answer a `WWW-Authenticate` challenge with an `Authorization` value.
+    fn authorize(
+        &self,
+        challenge: &str,
+        image_ref: &ImageRef,
+        auth: Option<&RegistryAuth>,
+    ) -> Result<String> {
+        let basic = auth.map(|a| {
+            format!(
+                "Basic {}",
+                base64_encode(&format!("{}:{}", a.username, a.password))
+            )
+        });
+
+        let Some((scheme, params)) = parse_challenge(challenge) else {
+            return basic.ok_or_else(|| {
+                StorageError::new(format!(
+                    "{}: 401 Unauthorized: authentication required",
+                    image_ref
+                ))
+            });
+        };
+        if scheme.eq_ignore_ascii_case("basic") {
+            return basic.ok_or_else(|| {
+                StorageError::new(format!(
+                    "{}: 401 Unauthorized: authentication required",
+                    image_ref
+                ))
+            });
+        }
+
+        let realm = params
+            .get("realm")
+            .ok_or_else(|| StorageError::MissingField {
+                context: "registry auth challenge".into(),
+                field: "realm".into(),
+            })?;
+        let scope = params
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:pull", image_ref.repository()));
+
+        let mut request = self.agent.get(realm).query("scope", &scope);
+        if let Some(service) = params.get("service") {
+            request = request.query("service", service);
+        }
+        if let Some(basic) = &basic {
+            request = request.set("Authorization", basic);
+        }
+        let body = read_text(request.call().map_err(|e| request_error(realm, e))?)?;
+        let json: serde_json::Value = serde_json::from_str(&body)
+            .map_err(|e| StorageError::parse_error("registry token", e))?;
+        let token = json["token"]
+            .as_str()
+            .or(json["access_token"].as_str())
+            .ok_or_else(|| StorageError::MissingField {
+                context: "registry token".into(),
+                field: "token".into(),
+            })?;
+
+        debug!(registry = %image_ref.registry(), scope = %scope, "obtained registry token");
+        Ok(format!("Bearer {}", token))
+    }
+}
+
+impl OciPuller for RegistryPuller {
+    fn manifest(
+        &self,
+        image: &str,
+        oci_platform: Option<&str>,
+        auth: Option<&RegistryAuth>,
+    ) -> Result<String> {
+        self.resolve_manifest(&parse_ref(image)?, oci_platform, auth)
+    }
+
+    fn config(&self, image: &str, digest: &str, auth: Option<&RegistryAuth>) -> Result<String> {
+        let image_ref = parse_ref(image)?;
+        let expected = sha256_hex(digest)?;
+        let config = read_text(self.get(&image_ref, &format!("blobs/{}", digest), None, auth)?)?;
+        let actual = format!("{:x}", Sha256::digest(config.as_bytes()));
+        if actual != expected {
+            return Err(digest_mismatch(digest, &actual));
+        }
+        Ok(config)
+    }
+
+    fn blob_stream(
+        &self,
+        image: &str,
+        digest: &str,
+        _oci_platform: Option<&str>,
+        auth: Option<&RegistryAuth>,
+        cancel: &PullCancel,
+    ) -> Result<Box<dyn Read + Send>> {
+        let image_ref = parse_ref(image)?;
+        let expected = sha256_hex(digest)?.to_string();
+        let resp = self.get(&image_ref, &format!("blobs/{}", digest), None, auth)?;
+        // ureq doesn't expose the socket to wait on, so read it on its own
+        // thread to let a cancel interrupt a stalled download
+        Ok(Box::new(VerifiedBlob {
+            inner: cancel.interruptible(resp.into_reader()),
+            hasher: Sha256::new(),
+            digest: digest.to_string(),
+            expected,
+            checked: false,
+        }))
+    }
+}
+
+/// A blob stream that is hashed as it is read, failing at the end of the
+/// stream if the content doesn't match its digest.
+struct VerifiedBlob<R> {
+    inner: R,
+    hasher: Sha256,
+    digest: String,
+    /// Expected hex sha256 of the content.
+    expected: String,
+    checked: bool,
+}
+
+impl<R: Read> Read for VerifiedBlob<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        if n == 0 && !buf.is_empty() && !self.checked {
+            self.checked = true;
+            let actual = format!("{:x}", self.hasher.finalize_reset());
+            if actual != self.expected {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    digest_mismatch(&self.digest, &actual).to_string(),
+                ));
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// The hex part of a `sha256:` digest; other algorithms aren't supported.
+fn sha256_hex(digest: &str) -> Result<&str> {
+    digest
+        .strip_prefix("sha256:")
+        .filter(|hex| hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or_else(|| StorageError::ValidationFailed {
+            context: "blob digest".to_string(),
+            reason: format!("unsupported digest '{}'", digest),
+        })
+}
+
+fn digest_mismatch(digest: &str, actual: &str) -> StorageError {
+    StorageError::ValidationFailed {
+        context: format!("blob {}", digest),
+        reason: format!("content has digest sha256:{}", actual),
+    }
+}
+
+fn parse_ref(image: &str) -> Result<ImageRef> {
+    ImageRef::parse(image).map_err(|e| StorageError::InvalidImageReference {
+        reference: image.to_string(),
+        reason: e.reason.to_string(),
+    })
+}
+
+/// Base URL of a registry's API.
+///
+/// Docker Hub's API lives on a separate host; local registries are assumed
+/// to speak plain HTTP.
+fn registry_base_url(registry: &str) -> String {
+    let host = registry.split(':').next().unwrap_or(registry);
+    if registry == "docker.io" {
+        "https://registry-1.docker.io".to_string()
+    } else if host == "localhost" || host == "127.0.0.1" {
+        format!("http://{}", registry)
+    } else {
+        format!("https://{}", registry)
+    }
+}
+
+fn read_text(resp: ureq::Response) -> Result<String> {
+    let url = resp.get_url().to_string();
+    resp.into_string()
+        .map_err(|e| StorageError::new(format!("GET {}: {}", url, e)))
+}
+
+/// Describe a failed request, keeping the status line and the registry's
+/// error body so retry classification can see them.
+fn request_error(url: &str, err: ureq::Error) -> StorageError {
+    match err {
+        ureq::Error::Status(code, resp) => {
+            let status = resp.status_text().to_string();
+            let body = resp.into_string().unwrap_or_default();
+            StorageError::new(format!("GET {}: {} {}: {}", url, code, status, body.trim()))
+        }
+        ureq::Error::Transport(t) => StorageError::new(format!("GET {}: {}", url, t)),
+    }
+}
+
+/// Parse a `WWW-Authenticate` header into its scheme and parameters, e.g.
+/// `Bearer realm="https://auth.example/token",service="registry"`.
+fn parse_challenge(header: &str) -> Option<(String, HashMap<String, String>)> {
+    let header = header.trim();
+    if header.is_empty() {
+        return None;
+    }
+    let (scheme, rest) = header.split_once(' ').unwrap_or((header, ""));
+
+    let mut params = HashMap::new();
+    let mut rest = rest.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key
+            .trim()
+            .trim_start_matches(',')
+            .trim()
+            .to_ascii_lowercase();
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+            }
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        params.insert(key, value.to_string());
+        rest = remaining.trim_start_matches(',').trim();
+    }
+
+    Some((scheme.to_string(), params))
+}
+
+/// Digest of the manifest list entry matching `oci_platform`
+/// (`os/arch[/variant]`), if `manifest` is a manifest list.
+fn select_platform_manifest(manifest: &serde_json::Value, oci_platform: &str) -> Option<String> {
+    let entries = manifest["manifests"].as_array()?;
+    let mut parts = oci_platform.split('/');
+    let os = parts.next()?;
+    let arch = parts.next()?;
+    let variant = parts.next();
+
+    entries
+        .iter()
+        .filter(|m| {
+            m["platform"]["os"].as_str() == Some(os)
+                && m["platform"]["architecture"].as_str() == Some(arch)
+                && variant.is_none_or(|v| m["platform"]["variant"].as_str() == Some(v))
+        })
+        .find_map(|m| m["digest"].as_str().map(String::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_challenge() {
+        let (scheme, params) = parse_challenge(
+            r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/alpine:pull""#,
+        )
+        .unwrap();
+        assert_eq!(scheme, "Bearer");
+        assert_eq!(params["realm"], "https://auth.docker.io/token");
+        assert_eq!(params["service"], "registry.docker.io");
+        assert_eq!(params["scope"], "repository:library/alpine:pull");
+
+        let (scheme, params) = parse_challenge(r#"Basic realm="Registry Realm""#).unwrap();
+        assert_eq!(scheme, "Basic");
+        assert_eq!(params["realm"], "Registry Realm");
+
+        assert!(parse_challenge("").is_none());
+    }
+
+    #[test]
+    fn test_select_platform_manifest() {
+        let index = serde_json::json!({
+            "manifests": [
+                {"digest": "sha256:amd", "platform": {"os": "linux", "architecture": "amd64"}},
+                {"digest": "sha256:v6", "platform": {"os": "linux", "architecture": "arm", "variant": "v6"}},
+                {"digest": "sha256:v7", "platform": {"os": "linux", "architecture": "arm", "variant": "v7"}},
+                {"digest": "sha256:arm64", "platform": {"os": "linux", "architecture": "arm64", "variant": "v8"}},
+            ]
+        });
+        assert_eq!(
+            select_platform_manifest(&index, "linux/amd64").as_deref(),
+            Some("sha256:amd")
+        );
+        assert_eq!(
+            select_platform_manifest(&index, "linux/arm/v7").as_deref(),
+            Some("sha256:v7")
+        );
+        assert_eq!(
+            select_platform_manifest(&index, "linux/arm64").as_deref(),
+            Some("sha256:arm64")
+        );
+        assert!(select_platform_manifest(&index, "linux/s390x").is_none());
+
+        // A plain image manifest has nothing to select
+        let manifest = serde_json::json!({"config": {"digest": "sha256:cfg"}, "layers": []});
+        assert!(select_platform_manifest(&manifest, "linux/amd64").is_none());
+    }
+
+    /// Serve `blobs` (path -> body) over plain HTTP on a local port, one
+    /// request per connection, and return the port.
+    fn serve_blobs(blobs: HashMap<String, Vec<u8>>) -> u16 {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                let path = request_line.split(' ').nth(1).unwrap_or_default();
+                let response = match blobs.get(path) {
+                    Some(body) => [
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes(),
+                        body.clone(),
+                    ]
+                    .concat(),
+                    None => {
+                        b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_vec()
+                    }
+                };
+                let _ = stream.write_all(&response);
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_registry_puller_rejects_tampered_blobs() {
+        let config = br#"{"os":"linux"}"#.to_vec();
+        let layer = b"layer contents".to_vec();
+        let config_digest = format!("sha256:{:x}", Sha256::digest(&config));
+        let layer_digest = format!("sha256:{:x}", Sha256::digest(&layer));
+        let tampered_digest = format!("sha256:{:x}", Sha256::digest(b"original"));
+        let port = serve_blobs(HashMap::from([
+            (
+                format!("/v2/test/img/blobs/{}", config_digest),
+                config.clone(),
+            ),
+            (
+                format!("/v2/test/img/blobs/{}", layer_digest),
+                layer.clone(),
+            ),
+            (
+                format!("/v2/test/img/blobs/{}", tampered_digest),
+                layer.clone(),
+            ),
+        ]));
+        let image = format!("localhost:{}/test/img:1", port);
+        let puller = RegistryPuller::new();
+        let cancel = PullCancel::default();
+
+        // Content matching its digest comes through as is
+        assert_eq!(
+            puller
+                .config(&image, &config_digest, None)
+                .unwrap()
+                .as_bytes(),
+            config
+        );
+        let mut blob = Vec::new();
+        puller
+            .blob_stream(&image, &layer_digest, None, None, &cancel)
+            .unwrap()
+            .read_to_end(&mut blob)
+            .unwrap();
+        assert_eq!(blob, layer);
+
+        // A substituted blob fails once it has been read
+        let err = puller.config(&image, &tampered_digest, None).unwrap_err();
+        assert!(
+            matches!(err, StorageError::ValidationFailed { .. }),
+            "{}",
+            err
+        );
+        let err = puller
+            .blob_stream(&image, &tampered_digest, None, None, &cancel)
+            .unwrap()
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains(&tampered_digest), "{}", err);
+    }
+
+    #[test]
+    fn test_registry_puller_cancel_interrupts_stalled_blob() {
+        use std::io::{BufRead, BufReader, Write};
+
+        // A registry that sends part of a blob and then stalls
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1024\r\n\r\npartial")
+                .unwrap();
+            // Hold the connection open without sending the rest
+            let _ = reader.read_line(&mut line);
+        });
+
+        let image = format!("localhost:{}/test/img:1", port);
+        let digest = format!("sha256:{:x}", Sha256::digest(b"never sent"));
+        let cancel = PullCancel::default();
+        let mut blob = RegistryPuller::new()
+            .blob_stream(&image, &digest, None, None, &cancel)
+            .unwrap();
+        let mut buf = [0u8; 7];
+        blob.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"partial");
+
+        let canceller = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            canceller.cancel();
+        });
+        let start = std::time::Instant::now();
+        let err = blob.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_ne!(err.kind(), std::io::ErrorKind::Interrupted);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_registry_base_url() {
+        assert_eq!(
+            registry_base_url("docker.io"),
+            "https://registry-1.docker.io"
+        );
+        assert_eq!(registry_base_url("ghcr.io"), "https://ghcr.io");
+        assert_eq!(registry_base_url("localhost:5000"), "http://localhost:5000");
+    }
+}
@@ -0,0 +1,414 @@
+//! Restarting containers according to their restart policy.
+//!
+//! A background thread checks the container registry every
+//! [`POLL_INTERVAL`]. When a container the registry has as running turns
+//! out to have exited, its [`RestartPolicy`](smolvm_protocol::RestartPolicy)
+//! decides whether it is started again, after an exponential backoff, or
+//! marked stopped. `stop_container` marks a container stopped before
+//! signalling it, so containers stopped on request are left alone.
+//!
+//! The registry is read without the agent's request lock, so a request may
+//! stop, remove or restart a container while it is being checked. The
+//! supervisor takes the lock and checks the container is still on the run
+//! it saw exit before recording the exit or restarting it.
+//!
+//! A running container its [health checks](crate::health) have found
+//! unhealthy is killed, after the same check, if its policy would restart
+//! it after a failure, and is then restarted the same way.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use parking_lot::{Mutex, MutexGuard};
+use smolvm_protocol::HealthStatus;
+use tracing::{info, warn};
+
+use crate::container::{
+    self, ContainerInfo, ContainerRegistry, ContainerState, StorageError, REGISTRY,
+};
+
+/// How often the registry is checked for exited containers.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delay before the first restart; doubled for each restart after that.
+const RESTART_BACKOFF_BASE: Duration = Duration::from_secs(1);
+
+/// Upper bound on the delay between restarts.
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+/// State of a container's main process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MainProcess {
+    /// Still running (or not started yet).
+    Running,
+    /// Exited, with its exit code if it is known.
+    Exited(Option<i32>),
+}
+
+/// Start the restart supervisor thread. `request_lock` is the lock requests
+/// that change containers run under.
+pub fn spawn(request_lock: &'static Mutex<()>) {
+    let result = std::thread::Builder::new()
+        .name("restart-supervisor".into())
+        .spawn(move || {
+            let mut supervisor = Supervisor::default();
+            loop {
+                std::thread::sleep(POLL_INTERVAL);
+                let changed = supervisor.tick(
+                    &REGISTRY,
+                    request_lock,
+                    Instant::now(),
+                    container::main_process,
+                    container::kill_container,
+                    container::start_container,
+                );
+                if changed {
+                    if let Err(e) = REGISTRY.persist() {
+                        warn!(error = %e, "failed to persist registry after restart check");
+                    }
+                }
+            }
+        });
+    if let Err(e) = result {
+        warn!(error = %e, "failed to start restart supervisor, containers will not be restarted");
+    }
+}
+
+/// Delay before restarting a container that has been restarted
+/// `restart_count` times already.
+fn restart_backoff(restart_count: u32) -> Duration {
+    RESTART_BACKOFF_BASE
+        .saturating_mul(1 << restart_count.min(6))
+        .min(MAX_RESTART_BACKOFF)
+}
+
+/// Take `lock` if `info`'s run is still the registry's running one, i.e.
+/// no request has stopped, removed or restarted the container since `info`
+/// was read. The guard keeps requests out while the caller acts on it.
+fn lock_if_current<'a>(
+    lock: &'a Mutex<()>,
+    registry: &ContainerRegistry,
+    info: &ContainerInfo,
+) -> Option<MutexGuard<'a, ()>> {
+    let guard = lock.lock();
+    let current = registry.get(&info.id)?;
+    (current.state == ContainerState::Running && current.started_at == info.started_at)
+        .then_some(guard)
+}
+
+/// Tracks containers waiting out their backoff.
+#[derive(Debug, Default)]
+struct Supervisor {
+    /// When each exited container is due to be restarted.
+    due: HashMap<String, Instant>,
+}
+
+impl Supervisor {
+    /// Check every running container in `registry` once.
+    ///
+    /// `main_process` reports a container's state, `kill` kills an
+    /// unhealthy one and `restart` starts a stopped container again; exits
+    /// are recorded and restarts run under `lock`. Returns whether the
+    /// registry changed.
+    fn tick(
+        &mut self,
+        registry: &ContainerRegistry,
+        lock: &Mutex<()>,
+        now: Instant,
+        mut main_process: impl FnMut(&str) -> MainProcess,
+        mut kill: impl FnMut(&str),
+        mut restart: impl FnMut(&str) -> Result<(), StorageError>,
+    ) -> bool {
+        let containers = registry.list();
+        self.due.retain(|id, _| {
+            containers
+                .iter()
+                .any(|c| &c.id == id && c.state == ContainerState::Running)
+        });
+
+        let mut changed = false;
+        for info in containers {
+            if info.state != ContainerState::Running || info.restart_policy.is_no() {
+                continue;
+            }
+            let exit_code = match main_process(&info.id) {
+                MainProcess::Exited(exit_code) => exit_code,
+                MainProcess::Running => {
+                    // Unhealthy counts as a failure; it's restarted once the
+                    // kill shows up as an exit
+                    if info.health == Some(HealthStatus::Unhealthy)
+                        && info.restart_policy.should_restart(None, info.restart_count)
+                    {
+                        if let Some(_serialized) = lock_if_current(lock, registry, &info) {
+                            warn!(container_id = %info.id, policy = %info.restart_policy, "killing unhealthy container");
+                            kill(&info.id);
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            if !info
+                .restart_policy
+                .should_restart(exit_code, info.restart_count)
+            {
+                let Some(_serialized) = lock_if_current(lock, registry, &info) else {
+                    continue;
+                };
+                info!(
+                    container_id = %info.id,
+                    exit_code = ?exit_code,
+                    policy = %info.restart_policy,
+                    restart_count = info.restart_count,
+                    "container exited, not restarting"
+                );
+                registry.record_exit(&info.id, exit_code);
+                changed = true;
+                continue;
+            }
+
+            let due = *self.due.entry(info.id.clone()).or_insert_with(|| {
+                let backoff = restart_backoff(info.restart_count);
+                info!(
+                    container_id = %info.id,
+                    exit_code = ?exit_code,
+                    backoff_ms = backoff.as_millis(),
+                    "container exited, scheduling restart"
+                );
+                now + backoff
+            });
+            if now < due {
+                continue;
+            }
+            self.due.remove(&info.id);
+            let Some(_serialized) = lock_if_current(lock, registry, &info) else {
+                continue;
+            };
+
+            // start_container recreates containers it has as stopped
+            registry.record_exit(&info.id, exit_code);
+            registry.record_restart(&info.id);
+            changed = true;
+            match restart(&info.id) {
+                Ok(()) => {
+                    info!(container_id = %info.id, restart_count = info.restart_count + 1, "container restarted");
+                }
+                Err(e) => {
+                    warn!(container_id = %info.id, error = %e, "failed to restart container");
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smolvm_protocol::RestartPolicy;
+
+    fn registry_with(policy: RestartPolicy) -> ContainerRegistry {
+        let registry = ContainerRegistry::new();
+        registry.register(ContainerInfo {
+            state: ContainerState::Running,
+            restart_policy: policy,
+            ..container::test_container("c1", &["false"])
+        });
+        registry
+    }
+
+    #[test]
+    fn test_on_failure_restarts_after_backoff() {
+        let registry = registry_with(RestartPolicy::OnFailure { max_retries: 2 });
+        let mut supervisor = Supervisor::default();
+        let mut restarts = Vec::new();
+        let start = Instant::now();
+        let tick = |supervisor: &mut Supervisor, at: Duration, restarts: &mut Vec<String>| {
+            supervisor.tick(
+                &registry,
+                &Mutex::new(()),
+                start + at,
+                |_| MainProcess::Exited(Some(1)),
+                |_| panic!("exited container killed"),
+                |id| {
+                    restarts.push(id.to_string());
+                    // The restarted container is running again
+                    registry.update_state(id, ContainerState::Running);
+                    Ok(())
+                },
+            )
+        };
+
+        // Exit noticed: restart waits out the backoff
+        tick(&mut supervisor, Duration::ZERO, &mut restarts);
+        assert!(restarts.is_empty());
+        tick(&mut supervisor, restart_backoff(0), &mut restarts);
+        assert_eq!(restarts, ["c1"]);
+        assert_eq!(registry.get("c1").unwrap().restart_count, 1);
+
+        // The second restart backs off for longer
+        let second_exit = restart_backoff(0) + Duration::from_millis(1);
+        tick(&mut supervisor, second_exit, &mut restarts);
+        tick(
+            &mut supervisor,
+            second_exit + restart_backoff(0),
+            &mut restarts,
+        );
+        assert_eq!(restarts.len(), 1);
+        tick(
+            &mut supervisor,
+            second_exit + restart_backoff(1),
+            &mut restarts,
+        );
+        assert_eq!(restarts.len(), 2);
+
+        // max_retries reached: the container is left stopped
+        assert!(tick(
+            &mut supervisor,
+            Duration::from_secs(600),
+            &mut restarts
+        ));
+        assert_eq!(restarts.len(), 2);
+        let info = registry.get("c1").unwrap();
+        assert_eq!(info.restart_count, 2);
+        assert_eq!(info.state, ContainerState::Stopped);
+        assert_eq!(
+            info.status(),
+            smolvm_protocol::ContainerStatus::Exited { code: Some(1) }
+        );
+        assert!(info.finished_at.is_some());
+    }
+
+    #[test]
+    fn test_no_policy_never_restarts() {
+        let registry = registry_with(RestartPolicy::No);
+        let mut supervisor = Supervisor::default();
+        let changed = supervisor.tick(
+            &registry,
+            &Mutex::new(()),
+            Instant::now() + Duration::from_secs(600),
+            |_| MainProcess::Exited(Some(1)),
+            |_| panic!("exited container killed"),
+            |_| panic!("container restarted under the 'no' policy"),
+        );
+        assert!(!changed);
+        assert_eq!(registry.get("c1").unwrap().restart_count, 0);
+    }
+
+    #[test]
+    fn test_on_failure_leaves_clean_exit_stopped() {
+        let registry = registry_with(RestartPolicy::OnFailure { max_retries: 0 });
+        let mut supervisor = Supervisor::default();
+        let mut probed = false;
+        supervisor.tick(
+            &registry,
+            &Mutex::new(()),
+            Instant::now(),
+            |_| {
+                probed = true;
+                MainProcess::Exited(Some(0))
+            },
+            |_| panic!("exited container killed"),
+            |_| panic!("clean exit restarted under on-failure"),
+        );
+        assert!(probed);
+        assert_eq!(registry.get("c1").unwrap().state, ContainerState::Stopped);
+    }
+
+    #[test]
+    fn test_unhealthy_container_killed_under_on_failure() {
+        for (policy, restart_count, killed) in [
+            (RestartPolicy::OnFailure { max_retries: 2 }, 0, true),
+            (RestartPolicy::Always, 5, true),
+            (RestartPolicy::OnFailure { max_retries: 2 }, 2, false),
+        ] {
+            let registry = registry_with(policy);
+            let mut info = registry.get("c1").unwrap();
+            info.health = Some(HealthStatus::Unhealthy);
+            info.restart_count = restart_count;
+            registry.register(info);
+
+            let mut kills = Vec::new();
+            Supervisor::default().tick(
+                &registry,
+                &Mutex::new(()),
+                Instant::now(),
+                |_| MainProcess::Running,
+                |id| kills.push(id.to_string()),
+                |_| panic!("running container restarted"),
+            );
+            assert_eq!(kills.len(), killed as usize, "policy {}", policy);
+        }
+
+        // Unhealthy isn't acted on without a restart policy
+        let registry = registry_with(RestartPolicy::No);
+        let mut info = registry.get("c1").unwrap();
+        info.health = Some(HealthStatus::Unhealthy);
+        registry.register(info);
+        Supervisor::default().tick(
+            &registry,
+            &Mutex::new(()),
+            Instant::now(),
+            |_| MainProcess::Running,
+            |_| panic!("container killed under the 'no' policy"),
+            |_| panic!("container restarted under the 'no' policy"),
+        );
+    }
+
+    #[test]
+    fn test_container_stopped_during_check_is_left_alone() {
+        for policy in [
+            RestartPolicy::Always,
+            RestartPolicy::OnFailure { max_retries: 0 },
+        ] {
+            let registry = registry_with(policy);
+            let changed = Supervisor::default().tick(
+                &registry,
+                &Mutex::new(()),
+                Instant::now() + Duration::from_secs(600),
+                |id| {
+                    // A stop request lands between the snapshot and the restart
+                    registry.update_state(id, ContainerState::Stopped);
+                    MainProcess::Exited(Some(137))
+                },
+                |_| panic!("exited container killed"),
+                |_| panic!("container stopped on request restarted"),
+            );
+            assert!(!changed, "policy {}", policy);
+            let info = registry.get("c1").unwrap();
+            assert_eq!(info.restart_count, 0);
+            assert_eq!(info.exit_code, None);
+        }
+    }
+
+    #[test]
+    fn test_container_restarted_during_check_is_not_killed() {
+        let registry = registry_with(RestartPolicy::Always);
+        let mut info = registry.get("c1").unwrap();
+        info.health = Some(HealthStatus::Unhealthy);
+        info.started_at = Some(100);
+        registry.register(info);
+
+        Supervisor::default().tick(
+            &registry,
+            &Mutex::new(()),
+            Instant::now(),
+            |id| {
+                // A request restarts it between the snapshot and the kill
+                let mut info = registry.get(id).unwrap();
+                info.started_at = Some(200);
+                registry.register(info);
+                MainProcess::Running
+            },
+            |_| panic!("restarted container killed for its previous run's health"),
+            |_| panic!("running container restarted"),
+        );
+    }
+
+    #[test]
+    fn test_restart_backoff_is_capped() {
+        assert_eq!(restart_backoff(0), Duration::from_secs(1));
+        assert_eq!(restart_backoff(3), Duration::from_secs(8));
+        assert_eq!(restart_backoff(100), MAX_RESTART_BACKOFF);
+    }
+}
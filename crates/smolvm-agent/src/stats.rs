@@ -0,0 +1,287 @@
+//! CPU and memory usage sampling.
+//!
+//! VM-wide usage comes from `/proc/stat` and `/proc/meminfo`. Container usage
+//! comes from the container's cgroup v2 accounting files when it has its own
+//! cgroup. crun runs with the cgroup manager disabled (see
+//! [`CRUN_CGROUP_MANAGER`](crate::paths::CRUN_CGROUP_MANAGER)), so containers
+//! usually share the root cgroup; in that case the container's init process
+//! is sampled from `/proc/<pid>` instead.
+
+use crate::container;
+use crate::storage::StorageError;
+use smolvm_protocol::ResourceStats;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// cgroup v2 mount point.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Microseconds per clock tick. The guest kernel runs with USER_HZ = 100.
+const USEC_PER_TICK: u64 = 10_000;
+
+/// Usage read from a cgroup, or from a single process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Usage {
+    cpu_usage_usec: u64,
+    memory_bytes: u64,
+    memory_limit_bytes: Option<u64>,
+}
+
+/// Sample the whole VM, or one container if `container_id` is given.
+pub fn sample(container_id: Option<&str>) -> Result<ResourceStats, StorageError> {
+    let proc_stat = read("/proc/stat")?;
+    let meminfo = read("/proc/meminfo")?;
+    let (vm_cpu_usec, online_cpus) = parse_proc_stat(&proc_stat)?;
+    let (vm_used, vm_total) = parse_meminfo(&meminfo)?;
+
+    let (container_id, usage) = match container_id {
+        None => (
+            None,
+            Usage {
+                cpu_usage_usec: vm_cpu_usec,
+                memory_bytes: vm_used,
+                memory_limit_bytes: Some(vm_total),
+            },
+        ),
+        Some(id) => {
+            let (id, pid) = container::container_pid(id)?;
+            let mut usage = container_usage(pid)?;
+            // Without a cgroup limit, the container can use all VM memory
+            usage.memory_limit_bytes = usage.memory_limit_bytes.or(Some(vm_total));
+            (Some(id), usage)
+        }
+    };
+
+    Ok(ResourceStats {
+        container_id,
+        timestamp_ms: now_ms(),
+        cpu_usage_usec: usage.cpu_usage_usec,
+        online_cpus,
+        memory_bytes: usage.memory_bytes,
+        memory_limit_bytes: usage.memory_limit_bytes,
+    })
+}
+
+/// Usage of the container whose init process is `pid`.
+fn container_usage(pid: u32) -> Result<Usage, StorageError> {
+    let proc_dir = PathBuf::from(format!("/proc/{}", pid));
+    let cgroup = read(proc_dir.join("cgroup"))?;
+    if let Some(rel) = parse_cgroup_path(&cgroup).filter(|p| *p != "/") {
+        let dir = Path::new(CGROUP_ROOT).join(rel.trim_start_matches('/'));
+        if dir.join("memory.current").exists() {
+            return read_cgroup(&dir);
+        }
+    }
+    process_usage(&proc_dir)
+}
+
+/// Read cgroup v2 accounting from `dir`.
+fn read_cgroup(dir: &Path) -> Result<Usage, StorageError> {
+    let memory_bytes = parse_u64(&read(dir.join("memory.current"))?, "memory.current")?;
+    let memory_limit_bytes = match fs::read_to_string(dir.join("memory.max")) {
+        Ok(max) if max.trim() != "max" => Some(parse_u64(&max, "memory.max")?),
+        _ => None,
+    };
+    let cpu_stat = read(dir.join("cpu.stat"))?;
+    let cpu_usage_usec = cpu_stat
+        .lines()
+        .find_map(|line| line.strip_prefix("usage_usec "))
+        .ok_or_else(|| StorageError::MissingField {
+            context: "cpu.stat".into(),
+            field: "usage_usec".into(),
+        })
+        .and_then(|v| parse_u64(v, "cpu.stat usage_usec"))?;
+
+    Ok(Usage {
+        cpu_usage_usec,
+        memory_bytes,
+        memory_limit_bytes,
+    })
+}
+
+/// Usage of a single process from `/proc/<pid>/{stat,status}`.
+fn process_usage(proc_dir: &Path) -> Result<Usage, StorageError> {
+    let stat = read(proc_dir.join("stat"))?;
+    // The command name may contain spaces; fields resume after its ')'
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .map(|(_, rest)| rest.split_whitespace().collect())
+        .unwrap_or_default();
+    // utime and stime are fields 14 and 15; `fields` starts at field 3
+    let ticks = match (fields.get(11), fields.get(12)) {
+        (Some(utime), Some(stime)) => {
+            parse_u64(utime, "proc stat utime")? + parse_u64(stime, "proc stat stime")?
+        }
+        _ => {
+            return Err(StorageError::MissingField {
+                context: "proc stat".into(),
+                field: "utime".into(),
+            })
+        }
+    };
+
+    let status = read(proc_dir.join("status"))?;
+    let rss_kb = meminfo_field(&status, "VmRSS").unwrap_or(0);
+
+    Ok(Usage {
+        cpu_usage_usec: ticks * USEC_PER_TICK,
+        memory_bytes: rss_kb * 1024,
+        memory_limit_bytes: None,
+    })
+}
+
+/// Busy CPU time in microseconds and the number of CPUs, from `/proc/stat`.
+fn parse_proc_stat(content: &str) -> Result<(u64, u32), StorageError> {
+    let mut busy_ticks = None;
+    let mut cpus = 0;
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("cpu") => {
+                let values: Vec<u64> = fields.filter_map(|v| v.parse().ok()).collect();
+                // user nice system idle iowait irq softirq steal ...
+                let busy: u64 = values
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != 3 && *i != 4)
+                    .take(6)
+                    .map(|(_, v)| v)
+                    .sum();
+                busy_ticks = Some(busy);
+            }
+            Some(name) if name.starts_with("cpu") => cpus += 1,
+            _ => {}
+        }
+    }
+    let busy_ticks = busy_ticks.ok_or_else(|| StorageError::MissingField {
+        context: "/proc/stat".into(),
+        field: "cpu".into(),
+    })?;
+    Ok((busy_ticks * USEC_PER_TICK, cpus.max(1)))
+}
+
+/// Used and total memory in bytes, from `/proc/meminfo`.
+fn parse_meminfo(content: &str) -> Result<(u64, u64), StorageError> {
+    let field = |name: &str| {
+        meminfo_field(content, name).ok_or_else(|| StorageError::MissingField {
+            context: "/proc/meminfo".into(),
+            field: name.into(),
+        })
+    };
+    let total = field("MemTotal")?;
+    let available = field("MemAvailable")?;
+    Ok((total.saturating_sub(available) * 1024, total * 1024))
+}
+
+/// Value of a `Name:   1234 kB` line, in kB.
+fn meminfo_field(content: &str, name: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (key, rest) = line.split_once(':')?;
+        if key != name {
+            return None;
+        }
+        rest.split_whitespace().next()?.parse().ok()
+    })
+}
+
+/// The cgroup v2 path from `/proc/<pid>/cgroup` (the `0::/path` line).
+fn parse_cgroup_path(content: &str) -> Option<&str> {
+    content.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+fn parse_u64(value: &str, context: &str) -> Result<u64, StorageError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|e: std::num::ParseIntError| StorageError::ParseError {
+            context: context.into(),
+            cause: e.to_string(),
+        })
+}
+
+fn read(path: impl AsRef<Path>) -> Result<String, StorageError> {
+    let path = path.as_ref();
+    fs::read_to_string(path).map_err(|e| StorageError::read_error(path.display().to_string(), e))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_cgroup_fixture() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("memory.current"), "52428800\n").unwrap();
+        fs::write(dir.path().join("memory.max"), "268435456\n").unwrap();
+        fs::write(
+            dir.path().join("cpu.stat"),
+            "usage_usec 1234567\nuser_usec 1000000\nsystem_usec 234567\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_cgroup(dir.path()).unwrap(),
+            Usage {
+                cpu_usage_usec: 1_234_567,
+                memory_bytes: 50 << 20,
+                memory_limit_bytes: Some(256 << 20),
+            }
+        );
+
+        // An unlimited cgroup reports "max"
+        fs::write(dir.path().join("memory.max"), "max\n").unwrap();
+        assert_eq!(read_cgroup(dir.path()).unwrap().memory_limit_bytes, None);
+    }
+
+    #[test]
+    fn test_parse_proc_fixtures() {
+        let stat = "cpu  100 5 50 1000 20 3 2 0 0 0\n\
+                    cpu0 60 5 30 500 10 2 1 0 0 0\n\
+                    cpu1 40 0 20 500 10 1 1 0 0 0\n\
+                    intr 12345\n";
+        // user + nice + system + irq + softirq + steal = 160 ticks
+        assert_eq!(parse_proc_stat(stat).unwrap(), (1_600_000, 2));
+
+        let meminfo = "MemTotal:        1024000 kB\n\
+                       MemFree:          200000 kB\n\
+                       MemAvailable:     768000 kB\n";
+        assert_eq!(
+            parse_meminfo(meminfo).unwrap(),
+            (256_000 * 1024, 1_024_000 * 1024)
+        );
+
+        assert_eq!(parse_cgroup_path("0::/smolvm/abc\n"), Some("/smolvm/abc"));
+    }
+
+    #[test]
+    fn test_process_usage_fixture() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("stat"),
+            "42 (my app) S 1 42 42 0 -1 4194560 100 0 0 0 150 50 0 0 20 0 1 0 10 1000 200\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("status"),
+            "Name:\tmy app\nVmRSS:\t  2048 kB\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            process_usage(dir.path()).unwrap(),
+            Usage {
+                cpu_usage_usec: 200 * USEC_PER_TICK,
+                memory_bytes: 2048 * 1024,
+                memory_limit_bytes: None,
+            }
+        );
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! This module handles:
 //! - Storage disk initialization and formatting
-//! - OCI image pulling via crane
+//! - OCI image pulling through a pluggable [`OciPuller`]
 //! - Layer extraction and deduplication
 //! - Overlay filesystem management
 //! - Container execution via crun OCI runtime
@@ -10,9 +10,26 @@
 
 use crate::crun::CrunCommand;
 use crate::oci::{generate_container_id, OciSpec};
+use crate::overlay_lru::{self, OverlayIndex, OverlayLease};
 use crate::paths;
-use crate::process::{wait_with_timeout_and_cleanup, WaitResult, TIMEOUT_EXIT_CODE};
-use smolvm_protocol::{ImageInfo, OverlayInfo, RegistryAuth, StorageStatus};
+use crate::process::{
+    oom_kill_count, wait_with_timeout_and_cleanup, WaitResult, TIMEOUT_EXIT_CODE,
+};
+use crate::pull_cancel::PullCancel;
+use crate::pull_limit;
+use crate::puller::{self, OciPuller};
+use crate::tools;
+use sha2::{Digest, Sha256};
+use smolvm_protocol::dns::{DnsConfig, DNS_SEARCH_ENV, DNS_SERVERS_ENV};
+use smolvm_protocol::platform::{self, native_arch};
+use smolvm_protocol::scratch::OVERLAY_UPPER_DIR_ENV;
+use smolvm_protocol::{
+    AgentStatus, DiffEntry, DiffKind, ExitReason, ImageInfo, ImageRef, LayerUsage, OverlayInfo,
+    PrunedOverlays, RegistryAuth, ResourceLimits, SecurityOptions, StorageStatus, TmpfsMount,
+    VerifyReport,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::OnceLock;
@@ -27,6 +44,176 @@ const CONFIGS_DIR: &str = "configs";
 const MANIFESTS_DIR: &str = "manifests";
 const OVERLAYS_DIR: &str = "overlays";
 
+/// File in an overlay's directory listing its lower layers, top first.
+const LOWERDIRS_FILE: &str = "lowerdirs";
+
+/// Content store that deduplicated layer files are hardlinked to.
+const CONTENT_DIR: &str = "content";
+
+/// Checksums of extracted layer content, recorded at pull time so `Verify`
+/// can spot layers damaged later (e.g. truncated by an unclean shutdown).
+const CHECKSUMS_DIR: &str = "checksums";
+
+/// Scratch space for layer exports and image archives. Kept on the storage
+/// disk, as the guest's /tmp can't be written on every host.
+const TMP_DIR: &str = "tmp";
+
+/// Annotation carrying a layer's digest in this store on the layer blobs of
+/// a saved image. Layers are saved from their extracted form, so the blob
+/// is a fresh tar whose digest differs from the one they were pulled as.
+const LAYER_DIGEST_ANNOTATION: &str = "io.smolvm.layer.digest";
+
+/// OCI annotation naming the reference an image in a layout was saved as.
+const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+const OCI_MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const OCI_CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const OCI_LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+
+/// Userspace overlay implementation used when the kernel's overlayfs can't
+/// be mounted (missing module, or overlay-on-overlay not permitted).
+const FUSE_OVERLAYFS_BIN: &str = "fuse-overlayfs";
+
+/// Default multiplier from compressed layer size to the disk space reserved
+/// for extracting it. Layers typically decompress to 2-3x their compressed
+/// size, so 3x errs on the side of failing early.
+const DEFAULT_PULL_SPACE_MULTIPLIER: f64 = 3.0;
+
+/// Environment variable overriding [`DEFAULT_PULL_SPACE_MULTIPLIER`].
+const PULL_SPACE_MULTIPLIER_ENV: &str = "SMOLVM_PULL_SPACE_MULTIPLIER";
+
+/// Free inodes below which a pull is refused. Every file in a layer takes
+/// one, so a disk can run out of inodes with plenty of blocks left.
+const MIN_FREE_INODES: u64 = 1024;
+
+/// Fraction of free inodes below which a pull warns that they are running
+/// out.
+const LOW_INODE_FRACTION: f64 = 0.05;
+
+/// Filesystems overlayfs can't use as an upper layer, by statfs magic: they
+/// lack the xattrs or d_type support it needs, or are overlays themselves.
+const UNSUPPORTED_UPPER_FILESYSTEMS: &[(u32, &str)] = &[
+    (0x794c_7630, "overlayfs"),
+    (0x6573_5546, "fuse"),
+    (0x0102_1997, "9p"),
+    (0x0000_6969, "nfs"),
+    (0xff53_4d42, "cifs"),
+    (0xfe53_4d42, "smb2"),
+    (0x0000_4d44, "vfat"),
+    (0x2011_bab0, "exfat"),
+];
+
+/// Upper layer root from [`OVERLAY_UPPER_DIR_ENV`], if set, or why it is
+/// unusable.
+static OVERLAY_UPPER_ROOT: OnceLock<std::result::Result<Option<PathBuf>, String>> = OnceLock::new();
+
+/// The directory overlays' upper and work directories go in, if one is
+/// configured. A directory that fails [`validate_overlay_upper_root`] is an
+/// error: every overlay setup fails with it rather than quietly filling the
+/// storage disk the scratch disk was meant to spare.
+pub fn overlay_upper_root() -> Result<Option<&'static Path>> {
+    let root = OVERLAY_UPPER_ROOT.get_or_init(|| {
+        let Some(dir) = std::env::var_os(OVERLAY_UPPER_DIR_ENV).map(PathBuf::from) else {
+            return Ok(None);
+        };
+        validate_overlay_upper_root(&dir)
+            .map(|()| {
+                info!(path = %dir.display(), "keeping overlay upper layers outside the layer store");
+                Some(dir)
+            })
+            .map_err(|e| e.to_string())
+    });
+    match root {
+        Ok(dir) => Ok(dir.as_deref()),
+        Err(reason) => Err(StorageError::ValidationFailed {
+            context: OVERLAY_UPPER_DIR_ENV.into(),
+            reason: reason.clone(),
+        }),
+    }
+}
+
+/// The configured upper layer root, for finding upper layers that already
+/// exist. An unusable one never had any created in it.
+fn existing_overlay_upper_root() -> Option<&'static Path> {
+    overlay_upper_root().ok().flatten()
+}
+
+/// Check that `dir` can hold overlay upper and work directories: it is an
+/// absolute path, can be created and written, and is on a filesystem
+/// overlayfs accepts as upperdir.
+fn validate_overlay_upper_root(dir: &Path) -> Result<()> {
+    let invalid = |reason: String| StorageError::ValidationFailed {
+        context: OVERLAY_UPPER_DIR_ENV.into(),
+        reason,
+    };
+    if !dir.is_absolute() {
+        return Err(invalid(format!(
+            "'{}' is not an absolute path",
+            dir.display()
+        )));
+    }
+    std::fs::create_dir_all(dir)?;
+
+    let probe = dir.join(format!(".smolvm-write-test-{}", std::process::id()));
+    std::fs::write(&probe, b"")
+        .map_err(|e| invalid(format!("'{}' is not writable: {}", dir.display(), e)))?;
+    let _ = std::fs::remove_file(&probe);
+
+    if let Some(name) = filesystem_magic(dir)?.and_then(|magic| {
+        UNSUPPORTED_UPPER_FILESYSTEMS
+            .iter()
+            .find(|(m, _)| *m == magic)
+            .map(|(_, name)| *name)
+    }) {
+        return Err(invalid(format!(
+            "'{}' is on {}, which overlayfs can't use as an upper layer",
+            dir.display(),
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// The statfs magic number of the filesystem `path` is on, where known.
+#[allow(unused_variables)] // path is used only on Linux
+fn filesystem_magic(path: &Path) -> Result<Option<u32>> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::ffi::CString;
+        use std::mem::MaybeUninit;
+
+        let path_cstr = CString::new(path.to_string_lossy().as_bytes()).map_err(|_| {
+            StorageError::InvalidPath {
+                path: path.display().to_string(),
+            }
+        })?;
+
+        unsafe {
+            let mut stat: MaybeUninit<libc::statfs> = MaybeUninit::uninit();
+            if libc::statfs(path_cstr.as_ptr(), stat.as_mut_ptr()) != 0 {
+                return Err(std::io::Error::last_os_error().into());
+            }
+            // Magic numbers are 32 bits, whatever width f_type has here
+            Ok(Some(stat.assume_init().f_type as u32))
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        Ok(None)
+    }
+}
+
+/// Directory holding a workload's `upper` and `work` directories: its own
+/// directory under `upper_root` if one is configured, else its overlay
+/// directory.
+fn overlay_upper_base(root: &Path, upper_root: Option<&Path>, workload_id: &str) -> PathBuf {
+    match upper_root {
+        Some(upper_root) => upper_root.join(workload_id),
+        None => root.join(OVERLAYS_DIR).join(workload_id),
+    }
+}
+
 /// Global state for packed layers support.
 /// Set at startup if SMOLVM_PACKED_LAYERS env var is present.
 static PACKED_LAYERS_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
@@ -93,9 +280,7 @@ pub fn init_packed_layers() -> Option<PathBuf> {
 
 /// Get the packed layers directory if available.
 pub fn get_packed_layers_dir() -> Option<&'static PathBuf> {
-    PACKED_LAYERS_DIR
-        .get_or_init(|| init_packed_layers())
-        .as_ref()
+    PACKED_LAYERS_DIR.get_or_init(init_packed_layers).as_ref()
 }
 
 /// Create a synthetic ImageInfo from packed layers.
@@ -133,13 +318,7 @@ fn create_packed_image_info(image: &str, packed_dir: &Path) -> Result<ImageInfo>
         }
     }
 
-    // Determine architecture from environment or default
-    #[cfg(target_arch = "aarch64")]
-    let architecture = "arm64".to_string();
-    #[cfg(target_arch = "x86_64")]
-    let architecture = "amd64".to_string();
-    #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
-    let architecture = "unknown".to_string();
+    let architecture = native_arch().to_string();
 
     Ok(ImageInfo {
         reference: image.to_string(),
@@ -155,6 +334,9 @@ fn create_packed_image_info(image: &str, packed_dir: &Path) -> Result<ImageInfo>
         cmd: Vec::new(),
         env: Vec::new(),
         workdir: None,
+        labels: BTreeMap::new(),
+        tags: Vec::new(),
+        refreshed_layers: Vec::new(),
     })
 }
 
@@ -189,8 +371,12 @@ pub enum StorageError {
     ImageNotFound { image: String },
     /// Failed to pull image from registry.
     ImagePullFailed { image: String, cause: String },
+    /// Image pull cancelled by the client.
+    PullCancelled { image: String },
     /// Invalid image reference format.
     InvalidImageReference { reference: String, reason: String },
+    /// Image can't be removed while a container or run still uses it.
+    ImageInUse { image: String, user: String },
 
     // ========================================================================
     // Layer Errors
@@ -215,12 +401,22 @@ pub enum StorageError {
     MissingField { context: String, field: String },
     /// Unsupported manifest format.
     UnsupportedManifest { media_type: String },
+    /// The registry only returned a manifest list, with no manifest for the
+    /// requested platform (`None` for the default).
+    PlatformUnavailable {
+        requested: Option<String>,
+        available: Vec<String>,
+    },
 
     // ========================================================================
     // Mount Errors
     // ========================================================================
     /// Failed to mount overlay filesystem.
     OverlayMountFailed { path: String, cause: String },
+    /// The workload has no overlay.
+    OverlayNotFound { workload_id: String },
+    /// Kernel overlayfs is unusable and fuse-overlayfs is not installed.
+    OverlayUnsupported { cause: String },
     /// Failed to unmount filesystem.
     UnmountFailed { path: String, cause: String },
 
@@ -241,14 +437,27 @@ pub enum StorageError {
     // ========================================================================
     /// Input validation failed.
     ValidationFailed { context: String, reason: String },
+    /// The requested working directory doesn't exist in the container.
+    WorkdirNotFound { path: String },
+    /// The image was built for a different architecture than it would run on.
+    ArchMismatch {
+        image_arch: String,
+        target_arch: String,
+    },
 
     // ========================================================================
     // Storage State Errors
     // ========================================================================
     /// Storage not formatted/initialized.
     StorageNotReady { reason: String },
+    /// Storage is already formatted and a reformat was not forced.
+    AlreadyFormatted { path: String },
     /// No images found in storage.
     NoImagesFound,
+    /// Not enough free space on the storage disk.
+    InsufficientSpace { required: u64, available: u64 },
+    /// The storage disk has (almost) no free inodes left.
+    InsufficientInodes { free: u64, total: u64 },
 
     // ========================================================================
     // Generic
@@ -351,9 +560,15 @@ impl std::fmt::Display for StorageError {
             StorageError::ImagePullFailed { image, cause } => {
                 write!(f, "failed to pull image '{}': {}", image, cause)
             }
+            StorageError::PullCancelled { image } => {
+                write!(f, "pull of image '{}' was cancelled", image)
+            }
             StorageError::InvalidImageReference { reference, reason } => {
                 write!(f, "invalid image reference '{}': {}", reference, reason)
             }
+            StorageError::ImageInUse { image, user } => {
+                write!(f, "image '{}' is in use by {}", image, user)
+            }
 
             // Layer errors
             StorageError::LayerNotFound { digest } => {
@@ -384,11 +599,33 @@ impl std::fmt::Display for StorageError {
             StorageError::UnsupportedManifest { media_type } => {
                 write!(f, "unsupported manifest format: {}", media_type)
             }
+            StorageError::PlatformUnavailable {
+                requested,
+                available,
+            } => {
+                write!(
+                    f,
+                    "got manifest list instead of image manifest: platform {} not available \
+                     (available: {})",
+                    requested.as_deref().unwrap_or("default"),
+                    available.join(", ")
+                )
+            }
 
             // Mount errors
             StorageError::OverlayMountFailed { path, cause } => {
                 write!(f, "overlay mount failed at '{}': {}", path, cause)
             }
+            StorageError::OverlayNotFound { workload_id } => {
+                write!(f, "no overlay for workload '{}'", workload_id)
+            }
+            StorageError::OverlayUnsupported { cause } => {
+                write!(
+                    f,
+                    "overlay filesystem unsupported ({}) and fuse-overlayfs is not installed",
+                    cause
+                )
+            }
             StorageError::UnmountFailed { path, cause } => {
                 write!(f, "failed to unmount '{}': {}", path, cause)
             }
@@ -413,14 +650,56 @@ impl std::fmt::Display for StorageError {
             StorageError::ValidationFailed { context, reason } => {
                 write!(f, "{}: {}", context, reason)
             }
+            StorageError::WorkdirNotFound { path } => {
+                write!(
+                    f,
+                    "working directory '{}' does not exist in the image",
+                    path
+                )
+            }
+            StorageError::ArchMismatch {
+                image_arch,
+                target_arch,
+            } => {
+                write!(
+                    f,
+                    "image is built for {} but would run on {}",
+                    image_arch, target_arch
+                )
+            }
 
             // Storage state errors
             StorageError::StorageNotReady { reason } => {
                 write!(f, "storage not ready: {}", reason)
             }
+            StorageError::AlreadyFormatted { path } => {
+                write!(
+                    f,
+                    "storage at '{}' is already formatted (use force to reformat)",
+                    path
+                )
+            }
             StorageError::NoImagesFound => {
                 write!(f, "no images found")
             }
+            StorageError::InsufficientSpace {
+                required,
+                available,
+            } => {
+                write!(
+                    f,
+                    "not enough disk space: need ~{} MiB, {} MiB available",
+                    required.div_ceil(1024 * 1024),
+                    available / (1024 * 1024)
+                )
+            }
+            StorageError::InsufficientInodes { free, total } => {
+                write!(
+                    f,
+                    "storage disk is out of inodes: {} of {} free (remove unused images with gc)",
+                    free, total
+                )
+            }
 
             // Generic
             StorageError::Internal { message } => {
@@ -525,6 +804,7 @@ pub fn init() -> Result<()> {
         (CONFIGS_DIR, "image configurations"),
         (MANIFESTS_DIR, "image manifests"),
         (OVERLAYS_DIR, "overlay filesystems"),
+        (CHECKSUMS_DIR, "layer checksums"),
     ];
 
     for (dir, description) in &required_dirs {
@@ -554,9 +834,29 @@ pub fn init() -> Result<()> {
 /// Format the storage disk.
 ///
 /// Creates all required directories and writes the format marker file.
-/// If directories already exist, they are left as-is.
-pub fn format() -> Result<()> {
-    let root = Path::new(STORAGE_ROOT);
+/// Refuses with [`StorageError::AlreadyFormatted`] if the marker already
+/// exists, unless `force` is set.
+pub fn format(force: bool) -> Result<()> {
+    let container_dirs = [
+        (PathBuf::from(paths::CONTAINERS_RUN_DIR), "container run"),
+        (PathBuf::from(paths::CONTAINERS_LOGS_DIR), "container logs"),
+        (PathBuf::from(paths::CONTAINERS_EXIT_DIR), "container exit"),
+        (PathBuf::from(paths::CRUN_ROOT_DIR), "crun state root"),
+    ];
+    format_at(Path::new(STORAGE_ROOT), &container_dirs, force)
+}
+
+/// Format the storage rooted at `root`, also creating `extra_dirs`.
+fn format_at(root: &Path, extra_dirs: &[(PathBuf, &str)], force: bool) -> Result<()> {
+    let marker = root.join(".smolvm_formatted");
+    if marker.exists() {
+        if !force {
+            return Err(StorageError::AlreadyFormatted {
+                path: root.display().to_string(),
+            });
+        }
+        warn!(path = %root.display(), "reformatting already formatted storage");
+    }
 
     // Ensure storage root exists
     if !root.exists() {
@@ -570,18 +870,15 @@ pub fn format() -> Result<()> {
     }
 
     // Create all storage directories
-    let all_dirs = [
+    let storage_dirs = [
         (root.join(LAYERS_DIR), "layers"),
         (root.join(CONFIGS_DIR), "configs"),
         (root.join(MANIFESTS_DIR), "manifests"),
         (root.join(OVERLAYS_DIR), "overlays"),
-        (PathBuf::from(paths::CONTAINERS_RUN_DIR), "container run"),
-        (PathBuf::from(paths::CONTAINERS_LOGS_DIR), "container logs"),
-        (PathBuf::from(paths::CONTAINERS_EXIT_DIR), "container exit"),
-        (PathBuf::from(paths::CRUN_ROOT_DIR), "crun state root"),
+        (root.join(CHECKSUMS_DIR), "checksums"),
     ];
 
-    for (path, name) in &all_dirs {
+    for (path, name) in storage_dirs.iter().chain(extra_dirs) {
         std::fs::create_dir_all(path).map_err(|e| {
             StorageError::new(format!(
                 "failed to create {} directory '{}': {}",
@@ -593,7 +890,6 @@ pub fn format() -> Result<()> {
     }
 
     // Create marker file
-    let marker = root.join(".smolvm_formatted");
     std::fs::write(&marker, "1").map_err(|e| {
         StorageError::new(format!(
             "failed to write format marker '{}': {}",
@@ -613,8 +909,7 @@ pub fn status() -> Result<StorageStatus> {
 
     let ready = marker.exists();
 
-    // Get disk usage (simplified)
-    let (total_bytes, used_bytes) = get_disk_usage(root)?;
+    let usage = get_disk_usage(root)?;
 
     // Count layers and images
     let layer_count = count_entries(&root.join(LAYERS_DIR))?;
@@ -622,13 +917,41 @@ pub fn status() -> Result<StorageStatus> {
 
     Ok(StorageStatus {
         ready,
-        total_bytes,
-        used_bytes,
+        total_bytes: usage.total_bytes,
+        used_bytes: usage.used_bytes,
+        total_inodes: usage.total_inodes,
+        free_inodes: usage.free_inodes,
         layer_count,
         image_count,
     })
 }
 
+/// Get the agent's liveness status, given how long it has been up.
+pub fn agent_status(uptime: std::time::Duration) -> Result<AgentStatus> {
+    agent_status_at(Path::new(STORAGE_ROOT), uptime, is_mountpoint)
+}
+
+fn agent_status_at(
+    root: &Path,
+    uptime: std::time::Duration,
+    is_mounted: impl Fn(&Path) -> bool,
+) -> Result<AgentStatus> {
+    let active_overlay_count = match std::fs::read_dir(root.join(OVERLAYS_DIR)) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| is_mounted(&entry.path().join("merged")))
+            .count(),
+        Err(_) => 0,
+    };
+    Ok(AgentStatus {
+        version: smolvm_protocol::PROTOCOL_VERSION,
+        uptime_secs: uptime.as_secs(),
+        image_count: count_entries(&root.join(MANIFESTS_DIR))?,
+        layer_count: count_entries(&root.join(LAYERS_DIR))?,
+        active_overlay_count,
+    })
+}
+
 /// Extract a JSON array of strings from a JSON value.
 fn json_string_array(value: &serde_json::Value, key: &str) -> Vec<String> {
     value[key]
@@ -641,13 +964,34 @@ fn json_string_array(value: &serde_json::Value, key: &str) -> Vec<String> {
         .unwrap_or_default()
 }
 
+/// Extract a JSON object of string values from a JSON value.
+///
+/// Non-string values are skipped; a missing or `null` object is empty.
+fn json_string_map(value: &serde_json::Value, key: &str) -> BTreeMap<String, String> {
+    value[key]
+        .as_object()
+        .map(|obj| {
+            obj.iter()
+                .filter_map(|(k, v)| v.as_str().map(|v| (k.clone(), v.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 /// Pull an OCI image with progress callback and optional authentication.
 ///
 /// The callback is called for each layer being pulled with (current, total, layer_id).
+/// With `no_cache`, an image that is already cached is pulled again: its
+/// manifest is fetched and any layers it now names are extracted. Once
+/// `cancel` is set the pull stops with [`StorageError::PullCancelled`].
+/// With `max_bytes_per_sec`, blob downloads share that rate limit.
 pub fn pull_image_with_progress_and_auth<F>(
     image: &str,
     oci_platform: Option<&str>,
     auth: Option<&RegistryAuth>,
+    no_cache: bool,
+    max_bytes_per_sec: Option<u64>,
+    cancel: &PullCancel,
     mut progress: F,
 ) -> Result<ImageInfo>
 where
@@ -667,32 +1011,24 @@ where
         return create_packed_image_info(image, packed_dir);
     }
 
-    // Determine OCI platform - default to current architecture
-    // This must happen BEFORE the cache check so we can verify architecture
-    let oci_platform = oci_platform.or({
-        #[cfg(target_arch = "aarch64")]
-        {
-            Some("linux/arm64")
-        }
-        #[cfg(target_arch = "x86_64")]
-        {
-            Some("linux/amd64")
-        }
-        #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
-        {
-            None
-        }
-    });
+    // The platforms this image may be used as. This must happen BEFORE the
+    // cache check so we can verify architecture
+    let rosetta = Path::new(paths::ROSETTA_BINFMT_ENTRY).exists();
+    let candidates = platform::platform_candidates(oci_platform, native_arch(), rosetta);
 
     // Check if already cached with correct architecture
-    if let Ok(Some(info)) = query_image(image) {
-        // Verify cached image architecture matches requested OCI platform
+    let cached = if no_cache {
+        None
+    } else {
+        query_image(image).ok().flatten()
+    };
+    if let Some(info) = cached {
+        // Verify cached image architecture is one runs will accept
         let cached_arch = &info.architecture;
-        let requested_arch = oci_platform
-            .map(|p| oci_platform_to_arch(p))
-            .unwrap_or_else(|| cached_arch.clone());
-
-        if cached_arch == &requested_arch {
+        if candidates
+            .iter()
+            .any(|c| &oci_platform_to_arch(c) == cached_arch)
+        {
             debug!(
                 image = %image,
                 architecture = %cached_arch,
@@ -704,24 +1040,102 @@ where
             info!(
                 image = %image,
                 cached_arch = %cached_arch,
-                requested_arch = %requested_arch,
+                requested = ?candidates,
                 "cached image has wrong architecture, will re-pull"
             );
             // Clean up the mismatched cached manifest
-            let root = Path::new(STORAGE_ROOT);
-            let manifest_path = root
-                .join(MANIFESTS_DIR)
-                .join(sanitize_image_name(image) + ".json");
-            let _ = std::fs::remove_file(&manifest_path);
+            let _ = std::fs::remove_file(find_manifest(Path::new(STORAGE_ROOT), image));
         }
     }
 
-    let root = Path::new(STORAGE_ROOT);
+    let rate_limited;
+    let puller: &dyn OciPuller = match max_bytes_per_sec.filter(|&n| n > 0) {
+        Some(rate) => {
+            info!(image = %image, bytes_per_sec = rate, "limiting download rate");
+            rate_limited = pull_limit::RateLimited::new(puller::default_puller(), rate);
+            &rate_limited
+        }
+        None => puller::default_puller(),
+    };
+
+    let preferred = &candidates[0];
+    let result = pull_into(
+        puller,
+        Path::new(STORAGE_ROOT),
+        image,
+        Some(preferred),
+        auth,
+        no_cache,
+        cancel,
+        &mut progress,
+    );
+
+    // Not published natively: fall back to a platform Rosetta can run
+    let Err(StorageError::PlatformUnavailable { ref available, .. }) = result else {
+        return result;
+    };
+    let fallback =
+        platform::resolve_oci_platform(oci_platform, native_arch(), rosetta, Some(available));
+    if &fallback == preferred {
+        return result;
+    }
+    info!(image = %image, platform = %fallback, "image not published natively, pulling for Rosetta");
+    pull_into(
+        puller,
+        Path::new(STORAGE_ROOT),
+        image,
+        Some(&fallback),
+        auth,
+        no_cache,
+        cancel,
+        progress,
+    )
+}
 
-    // Get manifest with OCI platform specified
-    progress(0, 0, "fetching manifest");
-    info!(image = %image, oci_platform = ?oci_platform, "fetching manifest");
-    let manifest = crane_manifest(image, oci_platform, auth)?;
+/// Fetch `image` through `puller` into the storage at `root`:
+/// manifest, then config, then each layer not already cached.
+///
+/// A reference pinned to a digest whose manifest is already stored (under
+/// any name) skips the manifest fetch unless `no_cache` is set, and a
+/// cached config is never fetched again, so an image fully cached under
+/// another tag costs at most one manifest request.
+///
+/// `cancel` is checked before each layer and while its blob downloads; a
+/// cancelled pull leaves no partial layer directory behind.
+#[allow(clippy::too_many_arguments)]
+fn pull_into<F>(
+    puller: &dyn OciPuller,
+    root: &Path,
+    image: &str,
+    oci_platform: Option<&str>,
+    auth: Option<&RegistryAuth>,
+    no_cache: bool,
+    cancel: &PullCancel,
+    mut progress: F,
+) -> Result<ImageInfo>
+where
+    F: FnMut(usize, usize, &str),
+{
+    let pinned_digest = ImageRef::parse(image)
+        .ok()
+        .filter(|_| !no_cache)
+        .and_then(|r| r.digest().map(String::from));
+    let manifest = match pinned_digest.and_then(|d| cached_manifest_with_digest(root, &d)) {
+        Some(manifest) => {
+            info!(image = %image, "manifest digest already cached, skipping fetch");
+            manifest
+        }
+        None => {
+            // Get manifest with OCI platform specified
+            progress(0, 0, "fetching manifest");
+            info!(image = %image, oci_platform = ?oci_platform, "fetching manifest");
+            with_pull_retry(
+                crate::retry::RetryConfig::for_network(),
+                "fetch manifest",
+                || puller.manifest(image, oci_platform, auth),
+            )?
+        }
+    };
 
     // Parse manifest to get config and layers
     let manifest_json: serde_json::Value =
@@ -735,15 +1149,11 @@ where
                 context: "manifest".into(),
                 field: "config digest".into(),
             })?
-    } else if manifest_json.get("manifests").is_some() {
-        return Err(StorageError::new(format!(
-            "got manifest list instead of image manifest - platform may not be available. \
-             manifests: {:?}",
-            manifest_json["manifests"].as_array().map(|arr| arr
-                .iter()
-                .filter_map(|m| m["platform"]["architecture"].as_str())
-                .collect::<Vec<_>>())
-        )));
+    } else if let Some(manifests) = manifest_json["manifests"].as_array() {
+        return Err(StorageError::PlatformUnavailable {
+            requested: oci_platform.map(String::from),
+            available: manifest_list_platforms(manifests),
+        });
     } else {
         return Err(StorageError::UnsupportedManifest {
             media_type: "unknown".into(),
@@ -762,19 +1172,63 @@ where
 
     let total_layers = layers.len();
 
-    // Save manifest
-    let manifest_path = root
-        .join(MANIFESTS_DIR)
-        .join(sanitize_image_name(image) + ".json");
+    // Check the whole layer set up front so progress can report how much
+    // of the image is already present
+    let layer_dirs: Vec<PathBuf> = layers
+        .iter()
+        .map(|digest| {
+            let layer_id = digest.strip_prefix("sha256:").unwrap_or(digest);
+            root.join(LAYERS_DIR).join(layer_id)
+        })
+        .collect();
+    let cached: Vec<bool> = layer_dirs.iter().map(|d| is_layer_cached(d)).collect();
+    let cached_count = cached.iter().filter(|&&c| c).count();
+    if cached_count > 0 {
+        info!(image = %image, cached = cached_count, total = total_layers, "layers already cached");
+    }
+    progress(
+        cached_count,
+        total_layers,
+        &format!("{} of {} layers already cached", cached_count, total_layers),
+    );
+
+    // Fail before writing anything if the layers clearly won't fit
+    let required = estimate_pull_space(root, &manifest_json, pull_space_multiplier());
+    if let Ok(usage) = get_disk_usage(root) {
+        // statvfs isn't available off Linux and reports zero
+        if usage.total_bytes > 0 {
+            check_free_space(required, usage.total_bytes - usage.used_bytes)?;
+        }
+        if let Some(warning) = check_free_inodes(usage.free_inodes, usage.total_inodes)? {
+            warn!(image = %image, "{}", warning);
+        }
+    }
+
+    // Save manifest under the canonical key, replacing any legacy entry
+    let manifest_path = manifest_path(root, image);
     std::fs::write(&manifest_path, &manifest)?;
+    let legacy_path = legacy_manifest_path(root, image);
+    if legacy_path != manifest_path {
+        let _ = std::fs::remove_file(&legacy_path);
+    }
 
-    // Fetch and save config
-    let config = crane_config(image, oci_platform, auth)?;
+    // Fetch and save config, unless an image sharing it is already cached
     let config_id = config_digest
         .strip_prefix("sha256:")
         .unwrap_or(config_digest);
     let config_path = root.join(CONFIGS_DIR).join(format!("{}.json", config_id));
-    std::fs::write(&config_path, &config)?;
+    let config = match std::fs::read_to_string(&config_path) {
+        Ok(config) => config,
+        Err(_) => {
+            let config = with_pull_retry(
+                crate::retry::RetryConfig::for_network(),
+                "fetch config",
+                || puller.config(image, config_digest, auth),
+            )?;
+            std::fs::write(&config_path, &config)?;
+            config
+        }
+    };
 
     // Parse config for metadata
     let config_json: serde_json::Value =
@@ -782,22 +1236,23 @@ where
 
     // Extract layers with progress updates
     let mut total_size = 0u64;
-    for (i, layer_digest) in layers.iter().enumerate() {
+    let mut refreshed_layers = Vec::new();
+    for (i, (layer_digest, layer_dir)) in layers.iter().zip(&layer_dirs).enumerate() {
         let layer_id = layer_digest.strip_prefix("sha256:").unwrap_or(layer_digest);
-        let layer_dir = root.join(LAYERS_DIR).join(layer_id);
+        cancel.check(image)?;
 
         // Report progress
         progress(i + 1, total_layers, layer_id);
 
-        if is_layer_cached(&layer_dir) {
-            info!(layer = %layer_id, "layer already cached");
+        if cached[i] {
+            debug!(layer = %layer_id, "layer already cached");
             continue;
         }
 
         // Clean up empty/incomplete layer directory if it exists
         if layer_dir.exists() {
             warn!(layer = %layer_id, "removing empty/incomplete layer directory");
-            if let Err(e) = std::fs::remove_dir_all(&layer_dir) {
+            if let Err(e) = std::fs::remove_dir_all(layer_dir) {
                 warn!(layer = %layer_id, error = %e, "failed to remove incomplete layer directory");
             }
         }
@@ -808,79 +1263,34 @@ where
             "extracting layer"
         );
 
-        std::fs::create_dir_all(&layer_dir)?;
-
-        // Stream layer directly to tar extraction using direct process piping
-        // (no shell to avoid injection risks)
-
-        // Set up auth if provided (temp_dir must stay alive until command completes)
-        let temp_dir = setup_docker_auth(image, auth)?;
-
-        // Build crane command
-        let mut crane_cmd = Command::new("crane");
-        crane_cmd.arg("blob");
-        crane_cmd.arg(format!("{}@{}", image, layer_digest));
-        if let Some(p) = oci_platform {
-            crane_cmd.arg("--platform").arg(p);
-        }
-        crane_cmd.stdout(Stdio::piped());
-        // Use null for stderr to avoid deadlock (pipe buffer can fill if not consumed)
-        crane_cmd.stderr(Stdio::null());
-
-        if let Some(ref td) = temp_dir {
-            crane_cmd.env("DOCKER_CONFIG", td.path());
-        }
-
-        // Spawn crane process
-        let mut crane = crane_cmd
-            .spawn()
-            .map_err(|e| StorageError::new(format!("failed to spawn crane: {}", e)))?;
-
-        // Build tar command with crane's stdout as input
-        let crane_stdout = crane
-            .stdout
-            .take()
-            .ok_or_else(|| StorageError::new("failed to capture crane stdout".to_string()))?;
-
-        let mut tar_cmd = Command::new("tar");
-        tar_cmd.args(["--no-same-owner", "-xzf", "-", "-C"]);
-        tar_cmd.arg(&layer_dir);
-        tar_cmd.stdin(crane_stdout);
-        tar_cmd.stdout(Stdio::null());
-        tar_cmd.stderr(Stdio::piped());
-
-        // Run tar and wait for it
-        let tar_output = tar_cmd
-            .output()
-            .map_err(|e| StorageError::new(format!("failed to run tar: {}", e)))?;
-
-        // Wait for crane to finish and check its status
-        let crane_status = crane
-            .wait()
-            .map_err(|e| StorageError::new(format!("failed to wait for crane: {}", e)))?;
-
-        if !crane_status.success() {
-            if let Err(e) = std::fs::remove_dir_all(&layer_dir) {
-                warn!(layer = %layer_id, error = %e, "failed to clean up layer directory after crane failure");
+        extract_layer(
+            puller,
+            image,
+            layer_digest,
+            layer_dir,
+            oci_platform,
+            auth,
+            cancel,
+        )?;
+        refreshed_layers.push(layer_digest.clone());
+
+        if crate::dedup::enabled() {
+            match crate::dedup::dedup_layer(layer_dir, &root.join(CONTENT_DIR)) {
+                Ok(stats) => debug!(
+                    layer = %layer_id,
+                    files_linked = stats.files_linked,
+                    bytes_saved = stats.bytes_saved,
+                    "deduplicated layer"
+                ),
+                Err(e) => warn!(layer = %layer_id, error = %e, "failed to deduplicate layer"),
             }
-            return Err(StorageError::new(format!(
-                "crane blob failed for layer {}",
-                layer_digest
-            )));
         }
 
-        if !tar_output.status.success() {
-            if let Err(e) = std::fs::remove_dir_all(&layer_dir) {
-                warn!(layer = %layer_id, error = %e, "failed to clean up layer directory after tar failure");
-            }
-            let stderr = String::from_utf8_lossy(&tar_output.stderr);
-            return Err(StorageError::new(format!(
-                "tar extraction failed for layer {}: {}",
-                layer_digest, stderr
-            )));
+        if let Err(e) = record_layer_checksum(root, layer_id, layer_dir) {
+            warn!(layer = %layer_id, error = %e, "failed to record layer checksum");
         }
 
-        if let Ok(size) = dir_size(&layer_dir) {
+        if let Ok(size) = dir_size(layer_dir) {
             total_size += size;
         }
     }
@@ -905,7 +1315,7 @@ where
     let os = config_json["os"].as_str().unwrap_or("linux").to_string();
     let created = config_json["created"].as_str().map(String::from);
 
-    // Extract OCI config fields (Entrypoint, Cmd, Env, WorkingDir)
+    // Extract OCI config fields (Entrypoint, Cmd, Env, WorkingDir, Labels)
     let oci_config = &config_json["config"];
     let entrypoint = json_string_array(oci_config, "Entrypoint");
     let cmd = json_string_array(oci_config, "Cmd");
@@ -914,6 +1324,23 @@ where
         .as_str()
         .filter(|s| !s.is_empty())
         .map(String::from);
+    let labels = json_string_map(oci_config, "Labels");
+
+    if no_cache {
+        info!(
+            image = %image,
+            refreshed = refreshed_layers.len(),
+            layers = ?refreshed_layers,
+            "refreshed image"
+        );
+        drop_read_only_overlay_at(root, image, overlay_lru::is_leased, cleanup_run_overlay);
+    }
+
+    // Listing other tags is informational; don't fail the pull over it
+    let tags = digest_references(root)
+        .ok()
+        .and_then(|mut refs| refs.remove(config_digest))
+        .unwrap_or_default();
 
     Ok(ImageInfo {
         reference: image.to_string(),
@@ -928,15 +1355,169 @@ where
         cmd,
         env,
         workdir,
+        labels,
+        tags,
+        refreshed_layers,
     })
 }
 
 /// Query if an image exists locally.
 pub fn query_image(image: &str) -> Result<Option<ImageInfo>> {
     let root = Path::new(STORAGE_ROOT);
-    let manifest_path = root
-        .join(MANIFESTS_DIR)
-        .join(sanitize_image_name(image) + ".json");
+    image_info_at(root, image, &digest_references(root)?)
+}
+
+/// Find cached images whose config digest is `digest`, one per reference.
+pub fn query_digest(digest: &str) -> Result<Vec<ImageInfo>> {
+    query_digest_at(Path::new(STORAGE_ROOT), digest)
+}
+
+fn query_digest_at(root: &Path, digest: &str) -> Result<Vec<ImageInfo>> {
+    let digest = if digest.contains(':') {
+        digest.to_string()
+    } else {
+        format!("sha256:{}", digest)
+    };
+    let references = digest_references(root)?;
+    let mut images = Vec::new();
+    for image in references.get(&digest).into_iter().flatten() {
+        if let Some(info) = image_info_at(root, image, &references)? {
+            images.push(info);
+        }
+    }
+    Ok(images)
+}
+
+/// Add `target` as another reference to the cached image `source`.
+///
+/// The target gets its own copy of the manifest; the config and layers are
+/// shared. An existing `target` is replaced. Returns `None` if `source`
+/// isn't cached.
+pub fn tag_image(source: &str, target: &str) -> Result<Option<ImageInfo>> {
+    tag_image_at(Path::new(STORAGE_ROOT), source, target)
+}
+
+fn tag_image_at(root: &Path, source: &str, target: &str) -> Result<Option<ImageInfo>> {
+    ImageRef::parse(target).map_err(|e| StorageError::ValidationFailed {
+        context: format!("tag '{}'", target),
+        reason: e.to_string(),
+    })?;
+
+    // Also drops the source's manifest if its layers have gone missing
+    if image_info_at(root, source, &digest_references(root)?)?.is_none() {
+        return Ok(None);
+    }
+
+    let manifest = std::fs::read(find_manifest(root, source))?;
+    let target_path = manifest_path(root, target);
+    std::fs::write(&target_path, manifest)?;
+    let legacy_path = legacy_manifest_path(root, target);
+    if legacy_path != target_path {
+        let _ = std::fs::remove_file(&legacy_path);
+    }
+
+    info!(source = %source, target = %target, "tagged image");
+    image_info_at(root, target, &digest_references(root)?)
+}
+
+/// Remove the cached image `image`.
+///
+/// Only the manifest goes; layers no other image references are left for
+/// [`garbage_collect`]. The persistent and read-only run overlays of runs
+/// given this reference are removed with it. Fails with [`StorageError::ImageInUse`] if any of
+/// `container_images` resolves to the same manifest or one of its overlays
+/// is busy. Returns false if `image` isn't cached.
+pub fn remove_image(image: &str, container_images: &[String]) -> Result<bool> {
+    remove_image_at(
+        Path::new(STORAGE_ROOT),
+        image,
+        container_images,
+        overlay_lru::is_leased,
+        cleanup_run_overlay,
+    )
+}
+
+/// Unmount the shared read-only mount of `image` under `root` (see
+/// [`run_workload_id`]), so the next read-only run mounts the image's
+/// current layers rather than the ones before a refresh.
+///
+/// A mount a run still holds is left alone; the refresh reaches read-only
+/// runs once it is next remounted.
+fn drop_read_only_overlay_at(
+    root: &Path,
+    image: &str,
+    is_leased: impl Fn(&str) -> bool,
+    remove_overlay: impl Fn(&str) -> Result<()>,
+) {
+    let workload_id = run_workload_id(image, false, true);
+    if !root.join(OVERLAYS_DIR).join(&workload_id).exists() {
+        return;
+    }
+    if is_leased(&workload_id) {
+        warn!(workload_id = %workload_id, "read-only mount in use, keeping the old layers for now");
+        return;
+    }
+    if let Err(e) = remove_overlay(&workload_id) {
+        warn!(workload_id = %workload_id, error = %e, "failed to remove stale read-only mount");
+    }
+}
+
+fn remove_image_at(
+    root: &Path,
+    image: &str,
+    container_images: &[String],
+    is_leased: impl Fn(&str) -> bool,
+    remove_overlay: impl Fn(&str) -> Result<()>,
+) -> Result<bool> {
+    let manifest = find_manifest(root, image);
+    if !manifest.exists() {
+        return Ok(false);
+    }
+
+    if container_images
+        .iter()
+        .any(|other| find_manifest(root, other) == manifest)
+    {
+        return Err(StorageError::ImageInUse {
+            image: image.to_string(),
+            user: "a container".to_string(),
+        });
+    }
+
+    let sanitized = sanitize_image_name(image);
+    let overlays: Vec<String> = [PERSISTENT_OVERLAY_PREFIX, READ_ONLY_OVERLAY_PREFIX]
+        .iter()
+        .map(|prefix| format!("{}{}", prefix, sanitized))
+        .filter(|id| root.join(OVERLAYS_DIR).join(id).exists())
+        .collect();
+    if let Some(busy) = overlays.iter().find(|id| is_leased(id)) {
+        return Err(StorageError::ImageInUse {
+            image: image.to_string(),
+            user: format!("run overlay {}", busy),
+        });
+    }
+    for id in &overlays {
+        remove_overlay(id)?;
+    }
+
+    std::fs::remove_file(&manifest)?;
+    let legacy_path = legacy_manifest_path(root, image);
+    if legacy_path != manifest {
+        let _ = std::fs::remove_file(&legacy_path);
+    }
+
+    info!(image = %image, "removed image");
+    Ok(true)
+}
+
+/// Read the cached image `image` from `root`, taking its tags from
+/// `references` (see [`digest_references`]).
+fn image_info_at(
+    root: &Path,
+    image: &str,
+    references: &HashMap<String, Vec<String>>,
+) -> Result<Option<ImageInfo>> {
+    let manifest_path = find_manifest(root, image);
 
     if !manifest_path.exists() {
         return Ok(None);
@@ -1007,6 +1588,7 @@ pub fn query_image(image: &str) -> Result<Option<ImageInfo>> {
         .as_str()
         .filter(|s| !s.is_empty())
         .map(String::from);
+    let labels = json_string_map(oci_config, "Labels");
 
     Ok(Some(ImageInfo {
         reference: image.to_string(),
@@ -1021,18 +1603,25 @@ pub fn query_image(image: &str) -> Result<Option<ImageInfo>> {
         cmd,
         env,
         workdir,
+        labels,
+        tags: references.get(config_digest).cloned().unwrap_or_default(),
+        refreshed_layers: Vec::new(),
     }))
 }
 
 /// List all cached images.
 pub fn list_images() -> Result<Vec<ImageInfo>> {
-    let root = Path::new(STORAGE_ROOT);
+    list_images_at(Path::new(STORAGE_ROOT))
+}
+
+fn list_images_at(root: &Path) -> Result<Vec<ImageInfo>> {
     let manifests_dir = root.join(MANIFESTS_DIR);
 
     if !manifests_dir.exists() {
         return Ok(Vec::new());
     }
 
+    let references = digest_references(root)?;
     let mut images = Vec::new();
 
     for entry in std::fs::read_dir(&manifests_dir)? {
@@ -1040,22 +1629,29 @@ pub fn list_images() -> Result<Vec<ImageInfo>> {
         let path = entry.path();
 
         if path.extension().map(|e| e == "json").unwrap_or(false) {
-            // Extract image name from filename
-            let name = path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .map(unsanitize_image_name)
-                .unwrap_or_default();
-
-            if let Ok(Some(info)) = query_image(&name) {
+            let name = image_name_for_manifest(&path);
+            if let Ok(Some(info)) = image_info_at(root, &name, &references) {
                 images.push(info);
             }
         }
     }
 
+    // A stable order, so paged listings line up
+    images.sort_by(|a, b| a.reference.cmp(&b.reference));
     Ok(images)
 }
 
+/// Recover the image reference from a manifest's filename.
+fn image_name_for_manifest(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .map(|stem| match ImageRef::from_storage_key(stem) {
+            Some(image_ref) => image_ref.to_string(),
+            None => unsanitize_image_name(stem),
+        })
+        .unwrap_or_default()
+}
+
 /// Export a layer as a tar archive to a file.
 ///
 /// Used by `smolvm pack` to extract layers for packaging.
@@ -1116,7 +1712,7 @@ pub fn export_layer(image_digest: &str, layer_index: usize) -> Result<PathBuf> {
 
     // Create tar archive on the storage disk (/tmp is on virtiofs which is
     // read-only on Linux — ENOTSUP)
-    let tmp_dir = root.join("tmp");
+    let tmp_dir = root.join(TMP_DIR);
     std::fs::create_dir_all(&tmp_dir)?;
     let tar_path = tmp_dir.join(format!("layer-{}.tar", &layer_id[..12]));
 
@@ -1134,7 +1730,8 @@ pub fn export_layer(image_digest: &str, layer_index: usize) -> Result<PathBuf> {
         .arg("-C")
         .arg(&layer_dir)
         .arg(".")
-        .status()?;
+        .status()
+        .map_err(|e| StorageError::new(tools::TAR.spawn_error(&e)))?;
 
     if !status.success() {
         return Err(StorageError::new(format!(
@@ -1181,14 +1778,636 @@ pub fn get_layer_digest(image_digest: &str, layer_index: usize) -> Result<String
     )))
 }
 
-/// Run garbage collection.
-pub fn garbage_collect(dry_run: bool) -> Result<u64> {
-    let root = Path::new(STORAGE_ROOT);
-    let layers_dir = root.join(LAYERS_DIR);
-    let manifests_dir = root.join(MANIFESTS_DIR);
+/// A new file path in the storage disk's scratch space, for an image
+/// archive being received.
+pub fn archive_path(name: &str) -> Result<PathBuf> {
+    let tmp_dir = Path::new(STORAGE_ROOT).join(TMP_DIR);
+    std::fs::create_dir_all(&tmp_dir)?;
+    Ok(tmp_dir.join(format!("{}-{}.tar", name, generate_container_id())))
+}
 
-    // Collect all referenced layers
-    let mut referenced_layers = std::collections::HashSet::new();
+/// The largest image archive [`load_image`] can take, or `None` if the
+/// storage disk's free space isn't known.
+///
+/// The archive and the image layout unpacked from it are both on the
+/// storage disk before any layer is imported, so it may use at most half
+/// of the free space.
+pub fn max_load_archive_bytes() -> Option<u64> {
+    get_disk_usage(Path::new(STORAGE_ROOT))
+        .ok()
+        .filter(|usage| usage.total_bytes > 0)
+        .map(|usage| load_archive_limit(usage.total_bytes - usage.used_bytes))
+}
+
+fn load_archive_limit(available: u64) -> u64 {
+    available / 2
+}
+
+/// Save the cached image `image` as an OCI image layout tar, like
+/// `docker save`.
+///
+/// Returns the path of the archive in the storage disk's scratch space, to
+/// be removed by the caller once sent, or `None` if `image` isn't cached.
+pub fn save_image(image: &str) -> Result<Option<PathBuf>> {
+    let archive = archive_path("save")?;
+    Ok(save_image_at(Path::new(STORAGE_ROOT), image, &archive)?.then_some(archive))
+}
+
+/// Write the cached image `image` to `archive`. Returns false if it isn't
+/// cached.
+///
+/// The archive holds `oci-layout`, an `index.json` naming the image by its
+/// reference, and under `blobs/sha256` a manifest, the image's config as
+/// pulled, and an uncompressed tar of each layer.
+fn save_image_at(root: &Path, image: &str, archive: &Path) -> Result<bool> {
+    // Also checks every layer is present
+    let Some(info) = image_info_at(root, image, &digest_references(root)?)? else {
+        return Ok(false);
+    };
+    let config_id = info.digest.strip_prefix("sha256:").unwrap_or(&info.digest);
+    let config = std::fs::read(root.join(CONFIGS_DIR).join(format!("{}.json", config_id)))?;
+
+    let tmp_dir = root.join(TMP_DIR);
+    std::fs::create_dir_all(&tmp_dir)?;
+    let layout = tempfile::tempdir_in(&tmp_dir)?;
+    let blobs = layout.path().join("blobs").join("sha256");
+    std::fs::create_dir_all(&blobs)?;
+
+    let mut layers = Vec::new();
+    for layer_digest in &info.layers {
+        let layer_id = layer_digest.strip_prefix("sha256:").unwrap_or(layer_digest);
+        let partial = blobs.join("layer.partial");
+        let output = Command::new("tar")
+            .arg("-cf")
+            .arg(&partial)
+            .arg("-C")
+            .arg(root.join(LAYERS_DIR).join(layer_id))
+            .arg(".")
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| StorageError::new(tools::TAR.spawn_error(&e)))?;
+        if !output.status.success() {
+            return Err(StorageError::command_failed(
+                format!("tar of layer {}", layer_id),
+                output.status.code(),
+                String::from_utf8_lossy(&output.stderr),
+            ));
+        }
+        let (digest, size) = file_digest(&partial)?;
+        std::fs::rename(&partial, blobs.join(&digest[7..]))?;
+        layers.push(serde_json::json!({
+            "mediaType": OCI_LAYER_MEDIA_TYPE,
+            "digest": digest,
+            "size": size,
+            "annotations": { LAYER_DIGEST_ANNOTATION: layer_digest },
+        }));
+    }
+
+    std::fs::write(blobs.join(config_id), &config)?;
+    let manifest = serde_json::to_vec(&serde_json::json!({
+        "schemaVersion": 2,
+        "mediaType": OCI_MANIFEST_MEDIA_TYPE,
+        "config": {
+            "mediaType": OCI_CONFIG_MEDIA_TYPE,
+            "digest": info.digest,
+            "size": config.len(),
+        },
+        "layers": layers,
+    }))
+    .map_err(|e| StorageError::new(e.to_string()))?;
+    let manifest_hex = format!("{:x}", Sha256::digest(&manifest));
+    std::fs::write(blobs.join(&manifest_hex), &manifest)?;
+
+    let reference = ImageRef::parse(image)
+        .map(|r| r.to_string())
+        .unwrap_or_else(|_| image.to_string());
+    let index = serde_json::json!({
+        "schemaVersion": 2,
+        "manifests": [{
+            "mediaType": OCI_MANIFEST_MEDIA_TYPE,
+            "digest": format!("sha256:{}", manifest_hex),
+            "size": manifest.len(),
+            "annotations": { REF_NAME_ANNOTATION: reference },
+        }],
+    });
+    std::fs::write(layout.path().join("index.json"), index.to_string())?;
+    std::fs::write(
+        layout.path().join("oci-layout"),
+        r#"{"imageLayoutVersion":"1.0.0"}"#,
+    )?;
+
+    let output = Command::new("tar")
+        .arg("-cf")
+        .arg(archive)
+        .arg("-C")
+        .arg(layout.path())
+        .args(["oci-layout", "index.json", "blobs"])
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| StorageError::new(tools::TAR.spawn_error(&e)))?;
+    if !output.status.success() {
+        let _ = std::fs::remove_file(archive);
+        return Err(StorageError::command_failed(
+            "tar of image archive",
+            output.status.code(),
+            String::from_utf8_lossy(&output.stderr),
+        ));
+    }
+
+    info!(image = %image, layers = info.layers.len(), archive = %archive.display(), "saved image");
+    Ok(true)
+}
+
+/// Import the images in an OCI image layout tar made by [`save_image`].
+///
+/// Each image is stored under the reference in its `index.json`
+/// annotation, replacing any image cached under that name. Every blob is
+/// checked against its digest before anything is stored.
+///
+/// A layer's annotated original digest can't be checked against the
+/// archive's content, so it is only used when the store already holds that
+/// layer, which is then kept as is. Other layers are stored under the
+/// digest of their blob, so an archive can't plant content under the digest
+/// of a layer that later pulls would skip.
+pub fn load_image(archive: &Path) -> Result<Vec<ImageInfo>> {
+    load_image_at(Path::new(STORAGE_ROOT), archive)
+}
+
+fn load_image_at(root: &Path, archive: &Path) -> Result<Vec<ImageInfo>> {
+    let invalid = |reason: String| StorageError::ValidationFailed {
+        context: "image archive".to_string(),
+        reason,
+    };
+
+    let tmp_dir = root.join(TMP_DIR);
+    std::fs::create_dir_all(&tmp_dir)?;
+    let layout = tempfile::tempdir_in(&tmp_dir)?;
+    let output = Command::new("tar")
+        .args(["--no-same-owner", "-xf"])
+        .arg(archive)
+        .arg("-C")
+        .arg(layout.path())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| StorageError::new(tools::TAR.spawn_error(&e)))?;
+    if !output.status.success() {
+        return Err(invalid(format!(
+            "not a tar archive: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let index: serde_json::Value = serde_json::from_slice(
+        &std::fs::read(layout.path().join("index.json"))
+            .map_err(|e| invalid(format!("no index.json: {}", e)))?,
+    )
+    .map_err(|e| StorageError::parse_error("index.json", e))?;
+    let entries = index["manifests"].as_array().cloned().unwrap_or_default();
+    if entries.is_empty() {
+        return Err(invalid("index.json lists no images".to_string()));
+    }
+
+    // Check and stage everything before touching the store
+    let mut images = Vec::new();
+    for entry in &entries {
+        let reference = entry["annotations"][REF_NAME_ANNOTATION]
+            .as_str()
+            .ok_or_else(|| invalid(format!("image has no '{}' annotation", REF_NAME_ANNOTATION)))?;
+        ImageRef::parse(reference).map_err(|e| StorageError::InvalidImageReference {
+            reference: reference.to_string(),
+            reason: e.to_string(),
+        })?;
+
+        let manifest_bytes = read_blob(layout.path(), entry)?;
+        let mut manifest: serde_json::Value = serde_json::from_slice(&manifest_bytes)
+            .map_err(|e| StorageError::parse_error("manifest", e))?;
+        let config = read_blob(layout.path(), &manifest["config"])?;
+        let mut layers = Vec::new();
+        for layer in manifest["layers"].as_array_mut().into_iter().flatten() {
+            let blob = blob_path(layout.path(), &layer["digest"])?;
+            let (digest, size) = file_digest(&blob)?;
+            if layer["digest"] != digest.as_str() {
+                return Err(invalid(format!(
+                    "layer blob {} is corrupt (digest {})",
+                    layer["digest"], digest
+                )));
+            }
+            // Share the layer it was saved from if it's already cached
+            let layer_digest = match layer["annotations"][LAYER_DIGEST_ANNOTATION].as_str() {
+                Some(original)
+                    if digest_hex(original)
+                        .is_some_and(|hex| is_layer_cached(&root.join(LAYERS_DIR).join(hex))) =>
+                {
+                    original.to_string()
+                }
+                _ => digest.clone(),
+            };
+            let gzipped = layer["mediaType"]
+                .as_str()
+                .is_some_and(|t| t.ends_with("+gzip"));
+            layer["digest"] = layer_digest.clone().into();
+            layers.push((layer_digest, digest, blob, size, gzipped));
+        }
+        images.push((reference.to_string(), manifest, config, layers));
+    }
+
+    let required = images
+        .iter()
+        .flat_map(|(_, _, _, layers)| layers)
+        .filter(|(digest, ..)| !is_layer_cached(&root.join(LAYERS_DIR).join(&digest[7..])))
+        .map(|(_, _, _, size, _)| size)
+        .sum();
+    if let Ok(usage) = get_disk_usage(root) {
+        if usage.total_bytes > 0 {
+            check_free_space(required, usage.total_bytes - usage.used_bytes)?;
+        }
+    }
+
+    let mut references = Vec::new();
+    for (reference, manifest, config, layers) in images {
+        for (layer_digest, blob_digest, blob, _, gzipped) in layers {
+            let layer_id = &layer_digest[7..];
+            let layer_dir = root.join(LAYERS_DIR).join(layer_id);
+            if is_layer_cached(&layer_dir) {
+                debug!(layer = %layer_id, "layer already cached");
+                continue;
+            }
+            if layer_digest != blob_digest {
+                // Only a cached layer is kept under its annotated digest
+                return Err(StorageError::new(format!(
+                    "layer {} was removed while loading",
+                    layer_digest
+                )));
+            }
+            import_layer(&blob, &layer_dir, gzipped)?;
+            if crate::dedup::enabled() {
+                if let Err(e) = crate::dedup::dedup_layer(&layer_dir, &root.join(CONTENT_DIR)) {
+                    warn!(layer = %layer_id, error = %e, "failed to deduplicate layer");
+                }
+            }
+            if let Err(e) = record_layer_checksum(root, layer_id, &layer_dir) {
+                warn!(layer = %layer_id, error = %e, "failed to record layer checksum");
+            }
+        }
+
+        let config_digest = manifest["config"]["digest"].as_str().unwrap_or_default();
+        let config_path = root
+            .join(CONFIGS_DIR)
+            .join(format!("{}.json", &config_digest[7..]));
+        std::fs::write(&config_path, &config)?;
+
+        let path = manifest_path(root, &reference);
+        std::fs::write(&path, manifest.to_string())?;
+        let legacy_path = legacy_manifest_path(root, &reference);
+        if legacy_path != path {
+            let _ = std::fs::remove_file(&legacy_path);
+        }
+        info!(image = %reference, "loaded image");
+        references.push(reference);
+    }
+
+    // As after a pull, make sure the layers survive an abrupt VM exit
+    // SAFETY: sync() is always safe to call
+    unsafe {
+        libc::sync();
+    }
+
+    let tags = digest_references(root)?;
+    let mut loaded = Vec::new();
+    for reference in references {
+        if let Some(info) = image_info_at(root, &reference, &tags)? {
+            loaded.push(info);
+        }
+    }
+    Ok(loaded)
+}
+
+/// Extract a layer tar from an image archive into `layer_dir`.
+fn import_layer(blob: &Path, layer_dir: &Path, gzipped: bool) -> Result<()> {
+    if layer_dir.exists() {
+        std::fs::remove_dir_all(layer_dir).map_err(|e| StorageError::RemoveDir {
+            path: layer_dir.display().to_string(),
+            cause: e.to_string(),
+        })?;
+    }
+    std::fs::create_dir_all(layer_dir)?;
+
+    let output = Command::new("tar")
+        .arg("--no-same-owner")
+        .arg(if gzipped { "-xzf" } else { "-xf" })
+        .arg(blob)
+        .arg("-C")
+        .arg(layer_dir)
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| StorageError::new(tools::TAR.spawn_error(&e)))?;
+    if !output.status.success() {
+        if let Err(e) = std::fs::remove_dir_all(layer_dir) {
+            warn!(layer = %layer_dir.display(), error = %e, "failed to clean up layer directory after tar failure");
+        }
+        return Err(StorageError::LayerExtractionFailed {
+            digest: layer_dir
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            cause: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// The hex part of a `sha256:` digest, if `digest` is one.
+fn digest_hex(digest: &str) -> Option<&str> {
+    digest
+        .strip_prefix("sha256:")
+        .filter(|hex| hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Path of the blob a descriptor's `digest` names in an image layout.
+fn blob_path(layout: &Path, digest: &serde_json::Value) -> Result<PathBuf> {
+    let hex =
+        digest
+            .as_str()
+            .and_then(digest_hex)
+            .ok_or_else(|| StorageError::ValidationFailed {
+                context: "image archive".to_string(),
+                reason: format!("invalid blob digest {}", digest),
+            })?;
+    Ok(layout.join("blobs").join("sha256").join(hex))
+}
+
+/// Read the blob `descriptor` points to, checking it against its digest.
+fn read_blob(layout: &Path, descriptor: &serde_json::Value) -> Result<Vec<u8>> {
+    let path = blob_path(layout, &descriptor["digest"])?;
+    let data = std::fs::read(&path)
+        .map_err(|e| StorageError::read_error(path.display().to_string(), e))?;
+    if descriptor["digest"] != format!("sha256:{:x}", Sha256::digest(&data)).as_str() {
+        return Err(StorageError::ValidationFailed {
+            context: "image archive".to_string(),
+            reason: format!("blob {} is corrupt", descriptor["digest"]),
+        });
+    }
+    Ok(data)
+}
+
+/// The `sha256:` digest and size of a file.
+fn file_digest(path: &Path) -> Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let size = std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok((format!("sha256:{:x}", hasher.finalize()), size))
+}
+
+/// Run garbage collection.
+pub fn garbage_collect(dry_run: bool) -> Result<u64> {
+    let root = Path::new(STORAGE_ROOT);
+    let layers_dir = root.join(LAYERS_DIR);
+
+    // Collect all referenced layers
+    let referenced_layers = layer_references(root)?;
+
+    // Find unreferenced layers
+    let mut unreferenced = Vec::new();
+    if layers_dir.exists() {
+        for entry in std::fs::read_dir(&layers_dir)? {
+            let entry = entry?;
+            let layer_id = entry.file_name().to_string_lossy().to_string();
+            if !referenced_layers.contains_key(&layer_id) {
+                info!(layer = %layer_id, dry_run = dry_run, "unreferenced layer");
+                unreferenced.push((layer_id, entry.path()));
+            }
+        }
+    }
+
+    // Deduplicated files are shared with the content store and maybe other
+    // layers, so count what removal actually frees
+    let content = root.join(CONTENT_DIR);
+    let dirs: Vec<PathBuf> = unreferenced.iter().map(|(_, dir)| dir.clone()).collect();
+    let freed = crate::dedup::reclaimable(&dirs, &content)?;
+
+    if !dry_run {
+        for (layer_id, dir) in &unreferenced {
+            std::fs::remove_dir_all(dir)?;
+            let _ = std::fs::remove_file(layer_checksum_path(root, layer_id));
+        }
+        // Drop deduplicated content only the removed layers linked to
+        crate::dedup::prune_store(&content)?;
+    }
+
+    Ok(freed)
+}
+
+/// The platforms (`os/arch[/variant]`) a manifest list offers, skipping
+/// entries such as attestations that aren't for any platform.
+fn manifest_list_platforms(manifests: &[serde_json::Value]) -> Vec<String> {
+    let mut platforms = Vec::new();
+    for platform in manifests.iter().map(|m| &m["platform"]) {
+        let os = platform["os"].as_str().unwrap_or("linux");
+        let Some(arch) = platform["architecture"].as_str() else {
+            continue;
+        };
+        if os == "unknown" || arch == "unknown" {
+            continue;
+        }
+        let name = match platform["variant"].as_str() {
+            Some(variant) => format!("{}/{}/{}", os, arch, variant),
+            None => format!("{}/{}", os, arch),
+        };
+        if !platforms.contains(&name) {
+            platforms.push(name);
+        }
+    }
+    platforms
+}
+
+/// Check cached images for corrupt layers, manifests and configs.
+///
+/// Checks `image`, or every cached image if `None`; returns `Ok(None)` if
+/// `image` isn't cached. With `remove_corrupt`, corrupt layers and configs
+/// are deleted so that pulling the image again fetches them.
+pub fn verify_images(
+    image: Option<&str>,
+    remove_corrupt: bool,
+) -> Result<Option<Vec<VerifyReport>>> {
+    verify_images_at(Path::new(STORAGE_ROOT), image, remove_corrupt)
+}
+
+fn verify_images_at(
+    root: &Path,
+    image: Option<&str>,
+    remove_corrupt: bool,
+) -> Result<Option<Vec<VerifyReport>>> {
+    let manifests = match image {
+        Some(image) => {
+            let path = find_manifest(root, image);
+            if !path.exists() {
+                return Ok(None);
+            }
+            vec![(image.to_string(), path)]
+        }
+        None => {
+            let mut manifests = Vec::new();
+            let manifests_dir = root.join(MANIFESTS_DIR);
+            if manifests_dir.exists() {
+                for entry in std::fs::read_dir(&manifests_dir)? {
+                    let path = entry?.path();
+                    if path.extension().map(|e| e == "json").unwrap_or(false) {
+                        manifests.push((image_name_for_manifest(&path), path));
+                    }
+                }
+            }
+            manifests.sort();
+            manifests
+        }
+    };
+
+    let mut reports = Vec::new();
+    for (image, path) in manifests {
+        let report = verify_image(root, &image, &path, remove_corrupt)?;
+        if !report.ok {
+            warn!(
+                image = %image,
+                corrupt_layers = ?report.corrupt_layers,
+                manifest_ok = report.manifest_ok,
+                config_ok = report.config_ok,
+                "image failed verification"
+            );
+        }
+        reports.push(report);
+    }
+    Ok(Some(reports))
+}
+
+fn verify_image(
+    root: &Path,
+    image: &str,
+    manifest_path: &Path,
+    remove_corrupt: bool,
+) -> Result<VerifyReport> {
+    let mut report = VerifyReport {
+        image: image.to_string(),
+        corrupt_layers: Vec::new(),
+        unverified_layers: Vec::new(),
+        manifest_ok: false,
+        config_ok: false,
+        ok: false,
+    };
+
+    let manifest = std::fs::read_to_string(manifest_path)?;
+    let Ok(manifest_json) = serde_json::from_str::<serde_json::Value>(&manifest) else {
+        return Ok(report);
+    };
+    let (Some(config_digest), Some(layers)) = (
+        manifest_json["config"]["digest"].as_str(),
+        manifest_json["layers"].as_array(),
+    ) else {
+        return Ok(report);
+    };
+    report.manifest_ok = true;
+
+    let config_id = config_digest
+        .strip_prefix("sha256:")
+        .unwrap_or(config_digest);
+    let config_path = root.join(CONFIGS_DIR).join(format!("{}.json", config_id));
+    report.config_ok = std::fs::read_to_string(&config_path)
+        .ok()
+        .and_then(|config| serde_json::from_str::<serde_json::Value>(&config).ok())
+        .is_some_and(|config| config.is_object());
+    if !report.config_ok && remove_corrupt {
+        let _ = std::fs::remove_file(&config_path);
+    }
+
+    for digest in layers.iter().filter_map(|l| l["digest"].as_str()) {
+        let layer_id = digest.strip_prefix("sha256:").unwrap_or(digest);
+        let layer_dir = root.join(LAYERS_DIR).join(layer_id);
+        let intact = if !is_layer_cached(&layer_dir) {
+            false
+        } else {
+            match std::fs::read_to_string(layer_checksum_path(root, layer_id)) {
+                Ok(recorded) => layer_checksum(&layer_dir)
+                    .map(|sum| sum == recorded.trim())
+                    .unwrap_or(false),
+                Err(_) => {
+                    report.unverified_layers.push(digest.to_string());
+                    true
+                }
+            }
+        };
+        if intact {
+            continue;
+        }
+        report.corrupt_layers.push(digest.to_string());
+        if remove_corrupt && layer_dir.exists() {
+            info!(layer = %layer_id, "removing corrupt layer");
+            std::fs::remove_dir_all(&layer_dir)?;
+            let _ = std::fs::remove_file(layer_checksum_path(root, layer_id));
+        }
+    }
+
+    report.ok = report.manifest_ok && report.config_ok && report.corrupt_layers.is_empty();
+    Ok(report)
+}
+
+fn layer_checksum_path(root: &Path, layer_id: &str) -> PathBuf {
+    root.join(CHECKSUMS_DIR)
+        .join(format!("{}.sha256", layer_id))
+}
+
+/// Record the checksum of a freshly extracted layer for later verification.
+fn record_layer_checksum(root: &Path, layer_id: &str, layer_dir: &Path) -> Result<()> {
+    let path = layer_checksum_path(root, layer_id);
+    // Storage formatted before checksums were recorded lacks the directory
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, layer_checksum(layer_dir)?)?;
+    Ok(())
+}
+
+/// Checksum of a layer's extracted content.
+///
+/// Covers the path, type and permissions of every entry, file contents and
+/// symlink targets. Owners and timestamps are left out: deduplication
+/// hardlinks files across layers, so their inode metadata is shared.
+fn layer_checksum(layer_dir: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hash_tree(layer_dir, Path::new(""), &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn hash_tree(dir: &Path, relative: &Path, hasher: &mut Sha256) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+
+    let mut entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let relative = relative.join(entry.file_name());
+        let meta = std::fs::symlink_metadata(&path)?;
+
+        hasher.update(relative.as_os_str().as_bytes());
+        hasher.update(format!("\0{:o}\0", meta.mode()).as_bytes());
+        if meta.is_dir() {
+            hash_tree(&path, &relative, hasher)?;
+        } else if meta.file_type().is_symlink() {
+            let target = std::fs::read_link(&path)?;
+            hasher.update(target.as_os_str().as_bytes());
+        } else if meta.is_file() {
+            // The length keeps a file's contents from running into the
+            // next entry's path
+            hasher.update(meta.len().to_le_bytes());
+            std::io::copy(&mut std::fs::File::open(&path)?, hasher)?;
+        }
+        hasher.update([0]);
+    }
+    Ok(())
+}
+
+/// Map each layer ID referenced by a cached image manifest to the
+/// references of the images using it.
+fn layer_references(root: &Path) -> Result<HashMap<String, Vec<String>>> {
+    let manifests_dir = root.join(MANIFESTS_DIR);
+    let mut references: HashMap<String, Vec<String>> = HashMap::new();
 
     if manifests_dir.exists() {
         for entry in std::fs::read_dir(&manifests_dir)? {
@@ -1196,10 +2415,14 @@ pub fn garbage_collect(dry_run: bool) -> Result<u64> {
             let content = std::fs::read_to_string(entry.path())?;
             if let Ok(manifest) = serde_json::from_str::<serde_json::Value>(&content) {
                 if let Some(layers) = manifest["layers"].as_array() {
+                    let image = image_name_for_manifest(&entry.path());
                     for layer in layers {
                         if let Some(digest) = layer["digest"].as_str() {
                             let id = digest.strip_prefix("sha256:").unwrap_or(digest);
-                            referenced_layers.insert(id.to_string());
+                            let users = references.entry(id.to_string()).or_default();
+                            if !users.contains(&image) {
+                                users.push(image.clone());
+                            }
                         }
                     }
                 }
@@ -1207,28 +2430,70 @@ pub fn garbage_collect(dry_run: bool) -> Result<u64> {
         }
     }
 
-    // Find unreferenced layers
-    let mut freed = 0u64;
+    Ok(references)
+}
 
-    if layers_dir.exists() {
-        for entry in std::fs::read_dir(&layers_dir)? {
+/// Map each config digest of a cached image manifest to the sorted
+/// references of the images with that config, i.e. its tags.
+fn digest_references(root: &Path) -> Result<HashMap<String, Vec<String>>> {
+    let manifests_dir = root.join(MANIFESTS_DIR);
+    let mut references: HashMap<String, Vec<String>> = HashMap::new();
+
+    if manifests_dir.exists() {
+        for entry in std::fs::read_dir(&manifests_dir)? {
             let entry = entry?;
-            let layer_id = entry.file_name().to_string_lossy().to_string();
+            let content = std::fs::read_to_string(entry.path())?;
+            let Ok(manifest) = serde_json::from_str::<serde_json::Value>(&content) else {
+                continue;
+            };
+            if let Some(digest) = manifest["config"]["digest"].as_str() {
+                let image = image_name_for_manifest(&entry.path());
+                references
+                    .entry(digest.to_string())
+                    .or_default()
+                    .push(image);
+            }
+        }
+    }
 
-            if !referenced_layers.contains(&layer_id) {
-                let size = dir_size(&entry.path()).unwrap_or(0);
-                info!(layer = %layer_id, size = size, dry_run = dry_run, "unreferenced layer");
+    for images in references.values_mut() {
+        images.sort();
+        images.dedup();
+    }
+    Ok(references)
+}
 
-                if !dry_run {
-                    std::fs::remove_dir_all(entry.path())?;
-                }
+/// Report the size of every cached layer and the images referencing it,
+/// largest layers first.
+pub fn disk_usage() -> Result<Vec<LayerUsage>> {
+    disk_usage_at(Path::new(STORAGE_ROOT))
+}
 
-                freed += size;
-            }
+fn disk_usage_at(root: &Path) -> Result<Vec<LayerUsage>> {
+    let layers_dir = root.join(LAYERS_DIR);
+    let mut references = layer_references(root)?;
+    let mut usage = Vec::new();
+
+    if layers_dir.exists() {
+        for entry in std::fs::read_dir(&layers_dir)? {
+            let entry = entry?;
+            let layer_id = entry.file_name().to_string_lossy().to_string();
+            let mut referenced_by = references.remove(&layer_id).unwrap_or_default();
+            referenced_by.sort();
+            usage.push(LayerUsage {
+                digest: format!("sha256:{}", layer_id),
+                size_bytes: dir_size(&entry.path()).unwrap_or(0),
+                referenced_by,
+            });
         }
     }
 
-    Ok(freed)
+    usage.sort_by(|a, b| {
+        b.size_bytes
+            .cmp(&a.size_bytes)
+            .then_with(|| a.digest.cmp(&b.digest))
+    });
+    Ok(usage)
 }
 
 // ============================================================================
@@ -1241,6 +2506,9 @@ pub fn garbage_collect(dry_run: bool) -> Result<u64> {
 /// mounting layers, and creating OCI bundles.
 struct OverlaySetup {
     overlay_root: PathBuf,
+    /// Where `upper_path` and `work_path` are; `overlay_root` unless upper
+    /// layers are kept elsewhere.
+    upper_base: PathBuf,
     upper_path: PathBuf,
     work_path: PathBuf,
     merged_path: PathBuf,
@@ -1249,20 +2517,42 @@ struct OverlaySetup {
 
 impl OverlaySetup {
     /// Create a new overlay setup for the given workload.
-    fn new(workload_id: &str) -> Self {
-        let root = Path::new(STORAGE_ROOT);
+    fn new(workload_id: &str) -> Result<Self> {
+        Ok(Self::at(
+            Path::new(STORAGE_ROOT),
+            overlay_upper_root()?,
+            workload_id,
+        ))
+    }
+
+    /// Create an overlay setup under a specific storage root, with the
+    /// upper and work directories under `upper_root` if set.
+    fn at(root: &Path, upper_root: Option<&Path>, workload_id: &str) -> Self {
         let overlay_root = root.join(OVERLAYS_DIR).join(workload_id);
+        let upper_base = overlay_upper_base(root, upper_root, workload_id);
         Self {
-            upper_path: overlay_root.join("upper"),
-            work_path: overlay_root.join("work"),
+            upper_path: upper_base.join("upper"),
+            work_path: upper_base.join("work"),
             merged_path: overlay_root.join("merged"),
             overlay_root,
+            upper_base,
             workload_id: workload_id.to_string(),
         }
     }
 
     /// Prepare overlay directories, cleaning up any previous state.
     fn prepare_directories(&self) -> Result<()> {
+        self.remove_previous();
+
+        std::fs::create_dir_all(&self.upper_path)?;
+        std::fs::create_dir_all(&self.work_path)?;
+        std::fs::create_dir_all(&self.merged_path)?;
+
+        Ok(())
+    }
+
+    /// Unmount and remove any previous overlay state.
+    fn remove_previous(&self) {
         // Clean up any previous overlay state - workdir must be empty for overlay mount
         if self.overlay_root.exists() {
             // Try to unmount if previously mounted
@@ -1274,12 +2564,11 @@ impl OverlaySetup {
                 warn!(path = %self.overlay_root.display(), error = %e, "failed to remove old overlay directory");
             }
         }
-
-        std::fs::create_dir_all(&self.upper_path)?;
-        std::fs::create_dir_all(&self.work_path)?;
-        std::fs::create_dir_all(&self.merged_path)?;
-
-        Ok(())
+        if self.upper_base != self.overlay_root && self.upper_base.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&self.upper_base) {
+                warn!(path = %self.upper_base.display(), error = %e, "failed to remove old overlay upper layer");
+            }
+        }
     }
 
     /// Set up the upper layer with DNS resolution and /dev directory.
@@ -1288,7 +2577,7 @@ impl OverlaySetup {
         let upper_etc = self.upper_path.join("etc");
         std::fs::create_dir_all(&upper_etc)?;
         let resolv_path = upper_etc.join("resolv.conf");
-        if let Err(e) = std::fs::write(&resolv_path, "nameserver 8.8.8.8\nnameserver 1.1.1.1\n") {
+        if let Err(e) = std::fs::write(&resolv_path, resolv_conf()) {
             warn!(error = %e, "failed to write resolv.conf to upper layer");
         }
 
@@ -1320,15 +2609,70 @@ impl OverlaySetup {
         Ok(())
     }
 
-    /// Mount the overlay filesystem with fallback from multi-lowerdir to sequential.
+    /// Mount the overlay filesystem, falling back to fuse-overlayfs if the
+    /// kernel overlay can't be used.
     fn mount(&self, lowerdirs: &[String]) -> Result<()> {
-        // Try multi-lowerdir mount first (efficient)
-        let mount_result = try_mount_overlay_multi_lower(
+        self.mount_with_fallback(
+            lowerdirs,
+            || self.mount_native(lowerdirs),
+            FUSE_OVERLAYFS_BIN,
+        )
+    }
+
+    /// Try `native`, and if it fails or leaves the merged directory empty,
+    /// mount with `fuse_bin` using the same lower/upper/work directories.
+    fn mount_with_fallback(
+        &self,
+        lowerdirs: &[String],
+        native: impl FnOnce() -> Result<()>,
+        fuse_bin: &str,
+    ) -> Result<()> {
+        let native_error = match native() {
+            Ok(()) if !dir_is_empty(&self.merged_path) => return Ok(()),
+            Ok(()) => {
+                // The upper layer always has etc/ and dev/, so an empty merged
+                // dir means the mount silently didn't take effect.
+                let _ = Command::new("umount").arg(&self.merged_path).output();
+                "overlay mount succeeded but merged directory is empty".to_string()
+            }
+            Err(e) => e.to_string(),
+        };
+
+        warn!(
+            workload_id = %self.workload_id,
+            error = %native_error,
+            "kernel overlay mount unusable, trying fuse-overlayfs"
+        );
+
+        // A failed kernel mount may have left state in the workdir
+        let _ = std::fs::remove_dir_all(&self.work_path);
+        std::fs::create_dir_all(&self.work_path)?;
+
+        mount_fuse_overlayfs(
+            fuse_bin,
             lowerdirs,
             &self.upper_path,
             &self.work_path,
             &self.merged_path,
-        );
+        )
+        .map_err(|e| match e {
+            FuseMountError::NotInstalled => StorageError::OverlayUnsupported {
+                cause: native_error,
+            },
+            FuseMountError::Failed(e) => e,
+        })
+    }
+
+    /// Mount with the kernel overlayfs, falling back from multi-lowerdir to
+    /// sequential.
+    fn mount_native(&self, lowerdirs: &[String]) -> Result<()> {
+        // Try multi-lowerdir mount first (efficient)
+        let mount_result = try_mount_overlay_multi_lower(
+            lowerdirs,
+            &self.upper_path,
+            &self.work_path,
+            &self.merged_path,
+        );
 
         if let Err(multi_err) = mount_result {
             if lowerdirs.len() > 1 {
@@ -1405,11 +2749,29 @@ impl OverlaySetup {
     }
 
     /// Execute the full overlay setup pipeline with the given lower directories.
+    ///
+    /// Read-only workloads (see [`READ_ONLY_OVERLAY_PREFIX`]) get the layers
+    /// mounted without an upper layer instead.
     fn execute(self, lowerdirs: Vec<String>) -> Result<OverlayInfo> {
+        self.execute_with(lowerdirs, Self::mount)
+    }
+
+    /// [`execute`](Self::execute), mounting with `mount`.
+    fn execute_with(
+        self,
+        lowerdirs: Vec<String>,
+        mount: impl FnOnce(&Self, &[String]) -> Result<()>,
+    ) -> Result<OverlayInfo> {
+        if self.workload_id.starts_with(READ_ONLY_OVERLAY_PREFIX) {
+            return self.execute_read_only(lowerdirs, "mount");
+        }
         self.prepare_directories()?;
+        // Recorded for diff_overlay, which needs to know what the upper
+        // layer shadows
+        std::fs::write(self.overlay_root.join(LOWERDIRS_FILE), lowerdirs.join("\n"))?;
         self.setup_upper_layer()?;
         self.verify_layers(&lowerdirs)?;
-        self.mount(&lowerdirs)?;
+        mount(&self, &lowerdirs)?;
 
         let entry_count = self.verify_mount();
         info!(workload_id = %self.workload_id, entry_count = entry_count, "overlay mounted");
@@ -1417,6 +2779,51 @@ impl OverlaySetup {
         self.create_bundle()?;
         Ok(self.into_overlay_info())
     }
+
+    /// Mount the layers read-only at the merged path, with no upper or work
+    /// directory to create (or, for ephemeral runs, remove) per run.
+    ///
+    /// Overlayfs needs at least two lower layers when there is no upper
+    /// one, so a single layer is stacked on an empty directory. The
+    /// container's resolv.conf is written beside the mount, for
+    /// [`configure_read_only_root`] to bind over the image's.
+    fn execute_read_only(self, lowerdirs: Vec<String>, mount_bin: &str) -> Result<OverlayInfo> {
+        self.remove_previous();
+        let empty_path = self.overlay_root.join("empty");
+        std::fs::create_dir_all(&self.merged_path)?;
+        std::fs::create_dir_all(&empty_path)?;
+        std::fs::write(self.overlay_root.join("resolv.conf"), resolv_conf())?;
+        self.verify_layers(&lowerdirs)?;
+
+        let mut lowerdir = lowerdirs.join(":");
+        if lowerdirs.len() < 2 {
+            lowerdir = format!("{}:{}", lowerdir, empty_path.display());
+        }
+        let output = Command::new(mount_bin)
+            .args(["-t", "overlay", "overlay", "-o"])
+            .arg(format!("lowerdir={}", lowerdir))
+            .arg(&self.merged_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| StorageError::new(tools::MOUNT.spawn_error(&e)))?;
+        if !output.status.success() {
+            return Err(StorageError::new(format!(
+                "read-only overlay mount failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        let entry_count = self.verify_mount();
+        info!(workload_id = %self.workload_id, entry_count = entry_count, "read-only overlay mounted");
+
+        self.create_bundle()?;
+        Ok(OverlayInfo {
+            rootfs_path: self.merged_path.display().to_string(),
+            upper_path: String::new(),
+            work_path: String::new(),
+        })
+    }
 }
 
 /// Prepare an overlay filesystem for a workload.
@@ -1427,12 +2834,27 @@ pub fn prepare_overlay(image: &str, workload_id: &str) -> Result<OverlayInfo> {
         return prepare_overlay_from_packed(image, workload_id, packed_dir);
     }
 
+    prepare_overlay_at(
+        Path::new(STORAGE_ROOT),
+        overlay_upper_root()?,
+        image,
+        workload_id,
+        OverlaySetup::mount,
+    )
+}
+
+fn prepare_overlay_at(
+    root: &Path,
+    upper_root: Option<&Path>,
+    image: &str,
+    workload_id: &str,
+    mount: impl FnOnce(&OverlaySetup, &[String]) -> Result<()>,
+) -> Result<OverlayInfo> {
     // Ensure image exists
-    let info = query_image(image)?
+    let info = image_info_at(root, image, &digest_references(root)?)?
         .ok_or_else(|| StorageError::new(format!("image not found: {}", image)))?;
 
     // Build lowerdir from layers (reversed for overlay order - top layer first)
-    let root = Path::new(STORAGE_ROOT);
     let lowerdirs: Vec<String> = info
         .layers
         .iter()
@@ -1444,7 +2866,7 @@ pub fn prepare_overlay(image: &str, workload_id: &str) -> Result<OverlayInfo> {
         .collect();
 
     // Use shared overlay setup logic
-    OverlaySetup::new(workload_id).execute(lowerdirs)
+    OverlaySetup::at(root, upper_root, workload_id).execute_with(lowerdirs, mount)
 }
 
 /// Prepare an overlay filesystem using pre-packed layers.
@@ -1501,24 +2923,43 @@ fn prepare_overlay_from_packed(
         .collect();
 
     // Use shared overlay setup logic
-    OverlaySetup::new(workload_id).execute(lowerdirs)
+    OverlaySetup::new(workload_id)?.execute(lowerdirs)
 }
 
 /// Clean up an overlay filesystem.
+///
+/// The merged mount is unmounted first (see [`unmount_overlay`]); the overlay
+/// directory is only removed once it is no longer mounted, so a busy mount is
+/// never deleted through.
 pub fn cleanup_overlay(workload_id: &str) -> Result<()> {
-    let root = Path::new(STORAGE_ROOT);
+    cleanup_overlay_at(
+        Path::new(STORAGE_ROOT),
+        existing_overlay_upper_root(),
+        workload_id,
+        "umount",
+        unmount_retry(),
+        is_mountpoint,
+    )
+}
+
+fn cleanup_overlay_at(
+    root: &Path,
+    upper_root: Option<&Path>,
+    workload_id: &str,
+    umount_bin: &str,
+    retry: crate::retry::RetryConfig,
+    is_mounted: impl Fn(&Path) -> bool,
+) -> Result<()> {
     let overlay_root = root.join(OVERLAYS_DIR).join(workload_id);
     let merged_path = overlay_root.join("merged");
 
-    // Unmount main merged path if mounted
-    if merged_path.exists() {
-        if let Err(e) = Command::new("umount").arg(&merged_path).status() {
-            debug!(
-                workload_id = %workload_id,
-                path = %merged_path.display(),
-                error = %e,
-                "failed to unmount overlay (may not have been mounted)"
-            );
+    if is_mounted(&merged_path) {
+        unmount_overlay(&merged_path, umount_bin, retry);
+        if is_mounted(&merged_path) {
+            return Err(StorageError::new(format!(
+                "overlay {} is still mounted, not removing it",
+                merged_path.display()
+            )));
         }
     }
 
@@ -1526,63 +2967,534 @@ pub fn cleanup_overlay(workload_id: &str) -> Result<()> {
     if overlay_root.exists() {
         std::fs::remove_dir_all(&overlay_root)?;
     }
+    let upper_base = overlay_upper_base(root, upper_root, workload_id);
+    if upper_base.exists() {
+        std::fs::remove_dir_all(&upper_base)?;
+    }
 
     info!(workload_id = %workload_id, "overlay cleaned up");
     Ok(())
 }
 
+/// Remove overlays nothing will use again: those of containers no longer in
+/// the registry (per `container_exists`) and ephemeral run overlays left
+/// behind by an interrupted run. Persistent and read-only run overlays are
+/// kept, as they're reused by the next run of their image.
+///
+/// With `dry_run`, only report what would be removed.
+pub fn prune_overlays(
+    dry_run: bool,
+    container_exists: impl Fn(&str) -> bool,
+) -> Result<PrunedOverlays> {
+    prune_overlays_at(
+        Path::new(STORAGE_ROOT),
+        existing_overlay_upper_root(),
+        dry_run,
+        container_exists,
+        overlay_lru::is_leased,
+        cleanup_run_overlay,
+    )
+}
+
+fn prune_overlays_at(
+    root: &Path,
+    upper_root: Option<&Path>,
+    dry_run: bool,
+    container_exists: impl Fn(&str) -> bool,
+    is_leased: impl Fn(&str) -> bool,
+    remove_overlay: impl Fn(&str) -> Result<()>,
+) -> Result<PrunedOverlays> {
+    let mut pruned = PrunedOverlays::default();
+    let Ok(entries) = std::fs::read_dir(root.join(OVERLAYS_DIR)) else {
+        return Ok(pruned);
+    };
+
+    let mut ids: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    ids.sort();
+
+    for id in ids {
+        let orphaned = if let Some(container_id) = id.strip_prefix("container-") {
+            !container_exists(container_id)
+        } else {
+            id.starts_with("ephemeral-")
+        };
+        if !orphaned || is_leased(&id) {
+            continue;
+        }
+
+        let size = overlay_size_at(root, upper_root, &id);
+        info!(workload_id = %id, size = size, dry_run = dry_run, "orphaned overlay");
+        if !dry_run {
+            remove_overlay(&id)?;
+        }
+        pruned.freed_bytes += size;
+        pruned.removed.push(id);
+    }
+
+    Ok(pruned)
+}
+
+/// Disk space used by the overlay of `workload_id`, including an upper
+/// layer kept outside the storage disk.
+pub fn workload_overlay_size(workload_id: &str) -> u64 {
+    overlay_size_at(
+        Path::new(STORAGE_ROOT),
+        existing_overlay_upper_root(),
+        workload_id,
+    )
+}
+
+fn overlay_size_at(root: &Path, upper_root: Option<&Path>, workload_id: &str) -> u64 {
+    let mut size = overlay_size(&root.join(OVERLAYS_DIR).join(workload_id));
+    if let Some(upper_root) = upper_root {
+        size += overlay_size(&overlay_upper_base(root, Some(upper_root), workload_id));
+    }
+    size
+}
+
+/// Disk space used by the overlay at `overlay_root`, not counting anything
+/// mounted in it (the merged rootfs shows the shared image layers).
+pub fn overlay_size(overlay_root: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(overlay_root) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| !is_mountpoint(path))
+        .map(|path| dir_size(&path).unwrap_or(0))
+        .sum()
+}
+
+/// Prefix of an OCI-style whiteout, marking the file it names as deleted.
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// OCI-style marker for an opaque directory, one hiding the lower layers'
+/// contents.
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// Paths [`OverlaySetup`] writes to every upper layer, which aren't the
+/// workload's changes.
+const AGENT_UPPER_PATHS: &[&str] = &["/dev", "/etc/resolv.conf"];
+
+/// List what a workload changed relative to its image.
+///
+/// Walks the overlay's upper layer: a path that is also in a lower layer
+/// is changed, one that isn't is added, and a whiteout (a 0/0 character
+/// device, or a `.wh.` marker) is a deletion. Overlays prepared before the
+/// lower layers were recorded report every path as added.
+pub fn diff_overlay(workload_id: &str) -> Result<Vec<DiffEntry>> {
+    diff_overlay_at(
+        Path::new(STORAGE_ROOT),
+        existing_overlay_upper_root(),
+        workload_id,
+    )
+}
+
+fn diff_overlay_at(
+    root: &Path,
+    upper_root: Option<&Path>,
+    workload_id: &str,
+) -> Result<Vec<DiffEntry>> {
+    if workload_id.is_empty() || workload_id.contains('/') || workload_id.starts_with('.') {
+        return Err(StorageError::ValidationFailed {
+            context: "workload_id".into(),
+            reason: format!("invalid workload ID '{}'", workload_id),
+        });
+    }
+    let overlay_root = root.join(OVERLAYS_DIR).join(workload_id);
+    let upper = overlay_upper_base(root, upper_root, workload_id).join("upper");
+    if !upper.is_dir() {
+        return Err(StorageError::OverlayNotFound {
+            workload_id: workload_id.to_string(),
+        });
+    }
+    let lowerdirs: Vec<PathBuf> = std::fs::read_to_string(overlay_root.join(LOWERDIRS_FILE))
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect();
+
+    let mut entries = Vec::new();
+    diff_dir(&upper, Path::new(""), &lowerdirs, &mut entries)?;
+
+    // A directory the agent only touched to write its own files isn't a
+    // change either
+    let reported: Vec<String> = entries.iter().map(|e| e.path.clone()).collect();
+    entries.retain(|entry| {
+        let agent_parent = AGENT_UPPER_PATHS
+            .iter()
+            .any(|p| p.starts_with(&format!("{}/", entry.path)));
+        let has_children = reported
+            .iter()
+            .any(|p| p.starts_with(&format!("{}/", entry.path)));
+        !(entry.kind == DiffKind::Changed && agent_parent && !has_children)
+    });
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Classify the entries of `rel` in `upper`, recursing into directories.
+fn diff_dir(
+    upper: &Path,
+    rel: &Path,
+    lowerdirs: &[PathBuf],
+    entries: &mut Vec<DiffEntry>,
+) -> Result<()> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let dir = upper.join(rel);
+    let listing = std::fs::read_dir(&dir)
+        .map_err(|e| StorageError::read_error(dir.display().to_string(), e))?;
+    for entry in listing {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name == OPAQUE_WHITEOUT {
+            continue;
+        }
+        if let Some(deleted) = name.strip_prefix(WHITEOUT_PREFIX) {
+            entries.push(DiffEntry {
+                path: rootfs_path(&rel.join(deleted)),
+                kind: DiffKind::Deleted,
+            });
+            continue;
+        }
+
+        let rel_path = rel.join(&*name);
+        let path = rootfs_path(&rel_path);
+        if AGENT_UPPER_PATHS.contains(&path.as_str()) {
+            continue;
+        }
+        // DirEntry::metadata doesn't follow symlinks
+        let meta = entry.metadata()?;
+        let kind = if meta.file_type().is_char_device() && meta.rdev() == 0 {
+            DiffKind::Deleted
+        } else if lowerdirs
+            .iter()
+            .any(|lower| lower.join(&rel_path).symlink_metadata().is_ok())
+        {
+            DiffKind::Changed
+        } else {
+            DiffKind::Added
+        };
+        entries.push(DiffEntry { path, kind });
+        if meta.is_dir() {
+            diff_dir(upper, &rel_path, lowerdirs, entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// `rel`, relative to a rootfs, as an absolute path inside it.
+fn rootfs_path(rel: &Path) -> String {
+    format!("/{}", rel.display())
+}
+
+/// Retries for unmounting an overlay that is still in use.
+fn unmount_retry() -> crate::retry::RetryConfig {
+    crate::retry::RetryConfig {
+        max_attempts: 5,
+        initial_delay: std::time::Duration::from_millis(100),
+        max_delay: std::time::Duration::from_secs(1),
+        ..Default::default()
+    }
+}
+
+/// Unmount `merged`, retrying with backoff while it is busy and detaching it
+/// lazily (`umount -l`) if it stays busy.
+///
+/// Failures are logged; callers check whether the path is still mounted.
+fn unmount_overlay(merged: &Path, umount_bin: &str, retry: crate::retry::RetryConfig) {
+    let umount = |lazy: bool| -> Result<()> {
+        let mut cmd = Command::new(umount_bin);
+        if lazy {
+            cmd.arg("-l");
+        }
+        let output = cmd
+            .arg(merged)
+            .output()
+            .map_err(|e| StorageError::SpawnFailed {
+                command: umount_bin.to_string(),
+                cause: e.to_string(),
+            })?;
+        if !output.status.success() {
+            return Err(StorageError::command_failed(
+                umount_bin,
+                output.status.code(),
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ));
+        }
+        Ok(())
+    };
+    let is_busy = |e: &StorageError| e.to_string().to_lowercase().contains("busy");
+
+    let err =
+        match crate::retry::retry_with_backoff(retry, "unmount overlay", || umount(false), is_busy)
+        {
+            Ok(()) => {
+                info!(path = %merged.display(), strategy = "umount", "overlay unmounted");
+                return;
+            }
+            Err(e) => e,
+        };
+    if !is_busy(&err) {
+        warn!(path = %merged.display(), error = %err, "failed to unmount overlay");
+        return;
+    }
+
+    match umount(true) {
+        Ok(()) => info!(path = %merged.display(), strategy = "lazy", "overlay unmounted"),
+        Err(e) => {
+            warn!(path = %merged.display(), error = %e, "failed to lazily unmount busy overlay")
+        }
+    }
+}
+
 /// Result of running a command.
 pub struct RunResult {
     pub exit_code: i32,
     pub stdout: String,
     pub stderr: String,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    pub reason: ExitReason,
+}
+
+/// Workload ID prefix for persistent run overlays.
+const PERSISTENT_OVERLAY_PREFIX: &str = "persistent-";
+
+/// Workload ID prefix for read-only run mounts, which have no upper layer.
+const READ_ONLY_OVERLAY_PREFIX: &str = "readonly-";
+
+/// DNS configuration given to containers when the host passes none, as
+/// older hosts don't.
+const FALLBACK_RESOLV_CONF: &str = "nameserver 8.8.8.8\nnameserver 1.1.1.1\n";
+
+/// DNS configuration given to containers: the resolvers and search domains
+/// the host passed in [`DNS_SERVERS_ENV`] and [`DNS_SEARCH_ENV`].
+fn resolv_conf() -> &'static str {
+    static RESOLV_CONF: OnceLock<String> = OnceLock::new();
+    RESOLV_CONF.get_or_init(|| {
+        resolv_conf_from(
+            std::env::var(DNS_SERVERS_ENV).ok().as_deref(),
+            std::env::var(DNS_SEARCH_ENV).ok().as_deref(),
+        )
+    })
+}
+
+fn resolv_conf_from(servers: Option<&str>, search: Option<&str>) -> String {
+    let Some(servers) = servers else {
+        return FALLBACK_RESOLV_CONF.to_string();
+    };
+    match DnsConfig::from_env_values(servers, search) {
+        Ok(config) => config.to_resolv_conf(),
+        Err(e) => {
+            warn!(error = %e, "invalid DNS configuration from host, using public resolvers");
+            FALLBACK_RESOLV_CONF.to_string()
+        }
+    }
+}
+
+/// Get the overlay workload ID for a `run` request.
+///
+/// Persistent runs share one overlay per image so rootfs writes survive
+/// across invocations. Ephemeral runs get a unique ID so each invocation
+/// starts from a clean upper layer. Read-only runs share one read-only
+/// mount per image, whatever `ephemeral` says, since they can't write to it.
+pub fn run_workload_id(image: &str, ephemeral: bool, read_only: bool) -> String {
+    if read_only {
+        format!("{}{}", READ_ONLY_OVERLAY_PREFIX, sanitize_image_name(image))
+    } else if ephemeral {
+        format!("ephemeral-{}", generate_container_id())
+    } else {
+        format!(
+            "{}{}",
+            PERSISTENT_OVERLAY_PREFIX,
+            sanitize_image_name(image)
+        )
+    }
+}
+
+/// Run `run` in the overlay `workload_id`. An `ephemeral` run's overlay is
+/// removed with `cleanup` afterwards, whether or not `run` succeeded; a
+/// persistent one is kept for the image's next run.
+pub fn with_run_overlay<T, E>(
+    workload_id: &str,
+    ephemeral: bool,
+    cleanup: impl FnOnce(&str) -> Result<()>,
+    run: impl FnOnce() -> std::result::Result<T, E>,
+) -> std::result::Result<T, E> {
+    let result = run();
+    if ephemeral {
+        if let Err(e) = cleanup(workload_id) {
+            warn!(workload_id = %workload_id, error = %e, "failed to clean up ephemeral overlay");
+        }
+    }
+    result
+}
+
+/// How to run a command in an image: everything a `Run` request asks for
+/// besides the image and command themselves.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Environment variables as (key, value) pairs.
+    pub env: Vec<(String, String)>,
+    /// Working directory inside the container; `/` if `None`.
+    pub workdir: Option<String>,
+    /// Volume mounts as (virtiofs_tag, container_path, read_only).
+    pub mounts: Vec<(String, String, bool)>,
+    /// In-memory filesystems to mount (see [`check_tmpfs_mounts`]).
+    pub tmpfs: Vec<TmpfsMount>,
+    /// Entries added to the container's `/etc/hosts` (see
+    /// [`add_extra_hosts`]).
+    pub extra_hosts: Vec<(String, IpAddr)>,
+    /// Kill the command after this long.
+    pub timeout_ms: Option<u64>,
+    /// Run in a fresh overlay that is removed afterwards, rather than the
+    /// image's persistent one.
+    pub ephemeral: bool,
+    /// Run on the image's layers mounted read-only (see
+    /// [`configure_read_only_root`]).
+    pub read_only: bool,
+    /// Caps on the container's cgroup.
+    pub limits: ResourceLimits,
+    /// Restrictions on the container's capabilities and syscalls.
+    pub security: SecurityOptions,
+    /// Who the command runs as (see [`crate::user`]); root if `None`.
+    pub user: Option<String>,
+    /// Create a missing `workdir` (see [`ensure_workdir`]).
+    pub create_workdir: bool,
+    /// Platform the image must suit (see [`check_run_platform`]).
+    pub platform: Option<String>,
+}
+
+impl RunOptions {
+    /// Check the options that can be checked before any overlay is set up.
+    pub fn validate(&self, image: &str) -> Result<()> {
+        crate::oci::validate_env_vars(&self.env).map_err(StorageError::new)?;
+        self.limits.validate().map_err(StorageError::new)?;
+        check_tmpfs_mounts(&self.tmpfs)?;
+        check_extra_hosts(&self.extra_hosts)?;
+        check_run_platform(image, self.platform.as_deref())
+    }
+
+    /// The overlay the run uses (see [`run_workload_id`]).
+    pub fn workload_id(&self, image: &str) -> String {
+        run_workload_id(image, self.ephemeral, self.read_only)
+    }
+
+    /// Whether the run's overlay is removed once it finishes.
+    pub fn cleans_up_overlay(&self) -> bool {
+        self.ephemeral && !self.read_only
+    }
+
+    /// The OCI spec for running `command` with these options in the overlay
+    /// mounted at `rootfs`.
+    pub fn oci_spec(&self, command: &[String], rootfs: &Path) -> Result<OciSpec> {
+        let workdir = self.workdir.as_deref().unwrap_or("/");
+        let mut spec = OciSpec::new(command, &self.env, workdir, false);
+        spec.set_resources(&self.limits);
+        spec.set_security(&self.security)
+            .map_err(|reason| StorageError::ValidationFailed {
+                context: "security options".into(),
+                reason,
+            })?;
+        if let Some(user) = &self.user {
+            spec.process.set_user(&crate::user::resolve(user, rootfs)?);
+        }
+
+        // Add virtiofs bind mounts to OCI spec
+        for (tag, container_path, read_only) in &self.mounts {
+            let virtiofs_mount = mount_source_path(tag)?;
+            spec.add_bind_mount(
+                &virtiofs_mount.to_string_lossy(),
+                container_path,
+                *read_only,
+            );
+        }
+        for mount in &self.tmpfs {
+            spec.add_tmpfs_mount(&mount.path, mount.size_bytes);
+        }
+        add_extra_hosts(&mut spec, rootfs, &self.extra_hosts)?;
+        if self.read_only {
+            configure_read_only_root(&mut spec, rootfs);
+        }
+        Ok(spec)
+    }
 }
 
 /// Run a command in an image's overlay rootfs using crun OCI runtime.
-/// Uses a persistent overlay per image for fast repeated execution.
+///
+/// Uses a persistent overlay per image for fast repeated execution, unless
+/// `options.ephemeral` is set, in which case a fresh overlay is created and
+/// removed once the command finishes (including on error). A read-only run
+/// uses the image's layers, which are shared between runs and never need
+/// cleaning up.
+///
+/// At most `output_limit` bytes of each output stream are captured.
 pub fn run_command(
     image: &str,
     command: &[String],
-    env: &[(String, String)],
-    workdir: Option<&str>,
-    mounts: &[(String, String, bool)],
-    timeout_ms: Option<u64>,
+    options: &RunOptions,
+    output_limit: usize,
 ) -> Result<RunResult> {
     // Validate inputs
     crate::oci::validate_image_reference(image).map_err(StorageError::new)?;
-    crate::oci::validate_env_vars(env).map_err(StorageError::new)?;
+    options.validate(image)?;
+
+    let workload_id = options.workload_id(image);
+    with_run_overlay(
+        &workload_id,
+        options.cleans_up_overlay(),
+        cleanup_run_overlay,
+        || run_command_in_overlay(&workload_id, image, command, options, output_limit),
+    )
+}
 
-    // Use consistent workload ID per image for overlay reuse
-    let workload_id = format!("persistent-{}", sanitize_image_name(image));
+/// Run a command in the overlay identified by `workload_id`.
+fn run_command_in_overlay(
+    workload_id: &str,
+    image: &str,
+    command: &[String],
+    options: &RunOptions,
+    output_limit: usize,
+) -> Result<RunResult> {
+    // Keep the overlay from being evicted while the command runs
+    let _lease = OverlayLease::acquire(workload_id);
 
     // Check if overlay is already mounted
-    let overlay = get_or_create_overlay(image, &workload_id)?;
+    let overlay = get_or_create_overlay(image, workload_id)?;
     debug!(rootfs = %overlay.rootfs_path, "using overlay for command execution");
+    let rootfs = Path::new(&overlay.rootfs_path);
+
+    if options.read_only {
+        check_read_only_targets(rootfs, &options.mounts, &options.tmpfs)?;
+    }
 
     // Setup volume mounts (mount virtiofs to staging area)
-    let mounted_paths = setup_volume_mounts(&overlay.rootfs_path, mounts)?;
+    let mounted_paths = setup_volume_mounts(&overlay.rootfs_path, &options.mounts)?;
+
+    if let Some(workdir) = &options.workdir {
+        ensure_workdir(
+            rootfs,
+            workdir,
+            &options.mounts,
+            options.create_workdir && !options.read_only,
+        )?;
+    }
 
     // Get bundle path
-    let overlay_root = Path::new(STORAGE_ROOT)
-        .join(OVERLAYS_DIR)
-        .join(&workload_id);
+    let overlay_root = Path::new(STORAGE_ROOT).join(OVERLAYS_DIR).join(workload_id);
     let bundle_path = overlay_root.join("bundle");
 
-    // Create OCI spec
-    let workdir_str = workdir.unwrap_or("/");
-    let mut spec = OciSpec::new(command, env, workdir_str, false);
-
-    // Add virtiofs bind mounts to OCI spec
-    for (tag, container_path, read_only) in mounts {
-        let virtiofs_mount = Path::new(paths::VIRTIOFS_MOUNT_ROOT).join(tag);
-        spec.add_bind_mount(
-            &virtiofs_mount.to_string_lossy(),
-            container_path,
-            *read_only,
-        );
-    }
-
-    // Write config.json to bundle
+    // Create OCI spec and write config.json to bundle
+    let spec = options.oci_spec(command, rootfs)?;
     spec.write_to(&bundle_path)
         .map_err(|e| StorageError::new(format!("failed to write OCI spec: {}", e)))?;
 
@@ -1590,7 +3502,12 @@ pub fn run_command(
     let container_id = generate_container_id();
 
     // Run with crun
-    let result = run_with_crun(&bundle_path, &container_id, timeout_ms);
+    let result = run_with_crun(
+        &bundle_path,
+        &container_id,
+        options.timeout_ms,
+        output_limit,
+    );
 
     // Note: virtiofs mounts are left in place for reuse
     // They will be cleaned up when the overlay is cleaned up or the VM shuts down
@@ -1599,38 +3516,349 @@ pub fn run_command(
     result
 }
 
+/// Check the tmpfs mounts of a run (see
+/// [`crate::oci::validate_tmpfs_mounts`]).
+pub fn check_tmpfs_mounts(tmpfs: &[TmpfsMount]) -> Result<()> {
+    crate::oci::validate_tmpfs_mounts(tmpfs).map_err(|reason| StorageError::ValidationFailed {
+        context: "tmpfs".into(),
+        reason,
+    })
+}
+
+/// Make the container root of a read-only run (mounted at `rootfs`)
+/// read-only, and give it the agent's resolv.conf in place of the
+/// image's. Images without an `/etc/resolv.conf` keep none, as there is
+/// nowhere to mount one.
+pub fn configure_read_only_root(spec: &mut OciSpec, rootfs: &Path) {
+    spec.root.readonly = true;
+    let image_resolv = rootfs.join("etc/resolv.conf");
+    let is_file = std::fs::symlink_metadata(&image_resolv).is_ok_and(|m| m.is_file());
+    if let (true, Some(overlay_root)) = (is_file, rootfs.parent()) {
+        spec.add_bind_mount(
+            &overlay_root.join("resolv.conf").to_string_lossy(),
+            "/etc/resolv.conf",
+            true,
+        );
+    }
+}
+
+/// File beside an overlay's mount holding the `/etc/hosts` of a run with
+/// extra hosts (see [`add_extra_hosts`]).
+const HOSTS_FILE: &str = "hosts";
+
+/// Loopback entries for images that ship no `/etc/hosts` of their own.
+const DEFAULT_HOSTS: &str = "127.0.0.1\tlocalhost\n::1\tlocalhost ip6-localhost ip6-loopback\n";
+
+/// Check the extra `/etc/hosts` entries of a run (see
+/// [`smolvm_protocol::validate_hostname`]).
+pub fn check_extra_hosts(extra_hosts: &[(String, IpAddr)]) -> Result<()> {
+    for (hostname, _) in extra_hosts {
+        smolvm_protocol::validate_hostname(hostname).map_err(|reason| {
+            StorageError::ValidationFailed {
+                context: "extra hosts".into(),
+                reason,
+            }
+        })?;
+    }
+    Ok(())
+}
+
+/// Give the container of a run (mounted at `rootfs`) an `/etc/hosts` with
+/// `extra_hosts` after the image's own entries, as `docker run --add-host`
+/// does.
+///
+/// The file is written beside the mount and bound over the image's rather
+/// than into the upper layer, so entries don't pile up in a persistent
+/// overlay across runs and read-only runs get them too.
+pub fn add_extra_hosts(
+    spec: &mut OciSpec,
+    rootfs: &Path,
+    extra_hosts: &[(String, IpAddr)],
+) -> Result<()> {
+    if extra_hosts.is_empty() {
+        return Ok(());
+    }
+    let overlay_root = rootfs
+        .parent()
+        .ok_or_else(|| StorageError::new("invalid rootfs path: no parent"))?;
+
+    // A symlinked /etc/hosts could point outside the rootfs
+    let image_hosts = rootfs.join("etc/hosts");
+    let mut hosts = match std::fs::symlink_metadata(&image_hosts) {
+        Ok(meta) if meta.is_file() => std::fs::read_to_string(&image_hosts)?,
+        _ => DEFAULT_HOSTS.to_string(),
+    };
+    if !hosts.is_empty() && !hosts.ends_with('\n') {
+        hosts.push('\n');
+    }
+    for (hostname, address) in extra_hosts {
+        hosts.push_str(&format!("{}\t{}\n", address, hostname));
+    }
+
+    let hosts_path = overlay_root.join(HOSTS_FILE);
+    std::fs::write(&hosts_path, hosts)?;
+    spec.add_bind_mount(&hosts_path.to_string_lossy(), "/etc/hosts", false);
+    Ok(())
+}
+
+/// Check that the volume and tmpfs mount points of a read-only run exist
+/// in its rootfs, since they can't be created on a read-only mount.
+pub fn check_read_only_targets(
+    rootfs: &Path,
+    mounts: &[(String, String, bool)],
+    tmpfs: &[TmpfsMount],
+) -> Result<()> {
+    let targets = mounts
+        .iter()
+        .map(|(_, path, _)| path.as_str())
+        .chain(tmpfs.iter().map(|mount| mount.path.as_str()));
+    for target in targets {
+        if !rootfs.join(target.trim_start_matches('/')).is_dir() {
+            return Err(StorageError::ValidationFailed {
+                context: "read-only run".into(),
+                reason: format!(
+                    "mount point {} doesn't exist in the image and can't be created on a read-only root",
+                    target
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
 /// Prepare for running a command - returns the rootfs path.
 /// This is used by interactive mode which spawns the command separately.
-pub fn prepare_for_run(image: &str) -> Result<String> {
-    // Use consistent workload ID per image for overlay reuse
-    let workload_id = format!("persistent-{}", sanitize_image_name(image));
-
+///
+/// `workload_id` should come from [`run_workload_id`].
+pub fn prepare_for_run(image: &str, workload_id: &str) -> Result<String> {
     // Check if overlay is already mounted
-    let overlay = get_or_create_overlay(image, &workload_id)?;
+    let overlay = get_or_create_overlay(image, workload_id)?;
     debug!(rootfs = %overlay.rootfs_path, "prepared overlay for interactive run");
 
     Ok(overlay.rootfs_path)
 }
 
+/// Tear down an ephemeral run overlay.
+///
+/// Volume bind mounts inside the merged rootfs are unmounted first so that
+/// removing the overlay directory can never recurse into host-shared data.
+pub fn cleanup_run_overlay(workload_id: &str) -> Result<()> {
+    let merged_path = Path::new(STORAGE_ROOT)
+        .join(OVERLAYS_DIR)
+        .join(workload_id)
+        .join("merged");
+
+    if let Ok(mounts) = std::fs::read_to_string("/proc/mounts") {
+        let prefix = format!("{}/", merged_path.display());
+        let mut nested: Vec<&str> = mounts
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(1))
+            .filter(|target| target.starts_with(&prefix))
+            .collect();
+        // Deepest mounts first
+        nested.sort_by_key(|target| std::cmp::Reverse(target.len()));
+        for target in nested {
+            let status = Command::new("umount").arg(target).status()?;
+            if !status.success() {
+                return Err(StorageError::new(format!(
+                    "failed to unmount {} before removing ephemeral overlay",
+                    target
+                )));
+            }
+        }
+    }
+
+    cleanup_overlay(workload_id)
+}
+
+/// Resolve the source directory for a mount tag.
+///
+/// Virtiofs tags map to their staging mountpoint under
+/// [`paths::VIRTIOFS_MOUNT_ROOT`]; `volume:<name>` tags map to the named
+/// volume's data directory, creating the volume on first use.
+pub fn mount_source_path(tag: &str) -> Result<PathBuf> {
+    match crate::volume::volume_name_from_tag(tag) {
+        Some(name) => crate::volume::ensure_volume(name),
+        None => Ok(Path::new(paths::VIRTIOFS_MOUNT_ROOT).join(tag)),
+    }
+}
+
 /// Setup volume mounts for a rootfs (public wrapper).
 pub fn setup_mounts(rootfs: &str, mounts: &[(String, String, bool)]) -> Result<()> {
     let _mounted_paths = setup_volume_mounts(rootfs, mounts)?;
     Ok(())
 }
 
-/// Setup volume mounts by mounting virtiofs and bind-mounting into the rootfs.
-fn setup_volume_mounts(rootfs: &str, mounts: &[(String, String, bool)]) -> Result<Vec<PathBuf>> {
-    let mut mounted_paths = Vec::new();
-
-    for (tag, container_path, read_only) in mounts {
-        debug!(tag = %tag, container_path = %container_path, read_only = %read_only, "setting up volume mount");
-
-        // First, mount the virtiofs device at a staging location
-        let virtiofs_mount = Path::new(paths::VIRTIOFS_MOUNT_ROOT).join(tag);
-        std::fs::create_dir_all(&virtiofs_mount)?;
+/// Maximum symlinks followed while resolving a path in a rootfs (as Linux).
+const MAX_SYMLINKS: usize = 40;
 
-        // Check if already mounted
-        if !is_mountpoint(&virtiofs_mount) {
+/// Make sure `workdir` exists in `rootfs`, creating it (in the overlay's
+/// upper layer) if `create` is set, as `docker run -w` does.
+///
+/// Symlinks along the path are resolved inside the rootfs, so an image
+/// can't have the directory created elsewhere. Paths at or under a mount
+/// target are left alone; crun creates those.
+pub fn ensure_workdir(
+    rootfs: &Path,
+    workdir: &str,
+    mounts: &[(String, String, bool)],
+    create: bool,
+) -> Result<()> {
+    let invalid = |reason: String| StorageError::ValidationFailed {
+        context: format!("workdir '{}'", workdir),
+        reason,
+    };
+    if !workdir.starts_with('/') {
+        return Err(invalid("must be an absolute path".into()));
+    }
+    if mounts
+        .iter()
+        .any(|(_, target, _)| Path::new(workdir).starts_with(target))
+    {
+        return Ok(());
+    }
+
+    // Components still to visit; `None` stands for `..`
+    let components = |path: &Path| -> Vec<Option<std::ffi::OsString>> {
+        path.components()
+            .filter_map(|c| match c {
+                std::path::Component::Normal(name) => Some(Some(name.to_os_string())),
+                std::path::Component::ParentDir => Some(None),
+                _ => None,
+            })
+            .collect()
+    };
+    let mut pending = components(Path::new(workdir));
+    pending.reverse();
+    let mut resolved = PathBuf::new();
+    let mut links = 0;
+
+    while let Some(component) = pending.pop() {
+        let Some(name) = component else {
+            resolved.pop();
+            continue;
+        };
+        let candidate = resolved.join(&name);
+        let path = rootfs.join(&candidate);
+        match std::fs::symlink_metadata(&path) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(invalid("too many levels of symbolic links".into()));
+                }
+                let target = std::fs::read_link(&path)
+                    .map_err(|e| StorageError::read_error(path.display().to_string(), e))?;
+                if target.is_absolute() {
+                    resolved = PathBuf::new();
+                }
+                let mut target = components(&target);
+                target.reverse();
+                pending.extend(target);
+            }
+            Ok(meta) if meta.is_dir() => resolved = candidate,
+            Ok(_) => {
+                return Err(invalid(format!(
+                    "/{} is not a directory",
+                    candidate.display()
+                )))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                if !create {
+                    return Err(StorageError::WorkdirNotFound {
+                        path: workdir.to_string(),
+                    });
+                }
+                std::fs::create_dir(&path).map_err(|e| StorageError::CreateDir {
+                    path: path.display().to_string(),
+                    cause: e.to_string(),
+                })?;
+                resolved = candidate;
+            }
+            Err(e) => return Err(StorageError::read_error(path.display().to_string(), e)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Maximum length of a virtiofs mount tag (the kernel's limit is 36 bytes).
+const MAX_MOUNT_TAG_LEN: usize = 36;
+
+/// Validate a request's volume mounts before anything is mounted.
+///
+/// Tags must be unique and either name a volume or consist of ASCII
+/// alphanumerics, `-` and `_`. Container paths must be absolute, must not
+/// contain `..`, and no two mounts may target the same path.
+pub fn validate_mounts(mounts: &[(String, String, bool)]) -> Result<()> {
+    let invalid = |context: String, reason: &str| StorageError::ValidationFailed {
+        context,
+        reason: reason.to_string(),
+    };
+
+    let mut tags = HashSet::new();
+    let mut targets = HashSet::new();
+    for (tag, container_path, _) in mounts {
+        let context = format!("invalid mount tag '{}'", tag);
+        match crate::volume::volume_name_from_tag(tag) {
+            Some(name) => crate::volume::validate_volume_name(name)?,
+            None => {
+                if tag.is_empty() || tag.len() > MAX_MOUNT_TAG_LEN {
+                    return Err(invalid(
+                        context,
+                        &format!("must be 1-{} characters", MAX_MOUNT_TAG_LEN),
+                    ));
+                }
+                if !tag
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                {
+                    return Err(invalid(
+                        context,
+                        "only alphanumerics, '-' and '_' are allowed",
+                    ));
+                }
+            }
+        }
+        if !tags.insert(tag.as_str()) {
+            return Err(invalid(context, "tag is used by more than one mount"));
+        }
+
+        let context = format!("invalid mount target '{}'", container_path);
+        let path = Path::new(container_path);
+        if !path.is_absolute() {
+            return Err(invalid(context, "must be an absolute path"));
+        }
+        if path
+            .components()
+            .any(|c| matches!(c, std::path::Component::ParentDir))
+        {
+            return Err(invalid(context, "must not contain '..'"));
+        }
+        // components() drops repeated and trailing slashes and `.`
+        let normalized: PathBuf = path.components().collect();
+        if !targets.insert(normalized) {
+            return Err(invalid(context, "path is targeted by more than one mount"));
+        }
+    }
+    Ok(())
+}
+
+/// Setup volume mounts by mounting virtiofs and bind-mounting into the rootfs.
+fn setup_volume_mounts(rootfs: &str, mounts: &[(String, String, bool)]) -> Result<Vec<PathBuf>> {
+    validate_mounts(mounts)?;
+
+    let mut mounted_paths = Vec::new();
+
+    for (tag, container_path, read_only) in mounts {
+        debug!(tag = %tag, container_path = %container_path, read_only = %read_only, "setting up volume mount");
+
+        // First, mount the virtiofs device at a staging location (named
+        // volumes already live on the storage disk and need no staging)
+        let virtiofs_mount = mount_source_path(tag)?;
+        std::fs::create_dir_all(&virtiofs_mount)?;
+
+        // Check if already mounted
+        if crate::volume::volume_name_from_tag(tag).is_none() && !is_mountpoint(&virtiofs_mount) {
             info!(tag = %tag, mount_point = %virtiofs_mount.display(), "mounting virtiofs");
 
             // Mount virtiofs with sync option to ensure writes are persisted immediately
@@ -1638,7 +3866,8 @@ fn setup_volume_mounts(rootfs: &str, mounts: &[(String, String, bool)]) -> Resul
             let status = Command::new("mount")
                 .args(["-t", "virtiofs", "-o", "sync", tag])
                 .arg(&virtiofs_mount)
-                .status()?;
+                .status()
+                .map_err(|e| StorageError::new(tools::MOUNT.spawn_error(&e)))?;
 
             if !status.success() {
                 warn!(tag = %tag, "failed to mount virtiofs device");
@@ -1661,7 +3890,10 @@ fn setup_volume_mounts(rootfs: &str, mounts: &[(String, String, bool)]) -> Resul
 
             let args = ["--bind", &virtiofs_mount.to_string_lossy(), &target_path];
 
-            let status = Command::new("mount").args(args).status()?;
+            let status = Command::new("mount")
+                .args(args)
+                .status()
+                .map_err(|e| StorageError::new(tools::MOUNT.spawn_error(&e)))?;
 
             if !status.success() {
                 warn!(target = %target_path, "failed to bind-mount");
@@ -1683,23 +3915,109 @@ fn setup_volume_mounts(rootfs: &str, mounts: &[(String, String, bool)]) -> Resul
 }
 
 /// Get existing overlay or create new one.
+///
+/// Persistent overlays are recorded in the LRU index, which may evict other
+/// idle persistent overlays to stay under the limit. Read-only mounts aren't
+/// tracked, as they take no space beyond the image's layers.
 fn get_or_create_overlay(image: &str, workload_id: &str) -> Result<OverlayInfo> {
-    let root = Path::new(STORAGE_ROOT);
+    get_or_create_overlay_at(
+        Path::new(STORAGE_ROOT),
+        existing_overlay_upper_root(),
+        workload_id,
+        is_mountpoint,
+        cleanup_run_overlay,
+        || prepare_overlay(image, workload_id),
+    )
+}
+
+/// [`get_or_create_overlay`] under `root`, with `is_mounted` telling
+/// whether an overlay is still mounted, `evict` removing overlays the LRU
+/// index evicts, and `prepare` creating the overlay if there is none.
+fn get_or_create_overlay_at(
+    root: &Path,
+    upper_root: Option<&Path>,
+    workload_id: &str,
+    is_mounted: impl Fn(&Path) -> bool,
+    evict: impl FnMut(&str) -> Result<()>,
+    prepare: impl FnOnce() -> Result<OverlayInfo>,
+) -> Result<OverlayInfo> {
     let overlay_root = root.join(OVERLAYS_DIR).join(workload_id);
     let merged_path = overlay_root.join("merged");
 
+    if workload_id.starts_with(PERSISTENT_OVERLAY_PREFIX) {
+        if let Err(e) = track_persistent_overlay(
+            &root.join(OVERLAYS_DIR),
+            workload_id,
+            overlay_lru::max_persistent_overlays(),
+            overlay_lru::now_millis(),
+            evict,
+        ) {
+            warn!(workload_id = %workload_id, error = %e, "failed to update overlay LRU index");
+        }
+    }
+
     // Check if already mounted
-    if merged_path.exists() && is_mountpoint(&merged_path) {
+    if merged_path.exists() && is_mounted(&merged_path) {
         debug!(workload_id = %workload_id, "reusing existing overlay");
+        let upper_base = overlay_upper_base(root, upper_root, workload_id);
         return Ok(OverlayInfo {
             rootfs_path: merged_path.display().to_string(),
-            upper_path: overlay_root.join("upper").display().to_string(),
-            work_path: overlay_root.join("work").display().to_string(),
+            upper_path: upper_base.join("upper").display().to_string(),
+            work_path: upper_base.join("work").display().to_string(),
         });
     }
 
     // Create new overlay
-    prepare_overlay(image, workload_id)
+    prepare()
+}
+
+/// Record a use of the persistent overlay `workload_id` at `now`, first
+/// evicting (via `evict`) the least recently used idle persistent overlays so
+/// that at most `max` remain. Returns the evicted workload IDs.
+fn track_persistent_overlay(
+    overlays_dir: &Path,
+    workload_id: &str,
+    max: usize,
+    now: u64,
+    mut evict: impl FnMut(&str) -> Result<()>,
+) -> Result<Vec<String>> {
+    // Serializes index updates between concurrent runs
+    static INDEX_LOCK: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+    let _guard = INDEX_LOCK.lock();
+
+    let index_path = overlays_dir.join(overlay_lru::INDEX_FILE);
+    let mut index = OverlayIndex::load(&index_path);
+    index.sync_with(&list_persistent_overlays(overlays_dir));
+
+    let mut evicted = Vec::new();
+    for victim in index.eviction_candidates(workload_id, max, overlay_lru::is_leased) {
+        match evict(&victim) {
+            Ok(()) => {
+                info!(workload_id = %victim, "evicted least recently used overlay");
+                index.remove(&victim);
+                evicted.push(victim);
+            }
+            Err(e) => warn!(workload_id = %victim, error = %e, "failed to evict overlay"),
+        }
+    }
+
+    index.touch(workload_id, now);
+    std::fs::create_dir_all(overlays_dir)?;
+    index.save(&index_path)?;
+    Ok(evicted)
+}
+
+/// Workload IDs of the persistent overlays under `overlays_dir`.
+fn list_persistent_overlays(overlays_dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(overlays_dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with(PERSISTENT_OVERLAY_PREFIX))
+        .collect()
 }
 
 /// Check if a path is a mountpoint.
@@ -1711,11 +4029,13 @@ fn is_mountpoint(path: &Path) -> bool {
 /// Run a command using crun OCI runtime (one-shot execution).
 ///
 /// This uses `crun run` which creates, starts, waits, and deletes the container
-/// in a single operation. Stdout and stderr are captured.
+/// in a single operation. Stdout and stderr are captured, up to
+/// `output_limit` bytes each.
 fn run_with_crun(
     bundle_dir: &Path,
     container_id: &str,
     timeout_ms: Option<u64>,
+    output_limit: usize,
 ) -> Result<RunResult> {
     info!(
         container_id = %container_id,
@@ -1724,43 +4044,57 @@ fn run_with_crun(
         "running container with crun"
     );
 
+    // Sample the OOM counter so a SIGKILL can be attributed to the OOM killer
+    let oom_kills_before = oom_kill_count();
+
     // Spawn the container using CrunCommand
     let mut child = CrunCommand::run(bundle_dir, container_id)
         .capture_output()
         .spawn()
-        .map_err(|e| {
-            StorageError::new(format!(
-                "failed to spawn crun: {}. Is crun installed at {}?",
-                e,
-                paths::CRUN_PATH
-            ))
-        })?;
+        .map_err(|e| StorageError::new(format!("failed to spawn crun: {}", e)))?;
 
     // Capture container_id for the cleanup closure
     let cid = container_id.to_string();
 
     // Wait with timeout, cleaning up container on timeout
-    let result = wait_with_timeout_and_cleanup(&mut child, timeout_ms, || {
+    let result = wait_with_timeout_and_cleanup(&mut child, timeout_ms, output_limit, || {
         // Kill and delete the container on timeout
         let _ = CrunCommand::kill(&cid, "SIGKILL").status();
         let _ = CrunCommand::delete(&cid, true).status();
     })?;
 
-    // Convert WaitResult to RunResult
+    Ok(run_result_from_wait(container_id, result, oom_kills_before))
+}
+
+/// Convert a crun `WaitResult` into a `RunResult`, classifying the exit.
+fn run_result_from_wait(
+    container_id: &str,
+    result: WaitResult,
+    oom_kills_before: Option<u64>,
+) -> RunResult {
+    let reason = result.exit_reason(oom_kills_before);
     match result {
-        WaitResult::Completed { exit_code, output } => {
+        WaitResult::Completed {
+            exit_code, output, ..
+        } => {
             info!(
                 container_id = %container_id,
                 exit_code = exit_code,
+                reason = ?reason,
                 stdout_len = output.stdout.len(),
                 stderr_len = output.stderr.len(),
+                stdout_truncated = output.stdout_truncated,
+                stderr_truncated = output.stderr_truncated,
                 "container finished"
             );
-            Ok(RunResult {
+            RunResult {
                 exit_code,
                 stdout: output.stdout,
                 stderr: output.stderr,
-            })
+                stdout_truncated: output.stdout_truncated,
+                stderr_truncated: output.stderr_truncated,
+                reason,
+            }
         }
         WaitResult::TimedOut { output, timeout_ms } => {
             warn!(
@@ -1768,14 +4102,17 @@ fn run_with_crun(
                 timeout_ms = timeout_ms,
                 "container timed out"
             );
-            Ok(RunResult {
+            RunResult {
                 exit_code: TIMEOUT_EXIT_CODE,
                 stdout: output.stdout,
+                stdout_truncated: output.stdout_truncated,
+                stderr_truncated: output.stderr_truncated,
                 stderr: format!(
                     "{}\ncontainer timed out after {}ms",
                     output.stderr, timeout_ms
                 ),
-            })
+                reason,
+            }
         }
     }
 }
@@ -1815,7 +4152,8 @@ fn try_mount_overlay_multi_lower(
         .arg(merged_path)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .output()?;
+        .output()
+        .map_err(|e| StorageError::new(tools::MOUNT.spawn_error(&e)))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -1828,6 +4166,73 @@ fn try_mount_overlay_multi_lower(
     Ok(())
 }
 
+/// Why the fuse-overlayfs fallback failed.
+enum FuseMountError {
+    /// The fuse-overlayfs binary isn't available.
+    NotInstalled,
+    /// fuse-overlayfs ran but the mount failed.
+    Failed(StorageError),
+}
+
+/// Mount an overlay with fuse-overlayfs, using the same options as the
+/// kernel mount.
+fn mount_fuse_overlayfs(
+    fuse_bin: &str,
+    lowerdirs: &[String],
+    upper_path: &Path,
+    work_path: &Path,
+    merged_path: &Path,
+) -> std::result::Result<(), FuseMountError> {
+    let mount_opts = format!(
+        "lowerdir={},upperdir={},workdir={}",
+        lowerdirs.join(":"),
+        upper_path.display(),
+        work_path.display()
+    );
+
+    info!(
+        layer_count = lowerdirs.len(),
+        merged_path = %merged_path.display(),
+        "mounting overlay with fuse-overlayfs"
+    );
+
+    let output = match Command::new(fuse_bin)
+        .args(["-o", &mount_opts])
+        .arg(merged_path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(FuseMountError::NotInstalled)
+        }
+        Err(e) => {
+            return Err(FuseMountError::Failed(StorageError::SpawnFailed {
+                command: fuse_bin.to_string(),
+                cause: e.to_string(),
+            }))
+        }
+    };
+
+    if !output.status.success() {
+        return Err(FuseMountError::Failed(StorageError::CommandFailed {
+            command: fuse_bin.to_string(),
+            exit_code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }));
+    }
+
+    Ok(())
+}
+
+/// Whether a directory is missing or has no entries.
+fn dir_is_empty(path: &Path) -> bool {
+    std::fs::read_dir(path)
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(true)
+}
+
 /// Mount overlay by merging layers into a single directory (most compatible).
 ///
 /// This approach physically copies all layers into a single merged directory,
@@ -1861,7 +4266,8 @@ fn mount_overlay_sequential(
             .arg(merged_path)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .output()?;
+            .output()
+            .map_err(|e| StorageError::new(tools::MOUNT.spawn_error(&e)))?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
@@ -1938,7 +4344,8 @@ fn mount_overlay_sequential(
         .arg(merged_path)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .output()?;
+        .output()
+        .map_err(|e| StorageError::new(tools::MOUNT.spawn_error(&e)))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -1960,171 +4367,149 @@ fn mount_overlay_sequential(
 // Helper functions
 // ============================================================================
 
-/// Extract the registry hostname from an image reference.
-/// e.g., "alpine:latest" -> "https://index.docker.io/v1/"
-/// e.g., "ghcr.io/owner/repo" -> "ghcr.io"
-fn extract_registry_from_image(image: &str) -> String {
-    if let Some(slash_pos) = image.find('/') {
-        let potential_registry = &image[..slash_pos];
-        if potential_registry.contains('.') || potential_registry.contains(':') {
-            return potential_registry.to_string();
-        }
-    }
-    // Docker Hub uses this URL in config.json
-    "https://index.docker.io/v1/".to_string()
-}
-
-/// Simple base64 encoding for auth string.
-fn base64_encode(input: &str) -> String {
-    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
-    let bytes = input.as_bytes();
-    let mut result = String::new();
-
-    for chunk in bytes.chunks(3) {
-        let b0 = chunk[0] as usize;
-        let b1 = chunk.get(1).copied().unwrap_or(0) as usize;
-        let b2 = chunk.get(2).copied().unwrap_or(0) as usize;
-
-        result.push(ALPHABET[b0 >> 2] as char);
-        result.push(ALPHABET[((b0 & 0x03) << 4) | (b1 >> 4)] as char);
-
-        if chunk.len() > 1 {
-            result.push(ALPHABET[((b1 & 0x0f) << 2) | (b2 >> 6)] as char);
-        } else {
-            result.push('=');
+/// Retry a registry operation on transient registry/network failures.
+///
+/// Permanent errors (auth, not found, manifest unknown) fail immediately;
+/// anything not recognized as transient is also not retried.
+fn with_pull_retry<T>(
+    config: crate::retry::RetryConfig,
+    op_name: &str,
+    operation: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    use crate::retry::{is_permanent_error, is_transient_network_error, retry_with_backoff};
+
+    retry_with_backoff(config, op_name, operation, |e| {
+        if matches!(e, StorageError::PullCancelled { .. }) {
+            return false;
         }
-
-        if chunk.len() > 2 {
-            result.push(ALPHABET[b2 & 0x3f] as char);
-        } else {
-            result.push('=');
+        let error_msg = e.to_string();
+        // Don't retry permanent errors
+        if is_permanent_error(&error_msg) {
+            return false;
         }
-    }
-
-    result
-}
-
-/// Set up Docker auth configuration for crane commands.
-///
-/// Creates a temporary directory with a Docker config.json file containing
-/// registry credentials. The returned TempDir must be kept alive for the
-/// duration of the command execution.
-///
-/// Returns `Ok(None)` if no auth is provided.
-fn setup_docker_auth(
-    image: &str,
-    auth: Option<&RegistryAuth>,
-) -> Result<Option<tempfile::TempDir>> {
-    let Some(a) = auth else {
-        return Ok(None);
-    };
-
-    let registry = extract_registry_from_image(image);
-
-    let temp_dir = tempfile::TempDir::new().map_err(|e| {
-        StorageError::new(format!("failed to create temp directory for auth: {}", e))
-    })?;
-
-    let auth_b64 = base64_encode(&format!("{}:{}", a.username, a.password));
-    let config_json = format!(
-        r#"{{"auths":{{"{}":{{"auth":"{}"}}}}}}"#,
-        registry, auth_b64
-    );
-
-    let config_path = temp_dir.path().join("config.json");
-    std::fs::write(&config_path, &config_json)
-        .map_err(|e| StorageError::new(format!("failed to write docker auth config: {}", e)))?;
-
-    debug!(
-        registry = %registry,
-        username = %a.username,
-        "using registry credentials via docker config"
-    );
-
-    Ok(Some(temp_dir))
+        // Retry transient network errors
+        is_transient_network_error(&error_msg)
+    })
 }
 
-/// Run a crane command with the given operation.
+/// Download a layer blob and extract it into `layer_dir`.
 ///
-/// If auth is provided, creates a temporary Docker config for crane to use.
-/// Includes retry logic for transient network failures.
-fn run_crane(
-    operation: &str,
+/// Retries on transient failures. The layer directory is recreated before
+/// each attempt so a partial extraction never survives a retry.
+fn extract_layer(
+    puller: &dyn OciPuller,
     image: &str,
+    layer_digest: &str,
+    layer_dir: &Path,
     oci_platform: Option<&str>,
     auth: Option<&RegistryAuth>,
-) -> Result<String> {
-    use crate::retry::{
-        is_permanent_error, is_transient_network_error, retry_with_backoff, RetryConfig,
-    };
-
-    let op_name = format!("crane {}", operation);
-
-    retry_with_backoff(
-        RetryConfig::for_network(),
-        &op_name,
-        || run_crane_once(operation, image, oci_platform, auth),
-        |e| {
-            let error_msg = e.to_string();
-            // Don't retry permanent errors
-            if is_permanent_error(&error_msg) {
-                return false;
-            }
-            // Retry transient network errors
-            is_transient_network_error(&error_msg)
+    cancel: &PullCancel,
+) -> Result<()> {
+    with_pull_retry(
+        crate::retry::RetryConfig::for_network(),
+        &format!("fetch blob {}", layer_digest),
+        || {
+            extract_layer_once(
+                puller,
+                image,
+                layer_digest,
+                layer_dir,
+                oci_platform,
+                auth,
+                cancel,
+            )
         },
     )
 }
 
-/// Execute a single crane command attempt.
-fn run_crane_once(
-    operation: &str,
+/// Execute a single download-and-extract attempt, streaming the blob
+/// straight into `tar -x`.
+///
+/// Cancelling fails the next read of the blob, which drops it (stopping the
+/// puller's child) and closes tar's stdin.
+fn extract_layer_once(
+    puller: &dyn OciPuller,
     image: &str,
+    layer_digest: &str,
+    layer_dir: &Path,
     oci_platform: Option<&str>,
     auth: Option<&RegistryAuth>,
-) -> Result<String> {
-    let mut cmd = Command::new("crane");
-    cmd.arg(operation).arg(image);
-
-    if let Some(p) = oci_platform {
-        cmd.arg("--platform").arg(p);
+    cancel: &PullCancel,
+) -> Result<()> {
+    if layer_dir.exists() {
+        std::fs::remove_dir_all(layer_dir).map_err(|e| StorageError::RemoveDir {
+            path: layer_dir.display().to_string(),
+            cause: e.to_string(),
+        })?;
     }
+    std::fs::create_dir_all(layer_dir)?;
+
+    let mut blob =
+        cancel.guard(puller.blob_stream(image, layer_digest, oci_platform, auth, cancel)?);
 
-    // Set up auth if provided (temp_dir must stay alive until command completes)
-    let _temp_dir = setup_docker_auth(image, auth)?;
-    if let Some(ref td) = _temp_dir {
-        cmd.env("DOCKER_CONFIG", td.path());
+    // Direct process spawn (no shell to avoid injection risks)
+    let mut tar = Command::new("tar")
+        .args(["--no-same-owner", "-xzf", "-", "-C"])
+        .arg(layer_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| StorageError::new(tools::TAR.spawn_error(&e)))?;
+    let mut tar_stdin = tar
+        .stdin
+        .take()
+        .ok_or_else(|| StorageError::new("failed to capture tar stdin".to_string()))?;
+
+    let copied = std::io::copy(&mut blob, &mut tar_stdin);
+    drop(tar_stdin);
+    // tar can stop reading before the end of the blob; read the rest so the
+    // puller still checks it against its digest
+    let copied = copied.or_else(|e| match e.kind() {
+        std::io::ErrorKind::BrokenPipe => {
+            std::io::copy(&mut blob, &mut std::io::sink()).and(Err(e))
+        }
+        _ => Err(e),
+    });
+    drop(blob);
+    let tar_output = tar
+        .wait_with_output()
+        .map_err(|e| StorageError::new(format!("failed to wait for tar: {}", e)))?;
+
+    if cancel.is_cancelled() {
+        if let Err(e) = std::fs::remove_dir_all(layer_dir) {
+            warn!(layer = %layer_digest, error = %e, "failed to clean up layer directory after cancel");
+        }
+        return Err(StorageError::PullCancelled {
+            image: image.to_string(),
+        });
     }
 
-    let output = cmd.output()?;
+    // A broken pipe means tar exited early; its own error explains why
+    if let Err(e) = copied.as_ref() {
+        if e.kind() != std::io::ErrorKind::BrokenPipe {
+            if let Err(e) = std::fs::remove_dir_all(layer_dir) {
+                warn!(layer = %layer_digest, error = %e, "failed to clean up layer directory after download failure");
+            }
+            return Err(StorageError::new(format!(
+                "failed to download layer {}: {}",
+                layer_digest, e
+            )));
+        }
+    }
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
+    if !tar_output.status.success() {
+        if let Err(e) = std::fs::remove_dir_all(layer_dir) {
+            warn!(layer = %layer_digest, error = %e, "failed to clean up layer directory after tar failure");
+        }
+        let stderr = String::from_utf8_lossy(&tar_output.stderr);
         return Err(StorageError::new(format!(
-            "crane {} failed: {}",
-            operation, stderr
+            "tar extraction failed for layer {}: {}",
+            layer_digest, stderr
         )));
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
-}
-
-/// Run crane manifest command.
-fn crane_manifest(
-    image: &str,
-    oci_platform: Option<&str>,
-    auth: Option<&RegistryAuth>,
-) -> Result<String> {
-    run_crane("manifest", image, oci_platform, auth)
-}
-
-/// Run crane config command.
-fn crane_config(
-    image: &str,
-    oci_platform: Option<&str>,
-    auth: Option<&RegistryAuth>,
-) -> Result<String> {
-    run_crane("config", image, oci_platform, auth)
+    Ok(())
 }
 
 /// Sanitize image name for use as filename.
@@ -2138,9 +4523,126 @@ fn unsanitize_image_name(name: &str) -> String {
     name.replacen('_', "/", 1).replacen('_', ":", 1)
 }
 
+/// Estimate the disk space needed to extract the uncached layers of an image.
+///
+/// Manifests only record compressed layer sizes, so the total is scaled by
+/// `multiplier` as a rough upper bound on the decompressed size.
+fn estimate_pull_space(root: &Path, manifest_json: &serde_json::Value, multiplier: f64) -> u64 {
+    let compressed: u64 = manifest_json["layers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|layer| {
+            let digest = layer["digest"].as_str().unwrap_or_default();
+            let layer_id = digest.strip_prefix("sha256:").unwrap_or(digest);
+            !is_layer_cached(&root.join(LAYERS_DIR).join(layer_id))
+        })
+        .filter_map(|layer| layer["size"].as_u64())
+        .sum();
+    (compressed as f64 * multiplier) as u64
+}
+
+/// Multiplier from compressed layer size to the space reserved for its
+/// extraction. Overridable with `SMOLVM_PULL_SPACE_MULTIPLIER`.
+fn pull_space_multiplier() -> f64 {
+    std::env::var(PULL_SPACE_MULTIPLIER_ENV)
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|m| m.is_finite() && *m >= 1.0)
+        .unwrap_or(DEFAULT_PULL_SPACE_MULTIPLIER)
+}
+
+/// Fail with `InsufficientSpace` if `required` bytes don't fit in `available`.
+fn check_free_space(required: u64, available: u64) -> Result<()> {
+    if required > available {
+        return Err(StorageError::InsufficientSpace {
+            required,
+            available,
+        });
+    }
+    Ok(())
+}
+
+/// Fail with `InsufficientInodes` if almost no inodes are free, and return
+/// a warning if they are running low. Filesystems that don't report inodes
+/// (`total` of zero) always pass.
+fn check_free_inodes(free: u64, total: u64) -> Result<Option<String>> {
+    if total == 0 {
+        return Ok(None);
+    }
+    if free < MIN_FREE_INODES {
+        return Err(StorageError::InsufficientInodes { free, total });
+    }
+    if (free as f64) < total as f64 * LOW_INODE_FRACTION {
+        return Ok(Some(format!(
+            "storage disk is low on inodes: {} of {} free",
+            free, total
+        )));
+    }
+    Ok(None)
+}
+
+/// Path where the manifest for `image` is stored.
+///
+/// Manifests are keyed by the canonical reference, so `alpine` and
+/// `docker.io/library/alpine:latest` share one entry and the reference can be
+/// recovered from the filename. References that don't parse fall back to the
+/// legacy sanitized name.
+fn manifest_path(root: &Path, image: &str) -> PathBuf {
+    match ImageRef::parse(image) {
+        Ok(image_ref) => root
+            .join(MANIFESTS_DIR)
+            .join(image_ref.storage_key() + ".json"),
+        Err(_) => legacy_manifest_path(root, image),
+    }
+}
+
+/// Manifest path used before references were canonicalized.
+fn legacy_manifest_path(root: &Path, image: &str) -> PathBuf {
+    root.join(MANIFESTS_DIR)
+        .join(sanitize_image_name(image) + ".json")
+}
+
+/// Find the stored manifest for `image`, falling back to the legacy name for
+/// images pulled before references were canonicalized.
+fn find_manifest(root: &Path, image: &str) -> PathBuf {
+    let path = manifest_path(root, image);
+    if path.exists() {
+        return path;
+    }
+    let legacy = legacy_manifest_path(root, image);
+    if legacy.exists() {
+        legacy
+    } else {
+        path
+    }
+}
+
+/// Contents of a stored manifest whose content digest is `digest`, if any.
+fn cached_manifest_with_digest(root: &Path, digest: &str) -> Option<String> {
+    let hex = digest.strip_prefix("sha256:")?;
+    let entries = std::fs::read_dir(root.join(MANIFESTS_DIR)).ok()?;
+    entries.flatten().find_map(|entry| {
+        let content = std::fs::read(entry.path()).ok()?;
+        if format!("{:x}", Sha256::digest(&content)) != hex {
+            return None;
+        }
+        String::from_utf8(content).ok()
+    })
+}
+
+/// Block and inode usage of a filesystem, from statvfs.
+#[derive(Debug, Default, Clone, Copy)]
+struct DiskUsage {
+    total_bytes: u64,
+    used_bytes: u64,
+    total_inodes: u64,
+    free_inodes: u64,
+}
+
 /// Get disk usage for a path.
 #[allow(unused_variables)] // path is used only on Linux
-fn get_disk_usage(path: &Path) -> Result<(u64, u64)> {
+fn get_disk_usage(path: &Path) -> Result<DiskUsage> {
     // Use statvfs on Linux
     #[cfg(target_os = "linux")]
     {
@@ -2162,15 +4664,19 @@ fn get_disk_usage(path: &Path) -> Result<(u64, u64)> {
             let stat = stat.assume_init();
             let total = stat.f_blocks * stat.f_frsize;
             let free = stat.f_bfree * stat.f_frsize;
-            let used = total - free;
 
-            Ok((total as u64, used as u64))
+            Ok(DiskUsage {
+                total_bytes: total,
+                used_bytes: total - free,
+                total_inodes: stat.f_files,
+                free_inodes: stat.f_ffree,
+            })
         }
     }
 
     #[cfg(not(target_os = "linux"))]
     {
-        Ok((0, 0))
+        Ok(DiskUsage::default())
     }
 }
 
@@ -2183,27 +4689,130 @@ fn count_entries(path: &Path) -> Result<usize> {
     Ok(std::fs::read_dir(path)?.count())
 }
 
-/// Convert an OCI platform string to its architecture component.
+/// The process argv for a `Run` request.
 ///
-/// # Examples
-/// - "linux/arm64" -> "arm64"
-/// - "linux/amd64" -> "amd64"
-/// - "linux/arm64/v8" -> "arm64"
-fn oci_platform_to_arch(oci_platform: &str) -> String {
-    // OCI platform format is "os/arch" or "os/arch/variant"
-    // We want just the arch part
-    let parts: Vec<&str> = oci_platform.split('/').collect();
-    if parts.len() >= 2 {
-        parts[1].to_string()
+/// With no command and no entrypoint override, this is the cached image's
+/// default: its config's `Entrypoint` followed by `Cmd`. Otherwise it is
+/// [`process_args`](crate::oci::process_args). Fails if the argv is empty
+/// or has an argument containing a NUL byte.
+pub fn run_argv(
+    image: &str,
+    entrypoint: Option<&[String]>,
+    command: &[String],
+) -> Result<Vec<String>> {
+    run_argv_at(Path::new(STORAGE_ROOT), image, entrypoint, command)
+}
+
+fn run_argv_at(
+    root: &Path,
+    image: &str,
+    entrypoint: Option<&[String]>,
+    command: &[String],
+) -> Result<Vec<String>> {
+    let invalid = |reason: String| StorageError::ValidationFailed {
+        context: "command".to_string(),
+        reason,
+    };
+    let argv = if entrypoint.is_none() && command.is_empty() {
+        let info = image_info_at(root, image, &HashMap::new())?;
+        let argv: Vec<String> = info
+            .map(|info| info.entrypoint.into_iter().chain(info.cmd).collect())
+            .unwrap_or_default();
+        if argv.is_empty() {
+            return Err(invalid(format!(
+                "no command given and image '{}' has no entrypoint or cmd",
+                image
+            )));
+        }
+        argv
     } else {
-        // Fallback: return as-is if not in expected format
-        oci_platform.to_string()
-    }
+        crate::oci::process_args(entrypoint, command)
+    };
+    crate::oci::validate_command(&argv).map_err(invalid)?;
+    Ok(argv)
 }
 
-/// Calculate directory size recursively.
-fn dir_size(path: &Path) -> Result<u64> {
-    let mut size = 0;
+/// Check that the cached `image` can run as `platform`, or on this VM.
+///
+/// Without a platform the image must be one a pull would have picked (see
+/// [`platform::platform_candidates`]): the VM's architecture, or amd64 on
+/// arm64 when Rosetta is registered with binfmt_misc. With one, the image
+/// must match it and no emulation check is made. Images
+/// that aren't cached, or don't record an architecture, are left for the run
+/// itself to deal with.
+pub fn check_run_platform(image: &str, platform: Option<&str>) -> Result<()> {
+    let Some(image_arch) = image_architecture_at(Path::new(STORAGE_ROOT), image)? else {
+        return Ok(());
+    };
+    let rosetta = Path::new(crate::paths::ROSETTA_BINFMT_ENTRY).exists();
+    check_image_arch(&image_arch, platform, native_arch(), rosetta)
+}
+
+/// The checks behind [`check_run_platform`].
+fn check_image_arch(
+    image_arch: &str,
+    platform: Option<&str>,
+    vm_arch: &str,
+    rosetta: bool,
+) -> Result<()> {
+    let candidates = platform::platform_candidates(platform, vm_arch, rosetta);
+    if candidates
+        .iter()
+        .any(|c| oci_platform_to_arch(c) == image_arch)
+    {
+        return Ok(());
+    }
+    Err(StorageError::ArchMismatch {
+        image_arch: image_arch.to_string(),
+        target_arch: oci_platform_to_arch(&candidates[0]),
+    })
+}
+
+/// The architecture recorded in the config of the cached `image`, if any.
+fn image_architecture_at(root: &Path, image: &str) -> Result<Option<String>> {
+    let manifest_path = find_manifest(root, image);
+    if !manifest_path.exists() {
+        return Ok(None);
+    }
+    let manifest = std::fs::read_to_string(&manifest_path)?;
+    let manifest_json: serde_json::Value =
+        serde_json::from_str(&manifest).map_err(|e| StorageError::parse_error("manifest", e))?;
+    let Some(config_digest) = manifest_json["config"]["digest"].as_str() else {
+        return Ok(None);
+    };
+    let config_id = config_digest
+        .strip_prefix("sha256:")
+        .unwrap_or(config_digest);
+    let config_path = root.join(CONFIGS_DIR).join(format!("{}.json", config_id));
+    let Ok(config) = std::fs::read_to_string(&config_path) else {
+        return Ok(None);
+    };
+    let config_json: serde_json::Value =
+        serde_json::from_str(&config).map_err(|e| StorageError::parse_error("config", e))?;
+    Ok(config_json["architecture"].as_str().map(String::from))
+}
+
+/// Convert an OCI platform string to its architecture component.
+///
+/// # Examples
+/// - "linux/arm64" -> "arm64"
+/// - "linux/amd64" -> "amd64"
+/// - "linux/arm64/v8" -> "arm64"
+fn oci_platform_to_arch(oci_platform: &str) -> String {
+    // OCI platform format is "os/arch" or "os/arch/variant"
+    // We want just the arch part
+    let parts: Vec<&str> = oci_platform.split('/').collect();
+    if parts.len() >= 2 {
+        parts[1].to_string()
+    } else {
+        // Fallback: return as-is if not in expected format
+        oci_platform.to_string()
+    }
+}
+
+/// Calculate directory size recursively.
+pub fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
 
     if path.is_file() {
         return Ok(std::fs::metadata(path)?.len());
@@ -2226,6 +4835,9 @@ fn dir_size(path: &Path) -> Result<u64> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::puller::CranePuller;
+    use smolvm_protocol::test_util::fake_tool;
+    use std::io::Read;
 
     #[test]
     fn test_oci_platform_to_arch_linux_arm64() {
@@ -2250,6 +4862,285 @@ mod tests {
         assert_eq!(oci_platform_to_arch("unknown"), "unknown");
     }
 
+    #[test]
+    fn test_disk_usage_lists_shared_layer_references() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+
+        let write_manifest = |image: &str, layers: &[&str]| {
+            let path = manifest_path(root, image);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let layers: Vec<_> = layers
+                .iter()
+                .map(|id| serde_json::json!({ "digest": format!("sha256:{}", id) }))
+                .collect();
+            std::fs::write(path, serde_json::json!({ "layers": layers }).to_string()).unwrap();
+        };
+        let write_layer = |id: &str, size: usize| {
+            let dir = root.join(LAYERS_DIR).join(id);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("data"), vec![0u8; size]).unwrap();
+        };
+
+        write_layer("base", 4096);
+        write_layer("app", 1024);
+        write_layer("orphan", 16);
+        write_manifest("alpine:3.19", &["base"]);
+        write_manifest("myapp:1.0", &["base", "app"]);
+
+        let usage = disk_usage_at(root).unwrap();
+        let digests: Vec<_> = usage.iter().map(|l| l.digest.as_str()).collect();
+        assert_eq!(digests, ["sha256:base", "sha256:app", "sha256:orphan"]);
+
+        assert_eq!(usage[0].size_bytes, 4096);
+        assert_eq!(
+            usage[0].referenced_by,
+            [
+                "docker.io/library/alpine:3.19",
+                "docker.io/library/myapp:1.0"
+            ]
+        );
+        assert_eq!(usage[1].referenced_by, ["docker.io/library/myapp:1.0"]);
+        assert!(usage[2].referenced_by.is_empty());
+    }
+
+    #[test]
+    fn test_tags_sharing_a_digest() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join(MANIFESTS_DIR)).unwrap();
+        std::fs::create_dir_all(root.join(CONFIGS_DIR)).unwrap();
+        std::fs::create_dir_all(root.join(LAYERS_DIR).join("base")).unwrap();
+
+        let write_image = |image: &str, config_id: &str| {
+            let manifest = serde_json::json!({
+                "config": { "digest": format!("sha256:{}", config_id) },
+                "layers": [{ "digest": "sha256:base" }],
+            });
+            std::fs::write(manifest_path(root, image), manifest.to_string()).unwrap();
+            std::fs::write(
+                root.join(CONFIGS_DIR).join(format!("{}.json", config_id)),
+                r#"{"architecture": "arm64", "os": "linux"}"#,
+            )
+            .unwrap();
+        };
+        write_image("alpine:3.19", "aaa");
+        write_image("alpine:latest", "aaa");
+        write_image("busybox", "bbb");
+
+        let both = [
+            "docker.io/library/alpine:3.19",
+            "docker.io/library/alpine:latest",
+        ];
+
+        // Both tags are found by digest, each listing the other
+        let found = query_digest_at(root, "sha256:aaa").unwrap();
+        let mut references: Vec<_> = found.iter().map(|i| i.reference.as_str()).collect();
+        references.sort();
+        assert_eq!(references, both);
+        assert!(found.iter().all(|i| i.tags == both));
+
+        // The algorithm prefix is optional
+        assert_eq!(query_digest_at(root, "aaa").unwrap().len(), 2);
+        assert!(query_digest_at(root, "sha256:ccc").unwrap().is_empty());
+
+        // Tags are filled in for plain queries and listings too
+        let refs = digest_references(root).unwrap();
+        let info = image_info_at(root, "alpine:3.19", &refs).unwrap().unwrap();
+        assert_eq!(info.tags, both);
+        for info in list_images_at(root).unwrap() {
+            let expected = if info.digest == "sha256:aaa" {
+                both.len()
+            } else {
+                1
+            };
+            assert_eq!(info.tags.len(), expected, "{}", info.reference);
+        }
+    }
+
+    #[test]
+    fn test_tag_shares_storage_with_source() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join(MANIFESTS_DIR)).unwrap();
+        std::fs::create_dir_all(root.join(CONFIGS_DIR)).unwrap();
+        std::fs::create_dir_all(root.join(LAYERS_DIR).join("base")).unwrap();
+        let manifest = serde_json::json!({
+            "config": { "digest": "sha256:cfg" },
+            "layers": [{ "digest": "sha256:base" }],
+        });
+        std::fs::write(manifest_path(root, "myimage:dev"), manifest.to_string()).unwrap();
+        std::fs::write(root.join(CONFIGS_DIR).join("cfg.json"), "{}").unwrap();
+
+        let tagged = tag_image_at(root, "myimage:dev", "myimage:stable")
+            .unwrap()
+            .unwrap();
+        assert_eq!(tagged.reference, "myimage:stable");
+        assert_eq!(tagged.digest, "sha256:cfg");
+        assert_eq!(
+            tagged.tags,
+            [
+                "docker.io/library/myimage:dev",
+                "docker.io/library/myimage:stable"
+            ]
+        );
+
+        // The new reference is queryable and uses the same layers
+        let refs = digest_references(root).unwrap();
+        let stable = image_info_at(root, "myimage:stable", &refs)
+            .unwrap()
+            .unwrap();
+        let dev = image_info_at(root, "myimage:dev", &refs).unwrap().unwrap();
+        assert_eq!(stable.layers, dev.layers);
+        assert_eq!(std::fs::read_dir(root.join(LAYERS_DIR)).unwrap().count(), 1);
+
+        // Missing sources and invalid targets are rejected
+        assert!(tag_image_at(root, "myimage:missing", "myimage:x")
+            .unwrap()
+            .is_none());
+        assert!(tag_image_at(root, "myimage:dev", "Bad Ref!").is_err());
+    }
+
+    fn cached_image_with_config(root: &Path, image: &str, config: serde_json::Value) {
+        for dir in [MANIFESTS_DIR, CONFIGS_DIR] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::create_dir_all(root.join(LAYERS_DIR).join("base")).unwrap();
+        let manifest = serde_json::json!({
+            "config": { "digest": "sha256:cfg" },
+            "layers": [{ "digest": "sha256:base" }],
+        });
+        std::fs::write(manifest_path(root, image), manifest.to_string()).unwrap();
+        std::fs::write(root.join(CONFIGS_DIR).join("cfg.json"), config.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_empty_run_command_uses_image_default() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        cached_image_with_config(
+            root,
+            "nginx:1.27",
+            serde_json::json!({
+                "config": {
+                    "Entrypoint": ["/docker-entrypoint.sh"],
+                    "Cmd": ["nginx", "-g", "daemon off;"],
+                }
+            }),
+        );
+        let strings = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            run_argv_at(root, "nginx:1.27", None, &[]).unwrap(),
+            ["/docker-entrypoint.sh", "nginx", "-g", "daemon off;"]
+        );
+        // A given command is used as is
+        assert_eq!(
+            run_argv_at(root, "nginx:1.27", None, &strings(&["ls"])).unwrap(),
+            ["ls"]
+        );
+
+        // Nothing to fall back to: an image without defaults, a cleared
+        // entrypoint, or an image that isn't cached
+        cached_image_with_config(root, "scratch:app", serde_json::json!({ "config": {} }));
+        let err = run_argv_at(root, "scratch:app", None, &[]).unwrap_err();
+        assert!(
+            matches!(err, StorageError::ValidationFailed { .. }),
+            "{}",
+            err
+        );
+        assert!(err.to_string().contains("no entrypoint or cmd"), "{}", err);
+        assert!(run_argv_at(root, "nginx:1.27", Some(&[]), &[]).is_err());
+        assert!(run_argv_at(root, "missing:1", None, &[]).is_err());
+    }
+
+    #[test]
+    fn test_run_command_with_nul_byte_is_rejected() {
+        let temp = tempfile::tempdir().unwrap();
+        let args = vec!["echo".to_string(), "a\0b".to_string()];
+
+        let err = run_argv_at(temp.path(), "alpine", None, &args).unwrap_err();
+        assert!(
+            matches!(err, StorageError::ValidationFailed { .. }),
+            "{}",
+            err
+        );
+        assert!(
+            err.to_string().contains("argument 1 contains a NUL byte"),
+            "{}",
+            err
+        );
+
+        let entrypoint = vec!["/bin/s\0h".to_string()];
+        assert!(run_argv_at(temp.path(), "alpine", Some(&entrypoint), &[]).is_err());
+    }
+
+    #[test]
+    fn test_oci_config_fields() {
+        let config_json: serde_json::Value = serde_json::from_str(
+            r#"{
+                "architecture": "arm64",
+                "os": "linux",
+                "config": {
+                    "Env": ["PATH=/usr/local/bin:/usr/bin", "LANG=C.UTF-8"],
+                    "Entrypoint": ["/docker-entrypoint.sh"],
+                    "Cmd": ["nginx", "-g", "daemon off;"],
+                    "WorkingDir": "",
+                    "Labels": {
+                        "maintainer": "NGINX Docker Maintainers",
+                        "org.opencontainers.image.version": "1.27",
+                        "bogus": 3
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let oci_config = &config_json["config"];
+
+        assert_eq!(
+            json_string_array(oci_config, "Env"),
+            ["PATH=/usr/local/bin:/usr/bin", "LANG=C.UTF-8"]
+        );
+        assert_eq!(
+            json_string_array(oci_config, "Cmd"),
+            ["nginx", "-g", "daemon off;"]
+        );
+        let labels = json_string_map(oci_config, "Labels");
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels["org.opencontainers.image.version"], "1.27");
+
+        // Missing or null fields are empty
+        assert!(json_string_array(oci_config, "Volumes").is_empty());
+        assert!(json_string_map(&serde_json::json!({ "Labels": null }), "Labels").is_empty());
+    }
+
+    #[test]
+    fn test_format_refuses_formatted_storage_without_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("storage");
+
+        format_at(&root, &[], false).unwrap();
+        assert!(root.join(".smolvm_formatted").exists());
+        assert!(root.join(LAYERS_DIR).is_dir());
+
+        let err = format_at(&root, &[], false).unwrap_err();
+        assert!(matches!(err, StorageError::AlreadyFormatted { .. }));
+    }
+
+    #[test]
+    fn test_format_force_reformats() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("storage");
+        format_at(&root, &[], false).unwrap();
+        std::fs::remove_dir(root.join(MANIFESTS_DIR)).unwrap();
+
+        let extra = [(dir.path().join("run"), "run")];
+        format_at(&root, &extra, true).unwrap();
+        assert!(root.join(MANIFESTS_DIR).is_dir());
+        assert!(dir.path().join("run").is_dir());
+        assert!(root.join(".smolvm_formatted").exists());
+    }
+
     #[test]
     fn test_sanitize_image_name() {
         assert_eq!(sanitize_image_name("alpine:latest"), "alpine_latest");
@@ -2262,4 +5153,1703 @@ mod tests {
             "ghcr.io_owner_repo_sha256_abc123"
         );
     }
+
+    #[test]
+    fn test_load_archive_limit_leaves_room_to_unpack() {
+        let available = 10 * 1024 * 1024 * 1024;
+        let limit = load_archive_limit(available);
+        assert_eq!(limit * 2, available);
+        assert!(check_free_space(limit * 2, available).is_ok());
+        assert!(check_free_space((limit + 1) * 2, available).is_err());
+    }
+
+    #[test]
+    fn test_pull_preflight_rejects_image_larger_than_free_space() {
+        let root = tempfile::tempdir().unwrap();
+        let manifest = serde_json::json!({
+            "layers": [
+                {"digest": "sha256:aaa", "size": 400 * 1024 * 1024},
+                {"digest": "sha256:bbb", "size": 100 * 1024 * 1024},
+            ]
+        });
+
+        let required = estimate_pull_space(root.path(), &manifest, 3.0);
+        assert_eq!(required, 1500 * 1024 * 1024);
+
+        // A nearly full disk rejects the pull with both numbers in the message
+        let err = check_free_space(required, 64 * 1024 * 1024).unwrap_err();
+        assert!(matches!(err, StorageError::InsufficientSpace { .. }));
+        let msg = err.to_string();
+        assert!(msg.contains("1500 MiB"), "{}", msg);
+        assert!(msg.contains("64 MiB"), "{}", msg);
+
+        assert!(check_free_space(required, 2048 * 1024 * 1024).is_ok());
+    }
+
+    #[test]
+    fn test_pull_preflight_checks_free_inodes() {
+        // Plenty of inodes: nothing to report
+        assert_eq!(check_free_inodes(500_000, 1_000_000).unwrap(), None);
+
+        // Under 5% free: the pull goes ahead with a warning
+        let warning = check_free_inodes(20_000, 1_000_000).unwrap().unwrap();
+        assert!(warning.contains("20000 of 1000000"), "{}", warning);
+
+        // Nearly none left: refuse rather than fail mid-extraction
+        let err = check_free_inodes(100, 1_000_000).unwrap_err();
+        assert!(matches!(
+            err,
+            StorageError::InsufficientInodes { free: 100, .. }
+        ));
+
+        // Filesystems without inode counts are not checked
+        assert_eq!(check_free_inodes(0, 0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_pull_preflight_skips_cached_layers() {
+        let root = tempfile::tempdir().unwrap();
+        let cached = root.path().join(LAYERS_DIR).join("aaa");
+        std::fs::create_dir_all(&cached).unwrap();
+        std::fs::write(cached.join("file"), "x").unwrap();
+
+        let manifest = serde_json::json!({
+            "layers": [
+                {"digest": "sha256:aaa", "size": 1000},
+                {"digest": "sha256:bbb", "size": 10},
+            ]
+        });
+        assert_eq!(estimate_pull_space(root.path(), &manifest, 2.0), 20);
+    }
+
+    #[test]
+    fn test_manifest_path_is_canonical() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join(MANIFESTS_DIR)).unwrap();
+
+        // Equivalent references share one manifest
+        let short = manifest_path(root.path(), "alpine");
+        assert_eq!(
+            short,
+            manifest_path(root.path(), "docker.io/library/alpine:latest")
+        );
+
+        // The reference is recoverable from the filename
+        let stem = short.file_stem().unwrap().to_str().unwrap();
+        assert_eq!(
+            ImageRef::from_storage_key(stem).unwrap().to_string(),
+            "docker.io/library/alpine:latest"
+        );
+    }
+
+    #[test]
+    fn test_find_manifest_falls_back_to_legacy_name() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(root.path().join(MANIFESTS_DIR)).unwrap();
+
+        let legacy = legacy_manifest_path(root.path(), "alpine:3.18");
+        std::fs::write(&legacy, "{}").unwrap();
+        assert_eq!(find_manifest(root.path(), "alpine:3.18"), legacy);
+
+        // Once a canonical manifest exists it wins
+        let canonical = manifest_path(root.path(), "alpine:3.18");
+        std::fs::write(&canonical, "{}").unwrap();
+        assert_eq!(find_manifest(root.path(), "alpine:3.18"), canonical);
+    }
+
+    #[test]
+    fn test_run_workload_id_persistent_is_stable() {
+        // Two persistent runs of the same image must land in the same
+        // overlay so the upper layer is retained between them.
+        let first = run_workload_id("alpine:latest", false, false);
+        let second = run_workload_id("alpine:latest", false, false);
+        assert_eq!(first, "persistent-alpine_latest");
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_run_workload_id_ephemeral_is_unique() {
+        let first = run_workload_id("alpine:latest", true, false);
+        let second = run_workload_id("alpine:latest", true, false);
+        assert!(first.starts_with("ephemeral-"));
+        assert_ne!(first, second);
+        assert_ne!(first, run_workload_id("alpine:latest", false, false));
+    }
+
+    #[test]
+    fn test_persistent_overlay_lru_eviction() {
+        let dir = tempfile::tempdir().unwrap();
+        let overlays = dir.path();
+        let evict = |id: &str| -> Result<()> {
+            std::fs::remove_dir_all(overlays.join(id))?;
+            Ok(())
+        };
+
+        // Prepare three overlays, oldest first
+        for (i, id) in ["persistent-a", "persistent-b", "persistent-c"]
+            .iter()
+            .enumerate()
+        {
+            std::fs::create_dir_all(overlays.join(id)).unwrap();
+            let evicted = track_persistent_overlay(overlays, id, 3, i as u64, evict).unwrap();
+            assert!(evicted.is_empty());
+        }
+
+        // "a" is the oldest but backs a running command
+        let busy = OverlayLease::acquire("persistent-a");
+        let evicted = track_persistent_overlay(overlays, "persistent-d", 3, 10, evict).unwrap();
+        assert_eq!(evicted, ["persistent-b"]);
+        assert!(overlays.join("persistent-a").exists());
+        assert!(!overlays.join("persistent-b").exists());
+
+        let index = OverlayIndex::load(&overlays.join(overlay_lru::INDEX_FILE));
+        assert_eq!(index.last_used("persistent-b"), None);
+        assert_eq!(index.last_used("persistent-d"), Some(10));
+
+        // Reusing an overlay refreshes it, so "c" outlives "a" once idle
+        std::fs::create_dir_all(overlays.join("persistent-d")).unwrap();
+        track_persistent_overlay(overlays, "persistent-c", 3, 11, evict).unwrap();
+        drop(busy);
+        let evicted = track_persistent_overlay(overlays, "persistent-e", 3, 12, evict).unwrap();
+        assert_eq!(evicted, ["persistent-a"]);
+    }
+
+    #[test]
+    fn test_cleanup_run_overlay_missing_dir() {
+        // Cleaning up an ephemeral overlay that was never created (e.g. the
+        // run failed before the overlay was prepared) must succeed.
+        let workload_id = run_workload_id("alpine:latest", true, false);
+        assert!(cleanup_run_overlay(&workload_id).is_ok());
+        assert!(!Path::new(STORAGE_ROOT)
+            .join(OVERLAYS_DIR)
+            .join(&workload_id)
+            .exists());
+    }
+
+    #[test]
+    fn test_refresh_drops_the_read_only_mount() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let removed = std::cell::RefCell::new(Vec::new());
+        let remove = |id: &str| {
+            removed.borrow_mut().push(id.to_string());
+            Ok(())
+        };
+
+        // Nothing mounted, nothing to drop
+        drop_read_only_overlay_at(root, "alpine", |_| false, remove);
+        assert!(removed.borrow().is_empty());
+
+        let workload_id = run_workload_id("alpine", false, true);
+        std::fs::create_dir_all(root.join(OVERLAYS_DIR).join(&workload_id)).unwrap();
+        drop_read_only_overlay_at(root, "alpine", |id| id == workload_id, remove);
+        assert!(removed.borrow().is_empty(), "a mount in use is kept");
+
+        drop_read_only_overlay_at(root, "alpine", |_| false, remove);
+        assert_eq!(*removed.borrow(), vec![workload_id]);
+    }
+
+    #[test]
+    fn test_remove_image_refuses_images_in_use() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        cached_image_with_config(root, "alpine:latest", serde_json::json!({}));
+        let overlay = format!(
+            "{}{}",
+            PERSISTENT_OVERLAY_PREFIX,
+            sanitize_image_name("alpine")
+        );
+        std::fs::create_dir_all(root.join(OVERLAYS_DIR).join(&overlay)).unwrap();
+        let removed = std::cell::RefCell::new(Vec::new());
+        let remove = |id: &str| {
+            removed.borrow_mut().push(id.to_string());
+            Ok(())
+        };
+
+        let err = remove_image_at(
+            root,
+            "alpine",
+            &["alpine:latest".to_string()],
+            |_| false,
+            remove,
+        )
+        .unwrap_err();
+        assert!(matches!(err, StorageError::ImageInUse { .. }));
+        let err = remove_image_at(root, "alpine", &[], |id| id == overlay, remove).unwrap_err();
+        assert!(matches!(err, StorageError::ImageInUse { .. }));
+        assert!(removed.borrow().is_empty());
+        assert!(manifest_path(root, "alpine").exists());
+
+        assert!(remove_image_at(root, "alpine", &[], |_| false, remove).unwrap());
+        assert_eq!(*removed.borrow(), vec![overlay]);
+        assert!(!manifest_path(root, "alpine").exists());
+        assert!(!remove_image_at(root, "alpine", &[], |_| false, remove).unwrap());
+    }
+
+    #[test]
+    fn test_prune_overlays_removes_only_orphans() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for id in [
+            "container-live",
+            "container-gone",
+            "ephemeral-busy",
+            "ephemeral-stale",
+            "persistent-alpine",
+            "readonly-alpine",
+        ] {
+            let upper = root.join(OVERLAYS_DIR).join(id).join("upper");
+            std::fs::create_dir_all(&upper).unwrap();
+            std::fs::write(upper.join("file"), b"12345").unwrap();
+        }
+        let removed = std::cell::RefCell::new(Vec::new());
+        let remove = |id: &str| {
+            removed.borrow_mut().push(id.to_string());
+            Ok(())
+        };
+        let exists = |id: &str| id == "live";
+        let leased = |id: &str| id == "ephemeral-busy";
+
+        let preview = prune_overlays_at(root, None, true, exists, leased, remove).unwrap();
+        assert_eq!(preview.removed, vec!["container-gone", "ephemeral-stale"]);
+        assert_eq!(preview.freed_bytes, 10);
+        assert!(removed.borrow().is_empty());
+
+        let pruned = prune_overlays_at(root, None, false, exists, leased, remove).unwrap();
+        assert_eq!(pruned, preview);
+        assert_eq!(*removed.borrow(), vec!["container-gone", "ephemeral-stale"]);
+    }
+
+    /// Retry config with negligible delays so tests run fast.
+    fn fast_retry() -> crate::retry::RetryConfig {
+        crate::retry::RetryConfig {
+            max_attempts: 4,
+            initial_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(5),
+            backoff_multiplier: 2.0,
+            ..Default::default()
+        }
+    }
+
+    /// Write a fake fuse-overlayfs that records its arguments and populates
+    /// the mount point as if the mount had succeeded.
+    fn fake_fuse_overlayfs(dir: &Path) -> String {
+        fake_tool(
+            dir,
+            "fuse-overlayfs",
+            &format!(
+                "echo \"$@\" > '{args}'\n\
+                 for last; do :; done\n\
+                 mkdir -p \"$last/bin\"",
+                args = dir.join("fuse-args").display(),
+            ),
+        )
+        .display()
+        .to_string()
+    }
+
+    fn overlay_setup_for_test(root: &Path) -> (OverlaySetup, Vec<String>) {
+        let setup = OverlaySetup::at(root, None, "test-workload");
+        setup.prepare_directories().unwrap();
+        let lower = root.join("layer0");
+        std::fs::create_dir_all(&lower).unwrap();
+        (setup, vec![lower.display().to_string()])
+    }
+
+    /// Write a fake umount that logs its arguments, reports the target busy
+    /// unless `-l` is given, and on `-l` clears `mounted` if `lazy_detaches`.
+    fn fake_umount(dir: &Path, lazy_detaches: bool) -> String {
+        fake_tool(
+            dir,
+            "umount",
+            &format!(
+                "echo \"$@\" >> '{log}'\n\
+                 if [ \"$1\" != -l ]; then echo \"umount: $1: target is busy.\" >&2; exit 32; fi\n\
+                 {detach}",
+                log = dir.join("umount-log").display(),
+                detach = if lazy_detaches {
+                    format!("rm -f '{}'", dir.join("mounted").display())
+                } else {
+                    ":".to_string()
+                },
+            ),
+        )
+        .display()
+        .to_string()
+    }
+
+    #[test]
+    fn test_cleanup_overlay_lazily_unmounts_busy_overlay() {
+        for lazy_detaches in [false, true] {
+            let dir = tempfile::tempdir().unwrap();
+            let root = dir.path().join("storage");
+            let overlay_root = root.join(OVERLAYS_DIR).join("busy");
+            std::fs::create_dir_all(overlay_root.join("merged/bin")).unwrap();
+            let marker = dir.path().join("mounted");
+            std::fs::write(&marker, "").unwrap();
+            let umount = fake_umount(dir.path(), lazy_detaches);
+
+            let result = cleanup_overlay_at(&root, None, "busy", &umount, fast_retry(), |_| {
+                marker.exists()
+            });
+
+            // Busy every time, then a lazy unmount
+            let log = std::fs::read_to_string(dir.path().join("umount-log")).unwrap();
+            let merged = overlay_root.join("merged").display().to_string();
+            let calls: Vec<&str> = log.lines().collect();
+            assert_eq!(
+                calls.len(),
+                fast_retry().max_attempts as usize + 1,
+                "{}",
+                log
+            );
+            assert!(calls[..calls.len() - 1].iter().all(|c| *c == merged));
+            assert_eq!(calls.last().unwrap(), &format!("-l {}", merged));
+
+            if lazy_detaches {
+                result.unwrap();
+                assert!(!overlay_root.exists());
+            } else {
+                // Still mounted: nothing is removed through the mount
+                let err = result.unwrap_err();
+                assert!(err.to_string().contains("still mounted"), "{}", err);
+                assert!(overlay_root.join("merged/bin").exists());
+            }
+        }
+    }
+
+    #[test]
+    fn test_cleanup_overlay_skips_unmount_when_not_mounted() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("storage");
+        let overlay_root = root.join(OVERLAYS_DIR).join("idle");
+        std::fs::create_dir_all(overlay_root.join("merged")).unwrap();
+        let umount = fake_umount(dir.path(), true);
+
+        cleanup_overlay_at(&root, None, "idle", &umount, fast_retry(), |_| false).unwrap();
+        assert!(!overlay_root.exists());
+        assert!(!dir.path().join("umount-log").exists());
+    }
+
+    #[test]
+    fn test_read_only_overlay_mounts_layers_without_upper() {
+        let dir = tempfile::tempdir().unwrap();
+        let layer = dir.path().join("layers/base");
+        std::fs::create_dir_all(layer.join("etc")).unwrap();
+        std::fs::write(layer.join("etc/resolv.conf"), "nameserver 10.0.0.1\n").unwrap();
+        std::fs::write(layer.join("etc/hostname"), "image\n").unwrap();
+        let snapshot = |layer: &Path| {
+            let mut files: Vec<(PathBuf, String)> = std::fs::read_dir(layer.join("etc"))
+                .unwrap()
+                .map(|e| e.unwrap().path())
+                .map(|p| {
+                    let contents = std::fs::read_to_string(&p).unwrap();
+                    (p, contents)
+                })
+                .collect();
+            files.sort();
+            files
+        };
+        let before = snapshot(&layer);
+
+        // Record the mount options instead of mounting
+        let log = dir.path().join("mount-log");
+        let mount = fake_tool(
+            dir.path(),
+            "mount",
+            &format!("echo \"$@\" > '{}'", log.display()),
+        );
+
+        let workload_id = run_workload_id("alpine:latest", true, false);
+        assert!(workload_id.starts_with("ephemeral-"));
+        let workload_id = run_workload_id("alpine:latest", true, true);
+        assert_eq!(workload_id, run_workload_id("alpine:latest", false, true));
+        let info = OverlaySetup::at(&dir.path().join("storage"), None, &workload_id)
+            .execute_read_only(
+                vec![layer.display().to_string()],
+                &mount.display().to_string(),
+            )
+            .unwrap();
+
+        // A single layer is stacked on an empty dir, with no upper or work dir
+        let overlay_root = dir
+            .path()
+            .join("storage")
+            .join(OVERLAYS_DIR)
+            .join(&workload_id);
+        let args = std::fs::read_to_string(&log).unwrap();
+        assert!(args.contains(&format!(
+            "lowerdir={}:{}",
+            layer.display(),
+            overlay_root.join("empty").display()
+        )));
+        assert!(!args.contains("upperdir"));
+        assert!(!overlay_root.join("upper").exists());
+        assert!(!overlay_root.join("work").exists());
+        assert!(info.upper_path.is_empty());
+        assert!(overlay_root.join("bundle/rootfs").is_symlink());
+
+        // The container root is read-only, with DNS bound over the image's
+        let mut spec = OciSpec::new(&["cat".to_string()], &[], "/", false);
+        configure_read_only_root(&mut spec, &layer);
+        assert!(spec.root.readonly);
+        let resolv = dir.path().join("layers/resolv.conf").display().to_string();
+        assert!(spec
+            .mounts
+            .iter()
+            .any(|m| m.destination == "/etc/resolv.conf" && m.source == resolv));
+
+        // Mount points must already exist in the image
+        let tmpfs = |path: &str| TmpfsMount {
+            path: path.to_string(),
+            size_bytes: None,
+        };
+        assert!(check_read_only_targets(&layer, &[], &[tmpfs("/etc")]).is_ok());
+        assert!(matches!(
+            check_read_only_targets(&layer, &[], &[tmpfs("/scratch")]),
+            Err(StorageError::ValidationFailed { .. })
+        ));
+
+        assert_eq!(snapshot(&layer), before);
+    }
+
+    #[test]
+    fn test_resolv_conf_uses_host_dns() {
+        assert_eq!(
+            resolv_conf_from(Some("10.0.0.2,fd00::53"), Some("corp.example")),
+            "search corp.example\nnameserver 10.0.0.2\nnameserver fd00::53\n"
+        );
+        assert_eq!(
+            resolv_conf_from(Some("10.0.0.2"), None),
+            "nameserver 10.0.0.2\n"
+        );
+
+        // Older hosts send nothing; a garbled value isn't half-applied
+        assert_eq!(resolv_conf_from(None, None), FALLBACK_RESOLV_CONF);
+        assert_eq!(
+            resolv_conf_from(Some("10.0.0.2,not-an-ip"), None),
+            FALLBACK_RESOLV_CONF
+        );
+    }
+
+    #[test]
+    fn test_extra_hosts_follow_image_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = dir.path().join("merged");
+        std::fs::create_dir_all(rootfs.join("etc")).unwrap();
+        std::fs::write(rootfs.join("etc/hosts"), "127.0.0.1\tlocalhost").unwrap();
+        let extra_hosts = vec![
+            ("db".to_string(), "10.0.0.5".parse().unwrap()),
+            ("mock.api".to_string(), "fd00::1".parse().unwrap()),
+        ];
+        check_extra_hosts(&extra_hosts).unwrap();
+
+        let mut spec = OciSpec::new(&["cat".to_string()], &[], "/", false);
+        add_extra_hosts(&mut spec, &rootfs, &extra_hosts).unwrap();
+
+        // The container's /etc/hosts is a copy with the entries appended
+        let hosts = dir.path().join(HOSTS_FILE);
+        let mount = spec
+            .mounts
+            .iter()
+            .find(|m| m.destination == "/etc/hosts")
+            .expect("/etc/hosts should be mounted");
+        assert_eq!(mount.source, hosts.display().to_string());
+        assert_eq!(
+            std::fs::read_to_string(&hosts).unwrap(),
+            "127.0.0.1\tlocalhost\n10.0.0.5\tdb\nfd00::1\tmock.api\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(rootfs.join("etc/hosts")).unwrap(),
+            "127.0.0.1\tlocalhost"
+        );
+
+        // A run without extra hosts keeps the image's own file
+        let mut spec = OciSpec::new(&["cat".to_string()], &[], "/", false);
+        add_extra_hosts(&mut spec, &dir.path().join("other/merged"), &[]).unwrap();
+        assert!(!spec.mounts.iter().any(|m| m.destination == "/etc/hosts"));
+
+        for hostname in ["", "bad_host", "-db", "db host\n10.0.0.1\tevil"] {
+            let invalid = vec![(hostname.to_string(), "10.0.0.5".parse().unwrap())];
+            assert!(
+                matches!(
+                    check_extra_hosts(&invalid),
+                    Err(StorageError::ValidationFailed { .. })
+                ),
+                "{:?} accepted",
+                hostname
+            );
+        }
+    }
+
+    #[test]
+    fn test_diff_overlay_classifies_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("storage");
+        let write = |path: PathBuf, contents: &str| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+
+        // Two image layers; the top one deletes /tmp/scratch
+        let base = dir.path().join("layers/base");
+        let top = dir.path().join("layers/top");
+        write(base.join("etc/hostname"), "image");
+        write(base.join("etc/motd"), "hello");
+        write(base.join("usr/bin/tool"), "v1");
+        write(base.join("var/log/old.log"), "old");
+        write(top.join("app/config.yml"), "debug: false");
+        let overlay_root = root.join(OVERLAYS_DIR).join("wl");
+        std::fs::create_dir_all(&overlay_root).unwrap();
+        std::fs::write(
+            overlay_root.join(LOWERDIRS_FILE),
+            format!("{}\n{}", top.display(), base.display()),
+        )
+        .unwrap();
+
+        // The workload creates, modifies and deletes files
+        let upper = overlay_root.join("upper");
+        write(upper.join("etc/resolv.conf"), "nameserver 8.8.8.8");
+        std::fs::create_dir_all(upper.join("dev")).unwrap();
+        write(upper.join("app/config.yml"), "debug: true");
+        write(upper.join("app/cache/data.bin"), "new");
+        write(upper.join("usr/bin/.wh.tool"), "");
+        write(upper.join("var/log/.wh..wh..opq"), "");
+        write(upper.join("var/log/new.log"), "new");
+
+        let diff: Vec<(String, DiffKind)> = diff_overlay_at(&root, None, "wl")
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.path, e.kind))
+            .collect();
+        let expected = [
+            ("/app", DiffKind::Changed),
+            ("/app/cache", DiffKind::Added),
+            ("/app/cache/data.bin", DiffKind::Added),
+            ("/app/config.yml", DiffKind::Changed),
+            ("/usr", DiffKind::Changed),
+            ("/usr/bin", DiffKind::Changed),
+            ("/usr/bin/tool", DiffKind::Deleted),
+            ("/var", DiffKind::Changed),
+            ("/var/log", DiffKind::Changed),
+            ("/var/log/new.log", DiffKind::Added),
+        ];
+        assert_eq!(
+            diff,
+            expected
+                .iter()
+                .map(|(p, k)| (p.to_string(), *k))
+                .collect::<Vec<_>>()
+        );
+
+        assert!(matches!(
+            diff_overlay_at(&root, None, "missing"),
+            Err(StorageError::OverlayNotFound { .. })
+        ));
+        assert!(matches!(
+            diff_overlay_at(&root, None, "../wl"),
+            Err(StorageError::ValidationFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_overlay_falls_back_to_fuse_when_native_mount_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let fuse = fake_fuse_overlayfs(dir.path());
+        let (setup, lowerdirs) = overlay_setup_for_test(dir.path());
+
+        setup
+            .mount_with_fallback(
+                &lowerdirs,
+                || Err(StorageError::new("unknown filesystem type 'overlay'")),
+                &fuse,
+            )
+            .unwrap();
+
+        let args = std::fs::read_to_string(dir.path().join("fuse-args")).unwrap();
+        assert!(
+            args.contains(&format!("lowerdir={}", lowerdirs[0])),
+            "{}",
+            args
+        );
+        assert!(args.contains(&format!("upperdir={}", setup.upper_path.display())));
+        assert!(args.contains(&format!("workdir={}", setup.work_path.display())));
+        assert!(args
+            .trim_end()
+            .ends_with(&setup.merged_path.display().to_string()));
+    }
+
+    /// A formatted storage root holding a one-layer `app:1.0` image, and
+    /// the directory of that layer.
+    fn root_with_image(dir: &Path) -> (PathBuf, PathBuf) {
+        let root = formatted_root(dir);
+        let layer_digest = format!("sha256:{}", "b".repeat(64));
+        let layer_dir = root.join(LAYERS_DIR).join(&layer_digest[7..]);
+        std::fs::create_dir_all(layer_dir.join("etc")).unwrap();
+        let config = r#"{"architecture":"arm64","os":"linux"}"#;
+        let config_digest = format!("sha256:{:x}", Sha256::digest(config));
+        std::fs::write(
+            root.join(CONFIGS_DIR)
+                .join(format!("{}.json", &config_digest[7..])),
+            config,
+        )
+        .unwrap();
+        let manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "config": {"digest": config_digest, "size": config.len()},
+            "layers": [{"digest": layer_digest, "size": 100}],
+        });
+        std::fs::write(manifest_path(&root, "app:1.0"), manifest.to_string()).unwrap();
+        (root, layer_dir)
+    }
+
+    #[test]
+    fn test_overlay_upper_layer_on_separate_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let (root, layer_dir) = root_with_image(dir.path());
+        let fuse = fake_fuse_overlayfs(dir.path());
+
+        let scratch = dir.path().join("scratch");
+        validate_overlay_upper_root(&scratch).unwrap();
+        assert!(validate_overlay_upper_root(Path::new("scratch")).is_err());
+
+        let info = prepare_overlay_at(
+            &root,
+            Some(&scratch),
+            "app:1.0",
+            "wl",
+            |setup, lowerdirs| {
+                setup.mount_with_fallback(
+                    lowerdirs,
+                    || Err(StorageError::new("unknown filesystem type 'overlay'")),
+                    &fuse,
+                )
+            },
+        )
+        .unwrap();
+
+        // Upper and work go to the scratch root; layers and the merged
+        // mount stay on the storage disk
+        let overlay_root = root.join(OVERLAYS_DIR).join("wl");
+        assert_eq!(Path::new(&info.upper_path), scratch.join("wl/upper"));
+        assert_eq!(Path::new(&info.work_path), scratch.join("wl/work"));
+        assert_eq!(Path::new(&info.rootfs_path), overlay_root.join("merged"));
+        let args = std::fs::read_to_string(dir.path().join("fuse-args")).unwrap();
+        assert!(
+            args.contains(&format!("lowerdir={}", layer_dir.display())),
+            "{}",
+            args
+        );
+        assert!(args.contains(&format!("upperdir={}", scratch.join("wl/upper").display())));
+        assert!(args.contains(&format!("workdir={}", scratch.join("wl/work").display())));
+        assert!(scratch.join("wl/upper/etc/resolv.conf").exists());
+        assert!(!overlay_root.join("upper").exists());
+
+        std::fs::write(scratch.join("wl/upper/etc/app.conf"), "x").unwrap();
+        let diff = diff_overlay_at(&root, Some(&scratch), "wl").unwrap();
+        assert!(diff.iter().any(|e| e.path == "/etc/app.conf"));
+        assert!(overlay_size_at(&root, Some(&scratch), "wl") > 0);
+
+        cleanup_overlay_at(&root, Some(&scratch), "wl", "true", fast_retry(), |_| false).unwrap();
+        assert!(!overlay_root.exists());
+        assert!(!scratch.join("wl").exists());
+        assert!(layer_dir.exists());
+    }
+
+    #[test]
+    fn test_run_overlay_kept_only_for_persistent_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (root, _) = root_with_image(dir.path());
+        let overlays = root.join(OVERLAYS_DIR);
+        // Stands in for the kernel's mount table
+        let mounted = std::cell::RefCell::new(std::collections::HashSet::new());
+        let cleanup = |id: &str| -> Result<()> {
+            cleanup_overlay_at(&root, None, id, "true", fast_retry(), |_| false)
+        };
+        let overlay = |id: &str| {
+            get_or_create_overlay_at(
+                &root,
+                None,
+                id,
+                |merged| mounted.borrow().contains(merged),
+                |_| Ok(()),
+                || {
+                    prepare_overlay_at(&root, None, "app:1.0", id, |setup, _| {
+                        mounted.borrow_mut().insert(setup.merged_path.clone());
+                        Ok(())
+                    })
+                },
+            )
+        };
+
+        // An ephemeral run's overlay is removed even when the run fails
+        let id = run_workload_id("app:1.0", true, false);
+        let result: std::result::Result<(), String> = with_run_overlay(&id, true, cleanup, || {
+            overlay(&id).unwrap();
+            assert!(overlays.join(&id).exists());
+            Err("exit 1".into())
+        });
+        assert_eq!(result.unwrap_err(), "exit 1");
+        assert!(!overlays.join(&id).exists());
+
+        // A persistent run's upper layer is still there on the next run
+        let id = run_workload_id("app:1.0", false, false);
+        with_run_overlay(&id, false, cleanup, || {
+            let info = overlay(&id)?;
+            std::fs::write(Path::new(&info.upper_path).join("state"), "1")?;
+            Ok::<_, StorageError>(())
+        })
+        .unwrap();
+        let info = with_run_overlay(&id, false, cleanup, || overlay(&id)).unwrap();
+        assert!(Path::new(&info.upper_path).join("state").exists());
+    }
+
+    #[test]
+    fn test_overlay_falls_back_to_fuse_when_merged_dir_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let fuse = fake_fuse_overlayfs(dir.path());
+        let (setup, lowerdirs) = overlay_setup_for_test(dir.path());
+
+        // "Succeeds" without populating merged/
+        setup
+            .mount_with_fallback(&lowerdirs, || Ok(()), &fuse)
+            .unwrap();
+        assert!(dir.path().join("fuse-args").exists());
+    }
+
+    #[test]
+    fn test_overlay_reports_missing_fuse_overlayfs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (setup, lowerdirs) = overlay_setup_for_test(dir.path());
+        let missing = dir.path().join("no-such-binary").display().to_string();
+
+        let err = setup
+            .mount_with_fallback(
+                &lowerdirs,
+                || Err(StorageError::new("unknown filesystem type 'overlay'")),
+                &missing,
+            )
+            .unwrap_err();
+
+        assert!(matches!(err, StorageError::OverlayUnsupported { .. }));
+        let msg = err.to_string();
+        assert!(msg.contains("unknown filesystem type"), "{}", msg);
+        assert!(msg.contains("fuse-overlayfs is not installed"), "{}", msg);
+    }
+
+    #[test]
+    fn test_overlay_native_mount_success_skips_fuse() {
+        let dir = tempfile::tempdir().unwrap();
+        let fuse = fake_fuse_overlayfs(dir.path());
+        let (setup, lowerdirs) = overlay_setup_for_test(dir.path());
+
+        setup
+            .mount_with_fallback(
+                &lowerdirs,
+                || {
+                    std::fs::create_dir_all(setup.merged_path.join("etc"))?;
+                    Ok(())
+                },
+                &fuse,
+            )
+            .unwrap();
+        assert!(!dir.path().join("fuse-args").exists());
+    }
+
+    /// Write a fake crane script that fails with `error` for the first
+    /// `failures` invocations and runs `success` afterwards.
+    fn fake_crane(dir: &Path, failures: u32, error: &str, success: &str) -> String {
+        fake_tool(
+            dir,
+            "crane",
+            &format!(
+                "n=$(cat '{counter}' 2>/dev/null || echo 0)\n\
+                 n=$((n + 1))\n\
+                 echo $n > '{counter}'\n\
+                 if [ $n -le {failures} ]; then echo '{error}' >&2; exit 1; fi\n\
+                 {success}",
+                counter = dir.join("attempts").display(),
+            ),
+        )
+        .display()
+        .to_string()
+    }
+
+    fn fake_crane_attempts(dir: &Path) -> u32 {
+        std::fs::read_to_string(dir.join("attempts"))
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_crane_retries_transient_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let crane = fake_crane(
+            dir.path(),
+            2,
+            "GET https://registry/v2/: read: connection reset by peer",
+            "echo '{\"schemaVersion\":2}'",
+        );
+
+        let crane = CranePuller::with_bin(crane);
+        let manifest = with_pull_retry(fast_retry(), "fetch manifest", || {
+            crane.manifest("alpine:latest", None, None)
+        })
+        .unwrap();
+        assert_eq!(manifest.trim(), r#"{"schemaVersion":2}"#);
+        assert_eq!(fake_crane_attempts(dir.path()), 3);
+    }
+
+    #[test]
+    fn test_crane_permanent_failure_not_retried() {
+        let dir = tempfile::tempdir().unwrap();
+        let crane = fake_crane(
+            dir.path(),
+            u32::MAX,
+            "MANIFEST_UNKNOWN: manifest unknown",
+            "true",
+        );
+
+        let crane = CranePuller::with_bin(crane);
+        let result = with_pull_retry(fast_retry(), "fetch manifest", || {
+            crane.manifest("alpine:nope", None, None)
+        });
+        assert!(result.is_err());
+        assert_eq!(fake_crane_attempts(dir.path()), 1);
+    }
+
+    #[test]
+    fn test_layer_extraction_retries_transient_failures() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Build a real gzipped layer for the fake crane to serve
+        let blob = dir.path().join("layer.tar.gz");
+        std::fs::write(&blob, gzipped_layer(dir.path(), "hello.txt", "hi")).unwrap();
+
+        let crane = fake_crane(
+            dir.path(),
+            2,
+            "503 Service Unavailable",
+            &format!("cat '{}'", blob.display()),
+        );
+
+        let crane = CranePuller::with_bin(crane);
+        let layer_dir = dir.path().join("layer");
+        with_pull_retry(fast_retry(), "fetch blob", || {
+            extract_layer_once(
+                &crane,
+                "alpine",
+                "sha256:abc",
+                &layer_dir,
+                None,
+                None,
+                &PullCancel::default(),
+            )
+        })
+        .unwrap();
+        assert_eq!(fake_crane_attempts(dir.path()), 3);
+        assert_eq!(
+            std::fs::read_to_string(layer_dir.join("hello.txt")).unwrap(),
+            "hi"
+        );
+    }
+
+    #[test]
+    fn test_cancel_stops_stalled_layer_download() {
+        let dir = tempfile::tempdir().unwrap();
+        let blob = dir.path().join("layer.tar.gz");
+        std::fs::write(&blob, gzipped_layer(dir.path(), "hello.txt", "hi")).unwrap();
+
+        // Serve part of the layer, then stall
+        let pid_file = dir.path().join("crane.pid");
+        let crane = fake_crane(
+            dir.path(),
+            0,
+            "",
+            &format!(
+                "echo $$ > '{}'; head -c 20 '{}'; exec sleep 30",
+                pid_file.display(),
+                blob.display()
+            ),
+        );
+        let crane = CranePuller::with_bin(crane);
+        let layer_dir = dir.path().join("layer");
+        let cancel = PullCancel::default();
+
+        let started = std::time::Instant::now();
+        let result = std::thread::scope(|s| {
+            let pull = s.spawn(|| {
+                extract_layer(
+                    &crane,
+                    "alpine",
+                    "sha256:abc",
+                    &layer_dir,
+                    None,
+                    None,
+                    &cancel,
+                )
+            });
+            while !pid_file.exists() || !layer_dir.exists() {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            cancel.cancel();
+            pull.join().unwrap()
+        });
+
+        assert!(
+            matches!(result, Err(StorageError::PullCancelled { .. })),
+            "{:?}",
+            result
+        );
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        assert!(!layer_dir.exists(), "partial layer directory left behind");
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        assert!(
+            !Path::new("/proc").join(pid.trim()).exists(),
+            "crane still running"
+        );
+        assert_eq!(fake_crane_attempts(dir.path()), 1);
+    }
+
+    /// Build a gzipped layer tarball containing a single file.
+    fn gzipped_layer(dir: &Path, file: &str, contents: &str) -> Vec<u8> {
+        let src = tempfile::tempdir_in(dir).unwrap();
+        std::fs::write(src.path().join(file), contents).unwrap();
+        let output = Command::new("tar")
+            .arg("-czf")
+            .arg("-")
+            .arg("-C")
+            .arg(src.path())
+            .arg(".")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        output.stdout
+    }
+
+    /// Puller serving a fixed image from memory and recording each call.
+    struct MockPuller {
+        manifest: String,
+        config: String,
+        blobs: HashMap<String, Vec<u8>>,
+        calls: parking_lot::Mutex<Vec<String>>,
+    }
+
+    impl MockPuller {
+        /// An image whose layers are `(digest, blob)` pairs.
+        fn image(layers: Vec<(&str, Vec<u8>)>) -> Self {
+            let manifest = serde_json::json!({
+                "schemaVersion": 2,
+                "config": {"digest": "sha256:cfg", "size": 100},
+                "layers": layers
+                    .iter()
+                    .map(|(digest, blob)| serde_json::json!({"digest": digest, "size": blob.len()}))
+                    .collect::<Vec<_>>(),
+            });
+            let config = serde_json::json!({
+                "architecture": "arm64",
+                "os": "linux",
+                "config": {"Cmd": ["/bin/sh"], "Labels": {"tier": "app"}},
+            });
+            Self {
+                manifest: manifest.to_string(),
+                config: config.to_string(),
+                blobs: layers
+                    .into_iter()
+                    .map(|(digest, blob)| (digest.to_string(), blob))
+                    .collect(),
+                calls: parking_lot::Mutex::new(Vec::new()),
+            }
+        }
+
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().clone()
+        }
+    }
+
+    impl OciPuller for MockPuller {
+        fn manifest(
+            &self,
+            _image: &str,
+            _oci_platform: Option<&str>,
+            _auth: Option<&RegistryAuth>,
+        ) -> Result<String> {
+            self.calls.lock().push("manifest".to_string());
+            Ok(self.manifest.clone())
+        }
+
+        fn config(
+            &self,
+            _image: &str,
+            _digest: &str,
+            _auth: Option<&RegistryAuth>,
+        ) -> Result<String> {
+            self.calls.lock().push("config".to_string());
+            Ok(self.config.clone())
+        }
+
+        fn blob_stream(
+            &self,
+            _image: &str,
+            digest: &str,
+            _oci_platform: Option<&str>,
+            _auth: Option<&RegistryAuth>,
+            _cancel: &PullCancel,
+        ) -> Result<Box<dyn Read + Send>> {
+            self.calls.lock().push(format!("blob {}", digest));
+            let blob =
+                self.blobs
+                    .get(digest)
+                    .cloned()
+                    .ok_or_else(|| StorageError::LayerNotFound {
+                        digest: digest.to_string(),
+                    })?;
+            Ok(Box::new(std::io::Cursor::new(blob)))
+        }
+    }
+
+    fn formatted_root(dir: &Path) -> PathBuf {
+        let root = dir.join("storage");
+        format_at(&root, &[], false).unwrap();
+        root
+    }
+
+    #[test]
+    fn test_pull_fetches_manifest_then_config_then_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = formatted_root(dir.path());
+        let puller = MockPuller::image(vec![
+            ("sha256:aaa", gzipped_layer(dir.path(), "a.txt", "base")),
+            ("sha256:bbb", gzipped_layer(dir.path(), "b.txt", "app")),
+        ]);
+
+        let mut reported = Vec::new();
+        let info = pull_into(
+            &puller,
+            &root,
+            "alpine:3.19",
+            None,
+            None,
+            false,
+            &PullCancel::default(),
+            |i, n, _| reported.push((i, n)),
+        )
+        .unwrap();
+
+        assert_eq!(
+            puller.calls(),
+            ["manifest", "config", "blob sha256:aaa", "blob sha256:bbb"]
+        );
+        assert_eq!(reported, [(0, 0), (0, 2), (1, 2), (2, 2)]);
+        assert_eq!(info.digest, "sha256:cfg");
+        assert_eq!(info.layers, ["sha256:aaa", "sha256:bbb"]);
+        assert_eq!(info.cmd, ["/bin/sh"]);
+        assert_eq!(info.labels["tier"], "app");
+
+        assert!(manifest_path(&root, "alpine:3.19").exists());
+        assert!(root.join(CONFIGS_DIR).join("cfg.json").exists());
+        assert_eq!(
+            std::fs::read_to_string(root.join(LAYERS_DIR).join("bbb").join("b.txt")).unwrap(),
+            "app"
+        );
+    }
+
+    #[test]
+    fn test_agent_status_counts_seeded_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = formatted_root(dir.path());
+        for image in ["alpine:3.19", "busybox:latest"] {
+            std::fs::write(manifest_path(&root, image), "{}").unwrap();
+        }
+        for layer in ["aaa", "bbb", "ccc"] {
+            std::fs::create_dir_all(root.join(LAYERS_DIR).join(layer)).unwrap();
+        }
+        for overlay in ["container-running", "container-stopped"] {
+            std::fs::create_dir_all(root.join(OVERLAYS_DIR).join(overlay).join("merged")).unwrap();
+        }
+
+        let status = agent_status_at(&root, std::time::Duration::from_millis(90_500), |path| {
+            path.starts_with(root.join(OVERLAYS_DIR).join("container-running"))
+        })
+        .unwrap();
+        assert_eq!(
+            status,
+            AgentStatus {
+                version: smolvm_protocol::PROTOCOL_VERSION,
+                uptime_secs: 90,
+                image_count: 2,
+                layer_count: 3,
+                active_overlay_count: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_saved_image_loads_back_runnable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = formatted_root(dir.path());
+        let layer_digest = format!("sha256:{}", "a".repeat(64));
+        let layer_id = &layer_digest[7..];
+        let layer_dir = root.join(LAYERS_DIR).join(layer_id);
+        let hello = layer_dir.join("bin").join("hello");
+        std::fs::create_dir_all(hello.parent().unwrap()).unwrap();
+        std::fs::write(&hello, "#!/bin/sh\necho hello\n").unwrap();
+        std::fs::set_permissions(&hello, std::fs::Permissions::from_mode(0o755)).unwrap();
+        record_layer_checksum(&root, layer_id, &layer_dir).unwrap();
+        let config = serde_json::json!({
+            "architecture": "arm64",
+            "os": "linux",
+            "config": {"Cmd": ["/bin/hello"]},
+        })
+        .to_string();
+        let config_digest = format!("sha256:{:x}", Sha256::digest(&config));
+        let config_path = root
+            .join(CONFIGS_DIR)
+            .join(format!("{}.json", &config_digest[7..]));
+        std::fs::write(&config_path, &config).unwrap();
+        let manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "config": {"digest": config_digest, "size": config.len()},
+            "layers": [{"digest": layer_digest, "size": 100}],
+        });
+        std::fs::write(manifest_path(&root, "hello:1.0"), manifest.to_string()).unwrap();
+
+        let archive = dir.path().join("hello.tar");
+        assert!(save_image_at(&root, "hello:1.0", &archive).unwrap());
+        assert!(!save_image_at(&root, "missing:1.0", &dir.path().join("missing.tar")).unwrap());
+
+        // Remove the image and everything it used, as on another host
+        assert!(remove_image_at(&root, "hello:1.0", &[], |_| false, |_| Ok(())).unwrap());
+        std::fs::remove_dir_all(&layer_dir).unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        std::fs::remove_file(layer_checksum_path(&root, layer_id)).unwrap();
+
+        let loaded = load_image_at(&root, &archive).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].reference, "docker.io/library/hello:1.0");
+        assert_eq!(loaded[0].digest, config_digest);
+        // Not cached here, so the layer is kept under its blob's digest
+        assert_eq!(loaded[0].layers.len(), 1);
+        assert_ne!(loaded[0].layers[0], layer_digest);
+        assert!(!layer_dir.exists());
+
+        // The default command resolves, and the layer is back as it was
+        assert_eq!(
+            run_argv_at(&root, "hello:1.0", None, &[]).unwrap(),
+            ["/bin/hello"]
+        );
+        let loaded_hello = root
+            .join(LAYERS_DIR)
+            .join(&loaded[0].layers[0][7..])
+            .join("bin")
+            .join("hello");
+        let mode = std::fs::metadata(&loaded_hello)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+        let report = &verify_images_at(&root, Some("hello:1.0"), false)
+            .unwrap()
+            .unwrap()[0];
+        assert!(
+            report.ok && report.unverified_layers.is_empty(),
+            "{:?}",
+            report
+        );
+
+        // Scratch space is cleaned up, and junk is refused
+        assert_eq!(std::fs::read_dir(root.join(TMP_DIR)).unwrap().count(), 0);
+        let junk = dir.path().join("junk.tar");
+        std::fs::write(&junk, "not a tar").unwrap();
+        assert!(matches!(
+            load_image_at(&root, &junk),
+            Err(StorageError::ValidationFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_load_ignores_forged_layer_digest_annotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = formatted_root(dir.path());
+        let base_digest = format!("sha256:{}", "c".repeat(64));
+        let base_dir = root.join(LAYERS_DIR).join(&base_digest[7..]);
+        let config = r#"{"os":"linux","config":{"Cmd":["/bin/sh"]}}"#;
+        let config_digest = format!("sha256:{:x}", Sha256::digest(config));
+        std::fs::write(
+            root.join(CONFIGS_DIR)
+                .join(format!("{}.json", &config_digest[7..])),
+            config,
+        )
+        .unwrap();
+        let manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "config": {"digest": config_digest, "size": config.len()},
+            "layers": [{"digest": base_digest, "size": 100}],
+        });
+
+        // An archive made from a store holding other content under the
+        // digest of a common base layer, so the annotation names that layer
+        std::fs::create_dir_all(&base_dir).unwrap();
+        std::fs::write(base_dir.join("evil.txt"), "evil").unwrap();
+        std::fs::write(manifest_path(&root, "evil:1.0"), manifest.to_string()).unwrap();
+        let archive = dir.path().join("evil.tar");
+        assert!(save_image_at(&root, "evil:1.0", &archive).unwrap());
+        assert!(remove_image_at(&root, "evil:1.0", &[], |_| false, |_| Ok(())).unwrap());
+        std::fs::remove_dir_all(&base_dir).unwrap();
+
+        // Without the base layer cached, the content isn't stored under it
+        let loaded = load_image_at(&root, &archive).unwrap();
+        assert_ne!(loaded[0].layers[0], base_digest);
+        assert!(!base_dir.exists());
+        let evil_dir = root.join(LAYERS_DIR).join(&loaded[0].layers[0][7..]);
+        assert_eq!(
+            std::fs::read_to_string(evil_dir.join("evil.txt")).unwrap(),
+            "evil"
+        );
+
+        // With it cached, the cached content is kept as pulled
+        std::fs::create_dir_all(&base_dir).unwrap();
+        std::fs::write(base_dir.join("base.txt"), "base").unwrap();
+        let loaded = load_image_at(&root, &archive).unwrap();
+        assert_eq!(loaded[0].layers, [base_digest]);
+        assert!(!base_dir.join("evil.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(base_dir.join("base.txt")).unwrap(),
+            "base"
+        );
+    }
+
+    #[test]
+    fn test_pull_skips_cached_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = formatted_root(dir.path());
+        let cached = root.join(LAYERS_DIR).join("aaa");
+        std::fs::create_dir_all(&cached).unwrap();
+        std::fs::write(cached.join("a.txt"), "base").unwrap();
+
+        let puller = MockPuller::image(vec![
+            ("sha256:aaa", Vec::new()),
+            ("sha256:bbb", gzipped_layer(dir.path(), "b.txt", "app")),
+        ]);
+        pull_into(
+            &puller,
+            &root,
+            "alpine:3.19",
+            None,
+            None,
+            false,
+            &PullCancel::default(),
+            |_, _, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(puller.calls(), ["manifest", "config", "blob sha256:bbb"]);
+    }
+
+    #[test]
+    fn test_pull_same_digest_under_second_tag_fetches_no_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = formatted_root(dir.path());
+        let puller = MockPuller::image(vec![
+            ("sha256:aaa", gzipped_layer(dir.path(), "a.txt", "base")),
+            ("sha256:bbb", gzipped_layer(dir.path(), "b.txt", "app")),
+        ]);
+        pull_into(
+            &puller,
+            &root,
+            "alpine:3.19",
+            None,
+            None,
+            false,
+            &PullCancel::default(),
+            |_, _, _| {},
+        )
+        .unwrap();
+        puller.calls.lock().clear();
+
+        let mut reported = Vec::new();
+        let info = pull_into(
+            &puller,
+            &root,
+            "alpine:latest",
+            None,
+            None,
+            false,
+            &PullCancel::default(),
+            |_, _, msg| reported.push(msg.to_string()),
+        )
+        .unwrap();
+        assert_eq!(puller.calls(), ["manifest"]);
+        assert!(reported.contains(&"2 of 2 layers already cached".to_string()));
+        assert!(manifest_path(&root, "alpine:latest").exists());
+        assert_eq!(info.layers, ["sha256:aaa", "sha256:bbb"]);
+        assert_eq!(info.cmd, ["/bin/sh"]);
+
+        // Pinned to the cached manifest's digest, nothing is fetched at all
+        let digest = format!("sha256:{:x}", Sha256::digest(puller.manifest.as_bytes()));
+        let pinned = format!("alpine@{}", digest);
+        pull_into(
+            &puller,
+            &root,
+            &pinned,
+            None,
+            None,
+            false,
+            &PullCancel::default(),
+            |_, _, _| {},
+        )
+        .unwrap();
+        assert_eq!(puller.calls(), ["manifest"]);
+        assert!(manifest_path(&root, &pinned).exists());
+    }
+
+    #[test]
+    fn test_no_cache_pull_picks_up_changed_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = formatted_root(dir.path());
+        let puller = MockPuller::image(vec![
+            ("sha256:aaa", gzipped_layer(dir.path(), "a.txt", "base")),
+            ("sha256:bbb", gzipped_layer(dir.path(), "b.txt", "app v1")),
+        ]);
+        let info = pull_into(
+            &puller,
+            &root,
+            "alpine:latest",
+            None,
+            None,
+            false,
+            &PullCancel::default(),
+            |_, _, _| {},
+        )
+        .unwrap();
+        assert_eq!(info.refreshed_layers, ["sha256:aaa", "sha256:bbb"]);
+
+        // The tag moves upstream: same base layer, new app layer and config
+        let mut puller = MockPuller::image(vec![
+            ("sha256:aaa", Vec::new()),
+            ("sha256:ccc", gzipped_layer(dir.path(), "b.txt", "app v2")),
+        ]);
+        puller.manifest = puller.manifest.replace("sha256:cfg", "sha256:cfg2");
+        let info = pull_into(
+            &puller,
+            &root,
+            "alpine:latest",
+            None,
+            None,
+            true,
+            &PullCancel::default(),
+            |_, _, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(puller.calls(), ["manifest", "config", "blob sha256:ccc"]);
+        assert_eq!(info.refreshed_layers, ["sha256:ccc"]);
+        assert_eq!(info.layers, ["sha256:aaa", "sha256:ccc"]);
+
+        // The stored reference now resolves to the new image
+        let stored = image_info_at(&root, "alpine:latest", &digest_references(&root).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.digest, "sha256:cfg2");
+        assert_eq!(stored.layers, ["sha256:aaa", "sha256:ccc"]);
+        assert!(stored.refreshed_layers.is_empty());
+        assert_eq!(
+            std::fs::read_to_string(root.join(LAYERS_DIR).join("ccc").join("b.txt")).unwrap(),
+            "app v2"
+        );
+    }
+
+    #[test]
+    fn test_verify_reports_truncated_layer() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = formatted_root(dir.path());
+        let puller = MockPuller::image(vec![
+            ("sha256:aaa", gzipped_layer(dir.path(), "a.txt", "base")),
+            ("sha256:bbb", gzipped_layer(dir.path(), "b.txt", "app")),
+        ]);
+        pull_into(
+            &puller,
+            &root,
+            "alpine:latest",
+            None,
+            None,
+            false,
+            &PullCancel::default(),
+            |_, _, _| {},
+        )
+        .unwrap();
+
+        let reports = verify_images_at(&root, None, false).unwrap().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].ok, "{:?}", reports[0]);
+        assert!(reports[0].unverified_layers.is_empty());
+
+        // Simulate a file cut short by an unclean shutdown
+        let file = root.join(LAYERS_DIR).join("bbb").join("b.txt");
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_len(1)
+            .unwrap();
+
+        let report = &verify_images_at(&root, Some("alpine:latest"), false)
+            .unwrap()
+            .unwrap()[0];
+        assert!(!report.ok);
+        assert!(report.manifest_ok && report.config_ok);
+        assert_eq!(report.corrupt_layers, ["sha256:bbb"]);
+        assert!(root.join(LAYERS_DIR).join("bbb").exists());
+
+        // Removing the corrupt layer lets a pull extract it again
+        verify_images_at(&root, Some("alpine:latest"), true).unwrap();
+        assert!(!root.join(LAYERS_DIR).join("bbb").exists());
+        pull_into(
+            &puller,
+            &root,
+            "alpine:latest",
+            None,
+            None,
+            true,
+            &PullCancel::default(),
+            |_, _, _| {},
+        )
+        .unwrap();
+        let report = &verify_images_at(&root, Some("alpine:latest"), false)
+            .unwrap()
+            .unwrap()[0];
+        assert!(report.ok, "{:?}", report);
+
+        assert!(verify_images_at(&root, Some("missing:latest"), false)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_verify_without_checksum_checks_presence() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = formatted_root(dir.path());
+        let puller = MockPuller::image(vec![(
+            "sha256:aaa",
+            gzipped_layer(dir.path(), "a.txt", "base"),
+        )]);
+        pull_into(
+            &puller,
+            &root,
+            "alpine:latest",
+            None,
+            None,
+            false,
+            &PullCancel::default(),
+            |_, _, _| {},
+        )
+        .unwrap();
+
+        // As if pulled before checksums were recorded
+        std::fs::remove_file(layer_checksum_path(&root, "aaa")).unwrap();
+        let report = &verify_images_at(&root, None, false).unwrap().unwrap()[0];
+        assert!(report.ok);
+        assert_eq!(report.unverified_layers, ["sha256:aaa"]);
+
+        std::fs::remove_dir_all(root.join(LAYERS_DIR).join("aaa")).unwrap();
+        std::fs::write(root.join(MANIFESTS_DIR).join("broken.json"), "{").unwrap();
+        let reports = verify_images_at(&root, None, false).unwrap().unwrap();
+        assert_eq!(reports.len(), 2);
+        let broken = reports.iter().find(|r| r.image == "broken").unwrap();
+        assert!(!broken.ok && !broken.manifest_ok);
+        let alpine = reports.iter().find(|r| r.image != "broken").unwrap();
+        assert_eq!(alpine.corrupt_layers, ["sha256:aaa"]);
+    }
+
+    #[test]
+    fn test_pull_stops_at_manifest_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = formatted_root(dir.path());
+        let mut puller = MockPuller::image(Vec::new());
+        puller.manifest = serde_json::json!({
+            "manifests": [
+                {"digest": "sha256:x", "platform": {"architecture": "s390x"}},
+                {"digest": "sha256:y", "platform": {"os": "linux", "architecture": "arm64", "variant": "v8"}},
+                {"digest": "sha256:z", "platform": {"os": "unknown", "architecture": "unknown"}}
+            ]
+        })
+        .to_string();
+
+        let err = pull_into(
+            &puller,
+            &root,
+            "alpine:3.19",
+            Some("linux/riscv64"),
+            None,
+            false,
+            &PullCancel::default(),
+            |_, _, _| {},
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("s390x"));
+        let StorageError::PlatformUnavailable {
+            requested,
+            available,
+        } = err
+        else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(requested.as_deref(), Some("linux/riscv64"));
+        assert_eq!(available, ["linux/s390x", "linux/arm64/v8"]);
+        assert_eq!(puller.calls(), ["manifest"]);
+    }
+
+    #[test]
+    fn test_run_result_from_wait_normal_exit() {
+        let result = WaitResult::Completed {
+            exit_code: 3,
+            signal: None,
+            output: crate::process::ChildOutput {
+                stdout: "out".into(),
+                ..Default::default()
+            },
+        };
+        let run = run_result_from_wait("test", result, None);
+        assert_eq!(run.exit_code, 3);
+        assert_eq!(run.stdout, "out");
+        assert_eq!(run.reason, ExitReason::Exited);
+    }
+
+    #[test]
+    fn test_run_result_from_wait_timeout() {
+        let result = WaitResult::TimedOut {
+            output: Default::default(),
+            timeout_ms: 500,
+        };
+        let run = run_result_from_wait("test", result, None);
+        assert_eq!(run.exit_code, TIMEOUT_EXIT_CODE);
+        assert_eq!(run.reason, ExitReason::TimedOut);
+        assert!(run.stderr.contains("timed out after 500ms"));
+    }
+
+    fn mount(tag: &str, target: &str) -> (String, String, bool) {
+        (tag.to_string(), target.to_string(), false)
+    }
+
+    #[test]
+    fn test_ensure_workdir_creates_missing_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = temp.path().join("rootfs");
+        std::fs::create_dir_all(rootfs.join("usr/src")).unwrap();
+
+        // Missing directories are created, nested ones included
+        ensure_workdir(&rootfs, "/usr/src/app/build", &[], true).unwrap();
+        assert!(rootfs.join("usr/src/app/build").is_dir());
+        ensure_workdir(&rootfs, "/usr/src/app/build", &[], true).unwrap();
+
+        // An absolute symlink resolves inside the rootfs, not on the host
+        std::os::unix::fs::symlink("/srv/data", rootfs.join("data")).unwrap();
+        ensure_workdir(&rootfs, "/data/work", &[], true).unwrap();
+        assert!(rootfs.join("srv/data/work").is_dir());
+        std::os::unix::fs::symlink("../../..", rootfs.join("up")).unwrap();
+        ensure_workdir(&rootfs, "/up/escaped", &[], true).unwrap();
+        assert!(rootfs.join("escaped").is_dir());
+        assert!(!temp.path().join("escaped").exists());
+
+        // Without create, a missing directory is a distinct error
+        let err = ensure_workdir(&rootfs, "/opt/missing", &[], false).unwrap_err();
+        assert!(matches!(err, StorageError::WorkdirNotFound { .. }));
+        assert!(!rootfs.join("opt").exists());
+        ensure_workdir(&rootfs, "/usr/src", &[], false).unwrap();
+
+        // Mount targets are created by crun
+        let mounts = [("tag0".to_string(), "/work".to_string(), false)];
+        ensure_workdir(&rootfs, "/work/sub", &mounts, false).unwrap();
+        assert!(!rootfs.join("work").exists());
+
+        std::fs::write(rootfs.join("file"), "").unwrap();
+        assert!(ensure_workdir(&rootfs, "/file/sub", &[], true).is_err());
+        assert!(ensure_workdir(&rootfs, "relative", &[], true).is_err());
+    }
+
+    #[test]
+    fn test_mismatched_arch_rejected_without_rosetta() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join(MANIFESTS_DIR)).unwrap();
+        std::fs::create_dir_all(root.join(CONFIGS_DIR)).unwrap();
+        let manifest = serde_json::json!({ "config": { "digest": "sha256:aaa" }, "layers": [] });
+        std::fs::write(manifest_path(root, "nginx"), manifest.to_string()).unwrap();
+        std::fs::write(
+            root.join(CONFIGS_DIR).join("aaa.json"),
+            r#"{"architecture": "amd64", "os": "linux"}"#,
+        )
+        .unwrap();
+
+        let image_arch = image_architecture_at(root, "nginx").unwrap().unwrap();
+        assert_eq!(image_arch, "amd64");
+        assert_eq!(image_architecture_at(root, "alpine").unwrap(), None);
+
+        // amd64 on an arm64 VM needs Rosetta
+        let err = check_image_arch(&image_arch, None, "arm64", false).unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                StorageError::ArchMismatch { image_arch, target_arch }
+                    if image_arch == "amd64" && target_arch == "arm64"
+            ),
+            "{}",
+            err
+        );
+        check_image_arch(&image_arch, None, "arm64", true).unwrap();
+        check_image_arch(&image_arch, None, "amd64", false).unwrap();
+
+        // Rosetta only translates amd64
+        assert!(check_image_arch("riscv64", None, "arm64", true).is_err());
+
+        // A platform override replaces the VM's architecture
+        check_image_arch(&image_arch, Some("linux/amd64"), "arm64", false).unwrap();
+        assert!(check_image_arch(&image_arch, Some("linux/arm64/v8"), "arm64", true).is_err());
+    }
+
+    #[test]
+    fn test_validate_mounts_accepts_distinct_mounts() {
+        let mounts = [
+            mount("smolvm0", "/app"),
+            mount("smolvm1", "/data"),
+            mount("volume:cache", "/root/.cache"),
+        ];
+        validate_mounts(&mounts).unwrap();
+        validate_mounts(&[]).unwrap();
+    }
+
+    #[test]
+    fn test_validate_mounts_duplicate_tag() {
+        let err = validate_mounts(&[mount("smolvm0", "/a"), mount("smolvm0", "/b")]).unwrap_err();
+        assert!(err.to_string().contains("more than one mount"), "{err}");
+    }
+
+    #[test]
+    fn test_validate_mounts_unsafe_tag() {
+        for tag in ["", "smol vm", "tag;rm -rf /", "../smolvm0", &"a".repeat(37)] {
+            assert!(
+                validate_mounts(&[mount(tag, "/app")]).is_err(),
+                "tag {tag:?} accepted"
+            );
+        }
+        assert!(validate_mounts(&[mount("volume:../escape", "/app")]).is_err());
+    }
+
+    #[test]
+    fn test_validate_mounts_overlapping_targets() {
+        for (a, b) in [("/app", "/app"), ("/app", "/app/"), ("/app", "//app/.")] {
+            let err = validate_mounts(&[mount("smolvm0", a), mount("smolvm1", b)]).unwrap_err();
+            assert!(
+                err.to_string().contains("more than one mount"),
+                "{a} {b}: {err}"
+            );
+        }
+        assert!(validate_mounts(&[mount("smolvm0", "app")]).is_err());
+        assert!(validate_mounts(&[mount("smolvm0", "/app/../etc")]).is_err());
+    }
 }
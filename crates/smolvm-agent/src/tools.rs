@@ -0,0 +1,247 @@
+//! External binaries the agent shells out to.
+//!
+//! The agent relies on crane, crun, mount and tar being in its rootfs. A
+//! rootfs built without one of them only fails when the binary is first
+//! spawned, with a bare "No such file or directory". [`spawn_startup_check`]
+//! probes them once at boot and logs what it found, [`status`] serves the
+//! result to `ToolCheck` requests, and [`Tool::spawn_error`] turns a failed
+//! spawn into an error naming the path and how to install the tool.
+
+use crate::paths;
+use smolvm_protocol::ToolStatus;
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+/// Hint appended to errors about tools that ship in the agent rootfs.
+const REBUILD_HINT: &str = "rebuild the agent rootfs with scripts/build-agent-rootfs.sh";
+
+/// An external binary the agent runs.
+pub struct Tool {
+    /// Name reported in [`ToolStatus`].
+    pub name: &'static str,
+    /// Absolute path, or a name looked up on `PATH`.
+    pub bin: &'static str,
+    /// Arguments that make the binary print its version.
+    version_args: &'static [&'static str],
+    /// How to install the tool if it is missing.
+    hint: &'static str,
+}
+
+/// crane, used to pull images unless `SMOLVM_OCI_PULLER=registry`.
+pub const CRANE: Tool = Tool {
+    name: "crane",
+    bin: "crane",
+    version_args: &["version"],
+    hint: "rebuild the agent rootfs with scripts/build-agent-rootfs.sh, \
+           or set SMOLVM_OCI_PULLER=registry to pull without it",
+};
+
+/// crun, the OCI runtime containers run under.
+pub const CRUN: Tool = Tool {
+    name: "crun",
+    bin: paths::CRUN_PATH,
+    version_args: &["--version"],
+    hint: REBUILD_HINT,
+};
+
+/// mount, for overlay and virtiofs mounts.
+pub const MOUNT: Tool = Tool {
+    name: "mount",
+    bin: "mount",
+    version_args: &["--version"],
+    hint: REBUILD_HINT,
+};
+
+/// tar, for extracting and exporting layers.
+pub const TAR: Tool = Tool {
+    name: "tar",
+    bin: "tar",
+    version_args: &["--version"],
+    hint: REBUILD_HINT,
+};
+
+/// Every tool the agent needs.
+pub const REQUIRED: &[Tool] = &[CRANE, CRUN, MOUNT, TAR];
+
+impl Tool {
+    /// Check whether the tool is installed and read its version.
+    pub fn probe(&self) -> ToolStatus {
+        probe_bin(
+            self.name,
+            self.bin,
+            self.version_args,
+            std::env::var_os("PATH").as_deref(),
+        )
+    }
+
+    /// Describe a failure to spawn the tool.
+    pub fn spawn_error(&self, err: &io::Error) -> String {
+        self.spawn_error_at(self.bin, err)
+    }
+
+    /// Describe a failure to spawn the tool from `bin` instead of its usual
+    /// location.
+    ///
+    /// A missing binary gets the path that was tried and an install hint.
+    pub fn spawn_error_at(&self, bin: &str, err: &io::Error) -> String {
+        if err.kind() != io::ErrorKind::NotFound {
+            let location = resolve(bin, std::env::var_os("PATH").as_deref())
+                .map_or_else(|| bin.to_string(), |p| p.display().to_string());
+            return format!("failed to run {} at {}: {}", self.name, location, err);
+        }
+        if is_path(bin) {
+            format!("{} not found at {}; {}", self.name, bin, self.hint)
+        } else {
+            format!("{} not found on PATH; {}", self.name, self.hint)
+        }
+    }
+}
+
+/// Probe every required tool, once per agent.
+pub fn status() -> &'static [ToolStatus] {
+    static STATUS: OnceLock<Vec<ToolStatus>> = OnceLock::new();
+    STATUS.get_or_init(|| REQUIRED.iter().map(Tool::probe).collect())
+}
+
+/// Probe the tools in the background and log the result, so boot isn't
+/// held up by running each of them.
+pub fn spawn_startup_check() {
+    std::thread::spawn(|| {
+        for tool in status() {
+            if tool.found {
+                info!(
+                    tool = %tool.name,
+                    path = %tool.path,
+                    version = tool.version.as_deref().unwrap_or("unknown"),
+                    "found tool"
+                );
+            } else {
+                warn!(tool = %tool.name, path = %tool.path, "required tool is missing");
+            }
+        }
+    });
+}
+
+fn probe_bin(name: &str, bin: &str, version_args: &[&str], path: Option<&OsStr>) -> ToolStatus {
+    match resolve(bin, path) {
+        Some(resolved) => ToolStatus {
+            name: name.to_string(),
+            found: true,
+            version: version(&resolved, version_args),
+            path: resolved.display().to_string(),
+        },
+        None => ToolStatus {
+            name: name.to_string(),
+            found: false,
+            path: bin.to_string(),
+            version: None,
+        },
+    }
+}
+
+fn is_path(bin: &str) -> bool {
+    bin.contains('/')
+}
+
+/// Find `bin` as an executable file, searching `path` if it's a bare name.
+fn resolve(bin: &str, path: Option<&OsStr>) -> Option<PathBuf> {
+    if is_path(bin) {
+        let bin = Path::new(bin);
+        return is_executable(bin).then(|| bin.to_path_buf());
+    }
+    std::env::split_paths(path?)
+        .map(|dir| dir.join(bin))
+        .find(|candidate| is_executable(candidate))
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// The first line the tool prints for its version arguments, if they
+/// succeed (busybox applets reject `--version`).
+fn version(bin: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new(bin).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    stdout
+        .lines()
+        .chain(stderr.lines())
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smolvm_protocol::test_util::fake_tool;
+
+    #[test]
+    fn test_probe_finds_tool_on_path() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_tool(dir.path(), "crane", "echo; echo 'v0.19.0'");
+        let path = std::env::join_paths(["/nonexistent", dir.path().to_str().unwrap()]).unwrap();
+
+        let status = probe_bin("crane", "crane", &["version"], Some(&path));
+        assert!(status.found);
+        assert_eq!(status.path, dir.path().join("crane").display().to_string());
+        assert_eq!(status.version.as_deref(), Some("v0.19.0"));
+
+        // A tool that rejects the version flag is still found
+        fake_tool(dir.path(), "tar", "exit 1");
+        let bin = dir.path().join("tar");
+        let status = probe_bin("tar", bin.to_str().unwrap(), &["--version"], None);
+        assert!(status.found);
+        assert_eq!(status.version, None);
+    }
+
+    #[test]
+    fn test_probe_reports_missing_tool() {
+        let dir = tempfile::tempdir().unwrap();
+        // Present but not executable counts as missing
+        std::fs::write(dir.path().join("crun"), "").unwrap();
+        let bin = dir.path().join("crun");
+
+        let status = probe_bin("crun", bin.to_str().unwrap(), &["--version"], None);
+        assert!(!status.found);
+        assert_eq!(status.path, bin.display().to_string());
+
+        let status = probe_bin("crane", "crane", &["version"], Some(dir.path().as_os_str()));
+        assert!(!status.found);
+        assert_eq!(status.path, "crane");
+    }
+
+    #[test]
+    fn test_spawn_error_names_path_and_hint() {
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        let msg = CRUN.spawn_error(&missing);
+        assert!(
+            msg.starts_with("crun not found at /usr/bin/crun;"),
+            "{}",
+            msg
+        );
+        assert!(msg.contains("build-agent-rootfs.sh"), "{}", msg);
+
+        let msg = CRANE.spawn_error_at("crane", &missing);
+        assert!(msg.starts_with("crane not found on PATH;"), "{}", msg);
+        assert!(msg.contains("SMOLVM_OCI_PULLER=registry"), "{}", msg);
+
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let msg = TAR.spawn_error_at("/bin/tar-missing", &denied);
+        assert!(
+            msg.starts_with("failed to run tar at /bin/tar-missing:"),
+            "{}",
+            msg
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Resolution of `--user` specs against a container rootfs.
+//!
+//! A spec is `user[:group]`, where each part is a numeric ID or a name
+//! looked up in the rootfs's `/etc/passwd` and `/etc/group`, as with
+//! `docker run --user`.
+
+use std::path::Path;
+
+use crate::storage::StorageError;
+
+type Result<T> = std::result::Result<T, StorageError>;
+
+/// A user resolved to numeric IDs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    /// User ID.
+    pub uid: u32,
+    /// Primary group ID.
+    pub gid: u32,
+    /// Home directory from `/etc/passwd`, if the user has an entry.
+    pub home: Option<String>,
+}
+
+/// An `/etc/passwd` entry.
+struct PasswdEntry {
+    name: String,
+    uid: u32,
+    gid: u32,
+    home: String,
+}
+
+/// Resolve `spec` against the passwd and group files under `rootfs`.
+///
+/// A numeric user without a group takes its primary group from
+/// `/etc/passwd`, or group 0 if it has no entry. Names must exist.
+pub fn resolve(spec: &str, rootfs: &Path) -> Result<User> {
+    let (user, group) = match spec.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (spec, None),
+    };
+    if user.is_empty() || group.is_some_and(str::is_empty) {
+        return Err(invalid(spec, "expected user[:group]"));
+    }
+
+    let passwd = read_passwd(rootfs)?;
+    let (uid, entry) = match user.parse::<u32>() {
+        Ok(uid) => (uid, passwd.iter().find(|e| e.uid == uid)),
+        Err(_) => {
+            let entry = passwd
+                .iter()
+                .find(|e| e.name == user)
+                .ok_or_else(|| invalid(spec, format!("no user '{}' in /etc/passwd", user)))?;
+            (entry.uid, Some(entry))
+        }
+    };
+
+    let gid = match group {
+        None => entry.map_or(0, |e| e.gid),
+        Some(group) => match group.parse::<u32>() {
+            Ok(gid) => gid,
+            Err(_) => lookup_group(rootfs, group)?
+                .ok_or_else(|| invalid(spec, format!("no group '{}' in /etc/group", group)))?,
+        },
+    };
+
+    Ok(User {
+        uid,
+        gid,
+        home: entry.map(|e| e.home.clone()),
+    })
+}
+
+fn invalid(spec: &str, reason: impl Into<String>) -> StorageError {
+    StorageError::ValidationFailed {
+        context: format!("user '{}'", spec),
+        reason: reason.into(),
+    }
+}
+
+/// Read `etc/<name>` under `rootfs`; a missing file reads as empty.
+fn read_etc(rootfs: &Path, name: &str) -> Result<String> {
+    let path = rootfs.join("etc").join(name);
+    match std::fs::read_to_string(&path) {
+        Ok(content) => Ok(content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(StorageError::read_error(path.display().to_string(), e)),
+    }
+}
+
+/// Parse `/etc/passwd`: `name:password:uid:gid:gecos:home:shell`.
+fn read_passwd(rootfs: &Path) -> Result<Vec<PasswdEntry>> {
+    Ok(read_etc(rootfs, "passwd")?
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() < 6 {
+                return None;
+            }
+            Some(PasswdEntry {
+                name: fields[0].to_string(),
+                uid: fields[2].parse().ok()?,
+                gid: fields[3].parse().ok()?,
+                home: fields[5].to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Look up a group ID in `/etc/group`: `name:password:gid:members`.
+fn lookup_group(rootfs: &Path, name: &str) -> Result<Option<u32>> {
+    Ok(read_etc(rootfs, "group")?.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        if fields.next()? != name {
+            return None;
+        }
+        fields.nth(1)?.parse().ok()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rootfs() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("etc")).unwrap();
+        std::fs::write(
+            dir.path().join("etc/passwd"),
+            "root:x:0:0:root:/root:/bin/sh\n\
+             nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin\n\
+             app:x:1000:1001:App:/home/app:/bin/sh\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("etc/group"),
+            "root:x:0:\nstaff:x:50:app\napp:x:1001:\n",
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_resolve_numeric() {
+        let dir = rootfs();
+        // Known uid: group and home come from passwd
+        assert_eq!(
+            resolve("1000", dir.path()).unwrap(),
+            User {
+                uid: 1000,
+                gid: 1001,
+                home: Some("/home/app".to_string()),
+            }
+        );
+        // Unknown uid: group 0, no home
+        assert_eq!(
+            resolve("4242:7", dir.path()).unwrap(),
+            User {
+                uid: 4242,
+                gid: 7,
+                home: None,
+            }
+        );
+        assert_eq!(resolve("4242", dir.path()).unwrap().gid, 0);
+
+        // No passwd file at all still works for numeric ids
+        let empty = tempfile::tempdir().unwrap();
+        assert_eq!(resolve("10:20", empty.path()).unwrap().uid, 10);
+    }
+
+    #[test]
+    fn test_resolve_names() {
+        let dir = rootfs();
+        assert_eq!(
+            resolve("app", dir.path()).unwrap(),
+            User {
+                uid: 1000,
+                gid: 1001,
+                home: Some("/home/app".to_string()),
+            }
+        );
+        assert_eq!(resolve("app:staff", dir.path()).unwrap().gid, 50);
+        assert_eq!(resolve("nobody:0", dir.path()).unwrap().uid, 65534);
+
+        assert!(resolve("ghost", dir.path()).is_err());
+        assert!(resolve("app:ghosts", dir.path()).is_err());
+        assert!(resolve("", dir.path()).is_err());
+        assert!(resolve("app:", dir.path()).is_err());
+    }
+}
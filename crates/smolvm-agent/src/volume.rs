@@ -0,0 +1,309 @@
+//! Named volume management.
+//!
+//! Named volumes are directories under `/storage/volumes` that outlive any
+//! single run or container. They are mounted by passing a mount tag of the
+//! form `volume:<name>` (see [`smolvm_protocol::VOLUME_TAG_PREFIX`]) in place
+//! of a virtiofs tag.
+//!
+//! Volumes are tracked in one index; a data directory the index doesn't
+//! list is left over from an interrupted removal and is not a volume.
+//!
+//! Layout:
+//!
+//! ```text
+//! /storage/volumes/
+//! ├── index.json    # VolumeIndex
+//! └── data/<name>/  # bind-mounted into containers
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use smolvm_protocol::{VolumeInfo, VOLUME_TAG_PREFIX};
+use tracing::info;
+
+use crate::paths;
+use crate::storage::StorageError;
+
+type Result<T> = std::result::Result<T, StorageError>;
+
+/// Maximum length of a volume name.
+const MAX_VOLUME_NAME_LEN: usize = 64;
+
+/// Index of all volumes.
+const INDEX_FILE: &str = "index.json";
+
+/// Directory holding one directory of contents per volume.
+const DATA_DIR: &str = "data";
+
+/// Persisted volume metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct VolumeMeta {
+    size_mib: Option<u64>,
+    created_at: u64,
+}
+
+/// All volumes, by name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VolumeIndex {
+    volumes: BTreeMap<String, VolumeMeta>,
+}
+
+impl VolumeIndex {
+    /// Read the index under `root`; none yet means no volumes.
+    fn load(root: &Path) -> Result<Self> {
+        let path = root.join(INDEX_FILE);
+        match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| StorageError::parse_error("volume index", e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(StorageError::read_error(path.display().to_string(), e)),
+        }
+    }
+
+    /// Write the index under `root`, replacing the old one atomically.
+    fn save(&self, root: &Path) -> Result<()> {
+        std::fs::create_dir_all(root)
+            .map_err(|e| StorageError::create_dir_error(root.display().to_string(), e))?;
+        let path = root.join(INDEX_FILE);
+        let tmp = root.join(format!("{}.tmp", INDEX_FILE));
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| StorageError::parse_error("volume index", e))?;
+        std::fs::write(&tmp, json)
+            .and_then(|()| std::fs::rename(&tmp, &path))
+            .map_err(|e| StorageError::write_error(path.display().to_string(), e))
+    }
+}
+
+fn data_dir(root: &Path, name: &str) -> PathBuf {
+    root.join(DATA_DIR).join(name)
+}
+
+fn volume_info(root: &Path, name: &str, meta: &VolumeMeta) -> VolumeInfo {
+    VolumeInfo {
+        name: name.to_string(),
+        size_mib: meta.size_mib,
+        used_bytes: crate::storage::dir_size(&data_dir(root, name)).unwrap_or(0),
+        created_at: meta.created_at,
+    }
+}
+
+/// Extract the volume name from a mount tag, if it refers to a named volume.
+pub fn volume_name_from_tag(tag: &str) -> Option<&str> {
+    tag.strip_prefix(VOLUME_TAG_PREFIX)
+}
+
+/// Validate a volume name.
+///
+/// Names must start with an alphanumeric character and contain only
+/// alphanumerics, `_`, `.` and `-` (same rules as Docker).
+pub fn validate_volume_name(name: &str) -> Result<()> {
+    let invalid = |reason: &str| StorageError::ValidationFailed {
+        context: format!("invalid volume name '{}'", name),
+        reason: reason.to_string(),
+    };
+
+    let first = name
+        .chars()
+        .next()
+        .ok_or_else(|| invalid("name is empty"))?;
+    if name.len() > MAX_VOLUME_NAME_LEN {
+        return Err(invalid("name is too long"));
+    }
+    if !first.is_ascii_alphanumeric() {
+        return Err(invalid("name must start with a letter or digit"));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+    {
+        return Err(invalid("only alphanumerics, '_', '.' and '-' are allowed"));
+    }
+    Ok(())
+}
+
+/// Create a named volume. Fails if it already exists.
+pub fn create_volume(name: &str, size_mib: Option<u64>) -> Result<VolumeInfo> {
+    create_volume_in(Path::new(paths::VOLUMES_DIR), name, size_mib)
+}
+
+/// List all named volumes, sorted by name.
+pub fn list_volumes() -> Result<Vec<VolumeInfo>> {
+    list_volumes_in(Path::new(paths::VOLUMES_DIR))
+}
+
+/// Remove a named volume and all of its data.
+pub fn remove_volume(name: &str) -> Result<()> {
+    remove_volume_in(Path::new(paths::VOLUMES_DIR), name)
+}
+
+/// Get the data directory for a named volume, creating the volume on first
+/// use (Docker semantics for `-v name:/path`).
+pub fn ensure_volume(name: &str) -> Result<PathBuf> {
+    ensure_volume_in(Path::new(paths::VOLUMES_DIR), name)
+}
+
+fn create_volume_in(root: &Path, name: &str, size_mib: Option<u64>) -> Result<VolumeInfo> {
+    validate_volume_name(name)?;
+
+    let mut index = VolumeIndex::load(root)?;
+    if index.volumes.contains_key(name) {
+        return Err(StorageError::ValidationFailed {
+            context: format!("create volume '{}'", name),
+            reason: "volume already exists".to_string(),
+        });
+    }
+
+    // Start empty even if an interrupted removal left data behind
+    let data = data_dir(root, name);
+    remove_data(&data)?;
+    std::fs::create_dir_all(&data)
+        .map_err(|e| StorageError::create_dir_error(data.display().to_string(), e))?;
+
+    let meta = VolumeMeta {
+        size_mib,
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    };
+    index.volumes.insert(name.to_string(), meta.clone());
+    index.save(root)?;
+
+    info!(name = %name, size_mib = ?size_mib, "created volume");
+    Ok(volume_info(root, name, &meta))
+}
+
+fn list_volumes_in(root: &Path) -> Result<Vec<VolumeInfo>> {
+    let index = VolumeIndex::load(root)?;
+    Ok(index
+        .volumes
+        .iter()
+        .map(|(name, meta)| volume_info(root, name, meta))
+        .collect())
+}
+
+fn remove_volume_in(root: &Path, name: &str) -> Result<()> {
+    validate_volume_name(name)?;
+
+    let mut index = VolumeIndex::load(root)?;
+    if index.volumes.remove(name).is_none() {
+        return Err(StorageError::ValidationFailed {
+            context: format!("remove volume '{}'", name),
+            reason: "volume not found".to_string(),
+        });
+    }
+
+    // Unlist it first, so a failure below leaves unlisted data, not a
+    // volume whose data is half gone
+    index.save(root)?;
+    remove_data(&data_dir(root, name))?;
+
+    info!(name = %name, "removed volume");
+    Ok(())
+}
+
+fn ensure_volume_in(root: &Path, name: &str) -> Result<PathBuf> {
+    validate_volume_name(name)?;
+    if !VolumeIndex::load(root)?.volumes.contains_key(name) {
+        create_volume_in(root, name, None)?;
+    }
+    Ok(data_dir(root, name))
+}
+
+fn remove_data(data: &Path) -> Result<()> {
+    match std::fs::remove_dir_all(data) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(StorageError::RemoveDir {
+            path: data.display().to_string(),
+            cause: e.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_volume_name() {
+        assert!(validate_volume_name("data").is_ok());
+        assert!(validate_volume_name("my-vol_1.0").is_ok());
+        assert!(validate_volume_name("").is_err());
+        assert!(validate_volume_name("-data").is_err());
+        assert!(validate_volume_name("../etc").is_err());
+        assert!(validate_volume_name("a/b").is_err());
+        assert!(validate_volume_name(&"a".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn test_volume_name_from_tag() {
+        assert_eq!(volume_name_from_tag("volume:data"), Some("data"));
+        assert_eq!(volume_name_from_tag("smolvm0"), None);
+    }
+
+    #[test]
+    fn test_create_list_remove() {
+        let root = tempfile::tempdir().unwrap();
+
+        let info = create_volume_in(root.path(), "data", Some(512)).unwrap();
+        assert_eq!(info.name, "data");
+        assert_eq!(info.size_mib, Some(512));
+        assert_eq!(info.used_bytes, 0);
+
+        // Duplicate create fails
+        assert!(create_volume_in(root.path(), "data", None).is_err());
+
+        create_volume_in(root.path(), "cache", None).unwrap();
+        let names: Vec<_> = list_volumes_in(root.path())
+            .unwrap()
+            .into_iter()
+            .map(|v| v.name)
+            .collect();
+        assert_eq!(names, vec!["cache", "data"]);
+
+        remove_volume_in(root.path(), "data").unwrap();
+        assert!(!data_dir(root.path(), "data").exists());
+        assert!(remove_volume_in(root.path(), "data").is_err());
+        assert_eq!(list_volumes_in(root.path()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_data_persists_across_mounts() {
+        let root = tempfile::tempdir().unwrap();
+
+        // First "run" creates the volume on demand and writes to it
+        let data = ensure_volume_in(root.path(), "shared").unwrap();
+        std::fs::write(data.join("state.txt"), "hello").unwrap();
+
+        // Second "run" mounting the same name sees the same data
+        let again = ensure_volume_in(root.path(), "shared").unwrap();
+        assert_eq!(again, data);
+        assert_eq!(
+            std::fs::read_to_string(again.join("state.txt")).unwrap(),
+            "hello"
+        );
+
+        let info = &list_volumes_in(root.path()).unwrap()[0];
+        assert_eq!(info.used_bytes, 5);
+    }
+
+    #[test]
+    fn test_unindexed_data_is_not_a_volume() {
+        let root = tempfile::tempdir().unwrap();
+
+        // Data left behind by an interrupted removal
+        let stale = data_dir(root.path(), "old");
+        std::fs::create_dir_all(&stale).unwrap();
+        std::fs::write(stale.join("secret"), "x").unwrap();
+        assert!(list_volumes_in(root.path()).unwrap().is_empty());
+        assert!(remove_volume_in(root.path(), "old").is_err());
+
+        // A new volume with that name starts empty
+        let data = ensure_volume_in(root.path(), "old").unwrap();
+        assert!(!data.join("secret").exists());
+        assert_eq!(list_volumes_in(root.path()).unwrap().len(), 1);
+    }
+}
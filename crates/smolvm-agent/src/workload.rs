@@ -0,0 +1,161 @@
+//! Workload protocol server.
+//!
+//! Serves the [`HostMessage`]/[`GuestMessage`] protocol on
+//! [`ports::WORKLOAD_CONTROL`], mapping each message onto the agent request
+//! it stands for (see [`smolvm_protocol::workload`]). The server only runs
+//! when the host passes a token in [`WORKLOAD_TOKEN_ENV`], so the port is
+//! never open without authentication.
+
+use smolvm_protocol::workload::{read_message, write_message, WorkloadSession, WORKLOAD_TOKEN_ENV};
+use smolvm_protocol::{ports, vsock, AgentRequest, AgentResponse, HostMessage};
+use std::io::{Read, Write};
+use tracing::{info, warn};
+
+/// Start serving the workload protocol, if a token was configured.
+pub fn spawn(dispatch: fn(AgentRequest) -> AgentResponse) {
+    let Some(token) = std::env::var(WORKLOAD_TOKEN_ENV)
+        .ok()
+        .filter(|t| !t.is_empty())
+    else {
+        return;
+    };
+    let listener = match vsock::listen(ports::WORKLOAD_CONTROL) {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!(error = %e, "failed to listen for workload connections");
+            return;
+        }
+    };
+    info!(port = ports::WORKLOAD_CONTROL, "serving workload protocol");
+
+    let result = std::thread::Builder::new()
+        .name("workload-server".into())
+        .spawn(move || loop {
+            match listener.accept() {
+                Ok(mut stream) => {
+                    let token = token.clone();
+                    std::thread::spawn(move || {
+                        if let Err(e) = serve(&mut stream, &token, dispatch) {
+                            warn!(error = %e, "workload connection error");
+                        }
+                    });
+                }
+                Err(e) => warn!(error = %e, "workload accept error"),
+            }
+        });
+    if let Err(e) = result {
+        warn!(error = %e, "failed to start workload server");
+    }
+}
+
+/// Serve one workload connection until the host disconnects, fails to
+/// authenticate or stops the VM.
+fn serve(
+    stream: &mut (impl Read + Write),
+    token: &str,
+    dispatch: impl Fn(AgentRequest) -> AgentResponse,
+) -> std::io::Result<()> {
+    let mut session = WorkloadSession::new(token);
+    while let Some(message) = read_message::<HostMessage>(stream)? {
+        let reply = session.handle(message, &dispatch);
+        for message in &reply.messages {
+            write_message(stream, message)?;
+        }
+        if reply.close {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smolvm_protocol::{ExitReason, GuestMessage, PROTOCOL_VERSION};
+    use std::os::unix::net::UnixStream;
+
+    fn recv(stream: &mut UnixStream) -> Option<GuestMessage> {
+        read_message(stream).unwrap()
+    }
+
+    #[test]
+    fn test_serve_auth_ready_run_exit() {
+        let (mut host, mut guest) = UnixStream::pair().unwrap();
+        let server = std::thread::spawn(move || {
+            serve(&mut guest, "token", |request| match request {
+                AgentRequest::VmExec { command, .. } => AgentResponse::Completed {
+                    exit_code: 0,
+                    stdout: command.join(" "),
+                    stderr: String::new(),
+                    stdout_truncated: false,
+                    stderr_truncated: false,
+                    reason: ExitReason::Exited,
+                },
+                other => panic!("unexpected request {:?}", other),
+            })
+        });
+
+        write_message(
+            &mut host,
+            &HostMessage::Auth {
+                token: "token".into(),
+                protocol_version: PROTOCOL_VERSION,
+            },
+        )
+        .unwrap();
+        assert_eq!(recv(&mut host), Some(GuestMessage::AuthOk));
+        assert_eq!(recv(&mut host), Some(GuestMessage::Ready));
+
+        write_message(
+            &mut host,
+            &HostMessage::Run {
+                request_id: 1,
+                command: vec!["echo".into(), "hi".into()],
+                env: Vec::new(),
+                workdir: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            recv(&mut host),
+            Some(GuestMessage::Started { request_id: 1 })
+        );
+        assert!(matches!(
+            recv(&mut host),
+            Some(GuestMessage::Stdout { request_id: 1, ref data, .. }) if data == b"echo hi"
+        ));
+        assert!(matches!(
+            recv(&mut host),
+            Some(GuestMessage::Exit {
+                request_id: 1,
+                code: 0,
+                ..
+            })
+        ));
+
+        drop(host);
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_serve_closes_on_failed_auth() {
+        let (mut host, mut guest) = UnixStream::pair().unwrap();
+        let server = std::thread::spawn(move || {
+            serve(&mut guest, "token", |request| {
+                panic!("unexpected request {:?}", request)
+            })
+        });
+
+        write_message(
+            &mut host,
+            &HostMessage::Auth {
+                token: "guess".into(),
+                protocol_version: PROTOCOL_VERSION,
+            },
+        )
+        .unwrap();
+        assert_eq!(recv(&mut host), Some(GuestMessage::AuthFailed));
+        server.join().unwrap().unwrap();
+        assert_eq!(recv(&mut host), None);
+    }
+}
@@ -6,15 +6,33 @@
 //! - OCI image layers
 
 use std::fs::{self, File};
-use std::io::{BufWriter, Read};
+use std::io::{self, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 
 use crate::format::{AssetEntry, AssetInventory, LayerEntry};
 use crate::{PackError, Result};
 
-/// Compression level for zstd (19 = high compression).
+/// Default compression level for zstd (19 = high compression).
 pub const ZSTD_LEVEL: i32 = 19;
 
+/// Range of zstd compression levels accepted by
+/// [`AssetCollector::with_compression_level`].
+pub const ZSTD_LEVEL_RANGE: std::ops::RangeInclusive<i32> = 1..=22;
+
+/// Largest zstd dictionary [`AssetCollector::with_trained_dictionary`]
+/// trains.
+pub const MAX_DICTIONARY_SIZE: usize = 112 * 1024;
+
+/// Bytes sampled from each layer file when training a dictionary.
+const DICTIONARY_SAMPLE_SIZE: u64 = 128 * 1024;
+
+/// Total bytes sampled when training a dictionary.
+const DICTIONARY_SAMPLES_TOTAL: usize = 64 * 1024 * 1024;
+
+/// Smallest dictionary worth training; below this there are too few
+/// samples for it to pay for its own size.
+const MIN_DICTIONARY_SIZE: usize = 1024;
+
 /// Find a pre-formatted disk template by filename.
 ///
 /// Searches in order:
@@ -38,10 +56,41 @@ fn find_existing_template(filename: &str) -> Option<PathBuf> {
     None
 }
 
+/// Default directory for reusing compressed layers across packs.
+pub fn default_compression_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("smolvm-pack").join("layers"))
+}
+
 /// Asset collector for gathering runtime components.
 pub struct AssetCollector {
     staging_dir: PathBuf,
     inventory: AssetInventory,
+    compression_cache: Option<PathBuf>,
+    compression_level: i32,
+    train_dictionary: bool,
+}
+
+/// Compressed assets written by [`AssetCollector::compress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressedAssets {
+    /// Size of the compressed output, dictionary included.
+    pub size: u64,
+    /// Size of the zstd dictionary at the start of the output, 0 if the
+    /// assets were compressed without one.
+    pub dictionary_size: u32,
+}
+
+/// Outcome of [`AssetCollector::compress`], for tests.
+#[derive(Debug, Default)]
+struct CompressStats {
+    /// Size of the compressed output.
+    size: u64,
+    /// Size of the dictionary at the start of the output.
+    dictionary_size: u32,
+    /// Layers compressed during this call.
+    layers_compressed: usize,
+    /// Layers copied from the compression cache.
+    layers_reused: usize,
 }
 
 impl AssetCollector {
@@ -63,9 +112,43 @@ impl AssetCollector {
                 storage_template: None,
                 overlay_template: None,
             },
+            compression_cache: None,
+            compression_level: ZSTD_LEVEL,
+            train_dictionary: false,
         })
     }
 
+    /// Reuse compressed layers across packs, stored in `dir` by digest.
+    ///
+    /// Repacking an image (e.g. with a new entrypoint) then only
+    /// compresses layers whose digest isn't cached yet.
+    pub fn with_compression_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.compression_cache = Some(dir.into());
+        self
+    }
+
+    /// Compress assets at zstd `level` instead of [`ZSTD_LEVEL`].
+    ///
+    /// Lower levels pack faster at the cost of a larger binary. The level
+    /// is clamped to [`ZSTD_LEVEL_RANGE`].
+    pub fn with_compression_level(mut self, level: i32) -> Self {
+        self.compression_level = level.clamp(*ZSTD_LEVEL_RANGE.start(), *ZSTD_LEVEL_RANGE.end());
+        self
+    }
+
+    /// Compress assets with a zstd dictionary trained on the layers.
+    ///
+    /// The dictionary captures content the layers have in common, which
+    /// helps most with many small layers. It is stored at the start of the
+    /// compressed assets, and its size is recorded in the footer. Layers
+    /// compressed with a dictionary can't be reused across packs, so the
+    /// compression cache is bypassed. Falls back to no dictionary if there
+    /// isn't enough layer content to train one.
+    pub fn with_trained_dictionary(mut self) -> Self {
+        self.train_dictionary = true;
+        self
+    }
+
     /// Get the staging directory path.
     pub fn staging_dir(&self) -> &Path {
         &self.staging_dir
@@ -324,34 +407,241 @@ impl AssetCollector {
     }
 
     /// Compress all staged assets into a single zstd-compressed tarball.
-    pub fn compress(&self, output: &Path) -> Result<u64> {
-        let output_file = File::create(output)?;
-        let encoder = zstd::stream::Encoder::new(output_file, ZSTD_LEVEL)
-            .map_err(|e| PackError::Compression(e.to_string()))?;
-        let mut tar_builder = tar::Builder::new(encoder);
+    ///
+    /// Each staged file becomes its own zstd frame; concatenated frames
+    /// decode as one continuous tar stream. That lets layer frames be
+    /// copied from the compression cache instead of recompressed.
+    pub fn compress(&self, output: &Path) -> Result<CompressedAssets> {
+        self.compress_with_stats(output)
+            .map(|stats| CompressedAssets {
+                size: stats.size,
+                dictionary_size: stats.dictionary_size,
+            })
+    }
 
-        // Add all files from staging directory
-        tar_builder
-            .append_dir_all(".", &self.staging_dir)
-            .map_err(|e| PackError::Tar(e.to_string()))?;
+    fn compress_with_stats(&self, output: &Path) -> Result<CompressStats> {
+        let mut entries = Vec::new();
+        staged_entries(&self.staging_dir, Path::new(""), &mut entries)?;
 
-        let encoder = tar_builder
-            .into_inner()
-            .map_err(|e| PackError::Tar(e.to_string()))?;
+        let mut out = BufWriter::new(File::create(output)?);
+        let mut stats = CompressStats::default();
+
+        let dictionary = if self.train_dictionary {
+            self.train()?
+        } else {
+            None
+        };
+        let dictionary = match &dictionary {
+            Some(dictionary) => {
+                out.write_all(dictionary)?;
+                stats.dictionary_size = dictionary.len() as u32;
+                Some(zstd::dict::EncoderDictionary::copy(
+                    dictionary,
+                    self.compression_level,
+                ))
+            }
+            None => None,
+        };
+        let level = self.compression_level;
+        let dictionary = dictionary.as_ref();
+
+        for name in entries {
+            let src = self.staging_dir.join(&name);
+            let layer = self
+                .inventory
+                .layers
+                .iter()
+                .find(|layer| Path::new(&layer.path) == name);
+            let Some(layer) = layer else {
+                compress_entry(&mut out, &name, &src, level, dictionary)?;
+                continue;
+            };
+            let cache = match &self.compression_cache {
+                Some(cache) if dictionary.is_none() => cache,
+                _ => {
+                    compress_entry(&mut out, &name, &src, level, dictionary)?;
+                    stats.layers_compressed += 1;
+                    continue;
+                }
+            };
+
+            // The staged size guards against a stale or truncated blob
+            let key = format!(
+                "{}-{}-{}.tar.zst",
+                layer.digest.replace(':', "-"),
+                fs::metadata(&src)?.len(),
+                self.compression_level
+            );
+            let cached = cache.join(key);
+            if cached.exists() {
+                stats.layers_reused += 1;
+            } else {
+                fs::create_dir_all(cache)?;
+                let mut blob = tempfile::NamedTempFile::new_in(cache)?;
+                compress_entry(blob.as_file_mut(), &name, &src, level, None)?;
+                blob.persist(&cached).map_err(|e| e.error)?;
+                stats.layers_compressed += 1;
+            }
+            io::copy(&mut File::open(&cached)?, &mut out)?;
+        }
+
+        // End-of-archive marker: two zero blocks
+        let mut encoder = new_encoder(&mut out, level, dictionary)?;
+        encoder.write_all(&[0u8; 1024])?;
         encoder
             .finish()
             .map_err(|e| PackError::Compression(e.to_string()))?;
+        out.flush()?;
+        drop(out);
 
-        let metadata = fs::metadata(output)?;
-        Ok(metadata.len())
+        stats.size = fs::metadata(output)?.len();
+        Ok(stats)
+    }
+
+    /// Train a dictionary on samples of the files in the staged layers.
+    ///
+    /// Returns `None` if the layers don't hold enough content to train a
+    /// useful dictionary.
+    fn train(&self) -> Result<Option<Vec<u8>>> {
+        let mut samples = Vec::new();
+        let mut sampled = 0;
+        'layers: for layer in &self.inventory.layers {
+            let file = File::open(self.staging_dir.join(&layer.path))?;
+            let mut archive = tar::Archive::new(file);
+            // A layer that isn't a readable tar just contributes no samples
+            let Ok(entries) = archive.entries() else {
+                continue;
+            };
+            for entry in entries {
+                let Ok(entry) = entry else {
+                    continue 'layers;
+                };
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let mut sample = Vec::new();
+                if entry
+                    .take(DICTIONARY_SAMPLE_SIZE)
+                    .read_to_end(&mut sample)
+                    .is_err()
+                {
+                    continue 'layers;
+                }
+                if sample.is_empty() {
+                    continue;
+                }
+                sampled += sample.len();
+                samples.push(sample);
+                if sampled >= DICTIONARY_SAMPLES_TOTAL {
+                    break 'layers;
+                }
+            }
+        }
+
+        // zstd suggests a dictionary about 1/100th of the sampled data
+        let max_size = (sampled / 100).min(MAX_DICTIONARY_SIZE);
+        if max_size < MIN_DICTIONARY_SIZE {
+            return Ok(None);
+        }
+        Ok(zstd::dict::from_samples(&samples, max_size).ok())
     }
 }
 
+/// List staged files and directories relative to `dir`, parents first, in
+/// a stable order.
+fn staged_entries(root: &Path, dir: &Path, entries: &mut Vec<PathBuf>) -> Result<()> {
+    let mut names: Vec<_> = fs::read_dir(root.join(dir))?
+        .map(|entry| entry.map(|e| e.file_name()))
+        .collect::<io::Result<_>>()?;
+    names.sort();
+    for name in names {
+        let rel = dir.join(name);
+        let is_dir = fs::metadata(root.join(&rel))?.is_dir();
+        entries.push(rel.clone());
+        if is_dir {
+            staged_entries(root, &rel, entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// Start a zstd frame at `level`, using `dictionary` if given.
+fn new_encoder<'d, W: Write>(
+    out: W,
+    level: i32,
+    dictionary: Option<&'d zstd::dict::EncoderDictionary<'d>>,
+) -> Result<zstd::stream::Encoder<'d, W>> {
+    match dictionary {
+        Some(dictionary) => zstd::stream::Encoder::with_prepared_dictionary(out, dictionary),
+        None => zstd::stream::Encoder::new(out, level),
+    }
+    .map_err(|e| PackError::Compression(e.to_string()))
+}
+
+/// Write `src` as a single tar entry named `name`, compressed as its own
+/// zstd frame at `level` (or with the level `dictionary` was prepared at).
+fn compress_entry(
+    out: &mut impl Write,
+    name: &Path,
+    src: &Path,
+    level: i32,
+    dictionary: Option<&zstd::dict::EncoderDictionary<'_>>,
+) -> Result<()> {
+    let metadata = fs::metadata(src)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata(&metadata);
+    header
+        .set_path(name)
+        .map_err(|e| PackError::Tar(e.to_string()))?;
+    header.set_cksum();
+
+    let mut encoder = new_encoder(out, level, dictionary)?;
+    encoder.write_all(header.as_bytes())?;
+    if metadata.is_file() {
+        let written = io::copy(&mut File::open(src)?.take(metadata.len()), &mut encoder)?;
+        if written != metadata.len() {
+            return Err(PackError::Tar(format!(
+                "{} changed size while compressing",
+                src.display()
+            )));
+        }
+        let padding = (512 - written % 512) % 512;
+        encoder.write_all(&[0u8; 512][..padding as usize])?;
+    }
+    encoder
+        .finish()
+        .map_err(|e| PackError::Compression(e.to_string()))?;
+    Ok(())
+}
+
+/// Create a decoder for an assets blob whose first `dictionary_size` bytes
+/// are the dictionary it was compressed with.
+pub(crate) fn assets_decoder<R: Read>(
+    mut compressed: R,
+    dictionary_size: u32,
+) -> io::Result<zstd::stream::Decoder<'static, io::BufReader<R>>> {
+    if dictionary_size == 0 {
+        return zstd::stream::Decoder::new(compressed);
+    }
+    if dictionary_size as usize > MAX_DICTIONARY_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("dictionary too large: {} bytes", dictionary_size),
+        ));
+    }
+    let mut dictionary = vec![0u8; dictionary_size as usize];
+    compressed.read_exact(&mut dictionary)?;
+    zstd::stream::Decoder::with_dictionary(io::BufReader::new(compressed), &dictionary)
+}
+
 /// Decompress a zstd-compressed assets blob.
-pub fn decompress_assets(compressed: &[u8], output_dir: &Path) -> Result<()> {
+///
+/// `dictionary_size` is the size of the dictionary at the start of the
+/// blob, from the footer (0 if none).
+pub fn decompress_assets(compressed: &[u8], dictionary_size: u32, output_dir: &Path) -> Result<()> {
     fs::create_dir_all(output_dir)?;
 
-    let decoder = zstd::stream::Decoder::new(compressed)
+    let decoder = assets_decoder(compressed, dictionary_size)
         .map_err(|e| PackError::Compression(e.to_string()))?;
     let mut archive = tar::Archive::new(decoder);
 
@@ -363,12 +653,18 @@ pub fn decompress_assets(compressed: &[u8], output_dir: &Path) -> Result<()> {
 }
 
 /// Decompress assets from a file.
-pub fn decompress_assets_from_file(compressed_path: &Path, output_dir: &Path) -> Result<()> {
+///
+/// `dictionary_size` is as for [`decompress_assets`].
+pub fn decompress_assets_from_file(
+    compressed_path: &Path,
+    dictionary_size: u32,
+    output_dir: &Path,
+) -> Result<()> {
     fs::create_dir_all(output_dir)?;
 
     let file = File::open(compressed_path)?;
     let decoder =
-        zstd::stream::Decoder::new(file).map_err(|e| PackError::Compression(e.to_string()))?;
+        assets_decoder(file, dictionary_size).map_err(|e| PackError::Compression(e.to_string()))?;
     let mut archive = tar::Archive::new(decoder);
 
     archive
@@ -453,6 +749,171 @@ mod tests {
         assert!(staging.join("layers").exists());
     }
 
+    #[test]
+    fn test_compression_cache_reuses_unchanged_layers() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let staging = temp_dir.path().join("staging");
+        let cache = temp_dir.path().join("cache");
+        let compressed = temp_dir.path().join("assets.tar.zst");
+
+        let mut collector = AssetCollector::new(staging)
+            .unwrap()
+            .with_compression_cache(&cache);
+        collector
+            .add_layer("sha256:aaaaaaaaaaaa0000", b"base layer")
+            .unwrap();
+        fs::write(collector.staging_dir().join("agent-rootfs.tar"), b"rootfs").unwrap();
+
+        let stats = collector.compress_with_stats(&compressed).unwrap();
+        assert_eq!((stats.layers_compressed, stats.layers_reused), (1, 0));
+
+        // Repack with an added layer: only the new one is compressed
+        collector
+            .add_layer("sha256:bbbbbbbbbbbb0000", b"app layer")
+            .unwrap();
+        let stats = collector.compress_with_stats(&compressed).unwrap();
+        assert_eq!((stats.layers_compressed, stats.layers_reused), (1, 1));
+        assert_eq!(stats.size, fs::metadata(&compressed).unwrap().len());
+
+        // The spliced archive still extracts to the staged files
+        let output = temp_dir.path().join("output");
+        decompress_assets_from_file(&compressed, 0, &output).unwrap();
+        assert_eq!(
+            fs::read(output.join("layers/aaaaaaaaaaaa.tar")).unwrap(),
+            b"base layer"
+        );
+        assert_eq!(
+            fs::read(output.join("layers/bbbbbbbbbbbb.tar")).unwrap(),
+            b"app layer"
+        );
+        assert_eq!(
+            fs::read(output.join("agent-rootfs.tar")).unwrap(),
+            b"rootfs"
+        );
+    }
+
+    #[test]
+    fn test_compression_level() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = temp_dir.path().join("cache");
+        let fast = temp_dir.path().join("fast.tar.zst");
+        let small = temp_dir.path().join("small.tar.zst");
+
+        let mut collector = AssetCollector::new(temp_dir.path().join("staging"))
+            .unwrap()
+            .with_compression_cache(&cache);
+        let layer: Vec<u8> = (0..256 * 1024u32).map(|i| (i * 7 % 251) as u8).collect();
+        collector
+            .add_layer("sha256:cccccccccccc0000", &layer)
+            .unwrap();
+
+        let collector = collector.with_compression_level(1);
+        let stats = collector.compress_with_stats(&fast).unwrap();
+        assert_eq!(stats.layers_compressed, 1);
+
+        // A layer cached at another level isn't reused
+        let collector = collector.with_compression_level(99);
+        assert_eq!(collector.compression_level, *ZSTD_LEVEL_RANGE.end());
+        let stats = collector.compress_with_stats(&small).unwrap();
+        assert_eq!((stats.layers_compressed, stats.layers_reused), (1, 0));
+        assert!(stats.size <= fs::metadata(&fast).unwrap().len());
+
+        let output = temp_dir.path().join("output");
+        decompress_assets_from_file(&fast, 0, &output).unwrap();
+        assert_eq!(
+            fs::read(output.join("layers/cccccccccccc.tar")).unwrap(),
+            layer
+        );
+    }
+
+    #[test]
+    fn test_trained_dictionary() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = temp_dir.path().join("cache");
+        let plain = temp_dir.path().join("plain.tar.zst");
+        let trained = temp_dir.path().join("trained.tar.zst");
+
+        // Many small layers with similar files, as configuration layers are
+        let mut collector = AssetCollector::new(temp_dir.path().join("staging"))
+            .unwrap()
+            .with_compression_cache(&cache);
+        for i in 0..40u32 {
+            let mut layer = tar::Builder::new(Vec::new());
+            for j in 0..20u32 {
+                let data = format!(
+                    "[service-{i}-{j}]\nname = \"app-{j}\"\nlisten = \"0.0.0.0:{}\"\n\
+                     log_level = \"info\"\nmax_connections = {}\nenabled = true\n\
+                     [service-{i}-{j}.tls]\ncertificate = \"/etc/ssl/certs/app-{j}.pem\"\n\
+                     private_key = \"/etc/ssl/private/app-{j}.key\"\n\
+                     protocols = [\"TLSv1.2\", \"TLSv1.3\"]\n\
+                     [service-{i}-{j}.health]\npath = \"/healthz\"\ninterval_seconds = {}\n",
+                    8000 + i * 20 + j,
+                    (i * 31 + j * 7) % 1000,
+                    5 + (i + j) % 30
+                );
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                layer
+                    .append_data(&mut header, format!("etc/app/{j}.toml"), data.as_bytes())
+                    .unwrap();
+            }
+            let digest = format!("sha256:{:012x}0000", i);
+            collector
+                .add_layer(&digest, &layer.into_inner().unwrap())
+                .unwrap();
+        }
+
+        let without = collector.compress_with_stats(&plain).unwrap();
+        assert_eq!(without.dictionary_size, 0);
+
+        let collector = collector.with_trained_dictionary();
+        let with = collector.compress_with_stats(&trained).unwrap();
+        assert!(with.dictionary_size > 0);
+        // The cache only holds frames compressed without a dictionary
+        assert_eq!((with.layers_compressed, with.layers_reused), (40, 0));
+        assert!(
+            with.size <= without.size,
+            "{} > {}",
+            with.size,
+            without.size
+        );
+
+        let output = temp_dir.path().join("output");
+        decompress_assets_from_file(&trained, with.dictionary_size, &output).unwrap();
+        let expected = temp_dir.path().join("expected");
+        decompress_assets_from_file(&plain, 0, &expected).unwrap();
+        for i in 0..40u32 {
+            let path = format!("layers/{:012x}.tar", i);
+            assert_eq!(
+                fs::read(output.join(&path)).unwrap(),
+                fs::read(expected.join(&path)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_trained_dictionary_needs_samples() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let compressed = temp_dir.path().join("assets.tar.zst");
+        let mut collector = AssetCollector::new(temp_dir.path().join("staging"))
+            .unwrap()
+            .with_trained_dictionary();
+        collector
+            .add_layer("sha256:dddddddddddd0000", b"not a tar")
+            .unwrap();
+
+        let assets = collector.compress(&compressed).unwrap();
+        assert_eq!(assets.dictionary_size, 0);
+        let output = temp_dir.path().join("output");
+        decompress_assets_from_file(&compressed, 0, &output).unwrap();
+        assert_eq!(
+            fs::read(output.join("layers/dddddddddddd.tar")).unwrap(),
+            b"not a tar"
+        );
+    }
+
     #[test]
     fn test_compression_roundtrip() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -470,7 +931,7 @@ mod tests {
         collector.compress(&compressed).unwrap();
 
         // Decompress and verify
-        decompress_assets_from_file(&compressed, &output).unwrap();
+        decompress_assets_from_file(&compressed, 0, &output).unwrap();
         let restored = output.join("test.txt");
         assert!(restored.exists());
         assert_eq!(fs::read_to_string(&restored).unwrap(), "hello world");
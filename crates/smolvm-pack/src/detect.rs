@@ -25,6 +25,8 @@ pub enum PackedMode {
         assets_ptr: *const u8,
         /// Size of compressed assets.
         assets_size: usize,
+        /// Size of the zstd dictionary at the start of the assets (0 if none).
+        dictionary_size: u32,
     },
     /// Assets appended to the binary (Linux single-file, or macOS fallback).
     Embedded {
@@ -130,6 +132,7 @@ fn try_section_mode() -> Option<PackedMode> {
         checksum: embedded.header.checksum,
         assets_ptr: embedded.assets_ptr,
         assets_size: embedded.assets_size,
+        dictionary_size: embedded.header.dictionary_size,
     })
 }
 
@@ -215,7 +218,16 @@ fn read_embedded_section() -> Option<EmbeddedData> {
         let manifest_start = data_ptr.add(SECTION_HEADER_SIZE);
         let manifest_bytes =
             std::slice::from_raw_parts(manifest_start, section_header.manifest_size as usize);
-        let manifest = PackManifest::from_json(manifest_bytes).ok()?;
+        let manifest = match PackManifest::from_json(manifest_bytes) {
+            Ok(manifest) => manifest,
+            // The section is ours but unreadable; falling back to the normal
+            // CLI would only confuse the user.
+            Err(e @ crate::PackError::UnsupportedManifestVersion { .. }) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+            Err(_) => return None,
+        };
 
         // Assets follow the manifest
         let assets_ptr = manifest_start.add(section_header.manifest_size as usize);
@@ -251,7 +263,7 @@ mod tests {
     fn test_read_footer_direct_rejects_invalid_magic() {
         let dir = tempfile::tempdir().unwrap();
         let path = dir.path().join("no_magic.bin");
-        std::fs::write(&path, &[0u8; 128]).unwrap();
+        std::fs::write(&path, [0u8; 128]).unwrap();
         assert!(read_footer_direct(&path).is_err());
     }
 }
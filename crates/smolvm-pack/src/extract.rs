@@ -4,6 +4,7 @@
 //! (sidecar mode via `runpack`) and the standalone stub executable.
 
 use crate::format::{PackFooter, SIDECAR_EXTENSION};
+use std::cell::RefCell;
 use std::fs::{self, File};
 use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
@@ -19,7 +20,13 @@ use std::os::unix::io::AsRawFd;
 /// `lib/libkrun.dylib → /tmp/evil.so`, and subsequent `dlopen()` would
 /// load the attacker's library. This function rejects any entry that is
 /// not a regular file or directory.
-fn safe_unpack<R: Read>(archive: &mut tar::Archive<R>, dest: &Path) -> std::io::Result<()> {
+///
+/// `on_entry` is called with each entry's path before it is unpacked.
+fn safe_unpack<R: Read>(
+    archive: &mut tar::Archive<R>,
+    dest: &Path,
+    mut on_entry: impl FnMut(&Path),
+) -> std::io::Result<()> {
     let canonical_dest = dest.canonicalize().unwrap_or_else(|_| dest.to_path_buf());
 
     for entry_result in archive.entries()? {
@@ -66,11 +73,104 @@ fn safe_unpack<R: Read>(archive: &mut tar::Archive<R>, dest: &Path) -> std::io::
         }
 
         // Unpack the individual entry
+        on_entry(&entry_path);
         entry.unpack_in(dest)?;
     }
     Ok(())
 }
 
+/// Extraction progress callback: (compressed bytes read, total compressed
+/// bytes, asset being extracted). Same shape as the image pull progress
+/// callback.
+pub type ExtractProgress<'a> = dyn FnMut(u64, u64, &str) + 'a;
+
+/// Compressed bytes between progress reports within a single asset.
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+/// Counts compressed bytes consumed and reports them to a callback.
+struct ProgressTracker<'a, 'f> {
+    callback: Option<&'a mut ExtractProgress<'f>>,
+    total: u64,
+    read: u64,
+    reported: u64,
+    entry: String,
+}
+
+impl ProgressTracker<'_, '_> {
+    fn advance(&mut self, bytes: u64) {
+        self.read += bytes;
+        if self.read - self.reported >= PROGRESS_INTERVAL {
+            self.report();
+        }
+    }
+
+    fn set_entry(&mut self, entry: &Path) {
+        self.entry = entry.to_string_lossy().into_owned();
+        self.report();
+    }
+
+    fn report(&mut self) {
+        if let Some(callback) = self.callback.as_mut() {
+            callback(self.read, self.total, &self.entry);
+        }
+        self.reported = self.read;
+    }
+}
+
+/// Reader feeding the bytes it passes through to a [`ProgressTracker`].
+struct CountingReader<'t, 'a, 'f, R> {
+    inner: R,
+    tracker: &'t RefCell<ProgressTracker<'a, 'f>>,
+}
+
+impl<R: Read> Read for CountingReader<'_, '_, '_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.tracker.borrow_mut().advance(n as u64);
+        Ok(n)
+    }
+}
+
+/// Decompress an assets blob of `total` compressed bytes, starting with a
+/// dictionary of `dictionary_size` bytes, into `cache_dir` and post-process
+/// it.
+fn unpack_assets<R: Read>(
+    compressed: R,
+    total: u64,
+    dictionary_size: u32,
+    cache_dir: &Path,
+    debug: bool,
+    progress: Option<&mut ExtractProgress<'_>>,
+) -> std::io::Result<()> {
+    let tracker = RefCell::new(ProgressTracker {
+        callback: progress,
+        total,
+        read: 0,
+        reported: 0,
+        entry: String::new(),
+    });
+    let reader = CountingReader {
+        inner: compressed,
+        tracker: &tracker,
+    };
+
+    let decoder = crate::assets::assets_decoder(reader, dictionary_size)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    let mut archive = tar::Archive::new(decoder);
+    safe_unpack(&mut archive, cache_dir, |entry| {
+        tracker.borrow_mut().set_entry(entry)
+    })?;
+    drop(archive);
+    tracker.borrow_mut().report();
+
+    if debug {
+        eprintln!("debug: extracted assets to {}", cache_dir.display());
+    }
+
+    post_process_extraction(cache_dir, debug)
+}
+
 /// Marker file indicating extraction is complete.
 const EXTRACTION_MARKER: &str = ".smolvm-extracted";
 
@@ -113,12 +213,15 @@ pub fn sidecar_path_for(exe_path: &Path) -> PathBuf {
 /// is false and extraction has already completed (marker file present), this
 /// is a no-op (after acquiring the lock to ensure visibility of a concurrent
 /// extraction that just finished).
+///
+/// `progress`, if given, is called as the compressed assets are consumed.
 pub fn extract_sidecar(
     sidecar_path: &Path,
     cache_dir: &Path,
     footer: &PackFooter,
     force: bool,
     debug: bool,
+    progress: Option<&mut ExtractProgress<'_>>,
 ) -> std::io::Result<()> {
     if !sidecar_path.exists() {
         return Err(std::io::Error::new(
@@ -165,7 +268,7 @@ pub fn extract_sidecar(
         let _ = fs::remove_dir_all(cache_dir);
     }
 
-    extract_sidecar_inner(sidecar_path, cache_dir, footer, debug)
+    extract_sidecar_inner(sidecar_path, cache_dir, footer, debug, progress)
     // Lock released on drop of lock_file
 }
 
@@ -175,6 +278,7 @@ fn extract_sidecar_inner(
     cache_dir: &Path,
     footer: &PackFooter,
     debug: bool,
+    progress: Option<&mut ExtractProgress<'_>>,
 ) -> std::io::Result<()> {
     fs::create_dir_all(cache_dir)?;
 
@@ -188,19 +292,14 @@ fn extract_sidecar_inner(
 
     let sidecar_file = File::open(sidecar_path)?;
     let limited_reader = sidecar_file.take(footer.assets_size);
-
-    let decoder = zstd::stream::Decoder::new(limited_reader)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-
-    let mut archive = tar::Archive::new(decoder);
-    safe_unpack(&mut archive, cache_dir)?;
-
-    if debug {
-        eprintln!("debug: extracted assets to {}", cache_dir.display());
-    }
-
-    post_process_extraction(cache_dir, debug)?;
-    Ok(())
+    unpack_assets(
+        limited_reader,
+        footer.assets_size,
+        footer.dictionary_size,
+        cache_dir,
+        debug,
+        progress,
+    )
 }
 
 /// Extract assets from a packed binary to the cache directory.
@@ -212,12 +311,13 @@ pub fn extract_from_binary(
     cache_dir: &Path,
     footer: &PackFooter,
     debug: bool,
+    progress: Option<&mut ExtractProgress<'_>>,
 ) -> std::io::Result<()> {
     fs::create_dir_all(cache_dir)?;
 
     if is_sidecar_mode(footer) {
         let sidecar = sidecar_path_for(exe_path);
-        extract_sidecar(&sidecar, cache_dir, footer, false, debug)
+        extract_sidecar(&sidecar, cache_dir, footer, false, debug, progress)
     } else {
         // Embedded mode: read compressed assets from the executable
         let mut exe_file = File::open(exe_path)?;
@@ -231,19 +331,14 @@ pub fn extract_from_binary(
         }
 
         let limited_reader = (&mut exe_file).take(footer.assets_size);
-
-        let decoder = zstd::stream::Decoder::new(limited_reader)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-
-        let mut archive = tar::Archive::new(decoder);
-        safe_unpack(&mut archive, cache_dir)?;
-
-        if debug {
-            eprintln!("debug: extracted assets to {}", cache_dir.display());
-        }
-
-        post_process_extraction(cache_dir, debug)?;
-        Ok(())
+        unpack_assets(
+            limited_reader,
+            footer.assets_size,
+            footer.dictionary_size,
+            cache_dir,
+            debug,
+            progress,
+        )
     }
 }
 
@@ -258,7 +353,9 @@ pub unsafe fn extract_from_section(
     cache_dir: &Path,
     assets_ptr: *const u8,
     assets_size: usize,
+    dictionary_size: u32,
     debug: bool,
+    progress: Option<&mut ExtractProgress<'_>>,
 ) -> std::io::Result<()> {
     fs::create_dir_all(cache_dir)?;
 
@@ -270,20 +367,14 @@ pub unsafe fn extract_from_section(
     }
 
     let assets_slice = unsafe { std::slice::from_raw_parts(assets_ptr, assets_size) };
-    let cursor = std::io::Cursor::new(assets_slice);
-
-    let decoder = zstd::stream::Decoder::new(cursor)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-
-    let mut archive = tar::Archive::new(decoder);
-    safe_unpack(&mut archive, cache_dir)?;
-
-    if debug {
-        eprintln!("debug: extracted assets to {}", cache_dir.display());
-    }
-
-    post_process_extraction(cache_dir, debug)?;
-    Ok(())
+    unpack_assets(
+        assets_slice,
+        assets_size as u64,
+        dictionary_size,
+        cache_dir,
+        debug,
+        progress,
+    )
 }
 
 /// Post-process extracted assets: unpack agent rootfs, OCI layers, fix permissions.
@@ -298,7 +389,7 @@ fn post_process_extraction(cache_dir: &Path, debug: bool) -> std::io::Result<()>
         fs::create_dir_all(&rootfs_dir)?;
         let tar_file = File::open(&rootfs_tar)?;
         let mut archive = tar::Archive::new(tar_file);
-        safe_unpack(&mut archive, &rootfs_dir)?;
+        safe_unpack(&mut archive, &rootfs_dir, |_| {})?;
     }
 
     // Extract OCI layer tars to layers/{digest}/ directories
@@ -320,7 +411,7 @@ fn post_process_extraction(cache_dir: &Path, debug: bool) -> std::io::Result<()>
                     fs::create_dir_all(&layer_dir)?;
                     let tar_file = File::open(&path)?;
                     let mut archive = tar::Archive::new(tar_file);
-                    safe_unpack(&mut archive, &layer_dir)?;
+                    safe_unpack(&mut archive, &layer_dir, |_| {})?;
                 }
             }
         }
@@ -376,7 +467,7 @@ pub fn cleanup_old_caches(keep: usize) -> std::io::Result<()> {
         }
     }
 
-    entries.sort_by(|a, b| b.1.cmp(&a.1));
+    entries.sort_by_key(|e| std::cmp::Reverse(e.1));
 
     for (path, _) in entries.into_iter().skip(keep) {
         let _ = fs::remove_dir_all(path);
@@ -520,6 +611,7 @@ mod tests {
             manifest_offset: 1000,
             manifest_size: 500,
             checksum: 0x12345678,
+            dictionary_size: 0,
         };
         assert!(is_sidecar_mode(&sidecar_footer));
 
@@ -530,6 +622,7 @@ mod tests {
             manifest_offset: 51000,
             manifest_size: 500,
             checksum: 0x12345678,
+            dictionary_size: 0,
         };
         assert!(!is_sidecar_mode(&embedded_footer));
     }
@@ -604,6 +697,7 @@ mod tests {
             manifest_offset: 0,
             manifest_size: 0,
             checksum: 0,
+            dictionary_size: 0,
         };
 
         // Should succeed without trying to open a nonexistent sidecar,
@@ -614,6 +708,7 @@ mod tests {
             &dummy_footer,
             false, // force=false
             false,
+            None,
         );
         // The sidecar doesn't exist, but we never try to open it because
         // the marker file is already present.
@@ -631,6 +726,7 @@ mod tests {
             &dummy_footer,
             false, // force=false
             false,
+            None,
         );
         assert!(result.is_ok());
     }
@@ -660,6 +756,7 @@ mod tests {
             manifest_offset: 22,
             manifest_size: 0,
             checksum: 0,
+            dictionary_size: 0,
         };
 
         let result = extract_sidecar(
@@ -668,6 +765,7 @@ mod tests {
             &dummy_footer,
             true, // force=true should bypass marker
             false,
+            None,
         );
 
         // Should fail during decompression (not short-circuit on marker),
@@ -677,4 +775,71 @@ mod tests {
             "force extraction should attempt (and fail on dummy data)"
         );
     }
+
+    #[test]
+    fn test_extract_sidecar_reports_progress() {
+        use crate::assets::AssetCollector;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut collector = AssetCollector::new(temp_dir.path().join("staging")).unwrap();
+        // Layers of incompressible data larger than the report interval
+        let mut state = 0x2545_f491_u32;
+        let mut noise = |len: usize| -> Vec<u8> {
+            let data: Vec<u8> = (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect();
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            let mut layer = tar::Builder::new(Vec::new());
+            layer.append_data(&mut header, "noise", &data[..]).unwrap();
+            layer.into_inner().unwrap()
+        };
+        collector
+            .add_layer("sha256:aaaaaaaaaaaa0000", &noise(3 * 1024 * 1024))
+            .unwrap();
+        collector
+            .add_layer("sha256:bbbbbbbbbbbb0000", &noise(2 * 1024 * 1024))
+            .unwrap();
+
+        let sidecar = temp_dir.path().join("progress.smolmachine");
+        let assets_size = collector.compress(&sidecar).unwrap().size;
+        let footer = PackFooter {
+            stub_size: 0,
+            assets_offset: 0,
+            assets_size,
+            manifest_offset: assets_size,
+            manifest_size: 0,
+            checksum: 0,
+            dictionary_size: 0,
+        };
+
+        let mut reports: Vec<(u64, u64, String)> = Vec::new();
+        let mut record = |read: u64, total: u64, entry: &str| {
+            reports.push((read, total, entry.to_string()));
+        };
+        extract_sidecar(
+            &sidecar,
+            &temp_dir.path().join("cache"),
+            &footer,
+            false,
+            false,
+            Some(&mut record),
+        )
+        .unwrap();
+
+        assert!(reports.len() > 2);
+        assert!(reports.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(reports.iter().all(|r| r.1 == assets_size));
+        let (last_read, _, _) = reports.last().unwrap();
+        assert!(*last_read > 0 && *last_read <= assets_size);
+        assert!(reports.iter().any(|r| r.2 == "layers/aaaaaaaaaaaa.tar"));
+        assert!(reports.iter().any(|r| r.2 == "layers/bbbbbbbbbbbb.tar"));
+    }
 }
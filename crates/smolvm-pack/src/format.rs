@@ -18,6 +18,14 @@ pub const SECTION_MAGIC: &[u8; 8] = b"SMOLSECT";
 /// Version 2: Assets in sidecar file (.smolmachine)
 pub const FORMAT_VERSION: u32 = 2;
 
+/// Current manifest format version.
+///
+/// Bumped whenever a manifest field changes meaning or becomes required.
+/// Fields added with a default don't need a bump: older manifests simply
+/// lack them. Manifests written before versioning was introduced carry no
+/// version and are read as version 1.
+pub const MANIFEST_FORMAT_VERSION: u32 = 1;
+
 /// Extension for sidecar assets file.
 pub const SIDECAR_EXTENSION: &str = ".smolmachine";
 
@@ -40,7 +48,7 @@ pub const SECTION_HEADER_SIZE: usize = 32;
 /// 12      4     manifest_size (u32 LE)
 /// 16      8     assets_size (u64 LE)
 /// 24      4     checksum (u32 LE)
-/// 28      4     reserved (zeroes)
+/// 28      4     dictionary_size (u32 LE) - 0 if no dictionary
 /// ```
 ///
 /// Following the header:
@@ -54,6 +62,9 @@ pub struct SectionHeader {
     pub assets_size: u64,
     /// CRC32 checksum of manifest + assets.
     pub checksum: u32,
+    /// Size of the zstd dictionary at the start of the assets, 0 if the
+    /// assets were compressed without one.
+    pub dictionary_size: u32,
 }
 
 impl SectionHeader {
@@ -76,7 +87,8 @@ impl SectionHeader {
         // Checksum
         buf[24..28].copy_from_slice(&self.checksum.to_le_bytes());
 
-        // Reserved (already zeroed)
+        // Dictionary size
+        buf[28..32].copy_from_slice(&self.dictionary_size.to_le_bytes());
 
         buf
     }
@@ -104,6 +116,7 @@ impl SectionHeader {
                 buf[16], buf[17], buf[18], buf[19], buf[20], buf[21], buf[22], buf[23],
             ]),
             checksum: u32::from_le_bytes([buf[24], buf[25], buf[26], buf[27]]),
+            dictionary_size: u32::from_le_bytes([buf[28], buf[29], buf[30], buf[31]]),
         })
     }
 }
@@ -121,7 +134,9 @@ impl SectionHeader {
 /// 36      8     manifest_offset (u64 LE) - offset to manifest JSON
 /// 44      8     manifest_size (u64 LE) - size of manifest JSON
 /// 52      4     checksum (u32 LE) - CRC32 of assets + manifest
-/// 56      8     reserved (zeroes)
+/// 56      4     dictionary_size (u32 LE) - size of the zstd dictionary
+///               at the start of the assets, 0 if none
+/// 60      4     reserved (zeroes)
 /// ```
 #[derive(Debug, Clone, Copy)]
 pub struct PackFooter {
@@ -137,6 +152,9 @@ pub struct PackFooter {
     pub manifest_size: u64,
     /// CRC32 checksum of assets + manifest.
     pub checksum: u32,
+    /// Size of the zstd dictionary at the start of the assets blob, 0 if
+    /// the assets were compressed without one.
+    pub dictionary_size: u32,
 }
 
 impl PackFooter {
@@ -164,6 +182,9 @@ impl PackFooter {
         // Checksum
         buf[52..56].copy_from_slice(&self.checksum.to_le_bytes());
 
+        // Dictionary size
+        buf[56..60].copy_from_slice(&self.dictionary_size.to_le_bytes());
+
         // Reserved (already zeroed)
 
         buf
@@ -199,6 +220,7 @@ impl PackFooter {
                 buf[44], buf[45], buf[46], buf[47], buf[48], buf[49], buf[50], buf[51],
             ]),
             checksum: u32::from_le_bytes([buf[52], buf[53], buf[54], buf[55]]),
+            dictionary_size: u32::from_le_bytes([buf[56], buf[57], buf[58], buf[59]]),
         })
     }
 }
@@ -221,6 +243,10 @@ pub enum PackMode {
 /// Manifest describing the packed image and configuration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackManifest {
+    /// Manifest format version (see [`MANIFEST_FORMAT_VERSION`]).
+    #[serde(default = "legacy_manifest_version")]
+    pub format_version: u32,
+
     /// Execution mode (container or VM).
     #[serde(default)]
     pub mode: PackMode,
@@ -310,6 +336,7 @@ impl PackManifest {
     /// Create a new manifest with default values.
     pub fn new(image: String, digest: String, platform: String) -> Self {
         Self {
+            format_version: MANIFEST_FORMAT_VERSION,
             mode: PackMode::default(),
             image,
             digest,
@@ -339,11 +366,33 @@ impl PackManifest {
     }
 
     /// Deserialize manifest from JSON.
+    ///
+    /// Fields missing from manifests written by older versions take their
+    /// defaults. A manifest from a newer format version is rejected before
+    /// its fields are interpreted, since they may have changed meaning.
     pub fn from_json(data: &[u8]) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Version {
+            #[serde(default = "legacy_manifest_version")]
+            format_version: u32,
+        }
+
+        let Version { format_version } = serde_json::from_slice(data)?;
+        if format_version > MANIFEST_FORMAT_VERSION {
+            return Err(PackError::UnsupportedManifestVersion {
+                found: format_version,
+                supported: MANIFEST_FORMAT_VERSION,
+            });
+        }
         Ok(serde_json::from_slice(data)?)
     }
 }
 
+/// Version of manifests written before the format was versioned.
+fn legacy_manifest_version() -> u32 {
+    1
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -357,6 +406,7 @@ mod tests {
             manifest_offset: 512 * 1024 + 50 * 1024 * 1024,
             manifest_size: 2048,
             checksum: 0xDEADBEEF,
+            dictionary_size: 64 * 1024,
         };
 
         let bytes = footer.to_bytes();
@@ -369,6 +419,13 @@ mod tests {
         assert_eq!(restored.manifest_offset, footer.manifest_offset);
         assert_eq!(restored.manifest_size, footer.manifest_size);
         assert_eq!(restored.checksum, footer.checksum);
+        assert_eq!(restored.dictionary_size, footer.dictionary_size);
+
+        // Footers written before dictionaries have zeroes there
+        let mut bytes = bytes;
+        bytes[56..64].fill(0);
+        let restored = PackFooter::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.dictionary_size, 0);
     }
 
     #[test]
@@ -459,6 +516,53 @@ mod tests {
         assert!(manifest.assets.overlay_template.is_none());
     }
 
+    #[test]
+    fn test_manifest_without_version_reads_as_legacy() {
+        // Written before format_version, workdir and storage templates existed
+        let json = br#"{
+            "image": "alpine:latest",
+            "digest": "sha256:abc",
+            "platform": "linux/arm64",
+            "cpus": 1,
+            "mem": 256,
+            "assets": {
+                "libraries": [],
+                "agent_rootfs": { "path": "rootfs.tar", "size": 1024 },
+                "layers": []
+            }
+        }"#;
+
+        let manifest = PackManifest::from_json(json).unwrap();
+        assert_eq!(manifest.format_version, 1);
+        assert!(manifest.entrypoint.is_empty());
+        assert!(manifest.workdir.is_none());
+        assert!(manifest.assets.storage_template.is_none());
+
+        // Re-serializing stamps the version
+        let json = String::from_utf8(manifest.to_json().unwrap()).unwrap();
+        assert!(json.contains("\"format_version\": 1"));
+    }
+
+    #[test]
+    fn test_manifest_from_newer_version_rejected() {
+        let json = format!(
+            r#"{{
+                "format_version": {},
+                "image": "alpine:latest",
+                "layout": "something this build doesn't understand"
+            }}"#,
+            MANIFEST_FORMAT_VERSION + 1
+        );
+
+        let err = PackManifest::from_json(json.as_bytes()).unwrap_err();
+        assert!(matches!(
+            err,
+            PackError::UnsupportedManifestVersion { found, supported }
+                if found == MANIFEST_FORMAT_VERSION + 1 && supported == MANIFEST_FORMAT_VERSION
+        ));
+        assert!(err.to_string().contains("please upgrade smolvm"));
+    }
+
     #[test]
     fn test_pack_mode_vm_roundtrip() {
         let mut manifest = PackManifest::new(
@@ -47,12 +47,12 @@ pub mod signing;
 
 pub use detect::{detect_packed_mode, PackedMode};
 pub use format::{
-    PackFooter, PackManifest, PackMode, SectionHeader, FOOTER_SIZE, MAGIC, SECTION_HEADER_SIZE,
-    SECTION_MAGIC, SIDECAR_EXTENSION,
+    PackFooter, PackManifest, PackMode, SectionHeader, FOOTER_SIZE, MAGIC, MANIFEST_FORMAT_VERSION,
+    SECTION_HEADER_SIZE, SECTION_MAGIC, SIDECAR_EXTENSION,
 };
 pub use packer::{
     read_footer, read_footer_from_sidecar, read_manifest, read_manifest_from_sidecar,
-    sidecar_path_for, verify_sidecar_checksum, Packer,
+    sidecar_path_for, verify_sidecar_checksum, PackPlan, Packer,
 };
 
 use thiserror::Error;
@@ -76,6 +76,18 @@ pub enum PackError {
     #[error("unsupported version: {0}")]
     UnsupportedVersion(u32),
 
+    /// Manifest written by a newer smolvm than this one.
+    #[error(
+        "this binary was packed with a newer smolvm (manifest format {found}, \
+         this build reads up to {supported}); please upgrade smolvm"
+    )]
+    UnsupportedManifestVersion {
+        /// Format version found in the manifest.
+        found: u32,
+        /// Newest format version this build can read.
+        supported: u32,
+    },
+
     /// Checksum mismatch.
     #[error("checksum mismatch: expected {expected:08x}, got {actual:08x}")]
     ChecksumMismatch {
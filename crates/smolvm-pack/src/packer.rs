@@ -10,7 +10,7 @@ use std::fs::{self, File};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 
-use crate::assets::{crc32_file_range, AssetCollector};
+use crate::assets::{crc32_file_range, AssetCollector, CompressedAssets};
 use crate::format::{PackFooter, PackManifest, FOOTER_SIZE, SIDECAR_EXTENSION};
 use crate::Result;
 
@@ -72,6 +72,56 @@ impl Packer {
         &mut self.manifest
     }
 
+    /// Report the sizes a pack would produce without writing any output.
+    ///
+    /// Compresses the assets to a temporary file exactly as [`pack`] does,
+    /// so the sizes match a subsequent pack. The embedded total assumes the
+    /// append layout; Mach-O section packing on macOS also adds section
+    /// alignment and a code signature.
+    ///
+    /// [`pack`]: Self::pack
+    pub fn plan(&self) -> Result<PackPlan> {
+        let temp_dir = tempfile::tempdir()?;
+
+        let stub_path = self
+            .stub_path
+            .as_ref()
+            .ok_or_else(|| crate::PackError::AssetNotFound("stub executable".to_string()))?;
+        let stub_size = fs::metadata(stub_path)?.len();
+
+        let assets_size = self
+            .compress_assets(&temp_dir.path().join("assets.tar.zst"))?
+            .size;
+        let manifest_size = self.manifest.to_json()?.len() as u64;
+
+        let sidecar_size = assets_size + manifest_size + FOOTER_SIZE as u64;
+        Ok(PackPlan {
+            stub_size,
+            assets_size,
+            manifest_size,
+            sidecar_size,
+            sidecar_total_size: stub_size + sidecar_size,
+            embedded_total_size: stub_size + assets_size + manifest_size + FOOTER_SIZE as u64,
+        })
+    }
+
+    /// Compress the collected assets (or an empty archive if there are
+    /// none) to `dest`.
+    fn compress_assets(&self, dest: &Path) -> Result<CompressedAssets> {
+        if let Some(collector) = &self.asset_collector {
+            return collector.compress(dest);
+        }
+        let empty_file = File::create(dest)?;
+        let encoder = zstd::stream::Encoder::new(empty_file, 1)?;
+        let tar_builder = tar::Builder::new(encoder);
+        let encoder = tar_builder.into_inner()?;
+        encoder.finish()?;
+        Ok(CompressedAssets {
+            size: fs::metadata(dest)?.len(),
+            dictionary_size: 0,
+        })
+    }
+
     /// Pack everything into the output file using sidecar format.
     ///
     /// Creates two files:
@@ -110,16 +160,10 @@ impl Packer {
 
         // 2a. Write compressed assets
         let assets_temp = temp_dir.path().join("assets.tar.zst");
-        let assets_size = if let Some(collector) = &self.asset_collector {
-            collector.compress(&assets_temp)?
-        } else {
-            let empty_file = File::create(&assets_temp)?;
-            let encoder = zstd::stream::Encoder::new(empty_file, 1)?;
-            let tar_builder = tar::Builder::new(encoder);
-            let encoder = tar_builder.into_inner()?;
-            encoder.finish()?;
-            fs::metadata(&assets_temp)?.len()
-        };
+        let CompressedAssets {
+            size: assets_size,
+            dictionary_size,
+        } = self.compress_assets(&assets_temp)?;
 
         let mut assets_file = File::open(&assets_temp)?;
         std::io::copy(&mut assets_file, &mut sidecar_file)?;
@@ -144,6 +188,7 @@ impl Packer {
             manifest_offset,
             manifest_size,
             checksum,
+            dictionary_size,
         };
 
         let mut sidecar_file = fs::OpenOptions::new().append(true).open(&sidecar_path)?;
@@ -251,16 +296,10 @@ impl Packer {
 
         // Compress assets
         let assets_temp = temp_dir.path().join("assets.tar.zst");
-        let assets_size = if let Some(collector) = &self.asset_collector {
-            collector.compress(&assets_temp)?
-        } else {
-            let empty_file = File::create(&assets_temp)?;
-            let encoder = zstd::stream::Encoder::new(empty_file, 1)?;
-            let tar_builder = tar::Builder::new(encoder);
-            let encoder = tar_builder.into_inner()?;
-            encoder.finish()?;
-            fs::metadata(&assets_temp)?.len()
-        };
+        let CompressedAssets {
+            size: assets_size,
+            dictionary_size,
+        } = self.compress_assets(&assets_temp)?;
 
         // Serialize manifest
         let manifest_json = self.manifest.to_json()?;
@@ -278,6 +317,7 @@ impl Packer {
             manifest_size,
             assets_size,
             checksum,
+            dictionary_size,
         };
 
         let mut section_data =
@@ -342,16 +382,10 @@ impl Packer {
 
         // 2. Compress and append assets
         let assets_temp = temp_dir.path().join("assets.tar.zst");
-        let assets_size = if let Some(collector) = &self.asset_collector {
-            collector.compress(&assets_temp)?
-        } else {
-            let empty_file = File::create(&assets_temp)?;
-            let encoder = zstd::stream::Encoder::new(empty_file, 1)?;
-            let tar_builder = tar::Builder::new(encoder);
-            let encoder = tar_builder.into_inner()?;
-            encoder.finish()?;
-            fs::metadata(&assets_temp)?.len()
-        };
+        let CompressedAssets {
+            size: assets_size,
+            dictionary_size,
+        } = self.compress_assets(&assets_temp)?;
 
         let assets_offset = stub_size; // Assets start right after stub
         let mut assets_file = File::open(&assets_temp)?;
@@ -377,6 +411,7 @@ impl Packer {
             manifest_offset,
             manifest_size,
             checksum,
+            dictionary_size,
         };
 
         let mut output_file = fs::OpenOptions::new().append(true).open(output)?;
@@ -432,6 +467,23 @@ pub struct PackedInfo {
     pub sidecar_path: Option<PathBuf>,
 }
 
+/// Projected sizes of a pack, from [`Packer::plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackPlan {
+    /// Size of stub executable.
+    pub stub_size: u64,
+    /// Size of compressed assets.
+    pub assets_size: u64,
+    /// Size of manifest JSON.
+    pub manifest_size: u64,
+    /// Size of the `.smolmachine` sidecar in sidecar mode.
+    pub sidecar_size: u64,
+    /// Total size (binary + sidecar) in sidecar mode.
+    pub sidecar_total_size: u64,
+    /// Size of the single executable in embedded mode.
+    pub embedded_total_size: u64,
+}
+
 /// Read footer from a sidecar file.
 ///
 /// Validates structural bounds: footer-derived sizes must be consistent with
@@ -636,7 +688,11 @@ pub fn extract_assets(packed_path: impl AsRef<Path>, output_dir: impl AsRef<Path
     if is_sidecar_mode(&footer) {
         // Sidecar mode: read from .smolmachine file
         let sidecar = sidecar_path_for(packed_path.as_ref());
-        crate::assets::decompress_assets_from_file(&sidecar, output_dir.as_ref())?;
+        crate::assets::decompress_assets_from_file(
+            &sidecar,
+            footer.dictionary_size,
+            output_dir.as_ref(),
+        )?;
     } else {
         // Embedded mode: read from the binary itself
         let mut file = File::open(packed_path.as_ref())?;
@@ -647,7 +703,7 @@ pub fn extract_assets(packed_path: impl AsRef<Path>, output_dir: impl AsRef<Path
         file.read_exact(&mut compressed)?;
 
         // Decompress
-        crate::assets::decompress_assets(&compressed, output_dir.as_ref())?;
+        crate::assets::decompress_assets(&compressed, footer.dictionary_size, output_dir.as_ref())?;
     }
 
     Ok(())
@@ -743,6 +799,55 @@ mod tests {
         assert_eq!(fs::read_to_string(&layer_file).unwrap(), "layer content");
     }
 
+    #[test]
+    fn test_plan_matches_pack() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let stub_path = temp_dir.path().join("stub");
+        fs::write(&stub_path, b"#!/bin/sh\necho stub").unwrap();
+
+        let staging = temp_dir.path().join("staging");
+        let mut collector = AssetCollector::new(staging.clone()).unwrap();
+        collector
+            .add_layer("sha256:abc123def456", &b"layer content ".repeat(1024))
+            .unwrap();
+        let manifest = PackManifest::new(
+            "test:latest".to_string(),
+            "sha256:test".to_string(),
+            "linux/arm64".to_string(),
+        );
+
+        let packer = Packer::new(manifest)
+            .with_stub(&stub_path)
+            .with_assets(collector);
+        let embedded_packer = Packer::new(packer.manifest.clone())
+            .with_stub(&stub_path)
+            .with_asset_collector(AssetCollector::new(staging).unwrap());
+
+        // Planning writes nothing
+        let plan = packer.plan().unwrap();
+        let output_path = temp_dir.path().join("packed");
+        assert!(!output_path.exists());
+        assert!(!sidecar_path_for(&output_path).exists());
+
+        let info = packer.pack(&output_path).unwrap();
+        assert_eq!(plan.stub_size, info.stub_size);
+        assert_eq!(plan.assets_size, info.assets_size);
+        assert_eq!(plan.manifest_size, info.manifest_size);
+        assert_eq!(plan.sidecar_total_size, info.total_size);
+        assert_eq!(
+            plan.sidecar_size,
+            fs::metadata(info.sidecar_path.unwrap()).unwrap().len()
+        );
+
+        let embedded_path = temp_dir.path().join("packed-single");
+        let info = embedded_packer.pack_embedded(&embedded_path).unwrap();
+        assert_eq!(plan.embedded_total_size, info.total_size);
+        assert_eq!(
+            plan.embedded_total_size,
+            fs::metadata(&embedded_path).unwrap().len()
+        );
+    }
+
     #[test]
     fn test_pack_embedded() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -890,6 +995,7 @@ mod tests {
             manifest_offset: 100,
             manifest_size: 32 * 1024 * 1024, // 32 MiB — exceeds cap
             checksum: 0,
+            dictionary_size: 0,
         };
 
         // Write a minimal sidecar: some bytes + footer
@@ -918,6 +1024,7 @@ mod tests {
             manifest_offset: 50, // should be 100 — points into assets region
             manifest_size: 50,
             checksum: 0,
+            dictionary_size: 0,
         };
 
         let footer_bytes = footer.to_bytes();
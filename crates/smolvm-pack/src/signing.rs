@@ -127,6 +127,7 @@ pub struct SignatureInfo {
 }
 
 #[cfg(test)]
+#[cfg(target_os = "macos")]
 mod tests {
     use super::*;
     use std::io::Write;
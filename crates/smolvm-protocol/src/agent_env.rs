@@ -0,0 +1,34 @@
+//! Agent settings taken from its environment.
+//!
+//! The host passes each of [`FORWARDED`] on to the agent at boot if it is
+//! set in the host's own environment, so a setting is made the same way
+//! whichever side reads it.
+
+/// Environment variable overriding the limit on one environment variable's
+/// value given to a workload, in bytes.
+pub const MAX_ENV_VALUE_BYTES_ENV: &str = "SMOLVM_MAX_ENV_VALUE_BYTES";
+
+/// Environment variable overriding the limit on a workload's whole
+/// environment, in bytes.
+pub const MAX_ENV_BYTES_ENV: &str = "SMOLVM_MAX_ENV_BYTES";
+
+/// Environment variable capping the requests the agent serves on one
+/// connection (pings excluded). Unset means unlimited.
+pub const MAX_REQUESTS_ENV: &str = "SMOLVM_AGENT_MAX_REQUESTS";
+
+/// Environment variable capping how long the agent serves one connection,
+/// in seconds. Unset means unlimited.
+pub const MAX_CONNECTION_SECS_ENV: &str = "SMOLVM_AGENT_MAX_CONNECTION_SECS";
+
+/// Environment variable enabling deduplication of identical files across
+/// extracted layers (`1`). Set by the host from its `layer_dedup` setting
+/// rather than forwarded.
+pub const LAYER_DEDUP_ENV: &str = "SMOLVM_LAYER_DEDUP";
+
+/// Settings the host forwards to the agent.
+pub const FORWARDED: &[&str] = &[
+    MAX_ENV_VALUE_BYTES_ENV,
+    MAX_ENV_BYTES_ENV,
+    MAX_REQUESTS_ENV,
+    MAX_CONNECTION_SECS_ENV,
+];
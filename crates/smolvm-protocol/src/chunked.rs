@@ -0,0 +1,480 @@
+//! Chunked transfer of payloads larger than a single frame.
+//!
+//! [`decode_message`](crate::decode_message) rejects frames over
+//! [`MAX_FRAME_SIZE`], so a large payload (a copied file, a committed
+//! overlay) is sent as a [`ChunkedHeader`] declaring its total length,
+//! followed by [`Chunk`] frames numbered from 0, the final one marked
+//! `last`. Each frame is an ordinary length-prefixed JSON frame.
+//!
+//! ```text
+//! header { total_len } | chunk { seq: 0 } | chunk { seq: 1 } | ... | chunk { seq: n, last }
+//! ```
+//!
+//! [`write_chunked`] and [`read_chunked`] do this over a byte stream;
+//! [`copy_to_chunked`] and [`copy_from_chunked`] do the same for payloads
+//! too large to hold in memory, such as image archives. [`chunks`] and
+//! [`Reassembler`] are the building blocks for transports that frame
+//! messages themselves.
+
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+use crate::{decode_json, encode_message, DecodeError, MAX_FRAME_SIZE};
+
+/// Payload bytes per chunk (~21 MB once base64-encoded in JSON).
+pub const CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+// Base64 grows data by 4/3; leave room for the JSON around it.
+const _: () = assert!(CHUNK_SIZE / 3 * 4 + 4096 <= MAX_FRAME_SIZE as usize);
+
+/// First frame of a chunked transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkedHeader {
+    /// Length of the whole payload in bytes.
+    pub total_len: u64,
+}
+
+/// One piece of a chunked payload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    /// Position of this chunk, starting at 0.
+    pub seq: u32,
+    /// Whether this is the final chunk.
+    pub last: bool,
+    /// Payload bytes.
+    #[serde(with = "crate::base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// Error in a chunked transfer.
+#[derive(Debug)]
+pub enum ChunkError {
+    /// Reading or writing the stream failed.
+    Io(std::io::Error),
+    /// A frame could not be decoded.
+    Decode(DecodeError),
+    /// The declared length exceeds what the receiver accepts.
+    TooLarge {
+        /// Declared payload length.
+        total_len: u64,
+        /// Receiver's limit.
+        max_len: u64,
+    },
+    /// A chunk arrived out of order, or after the last one.
+    OutOfSequence {
+        /// Sequence number expected next.
+        expected: u32,
+        /// Sequence number received.
+        got: u32,
+    },
+    /// The chunks don't add up to the declared length.
+    LengthMismatch {
+        /// Declared payload length.
+        expected: u64,
+        /// Bytes received so far.
+        got: u64,
+    },
+}
+
+impl std::fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChunkError::Io(e) => write!(f, "chunked transfer I/O error: {}", e),
+            ChunkError::Decode(e) => write!(f, "chunked transfer decode error: {}", e),
+            ChunkError::TooLarge { total_len, max_len } => write!(
+                f,
+                "chunked payload too large: {} bytes (limit {})",
+                total_len, max_len
+            ),
+            ChunkError::OutOfSequence { expected, got } => {
+                write!(
+                    f,
+                    "chunk out of sequence: expected {}, got {}",
+                    expected, got
+                )
+            }
+            ChunkError::LengthMismatch { expected, got } => write!(
+                f,
+                "chunked payload length mismatch: declared {} bytes, received {}",
+                expected, got
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChunkError {}
+
+impl From<std::io::Error> for ChunkError {
+    fn from(e: std::io::Error) -> Self {
+        ChunkError::Io(e)
+    }
+}
+
+impl From<DecodeError> for ChunkError {
+    fn from(e: DecodeError) -> Self {
+        ChunkError::Decode(e)
+    }
+}
+
+/// Split `payload` into chunks of at most `chunk_size` bytes.
+///
+/// An empty payload is a single empty `last` chunk.
+pub fn chunks(payload: &[u8], chunk_size: usize) -> impl Iterator<Item = Chunk> + '_ {
+    let count = payload.len().div_ceil(chunk_size).max(1);
+    (0..count).map(move |i| {
+        let start = i * chunk_size;
+        let end = (start + chunk_size).min(payload.len());
+        Chunk {
+            seq: i as u32,
+            last: i + 1 == count,
+            data: payload[start..end].to_vec(),
+        }
+    })
+}
+
+/// Checks that chunks arrive in order and add up to the declared length.
+#[derive(Debug)]
+struct Sequence {
+    total_len: u64,
+    received: u64,
+    next_seq: u32,
+    complete: bool,
+}
+
+impl Sequence {
+    fn new(header: ChunkedHeader, max_len: u64) -> Result<Self, ChunkError> {
+        if header.total_len > max_len {
+            return Err(ChunkError::TooLarge {
+                total_len: header.total_len,
+                max_len,
+            });
+        }
+        Ok(Self {
+            total_len: header.total_len,
+            received: 0,
+            next_seq: 0,
+            complete: false,
+        })
+    }
+
+    /// Account for `chunk`. Returns `true` if it was the last one.
+    fn accept(&mut self, chunk: &Chunk) -> Result<bool, ChunkError> {
+        if self.complete || chunk.seq != self.next_seq {
+            return Err(ChunkError::OutOfSequence {
+                expected: self.next_seq,
+                got: chunk.seq,
+            });
+        }
+
+        let received = self.received + chunk.data.len() as u64;
+        if received > self.total_len || (chunk.last && received != self.total_len) {
+            return Err(ChunkError::LengthMismatch {
+                expected: self.total_len,
+                got: received,
+            });
+        }
+
+        self.received = received;
+        self.next_seq += 1;
+        self.complete = chunk.last;
+        Ok(self.complete)
+    }
+
+    fn check_complete(&self) -> Result<(), ChunkError> {
+        if !self.complete {
+            return Err(ChunkError::LengthMismatch {
+                expected: self.total_len,
+                got: self.received,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Reassembles a payload from chunks, checking their order and length.
+#[derive(Debug)]
+pub struct Reassembler {
+    sequence: Sequence,
+    buf: Vec<u8>,
+}
+
+impl Reassembler {
+    /// Start reassembling the payload announced by `header`.
+    ///
+    /// Fails if the payload is longer than `max_len`.
+    pub fn new(header: ChunkedHeader, max_len: u64) -> Result<Self, ChunkError> {
+        Ok(Self {
+            sequence: Sequence::new(header, max_len)?,
+            // The length is only a claim until the chunks arrive
+            buf: Vec::with_capacity(header.total_len.min(CHUNK_SIZE as u64) as usize),
+        })
+    }
+
+    /// Add the next chunk. Returns `true` once the last chunk is in.
+    pub fn push(&mut self, chunk: Chunk) -> Result<bool, ChunkError> {
+        let last = self.sequence.accept(&chunk)?;
+        self.buf.extend_from_slice(&chunk.data);
+        Ok(last)
+    }
+
+    /// Whether the last chunk has been received.
+    pub fn is_complete(&self) -> bool {
+        self.sequence.complete
+    }
+
+    /// The reassembled payload. Fails if the last chunk hasn't arrived.
+    pub fn finish(self) -> Result<Vec<u8>, ChunkError> {
+        self.sequence.check_complete()?;
+        Ok(self.buf)
+    }
+}
+
+/// Write `payload` to `writer` as a chunked transfer.
+pub fn write_chunked<W: Write>(writer: &mut W, payload: &[u8]) -> Result<(), ChunkError> {
+    copy_to_chunked(&mut &*payload, writer, payload.len() as u64)
+}
+
+/// Read a chunked transfer from `reader`, accepting at most `max_len` bytes.
+pub fn read_chunked<R: Read>(reader: &mut R, max_len: u64) -> Result<Vec<u8>, ChunkError> {
+    let header: ChunkedHeader = read_frame(reader)?;
+    let mut reassembler = Reassembler::new(header, max_len)?;
+    while !reassembler.push(read_frame(reader)?)? {}
+    reassembler.finish()
+}
+
+/// Send the next `len` bytes of `reader` to `writer` as a chunked
+/// transfer, holding one chunk in memory at a time.
+///
+/// Fails with [`ChunkError::LengthMismatch`] if `reader` ends early.
+pub fn copy_to_chunked<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    len: u64,
+) -> Result<(), ChunkError> {
+    write_frame(writer, &ChunkedHeader { total_len: len })?;
+    let mut sent = 0u64;
+    let mut seq = 0;
+    loop {
+        let want = (len - sent).min(CHUNK_SIZE as u64);
+        let mut data = Vec::with_capacity(want as usize);
+        reader.by_ref().take(want).read_to_end(&mut data)?;
+        if (data.len() as u64) < want {
+            return Err(ChunkError::LengthMismatch {
+                expected: len,
+                got: sent + data.len() as u64,
+            });
+        }
+        sent += want;
+        let last = sent == len;
+        write_frame(writer, &Chunk { seq, last, data })?;
+        if last {
+            break;
+        }
+        seq += 1;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a chunked transfer from `reader` into `writer` as it arrives,
+/// accepting at most `max_len` bytes. Returns the payload's length.
+///
+/// On error, `writer` may have been given part of the payload.
+pub fn copy_from_chunked<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    max_len: u64,
+) -> Result<u64, ChunkError> {
+    let header: ChunkedHeader = read_frame(reader)?;
+    let mut sequence = Sequence::new(header, max_len)?;
+    loop {
+        let chunk: Chunk = read_frame(reader)?;
+        let last = sequence.accept(&chunk)?;
+        writer.write_all(&chunk.data)?;
+        if last {
+            break;
+        }
+    }
+    writer.flush()?;
+    Ok(sequence.received)
+}
+
+fn write_frame<W: Write, T: Serialize>(writer: &mut W, msg: &T) -> Result<(), ChunkError> {
+    let frame = encode_message(msg).map_err(|e| ChunkError::Decode(DecodeError::Json(e)))?;
+    writer.write_all(&frame)?;
+    Ok(())
+}
+
+fn read_frame<R: Read, T: for<'de> Deserialize<'de>>(reader: &mut R) -> Result<T, ChunkError> {
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header)?;
+    let len = u32::from_be_bytes(header) as usize;
+    if len > MAX_FRAME_SIZE as usize {
+        return Err(DecodeError::TooLarge(len).into());
+    }
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf)?;
+    Ok(decode_json(&buf)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Split `data` back into individual length-prefixed frames.
+    fn split_frames(mut data: &[u8]) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        while !data.is_empty() {
+            let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+            frames.push(data[..4 + len].to_vec());
+            data = &data[4 + len..];
+        }
+        frames
+    }
+
+    #[test]
+    fn test_round_trip_large_payload() {
+        let payload: Vec<u8> = (0..40 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+
+        let mut wire = Vec::new();
+        write_chunked(&mut wire, &payload).unwrap();
+
+        let frames = split_frames(&wire);
+        // Header plus three chunks (16 + 16 + 8 MB)
+        assert_eq!(frames.len(), 4);
+        assert!(frames
+            .iter()
+            .all(|f| f.len() - 4 <= MAX_FRAME_SIZE as usize));
+
+        let received = read_chunked(&mut Cursor::new(&wire), u64::MAX).unwrap();
+        assert_eq!(received.len(), payload.len());
+        assert!(received == payload);
+
+        // Small and empty payloads still use the chunked framing
+        for payload in [&b""[..], b"hello"] {
+            let mut wire = Vec::new();
+            write_chunked(&mut wire, payload).unwrap();
+            assert_eq!(
+                read_chunked(&mut Cursor::new(&wire), 1024).unwrap(),
+                payload
+            );
+        }
+    }
+
+    #[test]
+    fn test_streaming_copy_round_trip() {
+        let payload: Vec<u8> = (0..CHUNK_SIZE as u32 + 5).map(|i| (i % 13) as u8).collect();
+
+        let mut wire = Vec::new();
+        copy_to_chunked(&mut Cursor::new(&payload), &mut wire, payload.len() as u64).unwrap();
+        // Same framing as the in-memory writer
+        let mut expected = Vec::new();
+        write_chunked(&mut expected, &payload).unwrap();
+        assert!(wire == expected);
+
+        let mut received = Vec::new();
+        let len = copy_from_chunked(&mut Cursor::new(&wire), &mut received, u64::MAX).unwrap();
+        assert_eq!(len, payload.len() as u64);
+        assert!(received == payload);
+
+        // A source shorter than the declared length is caught by the sender
+        let err = copy_to_chunked(&mut &b"short"[..], &mut Vec::new(), 10).unwrap_err();
+        assert!(matches!(
+            err,
+            ChunkError::LengthMismatch {
+                expected: 10,
+                got: 5
+            }
+        ));
+
+        // And the receiver enforces its limit before taking any data
+        let err = copy_from_chunked(&mut Cursor::new(&wire), &mut Vec::new(), 100).unwrap_err();
+        assert!(matches!(err, ChunkError::TooLarge { .. }));
+    }
+
+    #[test]
+    fn test_out_of_order_chunks_are_rejected() {
+        let payload = vec![7u8; 100];
+        let header = ChunkedHeader { total_len: 100 };
+        let mut parts: Vec<Chunk> = chunks(&payload, 30).collect();
+        assert_eq!(parts.len(), 4);
+        parts.swap(1, 2);
+
+        let mut wire = Vec::new();
+        write_frame(&mut wire, &header).unwrap();
+        for chunk in &parts {
+            write_frame(&mut wire, chunk).unwrap();
+        }
+        let err = read_chunked(&mut Cursor::new(&wire), 1024).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ChunkError::OutOfSequence {
+                    expected: 1,
+                    got: 2
+                }
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_corrupted_sequence_errors() {
+        let payload = vec![1u8; 100];
+        let header = ChunkedHeader { total_len: 100 };
+        let parts: Vec<Chunk> = chunks(&payload, 50).collect();
+
+        // Duplicated chunk
+        let mut r = Reassembler::new(header, 1024).unwrap();
+        r.push(parts[0].clone()).unwrap();
+        assert!(matches!(
+            r.push(parts[0].clone()),
+            Err(ChunkError::OutOfSequence {
+                expected: 1,
+                got: 0
+            })
+        ));
+
+        // Chunk after the last one
+        let mut r = Reassembler::new(header, 1024).unwrap();
+        assert!(!r.push(parts[0].clone()).unwrap());
+        assert!(r.push(parts[1].clone()).unwrap());
+        assert!(matches!(
+            r.push(Chunk {
+                seq: 2,
+                last: true,
+                data: vec![],
+            }),
+            Err(ChunkError::OutOfSequence { .. })
+        ));
+
+        // Marked last before the declared length arrived
+        let mut r = Reassembler::new(header, 1024).unwrap();
+        let mut short = parts[0].clone();
+        short.last = true;
+        assert!(matches!(
+            r.push(short),
+            Err(ChunkError::LengthMismatch {
+                expected: 100,
+                got: 50
+            })
+        ));
+
+        // Stream ends before the last chunk
+        let mut wire = Vec::new();
+        write_frame(&mut wire, &header).unwrap();
+        write_frame(&mut wire, &parts[0]).unwrap();
+        assert!(matches!(
+            read_chunked(&mut Cursor::new(&wire), 1024),
+            Err(ChunkError::Io(_))
+        ));
+
+        // Declared length over the receiver's limit
+        assert!(matches!(
+            Reassembler::new(header, 99),
+            Err(ChunkError::TooLarge { .. })
+        ));
+    }
+}
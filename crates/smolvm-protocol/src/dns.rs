@@ -0,0 +1,158 @@
+//! DNS settings for containers.
+//!
+//! The host works out which resolvers containers should use (its own, by
+//! default) and passes them to the agent at boot in [`DNS_SERVERS_ENV`] and
+//! [`DNS_SEARCH_ENV`], as comma-separated lists. The agent writes them into
+//! every container's `resolv.conf`.
+
+use std::net::IpAddr;
+
+/// Environment variable carrying the nameservers, e.g. `10.0.0.2,1.1.1.1`.
+pub const DNS_SERVERS_ENV: &str = "SMOLVM_DNS";
+
+/// Environment variable carrying the search domains, e.g. `corp.example`.
+pub const DNS_SEARCH_ENV: &str = "SMOLVM_DNS_SEARCH";
+
+/// Nameservers and search domains, as in a `resolv.conf`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DnsConfig {
+    /// Nameservers, in the order they are tried.
+    pub servers: Vec<IpAddr>,
+    /// Domains appended to names that aren't fully qualified.
+    pub search: Vec<String>,
+}
+
+impl DnsConfig {
+    /// Read the `nameserver`, `search` and `domain` lines of a
+    /// `resolv.conf`. Anything else, including nameservers that aren't IP
+    /// addresses, is skipped.
+    pub fn parse_resolv_conf(text: &str) -> Self {
+        let mut config = Self::default();
+        for line in text.lines() {
+            let mut fields = line
+                .split(['#', ';'])
+                .next()
+                .unwrap_or_default()
+                .split_whitespace();
+            match fields.next() {
+                Some("nameserver") => {
+                    // Scoped IPv6 addresses (fe80::1%eth0) are left out with
+                    // the rest, as the zone means nothing elsewhere
+                    if let Some(Ok(server)) = fields.next().map(str::parse) {
+                        config.servers.push(server);
+                    }
+                }
+                // The last of these wins, as in glibc
+                Some("search") | Some("domain") => {
+                    config.search = fields.map(str::to_string).collect();
+                }
+                _ => {}
+            }
+        }
+        config
+    }
+
+    /// Parse the values of [`DNS_SERVERS_ENV`] and [`DNS_SEARCH_ENV`].
+    pub fn from_env_values(servers: &str, search: Option<&str>) -> Result<Self, String> {
+        let servers = split_list(servers)
+            .map(|s| {
+                s.parse()
+                    .map_err(|_| format!("invalid DNS server address '{}'", s))
+            })
+            .collect::<Result<Vec<IpAddr>, _>>()?;
+        if servers.is_empty() {
+            return Err("no DNS servers given".to_string());
+        }
+        let search = Self::parse_search(search.unwrap_or_default())?;
+        Ok(Self { servers, search })
+    }
+
+    /// Parse the value of [`DNS_SEARCH_ENV`].
+    pub fn parse_search(search: &str) -> Result<Vec<String>, String> {
+        split_list(search)
+            .map(|domain| crate::validate_hostname(domain).map(|()| domain.to_string()))
+            .collect()
+    }
+
+    /// The value of [`DNS_SERVERS_ENV`] for this configuration.
+    pub fn servers_env_value(&self) -> String {
+        self.servers
+            .iter()
+            .map(IpAddr::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// The value of [`DNS_SEARCH_ENV`] for this configuration.
+    pub fn search_env_value(&self) -> String {
+        self.search.join(",")
+    }
+
+    /// This configuration as a `resolv.conf`.
+    pub fn to_resolv_conf(&self) -> String {
+        let mut text = String::new();
+        if !self.search.is_empty() {
+            text.push_str(&format!("search {}\n", self.search.join(" ")));
+        }
+        for server in &self.servers {
+            text.push_str(&format!("nameserver {}\n", server));
+        }
+        text
+    }
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_resolv_conf() {
+        let config = DnsConfig::parse_resolv_conf(
+            "# Generated by NetworkManager\n\
+             domain old.example\n\
+             search corp.example  svc.local\n\
+             nameserver 10.0.0.2 # office\n\
+             nameserver fd00::53\n\
+             nameserver fe80::1%eth0\n\
+             options ndots:2\n",
+        );
+        assert_eq!(
+            config.servers,
+            vec![
+                "10.0.0.2".parse::<IpAddr>().unwrap(),
+                "fd00::53".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.search, vec!["corp.example", "svc.local"]);
+        assert_eq!(
+            config.to_resolv_conf(),
+            "search corp.example svc.local\nnameserver 10.0.0.2\nnameserver fd00::53\n"
+        );
+    }
+
+    #[test]
+    fn test_env_values_roundtrip() {
+        let config = DnsConfig::from_env_values("10.0.0.2, 1.1.1.1", Some("corp.example")).unwrap();
+        assert_eq!(config.servers_env_value(), "10.0.0.2,1.1.1.1");
+        assert_eq!(config.search_env_value(), "corp.example");
+        assert_eq!(
+            DnsConfig::from_env_values(
+                &config.servers_env_value(),
+                Some(&config.search_env_value())
+            ),
+            Ok(config)
+        );
+
+        assert!(DnsConfig::from_env_values("10.0.0.2", None)
+            .unwrap()
+            .search
+            .is_empty());
+        assert!(DnsConfig::from_env_values("", None).is_err());
+        assert!(DnsConfig::from_env_values("dns.example", None).is_err());
+        assert!(DnsConfig::from_env_values("10.0.0.2", Some("bad domain")).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Application-level heartbeats for long-lived sessions.
+//!
+//! Blocking reads on vsock don't notice a peer that has gone away without
+//! closing the connection (e.g. a host process killed mid-session), so
+//! interactive sessions and pulls exchange `Heartbeat` frames and treat a
+//! run of missed heartbeats as a disconnect.
+//!
+//! This module is shared between the host and agent so both sides agree on
+//! the defaults and on what counts as "missed".
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Default interval between heartbeats (5 seconds).
+pub const DEFAULT_INTERVAL_MS: u64 = 5_000;
+
+/// Default number of consecutive missed heartbeats before the peer is
+/// considered gone (15 seconds at the default interval).
+pub const DEFAULT_MISSED_THRESHOLD: u32 = 3;
+
+/// Environment variable overriding the heartbeat interval in milliseconds.
+pub const INTERVAL_ENV: &str = "SMOLVM_HEARTBEAT_INTERVAL_MS";
+
+/// Environment variable overriding the missed-heartbeat threshold.
+pub const MISSED_THRESHOLD_ENV: &str = "SMOLVM_HEARTBEAT_MISSED";
+
+/// Heartbeat timing for a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeartbeatConfig {
+    /// Interval between heartbeats in milliseconds.
+    pub interval_ms: u64,
+    /// Consecutive missed heartbeats before the peer is considered gone.
+    pub missed_threshold: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: DEFAULT_INTERVAL_MS,
+            missed_threshold: DEFAULT_MISSED_THRESHOLD,
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    /// Build a config from the defaults, overridden by
+    /// `SMOLVM_HEARTBEAT_INTERVAL_MS` / `SMOLVM_HEARTBEAT_MISSED` if set.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(ms) = env_parse(INTERVAL_ENV).filter(|&ms: &u64| ms > 0) {
+            config.interval_ms = ms;
+        }
+        if let Some(n) = env_parse(MISSED_THRESHOLD_ENV).filter(|&n: &u32| n > 0) {
+            config.missed_threshold = n;
+        }
+        config
+    }
+
+    /// Interval between heartbeats.
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms)
+    }
+
+    /// How long the peer may stay silent before it is considered gone.
+    pub fn timeout(&self) -> Duration {
+        self.interval() * self.missed_threshold
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+}
+
+/// Tracks when heartbeats are due and whether the peer has gone silent.
+///
+/// Any frame received from the peer counts as proof of life, not just
+/// heartbeats.
+#[derive(Debug, Clone)]
+pub struct HeartbeatTracker {
+    config: HeartbeatConfig,
+    last_sent: Instant,
+    last_received: Instant,
+}
+
+impl HeartbeatTracker {
+    /// Start tracking at `now`. The peer is assumed alive at this point.
+    pub fn new(config: HeartbeatConfig, now: Instant) -> Self {
+        Self {
+            config,
+            last_sent: now,
+            last_received: now,
+        }
+    }
+
+    /// The config this tracker was created with.
+    pub fn config(&self) -> &HeartbeatConfig {
+        &self.config
+    }
+
+    /// Record that a frame arrived from the peer.
+    pub fn on_receive(&mut self, now: Instant) {
+        self.last_received = now;
+    }
+
+    /// Returns true (and marks a heartbeat as sent) if one is due.
+    pub fn send_due(&mut self, now: Instant) -> bool {
+        if now.saturating_duration_since(self.last_sent) >= self.config.interval() {
+            self.last_sent = now;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns true if the peer has been silent for longer than
+    /// `missed_threshold` intervals.
+    pub fn peer_lost(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_received) > self.config.timeout()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> HeartbeatConfig {
+        HeartbeatConfig {
+            interval_ms: 100,
+            missed_threshold: 3,
+        }
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = HeartbeatConfig::default();
+        assert_eq!(config.interval(), Duration::from_secs(5));
+        assert_eq!(config.timeout(), Duration::from_secs(15));
+    }
+
+    #[test]
+    fn test_send_due_once_per_interval() {
+        let start = Instant::now();
+        let mut tracker = HeartbeatTracker::new(config(), start);
+
+        assert!(!tracker.send_due(start + Duration::from_millis(50)));
+        assert!(tracker.send_due(start + Duration::from_millis(100)));
+        assert!(!tracker.send_due(start + Duration::from_millis(150)));
+        assert!(tracker.send_due(start + Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn test_peer_lost_after_threshold() {
+        let start = Instant::now();
+        let mut tracker = HeartbeatTracker::new(config(), start);
+
+        assert!(!tracker.peer_lost(start + Duration::from_millis(300)));
+        assert!(tracker.peer_lost(start + Duration::from_millis(301)));
+
+        // Receiving anything resets the window
+        tracker.on_receive(start + Duration::from_millis(250));
+        assert!(!tracker.peer_lost(start + Duration::from_millis(500)));
+        assert!(tracker.peer_lost(start + Duration::from_millis(551)));
+    }
+}
@@ -0,0 +1,381 @@
+//! OCI image reference parsing and canonicalization.
+//!
+//! Users refer to the same image in many ways (`ubuntu`,
+//! `library/ubuntu:latest`, `docker.io/library/ubuntu:latest`). [`ImageRef`]
+//! parses any of these into one canonical form, and derives a
+//! filesystem-safe storage key from it that can be decoded back into the
+//! reference.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Registry used when a reference doesn't name one.
+pub const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// Namespace for single-component repositories on the default registry.
+const DEFAULT_NAMESPACE: &str = "library";
+
+/// Tag used when a reference has neither a tag nor a digest.
+pub const DEFAULT_TAG: &str = "latest";
+
+/// Legacy hostname for Docker Hub, normalized to [`DEFAULT_REGISTRY`].
+const LEGACY_DOCKER_HUB: &str = "index.docker.io";
+
+/// Maximum tag length (per the OCI distribution spec).
+const MAX_TAG_LEN: usize = 128;
+
+/// A canonical OCI image reference.
+///
+/// Always has a registry and repository, and at least one of tag or digest.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ImageRef {
+    registry: String,
+    repository: String,
+    tag: Option<String>,
+    digest: Option<String>,
+}
+
+/// Error parsing an image reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseImageRefError {
+    /// The reference that failed to parse.
+    pub reference: String,
+    /// Why it is invalid.
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseImageRefError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid image reference '{}': {}",
+            self.reference, self.reason
+        )
+    }
+}
+
+impl std::error::Error for ParseImageRefError {}
+
+impl ImageRef {
+    /// Parse and canonicalize an image reference.
+    ///
+    /// Fills in `docker.io`, the `library/` namespace and the `latest` tag
+    /// where they are implied.
+    pub fn parse(reference: &str) -> Result<Self, ParseImageRefError> {
+        let invalid = |reason| ParseImageRefError {
+            reference: reference.to_string(),
+            reason,
+        };
+
+        let (name, digest) = match reference.split_once('@') {
+            Some((name, digest)) => {
+                validate_digest(digest).map_err(invalid)?;
+                (name, Some(digest.to_string()))
+            }
+            None => (reference, None),
+        };
+
+        // A ':' after the last '/' separates the tag; earlier ones are ports.
+        let last_slash = name.rfind('/').map(|i| i + 1).unwrap_or(0);
+        let (name, tag) = match name[last_slash..].rfind(':') {
+            Some(i) => {
+                let tag = &name[last_slash + i + 1..];
+                validate_tag(tag).map_err(invalid)?;
+                (&name[..last_slash + i], Some(tag.to_string()))
+            }
+            None => (name, None),
+        };
+
+        let (registry, repository) = match name.split_once('/') {
+            Some((first, rest)) if is_registry(first) => (first, rest.to_string()),
+            _ => (DEFAULT_REGISTRY, name.to_string()),
+        };
+        let registry = if registry == LEGACY_DOCKER_HUB {
+            DEFAULT_REGISTRY
+        } else {
+            registry
+        };
+        let repository = if registry == DEFAULT_REGISTRY && !repository.contains('/') {
+            format!("{}/{}", DEFAULT_NAMESPACE, repository)
+        } else {
+            repository
+        };
+        validate_repository(&repository).map_err(invalid)?;
+
+        let tag = match (tag, &digest) {
+            (None, None) => Some(DEFAULT_TAG.to_string()),
+            (tag, _) => tag,
+        };
+
+        Ok(Self {
+            registry: registry.to_string(),
+            repository,
+            tag,
+            digest,
+        })
+    }
+
+    /// Registry host (and port), e.g. `docker.io` or `localhost:5000`.
+    pub fn registry(&self) -> &str {
+        &self.registry
+    }
+
+    /// Repository path, e.g. `library/ubuntu`.
+    pub fn repository(&self) -> &str {
+        &self.repository
+    }
+
+    /// Tag, if the reference has one.
+    pub fn tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    /// Digest (`algorithm:hex`), if the reference is pinned to one.
+    pub fn digest(&self) -> Option<&str> {
+        self.digest.as_deref()
+    }
+
+    /// A filesystem-safe key for this reference.
+    ///
+    /// Characters other than ASCII alphanumerics, `.`, `_` and `-` are
+    /// percent-encoded, so the key is a single path component that
+    /// [`from_storage_key`](Self::from_storage_key) can decode.
+    pub fn storage_key(&self) -> String {
+        let canonical = self.to_string();
+        let mut key = String::with_capacity(canonical.len() + 8);
+        for b in canonical.bytes() {
+            if b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-') {
+                key.push(b as char);
+            } else {
+                key.push_str(&format!("%{:02X}", b));
+            }
+        }
+        key
+    }
+
+    /// Decode a key produced by [`storage_key`](Self::storage_key).
+    ///
+    /// Returns `None` for anything that isn't exactly such a key, so keys
+    /// from older naming schemes are never misread.
+    pub fn from_storage_key(key: &str) -> Option<Self> {
+        let bytes = key.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' {
+                let hex = key.get(i + 1..i + 3)?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            } else {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+
+        let image_ref = Self::parse(std::str::from_utf8(&decoded).ok()?).ok()?;
+        (image_ref.storage_key() == key).then_some(image_ref)
+    }
+}
+
+impl fmt::Display for ImageRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ImageRef {
+    type Err = ParseImageRefError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// Whether the first path component of a reference is a registry host.
+fn is_registry(component: &str) -> bool {
+    component.contains('.') || component.contains(':') || component == "localhost"
+}
+
+fn validate_repository(repository: &str) -> Result<(), &'static str> {
+    if repository.is_empty() {
+        return Err("repository is empty");
+    }
+    for component in repository.split('/') {
+        if component.is_empty() {
+            return Err("repository has an empty path component");
+        }
+        if !component
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"._-".contains(&b))
+        {
+            return Err("repository may only contain lowercase letters, digits, '.', '_' and '-'");
+        }
+        if !component.as_bytes()[0].is_ascii_alphanumeric() {
+            return Err("repository components must start with a letter or digit");
+        }
+    }
+    Ok(())
+}
+
+fn validate_tag(tag: &str) -> Result<(), &'static str> {
+    if tag.is_empty() || tag.len() > MAX_TAG_LEN {
+        return Err("tag must be 1-128 characters");
+    }
+    if !tag
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b"._-".contains(&b))
+    {
+        return Err("tag may only contain letters, digits, '.', '_' and '-'");
+    }
+    if matches!(tag.as_bytes()[0], b'.' | b'-') {
+        return Err("tag must not start with '.' or '-'");
+    }
+    Ok(())
+}
+
+fn validate_digest(digest: &str) -> Result<(), &'static str> {
+    let (algorithm, hex) = digest
+        .split_once(':')
+        .ok_or("digest must be of the form 'algorithm:hex'")?;
+    if algorithm.is_empty()
+        || !algorithm
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b"+._-".contains(&b))
+    {
+        return Err("invalid digest algorithm");
+    }
+    if hex.len() < 32 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err("digest must be at least 32 hex characters");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "sha256:0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    fn canonical(reference: &str) -> String {
+        ImageRef::parse(reference).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_short_names() {
+        assert_eq!(canonical("ubuntu"), "docker.io/library/ubuntu:latest");
+        assert_eq!(canonical("ubuntu:22.04"), "docker.io/library/ubuntu:22.04");
+        assert_eq!(
+            canonical("library/ubuntu"),
+            "docker.io/library/ubuntu:latest"
+        );
+        assert_eq!(canonical("myuser/app:v1"), "docker.io/myuser/app:v1");
+    }
+
+    #[test]
+    fn test_fully_qualified_names() {
+        assert_eq!(
+            canonical("docker.io/library/ubuntu:22.04"),
+            "docker.io/library/ubuntu:22.04"
+        );
+        assert_eq!(
+            canonical("index.docker.io/library/ubuntu"),
+            "docker.io/library/ubuntu:latest"
+        );
+        assert_eq!(canonical("ghcr.io/owner/repo"), "ghcr.io/owner/repo:latest");
+
+        let r = ImageRef::parse("localhost:5000/team/app:1.2").unwrap();
+        assert_eq!(r.registry(), "localhost:5000");
+        assert_eq!(r.repository(), "team/app");
+        assert_eq!(r.tag(), Some("1.2"));
+
+        // Non-Docker Hub registries don't get a library/ namespace
+        assert_eq!(canonical("quay.io/app"), "quay.io/app:latest");
+    }
+
+    #[test]
+    fn test_equivalent_references_match() {
+        let forms = [
+            "ubuntu",
+            "ubuntu:latest",
+            "library/ubuntu",
+            "docker.io/ubuntu",
+            "docker.io/library/ubuntu:latest",
+        ];
+        let first = ImageRef::parse(forms[0]).unwrap();
+        for form in forms {
+            assert_eq!(ImageRef::parse(form).unwrap(), first, "{}", form);
+        }
+    }
+
+    #[test]
+    fn test_digests() {
+        let r = ImageRef::parse(&format!("alpine@{}", DIGEST)).unwrap();
+        assert_eq!(r.tag(), None, "digest-only references get no default tag");
+        assert_eq!(r.digest(), Some(DIGEST));
+        assert_eq!(
+            r.to_string(),
+            format!("docker.io/library/alpine@{}", DIGEST)
+        );
+
+        let r = ImageRef::parse(&format!("ghcr.io/o/r:v1@{}", DIGEST)).unwrap();
+        assert_eq!(r.tag(), Some("v1"));
+        assert_eq!(r.digest(), Some(DIGEST));
+
+        assert!(ImageRef::parse("alpine@sha256:abc").is_err());
+        assert!(ImageRef::parse("alpine@nocolon").is_err());
+    }
+
+    #[test]
+    fn test_invalid_references() {
+        assert!(ImageRef::parse("").is_err());
+        assert!(ImageRef::parse("Ubuntu").is_err());
+        assert!(ImageRef::parse("ubuntu:").is_err());
+        assert!(ImageRef::parse("ubuntu:-bad").is_err());
+        assert!(ImageRef::parse("a//b").is_err());
+        assert!(ImageRef::parse("ghcr.io/").is_err());
+    }
+
+    #[test]
+    fn test_storage_key_roundtrip() {
+        for reference in [
+            "ubuntu",
+            "docker.io/library/ubuntu:22.04",
+            "localhost:5000/team/app:1.2",
+            &format!("ghcr.io/owner/repo:v1@{}", DIGEST),
+        ] {
+            let r = ImageRef::parse(reference).unwrap();
+            let key = r.storage_key();
+            assert!(
+                !key.contains('/'),
+                "key must be one path component: {}",
+                key
+            );
+            assert!(!key.contains(':'), "key must be filesystem-safe: {}", key);
+            assert_eq!(ImageRef::from_storage_key(&key), Some(r));
+        }
+
+        assert_eq!(
+            ImageRef::parse("ubuntu:22.04").unwrap().storage_key(),
+            "docker.io%2Flibrary%2Fubuntu%3A22.04"
+        );
+    }
+
+    #[test]
+    fn test_from_storage_key_rejects_other_names() {
+        // Names from the old lossy scheme are not mistaken for keys
+        assert_eq!(ImageRef::from_storage_key("alpine_latest"), None);
+        assert_eq!(
+            ImageRef::from_storage_key("docker.io_library_alpine_3.18"),
+            None
+        );
+        assert_eq!(ImageRef::from_storage_key("bad%zz"), None);
+        assert_eq!(ImageRef::from_storage_key("trailing%2"), None);
+    }
+}
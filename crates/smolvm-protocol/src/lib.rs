@@ -13,12 +13,35 @@
 //! | Length (4 BE)  | JSON payload      |
 //! +----------------+-------------------+
 //! ```
+//!
+//! Payloads larger than [`MAX_FRAME_SIZE`] are split across frames; see
+//! [`chunked`].
 
 #![deny(missing_docs)]
 
 use serde::{Deserialize, Serialize};
-
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+pub mod agent_env;
+pub mod chunked;
+pub mod dns;
+pub mod heartbeat;
+pub mod image_ref;
+pub mod log_format;
+pub mod multiplex;
+pub mod platform;
 pub mod retry;
+pub mod scratch;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+pub mod vsock;
+pub mod workload;
+
+pub use chunked::{read_chunked, write_chunked, ChunkError};
+pub use heartbeat::HeartbeatConfig;
+pub use image_ref::ImageRef;
+pub use multiplex::{Demultiplexer, RequestFrame, ResponseFrame};
 
 /// Serde helper for encoding `Vec<u8>` as a base64 string in JSON.
 ///
@@ -44,6 +67,101 @@ pub mod base64_bytes {
 /// Protocol version.
 pub const PROTOCOL_VERSION: u32 = 1;
 
+/// Optional agent features advertised in [`AgentResponse::Pong`].
+///
+/// Unknown request fields are ignored by serde, so an older agent would
+/// silently drop e.g. resource limits. Hosts check for the capability before
+/// relying on a feature instead of bumping [`PROTOCOL_VERSION`].
+pub mod capabilities {
+    /// Interactive sessions and pulls exchange `Heartbeat` frames.
+    pub const HEARTBEAT: &str = "heartbeat";
+    /// `Run` honours `ephemeral` (fresh overlay per invocation).
+    pub const EPHEMERAL_RUN: &str = "ephemeral-run";
+    /// `Run`/`Exec` honour `memory_mib` and `cpu_quota`.
+    pub const RESOURCE_LIMITS: &str = "resource-limits";
+    /// Interactive sessions relay `Signal` requests to the command.
+    pub const SIGNAL: &str = "signal";
+    /// `Run`/`Exec` honour `security` (capabilities, no_new_privileges, seccomp).
+    pub const SECURITY_OPTIONS: &str = "security-options";
+    /// `Run`/`Exec` honour `user`.
+    pub const USER: &str = "user";
+    /// `CreateContainer` honours `restart_policy`.
+    pub const RESTART_POLICY: &str = "restart-policy";
+    /// Responses echo the `request_id` of a
+    /// [`RequestFrame`](crate::RequestFrame).
+    pub const REQUEST_ID: &str = "request-id";
+    /// `Pull` honours `no_cache`.
+    pub const PULL_NO_CACHE: &str = "pull-no-cache";
+    /// `Verify` checks cached images against per-layer checksums.
+    pub const VERIFY: &str = "verify";
+    /// `Run` honours `entrypoint`.
+    pub const ENTRYPOINT: &str = "entrypoint";
+    /// `ToolCheck` reports the agent's external binaries.
+    pub const TOOL_CHECK: &str = "tool-check";
+    /// `Run` honours `tmpfs`.
+    pub const TMPFS: &str = "tmpfs";
+    /// `ListImages` honours `offset` and `limit`.
+    pub const LIST_IMAGES_PAGING: &str = "list-images-paging";
+    /// `CancelPull` stops pulls in progress, as does disconnecting
+    /// mid-pull.
+    pub const CANCEL_PULL: &str = "cancel-pull";
+    /// `DiffOverlay` reports what a container changed in its overlay.
+    pub const DIFF_OVERLAY: &str = "diff-overlay";
+    /// `Run` honours `read_only`.
+    pub const READ_ONLY_RUN: &str = "read-only-run";
+    /// `PruneOverlays` and `RemoveImage` are supported, and
+    /// `DeleteContainer` reports the space it freed.
+    pub const PRUNE: &str = "prune";
+    /// `Stop` gracefully terminates everything running in the guest.
+    pub const STOP: &str = "stop";
+    /// `Exec` honours `resumable`, and `ResumeExec` is supported.
+    pub const RESUMABLE_EXEC: &str = "resumable-exec";
+    /// `Run` honours `extra_hosts`.
+    pub const EXTRA_HOSTS: &str = "extra-hosts";
+    /// `CreateContainer` honours `labels`, and `ListContainers` reports
+    /// them.
+    pub const CONTAINER_LABELS: &str = "container-labels";
+    /// `CreateContainer` honours `healthcheck`, and `ListContainers`
+    /// reports `health`.
+    pub const HEALTHCHECK: &str = "healthcheck";
+    /// `SaveImage` and `LoadImage` move whole images as OCI-layout tars.
+    pub const IMAGE_ARCHIVE: &str = "image-archive";
+    /// `Status` reports the agent's uptime and what it manages.
+    pub const STATUS: &str = "status";
+    /// `Pull` honours `max_bytes_per_sec`.
+    pub const PULL_RATE_LIMIT: &str = "pull-rate-limit";
+
+    /// Every capability this build of the protocol defines.
+    pub const ALL: &[&str] = &[
+        HEARTBEAT,
+        EPHEMERAL_RUN,
+        RESOURCE_LIMITS,
+        SIGNAL,
+        SECURITY_OPTIONS,
+        USER,
+        RESTART_POLICY,
+        REQUEST_ID,
+        PULL_NO_CACHE,
+        VERIFY,
+        ENTRYPOINT,
+        TOOL_CHECK,
+        TMPFS,
+        LIST_IMAGES_PAGING,
+        CANCEL_PULL,
+        DIFF_OVERLAY,
+        READ_ONLY_RUN,
+        PRUNE,
+        STOP,
+        RESUMABLE_EXEC,
+        EXTRA_HOSTS,
+        CONTAINER_LABELS,
+        HEALTHCHECK,
+        IMAGE_ARCHIVE,
+        STATUS,
+        PULL_RATE_LIMIT,
+    ];
+}
+
 /// Maximum frame size (32 MB - layer exports use chunked streaming).
 pub const MAX_FRAME_SIZE: u32 = 32 * 1024 * 1024;
 
@@ -60,12 +178,21 @@ pub mod ports {
     pub const AGENT_CONTROL: u32 = 6000;
 }
 
+/// Mount tag prefix marking a named volume instead of a virtiofs device.
+///
+/// Mount tuples in `Run` and `CreateContainer` carry a virtiofs tag as their
+/// first element. A tag of the form `volume:<name>` instead refers to the
+/// agent-managed named volume `<name>`, which is created on first use.
+pub const VOLUME_TAG_PREFIX: &str = "volume:";
+
 /// vsock CID constants.
 pub mod cid {
     /// Host CID (always 2).
     pub const HOST: u32 = 2;
-    /// Guest CID (always 3 for the first/only guest).
+    /// Guest CID of a VM that wasn't assigned its own (libkrun's default).
     pub const GUEST: u32 = 3;
+    /// Lowest CID assignable to a guest; 0-2 are reserved.
+    pub const FIRST_GUEST: u32 = 3;
     /// Any CID (for listening).
     pub const ANY: u32 = u32::MAX;
 }
@@ -74,6 +201,10 @@ pub mod cid {
 // Agent Protocol (OCI Operations)
 // ============================================================================
 
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
 /// Agent request types (for image management and OCI operations).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "method", rename_all = "snake_case")]
@@ -81,6 +212,11 @@ pub enum AgentRequest {
     /// Ping to check if agent is alive.
     Ping,
 
+    /// Get the agent's uptime and counts of what it manages.
+    ///
+    /// Returns an [`AgentStatus`].
+    Status,
+
     /// Pull an OCI image and extract layers.
     Pull {
         /// Image reference (e.g., "alpine:latest", "docker.io/library/ubuntu:22.04").
@@ -90,6 +226,35 @@ pub enum AgentRequest {
         /// Optional registry authentication credentials.
         #[serde(default, skip_serializing_if = "Option::is_none")]
         auth: Option<RegistryAuth>,
+        /// Fetch the manifest again even if the image is cached, and
+        /// extract any layers it now names that aren't cached. Picks up
+        /// updates to mutable tags like `:latest`.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        no_cache: bool,
+        /// Cap on the pull's combined blob download rate, in bytes per
+        /// second. `None` (or `0`) downloads at full speed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_bytes_per_sec: Option<u64>,
+        /// Heartbeat settings for the pull. When set, the agent sends
+        /// `Heartbeat` responses every interval between progress updates,
+        /// and cancels the pull if nothing arrives from the host for
+        /// `missed_threshold` intervals.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        heartbeat: Option<HeartbeatConfig>,
+    },
+
+    /// Cancel pulls of an image that are in progress.
+    ///
+    /// Sent on the pull's own connection while it streams progress, the
+    /// pull ends with [`error_codes::PULL_CANCELLED`] and no other
+    /// response. Sent on another connection, it is answered with the
+    /// number of pulls cancelled (`{"cancelled": n}`). Either way the
+    /// partly-downloaded layer is removed. Any other request sent on a
+    /// pulling connection is answered with [`error_codes::INVALID_REQUEST`]
+    /// and the pull goes on.
+    CancelPull {
+        /// Image reference, exactly as given to `Pull`.
+        image: String,
     },
 
     /// Query if an image exists locally.
@@ -98,8 +263,39 @@ pub enum AgentRequest {
         image: String,
     },
 
-    /// List all cached images.
-    ListImages,
+    /// Find cached images by config digest.
+    ///
+    /// Returns one `ImageInfo` per reference whose config digest matches;
+    /// an empty list if none do.
+    QueryDigest {
+        /// Config digest (`sha256:...`; the algorithm prefix may be omitted).
+        digest: String,
+    },
+
+    /// Add another reference to a cached image without copying its layers.
+    ///
+    /// Fails with [`error_codes::NOT_FOUND`] if `source` isn't cached. An
+    /// existing `target` is replaced.
+    Tag {
+        /// Reference of the cached image.
+        source: String,
+        /// New reference for it.
+        target: String,
+    },
+
+    /// List cached images, sorted by reference.
+    ///
+    /// With a `limit`, returns one [`ImagePage`] of at most `limit` images
+    /// starting at `offset`, so a huge cache needn't fit in one frame.
+    /// Without one, returns every image from `offset` on as a plain list.
+    ListImages {
+        /// Number of images to skip.
+        #[serde(default, skip_serializing_if = "is_zero")]
+        offset: usize,
+        /// Maximum number of images to return.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limit: Option<usize>,
+    },
 
     /// Run garbage collection on unused layers.
     GarbageCollect {
@@ -107,6 +303,18 @@ pub enum AgentRequest {
         dry_run: bool,
     },
 
+    /// Remove a cached image reference, leaving its layers for
+    /// `GarbageCollect` to free once nothing else references them.
+    ///
+    /// The image's idle persistent and read-only run overlays are removed
+    /// with it. Fails with [`error_codes::IMAGE_IN_USE`] if a container or
+    /// a run in progress uses the image, and [`error_codes::NOT_FOUND`] if
+    /// it isn't cached.
+    RemoveImage {
+        /// Image reference.
+        image: String,
+    },
+
     /// Prepare overlay rootfs for a workload.
     PrepareOverlay {
         /// Image reference.
@@ -121,12 +329,72 @@ pub enum AgentRequest {
         workload_id: String,
     },
 
+    /// List what a workload changed relative to its image, like
+    /// `docker diff`.
+    ///
+    /// Returns a [`DiffEntry`] per path in the container's overlay upper
+    /// layer, sorted by path. Fails with [`error_codes::NOT_FOUND`] if no
+    /// single container matches or it has no overlay.
+    DiffOverlay {
+        /// Container ID (full or prefix) whose overlay to inspect.
+        container_id: String,
+    },
+
+    /// Remove overlays nothing will use again: those of deleted containers
+    /// and ephemeral ones left behind by runs that didn't clean up.
+    ///
+    /// Returns a [`PrunedOverlays`].
+    PruneOverlays {
+        /// If true, only report what would be removed.
+        #[serde(default)]
+        dry_run: bool,
+    },
+
     /// Format the storage disk (first-time setup).
-    FormatStorage,
+    ///
+    /// Refused with [`error_codes::ALREADY_FORMATTED`] if the disk is
+    /// already formatted, unless `force` is set.
+    FormatStorage {
+        /// Reformat even if the disk is already formatted.
+        #[serde(default)]
+        force: bool,
+    },
 
     /// Get storage disk status.
     StorageStatus,
 
+    /// Get per-layer disk usage and which images reference each layer.
+    DiskUsage,
+
+    /// Check cached images for corrupt layers, manifests and configs.
+    ///
+    /// Returns one [`VerifyReport`] per image. Fails with
+    /// [`error_codes::NOT_FOUND`] if `image` is given but isn't cached.
+    Verify {
+        /// Image reference, or `None` to check every cached image.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        image: Option<String>,
+        /// Delete corrupt layers and configs so the next pull fetches them
+        /// again.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        remove_corrupt: bool,
+    },
+
+    /// Report which external binaries the agent relies on (crane, crun,
+    /// ...) are installed.
+    ///
+    /// Returns one [`ToolStatus`] per binary.
+    ToolCheck,
+
+    /// Sample CPU and memory usage of the whole VM, or of one container.
+    ///
+    /// Returns a single [`ResourceStats`]; callers stream by polling.
+    Stats {
+        /// Container ID (full or prefix), or `None` for the whole VM.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        container_id: Option<String>,
+    },
+
     /// Test network connectivity directly from the agent (not via chroot).
     /// Used to debug TSI networking.
     NetworkTest {
@@ -137,6 +405,18 @@ pub enum AgentRequest {
     /// Shutdown the agent.
     Shutdown,
 
+    /// Gracefully stop every container and process running in the guest
+    /// ahead of VM teardown.
+    ///
+    /// Each is sent SIGTERM, and any still running after `timeout_ms` is
+    /// sent SIGKILL. Filesystems are synced before responding, with
+    /// `{"terminated": n, "killed": n}` counting the processes that exited
+    /// on SIGTERM and those that had to be killed.
+    Stop {
+        /// How long to wait for processes to exit after SIGTERM.
+        timeout_ms: u64,
+    },
+
     /// Export a layer as a tar archive.
     ///
     /// Used by `smolvm pack` to extract OCI layers for packaging.
@@ -148,6 +428,24 @@ pub enum AgentRequest {
         layer_index: usize,
     },
 
+    /// Export a cached image as an OCI image layout tar, like
+    /// `docker save`.
+    ///
+    /// The agent responds `Ok` with `{"size": n}` once the archive is
+    /// built, then sends it as a [`chunked`] transfer of `n` bytes. Fails
+    /// with [`error_codes::NOT_FOUND`] if `image` isn't cached.
+    SaveImage {
+        /// Image reference.
+        image: String,
+    },
+
+    /// Import images from an OCI image layout tar made by `SaveImage`.
+    ///
+    /// The request is followed by the archive as a [`chunked`] transfer.
+    /// Once it is stored, responds with the `ImageInfo` of each image it
+    /// held, tagged with the reference it was saved under.
+    LoadImage,
+
     /// Execute a command directly in the VM (not in a container).
     ///
     /// This runs the command in the agent's Alpine rootfs without any
@@ -169,6 +467,11 @@ pub enum AgentRequest {
         /// Allocate a pseudo-TTY for the command.
         #[serde(default)]
         tty: bool,
+        /// Heartbeat settings for interactive sessions. When set, the agent
+        /// sends `Heartbeat` responses every interval and kills the command if
+        /// nothing arrives from the host for `missed_threshold` intervals.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        heartbeat: Option<HeartbeatConfig>,
     },
 
     /// Run a command in an image's rootfs.
@@ -178,7 +481,9 @@ pub enum AgentRequest {
     Run {
         /// Image reference (must be pulled first).
         image: String,
-        /// Command and arguments.
+        /// Command and arguments. If empty (and there is no `entrypoint`),
+        /// the image's entrypoint and cmd run instead. Arguments may not
+        /// contain NUL bytes.
         command: Vec<String>,
         /// Environment variables.
         #[serde(default)]
@@ -202,6 +507,72 @@ pub enum AgentRequest {
         /// Enables terminal features like colors, line editing, and signal handling.
         #[serde(default)]
         tty: bool,
+        /// Discard rootfs changes when the command exits.
+        /// When false (default), the per-image persistent overlay is reused so
+        /// writes survive across runs. When true, a fresh overlay is created
+        /// for this invocation and removed afterwards.
+        #[serde(default)]
+        ephemeral: bool,
+        /// Per-stream cap on captured stdout/stderr in bytes (non-interactive
+        /// mode only). Output past the cap is discarded and flagged as
+        /// truncated in the `Completed` response. Defaults to 10 MiB; the
+        /// agent clamps larger values to 12 MiB so the response fits a frame.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_output_bytes: Option<u64>,
+        /// Heartbeat settings for interactive sessions. When set, the agent
+        /// sends `Heartbeat` responses every interval and kills the command if
+        /// nothing arrives from the host for `missed_threshold` intervals.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        heartbeat: Option<HeartbeatConfig>,
+        /// Memory limit for the container's cgroup in MiB. The kernel OOM
+        /// killer terminates the command if it is exceeded.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        memory_mib: Option<u64>,
+        /// CPU limit in cores (e.g. `0.5` for half a core), enforced with a
+        /// cgroup CPU quota.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cpu_quota: Option<f64>,
+        /// Privilege restrictions for the container process.
+        #[serde(default, skip_serializing_if = "SecurityOptions::is_empty")]
+        security: SecurityOptions,
+        /// User to run as: `user[:group]`, each a numeric ID or a name from
+        /// the image's `/etc/passwd` and `/etc/group`. Defaults to root.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user: Option<String>,
+        /// Fail with [`error_codes::WORKDIR_NOT_FOUND`] if `workdir` doesn't
+        /// exist in the image, instead of creating it.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        no_create_workdir: bool,
+        /// Platform to run the image as (`os/arch`). By default the image
+        /// must match the VM's architecture, or be amd64 on an arm64 VM with
+        /// Rosetta; otherwise the run fails with
+        /// [`error_codes::ARCH_MISMATCH`]. When set, the image must match
+        /// this platform instead.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        platform: Option<String>,
+        /// Entrypoint to run `command` with, replacing the image's. The
+        /// process argv is `entrypoint + command`; an empty entrypoint
+        /// clears it so `command` runs as given. When `None`, `command` is
+        /// the whole argv.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        entrypoint: Option<Vec<String>>,
+        /// In-memory filesystems to mount in the container, for scratch
+        /// data that shouldn't go to the overlay.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        tmpfs: Vec<TmpfsMount>,
+        /// Run on the image's layers mounted read-only, without an overlay
+        /// upper dir. Skips creating (and for `ephemeral`, removing) a
+        /// writable overlay, so starts faster (about 3ms less overlay work
+        /// per run; see `tests/bench_container.sh`); any write to the root
+        /// filesystem fails with `EROFS`. Volume and tmpfs mounts stay
+        /// writable. Takes precedence over `ephemeral`.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        read_only: bool,
+        /// Extra `/etc/hosts` entries for the container, as (hostname,
+        /// address), like docker's `--add-host`. They follow the image's
+        /// own entries.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        extra_hosts: Vec<(String, IpAddr)>,
     },
 
     /// Send stdin data to a running interactive command.
@@ -219,6 +590,17 @@ pub enum AgentRequest {
         rows: u16,
     },
 
+    /// Keepalive sent by the host during an interactive session or pull
+    /// that negotiated heartbeats.
+    Heartbeat,
+
+    /// Deliver a signal to a running interactive command, e.g. a SIGINT
+    /// the host received on Ctrl-C.
+    Signal {
+        /// Signal number (e.g. 2 for SIGINT).
+        signal: i32,
+    },
+
     // ========================================================================
     // Container Lifecycle
     // ========================================================================
@@ -239,6 +621,16 @@ pub enum AgentRequest {
         /// Volume mounts (virtiofs_tag, container_path, read_only).
         #[serde(default)]
         mounts: Vec<(String, String, bool)>,
+        /// Whether the agent restarts the container when its main process exits.
+        #[serde(default, skip_serializing_if = "RestartPolicy::is_no")]
+        restart_policy: RestartPolicy,
+        /// User labels, stored with the container and reported when listing.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        labels: BTreeMap<String, String>,
+        /// Command run periodically inside the container to check its
+        /// health.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        healthcheck: Option<HealthCheck>,
     },
 
     /// Start a created container.
@@ -257,6 +649,8 @@ pub enum AgentRequest {
     },
 
     /// Delete a container.
+    ///
+    /// Responds with the bytes its overlay freed as `freed_bytes`.
     DeleteContainer {
         /// Container ID (full or prefix).
         container_id: String,
@@ -268,6 +662,27 @@ pub enum AgentRequest {
     /// List all containers.
     ListContainers,
 
+    // ========================================================================
+    // Named Volumes
+    // ========================================================================
+    /// Create a named volume that persists across runs and containers.
+    CreateVolume {
+        /// Volume name (alphanumeric, `_`, `.`, `-`).
+        name: String,
+        /// Advisory size in MiB, recorded with the volume.
+        #[serde(default)]
+        size_mib: Option<u64>,
+    },
+
+    /// List all named volumes.
+    ListVolumes,
+
+    /// Remove a named volume and its data.
+    RemoveVolume {
+        /// Volume name.
+        name: String,
+    },
+
     /// Execute a command in a running container.
     ///
     /// Unlike Run, this executes in an existing container created with CreateContainer.
@@ -293,9 +708,96 @@ pub enum AgentRequest {
         /// Enables terminal features like colors, line editing, and signal handling.
         #[serde(default)]
         tty: bool,
+        /// Heartbeat settings for interactive sessions. When set, the agent
+        /// sends `Heartbeat` responses every interval and kills the command if
+        /// nothing arrives from the host for `missed_threshold` intervals.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        heartbeat: Option<HeartbeatConfig>,
+        /// Rejected if set. Memory limits belong to the whole container's
+        /// cgroup, which exec'd processes share, and are set by `Run`; the
+        /// field is kept so older hosts' limits are refused, not ignored.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        memory_mib: Option<u64>,
+        /// Rejected if set, like `memory_mib`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cpu_quota: Option<f64>,
+        /// Privilege restrictions for the exec'd process. A seccomp filter
+        /// belongs to the whole container, so `seccomp_allow` is rejected here.
+        #[serde(default, skip_serializing_if = "SecurityOptions::is_empty")]
+        security: SecurityOptions,
+        /// User to run the exec'd process as, in the same form as for `Run`.
+        /// Defaults to the container's user.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        user: Option<String>,
+        /// Keep an interactive exec running if the connection drops, so the
+        /// host can pick it up again with `ResumeExec`. The agent sends
+        /// `ExecSession` with the session's ID before `Started`.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        resumable: bool,
+    },
+
+    /// Reattach to a resumable interactive `Exec` whose connection dropped.
+    ///
+    /// Responds `Started`, then streams the rest of the session as the
+    /// original `Exec` would have, including output produced while
+    /// disconnected. Fails with [`error_codes::NOT_FOUND`] if there is no
+    /// such session, e.g. because it waited too long to be resumed.
+    ResumeExec {
+        /// ID the agent gave the session in `ExecSession`.
+        session_id: u64,
     },
 }
 
+impl AgentRequest {
+    /// The request's wire name, as in its `method` field.
+    pub fn method(&self) -> &'static str {
+        match self {
+            Self::Ping => "ping",
+            Self::Status => "status",
+            Self::Pull { .. } => "pull",
+            Self::CancelPull { .. } => "cancel_pull",
+            Self::Query { .. } => "query",
+            Self::QueryDigest { .. } => "query_digest",
+            Self::Tag { .. } => "tag",
+            Self::ListImages { .. } => "list_images",
+            Self::GarbageCollect { .. } => "garbage_collect",
+            Self::RemoveImage { .. } => "remove_image",
+            Self::PrepareOverlay { .. } => "prepare_overlay",
+            Self::CleanupOverlay { .. } => "cleanup_overlay",
+            Self::DiffOverlay { .. } => "diff_overlay",
+            Self::PruneOverlays { .. } => "prune_overlays",
+            Self::FormatStorage { .. } => "format_storage",
+            Self::StorageStatus => "storage_status",
+            Self::DiskUsage => "disk_usage",
+            Self::Verify { .. } => "verify",
+            Self::ToolCheck => "tool_check",
+            Self::Stats { .. } => "stats",
+            Self::NetworkTest { .. } => "network_test",
+            Self::Shutdown => "shutdown",
+            Self::Stop { .. } => "stop",
+            Self::ExportLayer { .. } => "export_layer",
+            Self::SaveImage { .. } => "save_image",
+            Self::LoadImage => "load_image",
+            Self::VmExec { .. } => "vm_exec",
+            Self::Run { .. } => "run",
+            Self::Stdin { .. } => "stdin",
+            Self::Resize { .. } => "resize",
+            Self::Heartbeat => "heartbeat",
+            Self::Signal { .. } => "signal",
+            Self::CreateContainer { .. } => "create_container",
+            Self::StartContainer { .. } => "start_container",
+            Self::StopContainer { .. } => "stop_container",
+            Self::DeleteContainer { .. } => "delete_container",
+            Self::ListContainers => "list_containers",
+            Self::CreateVolume { .. } => "create_volume",
+            Self::ListVolumes => "list_volumes",
+            Self::RemoveVolume { .. } => "remove_volume",
+            Self::Exec { .. } => "exec",
+            Self::ResumeExec { .. } => "resume_exec",
+        }
+    }
+}
+
 /// Agent response types.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "status", rename_all = "snake_case")]
@@ -311,6 +813,10 @@ pub enum AgentResponse {
     Pong {
         /// Protocol version.
         version: u32,
+        /// Optional features the agent supports (see [`capabilities`]).
+        /// Empty for agents that predate capability negotiation.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        capabilities: Vec<String>,
     },
 
     /// Progress update (for long operations like pull).
@@ -332,6 +838,10 @@ pub enum AgentResponse {
         /// Error code (for programmatic handling).
         #[serde(default, skip_serializing_if = "Option::is_none")]
         code: Option<String>,
+        /// Structured details for codes that carry them, e.g. the platforms
+        /// an image offers for [`error_codes::PLATFORM_UNAVAILABLE`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        data: Option<serde_json::Value>,
     },
 
     /// Command execution completed (non-interactive mode).
@@ -342,12 +852,28 @@ pub enum AgentResponse {
         stdout: String,
         /// Standard error (may be truncated).
         stderr: String,
+        /// Stdout exceeded the capture limit and was cut off.
+        #[serde(default)]
+        stdout_truncated: bool,
+        /// Stderr exceeded the capture limit and was cut off.
+        #[serde(default)]
+        stderr_truncated: bool,
+        /// Why the command ended. Absent from older agents, in which case
+        /// it defaults to [`ExitReason::Exited`].
+        #[serde(default)]
+        reason: ExitReason,
     },
 
     /// Command started (interactive mode).
     /// Indicates the command is running and ready to receive stdin.
     Started,
 
+    /// Sent just before `Started` for an `Exec` with `resumable` set.
+    ExecSession {
+        /// ID to resume the session with, chosen by the agent.
+        session_id: u64,
+    },
+
     /// Stdout data from a running command (interactive mode).
     Stdout {
         /// Output data.
@@ -368,6 +894,10 @@ pub enum AgentResponse {
         exit_code: i32,
     },
 
+    /// Keepalive sent by the agent during an interactive session or pull
+    /// that negotiated heartbeats.
+    Heartbeat,
+
     /// Layer data chunk (for ExportLayer).
     LayerData {
         /// Binary data chunk.
@@ -427,12 +957,205 @@ pub mod error_codes {
     pub const DELETE_FAILED: &str = "DELETE_FAILED";
     /// Export operation failed.
     pub const EXPORT_FAILED: &str = "EXPORT_FAILED";
+    /// Import operation failed.
+    pub const IMPORT_FAILED: &str = "IMPORT_FAILED";
     /// Serialization error.
     pub const SERIALIZATION_ERROR: &str = "SERIALIZATION_ERROR";
     /// Message size exceeds maximum.
     pub const MESSAGE_TOO_LARGE: &str = "MESSAGE_TOO_LARGE";
     /// Process wait operation failed.
     pub const WAIT_FAILED: &str = "WAIT_FAILED";
+    /// Named volume operation failed.
+    pub const VOLUME_FAILED: &str = "VOLUME_FAILED";
+    /// Not enough free disk space for the operation.
+    pub const NO_SPACE: &str = "NO_SPACE";
+    /// Storage is already formatted and the request did not force a reformat.
+    pub const ALREADY_FORMATTED: &str = "ALREADY_FORMATTED";
+    /// Image tag operation failed.
+    pub const TAG_FAILED: &str = "TAG_FAILED";
+    /// The requested working directory doesn't exist in the container.
+    pub const WORKDIR_NOT_FOUND: &str = "WORKDIR_NOT_FOUND";
+    /// The image was built for a different architecture than it would run on.
+    pub const ARCH_MISMATCH: &str = "ARCH_MISMATCH";
+    /// Image verification failed.
+    pub const VERIFY_FAILED: &str = "VERIFY_FAILED";
+    /// The image has no manifest for the requested platform. The error's
+    /// `data` lists the platforms it does have under `available`.
+    pub const PLATFORM_UNAVAILABLE: &str = "PLATFORM_UNAVAILABLE";
+    /// The pull was cancelled by `CancelPull` or a disconnect.
+    pub const PULL_CANCELLED: &str = "PULL_CANCELLED";
+    /// The image is used by a container or a run in progress.
+    pub const IMAGE_IN_USE: &str = "IMAGE_IN_USE";
+}
+
+/// Typed form of the `code` field of [`AgentResponse::Error`].
+///
+/// Lets callers branch on the failure class instead of string-matching
+/// codes. Codes this version doesn't know about (e.g. from a newer agent)
+/// are preserved in [`ProtocolErrorCode::Unknown`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolErrorCode {
+    /// Request payload was invalid or malformed.
+    InvalidRequest,
+    /// Requested resource was not found.
+    NotFound,
+    /// Internal error during operation.
+    InternalError,
+    /// Image pull operation failed.
+    PullFailed,
+    /// Image query operation failed.
+    QueryFailed,
+    /// Command execution failed.
+    RunFailed,
+    /// Command execution failed in container.
+    ExecFailed,
+    /// Process spawn failed.
+    SpawnFailed,
+    /// Mount operation failed.
+    MountFailed,
+    /// Overlay filesystem operation failed.
+    OverlayFailed,
+    /// Cleanup operation failed.
+    CleanupFailed,
+    /// Storage format operation failed.
+    FormatFailed,
+    /// Storage status query failed.
+    StatusFailed,
+    /// List operation failed.
+    ListFailed,
+    /// Garbage collection failed.
+    GcFailed,
+    /// Container creation failed.
+    CreateFailed,
+    /// Container start failed.
+    StartFailed,
+    /// Container stop failed.
+    StopFailed,
+    /// Container delete failed.
+    DeleteFailed,
+    /// Export operation failed.
+    ExportFailed,
+    /// Import operation failed.
+    ImportFailed,
+    /// Serialization error.
+    SerializationError,
+    /// Message size exceeds maximum.
+    MessageTooLarge,
+    /// Process wait operation failed.
+    WaitFailed,
+    /// Named volume operation failed.
+    VolumeFailed,
+    /// Not enough free disk space for the operation.
+    NoSpace,
+    /// Storage is already formatted and the request did not force a reformat.
+    AlreadyFormatted,
+    /// Image tag operation failed.
+    TagFailed,
+    /// The requested working directory doesn't exist in the container.
+    WorkdirNotFound,
+    /// The image was built for a different architecture than it would run on.
+    ArchMismatch,
+    /// Image verification failed.
+    VerifyFailed,
+    /// The image has no manifest for the requested platform.
+    PlatformUnavailable,
+    /// The pull was cancelled.
+    PullCancelled,
+    /// The image is in use.
+    ImageInUse,
+    /// Unrecognized code string.
+    Unknown(String),
+}
+
+impl ProtocolErrorCode {
+    /// Parse a wire code string. Never fails; unknown codes map to
+    /// [`ProtocolErrorCode::Unknown`].
+    pub fn from_code(code: &str) -> Self {
+        match code {
+            error_codes::INVALID_REQUEST => Self::InvalidRequest,
+            error_codes::NOT_FOUND => Self::NotFound,
+            error_codes::INTERNAL_ERROR => Self::InternalError,
+            error_codes::PULL_FAILED => Self::PullFailed,
+            error_codes::QUERY_FAILED => Self::QueryFailed,
+            error_codes::RUN_FAILED => Self::RunFailed,
+            error_codes::EXEC_FAILED => Self::ExecFailed,
+            error_codes::SPAWN_FAILED => Self::SpawnFailed,
+            error_codes::MOUNT_FAILED => Self::MountFailed,
+            error_codes::OVERLAY_FAILED => Self::OverlayFailed,
+            error_codes::CLEANUP_FAILED => Self::CleanupFailed,
+            error_codes::FORMAT_FAILED => Self::FormatFailed,
+            error_codes::STATUS_FAILED => Self::StatusFailed,
+            error_codes::LIST_FAILED => Self::ListFailed,
+            error_codes::GC_FAILED => Self::GcFailed,
+            error_codes::CREATE_FAILED => Self::CreateFailed,
+            error_codes::START_FAILED => Self::StartFailed,
+            error_codes::STOP_FAILED => Self::StopFailed,
+            error_codes::DELETE_FAILED => Self::DeleteFailed,
+            error_codes::EXPORT_FAILED => Self::ExportFailed,
+            error_codes::IMPORT_FAILED => Self::ImportFailed,
+            error_codes::SERIALIZATION_ERROR => Self::SerializationError,
+            error_codes::MESSAGE_TOO_LARGE => Self::MessageTooLarge,
+            error_codes::WAIT_FAILED => Self::WaitFailed,
+            error_codes::VOLUME_FAILED => Self::VolumeFailed,
+            error_codes::NO_SPACE => Self::NoSpace,
+            error_codes::ALREADY_FORMATTED => Self::AlreadyFormatted,
+            error_codes::TAG_FAILED => Self::TagFailed,
+            error_codes::WORKDIR_NOT_FOUND => Self::WorkdirNotFound,
+            error_codes::ARCH_MISMATCH => Self::ArchMismatch,
+            error_codes::VERIFY_FAILED => Self::VerifyFailed,
+            error_codes::PLATFORM_UNAVAILABLE => Self::PlatformUnavailable,
+            error_codes::PULL_CANCELLED => Self::PullCancelled,
+            error_codes::IMAGE_IN_USE => Self::ImageInUse,
+            other => Self::Unknown(other.to_string()),
+        }
+    }
+
+    /// The wire representation of this code.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::InvalidRequest => error_codes::INVALID_REQUEST,
+            Self::NotFound => error_codes::NOT_FOUND,
+            Self::InternalError => error_codes::INTERNAL_ERROR,
+            Self::PullFailed => error_codes::PULL_FAILED,
+            Self::QueryFailed => error_codes::QUERY_FAILED,
+            Self::RunFailed => error_codes::RUN_FAILED,
+            Self::ExecFailed => error_codes::EXEC_FAILED,
+            Self::SpawnFailed => error_codes::SPAWN_FAILED,
+            Self::MountFailed => error_codes::MOUNT_FAILED,
+            Self::OverlayFailed => error_codes::OVERLAY_FAILED,
+            Self::CleanupFailed => error_codes::CLEANUP_FAILED,
+            Self::FormatFailed => error_codes::FORMAT_FAILED,
+            Self::StatusFailed => error_codes::STATUS_FAILED,
+            Self::ListFailed => error_codes::LIST_FAILED,
+            Self::GcFailed => error_codes::GC_FAILED,
+            Self::CreateFailed => error_codes::CREATE_FAILED,
+            Self::StartFailed => error_codes::START_FAILED,
+            Self::StopFailed => error_codes::STOP_FAILED,
+            Self::DeleteFailed => error_codes::DELETE_FAILED,
+            Self::ExportFailed => error_codes::EXPORT_FAILED,
+            Self::ImportFailed => error_codes::IMPORT_FAILED,
+            Self::SerializationError => error_codes::SERIALIZATION_ERROR,
+            Self::MessageTooLarge => error_codes::MESSAGE_TOO_LARGE,
+            Self::WaitFailed => error_codes::WAIT_FAILED,
+            Self::VolumeFailed => error_codes::VOLUME_FAILED,
+            Self::NoSpace => error_codes::NO_SPACE,
+            Self::AlreadyFormatted => error_codes::ALREADY_FORMATTED,
+            Self::TagFailed => error_codes::TAG_FAILED,
+            Self::WorkdirNotFound => error_codes::WORKDIR_NOT_FOUND,
+            Self::ArchMismatch => error_codes::ARCH_MISMATCH,
+            Self::VerifyFailed => error_codes::VERIFY_FAILED,
+            Self::PlatformUnavailable => error_codes::PLATFORM_UNAVAILABLE,
+            Self::PullCancelled => error_codes::PULL_CANCELLED,
+            Self::ImageInUse => error_codes::IMAGE_IN_USE,
+            Self::Unknown(code) => code,
+        }
+    }
+}
+
+impl std::fmt::Display for ProtocolErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl AgentResponse {
@@ -449,6 +1172,22 @@ impl AgentResponse {
         AgentResponse::Error {
             message: message.into(),
             code: Some(code.to_string()),
+            data: None,
+        }
+    }
+
+    /// Create an error response carrying structured details in `data`.
+    ///
+    /// Falls back to a plain error response if `data` can't be serialized.
+    pub fn error_with_data<T: serde::Serialize>(
+        message: impl Into<String>,
+        code: &str,
+        data: T,
+    ) -> Self {
+        AgentResponse::Error {
+            message: message.into(),
+            code: Some(code.to_string()),
+            data: serde_json::to_value(data).ok(),
         }
     }
 
@@ -465,6 +1204,7 @@ impl AgentResponse {
         AgentResponse::Error {
             message: err.to_string(),
             code: Some(code.to_string()),
+            data: None,
         }
     }
 
@@ -541,6 +1281,17 @@ pub struct ImageInfo {
     /// Image working directory (from OCI config).
     #[serde(default)]
     pub workdir: Option<String>,
+    /// Image labels (from OCI config).
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// All cached references sharing this image's config digest, including
+    /// `reference` itself, sorted.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Layers this pull downloaded and extracted, in order. Empty if every
+    /// layer was already cached, and for images that weren't just pulled.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub refreshed_layers: Vec<String>,
 }
 
 /// Overlay preparation result.
@@ -554,6 +1305,52 @@ pub struct OverlayInfo {
     pub work_path: String,
 }
 
+/// How a workload changed a path, relative to its image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    /// Not in the image.
+    Added,
+    /// In the image, but modified (for a directory, possibly only its
+    /// contents).
+    Changed,
+    /// In the image, but removed.
+    Deleted,
+}
+
+/// One path in a workload's overlay diff.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffEntry {
+    /// Absolute path inside the workload's rootfs.
+    pub path: String,
+    /// What happened to it.
+    pub kind: DiffKind,
+}
+
+/// Overlays removed (or, for a dry run, that would be) by `PruneOverlays`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrunedOverlays {
+    /// Workload IDs of the overlays.
+    pub removed: Vec<String>,
+    /// Disk space they took, in bytes.
+    pub freed_bytes: u64,
+}
+
+/// Liveness information about the agent, returned by `Status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentStatus {
+    /// Protocol version.
+    pub version: u32,
+    /// Seconds since the agent started.
+    pub uptime_secs: u64,
+    /// Number of cached images.
+    pub image_count: usize,
+    /// Number of cached layers.
+    pub layer_count: usize,
+    /// Number of overlays currently mounted.
+    pub active_overlay_count: usize,
+}
+
 /// Storage status information.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StorageStatus {
@@ -563,14 +1360,246 @@ pub struct StorageStatus {
     pub total_bytes: u64,
     /// Used size in bytes.
     pub used_bytes: u64,
+    /// Total number of inodes. Zero from agents that don't report inodes.
+    #[serde(default)]
+    pub total_inodes: u64,
+    /// Number of free inodes.
+    #[serde(default)]
+    pub free_inodes: u64,
     /// Number of cached layers.
     pub layer_count: usize,
     /// Number of cached images.
     pub image_count: usize,
 }
 
+/// Disk usage of a single cached layer, returned by DiskUsage.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerUsage {
+    /// Layer digest (sha256:...).
+    pub digest: String,
+    /// Size of the extracted layer in bytes.
+    pub size_bytes: u64,
+    /// References of the cached images using this layer. Empty if the
+    /// layer is unreferenced and would be removed by garbage collection.
+    pub referenced_by: Vec<String>,
+}
+
+/// Integrity of a cached image, returned by Verify.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyReport {
+    /// Image reference.
+    pub image: String,
+    /// Digests of layers that are missing or whose content no longer
+    /// matches the checksum recorded when they were extracted.
+    pub corrupt_layers: Vec<String>,
+    /// Digests of layers extracted before checksums were recorded. These
+    /// are only checked for being present and non-empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unverified_layers: Vec<String>,
+    /// Whether the stored manifest parses.
+    pub manifest_ok: bool,
+    /// Whether the stored config parses.
+    pub config_ok: bool,
+    /// True if the manifest and config parse and no layer is corrupt.
+    pub ok: bool,
+}
+
+/// A tmpfs mounted into a container (`Run`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TmpfsMount {
+    /// Absolute path inside the container.
+    pub path: String,
+    /// Size limit in bytes; the kernel default (half of RAM) if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+}
+
+/// Check that `name` can be given an `/etc/hosts` entry (`Run`'s
+/// `extra_hosts`): dot-separated labels of letters, digits and hyphens,
+/// none empty, longer than 63 characters or starting or ending with a
+/// hyphen, at most 253 characters in all.
+pub fn validate_hostname(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 253 {
+        return Err(format!(
+            "invalid hostname '{}': must be 1 to 253 characters",
+            name
+        ));
+    }
+    for label in name.split('.') {
+        let valid = !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-');
+        if !valid {
+            return Err(format!("invalid hostname '{}'", name));
+        }
+    }
+    Ok(())
+}
+
+/// One page of cached images, returned by a paged ListImages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImagePage {
+    /// The images in this page.
+    pub images: Vec<ImageInfo>,
+    /// Number of cached images in all pages.
+    pub total: usize,
+}
+
+/// Whether an external binary the agent runs is installed, returned by
+/// ToolCheck.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolStatus {
+    /// Tool name (e.g. `crun`).
+    pub name: String,
+    /// Whether the binary was found and is executable.
+    pub found: bool,
+    /// Resolved path of the binary, or where the agent looked for it.
+    pub path: String,
+    /// First line of the tool's version output, if it reports one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// Memory and CPU limits for a container's cgroup (`Run`/`Exec`).
+///
+/// Sent as the requests' flat `memory_mib` and `cpu_quota` fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// Memory limit in MiB. Exceeding it gets the command OOM-killed.
+    pub memory_mib: Option<u64>,
+    /// CPU limit in cores (e.g. `0.5` for half a core).
+    pub cpu_quota: Option<f64>,
+}
+
+impl ResourceLimits {
+    /// CFS period used for CPU limits (100ms, the kernel default).
+    pub const CPU_PERIOD_US: u64 = 100_000;
+
+    /// Smallest CPU quota the kernel accepts (1ms).
+    const MIN_CPU_QUOTA_US: i64 = 1_000;
+
+    /// Create limits from the optional protocol fields.
+    pub fn new(memory_mib: Option<u64>, cpu_quota: Option<f64>) -> Self {
+        Self {
+            memory_mib,
+            cpu_quota,
+        }
+    }
+
+    /// Whether no limits are set.
+    pub fn is_empty(&self) -> bool {
+        self.memory_mib.is_none() && self.cpu_quota.is_none()
+    }
+
+    /// Check that the limits are usable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.memory_mib == Some(0) {
+            return Err("memory limit must be at least 1 MiB".into());
+        }
+        if let Some(cpus) = self.cpu_quota {
+            if !cpus.is_finite() || cpus <= 0.0 {
+                return Err(format!("CPU limit must be a positive number, got {}", cpus));
+            }
+        }
+        Ok(())
+    }
+
+    /// Memory limit in bytes.
+    pub fn memory_bytes(&self) -> Option<i64> {
+        self.memory_mib
+            .map(|mib| i64::try_from(mib.saturating_mul(1024 * 1024)).unwrap_or(i64::MAX))
+    }
+
+    /// CPU quota in microseconds per [`CPU_PERIOD_US`](Self::CPU_PERIOD_US).
+    pub fn cpu_quota_us(&self) -> Option<i64> {
+        self.cpu_quota.map(|cpus| {
+            ((cpus * Self::CPU_PERIOD_US as f64).round() as i64).max(Self::MIN_CPU_QUOTA_US)
+        })
+    }
+}
+
+/// Privilege restrictions for a container process (`Run`/`Exec`).
+///
+/// Capabilities are named as in `capabilities(7)`, with or without the
+/// `CAP_` prefix. The default (empty) options leave the agent's defaults
+/// untouched.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecurityOptions {
+    /// Capabilities to remove from the default set; `ALL` removes every one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cap_drop: Vec<String>,
+    /// Capabilities to grant after `cap_drop` is applied; `ALL` grants every one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cap_add: Vec<String>,
+    /// Stop the process gaining privileges through setuid binaries or file
+    /// capabilities.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_new_privileges: bool,
+    /// Seccomp allowlist. When set, every syscall not listed fails with
+    /// `EPERM`; the list must include whatever the command needs to start
+    /// (e.g. `execve`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seccomp_allow: Option<Vec<String>>,
+}
+
+impl SecurityOptions {
+    /// Whether no restrictions are requested.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A CPU and memory usage sample, returned by Stats.
+///
+/// CPU time is cumulative, so utilization is derived from two samples with
+/// [`cpu_percent_since`](Self::cpu_percent_since).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceStats {
+    /// Container the sample is for, or `None` for the whole VM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    /// When the sample was taken (Unix epoch milliseconds).
+    pub timestamp_ms: u64,
+    /// CPU time consumed so far, in microseconds.
+    pub cpu_usage_usec: u64,
+    /// Number of CPUs online in the VM.
+    pub online_cpus: u32,
+    /// Memory in use, in bytes.
+    pub memory_bytes: u64,
+    /// Memory available to the VM or container, in bytes, if bounded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_bytes: Option<u64>,
+}
+
+impl ResourceStats {
+    /// CPU utilization between `prev` and this sample, in percent of one
+    /// CPU (so up to `100 * online_cpus`, as `docker stats` reports it).
+    ///
+    /// `None` if the samples are out of order or taken at the same time.
+    pub fn cpu_percent_since(&self, prev: &ResourceStats) -> Option<f64> {
+        let wall_ms = self.timestamp_ms.checked_sub(prev.timestamp_ms)?;
+        if wall_ms == 0 {
+            return None;
+        }
+        let cpu_usec = self.cpu_usage_usec.checked_sub(prev.cpu_usage_usec)?;
+        Some(cpu_usec as f64 / (wall_ms as f64 * 1000.0) * 100.0)
+    }
+
+    /// Memory in use as a percentage of the limit, if there is one.
+    pub fn memory_percent(&self) -> Option<f64> {
+        self.memory_limit_bytes
+            .filter(|&limit| limit > 0)
+            .map(|limit| self.memory_bytes as f64 / limit as f64 * 100.0)
+    }
+}
+
 /// Container information returned by ListContainers/CreateContainer.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ContainerInfoWire")]
 pub struct ContainerInfo {
     /// Unique container ID.
     pub id: String,
@@ -582,6 +1611,323 @@ pub struct ContainerInfo {
     pub created_at: u64,
     /// Command the container is running.
     pub command: Vec<String>,
+    /// How many times the agent has restarted the container.
+    #[serde(default)]
+    pub restart_count: u32,
+    /// Where the container is in its lifecycle. Older agents don't send
+    /// it, so it is then derived from `state`.
+    pub status: ContainerStatus,
+    /// When the container's main process last started (Unix epoch
+    /// seconds).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<u64>,
+    /// When the container's main process last exited (Unix epoch seconds),
+    /// if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    /// User labels set when the container was created.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Outcome of the container's health checks while it runs, if it has
+    /// any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<HealthStatus>,
+}
+
+/// [`ContainerInfo`] as received, before a missing `status` is filled in.
+#[derive(Deserialize)]
+struct ContainerInfoWire {
+    id: String,
+    image: String,
+    state: String,
+    created_at: u64,
+    command: Vec<String>,
+    #[serde(default)]
+    restart_count: u32,
+    #[serde(default)]
+    status: Option<ContainerStatus>,
+    #[serde(default)]
+    started_at: Option<u64>,
+    #[serde(default)]
+    finished_at: Option<u64>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    health: Option<HealthStatus>,
+}
+
+impl From<ContainerInfoWire> for ContainerInfo {
+    fn from(wire: ContainerInfoWire) -> Self {
+        Self {
+            status: wire
+                .status
+                .unwrap_or_else(|| ContainerStatus::from_state(&wire.state)),
+            id: wire.id,
+            image: wire.image,
+            state: wire.state,
+            created_at: wire.created_at,
+            command: wire.command,
+            restart_count: wire.restart_count,
+            started_at: wire.started_at,
+            finished_at: wire.finished_at,
+            labels: wire.labels,
+            health: wire.health,
+        }
+    }
+}
+
+/// Lifecycle status of a container.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum ContainerStatus {
+    /// Created but never started.
+    #[default]
+    Created,
+    /// The main process is running.
+    Running,
+    /// The main process exited, or the container was stopped.
+    Exited {
+        /// Exit code of the main process, if the agent knows it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        code: Option<i32>,
+    },
+    /// The container failed to start.
+    Error {
+        /// Why it failed.
+        message: String,
+    },
+}
+
+impl ContainerStatus {
+    /// The status implied by a container's `state` string, for agents that
+    /// predate [`ContainerInfo::status`]. Exit codes and errors are unknown.
+    pub fn from_state(state: &str) -> Self {
+        match state {
+            "running" => ContainerStatus::Running,
+            "stopped" | "exited" => ContainerStatus::Exited { code: None },
+            _ => ContainerStatus::Created,
+        }
+    }
+
+    /// The status without its details: `created`, `running`, `exited` or
+    /// `error`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContainerStatus::Created => "created",
+            ContainerStatus::Running => "running",
+            ContainerStatus::Exited { .. } => "exited",
+            ContainerStatus::Error { .. } => "error",
+        }
+    }
+}
+
+impl std::fmt::Display for ContainerStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContainerStatus::Exited { code: Some(code) } => write!(f, "exited ({})", code),
+            status => f.write_str(status.name()),
+        }
+    }
+}
+
+/// Whether the agent restarts a container when its main process exits.
+///
+/// Parsed from and displayed as `no`, `always`, `on-failure` or
+/// `on-failure:<max-retries>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Leave the container stopped.
+    #[default]
+    No,
+    /// Restart after a non-zero exit, or one whose exit code is unknown.
+    OnFailure {
+        /// Give up after this many restarts (0 = no limit).
+        #[serde(default)]
+        max_retries: u32,
+    },
+    /// Restart whenever the container exits.
+    Always,
+}
+
+impl RestartPolicy {
+    /// Whether this is the default policy of never restarting.
+    pub fn is_no(&self) -> bool {
+        *self == Self::No
+    }
+
+    /// Whether a container that has already been restarted `restart_count`
+    /// times should be restarted after exiting with `exit_code`.
+    pub fn should_restart(&self, exit_code: Option<i32>, restart_count: u32) -> bool {
+        match *self {
+            RestartPolicy::No => false,
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure { max_retries } => {
+                exit_code != Some(0) && (max_retries == 0 || restart_count < max_retries)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RestartPolicy::No => write!(f, "no"),
+            RestartPolicy::Always => write!(f, "always"),
+            RestartPolicy::OnFailure { max_retries: 0 } => write!(f, "on-failure"),
+            RestartPolicy::OnFailure { max_retries } => write!(f, "on-failure:{}", max_retries),
+        }
+    }
+}
+
+impl std::str::FromStr for RestartPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "no" => Ok(RestartPolicy::No),
+            None if s == "always" => Ok(RestartPolicy::Always),
+            None if s == "on-failure" => Ok(RestartPolicy::OnFailure { max_retries: 0 }),
+            Some(("on-failure", n)) => n
+                .parse()
+                .map(|max_retries| RestartPolicy::OnFailure { max_retries })
+                .map_err(|_| format!("invalid max retries '{}' in restart policy", n)),
+            _ => Err(format!(
+                "invalid restart policy '{}': expected no, always, on-failure or on-failure:N",
+                s
+            )),
+        }
+    }
+}
+
+/// A command the agent runs inside a container periodically to check that
+/// it is working. An exit status of 0 is a pass.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthCheck {
+    /// Command and arguments.
+    pub command: Vec<String>,
+    /// Time between checks, in milliseconds.
+    #[serde(default = "default_health_interval_ms")]
+    pub interval_ms: u64,
+    /// How long a check may run before it counts as failed, in
+    /// milliseconds.
+    #[serde(default = "default_health_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Consecutive failed checks before the container is unhealthy.
+    #[serde(default = "default_health_retries")]
+    pub retries: u32,
+}
+
+fn default_health_interval_ms() -> u64 {
+    HealthCheck::DEFAULT_INTERVAL_MS
+}
+
+fn default_health_timeout_ms() -> u64 {
+    HealthCheck::DEFAULT_TIMEOUT_MS
+}
+
+fn default_health_retries() -> u32 {
+    HealthCheck::DEFAULT_RETRIES
+}
+
+impl HealthCheck {
+    /// Default time between checks.
+    pub const DEFAULT_INTERVAL_MS: u64 = 30_000;
+    /// Default time a check may run.
+    pub const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+    /// Default number of failures before the container is unhealthy.
+    pub const DEFAULT_RETRIES: u32 = 3;
+
+    /// A check running `command` with the default timings.
+    pub fn new(command: Vec<String>) -> Self {
+        Self {
+            command,
+            interval_ms: Self::DEFAULT_INTERVAL_MS,
+            timeout_ms: Self::DEFAULT_TIMEOUT_MS,
+            retries: Self::DEFAULT_RETRIES,
+        }
+    }
+
+    /// Check the command is set and the timings are non-zero.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.command.is_empty() || self.command[0].is_empty() {
+            return Err("health check command must not be empty".to_string());
+        }
+        if self.interval_ms == 0 || self.timeout_ms == 0 {
+            return Err("health check interval and timeout must be non-zero".to_string());
+        }
+        if self.retries == 0 {
+            return Err("health check retries must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// What a container's health checks last concluded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    /// Running, but no check has passed yet.
+    Starting,
+    /// The last check passed.
+    Healthy,
+    /// The last `retries` checks failed.
+    Unhealthy,
+}
+
+impl std::fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            HealthStatus::Starting => "starting",
+            HealthStatus::Healthy => "healthy",
+            HealthStatus::Unhealthy => "unhealthy",
+        })
+    }
+}
+
+/// Named volume information returned by CreateVolume/ListVolumes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeInfo {
+    /// Volume name.
+    pub name: String,
+    /// Advisory size in MiB given at creation (not enforced).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_mib: Option<u64>,
+    /// Bytes currently used by the volume's data.
+    pub used_bytes: u64,
+    /// Creation timestamp (Unix epoch seconds).
+    pub created_at: u64,
+}
+
+/// How a command run via `Run`/`Exec` ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExitReason {
+    /// The process exited normally with its own exit code.
+    #[default]
+    Exited,
+    /// The process was killed by a signal.
+    Signaled {
+        /// Signal number.
+        signal: i32,
+    },
+    /// The process was killed because it exceeded its timeout.
+    TimedOut,
+    /// The process was killed by the kernel OOM killer.
+    OomKilled,
+}
+
+impl ExitReason {
+    /// Human-readable explanation for abnormal exits, or `None` for a
+    /// normal exit.
+    pub fn describe(&self) -> Option<String> {
+        match self {
+            ExitReason::Exited => None,
+            ExitReason::Signaled { signal } => Some(format!("killed by signal {}", signal)),
+            ExitReason::TimedOut => Some("killed: timed out".to_string()),
+            ExitReason::OomKilled => Some("killed: out of memory".to_string()),
+        }
+    }
 }
 
 /// Registry authentication credentials for pulling images.
@@ -598,7 +1944,9 @@ pub struct RegistryAuth {
 // ============================================================================
 
 /// Messages from host to workload VM.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Served by [`workload::WorkloadSession`] on [`ports::WORKLOAD_CONTROL`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum HostMessage {
     /// Authentication request.
@@ -647,7 +1995,7 @@ pub enum HostMessage {
 }
 
 /// Messages from workload VM to host.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum GuestMessage {
     /// Authentication successful.
@@ -723,7 +2071,21 @@ pub fn encode_message<T: Serialize>(msg: &T) -> Result<Vec<u8>, serde_json::Erro
 }
 
 /// Decode a message from wire format.
+///
+/// `data` must start with a complete frame; anything after it is ignored.
 pub fn decode_message<T: for<'de> Deserialize<'de>>(data: &[u8]) -> Result<T, DecodeError> {
+    decode_message_consuming(data).map(|(msg, _)| msg)
+}
+
+/// Decode the first message in `data`, returning it with the number of
+/// bytes its frame took (`4 + len`).
+///
+/// For buffers that accumulate several frames from a stream: advance past
+/// the consumed bytes and decode again, reading more on
+/// [`DecodeError::TooShort`] or [`DecodeError::Incomplete`].
+pub fn decode_message_consuming<T: for<'de> Deserialize<'de>>(
+    data: &[u8],
+) -> Result<(T, usize), DecodeError> {
     if data.len() < 4 {
         return Err(DecodeError::TooShort);
     }
@@ -741,7 +2103,83 @@ pub fn decode_message<T: for<'de> Deserialize<'de>>(data: &[u8]) -> Result<T, De
         });
     }
 
-    serde_json::from_slice(&data[4..4 + len]).map_err(DecodeError::Json)
+    let msg = decode_json(&data[4..4 + len])?;
+    Ok((msg, 4 + len))
+}
+
+/// Maximum nesting of arrays and objects in a message. Protocol messages
+/// nest a few levels; serde_json would recurse up to 128.
+pub const MAX_JSON_DEPTH: usize = 64;
+
+/// Maximum elements in one array, or members in one object.
+pub const MAX_JSON_ELEMENTS: usize = 1 << 20;
+
+/// Maximum length of one string, in encoded bytes.
+pub const MAX_JSON_STRING_LEN: usize = 24 * 1024 * 1024;
+
+// A base64-encoded chunk of streamed data must fit in a string
+const _: () = assert!(LAYER_CHUNK_SIZE.div_ceil(3) * 4 <= MAX_JSON_STRING_LEN);
+
+/// Deserialize a frame body (without its length header) received from the
+/// other side.
+///
+/// The body is first scanned for nesting, arrays, objects and strings over
+/// [`MAX_JSON_DEPTH`], [`MAX_JSON_ELEMENTS`] and [`MAX_JSON_STRING_LEN`],
+/// which are rejected as [`DecodeError::Malformed`] without being parsed.
+/// The scan is a single pass with no allocation per value, so a hostile
+/// body costs no more to reject than its length.
+pub fn decode_json<T: for<'de> Deserialize<'de>>(body: &[u8]) -> Result<T, DecodeError> {
+    check_json_limits(body)?;
+    serde_json::from_slice(body).map_err(DecodeError::Json)
+}
+
+fn check_json_limits(body: &[u8]) -> Result<(), DecodeError> {
+    let malformed = |reason: String| Err(DecodeError::Malformed(reason));
+    // Elements so far in each open array or object, innermost last
+    let mut open: Vec<usize> = Vec::new();
+    let mut bytes = body.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            b'[' | b'{' => {
+                if open.len() == MAX_JSON_DEPTH {
+                    return malformed(format!("nested deeper than {} levels", MAX_JSON_DEPTH));
+                }
+                open.push(1);
+            }
+            b']' | b'}' => {
+                open.pop();
+            }
+            b',' => {
+                if let Some(count) = open.last_mut() {
+                    *count += 1;
+                    if *count > MAX_JSON_ELEMENTS {
+                        return malformed(format!(
+                            "array or object with more than {} elements",
+                            MAX_JSON_ELEMENTS
+                        ));
+                    }
+                }
+            }
+            b'"' => {
+                let mut len = 0;
+                loop {
+                    match bytes.next() {
+                        Some(b'"') | None => break,
+                        Some(b'\\') => {
+                            bytes.next();
+                            len += 2;
+                        }
+                        Some(_) => len += 1,
+                    }
+                }
+                if len > MAX_JSON_STRING_LEN {
+                    return malformed(format!("string longer than {} bytes", MAX_JSON_STRING_LEN));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
 }
 
 /// Error decoding a wire message.
@@ -760,6 +2198,9 @@ pub enum DecodeError {
     },
     /// JSON parse error.
     Json(serde_json::Error),
+    /// JSON over the nesting, element or string limits, rejected before
+    /// parsing.
+    Malformed(String),
 }
 
 impl std::fmt::Display for DecodeError {
@@ -775,6 +2216,7 @@ impl std::fmt::Display for DecodeError {
                 )
             }
             DecodeError::Json(e) => write!(f, "JSON decode error: {}", e),
+            DecodeError::Malformed(reason) => write!(f, "malformed message: {}", reason),
         }
     }
 }
@@ -791,6 +2233,9 @@ mod tests {
             image: "alpine:latest".to_string(),
             oci_platform: Some("linux/arm64".to_string()),
             auth: None,
+            no_cache: true,
+            max_bytes_per_sec: Some(1 << 20),
+            heartbeat: Some(HeartbeatConfig::default()),
         };
 
         let encoded = encode_message(&req).unwrap();
@@ -800,13 +2245,37 @@ mod tests {
             image,
             oci_platform,
             auth,
+            no_cache,
+            max_bytes_per_sec,
+            heartbeat,
         } = decoded
         else {
             panic!("expected Pull variant, got {:?}", decoded);
         };
         assert_eq!(image, "alpine:latest");
+        assert_eq!(heartbeat, Some(HeartbeatConfig::default()));
         assert_eq!(oci_platform, Some("linux/arm64".to_string()));
         assert!(auth.is_none());
+        assert!(no_cache);
+        assert_eq!(max_bytes_per_sec, Some(1 << 20));
+    }
+
+    #[test]
+    fn test_resource_limits_validation() {
+        assert!(ResourceLimits::default().validate().is_ok());
+        assert!(ResourceLimits::new(Some(1), Some(0.25)).validate().is_ok());
+        assert!(ResourceLimits::new(Some(0), None).validate().is_err());
+        assert!(ResourceLimits::new(None, Some(0.0)).validate().is_err());
+        assert!(ResourceLimits::new(None, Some(-1.0)).validate().is_err());
+        assert!(ResourceLimits::new(None, Some(f64::NAN))
+            .validate()
+            .is_err());
+
+        // Tiny CPU limits are clamped to the kernel minimum
+        assert_eq!(
+            ResourceLimits::new(None, Some(0.001)).cpu_quota_us(),
+            Some(ResourceLimits::MIN_CPU_QUOTA_US)
+        );
     }
 
     #[test]
@@ -818,6 +2287,9 @@ mod tests {
                 username: "testuser".to_string(),
                 password: "testpass".to_string(),
             }),
+            no_cache: false,
+            max_bytes_per_sec: None,
+            heartbeat: None,
         };
 
         let encoded = encode_message(&req).unwrap();
@@ -827,10 +2299,16 @@ mod tests {
             image,
             oci_platform,
             auth,
+            no_cache,
+            max_bytes_per_sec,
+            heartbeat,
         } = decoded
         else {
             panic!("expected Pull variant, got {:?}", decoded);
         };
+        assert!(!no_cache);
+        assert!(heartbeat.is_none());
+        assert!(max_bytes_per_sec.is_none());
         assert_eq!(image, "ghcr.io/owner/repo:latest");
         assert!(oci_platform.is_none());
         let auth = auth.expect("auth should be Some");
@@ -853,6 +2331,180 @@ mod tests {
         assert!(matches!(result, Err(DecodeError::Incomplete { .. })));
     }
 
+    /// Frame `body` as a message.
+    fn frame(body: &[u8]) -> Vec<u8> {
+        let mut data = (body.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn test_decode_rejects_pathological_json_quickly() {
+        let started = std::time::Instant::now();
+        let deep = "[".repeat(1 << 20) + &"]".repeat(1 << 20);
+        let wide = format!("[{}0]", "0,".repeat(MAX_JSON_ELEMENTS));
+        let members: String = (0..=MAX_JSON_ELEMENTS)
+            .map(|i| format!("\"k{}\":0,", i))
+            .collect();
+        let long_string = format!("\"{}\"", "\\u0041".repeat(MAX_JSON_STRING_LEN / 6 + 1));
+        for body in [
+            deep,
+            format!("{{\"method\":\"ping\",\"x\":{}}}", "{\"a\":".repeat(100)),
+            wide,
+            format!("{{{}\"method\":\"ping\"}}", members),
+            long_string,
+        ] {
+            let result: Result<AgentRequest, _> = decode_message(&frame(body.as_bytes()));
+            assert!(
+                matches!(result, Err(DecodeError::Malformed(_))),
+                "{:?}",
+                result.map(|r| r.method())
+            );
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+
+        // Brackets, commas and escaped quotes inside strings aren't structure
+        let quoted = format!(
+            "{{\"method\":\"query\",\"image\":\"{}\\\"{}\"}}",
+            "[".repeat(MAX_JSON_DEPTH + 1),
+            ",".repeat(10)
+        );
+        let request: AgentRequest = decode_message(&frame(quoted.as_bytes())).unwrap();
+        assert!(matches!(request, AgentRequest::Query { image } if image.ends_with(",,")));
+
+        // Nesting up to the limit is parsed as usual
+        let nested = "[".repeat(MAX_JSON_DEPTH) + &"]".repeat(MAX_JSON_DEPTH);
+        assert!(decode_json::<serde_json::Value>(nested.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_decode_survives_mutated_frames() {
+        let requests = [
+            AgentRequest::Ping,
+            AgentRequest::Query {
+                image: "alpine:latest".to_string(),
+            },
+            AgentRequest::ListImages {
+                offset: 3,
+                limit: Some(10),
+            },
+        ];
+        let seeds: Vec<Vec<u8>> = requests
+            .iter()
+            .map(|r| encode_message(r).unwrap()[4..].to_vec())
+            .collect();
+        let interesting = b"[]{},\":\\0";
+
+        // A fixed xorshift sequence, so failures reproduce
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for i in 0..20_000 {
+            let mut body = seeds[i % seeds.len()].clone();
+            for _ in 0..=next() % 8 {
+                let pos = (next() as usize) % (body.len() + 1);
+                let byte = match next() % 3 {
+                    0 => interesting[(next() as usize) % interesting.len()],
+                    1 => next() as u8,
+                    _ => {
+                        // Repeat a structural byte, as a nesting bomb would
+                        let run = vec![interesting[(next() as usize) % 4]; (next() % 512) as usize];
+                        body.splice(pos..pos, run);
+                        continue;
+                    }
+                };
+                body.insert(pos, byte);
+            }
+            // Must return, never panic or overflow the stack
+            let _ = decode_message::<AgentRequest>(&frame(&body));
+        }
+    }
+
+    #[test]
+    fn test_list_images_paging_is_optional() {
+        // An unpaged request looks like it did before paging existed
+        let req = AgentRequest::ListImages {
+            offset: 0,
+            limit: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&req).unwrap(),
+            serde_json::json!({"method": "list_images"})
+        );
+        let req: AgentRequest = serde_json::from_str(r#"{"method":"list_images"}"#).unwrap();
+        assert!(matches!(
+            req,
+            AgentRequest::ListImages {
+                offset: 0,
+                limit: None
+            }
+        ));
+
+        let req: AgentRequest =
+            serde_json::from_str(r#"{"method":"list_images","offset":512,"limit":256}"#).unwrap();
+        assert!(matches!(
+            req,
+            AgentRequest::ListImages {
+                offset: 512,
+                limit: Some(256)
+            }
+        ));
+    }
+
+    #[test]
+    fn test_decode_concatenated_frames() {
+        let mut data = encode_message(&AgentRequest::Ping).unwrap();
+        let first_len = data.len();
+        data.extend(
+            encode_message(&AgentRequest::Query {
+                image: "alpine".to_string(),
+            })
+            .unwrap(),
+        );
+
+        let (first, consumed): (AgentRequest, _) = decode_message_consuming(&data).unwrap();
+        assert!(matches!(first, AgentRequest::Ping));
+        assert_eq!(consumed, first_len);
+
+        let rest = &data[consumed..];
+        let (second, consumed): (AgentRequest, _) = decode_message_consuming(rest).unwrap();
+        assert!(matches!(second, AgentRequest::Query { image } if image == "alpine"));
+        assert_eq!(consumed, rest.len());
+
+        // A partial trailing frame asks for more data
+        let partial = &data[..first_len + 6];
+        let (_, consumed): (AgentRequest, _) = decode_message_consuming(partial).unwrap();
+        let result: Result<(AgentRequest, _), _> = decode_message_consuming(&partial[consumed..]);
+        assert!(matches!(
+            result,
+            Err(DecodeError::Incomplete { got: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_image_info_config_fields_default() {
+        // Agents predating the OCI config fields send none of them
+        let info: ImageInfo = serde_json::from_value(serde_json::json!({
+            "reference": "alpine:latest",
+            "digest": "sha256:abc",
+            "size": 1024,
+            "created": null,
+            "architecture": "arm64",
+            "os": "linux",
+            "layer_count": 1,
+            "layers": ["sha256:layer"],
+        }))
+        .unwrap();
+        assert!(info.entrypoint.is_empty());
+        assert!(info.env.is_empty());
+        assert!(info.workdir.is_none());
+        assert!(info.labels.is_empty());
+    }
+
     #[test]
     fn test_agent_request_serialization() {
         let req = AgentRequest::Ping;
@@ -867,10 +2519,79 @@ mod tests {
         assert!(json.contains("prepare_overlay"));
     }
 
+    #[test]
+    fn test_method_matches_wire_name() {
+        let requests = [
+            AgentRequest::Ping,
+            AgentRequest::Status,
+            AgentRequest::DiskUsage,
+            AgentRequest::Verify {
+                image: None,
+                remove_corrupt: false,
+            },
+            AgentRequest::ToolCheck,
+            AgentRequest::QueryDigest {
+                digest: "sha256:abc".to_string(),
+            },
+            AgentRequest::CleanupOverlay {
+                workload_id: "wl-123".to_string(),
+            },
+            AgentRequest::DiffOverlay {
+                container_id: "smolvm-abc".to_string(),
+            },
+            AgentRequest::PruneOverlays { dry_run: true },
+            AgentRequest::RemoveImage {
+                image: "alpine:latest".to_string(),
+            },
+            AgentRequest::Stop { timeout_ms: 5000 },
+            AgentRequest::ResumeExec { session_id: 7 },
+            AgentRequest::SaveImage {
+                image: "alpine:latest".to_string(),
+            },
+            AgentRequest::LoadImage,
+        ];
+        for request in requests {
+            let json = serde_json::to_value(&request).unwrap();
+            assert_eq!(json["method"], request.method());
+        }
+    }
+
+    #[test]
+    fn test_format_storage_force_defaults_off() {
+        // Hosts predating the force flag send a bare request
+        let req: AgentRequest = serde_json::from_str(r#"{"method":"format_storage"}"#).unwrap();
+        assert!(matches!(req, AgentRequest::FormatStorage { force: false }));
+    }
+
+    #[test]
+    fn test_stats_request_and_cpu_percent() {
+        let req: AgentRequest = serde_json::from_str(r#"{"method":"stats"}"#).unwrap();
+        assert!(matches!(req, AgentRequest::Stats { container_id: None }));
+
+        let prev = ResourceStats {
+            container_id: None,
+            timestamp_ms: 10_000,
+            cpu_usage_usec: 5_000_000,
+            online_cpus: 2,
+            memory_bytes: 256 << 20,
+            memory_limit_bytes: Some(512 << 20),
+        };
+        // 1.5s of CPU time over 1s of wall time
+        let cur = ResourceStats {
+            timestamp_ms: 11_000,
+            cpu_usage_usec: 6_500_000,
+            ..prev.clone()
+        };
+        assert_eq!(cur.cpu_percent_since(&prev), Some(150.0));
+        assert_eq!(prev.cpu_percent_since(&cur), None);
+        assert_eq!(cur.memory_percent(), Some(50.0));
+    }
+
     #[test]
     fn test_agent_response_serialization() {
         let resp = AgentResponse::Pong {
             version: PROTOCOL_VERSION,
+            capabilities: vec![],
         };
         let json = serde_json::to_string(&resp).unwrap();
         assert!(json.contains("pong"));
@@ -884,6 +2605,384 @@ mod tests {
         assert!(json.contains("progress"));
     }
 
+    #[test]
+    fn test_run_ephemeral_defaults_to_false() {
+        // Older hosts don't send the field; the agent must keep reusing
+        // the persistent overlay for them.
+        let json = r#"{"method":"run","image":"alpine","command":["true"],"workdir":null}"#;
+        let req: AgentRequest = serde_json::from_str(json).unwrap();
+        let AgentRequest::Run { ephemeral, .. } = req else {
+            panic!("expected Run variant, got {:?}", req);
+        };
+        assert!(!ephemeral);
+    }
+
+    #[test]
+    fn test_validate_hostname() {
+        for name in ["db", "mock-api.internal", "a1.b2.c3", &"x".repeat(63)] {
+            assert!(validate_hostname(name).is_ok(), "{} rejected", name);
+        }
+        for name in [
+            "",
+            "-db",
+            "db-",
+            "a..b",
+            ".db",
+            "my_db",
+            "db host",
+            "db#1",
+            &"x".repeat(64),
+            &["x"; 128].join("."),
+        ] {
+            assert!(validate_hostname(name).is_err(), "{} accepted", name);
+        }
+    }
+
+    #[test]
+    fn test_run_extra_hosts_wire_format() {
+        let json = r#"{"method":"run","image":"alpine","command":["true"],"workdir":null,
+            "extra_hosts":[["db","10.0.0.5"],["api","fd00::1"]]}"#;
+        let req: AgentRequest = serde_json::from_str(json).unwrap();
+        let AgentRequest::Run { extra_hosts, .. } = req else {
+            panic!("expected Run variant, got {:?}", req);
+        };
+        assert_eq!(
+            extra_hosts,
+            vec![
+                ("db".to_string(), "10.0.0.5".parse().unwrap()),
+                ("api".to_string(), "fd00::1".parse().unwrap()),
+            ]
+        );
+
+        let bad = r#"{"method":"run","image":"alpine","command":["true"],"workdir":null,
+            "extra_hosts":[["db","10.0.0.300"]]}"#;
+        assert!(serde_json::from_str::<AgentRequest>(bad).is_err());
+    }
+
+    #[test]
+    fn test_pong_capabilities() {
+        // Agents that predate negotiation send only the version
+        let old: AgentResponse = serde_json::from_str(r#"{"status":"pong","version":1}"#).unwrap();
+        let AgentResponse::Pong {
+            capabilities: caps, ..
+        } = old
+        else {
+            panic!("expected Pong, got {:?}", old);
+        };
+        assert!(caps.is_empty());
+
+        let resp = AgentResponse::Pong {
+            version: PROTOCOL_VERSION,
+            capabilities: capabilities::ALL.iter().map(|c| c.to_string()).collect(),
+        };
+        let json = serde_json::to_string(&resp).unwrap();
+        let AgentResponse::Pong {
+            capabilities: caps, ..
+        } = serde_json::from_str(&json).unwrap()
+        else {
+            panic!("expected Pong");
+        };
+        assert_eq!(caps, capabilities::ALL);
+    }
+
+    #[test]
+    fn test_resource_limits_are_optional() {
+        let json = r#"{"method":"exec","container_id":"abc","command":["true"],"workdir":null}"#;
+        let req: AgentRequest = serde_json::from_str(json).unwrap();
+        let AgentRequest::Exec {
+            memory_mib,
+            cpu_quota,
+            ..
+        } = req
+        else {
+            panic!("expected Exec variant, got {:?}", req);
+        };
+        assert_eq!(memory_mib, None);
+        assert_eq!(cpu_quota, None);
+
+        // Unset limits are left out so older agents see the same request
+        let req = AgentRequest::Run {
+            image: "alpine".to_string(),
+            command: vec!["true".to_string()],
+            env: vec![],
+            workdir: None,
+            mounts: vec![],
+            timeout_ms: None,
+            interactive: false,
+            tty: false,
+            ephemeral: false,
+            max_output_bytes: None,
+            heartbeat: None,
+            memory_mib: Some(256),
+            cpu_quota: None,
+            security: SecurityOptions::default(),
+            user: None,
+            no_create_workdir: false,
+            platform: None,
+            entrypoint: None,
+            tmpfs: vec![],
+            read_only: false,
+            extra_hosts: vec![],
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(json.contains(r#""memory_mib":256"#));
+        assert!(!json.contains("entrypoint"));
+        assert!(!json.contains("extra_hosts"));
+        assert!(!json.contains("tmpfs"));
+        assert!(!json.contains("read_only"));
+        assert!(!json.contains("cpu_quota"));
+        assert!(!json.contains("security"));
+        assert!(!json.contains("user"));
+    }
+
+    #[test]
+    fn test_security_options_roundtrip() {
+        let security = SecurityOptions {
+            cap_drop: vec!["ALL".to_string()],
+            cap_add: vec!["NET_BIND_SERVICE".to_string()],
+            no_new_privileges: true,
+            seccomp_allow: Some(vec!["read".to_string(), "write".to_string()]),
+        };
+        let json = serde_json::to_string(&security).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SecurityOptions>(&json).unwrap(),
+            security
+        );
+
+        // Unset fields are left out
+        let json = serde_json::to_string(&SecurityOptions {
+            cap_drop: vec!["ALL".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(json, r#"{"cap_drop":["ALL"]}"#);
+        assert!(serde_json::from_str::<SecurityOptions>("{}")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_protocol_error_code_mapping() {
+        let cases = [
+            (
+                error_codes::INVALID_REQUEST,
+                ProtocolErrorCode::InvalidRequest,
+            ),
+            (error_codes::NOT_FOUND, ProtocolErrorCode::NotFound),
+            (
+                error_codes::INTERNAL_ERROR,
+                ProtocolErrorCode::InternalError,
+            ),
+            (error_codes::PULL_FAILED, ProtocolErrorCode::PullFailed),
+            (error_codes::QUERY_FAILED, ProtocolErrorCode::QueryFailed),
+            (error_codes::RUN_FAILED, ProtocolErrorCode::RunFailed),
+            (error_codes::EXEC_FAILED, ProtocolErrorCode::ExecFailed),
+            (error_codes::SPAWN_FAILED, ProtocolErrorCode::SpawnFailed),
+            (error_codes::MOUNT_FAILED, ProtocolErrorCode::MountFailed),
+            (
+                error_codes::OVERLAY_FAILED,
+                ProtocolErrorCode::OverlayFailed,
+            ),
+            (
+                error_codes::CLEANUP_FAILED,
+                ProtocolErrorCode::CleanupFailed,
+            ),
+            (error_codes::FORMAT_FAILED, ProtocolErrorCode::FormatFailed),
+            (error_codes::STATUS_FAILED, ProtocolErrorCode::StatusFailed),
+            (error_codes::LIST_FAILED, ProtocolErrorCode::ListFailed),
+            (error_codes::GC_FAILED, ProtocolErrorCode::GcFailed),
+            (error_codes::CREATE_FAILED, ProtocolErrorCode::CreateFailed),
+            (error_codes::START_FAILED, ProtocolErrorCode::StartFailed),
+            (error_codes::STOP_FAILED, ProtocolErrorCode::StopFailed),
+            (error_codes::DELETE_FAILED, ProtocolErrorCode::DeleteFailed),
+            (error_codes::EXPORT_FAILED, ProtocolErrorCode::ExportFailed),
+            (error_codes::IMPORT_FAILED, ProtocolErrorCode::ImportFailed),
+            (
+                error_codes::SERIALIZATION_ERROR,
+                ProtocolErrorCode::SerializationError,
+            ),
+            (
+                error_codes::MESSAGE_TOO_LARGE,
+                ProtocolErrorCode::MessageTooLarge,
+            ),
+            (error_codes::WAIT_FAILED, ProtocolErrorCode::WaitFailed),
+            (error_codes::VOLUME_FAILED, ProtocolErrorCode::VolumeFailed),
+            (error_codes::NO_SPACE, ProtocolErrorCode::NoSpace),
+            (
+                error_codes::ALREADY_FORMATTED,
+                ProtocolErrorCode::AlreadyFormatted,
+            ),
+            (error_codes::TAG_FAILED, ProtocolErrorCode::TagFailed),
+            (
+                error_codes::WORKDIR_NOT_FOUND,
+                ProtocolErrorCode::WorkdirNotFound,
+            ),
+            (error_codes::ARCH_MISMATCH, ProtocolErrorCode::ArchMismatch),
+            (error_codes::VERIFY_FAILED, ProtocolErrorCode::VerifyFailed),
+            (
+                error_codes::PLATFORM_UNAVAILABLE,
+                ProtocolErrorCode::PlatformUnavailable,
+            ),
+            (
+                error_codes::PULL_CANCELLED,
+                ProtocolErrorCode::PullCancelled,
+            ),
+            (error_codes::IMAGE_IN_USE, ProtocolErrorCode::ImageInUse),
+        ];
+        for (code, expected) in cases {
+            let parsed = ProtocolErrorCode::from_code(code);
+            assert_eq!(parsed, expected, "code {}", code);
+            assert_eq!(parsed.as_str(), code);
+        }
+    }
+
+    #[test]
+    fn test_protocol_error_code_unknown_roundtrips() {
+        let parsed = ProtocolErrorCode::from_code("SOMETHING_NEW");
+        assert_eq!(
+            parsed,
+            ProtocolErrorCode::Unknown("SOMETHING_NEW".to_string())
+        );
+        assert_eq!(parsed.to_string(), "SOMETHING_NEW");
+    }
+
+    #[test]
+    fn test_completed_reason_defaults_to_exited() {
+        let json = r#"{"status":"completed","exit_code":0,"stdout":"","stderr":""}"#;
+        let resp: AgentResponse = serde_json::from_str(json).unwrap();
+        let AgentResponse::Completed { reason, .. } = resp else {
+            panic!("expected Completed variant, got {:?}", resp);
+        };
+        assert_eq!(reason, ExitReason::Exited);
+    }
+
+    #[test]
+    fn test_exit_reason_serialization() {
+        let reason = ExitReason::Signaled { signal: 9 };
+        let json = serde_json::to_string(&reason).unwrap();
+        assert_eq!(json, r#"{"kind":"signaled","signal":9}"#);
+        assert_eq!(serde_json::from_str::<ExitReason>(&json).unwrap(), reason);
+
+        let json = serde_json::to_string(&ExitReason::OomKilled).unwrap();
+        assert_eq!(json, r#"{"kind":"oom_killed"}"#);
+        assert_eq!(
+            ExitReason::OomKilled.describe().as_deref(),
+            Some("killed: out of memory")
+        );
+        assert_eq!(ExitReason::Exited.describe(), None);
+    }
+
+    #[test]
+    fn test_container_status_serde() {
+        let json = serde_json::to_string(&ContainerStatus::Exited { code: Some(3) }).unwrap();
+        assert_eq!(json, r#"{"state":"exited","code":3}"#);
+        let status: ContainerStatus =
+            serde_json::from_str(r#"{"state":"error","message":"no such file"}"#).unwrap();
+        assert_eq!(
+            status,
+            ContainerStatus::Error {
+                message: "no such file".into()
+            }
+        );
+        assert_eq!(ContainerStatus::Exited { code: None }.to_string(), "exited");
+
+        // Older agents send neither status nor lifecycle timestamps; the
+        // status then follows the legacy state
+        let info: ContainerInfo = serde_json::from_str(
+            r#"{"id":"c1","image":"alpine","state":"running","created_at":1,"command":[]}"#,
+        )
+        .unwrap();
+        assert_eq!(info.status, ContainerStatus::Running);
+        assert_eq!(info.started_at, None);
+        assert_eq!(info.finished_at, None);
+        for (state, status) in [
+            ("created", ContainerStatus::Created),
+            ("stopped", ContainerStatus::Exited { code: None }),
+        ] {
+            let info: ContainerInfo = serde_json::from_value(serde_json::json!({
+                "id": "c1", "image": "alpine", "state": state, "created_at": 1, "command": [],
+            }))
+            .unwrap();
+            assert_eq!(info.status, status);
+        }
+
+        // A status sent by the agent wins over the legacy state
+        let info: ContainerInfo = serde_json::from_value(serde_json::json!({
+            "id": "c1", "image": "alpine", "state": "stopped", "created_at": 1, "command": [],
+            "status": {"state": "error", "message": "no such file"},
+        }))
+        .unwrap();
+        assert_eq!(info.status.name(), "error");
+    }
+
+    #[test]
+    fn test_restart_policy() {
+        let on_failure = RestartPolicy::OnFailure { max_retries: 2 };
+        assert!(on_failure.should_restart(Some(1), 1));
+        assert!(on_failure.should_restart(None, 0));
+        assert!(!on_failure.should_restart(Some(0), 0));
+        assert!(!on_failure.should_restart(Some(1), 2));
+        assert!(RestartPolicy::Always.should_restart(Some(0), 100));
+        assert!(!RestartPolicy::No.should_restart(Some(1), 0));
+
+        for text in ["no", "always", "on-failure", "on-failure:3"] {
+            assert_eq!(text.parse::<RestartPolicy>().unwrap().to_string(), text);
+        }
+        assert!("on-failure:x".parse::<RestartPolicy>().is_err());
+        assert!("sometimes".parse::<RestartPolicy>().is_err());
+
+        // The default policy is left out of the request
+        let req = AgentRequest::CreateContainer {
+            image: "alpine".into(),
+            command: vec!["sh".into()],
+            env: vec![],
+            workdir: None,
+            mounts: vec![],
+            restart_policy: RestartPolicy::No,
+            labels: BTreeMap::new(),
+            healthcheck: None,
+        };
+        let json = serde_json::to_value(&req).unwrap();
+        assert!(json.get("restart_policy").is_none());
+        assert!(json.get("labels").is_none());
+        assert!(json.get("healthcheck").is_none());
+
+        let json = serde_json::to_value(on_failure).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"policy": "on-failure", "max_retries": 2})
+        );
+    }
+
+    #[test]
+    fn test_healthcheck_defaults_and_validation() {
+        let check: HealthCheck =
+            serde_json::from_value(serde_json::json!({"command": ["true"], "retries": 5})).unwrap();
+        assert_eq!(check.interval_ms, HealthCheck::DEFAULT_INTERVAL_MS);
+        assert_eq!(check.timeout_ms, HealthCheck::DEFAULT_TIMEOUT_MS);
+        assert_eq!(check.retries, 5);
+        assert!(check.validate().is_ok());
+
+        assert!(HealthCheck::new(vec![]).validate().is_err());
+        assert!(HealthCheck {
+            interval_ms: 0,
+            ..HealthCheck::new(vec!["true".into()])
+        }
+        .validate()
+        .is_err());
+        assert!(HealthCheck {
+            retries: 0,
+            ..HealthCheck::new(vec!["true".into()])
+        }
+        .validate()
+        .is_err());
+
+        assert_eq!(
+            serde_json::to_value(HealthStatus::Unhealthy).unwrap(),
+            "unhealthy"
+        );
+    }
+
     #[test]
     fn test_ports_constants() {
         assert_eq!(ports::WORKLOAD_CONTROL, 5000);
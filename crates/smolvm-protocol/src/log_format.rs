@@ -0,0 +1,97 @@
+//! Log output format shared by the host and agent.
+//!
+//! Both default to human-readable text. Operators shipping logs to a
+//! collector can switch to one JSON object per line, with event and span
+//! fields (request method, workload ID, ...) as separate keys.
+//!
+//! The host forwards [`LOG_FORMAT_ENV`] into the VM, so one setting
+//! covers both sides.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Environment variable selecting the log format (`text` or `json`).
+pub const LOG_FORMAT_ENV: &str = "SMOLVM_LOG_FORMAT";
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl LogFormat {
+    /// The format named by [`LOG_FORMAT_ENV`], if it is set.
+    pub fn from_env() -> Option<Result<Self, ParseLogFormatError>> {
+        std::env::var(LOG_FORMAT_ENV).ok().map(|v| v.parse())
+    }
+
+    /// The name accepted by [`FromStr`].
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Json => "json",
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = ParseLogFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(ParseLogFormatError {
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Error parsing a log format name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseLogFormatError {
+    /// The value that isn't a known format.
+    pub value: String,
+}
+
+impl fmt::Display for ParseLogFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid log format '{}': expected 'text' or 'json'",
+            self.value
+        )
+    }
+}
+
+impl std::error::Error for ParseLogFormatError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_format() {
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert_eq!(" TEXT ".parse(), Ok(LogFormat::Text));
+        assert_eq!(LogFormat::default(), LogFormat::Text);
+        for format in [LogFormat::Text, LogFormat::Json] {
+            assert_eq!(format.to_string().parse(), Ok(format));
+        }
+
+        let err = "yaml".parse::<LogFormat>().unwrap_err();
+        assert_eq!(err.value, "yaml");
+        assert!(err.to_string().contains("'text' or 'json'"));
+    }
+}
@@ -0,0 +1,201 @@
+//! Request IDs for several requests on one connection.
+//!
+//! A host may tag a request with a `request_id` by sending a
+//! [`RequestFrame`] in place of a bare [`AgentRequest`]. An agent with the
+//! [`REQUEST_ID`](crate::capabilities::REQUEST_ID) capability echoes the ID
+//! on every frame it sends for that request, streaming ones included, as a
+//! [`ResponseFrame`]. The host can then send several requests without
+//! waiting and sort the frames with a [`Demultiplexer`].
+//!
+//! The ID is a top-level field next to the `method` / `status` tag and is
+//! omitted when absent, so untagged frames are exactly the bare request and
+//! response encodings older peers exchange.
+//!
+//! ```text
+//! {"method":"vm_exec","request_id":7,"command":["true"],...}
+//! {"status":"completed","request_id":7,"exit_code":0,...}
+//! ```
+
+use crate::{AgentRequest, AgentResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// A request, optionally tagged with the ID its responses will carry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestFrame {
+    /// ID echoed on every response to this request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+    /// The request itself.
+    #[serde(flatten)]
+    pub request: AgentRequest,
+}
+
+impl From<AgentRequest> for RequestFrame {
+    fn from(request: AgentRequest) -> Self {
+        Self {
+            request_id: None,
+            request,
+        }
+    }
+}
+
+/// A response, tagged with the ID of the request it answers if that
+/// request had one.
+///
+/// `R` is [`AgentResponse`] or a reference to one, so responses can be sent
+/// without cloning them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseFrame<R = AgentResponse> {
+    /// ID of the request this frame belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+    /// The response itself.
+    #[serde(flatten)]
+    pub response: R,
+}
+
+/// Sorts response frames from one connection by request ID.
+#[derive(Debug, Default)]
+pub struct Demultiplexer {
+    pending: HashMap<Option<u64>, VecDeque<AgentResponse>>,
+}
+
+impl Demultiplexer {
+    /// Create an empty demultiplexer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue a frame read from the connection.
+    pub fn push(&mut self, frame: ResponseFrame) {
+        self.pending
+            .entry(frame.request_id)
+            .or_default()
+            .push_back(frame.response);
+    }
+
+    /// Take the oldest queued response for `request_id`, if any.
+    pub fn pop(&mut self, request_id: Option<u64>) -> Option<AgentResponse> {
+        let queue = self.pending.get_mut(&request_id)?;
+        let response = queue.pop_front();
+        if queue.is_empty() {
+            self.pending.remove(&request_id);
+        }
+        response
+    }
+
+    /// Take the next response for `request_id`, reading frames with
+    /// `read_frame` (and queueing those for other requests) until one
+    /// arrives.
+    pub fn recv<E>(
+        &mut self,
+        request_id: Option<u64>,
+        mut read_frame: impl FnMut() -> Result<ResponseFrame, E>,
+    ) -> Result<AgentResponse, E> {
+        loop {
+            if let Some(response) = self.pop(request_id) {
+                return Ok(response);
+            }
+            self.push(read_frame()?);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_untagged_frames_match_bare_encoding() {
+        let frame = RequestFrame::from(AgentRequest::Ping);
+        assert_eq!(
+            serde_json::to_value(&frame).unwrap(),
+            serde_json::to_value(AgentRequest::Ping).unwrap()
+        );
+
+        let response = AgentResponse::Exited { exit_code: 1 };
+        let frame = ResponseFrame {
+            request_id: None,
+            response: &response,
+        };
+        assert_eq!(
+            serde_json::to_string(&frame).unwrap(),
+            serde_json::to_string(&response).unwrap()
+        );
+
+        // Bare responses parse as untagged frames
+        let frame: ResponseFrame = serde_json::from_str(r#"{"status":"started"}"#).unwrap();
+        assert_eq!(frame.request_id, None);
+        assert!(matches!(frame.response, AgentResponse::Started));
+    }
+
+    #[test]
+    fn test_request_id_roundtrip() {
+        let json = serde_json::to_value(RequestFrame {
+            request_id: Some(7),
+            request: AgentRequest::Ping,
+        })
+        .unwrap();
+        assert_eq!(json, serde_json::json!({"method": "ping", "request_id": 7}));
+        let frame: RequestFrame = serde_json::from_value(json).unwrap();
+        assert_eq!(frame.request_id, Some(7));
+        assert!(matches!(frame.request, AgentRequest::Ping));
+
+        // Agents that don't know the field still parse the request
+        let request: AgentRequest =
+            serde_json::from_str(r#"{"method":"ping","request_id":7}"#).unwrap();
+        assert!(matches!(request, AgentRequest::Ping));
+
+        let frame: ResponseFrame = serde_json::from_value(serde_json::json!({
+            "status": "stdout",
+            "request_id": 3,
+            "data": "aGk=",
+        }))
+        .unwrap();
+        assert_eq!(frame.request_id, Some(3));
+        assert!(matches!(frame.response, AgentResponse::Stdout { ref data } if data == b"hi"));
+    }
+
+    #[test]
+    fn test_demultiplexer_routes_interleaved_frames() {
+        let stdout = |id, data: &[u8]| ResponseFrame {
+            request_id: Some(id),
+            response: AgentResponse::Stdout {
+                data: data.to_vec(),
+            },
+        };
+        let exited = |id, exit_code| ResponseFrame {
+            request_id: Some(id),
+            response: AgentResponse::Exited { exit_code },
+        };
+        let mut wire = VecDeque::from([
+            stdout(1, b"a1"),
+            stdout(2, b"b1"),
+            stdout(1, b"a2"),
+            exited(2, 0),
+            exited(1, 3),
+        ]);
+        let mut read = || wire.pop_front().ok_or("connection closed");
+
+        type Read<'a> = dyn FnMut() -> Result<ResponseFrame, &'static str> + 'a;
+        fn collect(demux: &mut Demultiplexer, id: u64, read: &mut Read<'_>) -> (Vec<u8>, i32) {
+            let mut out = Vec::new();
+            loop {
+                match demux.recv(Some(id), &mut *read).unwrap() {
+                    AgentResponse::Stdout { data } => out.extend(data),
+                    AgentResponse::Exited { exit_code } => return (out, exit_code),
+                    other => panic!("unexpected {:?}", other),
+                }
+            }
+        }
+
+        let mut demux = Demultiplexer::new();
+
+        // Request 2 finishes first even though request 1's frames came first
+        assert_eq!(collect(&mut demux, 2, &mut read), (b"b1".to_vec(), 0));
+        assert_eq!(collect(&mut demux, 1, &mut read), (b"a1a2".to_vec(), 3));
+        assert!(demux.pop(Some(1)).is_none());
+        assert!(read().is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Choosing the OCI platform an image is pulled and run as.
+//!
+//! A platform requested with `--oci-platform` is always used as given.
+//! Otherwise the VM's native platform is preferred, and on an arm64 VM
+//! with Rosetta an image published only for `linux/amd64` is used as that.
+//! Pulls and runs both default through [`platform_candidates`], so an image
+//! pulled without a platform is also accepted by a run without one.
+
+/// The platform Rosetta lets an arm64 VM run.
+pub const ROSETTA_PLATFORM: &str = "linux/amd64";
+
+/// The architecture of the machine this is built for, named as in OCI
+/// platforms (`arm64`, `amd64`).
+pub fn native_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "aarch64" => "arm64",
+        "x86_64" => "amd64",
+        other => other,
+    }
+}
+
+/// The platforms an image may be used as, most preferred first.
+///
+/// Just `requested` if there is one. Otherwise `linux/<arch>`, followed by
+/// [`ROSETTA_PLATFORM`] if `arch` is arm64 and `rosetta` is available.
+pub fn platform_candidates(requested: Option<&str>, arch: &str, rosetta: bool) -> Vec<String> {
+    if let Some(requested) = requested {
+        return vec![requested.to_string()];
+    }
+    let mut candidates = vec![format!("linux/{}", arch)];
+    if rosetta && arch == "arm64" {
+        candidates.push(ROSETTA_PLATFORM.to_string());
+    }
+    candidates
+}
+
+/// The platform to use for an image published for `available` platforms.
+///
+/// This is the first of [`platform_candidates`] the image is published for,
+/// counting `linux/arm64/v8` as `linux/arm64`. If none are, or `available`
+/// isn't known, it's the most preferred candidate.
+pub fn resolve_oci_platform(
+    requested: Option<&str>,
+    arch: &str,
+    rosetta: bool,
+    available: Option<&[String]>,
+) -> String {
+    let mut candidates = platform_candidates(requested, arch, rosetta);
+    let chosen = available
+        .and_then(|available| {
+            candidates
+                .iter()
+                .position(|candidate| available.iter().any(|a| platform_matches(a, candidate)))
+        })
+        .unwrap_or(0);
+    candidates.swap_remove(chosen)
+}
+
+/// Whether `platform` is `candidate` or a variant of it.
+fn platform_matches(platform: &str, candidate: &str) -> bool {
+    platform
+        .strip_prefix(candidate)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn published(platforms: &[&str]) -> Vec<String> {
+        platforms.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_resolve_oci_platform_matrix() {
+        let both = published(&["linux/amd64", "linux/arm64/v8"]);
+        let amd64_only = published(&["linux/amd64"]);
+
+        // arm64 with Rosetta: native first, amd64 only as a fallback
+        assert_eq!(
+            resolve_oci_platform(None, "arm64", true, None),
+            "linux/arm64"
+        );
+        assert_eq!(
+            resolve_oci_platform(None, "arm64", true, Some(&both)),
+            "linux/arm64"
+        );
+        assert_eq!(
+            resolve_oci_platform(None, "arm64", true, Some(&amd64_only)),
+            "linux/amd64"
+        );
+
+        // arm64 without Rosetta never falls back
+        assert_eq!(
+            resolve_oci_platform(None, "arm64", false, Some(&amd64_only)),
+            "linux/arm64"
+        );
+
+        // amd64 hosts are native only, Rosetta or not
+        assert_eq!(
+            resolve_oci_platform(None, "amd64", true, Some(&both)),
+            "linux/amd64"
+        );
+        assert_eq!(
+            resolve_oci_platform(None, "amd64", false, Some(&published(&["linux/arm64"]))),
+            "linux/amd64"
+        );
+
+        // An explicit platform always wins
+        assert_eq!(
+            resolve_oci_platform(Some("linux/amd64"), "arm64", false, Some(&both)),
+            "linux/amd64"
+        );
+        assert_eq!(
+            resolve_oci_platform(Some("linux/riscv64"), "amd64", true, Some(&both)),
+            "linux/riscv64"
+        );
+    }
+
+    #[test]
+    fn test_platform_candidates() {
+        assert_eq!(
+            platform_candidates(None, "arm64", true),
+            vec!["linux/arm64", "linux/amd64"]
+        );
+        assert_eq!(
+            platform_candidates(None, "arm64", false),
+            vec!["linux/arm64"]
+        );
+        assert_eq!(
+            platform_candidates(None, "amd64", true),
+            vec!["linux/amd64"]
+        );
+        assert_eq!(
+            platform_candidates(Some("linux/arm64/v8"), "amd64", true),
+            vec!["linux/arm64/v8"]
+        );
+    }
+
+    #[test]
+    fn test_platform_matches_variants() {
+        assert!(platform_matches("linux/arm64", "linux/arm64"));
+        assert!(platform_matches("linux/arm64/v8", "linux/arm64"));
+        assert!(!platform_matches("linux/arm64v8", "linux/arm64"));
+        assert!(!platform_matches("linux/amd64", "linux/arm64"));
+    }
+}
@@ -7,7 +7,7 @@
 //! retry behavior across the system.
 
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tracing::{debug, warn};
 
 // ============================================================================
@@ -61,6 +61,27 @@ const CONNECTION_INITIAL_DELAY_MS: u64 = 50;
 /// is likely wrong beyond a transient issue.
 const CONNECTION_MAX_DELAY_SECS: u64 = 2;
 
+/// Total time budget for connection retries (10 seconds).
+/// Bounds reconnect storms: with jitter the attempt count alone no longer
+/// pins down how long a caller can be kept waiting.
+const CONNECTION_MAX_ELAPSED_SECS: u64 = 10;
+
+/// Randomization applied to each backoff delay.
+///
+/// Without jitter, clients that fail together (e.g. every sandbox losing its
+/// agent connection when the host restarts) retry in lockstep. Jitter spreads
+/// them out. See <https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/>.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Jitter {
+    /// Sleep exactly the computed backoff delay.
+    #[default]
+    None,
+    /// Sleep a random duration in `[0, delay]`.
+    Full,
+    /// Sleep `delay / 2` plus a random duration in `[0, delay / 2]`.
+    Equal,
+}
+
 /// Configuration for retry behavior.
 #[derive(Debug, Clone)]
 pub struct RetryConfig {
@@ -72,6 +93,14 @@ pub struct RetryConfig {
     pub max_delay: Duration,
     /// Multiplier for exponential backoff (typically 2.0).
     pub backoff_multiplier: f64,
+    /// Randomization applied to each delay.
+    pub jitter: Jitter,
+    /// Wall-clock budget for all attempts. No retry is started if its delay
+    /// would end past this budget; `None` means only `max_attempts` applies.
+    pub max_elapsed: Option<Duration>,
+    /// Seed for the jitter generator, for reproducible delays in tests.
+    /// `None` seeds from the clock.
+    pub jitter_seed: Option<u64>,
 }
 
 impl Default for RetryConfig {
@@ -81,6 +110,9 @@ impl Default for RetryConfig {
             initial_delay: Duration::from_millis(DEFAULT_INITIAL_DELAY_MS),
             max_delay: Duration::from_secs(DEFAULT_MAX_DELAY_SECS),
             backoff_multiplier: BACKOFF_MULTIPLIER,
+            jitter: Jitter::None,
+            max_elapsed: None,
+            jitter_seed: None,
         }
     }
 }
@@ -96,19 +128,65 @@ impl RetryConfig {
             initial_delay: Duration::from_millis(NETWORK_INITIAL_DELAY_MS),
             max_delay: Duration::from_secs(NETWORK_MAX_DELAY_SECS),
             backoff_multiplier: BACKOFF_MULTIPLIER,
+            jitter: Jitter::Equal,
+            max_elapsed: None,
+            jitter_seed: None,
         }
     }
 
     /// Create a config for socket/connection operations.
     ///
     /// Uses short delays because the agent should already be running.
-    /// These retries handle brief unavailability during high load.
+    /// These retries handle brief unavailability during high load. Full
+    /// jitter keeps many clients reconnecting at once from retrying in step.
     pub fn for_connection() -> Self {
         Self {
             max_attempts: DEFAULT_MAX_ATTEMPTS,
             initial_delay: Duration::from_millis(CONNECTION_INITIAL_DELAY_MS),
             max_delay: Duration::from_secs(CONNECTION_MAX_DELAY_SECS),
             backoff_multiplier: BACKOFF_MULTIPLIER,
+            jitter: Jitter::Full,
+            max_elapsed: Some(Duration::from_secs(CONNECTION_MAX_ELAPSED_SECS)),
+            jitter_seed: None,
+        }
+    }
+}
+
+/// Small deterministic generator for jitter (splitmix64).
+struct JitterRng(u64);
+
+impl JitterRng {
+    fn new(seed: Option<u64>) -> Self {
+        Self(seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or_default()
+        }))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `[0, 1]`.
+    fn next_unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / ((1u64 << 53) - 1) as f64
+    }
+}
+
+/// Apply `jitter` to a backoff `delay`.
+fn jittered(delay: Duration, jitter: Jitter, rng: &mut JitterRng) -> Duration {
+    match jitter {
+        Jitter::None => delay,
+        Jitter::Full => delay.mul_f64(rng.next_unit()),
+        Jitter::Equal => {
+            let half = delay / 2;
+            half + (delay - half).mul_f64(rng.next_unit())
         }
     }
 }
@@ -116,7 +194,8 @@ impl RetryConfig {
 /// Execute an operation with retry logic.
 ///
 /// The `should_retry` function determines whether a given error is transient
-/// and worth retrying.
+/// and worth retrying. Stops after `max_attempts`, or once the next delay
+/// would run past `max_elapsed`, returning the last error.
 ///
 /// # Example
 ///
@@ -139,6 +218,8 @@ where
     R: Fn(&E) -> bool,
     E: std::fmt::Display,
 {
+    let started = Instant::now();
+    let mut rng = JitterRng::new(config.jitter_seed);
     let mut attempt = 0;
     let mut delay = config.initial_delay;
 
@@ -177,16 +258,31 @@ where
                     return Err(e);
                 }
 
+                let sleep = jittered(delay, config.jitter, &mut rng);
+                if config
+                    .max_elapsed
+                    .is_some_and(|max| started.elapsed() + sleep > max)
+                {
+                    warn!(
+                        operation = %operation_name,
+                        attempts = attempt,
+                        elapsed_ms = started.elapsed().as_millis(),
+                        error = %e,
+                        "operation failed, retry budget exhausted"
+                    );
+                    return Err(e);
+                }
+
                 warn!(
                     operation = %operation_name,
                     attempt = attempt,
                     max_attempts = config.max_attempts,
-                    delay_ms = delay.as_millis(),
+                    delay_ms = sleep.as_millis(),
                     error = %e,
                     "operation failed, will retry"
                 );
 
-                thread::sleep(delay);
+                thread::sleep(sleep);
 
                 // Exponential backoff with cap
                 delay = Duration::from_secs_f64(
@@ -227,8 +323,14 @@ pub fn is_transient_network_error(error_msg: &str) -> bool {
         return true;
     }
 
+    // Timeouts (e.g. Go's "i/o timeout", "TLS handshake timeout")
+    if error_lower.contains("i/o timeout") || error_lower.contains("handshake timeout") {
+        return true;
+    }
+
     // HTTP errors that may be transient
-    if error_lower.contains("502 bad gateway")
+    if error_lower.contains("500 internal server error")
+        || error_lower.contains("502 bad gateway")
         || error_lower.contains("503 service unavailable")
         || error_lower.contains("504 gateway timeout")
         || error_lower.contains("429 too many requests")
@@ -327,6 +429,7 @@ mod tests {
                 initial_delay: Duration::from_millis(1),
                 max_delay: Duration::from_millis(10),
                 backoff_multiplier: 2.0,
+                ..Default::default()
             },
             "test",
             || {
@@ -352,6 +455,7 @@ mod tests {
                 initial_delay: Duration::from_millis(1),
                 max_delay: Duration::from_millis(10),
                 backoff_multiplier: 2.0,
+                ..Default::default()
             },
             "test",
             || {
@@ -386,6 +490,10 @@ mod tests {
         assert!(is_transient_network_error("Connection timed out"));
         assert!(is_transient_network_error("503 Service Unavailable"));
         assert!(is_transient_network_error("rate limit exceeded"));
+        assert!(is_transient_network_error("500 Internal Server Error"));
+        assert!(is_transient_network_error(
+            "dial tcp 1.2.3.4:443: i/o timeout"
+        ));
         assert!(!is_transient_network_error("404 not found"));
         assert!(!is_transient_network_error("some random error"));
     }
@@ -408,5 +516,64 @@ mod tests {
         let connection = RetryConfig::for_connection();
         assert_eq!(connection.max_attempts, 3);
         assert_eq!(connection.initial_delay, Duration::from_millis(50));
+        assert_eq!(connection.jitter, Jitter::Full);
+        assert!(connection.max_elapsed.is_some());
+    }
+
+    #[test]
+    fn test_retry_stops_at_max_elapsed() {
+        let attempts = RefCell::new(0);
+        let max_elapsed = Duration::from_millis(60);
+        let started = Instant::now();
+        let result: Result<i32, &str> = retry_with_backoff(
+            RetryConfig {
+                max_attempts: u32::MAX,
+                initial_delay: Duration::from_millis(5),
+                max_delay: Duration::from_millis(20),
+                backoff_multiplier: 2.0,
+                jitter: Jitter::Equal,
+                max_elapsed: Some(max_elapsed),
+                jitter_seed: Some(7),
+            },
+            "test",
+            || {
+                *attempts.borrow_mut() += 1;
+                Err("always fails")
+            },
+            |_| true,
+        );
+        assert_eq!(result.unwrap_err(), "always fails");
+        assert!(*attempts.borrow() > 1);
+        // Sleeps never cross the budget; only the (instant) attempts could
+        assert!(started.elapsed() < max_elapsed + Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_jitter_within_interval() {
+        let delay = Duration::from_millis(400);
+        let mut rng = JitterRng::new(Some(42));
+        let full: Vec<_> = (0..200)
+            .map(|_| jittered(delay, Jitter::Full, &mut rng))
+            .collect();
+        assert!(full.iter().all(|d| *d <= delay));
+        assert!(full.iter().any(|d| *d < delay / 2));
+
+        let equal: Vec<_> = (0..200)
+            .map(|_| jittered(delay, Jitter::Equal, &mut rng))
+            .collect();
+        assert!(equal.iter().all(|d| *d >= delay / 2 && *d <= delay));
+        assert!(equal.windows(2).any(|w| w[0] != w[1]));
+
+        assert_eq!(jittered(delay, Jitter::None, &mut rng), delay);
+
+        // The same seed yields the same delays
+        let mut a = JitterRng::new(Some(9));
+        let mut b = JitterRng::new(Some(9));
+        for _ in 0..10 {
+            assert_eq!(
+                jittered(delay, Jitter::Full, &mut a),
+                jittered(delay, Jitter::Full, &mut b)
+            );
+        }
     }
 }
@@ -0,0 +1,17 @@
+//! Scratch disk for container overlay upper layers.
+//!
+//! A VM created with a scratch disk gets it as its third block device. The
+//! host tells the agent where to mount it in [`OVERLAY_UPPER_DIR_ENV`], and
+//! the agent keeps every container overlay's upper and work directories
+//! there, so the layer store on the storage disk only takes reads.
+
+/// Environment variable naming the directory overlays' upper and work
+/// directories go in.
+pub const OVERLAY_UPPER_DIR_ENV: &str = "SMOLVM_OVERLAY_UPPER_DIR";
+
+/// Block device the scratch disk appears as in the guest, after the storage
+/// (`/dev/vda`) and rootfs overlay (`/dev/vdb`) disks.
+pub const SCRATCH_DEVICE: &str = "/dev/vdc";
+
+/// Where the agent mounts the scratch disk.
+pub const SCRATCH_MOUNT: &str = "/scratch";
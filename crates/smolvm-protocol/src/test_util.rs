@@ -0,0 +1,13 @@
+//! Helpers for tests in the smolvm crates (the `test-util` feature).
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// Write an executable shell script `name` into `dir` that runs `body`, to
+/// stand in for an external tool. Returns its path.
+pub fn fake_tool(dir: &Path, name: &str, body: &str) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
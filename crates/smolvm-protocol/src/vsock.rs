@@ -0,0 +1,360 @@
+//! vsock sockets for the daemons on either side of the protocol.
+//!
+//! Guests listen with [`listen`] on one of the [`ports`](crate::ports) and
+//! the peer connects with [`connect`]. vsock only exists on Linux, so for
+//! testing a daemon outside a VM the same calls can use Unix sockets instead:
+//! [`listen_unix`] and [`connect_unix`] take a path directly, and when
+//! [`UNIX_FALLBACK_ENV`] names a directory, [`listen`] and [`connect`] use
+//! the socket [`unix_path`] gives for the port there.
+//!
+//! Both kinds of socket are [`VsockListener`] / [`VsockStream`], and all
+//! errors are [`std::io::Error`].
+
+use std::io::{Read, Write};
+use std::os::fd::OwnedFd;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// Environment variable naming a directory of Unix sockets to use in place
+/// of vsock.
+pub const UNIX_FALLBACK_ENV: &str = "SMOLVM_VSOCK_UNIX_DIR";
+
+/// Listening socket, vsock or Unix.
+#[derive(Debug)]
+pub struct VsockListener {
+    inner: Socket<OwnedFd, UnixListener>,
+}
+
+/// Connected socket, vsock or Unix.
+#[derive(Debug)]
+pub struct VsockStream {
+    inner: Socket<OwnedFd, UnixStream>,
+}
+
+#[derive(Debug)]
+enum Socket<V, U> {
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    Vsock(V),
+    Unix(U),
+}
+
+/// Path of the Unix socket standing in for vsock `port` under `dir`.
+pub fn unix_path(dir: &Path, port: u32) -> PathBuf {
+    dir.join(format!("{}.sock", port))
+}
+
+fn unix_fallback_dir() -> Option<PathBuf> {
+    std::env::var_os(UNIX_FALLBACK_ENV)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Listen on vsock `port` (or its Unix socket, see [`UNIX_FALLBACK_ENV`]).
+pub fn listen(port: u32) -> std::io::Result<VsockListener> {
+    match unix_fallback_dir() {
+        Some(dir) => listen_unix(unix_path(&dir, port)),
+        None => VsockListener::bind(port),
+    }
+}
+
+/// Connect to vsock `port` on `cid` (or its Unix socket, see
+/// [`UNIX_FALLBACK_ENV`], in which case `cid` is ignored).
+pub fn connect(cid: u32, port: u32) -> std::io::Result<VsockStream> {
+    match unix_fallback_dir() {
+        Some(dir) => connect_unix(unix_path(&dir, port)),
+        None => VsockStream::connect(cid, port),
+    }
+}
+
+/// Listen on the Unix socket at `path`.
+pub fn listen_unix(path: impl AsRef<Path>) -> std::io::Result<VsockListener> {
+    Ok(VsockListener {
+        inner: Socket::Unix(UnixListener::bind(path)?),
+    })
+}
+
+/// Connect to the Unix socket at `path`.
+pub fn connect_unix(path: impl AsRef<Path>) -> std::io::Result<VsockStream> {
+    Ok(VsockStream {
+        inner: Socket::Unix(UnixStream::connect(path)?),
+    })
+}
+
+impl VsockListener {
+    /// Accept a new connection.
+    pub fn accept(&self) -> std::io::Result<VsockStream> {
+        let inner = match &self.inner {
+            Socket::Vsock(fd) => Socket::Vsock(sys::accept(fd)?),
+            Socket::Unix(listener) => Socket::Unix(listener.accept()?.0),
+        };
+        Ok(VsockStream { inner })
+    }
+}
+
+impl VsockStream {
+    /// Shut down both directions of the connection.
+    pub fn shutdown(&self) -> std::io::Result<()> {
+        match &self.inner {
+            Socket::Vsock(fd) => sys::shutdown(fd),
+            Socket::Unix(stream) => stream.shutdown(std::net::Shutdown::Both),
+        }
+    }
+}
+
+impl AsRawFd for VsockListener {
+    fn as_raw_fd(&self) -> RawFd {
+        match &self.inner {
+            Socket::Vsock(fd) => fd.as_raw_fd(),
+            Socket::Unix(listener) => listener.as_raw_fd(),
+        }
+    }
+}
+
+impl AsRawFd for VsockStream {
+    fn as_raw_fd(&self) -> RawFd {
+        match &self.inner {
+            Socket::Vsock(fd) => fd.as_raw_fd(),
+            Socket::Unix(stream) => stream.as_raw_fd(),
+        }
+    }
+}
+
+impl Read for VsockStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match &mut self.inner {
+            Socket::Vsock(fd) => sys::read(fd, buf),
+            Socket::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for VsockStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match &mut self.inner {
+            Socket::Vsock(fd) => sys::write(fd, buf),
+            Socket::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.inner {
+            Socket::Vsock(_) => Ok(()),
+            Socket::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::*;
+    use std::mem;
+    use std::os::fd::FromRawFd;
+
+    #[repr(C)]
+    struct sockaddr_vm {
+        svm_family: libc::sa_family_t,
+        svm_reserved1: u16,
+        svm_port: u32,
+        svm_cid: u32,
+        svm_zero: [u8; 4],
+    }
+
+    impl sockaddr_vm {
+        fn new(cid: u32, port: u32) -> Self {
+            Self {
+                svm_family: libc::AF_VSOCK as libc::sa_family_t,
+                svm_reserved1: 0,
+                svm_port: port,
+                svm_cid: cid,
+                svm_zero: [0; 4],
+            }
+        }
+    }
+
+    const SOCKADDR_VM_LEN: libc::socklen_t = mem::size_of::<sockaddr_vm>() as libc::socklen_t;
+
+    fn socket() -> std::io::Result<OwnedFd> {
+        let fd = unsafe { libc::socket(libc::AF_VSOCK, libc::SOCK_STREAM, 0) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    fn check(ret: libc::c_int) -> std::io::Result<()> {
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    impl VsockListener {
+        pub(super) fn bind(port: u32) -> std::io::Result<Self> {
+            let fd = socket()?;
+            let addr = sockaddr_vm::new(crate::cid::ANY, port);
+            check(unsafe {
+                libc::bind(
+                    fd.as_raw_fd(),
+                    &addr as *const sockaddr_vm as *const libc::sockaddr,
+                    SOCKADDR_VM_LEN,
+                )
+            })?;
+            check(unsafe { libc::listen(fd.as_raw_fd(), 1) })?;
+            Ok(Self {
+                inner: Socket::Vsock(fd),
+            })
+        }
+    }
+
+    impl VsockStream {
+        pub(super) fn connect(cid: u32, port: u32) -> std::io::Result<Self> {
+            let fd = socket()?;
+            let addr = sockaddr_vm::new(cid, port);
+            check(unsafe {
+                libc::connect(
+                    fd.as_raw_fd(),
+                    &addr as *const sockaddr_vm as *const libc::sockaddr,
+                    SOCKADDR_VM_LEN,
+                )
+            })?;
+            Ok(Self {
+                inner: Socket::Vsock(fd),
+            })
+        }
+    }
+
+    pub(super) fn accept(listener: &OwnedFd) -> std::io::Result<OwnedFd> {
+        let fd = unsafe {
+            libc::accept(
+                listener.as_raw_fd(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    pub(super) fn shutdown(fd: &OwnedFd) -> std::io::Result<()> {
+        check(unsafe { libc::shutdown(fd.as_raw_fd(), libc::SHUT_RDWR) })
+    }
+
+    pub(super) fn read(fd: &OwnedFd, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len()) };
+        if n < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(n as usize)
+    }
+
+    pub(super) fn write(fd: &OwnedFd, buf: &[u8]) -> std::io::Result<usize> {
+        let n = unsafe { libc::write(fd.as_raw_fd(), buf.as_ptr() as *const _, buf.len()) };
+        if n < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(n as usize)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use super::*;
+
+    fn unsupported() -> std::io::Error {
+        std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "vsock only supported on Linux",
+        )
+    }
+
+    impl VsockListener {
+        pub(super) fn bind(_port: u32) -> std::io::Result<Self> {
+            Err(unsupported())
+        }
+    }
+
+    impl VsockStream {
+        pub(super) fn connect(_cid: u32, _port: u32) -> std::io::Result<Self> {
+            Err(unsupported())
+        }
+    }
+
+    // No vsock socket can be created, so these are never reached.
+    pub(super) fn accept(_listener: &OwnedFd) -> std::io::Result<OwnedFd> {
+        Err(unsupported())
+    }
+
+    pub(super) fn shutdown(_fd: &OwnedFd) -> std::io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub(super) fn read(_fd: &OwnedFd, _buf: &mut [u8]) -> std::io::Result<usize> {
+        Err(unsupported())
+    }
+
+    pub(super) fn write(_fd: &OwnedFd, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encode_message, AgentRequest, AgentResponse};
+
+    /// Read one length-prefixed frame from `stream`.
+    fn read_frame<T: for<'de> serde::Deserialize<'de>>(stream: &mut VsockStream) -> T {
+        let mut header = [0u8; 4];
+        stream.read_exact(&mut header).unwrap();
+        let mut buf = vec![0u8; u32::from_be_bytes(header) as usize];
+        stream.read_exact(&mut buf).unwrap();
+        serde_json::from_slice(&buf).unwrap()
+    }
+
+    #[test]
+    fn test_unix_fallback_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = unix_path(dir.path(), crate::ports::AGENT_CONTROL);
+        assert_eq!(path, dir.path().join("6000.sock"));
+
+        let listener = listen_unix(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let mut stream = listener.accept().unwrap();
+            let request: AgentRequest = read_frame(&mut stream);
+            assert!(matches!(request, AgentRequest::Ping));
+            stream
+                .write_all(
+                    &encode_message(&AgentResponse::Pong {
+                        version: 7,
+                        capabilities: Vec::new(),
+                    })
+                    .unwrap(),
+                )
+                .unwrap();
+            stream.flush().unwrap();
+        });
+
+        let mut client = connect_unix(&path).unwrap();
+        client
+            .write_all(&encode_message(&AgentRequest::Ping).unwrap())
+            .unwrap();
+        let response: AgentResponse = read_frame(&mut client);
+        assert!(matches!(response, AgentResponse::Pong { version: 7, .. }));
+        server.join().unwrap();
+
+        // The server dropped its end
+        let mut rest = Vec::new();
+        assert_eq!(client.read_to_end(&mut rest).unwrap(), 0);
+        client.shutdown().unwrap();
+    }
+
+    #[test]
+    fn test_connect_unix_without_listener_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = connect_unix(unix_path(dir.path(), 1)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    }
+}
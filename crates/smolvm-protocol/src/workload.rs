@@ -0,0 +1,424 @@
+//! Workload protocol served on [`ports::WORKLOAD_CONTROL`](crate::ports).
+//!
+//! The workload protocol ([`HostMessage`] / [`GuestMessage`]) is a small,
+//! authenticated command-execution protocol. It is not a second agent: a
+//! [`WorkloadSession`] translates each message onto the [`AgentRequest`] the
+//! agent already handles and translates the [`AgentResponse`] back.
+//!
+//! | Host message        | Agent request                  | Guest messages                 |
+//! |---------------------|--------------------------------|--------------------------------|
+//! | `Auth`              | -                              | `AuthOk`, `Ready` / `AuthFailed` |
+//! | `Run`               | `VmExec`                       | `Started`, `Stdout`, `Stderr`, `Exit` |
+//! | `Exec` (no tty)     | `VmExec`                       | as for `Run`                   |
+//! | `Stop`              | `Stop`                         | - (connection is closed)       |
+//! | `Signal`, tty `Exec`| -                              | `Error`                        |
+//!
+//! Commands run to completion before the next message is read, so there is
+//! never a running command to signal, and output arrives in one `Stdout` and
+//! one `Stderr` message after the command has exited.
+//!
+//! Messages use the same length-prefixed JSON framing as the agent protocol;
+//! see [`read_message`] and [`write_message`].
+//!
+//! The agent only serves the protocol when the host passes it a token in
+//! [`WORKLOAD_TOKEN_ENV`] at boot.
+
+use crate::{
+    AgentRequest, AgentResponse, GuestMessage, HostMessage, MAX_FRAME_SIZE, PROTOCOL_VERSION,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{self, Read, Write};
+
+/// Environment variable carrying the token workload clients authenticate
+/// with. The workload port is only served when it is set.
+pub const WORKLOAD_TOKEN_ENV: &str = "SMOLVM_WORKLOAD_TOKEN";
+
+/// Server side of one workload connection.
+#[derive(Debug)]
+pub struct WorkloadSession {
+    token: String,
+    authenticated: bool,
+}
+
+/// What the server should do after handling a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply {
+    /// Messages to send back, in order.
+    pub messages: Vec<GuestMessage>,
+    /// Close the connection once `messages` are sent.
+    pub close: bool,
+}
+
+impl WorkloadSession {
+    /// Start a session that accepts `token` in `Auth`.
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+            authenticated: false,
+        }
+    }
+
+    /// Whether the host has authenticated.
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+
+    /// Handle one message from the host, running commands with `dispatch`.
+    pub fn handle(
+        &mut self,
+        message: HostMessage,
+        dispatch: impl FnOnce(AgentRequest) -> AgentResponse,
+    ) -> Reply {
+        if let HostMessage::Auth {
+            token,
+            protocol_version,
+        } = &message
+        {
+            if self.authenticated {
+                return reply(error(None, "already authenticated"));
+            }
+            if !constant_time_eq(token.as_bytes(), self.token.as_bytes())
+                || *protocol_version != PROTOCOL_VERSION
+            {
+                return Reply {
+                    messages: vec![GuestMessage::AuthFailed],
+                    close: true,
+                };
+            }
+            self.authenticated = true;
+            return Reply {
+                messages: vec![GuestMessage::AuthOk, GuestMessage::Ready],
+                close: false,
+            };
+        }
+
+        if !self.authenticated {
+            return Reply {
+                messages: vec![error(None, "not authenticated")],
+                close: true,
+            };
+        }
+
+        let request_id = request_id(&message);
+        match agent_request(&message) {
+            Ok(request) => {
+                let response = dispatch(request);
+                Reply {
+                    messages: guest_messages(request_id, response),
+                    close: matches!(message, HostMessage::Stop { .. }),
+                }
+            }
+            Err(message) => reply(error(request_id, message)),
+        }
+    }
+}
+
+/// Compare two byte strings in time that depends only on their lengths, so
+/// a token can't be guessed a byte at a time.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn reply(message: GuestMessage) -> Reply {
+    Reply {
+        messages: vec![message],
+        close: false,
+    }
+}
+
+fn error(request_id: Option<u64>, message: impl Into<String>) -> GuestMessage {
+    GuestMessage::Error {
+        request_id,
+        message: message.into(),
+    }
+}
+
+fn request_id(message: &HostMessage) -> Option<u64> {
+    match message {
+        HostMessage::Run { request_id, .. }
+        | HostMessage::Exec { request_id, .. }
+        | HostMessage::Signal { request_id, .. } => Some(*request_id),
+        HostMessage::Auth { .. } | HostMessage::Stop { .. } => None,
+    }
+}
+
+/// The agent request a host message maps onto, or why it has none.
+pub fn agent_request(message: &HostMessage) -> Result<AgentRequest, String> {
+    let vm_exec = |command: &[String], env: &[(String, String)], workdir: &Option<String>| {
+        AgentRequest::VmExec {
+            command: command.to_vec(),
+            env: env.to_vec(),
+            workdir: workdir.clone(),
+            timeout_ms: None,
+            interactive: false,
+            tty: false,
+            heartbeat: None,
+        }
+    };
+    match message {
+        HostMessage::Run {
+            command,
+            env,
+            workdir,
+            ..
+        } => Ok(vm_exec(command, env, workdir)),
+        HostMessage::Exec {
+            command,
+            tty: false,
+            ..
+        } => Ok(vm_exec(command, &[], &None)),
+        HostMessage::Exec { tty: true, .. } => {
+            Err("tty exec is not supported by the workload protocol".to_string())
+        }
+        HostMessage::Stop { timeout_ms } => Ok(AgentRequest::Stop {
+            timeout_ms: *timeout_ms,
+        }),
+        HostMessage::Signal { request_id, .. } => {
+            Err(format!("request {} is not running", request_id))
+        }
+        HostMessage::Auth { .. } => Err("auth has no agent request".to_string()),
+    }
+}
+
+/// The guest messages reporting an agent response to the request
+/// `request_id`.
+pub fn guest_messages(request_id: Option<u64>, response: AgentResponse) -> Vec<GuestMessage> {
+    match (request_id, response) {
+        (
+            Some(request_id),
+            AgentResponse::Completed {
+                exit_code,
+                stdout,
+                stderr,
+                stdout_truncated,
+                stderr_truncated,
+                reason,
+            },
+        ) => {
+            let mut messages = vec![GuestMessage::Started { request_id }];
+            if !stdout.is_empty() || stdout_truncated {
+                messages.push(GuestMessage::Stdout {
+                    request_id,
+                    data: stdout.into_bytes(),
+                    truncated: stdout_truncated,
+                });
+            }
+            if !stderr.is_empty() || stderr_truncated {
+                messages.push(GuestMessage::Stderr {
+                    request_id,
+                    data: stderr.into_bytes(),
+                    truncated: stderr_truncated,
+                });
+            }
+            messages.push(GuestMessage::Exit {
+                request_id,
+                code: exit_code,
+                reason: reason.describe().unwrap_or_else(|| "exited".to_string()),
+            });
+            messages
+        }
+        (request_id, AgentResponse::Error { message, .. }) => {
+            vec![GuestMessage::Error {
+                request_id,
+                message,
+            }]
+        }
+        (None, _) => Vec::new(),
+        (request_id, response) => vec![GuestMessage::Error {
+            request_id,
+            message: format!("unexpected agent response: {:?}", response),
+        }],
+    }
+}
+
+/// Read one length-prefixed message. Returns `None` if the peer closed the
+/// connection between messages.
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl Read) -> io::Result<Option<T>> {
+    let mut header = [0u8; 4];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(header);
+    if len > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame too large: {} bytes", len),
+        ));
+    }
+    let mut body = vec![0u8; len as usize];
+    reader.read_exact(&mut body)?;
+    crate::decode_json(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Write one length-prefixed message.
+pub fn write_message(writer: &mut impl Write, message: &impl Serialize) -> io::Result<()> {
+    let frame = crate::encode_message(message)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExitReason;
+
+    fn auth(token: &str) -> HostMessage {
+        HostMessage::Auth {
+            token: token.to_string(),
+            protocol_version: PROTOCOL_VERSION,
+        }
+    }
+
+    fn no_dispatch(request: AgentRequest) -> AgentResponse {
+        panic!("unexpected dispatch of {:?}", request)
+    }
+
+    #[test]
+    fn test_auth_ready_run_exit() {
+        let mut session = WorkloadSession::new("secret");
+
+        let reply = session.handle(auth("secret"), no_dispatch);
+        assert_eq!(reply.messages, [GuestMessage::AuthOk, GuestMessage::Ready]);
+        assert!(!reply.close);
+
+        let run = HostMessage::Run {
+            request_id: 7,
+            command: vec!["sh".into(), "-c".into(), "echo hi; exit 3".into()],
+            env: vec![("A".into(), "1".into())],
+            workdir: Some("/tmp".into()),
+        };
+        let reply = session.handle(run, |request| {
+            let AgentRequest::VmExec {
+                command,
+                env,
+                workdir,
+                interactive,
+                ..
+            } = request
+            else {
+                panic!("expected VmExec, got {:?}", request);
+            };
+            assert_eq!(command[0], "sh");
+            assert_eq!(env, [("A".to_string(), "1".to_string())]);
+            assert_eq!(workdir.as_deref(), Some("/tmp"));
+            assert!(!interactive);
+            AgentResponse::Completed {
+                exit_code: 3,
+                stdout: "hi\n".into(),
+                stderr: String::new(),
+                stdout_truncated: false,
+                stderr_truncated: false,
+                reason: ExitReason::Exited,
+            }
+        });
+        assert_eq!(
+            reply.messages,
+            [
+                GuestMessage::Started { request_id: 7 },
+                GuestMessage::Stdout {
+                    request_id: 7,
+                    data: b"hi\n".to_vec(),
+                    truncated: false,
+                },
+                GuestMessage::Exit {
+                    request_id: 7,
+                    code: 3,
+                    reason: "exited".into(),
+                },
+            ]
+        );
+
+        let reply = session.handle(HostMessage::Stop { timeout_ms: 1000 }, |request| {
+            assert!(matches!(request, AgentRequest::Stop { timeout_ms: 1000 }));
+            AgentResponse::Ok { data: None }
+        });
+        assert!(reply.messages.is_empty());
+        assert!(reply.close);
+    }
+
+    #[test]
+    fn test_commands_require_auth() {
+        let mut session = WorkloadSession::new("secret");
+        let reply = session.handle(
+            HostMessage::Exec {
+                request_id: 1,
+                command: vec!["id".into()],
+                tty: false,
+            },
+            no_dispatch,
+        );
+        assert!(matches!(
+            reply.messages[..],
+            [GuestMessage::Error {
+                request_id: None,
+                ..
+            }]
+        ));
+        assert!(reply.close);
+
+        let reply = session.handle(auth("wrong"), no_dispatch);
+        assert_eq!(reply.messages, [GuestMessage::AuthFailed]);
+        assert!(reply.close);
+        assert!(!session.is_authenticated());
+
+        let reply = WorkloadSession::new("secret").handle(
+            HostMessage::Auth {
+                token: "secret".into(),
+                protocol_version: PROTOCOL_VERSION + 1,
+            },
+            no_dispatch,
+        );
+        assert_eq!(reply.messages, [GuestMessage::AuthFailed]);
+    }
+
+    #[test]
+    fn test_unmapped_messages_report_errors() {
+        let mut session = WorkloadSession::new("t");
+        session.handle(auth("t"), no_dispatch);
+        for message in [
+            HostMessage::Signal {
+                request_id: 2,
+                signal: 15,
+            },
+            HostMessage::Exec {
+                request_id: 2,
+                command: vec!["sh".into()],
+                tty: true,
+            },
+        ] {
+            let reply = session.handle(message, no_dispatch);
+            assert!(matches!(
+                reply.messages[..],
+                [GuestMessage::Error {
+                    request_id: Some(2),
+                    ..
+                }]
+            ));
+            assert!(!reply.close);
+        }
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn test_message_framing_roundtrip() {
+        let mut buf = Vec::new();
+        write_message(&mut buf, &GuestMessage::Ready).unwrap();
+        let mut reader = &buf[..];
+        let message: Option<GuestMessage> = read_message(&mut reader).unwrap();
+        assert_eq!(message, Some(GuestMessage::Ready));
+        let eof: Option<GuestMessage> = read_message(&mut reader).unwrap();
+        assert_eq!(eof, None);
+    }
+}
@@ -0,0 +1,501 @@
+//! Async client for communicating with the smolvm-agent.
+//!
+//! [`AsyncAgentClient`] speaks the same length-prefixed JSON protocol as the
+//! blocking [`AgentClient`](super::AgentClient), but over a
+//! `tokio::net::UnixStream`, so the API server can talk to agents without
+//! tying up a blocking thread per request. Framing and response
+//! interpretation are shared with the blocking client.
+
+use super::client::{
+    check_frame_len, check_protocol_version, decode_response, parse_pong, pull_step,
+    resolve_pull_target, PullStep, DEFAULT_READ_TIMEOUT_SECS, DEFAULT_WRITE_TIMEOUT_SECS,
+    IMAGE_PULL_TIMEOUT_SECS, INTERACTIVE_TIMEOUT_SECS, READY_PING_INTERVAL_MS,
+};
+use super::PullOptions;
+use crate::error::{Error, ErrorKind, Result};
+use smolvm_protocol::{
+    capabilities, encode_message, AgentRequest, AgentResponse, HeartbeatConfig, ImageInfo,
+};
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio_stream::Stream;
+
+/// Async client for communicating with the smolvm-agent.
+pub struct AsyncAgentClient {
+    stream: UnixStream,
+    /// Capabilities advertised in the agent's last Pong, if it has been
+    /// pinged on this connection.
+    capabilities: Option<Vec<String>>,
+}
+
+impl AsyncAgentClient {
+    /// Connect to the agent via Unix socket.
+    pub async fn connect(socket_path: impl AsRef<Path>) -> Result<Self> {
+        let stream = UnixStream::connect(socket_path.as_ref())
+            .await
+            .map_err(|e| Error::agent_io("connect to agent", &e))?;
+        Ok(Self::from_stream(stream))
+    }
+
+    /// Wrap an already-connected stream.
+    pub fn from_stream(stream: UnixStream) -> Self {
+        Self {
+            stream,
+            capabilities: None,
+        }
+    }
+
+    /// Send a request and receive a single response.
+    pub async fn request(&mut self, req: &AgentRequest) -> Result<AgentResponse> {
+        self.send(req).await?;
+        self.receive_within(Duration::from_secs(DEFAULT_READ_TIMEOUT_SECS))
+            .await
+    }
+
+    /// Ping the agent and validate the protocol version.
+    ///
+    /// Returns the agent's protocol version and records its capabilities
+    /// for [`supported`](Self::supported).
+    pub async fn ping(&mut self) -> Result<u32> {
+        let (version, capabilities) = parse_pong(self.request(&AgentRequest::Ping).await?)?;
+        self.capabilities = Some(capabilities);
+        Ok(version)
+    }
+
+    /// Ping the agent until it answers, for up to `timeout`.
+    ///
+    /// Like [`AgentClient::ping_until_ready`](super::AgentClient::ping_until_ready):
+    /// failed pings are retried on this connection, an incompatible protocol
+    /// version fails immediately, and running out of time returns
+    /// [`Error::BootTimeout`] with the last failure.
+    pub async fn ping_until_ready(&mut self, timeout: Duration) -> Result<u32> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut last_error = None;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::agent_not_ready(timeout, last_error.as_ref()));
+            }
+            let ping = async {
+                self.send(&AgentRequest::Ping).await?;
+                let (version, capabilities) = parse_pong(self.receive_within(remaining).await?)?;
+                self.capabilities = Some(capabilities);
+                Ok(version)
+            };
+            match ping.await {
+                Ok(version) => return check_protocol_version(version),
+                Err(e) => {
+                    tracing::trace!(error = %e, "agent not ready yet");
+                    last_error = Some(e);
+                }
+            }
+            tokio::time::sleep_until(
+                (tokio::time::Instant::now() + Duration::from_millis(READY_PING_INTERVAL_MS))
+                    .min(deadline),
+            )
+            .await;
+        }
+    }
+
+    /// Whether the agent advertised capability `cap` (see
+    /// [`smolvm_protocol::capabilities`]).
+    ///
+    /// Returns false until the agent has answered a ping.
+    pub fn supported(&self, cap: &str) -> bool {
+        self.capabilities
+            .as_ref()
+            .is_some_and(|caps| caps.iter().any(|c| c == cap))
+    }
+
+    /// Whether the agent supports `cap`, pinging it first if its
+    /// capabilities aren't known yet.
+    async fn has_capability(&mut self, cap: &str) -> Result<bool> {
+        if self.capabilities.is_none() {
+            self.ping().await?;
+        }
+        Ok(self.supported(cap))
+    }
+
+    /// Pull an OCI image into the agent's storage.
+    ///
+    /// Progress frames are reported through the options' progress callback.
+    pub async fn pull<F: FnMut(usize, usize, &str)>(
+        &mut self,
+        image: &str,
+        options: PullOptions<F>,
+    ) -> Result<ImageInfo> {
+        let target = resolve_pull_target(
+            image,
+            options.auth,
+            options.max_bytes_per_sec,
+            options.use_registry_config,
+        );
+        let mut progress = options.progress;
+
+        for (needed, capability) in [
+            (options.no_cache, capabilities::PULL_NO_CACHE),
+            (
+                target.max_bytes_per_sec.is_some(),
+                capabilities::PULL_RATE_LIMIT,
+            ),
+        ] {
+            if needed && !self.has_capability(capability).await? {
+                return Err(Error::unsupported("pull image", capability));
+            }
+        }
+        let heartbeat = self
+            .has_capability(capabilities::HEARTBEAT)
+            .await?
+            .then(HeartbeatConfig::from_env);
+
+        self.send(&AgentRequest::Pull {
+            image: target.image,
+            oci_platform: options.oci_platform,
+            auth: target.auth,
+            no_cache: options.no_cache,
+            max_bytes_per_sec: target.max_bytes_per_sec,
+            heartbeat,
+        })
+        .await?;
+
+        // Large images can take minutes between frames while a layer
+        // downloads or extracts, unless the agent sends heartbeats: then
+        // something arrives every interval.
+        let mut timeout = Duration::from_secs(IMAGE_PULL_TIMEOUT_SECS);
+        let mut heartbeat_started = false;
+        loop {
+            let response = match self.receive_within(timeout).await {
+                Err(e) if heartbeat_started && e.kind() == ErrorKind::Timeout => {
+                    return Err(Error::agent_with_kind(
+                        ErrorKind::Timeout,
+                        "pull image",
+                        "agent heartbeat lost",
+                    ));
+                }
+                response => response?,
+            };
+            match pull_step(response)? {
+                PullStep::Progress { percent, layer } => {
+                    if let Some(ref mut cb) = progress {
+                        cb(percent, 100, &layer);
+                    }
+                }
+                PullStep::Heartbeat => {
+                    if let Some(config) = heartbeat {
+                        timeout = config.timeout();
+                        heartbeat_started = true;
+                    }
+                    self.send(&AgentRequest::Heartbeat).await?;
+                }
+                PullStep::Done(info) => return Ok(*info),
+            }
+        }
+    }
+
+    /// Stream the response frames of a request already sent with
+    /// [`send`](Self::send), e.g. the output of an interactive run or a
+    /// layer export.
+    ///
+    /// The stream ends after the first terminal frame (`Exited`,
+    /// `Completed`, `Ok`, `Error`, or the last `LayerData` chunk), or after
+    /// the first read error.
+    pub fn responses(&mut self) -> impl Stream<Item = Result<AgentResponse>> + '_ {
+        async_stream::stream! {
+            let timeout = Duration::from_secs(INTERACTIVE_TIMEOUT_SECS);
+            loop {
+                match self.receive_within(timeout).await {
+                    Ok(resp) => {
+                        let done = is_terminal(&resp);
+                        yield Ok(resp);
+                        if done {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send a request without waiting for a response.
+    pub async fn send(&mut self, req: &AgentRequest) -> Result<()> {
+        let data =
+            encode_message(req).map_err(|e| Error::agent("encode message", e.to_string()))?;
+        let write = async {
+            self.stream.write_all(&data).await?;
+            self.stream.flush().await
+        };
+        tokio::time::timeout(Duration::from_secs(DEFAULT_WRITE_TIMEOUT_SECS), write)
+            .await
+            .map_err(|_| Error::agent_with_kind(ErrorKind::Timeout, "send message", "timed out"))?
+            .map_err(|e| Error::agent_io("send message", &e))
+    }
+
+    /// Receive a single response, failing if none arrives within `timeout`.
+    async fn receive_within(&mut self, timeout: Duration) -> Result<AgentResponse> {
+        tokio::time::timeout(timeout, self.receive())
+            .await
+            .map_err(|_| {
+                Error::agent_with_kind(ErrorKind::Timeout, "receive response", "timed out")
+            })?
+    }
+
+    /// Receive a single response.
+    ///
+    /// Not cancellation-safe: if the future is dropped mid-frame (e.g. by a
+    /// timeout) the stream is desynchronized and the client should be
+    /// discarded.
+    async fn receive(&mut self) -> Result<AgentResponse> {
+        let mut header = [0u8; 4];
+        self.stream
+            .read_exact(&mut header)
+            .await
+            .map_err(|e| Error::agent_io("receive response", &e))?;
+        let len = u32::from_be_bytes(header) as usize;
+        check_frame_len(len)?;
+
+        let mut body = vec![0u8; len];
+        self.stream
+            .read_exact(&mut body)
+            .await
+            .map_err(|e| Error::agent_io("receive response", &e))?;
+        decode_response(&body)
+    }
+}
+
+/// Whether `resp` is the last frame the agent sends for a request.
+fn is_terminal(resp: &AgentResponse) -> bool {
+    match resp {
+        AgentResponse::Ok { .. }
+        | AgentResponse::Pong { .. }
+        | AgentResponse::Error { .. }
+        | AgentResponse::Completed { .. }
+        | AgentResponse::Exited { .. } => true,
+        AgentResponse::LayerData { done, .. } => *done,
+        AgentResponse::Progress { .. }
+        | AgentResponse::Started
+        | AgentResponse::ExecSession { .. }
+        | AgentResponse::Stdout { .. }
+        | AgentResponse::Stderr { .. }
+        | AgentResponse::Heartbeat => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smolvm_protocol::{capabilities, PROTOCOL_VERSION};
+    use tokio::task::JoinHandle;
+    use tokio_stream::StreamExt;
+
+    /// Connect a client to a mock agent that answers each request with the
+    /// frames `respond` returns for its method. The handle yields the
+    /// methods received once the client disconnects.
+    fn client_with_mock_agent(
+        respond: fn(&str) -> Vec<AgentResponse>,
+    ) -> (AsyncAgentClient, JoinHandle<Vec<String>>) {
+        let (host, mut agent) = UnixStream::pair().unwrap();
+
+        let handle = tokio::spawn(async move {
+            let mut methods = Vec::new();
+            let mut header = [0u8; 4];
+            while agent.read_exact(&mut header).await.is_ok() {
+                let mut body = vec![0u8; u32::from_be_bytes(header) as usize];
+                agent.read_exact(&mut body).await.unwrap();
+                let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                let method = request["method"].as_str().unwrap().to_string();
+
+                for response in respond(&method) {
+                    agent
+                        .write_all(&encode_message(&response).unwrap())
+                        .await
+                        .unwrap();
+                }
+                methods.push(method);
+            }
+            methods
+        });
+
+        (AsyncAgentClient::from_stream(host), handle)
+    }
+
+    #[tokio::test]
+    async fn test_request_response() {
+        let (mut client, agent) = client_with_mock_agent(|method| match method {
+            "ping" => vec![AgentResponse::Pong {
+                version: PROTOCOL_VERSION,
+                capabilities: vec![capabilities::HEARTBEAT.to_string()],
+            }],
+            _ => vec![AgentResponse::Ok { data: None }],
+        });
+
+        assert!(!client.supported(capabilities::HEARTBEAT));
+        assert_eq!(client.ping().await.unwrap(), PROTOCOL_VERSION);
+        assert!(client.supported(capabilities::HEARTBEAT));
+        assert!(!client.supported(capabilities::RESOURCE_LIMITS));
+
+        let resp = client.request(&AgentRequest::Shutdown).await.unwrap();
+        assert!(matches!(resp, AgentResponse::Ok { data: None }));
+
+        drop(client);
+        assert_eq!(agent.await.unwrap(), ["ping", "shutdown"]);
+    }
+
+    #[tokio::test]
+    async fn test_ping_until_ready_retries_until_pong() {
+        static PINGS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let (mut client, agent) = client_with_mock_agent(|_| {
+            if PINGS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < 2 {
+                vec![AgentResponse::Error {
+                    message: "storage not ready".to_string(),
+                    code: None,
+                    data: None,
+                }]
+            } else {
+                vec![AgentResponse::Pong {
+                    version: PROTOCOL_VERSION,
+                    capabilities: Vec::new(),
+                }]
+            }
+        });
+
+        let version = client
+            .ping_until_ready(Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!(version, PROTOCOL_VERSION);
+        drop(client);
+        assert_eq!(agent.await.unwrap(), ["ping", "ping", "ping"]);
+    }
+
+    #[tokio::test]
+    async fn test_ping_until_ready_times_out() {
+        let (mut client, _agent) = client_with_mock_agent(|_| {
+            vec![AgentResponse::Error {
+                message: "storage not ready".to_string(),
+                code: None,
+                data: None,
+            }]
+        });
+
+        let err = client
+            .ping_until_ready(Duration::from_millis(250))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert!(err.to_string().contains("storage not ready"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_pull_reports_progress() {
+        let (mut client, agent) = client_with_mock_agent(|method| {
+            match method {
+                "ping" => {
+                    return vec![AgentResponse::Pong {
+                        version: PROTOCOL_VERSION,
+                        capabilities: vec![capabilities::HEARTBEAT.to_string()],
+                    }]
+                }
+                "pull" => {}
+                _ => return Vec::new(),
+            }
+            let info = serde_json::json!({
+                "reference": "alpine:latest",
+                "digest": "sha256:abc",
+                "size": 1024,
+                "created": null,
+                "architecture": "arm64",
+                "os": "linux",
+                "layer_count": 1,
+                "layers": ["sha256:layer"],
+            });
+            vec![
+                AgentResponse::Progress {
+                    message: "pulling".to_string(),
+                    percent: Some(50),
+                    layer: Some("sha256:layer".to_string()),
+                },
+                AgentResponse::Heartbeat,
+                AgentResponse::Ok { data: Some(info) },
+            ]
+        });
+
+        let mut seen = Vec::new();
+        let info = client
+            .pull(
+                "alpine:latest",
+                PullOptions::new().progress(|current, total, layer: &str| {
+                    seen.push((current, total, layer.to_string()))
+                }),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(info.digest, "sha256:abc");
+        assert_eq!(seen, [(50, 100, "sha256:layer".to_string())]);
+
+        // The agent's heartbeat was answered
+        drop(client);
+        assert_eq!(agent.await.unwrap(), ["ping", "pull", "heartbeat"]);
+    }
+
+    #[tokio::test]
+    async fn test_pull_error() {
+        let (mut client, _agent) = client_with_mock_agent(|method| match method {
+            "ping" => vec![AgentResponse::Pong {
+                version: PROTOCOL_VERSION,
+                capabilities: Vec::new(),
+            }],
+            _ => vec![AgentResponse::Error {
+                message: "manifest unknown".to_string(),
+                code: None,
+                data: None,
+            }],
+        });
+
+        let err = client
+            .pull("missing:latest", PullOptions::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("manifest unknown"));
+    }
+
+    #[tokio::test]
+    async fn test_responses_stream_ends_at_exit() {
+        let (mut client, _agent) = client_with_mock_agent(|_| {
+            vec![
+                AgentResponse::Started,
+                AgentResponse::Stdout {
+                    data: b"hello\n".to_vec(),
+                },
+                AgentResponse::Stderr {
+                    data: b"oops\n".to_vec(),
+                },
+                AgentResponse::Exited { exit_code: 3 },
+            ]
+        });
+
+        client.send(&AgentRequest::Shutdown).await.unwrap();
+        let frames: Vec<AgentResponse> = client.responses().collect::<Result<_>>().await.unwrap();
+
+        assert_eq!(frames.len(), 4);
+        assert!(matches!(&frames[1], AgentResponse::Stdout { data } if data == b"hello\n"));
+        assert!(matches!(frames[3], AgentResponse::Exited { exit_code: 3 }));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_rejected() {
+        let (host, mut agent) = UnixStream::pair().unwrap();
+        let mut client = AsyncAgentClient::from_stream(host);
+        agent.write_all(&u32::MAX.to_be_bytes()).await.unwrap();
+
+        let err = client.receive().await.unwrap_err();
+        assert!(err.to_string().contains("frame too large"));
+    }
+}
@@ -3,16 +3,24 @@
 //! This module provides a client for sending requests to the agent
 //! and receiving responses.
 
-use crate::error::{Error, Result};
+use super::{Deadline, ImageFilter};
+use crate::error::{Error, ErrorKind, Result};
 use crate::registry::{extract_registry, rewrite_image_registry, RegistryAuth, RegistryConfig};
+use crate::util::{retry_with_backoff, RetryConfig};
+use smolvm_protocol::heartbeat::HeartbeatTracker;
 use smolvm_protocol::{
-    encode_message, AgentRequest, AgentResponse, ContainerInfo, ImageInfo, OverlayInfo,
-    StorageStatus, MAX_FRAME_SIZE, PROTOCOL_VERSION,
+    capabilities, chunked, decode_json, encode_message, AgentRequest, AgentResponse, AgentStatus,
+    ContainerInfo, DiffEntry, ExitReason, HealthCheck, HeartbeatConfig, ImageInfo, ImagePage,
+    LayerUsage, OverlayInfo, ProtocolErrorCode, PrunedOverlays, RequestFrame, ResourceStats,
+    RestartPolicy, SecurityOptions, StorageStatus, TmpfsMount, ToolStatus, VerifyReport,
+    VolumeInfo, MAX_FRAME_SIZE, PROTOCOL_VERSION,
 };
+use std::collections::BTreeMap;
 use std::io::{Read, Write};
+use std::net::IpAddr;
 use std::os::unix::net::UnixStream;
-use std::path::Path;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 // ============================================================================
 // Socket Timeout Constants
@@ -25,20 +33,25 @@ use std::time::Duration;
 /// Default socket read timeout (30 seconds).
 /// Used for most request/response operations. Long enough for the agent to
 /// process requests, short enough to detect hung connections.
-const DEFAULT_READ_TIMEOUT_SECS: u64 = 30;
+pub(super) const DEFAULT_READ_TIMEOUT_SECS: u64 = 30;
 
 /// Default socket write timeout (10 seconds).
 /// Writes should complete quickly - if they don't, the connection is likely broken.
-const DEFAULT_WRITE_TIMEOUT_SECS: u64 = 10;
+pub(super) const DEFAULT_WRITE_TIMEOUT_SECS: u64 = 10;
 
 /// Read timeout for image pull operations (10 minutes).
 /// Image pulls can take a long time for large images over slow connections.
-const IMAGE_PULL_TIMEOUT_SECS: u64 = 600;
+pub(super) const IMAGE_PULL_TIMEOUT_SECS: u64 = 600;
+
+/// Read timeout for image archive transfers (30 minutes).
+/// Covers the agent building a large archive before sending it, and
+/// extracting its layers after receiving one.
+const IMAGE_ARCHIVE_TIMEOUT_SECS: u64 = 1800;
 
 /// Read timeout for interactive/long-running sessions (1 hour).
 /// Used for exec, run, and container exec operations where the user may be
 /// running long commands or interactive shells.
-const INTERACTIVE_TIMEOUT_SECS: u64 = 3600;
+pub(super) const INTERACTIVE_TIMEOUT_SECS: u64 = 3600;
 
 /// Buffer time added to user-specified timeouts (5 seconds).
 /// When users specify a command timeout, we add this buffer to the socket
@@ -49,6 +62,9 @@ const TIMEOUT_BUFFER_SECS: u64 = 5;
 /// Used when checking agent status where we want to fail fast.
 const STATUS_CHECK_TIMEOUT_SECS: u64 = 5;
 
+/// Pause between pings while waiting for the agent to become ready (100ms).
+pub(super) const READY_PING_INTERVAL_MS: u64 = 100;
+
 // ============================================================================
 // I/O Constants
 // ============================================================================
@@ -60,6 +76,9 @@ const STDIN_BUF_SIZE: usize = 4096;
 /// Short enough for responsive SIGWINCH handling, long enough to avoid busy-waiting.
 const POLL_TIMEOUT_MS: i32 = 100;
 
+/// Images fetched per request by [`AgentClient::list_images`].
+pub const LIST_IMAGES_PAGE_SIZE: usize = 256;
+
 /// RAII guard that resets the socket read timeout on drop.
 ///
 /// Ensures the timeout is always restored, even if the operation
@@ -88,6 +107,26 @@ impl Drop for ReadTimeoutGuard {
     }
 }
 
+/// Output of a non-interactive command run.
+#[derive(Debug, Clone)]
+pub struct RunOutput {
+    /// Exit code from the command.
+    pub exit_code: i32,
+    /// Captured standard output.
+    pub stdout: String,
+    /// Captured standard error.
+    pub stderr: String,
+    /// Stdout exceeded the agent's capture limit and was cut off.
+    pub stdout_truncated: bool,
+    /// Stderr exceeded the agent's capture limit and was cut off.
+    pub stderr_truncated: bool,
+    /// Why the command ended (normal exit, signal, timeout, OOM).
+    pub reason: ExitReason,
+}
+
+// Re-export ResourceLimits from protocol so the agent and host share one type
+pub use smolvm_protocol::ResourceLimits;
+
 /// Configuration for running a command interactively.
 #[derive(Debug, Clone)]
 pub struct RunConfig {
@@ -105,6 +144,33 @@ pub struct RunConfig {
     pub timeout: Option<Duration>,
     /// Whether to allocate a TTY.
     pub tty: bool,
+    /// Discard rootfs changes when the command exits instead of persisting
+    /// them in the per-image overlay.
+    pub ephemeral: bool,
+    /// Per-stream cap on captured output in bytes (agent default if `None`).
+    pub max_output_bytes: Option<u64>,
+    /// Memory/CPU limits for the container.
+    pub limits: ResourceLimits,
+    /// Capability, `no_new_privileges` and seccomp restrictions.
+    pub security: SecurityOptions,
+    /// User to run as (`user[:group]`, names or IDs); root if `None`.
+    pub user: Option<String>,
+    /// Create `workdir` if the image doesn't have it (the default), rather
+    /// than failing with [`ProtocolErrorCode::WorkdirNotFound`].
+    pub create_workdir: bool,
+    /// Platform to run the image as (`os/arch`); the VM's architecture if
+    /// `None`. A mismatched image fails with [`ProtocolErrorCode::ArchMismatch`].
+    pub platform: Option<String>,
+    /// Entrypoint replacing the image's, with `command` as its arguments;
+    /// empty to clear it. `command` is the whole argv if `None`.
+    pub entrypoint: Option<Vec<String>>,
+    /// In-memory filesystems to mount in the container.
+    pub tmpfs: Vec<TmpfsMount>,
+    /// Run on the image's layers mounted read-only, with no writable
+    /// overlay. Writes to the root filesystem fail.
+    pub read_only: bool,
+    /// Extra `/etc/hosts` entries as (hostname, address) pairs.
+    pub extra_hosts: Vec<(String, IpAddr)>,
 }
 
 impl RunConfig {
@@ -118,6 +184,17 @@ impl RunConfig {
             mounts: Vec::new(),
             timeout: None,
             tty: false,
+            ephemeral: false,
+            max_output_bytes: None,
+            limits: ResourceLimits::default(),
+            security: SecurityOptions::default(),
+            user: None,
+            create_workdir: true,
+            platform: None,
+            entrypoint: None,
+            tmpfs: Vec::new(),
+            read_only: false,
+            extra_hosts: Vec::new(),
         }
     }
 
@@ -150,6 +227,255 @@ impl RunConfig {
         self.tty = tty;
         self
     }
+
+    /// Use a fresh overlay for this run and remove it afterwards.
+    pub fn with_ephemeral(mut self, ephemeral: bool) -> Self {
+        self.ephemeral = ephemeral;
+        self
+    }
+
+    /// Set the per-stream cap on captured stdout/stderr.
+    pub fn with_max_output_bytes(mut self, max_output_bytes: Option<u64>) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    /// Set memory/CPU limits for the container.
+    pub fn with_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Restrict the container's capabilities and syscalls.
+    pub fn with_security(mut self, security: SecurityOptions) -> Self {
+        self.security = security;
+        self
+    }
+
+    /// Run as `user` (`user[:group]`, names or IDs) instead of root.
+    pub fn with_user(mut self, user: Option<String>) -> Self {
+        self.user = user;
+        self
+    }
+
+    /// Set whether a missing working directory is created.
+    pub fn with_create_workdir(mut self, create_workdir: bool) -> Self {
+        self.create_workdir = create_workdir;
+        self
+    }
+
+    /// Run the image as `platform` (`os/arch`) instead of the VM's architecture.
+    pub fn with_platform(mut self, platform: Option<String>) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    /// Run `command` with `entrypoint` instead of the image's entrypoint.
+    pub fn with_entrypoint(mut self, entrypoint: Option<Vec<String>>) -> Self {
+        self.entrypoint = entrypoint;
+        self
+    }
+
+    /// Mount in-memory filesystems in the container.
+    pub fn with_tmpfs(mut self, tmpfs: Vec<TmpfsMount>) -> Self {
+        self.tmpfs = tmpfs;
+        self
+    }
+
+    /// Mount the image read-only instead of giving the run an overlay.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Add `/etc/hosts` entries to the container, as `--add-host` does.
+    pub fn with_extra_hosts(mut self, extra_hosts: Vec<(String, IpAddr)>) -> Self {
+        self.extra_hosts = extra_hosts;
+        self
+    }
+}
+
+/// Configuration for executing a command in a running container.
+#[derive(Debug, Clone)]
+pub struct ExecConfig {
+    /// Container ID (full or prefix).
+    pub container_id: String,
+    /// Command and arguments to execute.
+    pub command: Vec<String>,
+    /// Environment variables as (key, value) pairs.
+    pub env: Vec<(String, String)>,
+    /// Working directory; the container's if `None`.
+    pub workdir: Option<String>,
+    /// Timeout for command execution.
+    pub timeout: Option<Duration>,
+    /// Whether to allocate a TTY (interactive execs only).
+    pub tty: bool,
+    /// Capability and `no_new_privileges` restrictions for the process; a
+    /// seccomp filter can't be set per exec.
+    pub security: SecurityOptions,
+    /// User to run as (`user[:group]`, names or IDs); the container's user
+    /// if `None`.
+    pub user: Option<String>,
+}
+
+impl ExecConfig {
+    /// Create a new exec configuration for `command` in `container_id`.
+    pub fn new(container_id: impl Into<String>, command: Vec<String>) -> Self {
+        Self {
+            container_id: container_id.into(),
+            command,
+            env: Vec::new(),
+            workdir: None,
+            timeout: None,
+            tty: false,
+            security: SecurityOptions::default(),
+            user: None,
+        }
+    }
+
+    /// Set environment variables.
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Set working directory.
+    pub fn with_workdir(mut self, workdir: Option<String>) -> Self {
+        self.workdir = workdir;
+        self
+    }
+
+    /// Set timeout.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Enable TTY mode.
+    pub fn with_tty(mut self, tty: bool) -> Self {
+        self.tty = tty;
+        self
+    }
+
+    /// Restrict the process's capabilities.
+    pub fn with_security(mut self, security: SecurityOptions) -> Self {
+        self.security = security;
+        self
+    }
+
+    /// Run as `user` (`user[:group]`, names or IDs) instead of the
+    /// container's user.
+    pub fn with_user(mut self, user: Option<String>) -> Self {
+        self.user = user;
+        self
+    }
+
+    /// The `Exec` request for this configuration. Interactive execs send
+    /// heartbeats, and may be resumed after a dropped connection.
+    fn into_request(self, interactive: bool, resumable: bool) -> AgentRequest {
+        AgentRequest::Exec {
+            container_id: self.container_id,
+            command: self.command,
+            env: self.env,
+            workdir: self.workdir,
+            timeout_ms: self.timeout.map(|t| t.as_millis() as u64),
+            interactive,
+            tty: interactive && self.tty,
+            heartbeat: interactive.then(HeartbeatConfig::from_env),
+            memory_mib: None,
+            cpu_quota: None,
+            security: self.security,
+            user: self.user,
+            resumable,
+        }
+    }
+}
+
+/// Configuration for creating a long-running container.
+#[derive(Debug, Clone)]
+pub struct CreateContainerConfig {
+    /// Image reference (must be pulled first).
+    pub image: String,
+    /// Command and arguments to run (e.g. `["sleep", "infinity"]`).
+    pub command: Vec<String>,
+    /// Environment variables as (key, value) pairs.
+    pub env: Vec<(String, String)>,
+    /// Working directory inside the container; `/` if `None`.
+    pub workdir: Option<String>,
+    /// Volume mounts as (virtiofs_tag, container_path, read_only).
+    pub mounts: Vec<(String, String, bool)>,
+    /// Whether the agent restarts the container when it exits.
+    pub restart_policy: RestartPolicy,
+    /// User labels stored with the container.
+    pub labels: BTreeMap<String, String>,
+    /// Command the agent runs periodically to check the container.
+    pub healthcheck: Option<HealthCheck>,
+}
+
+impl CreateContainerConfig {
+    /// Create a new container configuration running `command` from `image`.
+    pub fn new(image: impl Into<String>, command: Vec<String>) -> Self {
+        Self {
+            image: image.into(),
+            command,
+            env: Vec::new(),
+            workdir: None,
+            mounts: Vec::new(),
+            restart_policy: RestartPolicy::No,
+            labels: BTreeMap::new(),
+            healthcheck: None,
+        }
+    }
+
+    /// Set environment variables.
+    pub fn with_env(mut self, env: Vec<(String, String)>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Set working directory.
+    pub fn with_workdir(mut self, workdir: Option<String>) -> Self {
+        self.workdir = workdir;
+        self
+    }
+
+    /// Set volume mounts.
+    pub fn with_mounts(mut self, mounts: Vec<(String, String, bool)>) -> Self {
+        self.mounts = mounts;
+        self
+    }
+
+    /// Set the restart policy.
+    pub fn with_restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    /// Set user labels.
+    pub fn with_labels(mut self, labels: BTreeMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Set the health check.
+    pub fn with_healthcheck(mut self, healthcheck: Option<HealthCheck>) -> Self {
+        self.healthcheck = healthcheck;
+        self
+    }
+
+    /// The `CreateContainer` request for this configuration.
+    fn into_request(self) -> AgentRequest {
+        AgentRequest::CreateContainer {
+            image: self.image,
+            command: self.command,
+            env: self.env,
+            workdir: self.workdir,
+            mounts: self.mounts,
+            restart_policy: self.restart_policy,
+            labels: self.labels,
+            healthcheck: self.healthcheck,
+        }
+    }
 }
 
 /// Options for pulling an OCI image.
@@ -178,6 +504,10 @@ where
     pub auth: Option<RegistryAuth>,
     /// Whether to load credentials from registry config file.
     pub use_registry_config: bool,
+    /// Fetch the manifest again even if the image is cached.
+    pub no_cache: bool,
+    /// Cap on the pull's download rate, in bytes per second.
+    pub max_bytes_per_sec: Option<u64>,
     /// Progress callback: (current, total, layer_id).
     pub progress: Option<F>,
 }
@@ -189,6 +519,8 @@ impl PullOptions<fn(usize, usize, &str)> {
             oci_platform: None,
             auth: None,
             use_registry_config: false,
+            no_cache: false,
+            max_bytes_per_sec: None,
             progress: None,
         }
     }
@@ -211,12 +543,35 @@ impl<F: FnMut(usize, usize, &str)> PullOptions<F> {
     ///
     /// When enabled, loads `~/.config/smolvm/registries.toml` and
     /// automatically provides credentials for matching registries.
-    /// Also applies registry mirrors if configured.
+    /// Also applies registry mirrors and the default download rate limit
+    /// if configured.
     pub fn use_registry_config(mut self, enabled: bool) -> Self {
         self.use_registry_config = enabled;
         self
     }
 
+    /// Fetch the manifest again even if the image is cached.
+    ///
+    /// Picks up updates to a mutable tag like `:latest`: layers the new
+    /// manifest names that aren't cached are extracted, unchanged ones are
+    /// kept, and the tag then points at the new image. The refreshed layers
+    /// are listed in [`ImageInfo::refreshed_layers`]. Requires an agent
+    /// with the [`PULL_NO_CACHE`](capabilities::PULL_NO_CACHE) capability.
+    pub fn no_cache(mut self, enabled: bool) -> Self {
+        self.no_cache = enabled;
+        self
+    }
+
+    /// Cap the pull's download rate at `bytes_per_sec`.
+    ///
+    /// Overrides `max_download_rate` from the registry config. Requires an
+    /// agent with the [`PULL_RATE_LIMIT`](capabilities::PULL_RATE_LIMIT)
+    /// capability.
+    pub fn max_bytes_per_sec(mut self, bytes_per_sec: u64) -> Self {
+        self.max_bytes_per_sec = Some(bytes_per_sec);
+        self
+    }
+
     /// Set a progress callback.
     ///
     /// The callback receives (current_percent, total=100, layer_id) for each layer.
@@ -225,6 +580,8 @@ impl<F: FnMut(usize, usize, &str)> PullOptions<F> {
             oci_platform: self.oci_platform,
             auth: self.auth,
             use_registry_config: self.use_registry_config,
+            no_cache: self.no_cache,
+            max_bytes_per_sec: self.max_bytes_per_sec,
             progress: Some(callback),
         }
     }
@@ -233,6 +590,15 @@ impl<F: FnMut(usize, usize, &str)> PullOptions<F> {
 /// Client for communicating with the smolvm-agent.
 pub struct AgentClient {
     stream: UnixStream,
+    /// Capabilities from the agent's last `Pong`, or `None` until a ping
+    /// has been answered.
+    capabilities: Option<Vec<String>>,
+    /// End-to-end deadline bounding every request, if any.
+    deadline: Option<Deadline>,
+    /// Socket the client connected to, for reconnecting.
+    socket_path: Option<PathBuf>,
+    /// Backoff for reconnecting to resume an interactive exec, if enabled.
+    reconnect: Option<RetryConfig>,
 }
 
 // ============================================================================
@@ -247,7 +613,9 @@ fn expect_data<T: serde::de::DeserializeOwned>(resp: AgentResponse, op: &str) ->
         } => {
             serde_json::from_value(data).map_err(|e| Error::agent("parse response", e.to_string()))
         }
-        AgentResponse::Error { message, .. } => Err(Error::agent(op, message)),
+        AgentResponse::Error { message, code, .. } => {
+            Err(Error::agent_response(op, message, code.as_deref()))
+        }
         _ => Err(Error::agent(op, "unexpected response type")),
     }
 }
@@ -256,24 +624,65 @@ fn expect_data<T: serde::de::DeserializeOwned>(resp: AgentResponse, op: &str) ->
 fn expect_ok(resp: AgentResponse, op: &str) -> Result<()> {
     match resp {
         AgentResponse::Ok { .. } => Ok(()),
-        AgentResponse::Error { message, .. } => Err(Error::agent(op, message)),
+        AgentResponse::Error { message, code, .. } => {
+            Err(Error::agent_response(op, message, code.as_deref()))
+        }
         _ => Err(Error::agent(op, "unexpected response type")),
     }
 }
 
-/// Extract exit code, stdout, stderr from a `Completed` response.
-fn expect_completed(resp: AgentResponse, op: &str) -> Result<(i32, String, String)> {
+/// Extract the full run output from a `Completed` response.
+fn expect_run_output(resp: AgentResponse, op: &str) -> Result<RunOutput> {
     match resp {
         AgentResponse::Completed {
             exit_code,
             stdout,
             stderr,
-        } => Ok((exit_code, stdout, stderr)),
-        AgentResponse::Error { message, .. } => Err(Error::agent(op, message)),
+            stdout_truncated,
+            stderr_truncated,
+            reason,
+        } => Ok(RunOutput {
+            exit_code,
+            stdout,
+            stderr,
+            stdout_truncated,
+            stderr_truncated,
+            reason,
+        }),
+        AgentResponse::Error { message, code, .. } => {
+            Err(Error::agent_response(op, message, code.as_deref()))
+        }
         _ => Err(Error::agent(op, "unexpected response type")),
     }
 }
 
+/// Extract exit code, stdout, stderr from a `Completed` response.
+fn expect_completed(resp: AgentResponse, op: &str) -> Result<(i32, String, String)> {
+    expect_run_output(resp, op).map(|out| (out.exit_code, out.stdout, out.stderr))
+}
+
+/// Convert a failed chunked transfer to an error for `op`.
+fn chunk_error(op: &str, e: chunked::ChunkError) -> Error {
+    match e {
+        chunked::ChunkError::Io(e) => Error::agent_io(op, &e),
+        e => Error::agent(op, e.to_string()),
+    }
+}
+
+/// A chunked transfer following a response, read with the same handling
+/// of spurious `WouldBlock`s as responses themselves.
+struct ChunkedResponse<'a>(&'a mut AgentClient);
+
+impl Read for ChunkedResponse<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.stream.read(buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+        self.0.read_exact_retry(buf, true)
+    }
+}
+
 impl AgentClient {
     /// Set socket read timeout, returning an error if it fails.
     ///
@@ -306,34 +715,26 @@ impl AgentClient {
     /// Connect to the agent with retry logic for transient failures.
     ///
     /// This is useful when the agent might be temporarily unavailable
-    /// (e.g., during high load or brief network issues).
+    /// (e.g., during high load or brief network issues). Once connected, the
+    /// agent is pinged so its capabilities are known up front.
     pub fn connect_with_retry(socket_path: impl AsRef<Path>) -> Result<Self> {
-        use crate::util::{retry_with_backoff, RetryConfig};
-
         let path = socket_path.as_ref();
 
-        retry_with_backoff(
+        let mut client = retry_with_backoff(
             RetryConfig::for_connection(),
             "agent connect",
             || Self::connect_once(path),
-            |e| {
-                // Check if this is a transient error worth retrying
-                let error_msg = e.to_string();
-                // Connection refused/reset are transient during VM startup
-                error_msg.contains("Connection refused")
-                    || error_msg.contains("connection refused")
-                    || error_msg.contains("Connection reset")
-                    || error_msg.contains("connection reset")
-                    || error_msg.contains("Broken pipe")
-                    || error_msg.contains("Resource temporarily unavailable")
-            },
-        )
+            // Connection refused/reset are transient during VM startup
+            |e| e.kind().is_transient(),
+        )?;
+        client.ping()?;
+        Ok(client)
     }
 
     /// Internal connect implementation (single attempt).
     fn connect_once(socket_path: &Path) -> Result<Self> {
         let stream = UnixStream::connect(socket_path)
-            .map_err(|e| Error::agent("connect to agent", e.to_string()))?;
+            .map_err(|e| Error::agent_io("connect to agent", &e))?;
 
         // Set timeouts - fail early if we can't set them to prevent indefinite hangs
         stream
@@ -354,7 +755,81 @@ impl AgentClient {
                 )
             })?;
 
-        Ok(Self { stream })
+        Ok(Self {
+            stream,
+            capabilities: None,
+            deadline: None,
+            socket_path: Some(socket_path.to_path_buf()),
+            reconnect: None,
+        })
+    }
+
+    /// Keep interactive container execs going across a dropped connection.
+    ///
+    /// The exec is left running in the guest, and the client reconnects,
+    /// backing off as `retry` says, and resumes it where it left off. Stdin
+    /// sent as the connection failed may be lost. Has no effect with an
+    /// agent that can't resume execs, or on `run` and VM execs, whose
+    /// process goes away with the connection.
+    pub fn with_reconnect(mut self, retry: RetryConfig) -> Self {
+        self.reconnect = Some(retry);
+        self
+    }
+
+    /// Bound all further requests by `deadline`.
+    ///
+    /// Each socket timeout becomes the smaller of the operation's own
+    /// timeout and the time left, and requests fail with
+    /// [`ErrorKind::Timeout`] once the deadline has passed. Interactive
+    /// sessions are not bounded.
+    pub fn with_deadline(mut self, deadline: Deadline) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Set or clear the deadline bounding further requests.
+    pub fn set_deadline(&mut self, deadline: Option<Deadline>) {
+        self.deadline = deadline;
+    }
+
+    /// The deadline bounding requests, if any.
+    pub fn deadline(&self) -> Option<Deadline> {
+        self.deadline
+    }
+
+    /// Run `f` with the socket timeouts clamped to the deadline.
+    ///
+    /// The operation's own timeouts are restored afterwards. A failure once
+    /// the deadline has passed is reported as [`ErrorKind::Timeout`].
+    fn within_deadline<T>(
+        &mut self,
+        op: &str,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let Some(deadline) = self.deadline else {
+            return f(self);
+        };
+
+        let read = self.stream.read_timeout().ok().flatten();
+        let write = self.stream.write_timeout().ok().flatten();
+        self.set_read_timeout(deadline.clamp(read, op)?)?;
+        self.stream
+            .set_write_timeout(Some(deadline.clamp(write, op)?))
+            .map_err(|e| Error::agent_io("set write timeout", &e))?;
+
+        let result = f(self);
+
+        if let Err(e) = self
+            .stream
+            .set_read_timeout(read)
+            .and_then(|()| self.stream.set_write_timeout(write))
+        {
+            tracing::warn!(error = %e, "failed to restore socket timeouts after deadline");
+        }
+        match result {
+            Err(_) if deadline.is_expired() => Err(deadline.exceeded(op)),
+            result => result,
+        }
     }
 
     /// Send a request and receive a response.
@@ -362,34 +837,228 @@ impl AgentClient {
         // Encode and send request
         let data =
             encode_message(req).map_err(|e| Error::agent("encode message", e.to_string()))?;
-        self.stream
-            .write_all(&data)
-            .map_err(|e| Error::agent("send message", e.to_string()))?;
-
-        // Read response
-        self.receive()
+        self.within_deadline("agent request", |client| {
+            client
+                .stream
+                .write_all(&data)
+                .map_err(|e| Error::agent_io("send message", &e))?;
+
+            // Read response
+            client.read_response()
+        })
     }
 
     /// Ping the helper daemon and validate the protocol version.
     ///
     /// Returns the agent's protocol version. Logs a warning if the version
-    /// doesn't match the host's expected version.
+    /// doesn't match the host's expected version. The agent's capabilities
+    /// are recorded for [`supported`](Self::supported).
     pub fn ping(&mut self) -> Result<u32> {
-        let resp = self.request(&AgentRequest::Ping)?;
+        let (version, capabilities) = parse_pong(self.request(&AgentRequest::Ping)?)?;
+        self.capabilities = Some(capabilities);
+        Ok(version)
+    }
 
-        match resp {
-            AgentResponse::Pong { version } => {
-                if version != PROTOCOL_VERSION {
-                    tracing::warn!(
-                        host_version = PROTOCOL_VERSION,
-                        agent_version = version,
-                        "protocol version mismatch — agent may be outdated or newer than host"
-                    );
+    /// Whether the agent advertised capability `cap` (see
+    /// [`smolvm_protocol::capabilities`]).
+    ///
+    /// Returns false until the agent has answered a ping, and for agents
+    /// that predate capability negotiation.
+    pub fn supported(&self, cap: &str) -> bool {
+        self.capabilities
+            .as_ref()
+            .is_some_and(|caps| caps.iter().any(|c| c == cap))
+    }
+
+    /// Whether the agent supports `cap`, pinging it first if its
+    /// capabilities aren't known yet.
+    fn has_capability(&mut self, cap: &str) -> Result<bool> {
+        if self.capabilities.is_none() {
+            self.ping()?;
+        }
+        Ok(self.supported(cap))
+    }
+
+    /// Make `request` safe to send to this agent.
+    ///
+    /// Features an older agent would silently ignore fail with
+    /// [`Error::Unsupported`] instead, and optional ones (heartbeats) are
+    /// dropped. The agent is pinged first if its capabilities aren't known.
+    fn negotiate(&mut self, request: &mut AgentRequest, op: &str) -> Result<()> {
+        if let AgentRequest::Exec {
+            resumable: resumable @ true,
+            ..
+        } = request
+        {
+            if !self.has_capability(capabilities::RESUMABLE_EXEC)? {
+                tracing::debug!("agent can't resume execs, session won't survive a disconnect");
+                *resumable = false;
+            }
+        }
+
+        // Capabilities the request needs, and a heartbeat to drop if the
+        // agent can't send them
+        let (required, heartbeat): (Vec<(bool, &str)>, Option<&mut Option<HeartbeatConfig>>) =
+            match request {
+                AgentRequest::Run {
+                    ephemeral,
+                    memory_mib,
+                    cpu_quota,
+                    security,
+                    user,
+                    entrypoint,
+                    tmpfs,
+                    read_only,
+                    extra_hosts,
+                    heartbeat,
+                    ..
+                } => (
+                    vec![
+                        (*ephemeral, capabilities::EPHEMERAL_RUN),
+                        (
+                            memory_mib.is_some() || cpu_quota.is_some(),
+                            capabilities::RESOURCE_LIMITS,
+                        ),
+                        (!security.is_empty(), capabilities::SECURITY_OPTIONS),
+                        (user.is_some(), capabilities::USER),
+                        (entrypoint.is_some(), capabilities::ENTRYPOINT),
+                        (!tmpfs.is_empty(), capabilities::TMPFS),
+                        (*read_only, capabilities::READ_ONLY_RUN),
+                        (!extra_hosts.is_empty(), capabilities::EXTRA_HOSTS),
+                    ],
+                    Some(heartbeat),
+                ),
+                AgentRequest::Exec {
+                    security,
+                    user,
+                    heartbeat,
+                    ..
+                } => (
+                    vec![
+                        (!security.is_empty(), capabilities::SECURITY_OPTIONS),
+                        (user.is_some(), capabilities::USER),
+                    ],
+                    Some(heartbeat),
+                ),
+                AgentRequest::VmExec { heartbeat, .. } => (Vec::new(), Some(heartbeat)),
+                AgentRequest::CreateContainer {
+                    restart_policy,
+                    labels,
+                    healthcheck,
+                    ..
+                } => (
+                    vec![
+                        (!restart_policy.is_no(), capabilities::RESTART_POLICY),
+                        (!labels.is_empty(), capabilities::CONTAINER_LABELS),
+                        (healthcheck.is_some(), capabilities::HEALTHCHECK),
+                    ],
+                    None,
+                ),
+                AgentRequest::Pull {
+                    no_cache,
+                    max_bytes_per_sec,
+                    heartbeat,
+                    ..
+                } => (
+                    vec![
+                        (*no_cache, capabilities::PULL_NO_CACHE),
+                        (max_bytes_per_sec.is_some(), capabilities::PULL_RATE_LIMIT),
+                    ],
+                    Some(heartbeat),
+                ),
+                AgentRequest::ListImages { offset, limit } => (
+                    vec![(
+                        *offset != 0 || limit.is_some(),
+                        capabilities::LIST_IMAGES_PAGING,
+                    )],
+                    None,
+                ),
+                AgentRequest::Verify { .. } => (vec![(true, capabilities::VERIFY)], None),
+                AgentRequest::ToolCheck => (vec![(true, capabilities::TOOL_CHECK)], None),
+                AgentRequest::DiffOverlay { .. } => {
+                    (vec![(true, capabilities::DIFF_OVERLAY)], None)
                 }
-                Ok(version)
+                AgentRequest::CancelPull { .. } => (vec![(true, capabilities::CANCEL_PULL)], None),
+                AgentRequest::Stop { .. } => (vec![(true, capabilities::STOP)], None),
+                AgentRequest::SaveImage { .. } | AgentRequest::LoadImage => {
+                    (vec![(true, capabilities::IMAGE_ARCHIVE)], None)
+                }
+                AgentRequest::Status => (vec![(true, capabilities::STATUS)], None),
+                AgentRequest::PruneOverlays { .. } | AgentRequest::RemoveImage { .. } => {
+                    (vec![(true, capabilities::PRUNE)], None)
+                }
+                _ => return Ok(()),
+            };
+        for (needed, capability) in required {
+            if needed && !self.has_capability(capability)? {
+                return Err(Error::unsupported(op, capability));
             }
-            AgentResponse::Error { message, .. } => Err(Error::agent("ping", message)),
-            _ => Err(Error::agent("ping", "unexpected response type")),
+        }
+        if let Some(heartbeat) = heartbeat.filter(|h| h.is_some()) {
+            if !self.has_capability(capabilities::HEARTBEAT)? {
+                tracing::debug!("agent does not support heartbeats, session will run without them");
+                *heartbeat = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Ping the agent until it answers, for up to `timeout`.
+    ///
+    /// Failed pings (error responses, read timeouts) are retried every 100ms
+    /// on this connection; a connection the agent has dropped won't recover,
+    /// so reconnect for that (see
+    /// [`wait_for_agent_ready`](super::wait_for_agent_ready)).
+    /// Returns the agent's protocol version, or [`Error::BootTimeout`] with
+    /// the last failure once `timeout` passes. An agent speaking another
+    /// protocol version fails immediately.
+    pub fn ping_until_ready(&mut self, timeout: Duration) -> Result<u32> {
+        let start = Instant::now();
+        let _timeout_guard = ReadTimeoutGuard::new(&self.stream);
+        let mut last_error = None;
+
+        loop {
+            let remaining = timeout.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                return Err(Error::agent_not_ready(timeout, last_error.as_ref()));
+            }
+            self.set_read_timeout(remaining)?;
+            match self.ping() {
+                Ok(version) => return check_protocol_version(version),
+                Err(e) => {
+                    tracing::trace!(error = %e, "agent not ready yet");
+                    last_error = Some(e);
+                }
+            }
+            std::thread::sleep(
+                Duration::from_millis(READY_PING_INTERVAL_MS)
+                    .min(timeout.saturating_sub(start.elapsed())),
+            );
+        }
+    }
+
+    /// Wait for the agent's ready signal.
+    ///
+    /// The agent only starts serving connections once storage init has
+    /// completed, so the reply to a single `Ping` is the ready signal. Blocks
+    /// for up to `timeout` waiting for it instead of re-pinging.
+    ///
+    /// Returns `Ok(None)` if the timeout elapsed before the agent answered.
+    pub fn wait_ready(&mut self, timeout: Duration) -> Result<Option<u32>> {
+        self.set_read_timeout(timeout.max(Duration::from_millis(1)))?;
+        let _timeout_guard = ReadTimeoutGuard::new(&self.stream);
+
+        match self.ping() {
+            Ok(version) => Ok(Some(version)),
+            Err(e)
+                if matches!(
+                    e.source_io_error_kind(),
+                    Some(std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut)
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
         }
     }
 
@@ -421,55 +1090,29 @@ impl AgentClient {
         image: &str,
         options: PullOptions<F>,
     ) -> Result<ImageInfo> {
-        // Resolve effective image and auth based on options
-        let (effective_image, effective_auth) = if options.use_registry_config {
-            let registry_config = RegistryConfig::load().unwrap_or_default();
-            let registry = extract_registry(image);
-
-            // Get credentials from config if not explicitly provided
-            let auth = options.auth.or_else(|| {
-                registry_config.get_credentials(&registry).inspect(|creds| {
-                    tracing::debug!(
-                        registry = %registry,
-                        username = %creds.username,
-                        "using configured registry credentials"
-                    );
-                })
-            });
-
-            // Apply mirror if configured
-            let img = if let Some(mirror) = registry_config.get_mirror(&registry) {
-                let mirrored = rewrite_image_registry(image, mirror);
-                tracing::debug!(
-                    original = %image,
-                    mirrored = %mirrored,
-                    mirror = %mirror,
-                    "using registry mirror"
-                );
-                mirrored
-            } else {
-                image.to_string()
-            };
-
-            (img, auth)
-        } else {
-            (image.to_string(), options.auth)
+        let target = resolve_pull_target(
+            image,
+            options.auth,
+            options.max_bytes_per_sec,
+            options.use_registry_config,
+        );
+
+        let mut request = AgentRequest::Pull {
+            image: target.image,
+            oci_platform: options.oci_platform,
+            auth: target.auth,
+            no_cache: options.no_cache,
+            max_bytes_per_sec: target.max_bytes_per_sec,
+            heartbeat: Some(HeartbeatConfig::from_env()),
         };
-
-        self.pull_image_internal(
-            &effective_image,
-            options.oci_platform.as_deref(),
-            effective_auth.as_ref(),
-            options.progress,
-        )
+        self.negotiate(&mut request, "pull image")?;
+        self.pull_image_internal(&request, options.progress)
     }
 
     /// Internal implementation of image pull.
     fn pull_image_internal<F: FnMut(usize, usize, &str)>(
         &mut self,
-        image: &str,
-        oci_platform: Option<&str>,
-        auth: Option<&RegistryAuth>,
+        request: &AgentRequest,
         mut progress: Option<F>,
     ) -> Result<ImageInfo> {
         // Use a long timeout for pull - large images can take minutes to download/extract.
@@ -478,41 +1121,48 @@ impl AgentClient {
         let _timeout_guard = ReadTimeoutGuard::new(&self.stream);
 
         // Send the pull request
-        let data = encode_message(&AgentRequest::Pull {
-            image: image.to_string(),
-            oci_platform: oci_platform.map(String::from),
-            auth: auth.cloned(),
-        })
-        .map_err(|e| Error::agent("encode message", e.to_string()))?;
+        let data =
+            encode_message(request).map_err(|e| Error::agent("encode message", e.to_string()))?;
 
         self.stream
             .write_all(&data)
-            .map_err(|e| Error::agent("send request", e.to_string()))?;
+            .map_err(|e| Error::agent_io("send request", &e))?;
+
+        // Once the agent sends heartbeats, it sends something every
+        // interval, so a read can time out after the heartbeat threshold
+        // rather than the pull timeout
+        let heartbeat = match request {
+            AgentRequest::Pull { heartbeat, .. } => *heartbeat,
+            _ => None,
+        };
+        let mut heartbeat_started = false;
 
         // Read responses - loop until we get Ok or Error (skip Progress)
         loop {
-            match self.receive()? {
-                AgentResponse::Progress {
-                    percent,
-                    layer,
-                    message: _,
-                } => {
+            let response = match self.receive() {
+                Err(e) if heartbeat_started && e.kind() == ErrorKind::Timeout => {
+                    return Err(Error::agent_with_kind(
+                        ErrorKind::Timeout,
+                        "pull image",
+                        "agent heartbeat lost",
+                    ));
+                }
+                response => response?,
+            };
+            match pull_step(response)? {
+                PullStep::Progress { percent, layer } => {
                     if let Some(ref mut cb) = progress {
-                        let current = percent.unwrap_or(0) as usize;
-                        let layer_id = layer.as_deref().unwrap_or("");
-                        cb(current, 100, layer_id);
+                        cb(percent, 100, &layer);
                     }
                 }
-                AgentResponse::Ok { data: Some(data) } => {
-                    return serde_json::from_value(data)
-                        .map_err(|e| Error::agent("parse response", e.to_string()));
-                }
-                AgentResponse::Error { message, .. } => {
-                    return Err(Error::agent("pull image", message));
-                }
-                _ => {
-                    return Err(Error::agent("pull image", "unexpected response type"));
+                PullStep::Heartbeat => {
+                    if let (false, Some(config)) = (heartbeat_started, heartbeat) {
+                        self.set_read_timeout(config.timeout())?;
+                        heartbeat_started = true;
+                    }
+                    self.send(&AgentRequest::Heartbeat)?;
                 }
+                PullStep::Done(info) => return Ok(*info),
             }
         }
     }
@@ -554,6 +1204,22 @@ impl AgentClient {
         self.pull(image, opts)
     }
 
+    /// Cancel pulls of `image` running on other connections to the agent,
+    /// returning how many were cancelled.
+    ///
+    /// `image` must be the reference the pull sent, after any registry
+    /// mirror was applied. Dropping the connection a pull runs on also
+    /// cancels it.
+    pub fn cancel_pull(&mut self, image: &str) -> Result<usize> {
+        let mut request = AgentRequest::CancelPull {
+            image: image.to_string(),
+        };
+        self.negotiate(&mut request, "cancel pull")?;
+        let resp = self.request(&request)?;
+        let data: serde_json::Value = expect_data(resp, "cancel pull")?;
+        Ok(data["cancelled"].as_u64().unwrap_or(0) as usize)
+    }
+
     /// Query if an image exists locally.
     pub fn query(&mut self, image: &str) -> Result<Option<ImageInfo>> {
         let resp = self.request(&AgentRequest::Query {
@@ -566,18 +1232,105 @@ impl AgentClient {
                     .map_err(|e| Error::agent("parse response", e.to_string()))?;
                 Ok(Some(info))
             }
-            AgentResponse::Error { code, .. } if code.as_deref() == Some("NOT_FOUND") => Ok(None),
-            AgentResponse::Error { message, .. } => Err(Error::agent("query image", message)),
+            AgentResponse::Error { message, code, .. } => {
+                let err = Error::agent_response("query image", message, code.as_deref());
+                if err.protocol_code() == Some(&ProtocolErrorCode::NotFound) {
+                    Ok(None)
+                } else {
+                    Err(err)
+                }
+            }
             _ => Err(Error::agent("query image", "unexpected response type")),
         }
     }
 
+    /// Find cached images by config digest.
+    ///
+    /// Returns one entry per reference with that digest (see
+    /// [`ImageInfo::tags`]); an empty list if the digest isn't cached.
+    pub fn query_digest(&mut self, digest: &str) -> Result<Vec<ImageInfo>> {
+        let resp = self.request(&AgentRequest::QueryDigest {
+            digest: digest.to_string(),
+        })?;
+        expect_data(resp, "query digest")
+    }
+
+    /// Add `target` as another reference to the cached image `source`,
+    /// sharing its layers.
+    ///
+    /// Fails with [`ErrorKind::NotFound`](crate::error::ErrorKind::NotFound)
+    /// if `source` isn't cached.
+    pub fn tag(&mut self, source: &str, target: &str) -> Result<ImageInfo> {
+        let resp = self.request(&AgentRequest::Tag {
+            source: source.to_string(),
+            target: target.to_string(),
+        })?;
+        expect_data(resp, "tag image")
+    }
+
+    /// Inspect an image, pulling it first if it isn't cached.
+    ///
+    /// The returned info includes the image's runtime defaults (entrypoint,
+    /// command, environment, working directory) and labels.
+    pub fn inspect(&mut self, image: &str) -> Result<ImageInfo> {
+        match self.query(image)? {
+            Some(info) => Ok(info),
+            None => self.pull_with_registry_config(image),
+        }
+    }
+
     /// List all cached images.
+    ///
+    /// Agents that page the list are asked for [`LIST_IMAGES_PAGE_SIZE`]
+    /// images at a time, so a huge cache doesn't overflow a frame.
     pub fn list_images(&mut self) -> Result<Vec<ImageInfo>> {
-        let resp = self.request(&AgentRequest::ListImages)?;
+        self.list_images_paged(LIST_IMAGES_PAGE_SIZE)
+    }
+
+    /// List all cached images, fetching `page_size` at a time if the agent
+    /// supports paging and all at once otherwise.
+    pub fn list_images_paged(&mut self, page_size: usize) -> Result<Vec<ImageInfo>> {
+        if !self.has_capability(capabilities::LIST_IMAGES_PAGING)? {
+            let resp = self.request(&AgentRequest::ListImages {
+                offset: 0,
+                limit: None,
+            })?;
+            return expect_data(resp, "list images");
+        }
+
+        let mut images = Vec::new();
+        loop {
+            let page = self.list_images_page(images.len(), page_size.max(1))?;
+            let done = page.images.is_empty() || images.len() + page.images.len() >= page.total;
+            images.extend(page.images);
+            if done {
+                return Ok(images);
+            }
+        }
+    }
+
+    /// Fetch up to `limit` cached images, starting at `offset` in reference
+    /// order.
+    ///
+    /// Images pulled or removed between pages can shift the rest along, so
+    /// a listing assembled from pages may miss or repeat one.
+    pub fn list_images_page(&mut self, offset: usize, limit: usize) -> Result<ImagePage> {
+        let mut request = AgentRequest::ListImages {
+            offset,
+            limit: Some(limit),
+        };
+        self.negotiate(&mut request, "list images")?;
+        let resp = self.request(&request)?;
         expect_data(resp, "list images")
     }
 
+    /// List cached images matching `filter`.
+    ///
+    /// Returns an empty list if nothing matches.
+    pub fn list_images_filtered(&mut self, filter: &ImageFilter) -> Result<Vec<ImageInfo>> {
+        Ok(filter.apply(self.list_images()?))
+    }
+
     /// Run garbage collection.
     ///
     /// # Arguments
@@ -591,11 +1344,82 @@ impl AgentClient {
                 let freed = data["freed_bytes"].as_u64().unwrap_or(0);
                 Ok(freed)
             }
-            AgentResponse::Error { message, .. } => Err(Error::agent("garbage collect", message)),
+            AgentResponse::Error { message, code, .. } => Err(Error::agent_response(
+                "garbage collect",
+                message,
+                code.as_deref(),
+            )),
             _ => Err(Error::agent("garbage collect", "unexpected response type")),
         }
     }
 
+    /// Remove a cached image, leaving its layers for [`Self::garbage_collect`].
+    ///
+    /// Fails with [`ProtocolErrorCode::ImageInUse`] if a container or run
+    /// still uses the image.
+    pub fn remove_image(&mut self, image: &str) -> Result<()> {
+        let mut request = AgentRequest::RemoveImage {
+            image: image.to_string(),
+        };
+        self.negotiate(&mut request, "remove image")?;
+        let resp = self.request(&request)?;
+        expect_ok(resp, "remove image")
+    }
+
+    /// Save a cached image to `path` as an OCI image layout tar, like
+    /// `docker save`. Returns the archive's size.
+    ///
+    /// The archive is written to the file as it arrives; the file is only
+    /// created once the agent has started sending it, and is removed if the
+    /// transfer fails. Fails with
+    /// [`ErrorKind::NotFound`](crate::error::ErrorKind::NotFound) if
+    /// `image` isn't cached.
+    pub fn save_image(&mut self, image: &str, path: &Path) -> Result<u64> {
+        let mut request = AgentRequest::SaveImage {
+            image: image.to_string(),
+        };
+        self.negotiate(&mut request, "save image")?;
+        let _timeout_guard =
+            self.set_extended_read_timeout(Duration::from_secs(IMAGE_ARCHIVE_TIMEOUT_SECS))?;
+        self.send(&request)?;
+        expect_ok(self.receive()?, "save image")?;
+
+        let mut file = std::fs::File::create(path)?;
+        chunked::copy_from_chunked(&mut ChunkedResponse(self), &mut file, u64::MAX).map_err(|e| {
+            // Don't leave a truncated archive behind
+            let _ = std::fs::remove_file(path);
+            chunk_error("save image", e)
+        })
+    }
+
+    /// Load the images in an OCI image layout tar at `path`, as written by
+    /// [`save_image`](Self::save_image), into the agent's storage.
+    ///
+    /// The file is streamed to the agent. Returns the loaded images, each
+    /// under the reference it was saved as.
+    pub fn load_image(&mut self, path: &Path) -> Result<Vec<ImageInfo>> {
+        let mut request = AgentRequest::LoadImage;
+        self.negotiate(&mut request, "load image")?;
+        let mut file = std::fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        let _timeout_guard =
+            self.set_extended_read_timeout(Duration::from_secs(IMAGE_ARCHIVE_TIMEOUT_SECS))?;
+        self.send(&request)?;
+
+        if let Err(e) = chunked::copy_to_chunked(&mut file, &mut self.stream, size) {
+            // If the agent gave up on the transfer, its reason beats a
+            // broken pipe
+            self.set_read_timeout(Duration::from_secs(STATUS_CHECK_TIMEOUT_SECS))?;
+            return Err(match self.receive() {
+                Ok(resp @ AgentResponse::Error { .. }) => expect_ok(resp, "load image")
+                    .err()
+                    .unwrap_or_else(|| chunk_error("load image", e)),
+                _ => chunk_error("load image", e),
+            });
+        }
+        expect_data(self.receive()?, "load image")
+    }
+
     /// Prepare an overlay filesystem for a workload.
     ///
     /// # Arguments
@@ -618,18 +1442,122 @@ impl AgentClient {
         expect_ok(resp, "cleanup overlay")
     }
 
+    /// List what a container changed in its overlay, relative to its image.
+    ///
+    /// `container_id` may be a unique prefix; the agent resolves it.
+    pub fn diff_container(&mut self, container_id: &str) -> Result<Vec<DiffEntry>> {
+        let mut request = AgentRequest::DiffOverlay {
+            container_id: container_id.to_string(),
+        };
+        self.negotiate(&mut request, "diff overlay")?;
+        let resp = self.request(&request)?;
+        expect_data(resp, "diff overlay")
+    }
+
+    /// Remove overlays of deleted containers and abandoned ephemeral runs.
+    pub fn prune_overlays(&mut self, dry_run: bool) -> Result<PrunedOverlays> {
+        let mut request = AgentRequest::PruneOverlays { dry_run };
+        self.negotiate(&mut request, "prune overlays")?;
+        let resp = self.request(&request)?;
+        expect_data(resp, "prune overlays")
+    }
+
     /// Format the storage disk.
-    pub fn format_storage(&mut self) -> Result<()> {
-        let resp = self.request(&AgentRequest::FormatStorage)?;
+    ///
+    /// Fails with [`ProtocolErrorCode::AlreadyFormatted`] if the disk is
+    /// already formatted, unless `force` is set.
+    pub fn format_storage(&mut self, force: bool) -> Result<()> {
+        let resp = self.request(&AgentRequest::FormatStorage { force })?;
         expect_ok(resp, "format storage")
     }
 
+    /// Get the agent's uptime and counts of the images, layers and
+    /// overlays it manages.
+    pub fn agent_status(&mut self) -> Result<AgentStatus> {
+        let mut request = AgentRequest::Status;
+        self.negotiate(&mut request, "agent status")?;
+        let resp = self.request(&request)?;
+        expect_data(resp, "agent status")
+    }
+
     /// Get storage status.
     pub fn storage_status(&mut self) -> Result<StorageStatus> {
         let resp = self.request(&AgentRequest::StorageStatus)?;
         expect_data(resp, "storage status")
     }
 
+    /// Get per-layer disk usage, largest layers first.
+    pub fn disk_usage(&mut self) -> Result<Vec<LayerUsage>> {
+        let resp = self.request(&AgentRequest::DiskUsage)?;
+        expect_data(resp, "disk usage")
+    }
+
+    /// Check cached images for corrupt layers, manifests and configs.
+    ///
+    /// Checks `image`, or every cached image if `None`. With
+    /// `remove_corrupt`, corrupt layers and configs are deleted so that
+    /// pulling the image again fetches them.
+    ///
+    /// Fails with [`ErrorKind::NotFound`](crate::error::ErrorKind::NotFound)
+    /// if `image` isn't cached.
+    pub fn verify(
+        &mut self,
+        image: Option<&str>,
+        remove_corrupt: bool,
+    ) -> Result<Vec<VerifyReport>> {
+        let mut request = AgentRequest::Verify {
+            image: image.map(String::from),
+            remove_corrupt,
+        };
+        self.negotiate(&mut request, "verify images")?;
+        let resp = self.request(&request)?;
+        expect_data(resp, "verify images")
+    }
+
+    /// Report which of the external binaries the agent runs are installed.
+    pub fn tool_check(&mut self) -> Result<Vec<ToolStatus>> {
+        let mut request = AgentRequest::ToolCheck;
+        self.negotiate(&mut request, "check agent tools")?;
+        let resp = self.request(&request)?;
+        expect_data(resp, "check agent tools")
+    }
+
+    /// Fail with a clear error if the agent is missing any of `tools`.
+    ///
+    /// Call before an operation that needs them, e.g. `crun` for a run, to
+    /// report the missing binary instead of a spawn failure. Agents that
+    /// can't report their tools are assumed to have them.
+    pub fn require_tools(&mut self, op: &str, tools: &[&str]) -> Result<()> {
+        if !self.has_capability(capabilities::TOOL_CHECK)? {
+            return Ok(());
+        }
+        let missing: Vec<String> = self
+            .tool_check()?
+            .into_iter()
+            .filter(|tool| !tool.found && tools.contains(&tool.name.as_str()))
+            .map(|tool| format!("{} (looked for {})", tool.name, tool.path))
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::agent(
+                op,
+                format!("agent is missing {}", missing.join(", ")),
+            ))
+        }
+    }
+
+    /// Sample CPU and memory usage of the whole VM, or of one container.
+    ///
+    /// CPU time is cumulative; compare two samples with
+    /// [`ResourceStats::cpu_percent_since`] to get utilization.
+    pub fn stats(&mut self, container_id: Option<&str>) -> Result<ResourceStats> {
+        let resp = self.request(&AgentRequest::Stats {
+            container_id: container_id.map(String::from),
+        })?;
+        expect_data(resp, "stats")
+    }
+
     /// Test network connectivity directly from the agent (not via chroot).
     /// Used to debug TSI networking.
     pub fn network_test(&mut self, url: &str) -> Result<serde_json::Value> {
@@ -639,11 +1567,46 @@ impl AgentClient {
 
         match resp {
             AgentResponse::Ok { data: Some(data) } => Ok(data),
-            AgentResponse::Error { message, .. } => Err(Error::agent("network test", message)),
+            AgentResponse::Error { message, code, .. } => Err(Error::agent_response(
+                "network test",
+                message,
+                code.as_deref(),
+            )),
             _ => Err(Error::agent("network test", "unexpected response type")),
         }
     }
 
+    /// Gracefully stop everything running in the guest before teardown.
+    ///
+    /// Containers and processes get SIGTERM, and up to `timeout` to exit
+    /// before the agent SIGKILLs them; filesystems are synced before it
+    /// answers.
+    pub fn stop(&mut self, timeout: Duration) -> Result<()> {
+        let mut request = AgentRequest::Stop {
+            timeout_ms: timeout.as_millis() as u64,
+        };
+        self.negotiate(&mut request, "stop")?;
+
+        self.set_read_timeout(timeout + Duration::from_secs(STATUS_CHECK_TIMEOUT_SECS))?;
+        let _timeout_guard = ReadTimeoutGuard::new(&self.stream);
+        let resp = self.request(&request)?;
+        match resp {
+            AgentResponse::Ok { data } => {
+                let data = data.unwrap_or_default();
+                tracing::debug!(
+                    terminated = data["terminated"].as_u64().unwrap_or(0),
+                    killed = data["killed"].as_u64().unwrap_or(0),
+                    "guest processes stopped"
+                );
+                Ok(())
+            }
+            AgentResponse::Error { message, code, .. } => {
+                Err(Error::agent_response("stop", message, code.as_deref()))
+            }
+            _ => Err(Error::agent("stop", "unexpected response type")),
+        }
+    }
+
     /// Request agent shutdown.
     ///
     /// Waits for the agent to acknowledge the shutdown request before returning.
@@ -664,7 +1627,7 @@ impl AgentClient {
             .map_err(|e| Error::agent("encode message", e.to_string()))?;
         self.stream
             .write_all(&data)
-            .map_err(|e| Error::agent("send shutdown", e.to_string()))?;
+            .map_err(|e| Error::agent_io("send shutdown", &e))?;
 
         // Wait for acknowledgment - this confirms sync() completed.
         // If the agent crashes or times out, we proceed anyway since
@@ -731,6 +1694,7 @@ impl AgentClient {
             timeout_ms,
             interactive: false,
             tty: false,
+            heartbeat: None,
         })?;
 
         expect_completed(resp, "vm exec")
@@ -739,29 +1703,57 @@ impl AgentClient {
     /// Run an interactive I/O session.
     ///
     /// Sends `request`, waits for `Started`, then runs the poll loop
-    /// streaming stdout/stderr and forwarding stdin until `Exited`.
-    fn interactive_session(&mut self, request: AgentRequest, tty: bool, op: &str) -> Result<i32> {
+    /// streaming stdout/stderr and forwarding stdin until `Exited`. If the
+    /// agent supports it, SIGINT and SIGTERM received by the CLI are relayed
+    /// to the command rather than terminating the CLI.
+    fn interactive_session(
+        &mut self,
+        mut request: AgentRequest,
+        tty: bool,
+        op: &str,
+    ) -> Result<i32> {
         use crate::agent::terminal::{
             check_sigwinch, flush_retry, get_terminal_size, install_sigwinch_handler, poll_io,
-            stdin_is_tty, write_all_retry, NonBlockingStdin, RawModeGuard,
+            stdin_is_tty, take_forwarded_signal, write_all_retry, NonBlockingStdin, RawModeGuard,
+            SignalForwardGuard,
         };
         use std::io::{stderr, stdin, stdout, Read};
         use std::os::unix::io::AsRawFd;
 
+        self.negotiate(&mut request, op)?;
+
         // Disable socket read timeout for interactive sessions — the poll loop
         // handles readiness checking, and the session runs until the user exits.
         self.stream
             .set_read_timeout(None)
             .map_err(|e| Error::agent("set read timeout", e.to_string()))?;
 
+        // Heartbeats only start once the agent sends one, so an older agent
+        // that ignores the request's heartbeat config is never timed out.
+        let heartbeat_config = match &request {
+            AgentRequest::Run { heartbeat, .. }
+            | AgentRequest::VmExec { heartbeat, .. }
+            | AgentRequest::Exec { heartbeat, .. } => *heartbeat,
+            _ => None,
+        };
+        let mut heartbeat: Option<HeartbeatTracker> = None;
+
         self.send(&request)?;
 
-        // Wait for Started response
-        let started = self.receive()?;
+        // Wait for Started response. A resumable exec is first given the
+        // ID the agent knows it by if the connection drops.
+        let mut started = self.receive()?;
+        let session = match started {
+            AgentResponse::ExecSession { session_id } => {
+                started = self.receive()?;
+                Some(session_id)
+            }
+            _ => None,
+        };
         match started {
             AgentResponse::Started => {}
-            AgentResponse::Error { message, .. } => {
-                return Err(Error::agent(op, message));
+            AgentResponse::Error { message, code, .. } => {
+                return Err(Error::agent_response(op, message, code.as_deref()));
             }
             _ => {
                 return Err(Error::agent(op, "expected Started response"));
@@ -784,6 +1776,13 @@ impl AgentClient {
             install_sigwinch_handler();
         }
 
+        // Relay Ctrl-C/SIGTERM to the command so it can shut down cleanly
+        // instead of being orphaned when the CLI exits (guard restores the
+        // default handlers on drop)
+        let signals = self
+            .supported(capabilities::SIGNAL)
+            .then(SignalForwardGuard::install);
+
         // Set stdin to non-blocking (guard restores on drop)
         let _nonblock_stdin = NonBlockingStdin::new()
             .map_err(|e| Error::agent("set stdin nonblocking", e.to_string()))?;
@@ -793,26 +1792,62 @@ impl AgentClient {
         // that occur with non-blocking read_exact/write_all.
         let mut stdin_handle = stdin();
         let stdin_fd = stdin_handle.as_raw_fd();
-        let socket_fd = self.stream.as_raw_fd();
         let mut stdin_buf = [0u8; STDIN_BUF_SIZE];
         let mut stdin_eof = false;
 
         let exit_code = loop {
             let effective_stdin_fd = if stdin_eof { -1 } else { stdin_fd };
-            let poll_result = poll_io(effective_stdin_fd, socket_fd, POLL_TIMEOUT_MS)
+            let poll_result = poll_io(effective_stdin_fd, self.stream.as_raw_fd(), POLL_TIMEOUT_MS)
                 .map_err(|e| Error::agent("poll", e.to_string()))?;
 
+            // A resumed session's heartbeats start over on the new connection
+            if let Some(ref mut hb) = heartbeat {
+                let now = Instant::now();
+                if hb.peer_lost(now) {
+                    self.resume_session(session, op, Error::agent(op, "agent heartbeat lost"))?;
+                    heartbeat = None;
+                    continue;
+                }
+                if hb.send_due(now) && self.session_send(&AgentRequest::Heartbeat, session, op)? {
+                    heartbeat = None;
+                }
+            }
+
+            if signals.is_some() {
+                if let Some(signal) = take_forwarded_signal() {
+                    tracing::debug!(signal, "forwarding signal to guest command");
+                    if self.session_send(&AgentRequest::Signal { signal }, session, op)? {
+                        heartbeat = None;
+                    }
+                }
+            }
+
             // Check for terminal resize (SIGWINCH)
             if tty && check_sigwinch() {
                 if let Some((cols, rows)) = get_terminal_size() {
-                    self.send(&AgentRequest::Resize { cols, rows })?;
+                    if self.session_send(&AgentRequest::Resize { cols, rows }, session, op)? {
+                        heartbeat = None;
+                    }
                 }
             }
 
             // Handle socket data FIRST — drain agent output before writing stdin
             // to prevent deadlock when send buffer is full
             if poll_result.socket_ready {
-                match self.receive() {
+                let response = self.receive();
+                if response.is_ok() {
+                    if let Some(ref mut hb) = heartbeat {
+                        hb.on_receive(Instant::now());
+                    }
+                }
+                match response {
+                    Ok(AgentResponse::Heartbeat) => {
+                        if heartbeat.is_none() {
+                            if let Some(config) = heartbeat_config {
+                                heartbeat = Some(HeartbeatTracker::new(config, Instant::now()));
+                            }
+                        }
+                    }
                     Ok(AgentResponse::Stdout { data }) => {
                         write_all_retry(&mut stdout(), &data)?;
                         flush_retry(&mut stdout())?;
@@ -824,8 +1859,8 @@ impl AgentClient {
                     Ok(AgentResponse::Exited { exit_code }) => {
                         break exit_code;
                     }
-                    Ok(AgentResponse::Error { message, .. }) => {
-                        return Err(Error::agent(op, message));
+                    Ok(AgentResponse::Error { message, code, .. }) => {
+                        return Err(Error::agent_response(op, message, code.as_deref()));
                     }
                     Ok(_) => {}
                     Err(e) => {
@@ -841,37 +1876,114 @@ impl AgentClient {
                             tracing::debug!("socket read returned EAGAIN, retrying");
                             continue;
                         }
-                        return Err(e);
+                        self.resume_session(session, op, e)?;
+                        heartbeat = None;
+                        continue;
                     }
                 }
             }
 
             // Socket peer closed without sending Exited — VM crashed or was killed
             if poll_result.socket_hangup && !poll_result.socket_ready {
-                return Err(Error::agent(op, "connection to VM lost".to_string()));
+                self.resume_session(
+                    session,
+                    op,
+                    Error::agent(op, "connection to VM lost".to_string()),
+                )?;
+                heartbeat = None;
+                continue;
             }
 
             // Handle stdin input — send to agent
             if poll_result.stdin_ready && !stdin_eof {
-                match stdin_handle.read(&mut stdin_buf) {
+                let request = match stdin_handle.read(&mut stdin_buf) {
                     Ok(0) => {
                         stdin_eof = true;
-                        self.send(&AgentRequest::Stdin { data: Vec::new() })?;
-                    }
-                    Ok(n) => {
-                        self.send(&AgentRequest::Stdin {
-                            data: stdin_buf[..n].to_vec(),
-                        })?;
+                        Some(AgentRequest::Stdin { data: Vec::new() })
                     }
-                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                    Ok(n) => Some(AgentRequest::Stdin {
+                        data: stdin_buf[..n].to_vec(),
+                    }),
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => None,
                     Err(e) => {
                         tracing::warn!(error = %e, "error reading stdin");
+                        None
+                    }
+                };
+                if let Some(request) = request {
+                    if self.session_send(&request, session, op)? {
+                        heartbeat = None;
                     }
                 }
             }
         };
 
-        Ok(exit_code)
+        Ok(exit_code)
+    }
+
+    /// Send `request` during an interactive session, resuming the session
+    /// and sending it again if the connection has failed.
+    ///
+    /// Returns whether the session was resumed on a new connection.
+    fn session_send(
+        &mut self,
+        request: &AgentRequest,
+        session: Option<u64>,
+        op: &str,
+    ) -> Result<bool> {
+        match self.send(request) {
+            Ok(()) => Ok(false),
+            Err(e) => {
+                self.resume_session(session, op, e)?;
+                self.send(request)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Reconnect after an interactive session's connection failed with
+    /// `error`, and carry on with `session` over the new connection.
+    ///
+    /// Fails with `error` if the session isn't resumable, and with the
+    /// agent's error if it no longer has the session.
+    fn resume_session(&mut self, session: Option<u64>, op: &str, error: Error) -> Result<()> {
+        let (Some(session_id), Some(path), Some(retry)) =
+            (session, self.socket_path.clone(), self.reconnect.clone())
+        else {
+            return Err(error);
+        };
+        tracing::warn!(error = %error, session_id, "connection to agent lost, reconnecting");
+
+        // Make sure the agent sees the old connection go, so it parks the
+        // session for us to resume
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+
+        let stream = retry_with_backoff(
+            retry,
+            "resume session",
+            || {
+                let mut client = Self::connect_once(&path)?;
+                client.send(&RequestFrame {
+                    request_id: Some(session_id),
+                    request: AgentRequest::ResumeExec { session_id },
+                })?;
+                match client.receive()? {
+                    AgentResponse::Started => Ok(client.stream),
+                    AgentResponse::Error { message, code, .. } => {
+                        Err(Error::agent_response(op, message, code.as_deref()))
+                    }
+                    _ => Err(Error::agent(op, "expected Started response")),
+                }
+            },
+            |e| e.kind().is_transient(),
+        )?;
+        stream
+            .set_read_timeout(None)
+            .map_err(|e| Error::agent("set read timeout", e.to_string()))?;
+        self.stream = stream;
+
+        tracing::info!(session_id, "resumed interactive session");
+        Ok(())
     }
 
     /// Execute a command directly in the VM with interactive I/O.
@@ -892,6 +2004,7 @@ impl AgentClient {
                 timeout_ms,
                 interactive: true,
                 tty,
+                heartbeat: Some(HeartbeatConfig::from_env()),
             },
             tty,
             "vm exec interactive",
@@ -967,21 +2080,53 @@ impl AgentClient {
         mounts: Vec<(String, String, bool)>,
         timeout: Option<Duration>,
     ) -> Result<(i32, String, String)> {
-        let _timeout_guard = self.set_exec_timeout(timeout)?;
-        let timeout_ms = timeout.map(|t| t.as_millis() as u64);
+        let out = self.run_with_config(
+            RunConfig::new(image, command)
+                .with_env(env)
+                .with_workdir(workdir)
+                .with_mounts(mounts)
+                .with_timeout(timeout),
+        )?;
+        Ok((out.exit_code, out.stdout, out.stderr))
+    }
 
-        let resp = self.request(&AgentRequest::Run {
-            image: image.to_string(),
-            command,
-            env,
-            workdir,
-            mounts,
+    /// Run a command in an image's rootfs using a full run configuration.
+    ///
+    /// The `tty` setting is ignored; use `run_interactive` for TTY sessions.
+    ///
+    /// # Returns
+    ///
+    /// The exit code, captured output and exit reason.
+    pub fn run_with_config(&mut self, config: RunConfig) -> Result<RunOutput> {
+        let timeout_ms = config.timeout.map(|t| t.as_millis() as u64);
+        let mut request = AgentRequest::Run {
+            image: config.image,
+            command: config.command,
+            env: config.env,
+            workdir: config.workdir,
+            mounts: config.mounts,
             timeout_ms,
             interactive: false,
             tty: false,
-        })?;
+            ephemeral: config.ephemeral,
+            max_output_bytes: config.max_output_bytes,
+            heartbeat: None,
+            memory_mib: config.limits.memory_mib,
+            cpu_quota: config.limits.cpu_quota,
+            security: config.security,
+            user: config.user,
+            no_create_workdir: !config.create_workdir,
+            platform: config.platform,
+            entrypoint: config.entrypoint,
+            tmpfs: config.tmpfs,
+            read_only: config.read_only,
+            extra_hosts: config.extra_hosts,
+        };
+        self.negotiate(&mut request, "run command")?;
 
-        expect_completed(resp, "run command")
+        let _timeout_guard = self.set_exec_timeout(config.timeout)?;
+        let resp = self.request(&request)?;
+        expect_run_output(resp, "run command")
     }
 
     /// Run a command interactively with streaming I/O.
@@ -1009,6 +2154,19 @@ impl AgentClient {
                 timeout_ms,
                 interactive: true,
                 tty,
+                ephemeral: config.ephemeral,
+                max_output_bytes: None,
+                heartbeat: Some(HeartbeatConfig::from_env()),
+                memory_mib: config.limits.memory_mib,
+                cpu_quota: config.limits.cpu_quota,
+                security: config.security,
+                user: config.user,
+                no_create_workdir: !config.create_workdir,
+                platform: config.platform,
+                entrypoint: config.entrypoint,
+                tmpfs: config.tmpfs,
+                read_only: config.read_only,
+                extra_hosts: config.extra_hosts,
             },
             tty,
             "run interactive",
@@ -1035,32 +2193,13 @@ impl AgentClient {
     ///
     /// The container is created and started, ready for exec.
     ///
-    /// # Arguments
-    ///
-    /// * `image` - Image reference (must be pulled first)
-    /// * `command` - Command to run (e.g., ["sleep", "infinity"])
-    /// * `env` - Environment variables
-    /// * `workdir` - Working directory inside the container
-    /// * `mounts` - Volume mounts as (virtiofs_tag, container_path, read_only)
-    ///
     /// # Returns
     ///
     /// ContainerInfo with the container ID
-    pub fn create_container(
-        &mut self,
-        image: &str,
-        command: Vec<String>,
-        env: Vec<(String, String)>,
-        workdir: Option<String>,
-        mounts: Vec<(String, String, bool)>,
-    ) -> Result<ContainerInfo> {
-        let resp = self.request(&AgentRequest::CreateContainer {
-            image: image.to_string(),
-            command,
-            env,
-            workdir,
-            mounts,
-        })?;
+    pub fn create_container(&mut self, config: CreateContainerConfig) -> Result<ContainerInfo> {
+        let mut request = config.into_request();
+        self.negotiate(&mut request, "create container")?;
+        let resp = self.request(&request)?;
 
         expect_data(resp, "create container")
     }
@@ -1087,18 +2226,30 @@ impl AgentClient {
         expect_ok(resp, "stop container")
     }
 
-    /// Delete a container.
+    /// Delete a container, returning the bytes its overlay freed (0 from
+    /// agents that don't report it).
     ///
     /// # Arguments
     ///
     /// * `container_id` - Container ID (full or prefix)
     /// * `force` - Force delete even if running
-    pub fn delete_container(&mut self, container_id: &str, force: bool) -> Result<()> {
+    pub fn delete_container(&mut self, container_id: &str, force: bool) -> Result<u64> {
         let resp = self.request(&AgentRequest::DeleteContainer {
             container_id: container_id.to_string(),
             force,
         })?;
-        expect_ok(resp, "delete container")
+
+        match resp {
+            AgentResponse::Ok { data } => Ok(data
+                .and_then(|data| data["freed_bytes"].as_u64())
+                .unwrap_or(0)),
+            AgentResponse::Error { message, code, .. } => Err(Error::agent_response(
+                "delete container",
+                message,
+                code.as_deref(),
+            )),
+            _ => Err(Error::agent("delete container", "unexpected response type")),
+        }
     }
 
     /// List all containers.
@@ -1109,11 +2260,45 @@ impl AgentClient {
             AgentResponse::Ok { data: Some(data) } => serde_json::from_value(data)
                 .map_err(|e| Error::agent("parse response", e.to_string())),
             AgentResponse::Ok { data: None } => Ok(Vec::new()),
-            AgentResponse::Error { message, .. } => Err(Error::agent("list containers", message)),
+            AgentResponse::Error { message, code, .. } => Err(Error::agent_response(
+                "list containers",
+                message,
+                code.as_deref(),
+            )),
             _ => Err(Error::agent("list containers", "unexpected response type")),
         }
     }
 
+    // ========================================================================
+    // Named Volumes
+    // ========================================================================
+
+    /// Create a named volume.
+    ///
+    /// Volumes are also created implicitly the first time they are mounted
+    /// with `-v name:/path`.
+    pub fn create_volume(&mut self, name: &str, size_mib: Option<u64>) -> Result<VolumeInfo> {
+        let resp = self.request(&AgentRequest::CreateVolume {
+            name: name.to_string(),
+            size_mib,
+        })?;
+        expect_data(resp, "create volume")
+    }
+
+    /// List all named volumes.
+    pub fn list_volumes(&mut self) -> Result<Vec<VolumeInfo>> {
+        let resp = self.request(&AgentRequest::ListVolumes)?;
+        expect_data(resp, "list volumes")
+    }
+
+    /// Remove a named volume and its data.
+    pub fn remove_volume(&mut self, name: &str) -> Result<()> {
+        let resp = self.request(&AgentRequest::RemoveVolume {
+            name: name.to_string(),
+        })?;
+        expect_ok(resp, "remove volume")
+    }
+
     /// Execute a command in a running container.
     ///
     /// Unlike `run`, this executes in an existing container created with `create_container`.
@@ -1137,20 +2322,31 @@ impl AgentClient {
         workdir: Option<String>,
         timeout: Option<Duration>,
     ) -> Result<(i32, String, String)> {
-        let _timeout_guard = self.set_exec_timeout(timeout)?;
-        let timeout_ms = timeout.map(|t| t.as_millis() as u64);
+        let out = self.exec_with_config(
+            ExecConfig::new(container_id, command)
+                .with_env(env)
+                .with_workdir(workdir)
+                .with_timeout(timeout),
+        )?;
+        Ok((out.exit_code, out.stdout, out.stderr))
+    }
 
-        let resp = self.request(&AgentRequest::Exec {
-            container_id: container_id.to_string(),
-            command,
-            env,
-            workdir,
-            timeout_ms,
-            interactive: false,
-            tty: false,
-        })?;
+    /// Execute a command in a running container using a full exec
+    /// configuration.
+    ///
+    /// The `tty` setting is ignored; use `exec_interactive` for TTY sessions.
+    ///
+    /// # Returns
+    ///
+    /// The exit code, captured output and exit reason.
+    pub fn exec_with_config(&mut self, config: ExecConfig) -> Result<RunOutput> {
+        let timeout = config.timeout;
+        let mut request = config.into_request(false, false);
+        self.negotiate(&mut request, "exec command")?;
 
-        expect_completed(resp, "exec command")
+        let _timeout_guard = self.set_exec_timeout(timeout)?;
+        let resp = self.request(&request)?;
+        expect_run_output(resp, "exec command")
     }
 
     /// Execute a command interactively in a running container with streaming I/O.
@@ -1160,39 +2356,16 @@ impl AgentClient {
     ///
     /// # Arguments
     ///
-    /// * `container_id` - Container ID (full or prefix)
-    /// * `command` - Command and arguments to execute
-    /// * `env` - Environment variables for this exec
-    /// * `workdir` - Working directory for this exec
-    /// * `timeout` - Optional timeout duration
-    /// * `tty` - Whether to allocate a PTY
+    /// * `config` - Exec configuration including container, command, environment, etc.
     ///
     /// # Returns
     ///
     /// The exit code of the command
-    pub fn exec_interactive(
-        &mut self,
-        container_id: &str,
-        command: Vec<String>,
-        env: Vec<(String, String)>,
-        workdir: Option<String>,
-        timeout: Option<Duration>,
-        tty: bool,
-    ) -> Result<i32> {
-        let timeout_ms = timeout.map(|t| t.as_millis() as u64);
-        self.interactive_session(
-            AgentRequest::Exec {
-                container_id: container_id.to_string(),
-                command,
-                env,
-                workdir,
-                timeout_ms,
-                interactive: true,
-                tty,
-            },
-            tty,
-            "exec interactive",
-        )
+    pub fn exec_interactive(&mut self, config: ExecConfig) -> Result<i32> {
+        let tty = config.tty;
+        let resumable = self.reconnect.is_some() && self.socket_path.is_some();
+        let request = config.into_request(true, resumable);
+        self.interactive_session(request, tty, "exec interactive")
     }
 
     /// Low-level send without waiting for response (public).
@@ -1227,17 +2400,19 @@ impl AgentClient {
         Ok(ReadTimeoutGuard::new(&self.stream))
     }
 
-    /// Low-level send without waiting for response.
-    fn send(&mut self, request: &AgentRequest) -> Result<()> {
+    /// Low-level send of a request, or a [`RequestFrame`], without waiting
+    /// for response.
+    fn send(&mut self, request: &impl serde::Serialize) -> Result<()> {
         let json = serde_json::to_vec(request)
             .map_err(|e| Error::agent("serialize request", e.to_string()))?;
         let len = json.len() as u32;
 
-        self.stream.write_all(&len.to_be_bytes())?;
-        self.stream.write_all(&json)?;
-        self.stream.flush()?;
-
-        Ok(())
+        self.within_deadline("send request", |client| {
+            client.stream.write_all(&len.to_be_bytes())?;
+            client.stream.write_all(&json)?;
+            client.stream.flush()?;
+            Ok(())
+        })
     }
 
     /// Read exactly `buf.len()` bytes, retrying on EAGAIN/WouldBlock.
@@ -1282,6 +2457,11 @@ impl AgentClient {
 
     /// Low-level receive a single response.
     fn receive(&mut self) -> Result<AgentResponse> {
+        self.within_deadline("receive response", Self::read_response)
+    }
+
+    /// Read and decode a single response frame.
+    fn read_response(&mut self) -> Result<AgentResponse> {
         // Check if a read timeout is set — if so, WouldBlock before any data
         // means a real timeout and should be propagated. If no timeout (interactive
         // sessions), WouldBlock is always a spurious macOS vsock EAGAIN.
@@ -1292,18 +2472,12 @@ impl AgentClient {
         let len = u32::from_be_bytes(header) as usize;
 
         // Validate frame size to prevent OOM from malicious/buggy responses
-        if len > MAX_FRAME_SIZE as usize {
+        if let Err(e) = check_frame_len(len) {
             // Header consumed but body not read — stream is desynchronized.
             // Shut down the read half so all future reads fail immediately
             // rather than interpreting body bytes as a frame header.
             let _ = self.stream.shutdown(std::net::Shutdown::Read);
-            return Err(Error::agent(
-                "validate frame",
-                format!(
-                    "frame too large: {} bytes (max: {} bytes)",
-                    len, MAX_FRAME_SIZE
-                ),
-            ));
+            return Err(e);
         }
 
         let mut buf = vec![0u8; len];
@@ -1316,8 +2490,882 @@ impl AgentClient {
             return Err(e.into());
         }
 
-        let resp: AgentResponse = serde_json::from_slice(&buf)
-            .map_err(|e| Error::agent("deserialize response", e.to_string()))?;
-        Ok(resp)
+        decode_response(&buf)
+    }
+}
+
+// ============================================================================
+// Shared Helpers
+// ============================================================================
+//
+// Used by both the blocking client above and `AsyncAgentClient`, so the two
+// agree on framing and on how pull responses are interpreted.
+
+/// Reject a response frame whose length header exceeds [`MAX_FRAME_SIZE`].
+pub(super) fn check_frame_len(len: usize) -> Result<()> {
+    if len > MAX_FRAME_SIZE as usize {
+        return Err(Error::agent_with_kind(
+            ErrorKind::Protocol,
+            "validate frame",
+            format!(
+                "frame too large: {} bytes (max: {} bytes)",
+                len, MAX_FRAME_SIZE
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Deserialize a response frame body (without its length header).
+pub(super) fn decode_response(body: &[u8]) -> Result<AgentResponse> {
+    decode_json(body).map_err(|e| {
+        Error::agent_with_kind(ErrorKind::Protocol, "deserialize response", e.to_string())
+    })
+}
+
+/// Image reference, credentials and rate limit to send for a pull.
+pub(super) struct PullTarget {
+    pub image: String,
+    pub auth: Option<RegistryAuth>,
+    pub max_bytes_per_sec: Option<u64>,
+}
+
+/// Resolve the image reference, credentials and rate limit to send for a
+/// pull.
+///
+/// With `use_registry_config`, credentials missing from `auth` and a rate
+/// limit missing from `max_bytes_per_sec` are taken from the registry
+/// config, and a configured mirror replaces the registry.
+pub(super) fn resolve_pull_target(
+    image: &str,
+    auth: Option<RegistryAuth>,
+    max_bytes_per_sec: Option<u64>,
+    use_registry_config: bool,
+) -> PullTarget {
+    if !use_registry_config {
+        return PullTarget {
+            image: image.to_string(),
+            auth,
+            max_bytes_per_sec,
+        };
+    }
+
+    let registry_config = RegistryConfig::load().unwrap_or_default();
+    let registry = extract_registry(image);
+
+    // Get credentials from config if not explicitly provided
+    let auth = auth.or_else(|| {
+        registry_config.get_credentials(&registry).inspect(|creds| {
+            tracing::debug!(
+                registry = %registry,
+                username = %creds.username,
+                "using configured registry credentials"
+            );
+        })
+    });
+
+    // Apply mirror if configured
+    let img = if let Some(mirror) = registry_config.get_mirror(&registry) {
+        let mirrored = rewrite_image_registry(image, mirror);
+        tracing::debug!(
+            original = %image,
+            mirrored = %mirrored,
+            mirror = %mirror,
+            "using registry mirror"
+        );
+        mirrored
+    } else {
+        image.to_string()
+    };
+
+    PullTarget {
+        image: img,
+        auth,
+        max_bytes_per_sec: max_bytes_per_sec.or(registry_config.defaults.max_download_rate),
+    }
+}
+
+/// Interpret a ping response as the agent's protocol version and
+/// capabilities, warning if the version differs from the host's.
+pub(super) fn parse_pong(resp: AgentResponse) -> Result<(u32, Vec<String>)> {
+    match resp {
+        AgentResponse::Pong {
+            version,
+            capabilities,
+        } => {
+            if version != PROTOCOL_VERSION {
+                tracing::warn!(
+                    host_version = PROTOCOL_VERSION,
+                    agent_version = version,
+                    "protocol version mismatch — agent may be outdated or newer than host"
+                );
+            }
+            tracing::debug!(agent_version = version, ?capabilities, "agent capabilities");
+            Ok((version, capabilities))
+        }
+        AgentResponse::Error { message, code, .. } => {
+            Err(Error::agent_response("ping", message, code.as_deref()))
+        }
+        _ => Err(Error::agent("ping", "unexpected response type")),
+    }
+}
+
+/// Accept `version` only if it is the host's [`PROTOCOL_VERSION`].
+pub(super) fn check_protocol_version(version: u32) -> Result<u32> {
+    if version != PROTOCOL_VERSION {
+        return Err(Error::agent(
+            "ping",
+            format!(
+                "agent speaks protocol version {}, host expects {}",
+                version, PROTOCOL_VERSION
+            ),
+        ));
+    }
+    Ok(version)
+}
+
+/// One response to a pull request, interpreted.
+pub(super) enum PullStep {
+    /// Layer progress; the pull is still running.
+    Progress {
+        /// Completion percentage (0-100).
+        percent: usize,
+        /// Layer being processed, empty if unknown.
+        layer: String,
+    },
+    /// Keepalive from the agent; the pull is still running and expects a
+    /// `Heartbeat` back.
+    Heartbeat,
+    /// The pull finished.
+    Done(Box<ImageInfo>),
+}
+
+/// Interpret a response to a pull request.
+pub(super) fn pull_step(resp: AgentResponse) -> Result<PullStep> {
+    match resp {
+        AgentResponse::Progress { percent, layer, .. } => Ok(PullStep::Progress {
+            percent: percent.unwrap_or(0) as usize,
+            layer: layer.unwrap_or_default(),
+        }),
+        AgentResponse::Heartbeat => Ok(PullStep::Heartbeat),
+        AgentResponse::Ok { data: Some(data) } => serde_json::from_value(data)
+            .map(|info| PullStep::Done(Box::new(info)))
+            .map_err(|e| Error::agent("parse response", e.to_string())),
+        AgentResponse::Error {
+            message,
+            code,
+            data,
+        } => Err(Error::agent_response_with_data(
+            "pull image",
+            message,
+            code.as_deref(),
+            data.as_ref(),
+        )),
+        _ => Err(Error::agent("pull image", "unexpected response type")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::JoinHandle;
+
+    /// Number of images the fake agent has cached.
+    const FAKE_IMAGE_COUNT: usize = 5;
+
+    /// Image archive the fake agent saves, and expects to be loaded.
+    const FAKE_ARCHIVE: &[u8] = b"oci-layout index.json blobs";
+
+    fn fake_image(reference: &str) -> ImageInfo {
+        serde_json::from_value(serde_json::json!({
+            "reference": reference,
+            "digest": "sha256:abc",
+            "size": 1024,
+            "created": null,
+            "architecture": "amd64",
+            "os": "linux",
+            "layer_count": 1,
+            "layers": [],
+        }))
+        .unwrap()
+    }
+
+    /// Client connected to a fake agent that answers every `Ping` with
+    /// `capabilities` and records the methods of all requests it receives.
+    fn client_with_fake_agent(capabilities: &[&str]) -> (AgentClient, JoinHandle<Vec<String>>) {
+        let (host, mut agent) = UnixStream::pair().unwrap();
+        let capabilities: Vec<String> = capabilities.iter().map(|c| c.to_string()).collect();
+
+        let handle = std::thread::spawn(move || {
+            let mut methods = Vec::new();
+            let mut header = [0u8; 4];
+            while agent.read_exact(&mut header).is_ok() {
+                let mut body = vec![0u8; u32::from_be_bytes(header) as usize];
+                agent.read_exact(&mut body).unwrap();
+                let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                methods.push(request["method"].as_str().unwrap().to_string());
+
+                let response = match methods.last().unwrap().as_str() {
+                    "ping" => AgentResponse::Pong {
+                        version: PROTOCOL_VERSION,
+                        capabilities: capabilities.clone(),
+                    },
+                    "save_image" => {
+                        let header = AgentResponse::ok(Some(
+                            serde_json::json!({ "size": FAKE_ARCHIVE.len() }),
+                        ));
+                        agent.write_all(&encode_message(&header).unwrap()).unwrap();
+                        smolvm_protocol::write_chunked(&mut agent, FAKE_ARCHIVE).unwrap();
+                        continue;
+                    }
+                    "pull" => {
+                        // The pull finishes once the heartbeat is answered
+                        let heartbeat = AgentResponse::Heartbeat;
+                        agent
+                            .write_all(&encode_message(&heartbeat).unwrap())
+                            .unwrap();
+                        continue;
+                    }
+                    "heartbeat" => AgentResponse::ok_with_data(fake_image("alpine:latest")),
+                    "load_image" => {
+                        let archive = smolvm_protocol::read_chunked(&mut agent, 1024).unwrap();
+                        assert_eq!(archive, FAKE_ARCHIVE);
+                        AgentResponse::ok_with_data(vec![fake_image(
+                            "docker.io/library/alpine:latest",
+                        )])
+                    }
+                    "list_images" => {
+                        let offset = request["offset"].as_u64().unwrap_or(0) as usize;
+                        let images: Vec<ImageInfo> = (0..FAKE_IMAGE_COUNT)
+                            .map(|i| fake_image(&format!("image-{}:latest", i)))
+                            .collect();
+                        match request["limit"].as_u64() {
+                            Some(limit) => AgentResponse::ok_with_data(ImagePage {
+                                images: images
+                                    .into_iter()
+                                    .skip(offset)
+                                    .take(limit as usize)
+                                    .collect(),
+                                total: FAKE_IMAGE_COUNT,
+                            }),
+                            None => AgentResponse::ok_with_data(images),
+                        }
+                    }
+                    "tool_check" => AgentResponse::ok_with_data(vec![
+                        ToolStatus {
+                            name: "crane".to_string(),
+                            found: true,
+                            path: "/usr/local/bin/crane".to_string(),
+                            version: Some("v0.19.0".to_string()),
+                        },
+                        ToolStatus {
+                            name: "crun".to_string(),
+                            found: false,
+                            path: "/usr/bin/crun".to_string(),
+                            version: None,
+                        },
+                    ]),
+                    _ => AgentResponse::Completed {
+                        exit_code: 0,
+                        stdout: String::new(),
+                        stderr: String::new(),
+                        stdout_truncated: false,
+                        stderr_truncated: false,
+                        reason: ExitReason::Exited,
+                    },
+                };
+                agent
+                    .write_all(&encode_message(&response).unwrap())
+                    .unwrap();
+            }
+            methods
+        });
+
+        let client = AgentClient {
+            stream: host,
+            capabilities: None,
+            deadline: None,
+            socket_path: None,
+            reconnect: None,
+        };
+        (client, handle)
+    }
+
+    fn limited_run() -> RunConfig {
+        RunConfig::new("alpine", vec!["true".to_string()]).with_limits(ResourceLimits {
+            memory_mib: Some(64),
+            cpu_quota: None,
+        })
+    }
+
+    #[test]
+    fn test_exec_config_defaults_and_overrides() {
+        let config = ExecConfig::new("c1", vec!["ls".to_string()]);
+        assert_eq!(config.container_id, "c1");
+        assert!(config.env.is_empty());
+        assert!(config.workdir.is_none());
+        assert!(config.timeout.is_none());
+        assert!(!config.tty);
+        assert!(config.user.is_none());
+
+        let config = config
+            .with_env(vec![("A".to_string(), "1".to_string())])
+            .with_workdir(Some("/app".to_string()))
+            .with_timeout(Some(Duration::from_secs(5)))
+            .with_tty(true)
+            .with_user(Some("nobody".to_string()));
+        let AgentRequest::Exec {
+            env,
+            workdir,
+            timeout_ms,
+            tty,
+            heartbeat,
+            memory_mib,
+            user,
+            resumable,
+            ..
+        } = config.clone().into_request(false, false)
+        else {
+            panic!("expected an exec request");
+        };
+        assert_eq!(env, [("A".to_string(), "1".to_string())]);
+        assert_eq!(workdir.as_deref(), Some("/app"));
+        assert_eq!(timeout_ms, Some(5000));
+        // Only interactive execs get a TTY and heartbeats
+        assert!(!tty);
+        assert!(heartbeat.is_none());
+        assert!(memory_mib.is_none());
+        assert_eq!(user.as_deref(), Some("nobody"));
+        assert!(!resumable);
+
+        let AgentRequest::Exec {
+            tty,
+            heartbeat,
+            resumable,
+            ..
+        } = config.into_request(true, true)
+        else {
+            panic!("expected an exec request");
+        };
+        assert!(tty);
+        assert!(heartbeat.is_some());
+        assert!(resumable);
+    }
+
+    #[test]
+    fn test_ping_records_capabilities() {
+        let (mut client, agent) = client_with_fake_agent(&[capabilities::HEARTBEAT]);
+        assert!(!client.supported(capabilities::HEARTBEAT));
+
+        client.ping().unwrap();
+        assert!(client.supported(capabilities::HEARTBEAT));
+        assert!(!client.supported(capabilities::RESOURCE_LIMITS));
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping"]);
+    }
+
+    #[test]
+    fn test_unsupported_feature_fails_before_sending_request() {
+        // An older agent would silently ignore the limits
+        let (mut client, agent) = client_with_fake_agent(&[]);
+
+        let err = client.run_with_config(limited_run()).unwrap_err();
+        assert!(
+            matches!(&err, Error::Unsupported { capability, .. } if capability == capabilities::RESOURCE_LIMITS),
+            "unexpected error: {}",
+            err
+        );
+        assert!(err.to_string().contains("resource-limits"));
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping"]);
+    }
+
+    #[test]
+    fn test_restart_policy_requires_capability() {
+        let (mut client, agent) = client_with_fake_agent(&[capabilities::USER]);
+
+        let err = client
+            .create_container(
+                CreateContainerConfig::new("alpine", vec!["false".to_string()])
+                    .with_restart_policy(RestartPolicy::OnFailure { max_retries: 3 }),
+            )
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Unsupported { capability, .. } if capability == capabilities::RESTART_POLICY),
+            "unexpected error: {}",
+            err
+        );
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping"]);
+    }
+
+    #[test]
+    fn test_container_labels_require_capability() {
+        let (mut client, agent) = client_with_fake_agent(&[capabilities::RESTART_POLICY]);
+
+        let err = client
+            .create_container(
+                CreateContainerConfig::new(
+                    "alpine",
+                    vec!["sleep".to_string(), "infinity".to_string()],
+                )
+                .with_labels(BTreeMap::from([(
+                    "project".to_string(),
+                    "billing".to_string(),
+                )])),
+            )
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Unsupported { capability, .. } if capability == capabilities::CONTAINER_LABELS),
+            "unexpected error: {}",
+            err
+        );
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping"]);
+    }
+
+    #[test]
+    fn test_healthcheck_requires_capability() {
+        let (mut client, agent) = client_with_fake_agent(&[capabilities::CONTAINER_LABELS]);
+
+        let err = client
+            .create_container(
+                CreateContainerConfig::new(
+                    "alpine",
+                    vec!["sleep".to_string(), "infinity".to_string()],
+                )
+                .with_healthcheck(Some(HealthCheck::new(vec!["true".to_string()]))),
+            )
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Unsupported { capability, .. } if capability == capabilities::HEALTHCHECK),
+            "unexpected error: {}",
+            err
+        );
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping"]);
+    }
+
+    #[test]
+    fn test_image_archive_streams_through_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("alpine.tar");
+        let (mut client, agent) = client_with_fake_agent(&[capabilities::IMAGE_ARCHIVE]);
+
+        let size = client.save_image("alpine", &path).unwrap();
+        assert_eq!(size, FAKE_ARCHIVE.len() as u64);
+        assert_eq!(std::fs::read(&path).unwrap(), FAKE_ARCHIVE);
+
+        let loaded = client.load_image(&path).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].reference, "docker.io/library/alpine:latest");
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping", "save_image", "load_image"]);
+
+        let (mut client, agent) = client_with_fake_agent(&[]);
+        let err = client.load_image(&path).unwrap_err();
+        assert!(
+            matches!(&err, Error::Unsupported { capability, .. } if capability == capabilities::IMAGE_ARCHIVE),
+            "unexpected error: {}",
+            err
+        );
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping"]);
+    }
+
+    #[test]
+    fn test_no_cache_pull_requires_capability() {
+        let (mut client, agent) = client_with_fake_agent(&[capabilities::USER]);
+
+        let err = client
+            .pull("alpine:latest", PullOptions::new().no_cache(true))
+            .unwrap_err();
+        assert!(
+            matches!(&err, Error::Unsupported { capability, .. } if capability == capabilities::PULL_NO_CACHE),
+            "unexpected error: {}",
+            err
+        );
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping"]);
+    }
+
+    #[test]
+    fn test_pull_answers_heartbeats() {
+        let (mut client, agent) = client_with_fake_agent(&[capabilities::HEARTBEAT]);
+
+        let info = client.pull("alpine:latest", PullOptions::new()).unwrap();
+        assert_eq!(info.reference, "alpine:latest");
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping", "pull", "heartbeat"]);
+    }
+
+    #[test]
+    fn test_verify_requires_capability() {
+        let (mut client, agent) = client_with_fake_agent(&[capabilities::PULL_NO_CACHE]);
+
+        let err = client.verify(None, false).unwrap_err();
+        assert!(
+            matches!(&err, Error::Unsupported { capability, .. } if capability == capabilities::VERIFY),
+            "unexpected error: {}",
+            err
+        );
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping"]);
+    }
+
+    #[test]
+    fn test_entrypoint_requires_capability() {
+        let (mut client, agent) = client_with_fake_agent(&[capabilities::USER]);
+
+        let config = RunConfig::new("alpine", vec!["-c".to_string(), "true".to_string()])
+            .with_entrypoint(Some(vec!["/bin/sh".to_string()]));
+        let err = client.run_with_config(config).unwrap_err();
+        assert!(
+            matches!(&err, Error::Unsupported { capability, .. } if capability == capabilities::ENTRYPOINT),
+            "unexpected error: {}",
+            err
+        );
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping"]);
+    }
+
+    #[test]
+    fn test_list_images_reassembles_pages() {
+        let (mut client, agent) = client_with_fake_agent(&[capabilities::LIST_IMAGES_PAGING]);
+
+        let images = client.list_images_paged(2).unwrap();
+        let references: Vec<_> = images.iter().map(|i| i.reference.as_str()).collect();
+        assert_eq!(
+            references,
+            [
+                "image-0:latest",
+                "image-1:latest",
+                "image-2:latest",
+                "image-3:latest",
+                "image-4:latest"
+            ]
+        );
+
+        let page = client.list_images_page(4, 2).unwrap();
+        assert_eq!(page.images.len(), 1);
+        assert_eq!(page.total, FAKE_IMAGE_COUNT);
+
+        drop(client);
+        assert_eq!(
+            agent.join().unwrap(),
+            [
+                "ping",
+                "list_images",
+                "list_images",
+                "list_images",
+                "list_images"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_images_without_paging_fetches_all() {
+        let (mut client, agent) = client_with_fake_agent(&[]);
+
+        assert_eq!(client.list_images_paged(2).unwrap().len(), FAKE_IMAGE_COUNT);
+        let err = client.list_images_page(0, 2).unwrap_err();
+        assert!(matches!(err, Error::Unsupported { .. }), "{}", err);
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping", "list_images"]);
+    }
+
+    #[test]
+    fn test_require_tools_reports_missing_tool() {
+        let (mut client, agent) = client_with_fake_agent(&[capabilities::TOOL_CHECK]);
+
+        client.require_tools("run sandbox", &["crane"]).unwrap();
+        let err = client
+            .require_tools("run sandbox", &["crane", "crun"])
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("agent is missing crun (looked for /usr/bin/crun)"),
+            "{}",
+            err
+        );
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping", "tool_check", "tool_check"]);
+
+        // Older agents can't report their tools and aren't second-guessed
+        let (mut client, agent) = client_with_fake_agent(&[]);
+        client.require_tools("run sandbox", &["crun"]).unwrap();
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping"]);
+    }
+
+    #[test]
+    fn test_supported_feature_is_sent() {
+        let (mut client, agent) = client_with_fake_agent(capabilities::ALL);
+
+        let out = client.run_with_config(limited_run()).unwrap();
+        assert_eq!(out.exit_code, 0);
+
+        // Capabilities are only fetched once per connection
+        client
+            .run_with_config(RunConfig::new("alpine", vec![]).with_ephemeral(true))
+            .unwrap();
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["ping", "run", "run"]);
+    }
+
+    #[test]
+    fn test_negotiate_drops_heartbeat_for_old_agents() {
+        let (mut client, _agent) = client_with_fake_agent(&[]);
+        let mut request = AgentRequest::VmExec {
+            command: vec!["sh".to_string()],
+            env: vec![],
+            workdir: None,
+            timeout_ms: None,
+            interactive: true,
+            tty: false,
+            heartbeat: Some(HeartbeatConfig::default()),
+        };
+
+        client.negotiate(&mut request, "vm exec").unwrap();
+        let AgentRequest::VmExec { heartbeat, .. } = request else {
+            unreachable!();
+        };
+        assert!(heartbeat.is_none());
+    }
+
+    #[test]
+    fn test_plain_requests_skip_negotiation() {
+        let (mut client, agent) = client_with_fake_agent(&[]);
+        client
+            .run_with_config(RunConfig::new("alpine", vec!["true".to_string()]))
+            .unwrap();
+
+        drop(client);
+        assert_eq!(agent.join().unwrap(), ["run"]);
+    }
+
+    #[test]
+    fn test_deadline_shrinks_later_timeouts() {
+        let delay = Duration::from_millis(300);
+        let (host, mut agent) = UnixStream::pair().unwrap();
+        std::thread::spawn(move || {
+            let mut header = [0u8; 4];
+            while agent.read_exact(&mut header).is_ok() {
+                let mut body = vec![0u8; u32::from_be_bytes(header) as usize];
+                agent.read_exact(&mut body).unwrap();
+                std::thread::sleep(delay);
+                let pong = AgentResponse::Pong {
+                    version: PROTOCOL_VERSION,
+                    capabilities: Vec::new(),
+                };
+                if agent.write_all(&encode_message(&pong).unwrap()).is_err() {
+                    break;
+                }
+            }
+        });
+        host.set_read_timeout(Some(Duration::from_secs(DEFAULT_READ_TIMEOUT_SECS)))
+            .unwrap();
+        let deadline = Deadline::after(Duration::from_millis(500));
+        let mut client = AgentClient {
+            stream: host,
+            capabilities: None,
+            deadline: None,
+            socket_path: None,
+            reconnect: None,
+        }
+        .with_deadline(deadline);
+
+        // The first ping fits in the budget and uses up most of it
+        client.ping().unwrap();
+        let budget = deadline
+            .clamp(Some(Duration::from_secs(DEFAULT_READ_TIMEOUT_SECS)), "test")
+            .unwrap();
+        assert!(budget <= Duration::from_millis(500) - delay);
+
+        // The second would fit the 30s default, but only the rest is left
+        let started = Instant::now();
+        let err = client.ping().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        assert!(started.elapsed() < delay);
+
+        // The operation's own timeout is restored for later requests
+        client.set_deadline(None);
+        assert_eq!(
+            client.stream.read_timeout().unwrap(),
+            Some(Duration::from_secs(DEFAULT_READ_TIMEOUT_SECS))
+        );
+    }
+
+    /// Client connected to a fake agent that answers its first `not_ready`
+    /// pings with an error and later ones with a `Pong` of `version`. The
+    /// handle yields the number of pings received.
+    fn client_with_warming_agent(
+        not_ready: usize,
+        version: u32,
+    ) -> (AgentClient, JoinHandle<usize>) {
+        let (host, mut agent) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut pings = 0;
+            let mut header = [0u8; 4];
+            while agent.read_exact(&mut header).is_ok() {
+                let mut body = vec![0u8; u32::from_be_bytes(header) as usize];
+                agent.read_exact(&mut body).unwrap();
+                pings += 1;
+                let response = if pings <= not_ready {
+                    AgentResponse::error(
+                        "storage not ready",
+                        smolvm_protocol::error_codes::INTERNAL_ERROR,
+                    )
+                } else {
+                    AgentResponse::Pong {
+                        version,
+                        capabilities: Vec::new(),
+                    }
+                };
+                if agent
+                    .write_all(&encode_message(&response).unwrap())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+            pings
+        });
+        let client = AgentClient {
+            stream: host,
+            capabilities: None,
+            deadline: None,
+            socket_path: None,
+            reconnect: None,
+        };
+        (client, handle)
+    }
+
+    #[test]
+    fn test_ping_until_ready_retries_until_pong() {
+        let (mut client, agent) = client_with_warming_agent(3, PROTOCOL_VERSION);
+        let version = client.ping_until_ready(Duration::from_secs(5)).unwrap();
+        assert_eq!(version, PROTOCOL_VERSION);
+        assert!(client.capabilities.is_some());
+        drop(client);
+        assert_eq!(agent.join().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_ping_until_ready_times_out_with_last_error() {
+        let (mut client, _agent) = client_with_warming_agent(usize::MAX, PROTOCOL_VERSION);
+        let started = Instant::now();
+        let err = client
+            .ping_until_ready(Duration::from_millis(350))
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(
+            matches!(&err, Error::BootTimeout { last_error: Some(e), .. } if e.contains("storage not ready")),
+            "{}",
+            err
+        );
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+    }
+
+    #[test]
+    fn test_ping_until_ready_rejects_incompatible_version() {
+        let (mut client, agent) = client_with_warming_agent(0, PROTOCOL_VERSION + 1);
+        let err = client.ping_until_ready(Duration::from_secs(5)).unwrap_err();
+        assert!(err.to_string().contains("protocol version"), "{}", err);
+        assert!(!matches!(err, Error::BootTimeout { .. }));
+        drop(client);
+        assert_eq!(agent.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_exec_resumes_after_dropped_connection() {
+        use std::os::unix::net::UnixListener;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        fn read_frame(agent: &mut UnixStream) -> Option<serde_json::Value> {
+            let mut header = [0u8; 4];
+            agent.read_exact(&mut header).ok()?;
+            let mut body = vec![0u8; u32::from_be_bytes(header) as usize];
+            agent.read_exact(&mut body).ok()?;
+            Some(serde_json::from_slice(&body).unwrap())
+        }
+        fn respond(agent: &mut UnixStream, response: AgentResponse) {
+            agent
+                .write_all(&encode_message(&response).unwrap())
+                .unwrap();
+        }
+        // Output chunks are empty so the test's stdout stays clean
+        let output = || AgentResponse::Stdout { data: Vec::new() };
+
+        let agent = std::thread::spawn(move || {
+            // First connection: start the exec, stream a little, then drop
+            let (mut agent, _) = listener.accept().unwrap();
+            let ping = read_frame(&mut agent).unwrap();
+            assert_eq!(ping["method"], "ping");
+            respond(
+                &mut agent,
+                AgentResponse::Pong {
+                    version: PROTOCOL_VERSION,
+                    capabilities: vec![capabilities::RESUMABLE_EXEC.to_string()],
+                },
+            );
+            let exec = read_frame(&mut agent).unwrap();
+            assert_eq!(exec["method"], "exec");
+            assert_eq!(exec["resumable"], true);
+            let session_id = 0x1234_0000_0001;
+            respond(&mut agent, AgentResponse::ExecSession { session_id });
+            respond(&mut agent, AgentResponse::Started);
+            respond(&mut agent, output());
+            drop(agent);
+
+            // Second connection: the client resumes the same session
+            let (mut agent, _) = listener.accept().unwrap();
+            let resume = read_frame(&mut agent).unwrap();
+            assert_eq!(resume["method"], "resume_exec");
+            assert_eq!(resume["session_id"], session_id);
+            respond(&mut agent, AgentResponse::Started);
+            respond(&mut agent, output());
+            respond(&mut agent, AgentResponse::Exited { exit_code: 3 });
+            while read_frame(&mut agent).is_some() {}
+        });
+
+        let mut client = AgentClient::connect(&socket)
+            .unwrap()
+            .with_reconnect(RetryConfig::for_connection());
+        let exit_code = client
+            .exec_interactive(ExecConfig::new("c1", vec!["sh".to_string()]))
+            .unwrap();
+        assert_eq!(exit_code, 3);
+
+        drop(client);
+        agent.join().unwrap();
+    }
+
+    #[test]
+    fn test_refused_connection_is_classified() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        // Binding and dropping the listener leaves a socket nobody accepts on
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+
+        let err = AgentClient::connect(&socket).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+        assert!(err.kind().is_transient());
     }
 }
@@ -0,0 +1,86 @@
+//! End-to-end deadlines for agent requests.
+//!
+//! Each [`AgentClient`](super::AgentClient) operation has its own socket
+//! timeout, so a flow of several operations (pull, then run) can take the sum
+//! of them. A [`Deadline`] attached to the client caps the whole flow: every
+//! socket timeout becomes the smaller of the operation's default and the time
+//! left, and once the deadline passes requests fail with
+//! [`ErrorKind::Timeout`](crate::error::ErrorKind::Timeout).
+
+use crate::error::{Error, ErrorKind, Result};
+use std::time::{Duration, Instant};
+
+/// A point in time by which a sequence of requests must finish.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline {
+    at: Instant,
+}
+
+impl Deadline {
+    /// Deadline `budget` from now.
+    pub fn after(budget: Duration) -> Self {
+        Self {
+            at: Instant::now() + budget,
+        }
+    }
+
+    /// Deadline at `at`.
+    pub fn at(at: Instant) -> Self {
+        Self { at }
+    }
+
+    /// When the deadline expires.
+    pub fn instant(&self) -> Instant {
+        self.at
+    }
+
+    /// Time left, or `None` once the deadline has passed.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.at
+            .checked_duration_since(Instant::now())
+            .filter(|d| !d.is_zero())
+    }
+
+    /// Whether the deadline has passed.
+    pub fn is_expired(&self) -> bool {
+        self.remaining().is_none()
+    }
+
+    /// Shorten an operation's timeout to the time left.
+    ///
+    /// `None` means the operation has no timeout of its own. Fails with
+    /// [`ErrorKind::Timeout`] if the deadline has already passed.
+    pub fn clamp(&self, timeout: Option<Duration>, op: &str) -> Result<Duration> {
+        let remaining = self.remaining().ok_or_else(|| self.exceeded(op))?;
+        Ok(timeout.map_or(remaining, |t| t.min(remaining)))
+    }
+
+    /// The error reported when `op` runs out of time.
+    pub(super) fn exceeded(&self, op: &str) -> Error {
+        Error::agent_with_kind(ErrorKind::Timeout, op, "request deadline exceeded")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_to_remaining_budget() {
+        let deadline = Deadline::after(Duration::from_secs(10));
+        let clamped = deadline
+            .clamp(Some(Duration::from_secs(30)), "test")
+            .unwrap();
+        assert!(clamped <= Duration::from_secs(10));
+        assert!(clamped > Duration::from_secs(9));
+
+        // Shorter operation timeouts are kept as they are
+        let short = Duration::from_secs(1);
+        assert_eq!(deadline.clamp(Some(short), "test").unwrap(), short);
+
+        let expired = Deadline::at(Instant::now());
+        assert!(expired.is_expired());
+        let err = expired.clamp(None, "test").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+    }
+}
@@ -0,0 +1,206 @@
+//! Filtering of cached image listings.
+//!
+//! The agent returns every cached image; [`ImageFilter`] narrows that list on
+//! the host by creation time and config labels.
+
+use crate::error::{Error, Result};
+use crate::labels::{labels_match, LabelFilter};
+use smolvm_protocol::ImageInfo;
+use std::time::SystemTime;
+
+/// Criteria an image must meet to be listed.
+///
+/// An empty filter matches every image. Images without a parseable
+/// `created` timestamp never match a date bound.
+///
+/// # Example
+///
+/// ```ignore
+/// let filter = ImageFilter::new()
+///     .created_after(parse_image_timestamp("2024-01-01")?)
+///     .label("org.opencontainers.image.vendor", Some("Acme"));
+///
+/// let images = client.list_images_filtered(&filter)?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageFilter {
+    /// Only images created strictly before this time.
+    pub created_before: Option<SystemTime>,
+    /// Only images created strictly after this time.
+    pub created_after: Option<SystemTime>,
+    /// Labels every image must carry, with the required value if any.
+    pub labels: Vec<LabelFilter>,
+}
+
+impl ImageFilter {
+    /// Create a filter matching every image.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match images created before `time`.
+    pub fn created_before(mut self, time: SystemTime) -> Self {
+        self.created_before = Some(time);
+        self
+    }
+
+    /// Only match images created after `time`.
+    pub fn created_after(mut self, time: SystemTime) -> Self {
+        self.created_after = Some(time);
+        self
+    }
+
+    /// Only match images carrying label `key`, set to `value` if given.
+    pub fn label(mut self, key: impl Into<String>, value: Option<impl Into<String>>) -> Self {
+        self.labels.push((key.into(), value.map(Into::into)));
+        self
+    }
+
+    /// Whether the filter has no criteria.
+    pub fn is_empty(&self) -> bool {
+        self.created_before.is_none() && self.created_after.is_none() && self.labels.is_empty()
+    }
+
+    /// Whether `image` meets every criterion.
+    pub fn matches(&self, image: &ImageInfo) -> bool {
+        if self.created_before.is_some() || self.created_after.is_some() {
+            let Some(created) = image
+                .created
+                .as_deref()
+                .and_then(|c| parse_image_timestamp(c).ok())
+            else {
+                return false;
+            };
+            if self.created_before.is_some_and(|t| created >= t)
+                || self.created_after.is_some_and(|t| created <= t)
+            {
+                return false;
+            }
+        }
+
+        labels_match(&image.labels, &self.labels)
+    }
+
+    /// Keep only the images that match.
+    pub fn apply(&self, images: Vec<ImageInfo>) -> Vec<ImageInfo> {
+        if self.is_empty() {
+            return images;
+        }
+        images.into_iter().filter(|i| self.matches(i)).collect()
+    }
+}
+
+/// Parse an image timestamp: RFC 3339 in UTC (as found in OCI image
+/// configs, e.g. `2024-05-01T12:00:00.123Z`) or a bare `YYYY-MM-DD` date,
+/// meaning midnight UTC.
+pub fn parse_image_timestamp(s: &str) -> Result<SystemTime> {
+    let s = s.trim();
+    let parsed = if s.len() == 10 {
+        humantime::parse_rfc3339_weak(&format!("{}T00:00:00Z", s))
+    } else {
+        humantime::parse_rfc3339_weak(s)
+    };
+    parsed.map_err(|e| Error::config("parse timestamp", format!("'{}': {}", s, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::labels::parse_label_filter;
+    use std::collections::BTreeMap;
+
+    fn image(reference: &str, created: Option<&str>, labels: &[(&str, &str)]) -> ImageInfo {
+        ImageInfo {
+            reference: reference.to_string(),
+            digest: format!("sha256:{}", reference),
+            size: 0,
+            created: created.map(String::from),
+            architecture: "arm64".to_string(),
+            os: "linux".to_string(),
+            layer_count: 0,
+            layers: Vec::new(),
+            entrypoint: Vec::new(),
+            cmd: Vec::new(),
+            env: Vec::new(),
+            workdir: None,
+            labels: labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+            tags: Vec::new(),
+            refreshed_layers: Vec::new(),
+        }
+    }
+
+    fn seeded() -> Vec<ImageInfo> {
+        vec![
+            image(
+                "old",
+                Some("2022-03-01T10:00:00.123456789Z"),
+                &[("tier", "base")],
+            ),
+            image(
+                "mid",
+                Some("2023-06-15T00:00:00Z"),
+                &[("tier", "app"), ("team", "web")],
+            ),
+            image("new", Some("2024-11-30T23:59:59Z"), &[("tier", "app")]),
+            image("undated", None, &[("tier", "app")]),
+        ]
+    }
+
+    fn references(images: &[ImageInfo]) -> Vec<&str> {
+        images.iter().map(|i| i.reference.as_str()).collect()
+    }
+
+    #[test]
+    fn test_filter_by_date_range() {
+        let filter = ImageFilter::new()
+            .created_after(parse_image_timestamp("2023-01-01").unwrap())
+            .created_before(parse_image_timestamp("2024-12-01T00:00:00Z").unwrap());
+        assert_eq!(references(&filter.apply(seeded())), ["mid", "new"]);
+
+        let filter =
+            ImageFilter::new().created_before(parse_image_timestamp("2023-06-15").unwrap());
+        assert_eq!(references(&filter.apply(seeded())), ["old"]);
+
+        // No matches is an empty list, not an error
+        let filter = ImageFilter::new().created_after(parse_image_timestamp("2030-01-01").unwrap());
+        assert!(filter.apply(seeded()).is_empty());
+    }
+
+    #[test]
+    fn test_filter_by_labels() {
+        let (key, value) = parse_label_filter("tier=app").unwrap();
+        let filter = ImageFilter::new().label(key, value);
+        assert_eq!(
+            references(&filter.apply(seeded())),
+            ["mid", "new", "undated"]
+        );
+
+        let filter = filter.label("team", None::<String>);
+        assert_eq!(references(&filter.apply(seeded())), ["mid"]);
+
+        let filter = ImageFilter::new().label("tier", Some("missing"));
+        assert!(filter.apply(seeded()).is_empty());
+
+        assert_eq!(ImageFilter::new().apply(seeded()).len(), 4);
+    }
+
+    #[test]
+    fn test_parse_filters() {
+        assert_eq!(
+            parse_label_filter("a.b=c=d").unwrap(),
+            ("a.b".to_string(), Some("c=d".to_string()))
+        );
+        assert_eq!(
+            parse_label_filter("key").unwrap(),
+            ("key".to_string(), None)
+        );
+        assert!(parse_label_filter("=value").is_err());
+
+        assert!(parse_image_timestamp("2024-01-01").is_ok());
+        assert!(parse_image_timestamp("2024-01-01T12:30:00.5Z").is_ok());
+        assert!(parse_image_timestamp("last tuesday").is_err());
+    }
+}
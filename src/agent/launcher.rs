@@ -5,9 +5,13 @@
 //! DYLD_LIBRARY_PATH is still available for dlopen.
 
 use crate::error::{Error, Result};
-use crate::storage::{OverlayDisk, StorageDisk};
+use crate::storage::{OverlayDisk, ScratchDisk, StorageDisk};
 use crate::vm::config::HostMount;
+use smolvm_protocol::agent_env;
+use smolvm_protocol::dns;
+use smolvm_protocol::log_format::LOG_FORMAT_ENV;
 use smolvm_protocol::ports;
+use smolvm_protocol::scratch;
 use std::ffi::{CStr, CString};
 use std::path::{Path, PathBuf};
 
@@ -19,6 +23,9 @@ pub struct VmDisks<'a> {
     pub storage: &'a StorageDisk,
     /// Optional overlay disk for persistent rootfs (/dev/vdb in guest).
     pub overlay: Option<&'a OverlayDisk>,
+    /// Optional scratch disk for container overlay upper layers
+    /// (/dev/vdc in guest, so it needs the overlay disk too).
+    pub scratch: Option<&'a ScratchDisk>,
 }
 
 // FFI bindings to libkrun
@@ -295,6 +302,31 @@ pub fn launch_agent_vm(
             }
         }
 
+        // Add scratch disk for container writes (optional)
+        // This is the third disk → /dev/vdc in guest
+        if let Some(scratch) = disks.scratch {
+            if disks.overlay.is_none() {
+                krun_free_ctx(ctx);
+                return Err(Error::agent(
+                    "add scratch disk",
+                    "scratch disk requires the overlay disk to come up as /dev/vdc",
+                ));
+            }
+            let scratch_id = cstr("scratch");
+            let scratch_path = try_or_free_ctx!(
+                path_to_cstring(scratch.path()),
+                "add scratch disk",
+                "path contains null byte"
+            );
+            if krun_add_disk2(ctx, scratch_id.as_ptr(), scratch_path.as_ptr(), 0, false) < 0 {
+                krun_free_ctx(ctx);
+                return Err(Error::agent(
+                    "add scratch disk",
+                    "krun_add_disk2 failed for scratch disk",
+                ));
+            }
+        }
+
         // Add vsock port for control channel (critical - host-guest communication)
         let socket_path = try_or_free_ctx!(
             path_to_cstring(vsock_socket),
@@ -309,15 +341,56 @@ pub fn launch_agent_vm(
             ));
         }
 
-        // Set console output if specified
-        if let Some(log_path) = console_log {
-            let console_path = try_or_free_ctx!(
-                path_to_cstring(log_path),
-                "set console output",
+        // Expose the workload protocol port only when a token authenticates it
+        let workload_token = std::env::var(super::WORKLOAD_TOKEN_ENV)
+            .ok()
+            .filter(|t| !t.is_empty());
+        if workload_token.is_some() {
+            let workload_socket = try_or_free_ctx!(
+                path_to_cstring(&super::workload_socket_path(vsock_socket)),
+                "add workload vsock port",
                 "path contains null byte"
             );
+            if krun_add_vsock_port2(ctx, ports::WORKLOAD_CONTROL, workload_socket.as_ptr(), true)
+                < 0
+            {
+                krun_free_ctx(ctx);
+                return Err(Error::agent(
+                    "add workload vsock port",
+                    "krun_add_vsock_port2 failed",
+                ));
+            }
+        }
+
+        // Set console output if specified. Output goes through a FIFO so the
+        // log can be size-capped; fall back to the plain file if the FIFO
+        // can't be created. The pump starts just before the VM does.
+        let mut console_pump = None;
+        if let Some(log_path) = console_log {
+            let pump = crate::log_rotation::ConsolePump::create(
+                log_path,
+                crate::log_rotation::MAX_LOG_SIZE,
+            )
+            .map_err(|e| tracing::warn!(error = %e, "failed to create console FIFO"))
+            .ok();
+            let target = pump.as_ref().map_or(log_path, |p| p.fifo_path());
+            let Ok(console_path) = path_to_cstring(target) else {
+                if let Some(pump) = pump {
+                    pump.discard();
+                }
+                krun_free_ctx(ctx);
+                return Err(Error::agent(
+                    "set console output",
+                    "path contains null byte",
+                ));
+            };
             if krun_set_console_output(ctx, console_path.as_ptr()) < 0 {
                 tracing::warn!("failed to set console output");
+                if let Some(pump) = pump {
+                    pump.discard();
+                }
+            } else {
+                console_pump = pump;
             }
         }
 
@@ -390,6 +463,66 @@ pub fn launch_agent_vm(
             }
         }
 
+        // Forward the image puller selection (crane or registry)
+        if let Ok(puller) = std::env::var("SMOLVM_OCI_PULLER") {
+            if let Ok(cstr) = CString::new(format!("SMOLVM_OCI_PULLER={}", puller)) {
+                env_strings.push(cstr);
+            }
+        }
+
+        // Tell the agent to deduplicate the layers it extracts
+        if resources.layer_dedup {
+            if let Ok(cstr) = CString::new(format!("{}=1", agent_env::LAYER_DEDUP_ENV)) {
+                env_strings.push(cstr);
+            }
+        }
+
+        // Tell the agent to mount the scratch disk and keep overlay upper
+        // layers on it
+        if disks.scratch.is_some() {
+            if let Ok(cstr) = CString::new(format!(
+                "{}={}",
+                scratch::OVERLAY_UPPER_DIR_ENV,
+                scratch::SCRATCH_MOUNT
+            )) {
+                env_strings.push(cstr);
+            }
+        }
+
+        // Forward the agent's per-connection and environment limits, if set
+        for name in agent_env::FORWARDED.iter().copied() {
+            if let Ok(value) = std::env::var(name) {
+                if let Ok(cstr) = CString::new(format!("{}={}", name, value)) {
+                    env_strings.push(cstr);
+                }
+            }
+        }
+
+        // Tell the agent which resolvers containers should use
+        if let Some(guest_dns) = resources.guest_dns() {
+            for (name, value) in [
+                (dns::DNS_SERVERS_ENV, guest_dns.servers_env_value()),
+                (dns::DNS_SEARCH_ENV, guest_dns.search_env_value()),
+            ] {
+                if let Ok(cstr) = CString::new(format!("{}={}", name, value)) {
+                    env_strings.push(cstr);
+                }
+            }
+        }
+
+        // Forward the log format so agent logs match the host's
+        if let Ok(format) = std::env::var(LOG_FORMAT_ENV) {
+            if let Ok(cstr) = CString::new(format!("{}={}", LOG_FORMAT_ENV, format)) {
+                env_strings.push(cstr);
+            }
+        }
+
+        if let Some(token) = &workload_token {
+            if let Ok(cstr) = CString::new(format!("{}={}", super::WORKLOAD_TOKEN_ENV, token)) {
+                env_strings.push(cstr);
+            }
+        }
+
         let mut envp: Vec<*const libc::c_char> = env_strings.iter().map(|s| s.as_ptr()).collect();
         envp.push(std::ptr::null());
 
@@ -404,6 +537,12 @@ pub fn launch_agent_vm(
             return Err(Error::agent("set exec command", "krun_set_exec failed"));
         }
 
+        if let Some(pump) = console_pump {
+            if let Err(e) = pump.spawn() {
+                tracing::warn!(error = %e, "failed to start console log pump");
+            }
+        }
+
         // Start VM (this replaces the process on success)
         tracing::info!("starting agent VM");
         let ret = krun_start_enter(ctx);
@@ -6,8 +6,9 @@
 use crate::error::{Error, Result};
 use crate::process::{self, ChildProcess};
 use crate::storage::{OverlayDisk, StorageDisk};
+use crate::vm::boot::{self, BootReport};
+use crate::vm::state::ExitReason;
 use parking_lot::Mutex;
-use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -19,8 +20,8 @@ use super::{HostMount, PortMapping, VmResources};
 // Configuration Constants
 // ============================================================================
 
-/// Timeout for the agent to become ready after starting.
-const AGENT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Console log file name inside a VM's runtime directory.
+const CONSOLE_LOG_FILENAME: &str = "agent-console.log";
 
 // Re-use shared polling constants from process module.
 use crate::process::{FAST_POLL_COUNT, FAST_POLL_INTERVAL};
@@ -29,6 +30,10 @@ use crate::process::{FAST_POLL_COUNT, FAST_POLL_INTERVAL};
 /// Reduced from 5s - VMs typically exit within 100ms after shutdown signal.
 const AGENT_STOP_TIMEOUT: Duration = Duration::from_secs(2);
 
+/// How long processes in the guest get to exit after SIGTERM before the
+/// agent kills them, when stopping the VM.
+const GUEST_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Timeout when waiting for agent to stop.
 const WAIT_FOR_STOP_TIMEOUT: Duration = Duration::from_secs(10);
 
@@ -128,11 +133,7 @@ pub fn docker_config_mount() -> Option<HostMount> {
 
     // Mount to /root/.docker which is where crane looks by default
     // Use read-only mount to prevent modification
-    Some(HostMount {
-        source: docker_dir,
-        target: PathBuf::from("/root/.docker"),
-        read_only: true,
-    })
+    Some(HostMount::new(docker_dir, "/root/.docker"))
 }
 
 /// Internal state shared between threads.
@@ -165,6 +166,131 @@ pub fn vm_data_dir(name: &str) -> PathBuf {
         .join(name)
 }
 
+/// Get the runtime directory (socket, PID file, console log) for a VM.
+///
+/// Named VMs get their own subdirectory; `None` is the legacy unnamed agent.
+fn vm_runtime_dir(name: Option<&str>) -> PathBuf {
+    let runtime_dir = dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+
+    match name {
+        Some(vm_name) => runtime_dir.join("smolvm").join("vms").join(vm_name),
+        None => runtime_dir.join("smolvm"),
+    }
+}
+
+/// Get the console log path for a named VM.
+///
+/// Does not create anything, so it is safe to use for VMs that aren't running.
+pub fn vm_console_log_path(name: &str) -> PathBuf {
+    vm_runtime_dir(Some(name)).join(CONSOLE_LOG_FILENAME)
+}
+
+/// Wait for a freshly started agent to signal that it is ready.
+///
+/// Connects to the vsock socket once it appears and waits for the agent's
+/// ready signal (see [`AgentClient::wait_ready`](super::AgentClient::wait_ready)),
+/// bounded by `timeout`. Connection attempts are only retried while the
+/// socket is not yet accepting, or when the guest end is not listening yet.
+///
+/// `process_exit` is checked between attempts and returns how the VM process
+/// exited once it is no longer running, so a VM that dies during boot fails
+/// fast. On failure, returns [`Error::BootFailed`] with a [`BootReport`]
+/// carrying the tail of `console_log` so boot failures can be diagnosed.
+pub fn wait_for_agent_ready(
+    socket_path: &Path,
+    timeout: Duration,
+    console_log: Option<&Path>,
+    mut process_exit: impl FnMut() -> Option<ExitReason>,
+) -> Result<super::AgentClient> {
+    let start = Instant::now();
+    let mut socket_appeared_at: Option<Duration> = None;
+    let mut poll_count: u32 = 0;
+    let mut report = BootReport {
+        timeout_secs: timeout.as_secs(),
+        exit_reason: None,
+        console_tail: None,
+        vsock_connected: false,
+    };
+
+    tracing::debug!("waiting for agent to be ready");
+
+    loop {
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            break;
+        }
+
+        if let Some(reason) = process_exit() {
+            report.exit_reason = Some(reason);
+            break;
+        }
+
+        if socket_path.exists() {
+            if socket_appeared_at.is_none() {
+                socket_appeared_at = Some(elapsed);
+                tracing::debug!(elapsed_ms = elapsed.as_millis(), "vsock socket appeared");
+            }
+
+            match super::AgentClient::connect(socket_path) {
+                Ok(mut client) => {
+                    report.vsock_connected = true;
+                    match client.wait_ready(timeout - elapsed) {
+                        Ok(Some(_)) => {
+                            tracing::info!(
+                                total_ms = start.elapsed().as_millis(),
+                                socket_wait_ms =
+                                    socket_appeared_at.map(|d| d.as_millis()).unwrap_or(0),
+                                "agent ready"
+                            );
+                            return Ok(client);
+                        }
+                        Ok(None) => break,
+                        Err(e) => {
+                            // The host end accepted but the guest end went away
+                            // (agent not listening yet) — reconnect.
+                            tracing::trace!("ready handshake failed: {}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::trace!("connect failed: {}", e);
+                }
+            }
+        }
+
+        // Use aggressive polling at first, then back off
+        let poll_interval = if poll_count < FAST_POLL_COUNT {
+            FAST_POLL_INTERVAL
+        } else {
+            Duration::from_millis(100)
+        };
+        poll_count += 1;
+        std::thread::sleep(poll_interval.min(timeout.saturating_sub(start.elapsed())));
+    }
+
+    report.console_tail = boot::console_tail(console_log);
+    Err(Error::boot_failed(report))
+}
+
+/// How a VM process that is no longer running exited, or `None` while it
+/// is still running.
+fn child_exit_reason(child: &mut ChildProcess) -> Option<ExitReason> {
+    if child.is_running() {
+        return None;
+    }
+    Some(ExitReason::from_process_exit(child.exit_code()))
+}
+
+/// Read the last `max_lines` lines of a log file, including rotated
+/// segments. Returns `None` if the log can't be read.
+pub fn read_log_tail(path: &Path, max_lines: usize) -> Option<String> {
+    crate::log_rotation::tail_lines(path, max_lines)
+        .ok()
+        .map(|lines| lines.join("\n"))
+}
+
 /// Agent VM manager.
 ///
 /// Manages the lifecycle of the agent VM which handles OCI image operations
@@ -189,6 +315,8 @@ pub struct AgentManager {
     config_file: PathBuf,
     /// Console log path (optional).
     console_log: Option<PathBuf>,
+    /// How long to wait for the agent's ready signal after starting.
+    boot_timeout: Duration,
     /// Internal state.
     inner: Arc<Mutex<AgentInner>>,
 }
@@ -234,22 +362,13 @@ impl AgentManager {
         overlay_disk: OverlayDisk,
     ) -> Result<Self> {
         // Create runtime directory for sockets
-        let runtime_dir = dirs::runtime_dir()
-            .or_else(dirs::cache_dir)
-            .unwrap_or_else(|| PathBuf::from("/tmp"));
-
-        // Named VMs get their own subdirectory
-        let smolvm_runtime = if let Some(ref vm_name) = name {
-            runtime_dir.join("smolvm").join("vms").join(vm_name)
-        } else {
-            runtime_dir.join("smolvm")
-        };
+        let smolvm_runtime = vm_runtime_dir(name.as_deref());
         std::fs::create_dir_all(&smolvm_runtime)?;
 
         let vsock_socket = smolvm_runtime.join("agent.sock");
         let pid_file = smolvm_runtime.join("agent.pid");
         let config_file = smolvm_runtime.join("agent.config.json");
-        let console_log = Some(smolvm_runtime.join("agent-console.log"));
+        let console_log = Some(smolvm_runtime.join(CONSOLE_LOG_FILENAME));
 
         Ok(Self {
             name,
@@ -260,6 +379,7 @@ impl AgentManager {
             pid_file,
             config_file,
             console_log,
+            boot_timeout: crate::vm::config::Timeouts::default().boot,
             inner: Arc::new(Mutex::new(AgentInner {
                 state: AgentState::Stopped,
                 child: None,
@@ -326,6 +446,13 @@ impl AgentManager {
         Self::for_vm_with_sizes(name, None, None)
     }
 
+    /// Set how long to wait for the agent to become ready after starting
+    /// (defaults to `Timeouts::boot`).
+    pub fn with_boot_timeout(mut self, timeout: Duration) -> Self {
+        self.boot_timeout = timeout;
+        self
+    }
+
     /// Get the VM name if this is a named agent.
     pub fn name(&self) -> Option<&str> {
         self.name.as_deref()
@@ -587,6 +714,12 @@ impl AgentManager {
         super::AgentClient::connect_with_retry(&self.vsock_socket)
     }
 
+    /// Ask the running agent for its uptime and what it manages, for
+    /// diagnostics.
+    pub fn agent_status(&self) -> crate::error::Result<smolvm_protocol::AgentStatus> {
+        self.connect()?.agent_status()
+    }
+
     /// Get the currently configured mounts.
     pub fn mounts(&self) -> Vec<HostMount> {
         self.inner.lock().mounts.clone()
@@ -604,6 +737,14 @@ impl AgentManager {
         inner.resources == resources
     }
 
+    /// Resources of the agent VM that is already running, if its
+    /// configuration is known.
+    pub fn running_resources(&self) -> Option<VmResources> {
+        self.try_connect_existing()?;
+        let inner = self.inner.lock();
+        matches!(inner.config_state, ConfigState::Known).then_some(inner.resources)
+    }
+
     /// Check if the given port mappings match the currently running agent's ports.
     pub fn ports_match(&self, ports: &[PortMapping]) -> bool {
         let inner = self.inner.lock();
@@ -825,6 +966,7 @@ impl AgentManager {
                 tracing::debug!(error = %e, path = %self.vsock_socket.display(), "failed to remove old socket");
             }
         }
+        let _ = std::fs::remove_file(super::workload_socket_path(&self.vsock_socket));
 
         // Clone paths for the child process (owned copies)
         let rootfs_path = self.rootfs_path.clone();
@@ -838,6 +980,9 @@ impl AgentManager {
         let overlay_size_gb = resources
             .overlay_gb
             .unwrap_or(crate::storage::DEFAULT_OVERLAY_SIZE_GB);
+        let scratch_size_gb = resources.scratch_gb;
+        let scratch_disk_path =
+            storage_disk_path.with_file_name(crate::storage::SCRATCH_DISK_FILENAME);
 
         // Fork child process using the safe abstraction.
         // The child becomes a session leader (detached from parent's session)
@@ -873,6 +1018,18 @@ impl AgentManager {
                 }
             };
 
+            // Create the scratch disk in child, if the VM has one
+            let scratch_disk = match scratch_size_gb
+                .map(|gb| crate::storage::ScratchDisk::open_or_create_at(&scratch_disk_path, gb))
+                .transpose()
+            {
+                Ok(d) => d,
+                Err(e) => {
+                    eprintln!("failed to open scratch disk: {}", e);
+                    process::exit_child(1);
+                }
+            };
+
             // Detach from parent's terminal before launching the VM.
             // Without this, libkrun's threads inherit stdin and steal
             // keystrokes from the user's shell.
@@ -882,6 +1039,7 @@ impl AgentManager {
             let disks = launcher::VmDisks {
                 storage: &storage_disk,
                 overlay: Some(&overlay_disk),
+                scratch: scratch_disk.as_ref(),
             };
             let result = launch_agent_vm(
                 &rootfs_path,
@@ -952,11 +1110,17 @@ impl AgentManager {
     /// 2. **PID start-time** — strict comparison guards against PID reuse
     ///
     /// If either method confirms identity, sends SIGTERM (then SIGKILL on timeout).
+    /// Before the shutdown, the agent is asked to stop the guest's workloads,
+    /// giving them [`GUEST_STOP_TIMEOUT`] to exit on SIGTERM.
     /// Returns `Ok(())` if the process is confirmed dead, `Err` if still alive
     /// or identity could not be verified.
     fn stop_vm_process(&self, pid: libc::pid_t, start_time: Option<u64>) -> Result<()> {
         let shutdown_acked = if let Ok(mut client) = super::AgentClient::connect(&self.vsock_socket)
         {
+            // Let workloads exit cleanly before the VM goes away
+            if let Err(e) = client.stop(GUEST_STOP_TIMEOUT) {
+                tracing::debug!(error = %e, "graceful guest stop failed, shutting down anyway");
+            }
             client.shutdown().is_ok()
         } else {
             false
@@ -1065,9 +1229,14 @@ impl AgentManager {
         // Defense in depth: sync host's view of the disk files
         // This catches any writes that made it to the host buffer but weren't flushed
         // Combined with agent-side sync(), this provides robust data integrity
+        let scratch_path = self
+            .storage_disk
+            .path()
+            .with_file_name(crate::storage::SCRATCH_DISK_FILENAME);
         for (label, path) in [
             ("storage", self.storage_disk.path()),
             ("overlay", self.overlay_disk.path()),
+            ("scratch", scratch_path.as_path()),
         ] {
             if let Ok(file) = std::fs::File::open(path) {
                 if file.sync_all().is_ok() {
@@ -1090,98 +1259,13 @@ impl AgentManager {
 
     /// Wait for the agent to be ready.
     fn wait_for_ready(&self) -> Result<()> {
-        let timeout = AGENT_READY_TIMEOUT;
-        let start = Instant::now();
-
-        tracing::debug!("waiting for agent to be ready");
-
-        // Track timing for each phase
-        let mut socket_appeared_at: Option<Duration> = None;
-        let mut first_connect_at: Option<Duration> = None;
-        let mut poll_count: u32 = 0;
-
-        while start.elapsed() < timeout {
-            // Use aggressive polling at first, then back off
-            let poll_interval = if poll_count < FAST_POLL_COUNT {
-                FAST_POLL_INTERVAL // 10ms
-            } else {
-                Duration::from_millis(100) // Back off to 100ms
-            };
-            poll_count += 1;
-            // Check if child process is still alive
-            {
-                let mut inner = self.inner.lock();
-                if let Some(ref mut child) = inner.child {
-                    if !child.is_running() {
-                        // Child exited
-                        return Err(Error::agent(
-                            "monitor agent",
-                            "agent process exited during startup",
-                        ));
-                    }
-                }
-            }
-
-            // Try to connect to vsock socket
-            if self.vsock_socket.exists() {
-                // Log when socket first appears
-                if socket_appeared_at.is_none() {
-                    let elapsed = start.elapsed();
-                    socket_appeared_at = Some(elapsed);
-                    tracing::debug!(elapsed_ms = elapsed.as_millis(), "vsock socket appeared");
-                }
-
-                match UnixStream::connect(&self.vsock_socket) {
-                    Ok(stream) => {
-                        drop(stream);
-
-                        // Log when first connect succeeds
-                        if first_connect_at.is_none() {
-                            let elapsed = start.elapsed();
-                            first_connect_at = Some(elapsed);
-                            tracing::debug!(
-                                elapsed_ms = elapsed.as_millis(),
-                                "vsock first connect succeeded"
-                            );
-                        }
-
-                        // Try to ping
-                        match super::AgentClient::connect(&self.vsock_socket) {
-                            Ok(mut client) => {
-                                if client.ping().is_ok() {
-                                    let total = start.elapsed();
-                                    tracing::info!(
-                                        total_ms = total.as_millis(),
-                                        socket_wait_ms =
-                                            socket_appeared_at.map(|d| d.as_millis()).unwrap_or(0),
-                                        connect_wait_ms =
-                                            first_connect_at.map(|d| d.as_millis()).unwrap_or(0),
-                                        "agent ready - timing breakdown"
-                                    );
-                                    return Ok(());
-                                }
-                            }
-                            Err(e) => {
-                                tracing::trace!("ping failed: {}", e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        tracing::trace!("connect failed: {}", e);
-                    }
-                }
-            }
-
-            std::thread::sleep(poll_interval);
-        }
-
-        Err(Error::agent(
-            "wait for ready",
-            format!(
-                "agent did not become ready within {} seconds",
-                timeout.as_secs()
-            ),
-        ))
+        wait_for_agent_ready(
+            &self.vsock_socket,
+            self.boot_timeout,
+            self.console_log.as_deref(),
+            || self.inner.lock().child.as_mut().and_then(child_exit_reason),
+        )
+        .map(drop)
     }
 
     /// Wait for the agent to stop.
@@ -1247,3 +1331,123 @@ impl Drop for AgentManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smolvm_protocol::{encode_message, AgentResponse, PROTOCOL_VERSION};
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixListener;
+
+    /// Fake agent that accepts one connection, reads the ping and only
+    /// answers after `delay` (simulating slow storage init).
+    fn spawn_fake_agent(socket: &Path, delay: Duration) -> std::thread::JoinHandle<()> {
+        let listener = UnixListener::bind(socket).unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0u8; 4];
+            stream.read_exact(&mut header).unwrap();
+            let mut body = vec![0u8; u32::from_be_bytes(header) as usize];
+            stream.read_exact(&mut body).unwrap();
+
+            std::thread::sleep(delay);
+            let pong = encode_message(&AgentResponse::Pong {
+                version: PROTOCOL_VERSION,
+                capabilities: vec![],
+            })
+            .unwrap();
+            let _ = stream.write_all(&pong);
+        })
+    }
+
+    #[test]
+    fn test_wait_for_agent_ready_times_out_with_console_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let console = dir.path().join("console.log");
+        std::fs::write(&console, "booting\nkernel panic: no init found\n").unwrap();
+
+        let agent = spawn_fake_agent(&socket, Duration::from_millis(500));
+        let start = Instant::now();
+        let result =
+            wait_for_agent_ready(&socket, Duration::from_millis(150), Some(&console), || None);
+        assert!(start.elapsed() < Duration::from_millis(450));
+
+        match result {
+            Err(Error::BootFailed(report)) => {
+                assert!(report
+                    .console_tail
+                    .as_deref()
+                    .unwrap()
+                    .contains("kernel panic: no init found"));
+                assert!(report.vsock_connected);
+                assert_eq!(report.exit_reason, None);
+
+                let msg = report.to_string();
+                assert!(msg.starts_with("boot timed out"), "{}", msg);
+                assert!(msg.contains("kernel panic: no init found"), "{}", msg);
+            }
+            Err(e) => panic!("expected boot failure, got {}", e),
+            Ok(_) => panic!("expected boot failure, got ready"),
+        }
+        agent.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_for_agent_ready_reports_exit_reason() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+        let console = dir.path().join("console.log");
+        std::fs::write(&console, "Out of memory: Killed process 1 (init)\n").unwrap();
+
+        let result = wait_for_agent_ready(&socket, Duration::from_secs(5), Some(&console), || {
+            Some(ExitReason::signaled(9))
+        });
+        let err = result.err().expect("expected boot failure");
+        assert_eq!(err.kind(), crate::error::ErrorKind::Other);
+        let Error::BootFailed(report) = &err else {
+            panic!("expected boot failure, got {}", err);
+        };
+        assert_eq!(report.exit_reason, Some(ExitReason::signaled(9)));
+        assert!(!report.vsock_connected);
+
+        let msg = err.to_string();
+        assert!(msg.contains("killed by signal 9"), "{}", msg);
+        assert!(msg.contains("Out of memory"), "{}", msg);
+    }
+
+    #[test]
+    fn test_wait_for_agent_ready_waits_for_signal() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+
+        let agent = spawn_fake_agent(&socket, Duration::from_millis(100));
+        let result = wait_for_agent_ready(&socket, Duration::from_secs(5), None, || None);
+        assert!(result.is_ok());
+        agent.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_for_agent_ready_fails_fast_if_vm_exits() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("agent.sock");
+
+        let start = Instant::now();
+        let result = wait_for_agent_ready(&socket, Duration::from_secs(5), None, || {
+            Some(ExitReason::exited(1))
+        });
+        assert!(matches!(result, Err(Error::BootFailed(_))));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_read_log_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("console.log");
+        std::fs::write(&log, "one\ntwo\nthree\nfour\n").unwrap();
+
+        assert_eq!(read_log_tail(&log, 2).unwrap(), "three\nfour");
+        assert_eq!(read_log_tail(&log, 10).unwrap(), "one\ntwo\nthree\nfour");
+        assert!(read_log_tail(&dir.path().join("missing.log"), 2).is_none());
+    }
+}
@@ -3,15 +3,31 @@
 //! This module manages the agent VM lifecycle and provides a client
 //! for communicating with the smolvm-agent via vsock.
 
+mod async_client;
 mod client;
+mod deadline;
+mod image_filter;
 mod launcher;
 pub mod launcher_dynamic;
 mod manager;
+pub mod resource_hints;
 pub mod terminal;
+mod workload;
 
 pub use crate::vm::config::HostMount;
-pub use client::{AgentClient, PullOptions, RunConfig};
-pub use manager::{docker_config_dir, docker_config_mount, vm_data_dir, AgentManager, AgentState};
+pub use crate::vm::BootReport;
+pub use async_client::AsyncAgentClient;
+pub use client::{
+    AgentClient, CreateContainerConfig, ExecConfig, PullOptions, ResourceLimits, RunConfig,
+    RunOutput, LIST_IMAGES_PAGE_SIZE,
+};
+pub use deadline::Deadline;
+pub use image_filter::{parse_image_timestamp, ImageFilter};
+pub use manager::{
+    docker_config_dir, docker_config_mount, read_log_tail, vm_console_log_path, vm_data_dir,
+    wait_for_agent_ready, AgentManager, AgentState,
+};
+pub use workload::{workload_socket_path, WorkloadClient, WorkloadOutput, WORKLOAD_TOKEN_ENV};
 
 /// Default agent VM memory in MiB.
 pub const DEFAULT_MEMORY_MIB: u32 = 512;
@@ -30,6 +46,11 @@ pub fn mount_tag(index: usize) -> String {
     format!("smolvm{}", index)
 }
 
+/// Generate the mount tag referring to a named volume managed by the agent.
+pub fn volume_mount_tag(name: &str) -> String {
+    format!("{}{}", smolvm_protocol::VOLUME_TAG_PREFIX, name)
+}
+
 /// TCP port mapping from host to guest.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub struct PortMapping {
@@ -67,6 +88,42 @@ pub struct VmResources {
     pub storage_gb: Option<u64>,
     /// Overlay disk size in GiB (None = default 2 GiB).
     pub overlay_gb: Option<u64>,
+    /// Scratch disk size in GiB for container overlay upper layers (None =
+    /// no scratch disk; they stay on the storage disk).
+    pub scratch_gb: Option<u64>,
+    /// DNS server for containers, in place of the host's resolvers (the
+    /// `dns` of [`NetworkPolicy::Egress`](crate::vm::config::NetworkPolicy)).
+    #[serde(default)]
+    pub dns: Option<std::net::IpAddr>,
+    /// DNS server for containers when the host has no usable resolvers
+    /// (None = [`DEFAULT_DNS_ADDR`](crate::network::DEFAULT_DNS_ADDR)).
+    #[serde(default)]
+    pub fallback_dns: Option<std::net::IpAddr>,
+    /// Hardlink identical files across the image layers the agent extracts
+    /// (the `layer_dedup` setting).
+    #[serde(default)]
+    pub layer_dedup: bool,
+}
+
+impl VmResources {
+    /// The network policy these resources describe.
+    pub fn network_policy(&self) -> crate::vm::config::NetworkPolicy {
+        if self.network {
+            crate::vm::config::NetworkPolicy::Egress { dns: self.dns }
+        } else {
+            crate::vm::config::NetworkPolicy::None
+        }
+    }
+
+    /// The DNS configuration containers should use, or `None` without
+    /// network access.
+    pub fn guest_dns(&self) -> Option<smolvm_protocol::dns::DnsConfig> {
+        crate::network::policy_dns(
+            &self.network_policy(),
+            self.fallback_dns
+                .unwrap_or(crate::network::DEFAULT_DNS_ADDR),
+        )
+    }
 }
 
 impl Default for VmResources {
@@ -77,6 +134,10 @@ impl Default for VmResources {
             network: false,
             storage_gb: None,
             overlay_gb: None,
+            scratch_gb: None,
+            dns: None,
+            fallback_dns: None,
+            layer_dedup: false,
         }
     }
 }
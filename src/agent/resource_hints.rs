@@ -0,0 +1,263 @@
+//! VM resources suggested by image labels.
+//!
+//! An image can suggest the resources it needs with the [`CPUS_LABEL`] and
+//! [`MEMORY_LABEL`] config labels, so a memory-hungry image doesn't OOM at
+//! the default size and a tiny one isn't over-allocated. Precedence is an
+//! explicit flag, then the label, then the built-in default; see
+//! [`resolve`].
+
+use crate::error::{Error, Result};
+use crate::vm::config::MIN_MEMORY_MIB;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// Label suggesting the number of vCPUs.
+pub const CPUS_LABEL: &str = "org.smolvm.cpus";
+
+/// Label suggesting the VM memory in MiB.
+pub const MEMORY_LABEL: &str = "org.smolvm.memory-mib";
+
+/// vCPU counts accepted from [`CPUS_LABEL`].
+pub const CPUS_HINT_RANGE: RangeInclusive<u8> = 1..=64;
+
+/// Memory sizes in MiB accepted from [`MEMORY_LABEL`].
+pub const MEMORY_HINT_RANGE: RangeInclusive<u32> = MIN_MEMORY_MIB..=64 * 1024;
+
+/// Resources an image's labels suggest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceHints {
+    /// Suggested vCPU count.
+    pub cpus: Option<u8>,
+    /// Suggested memory in MiB.
+    pub mem: Option<u32>,
+}
+
+impl ResourceHints {
+    /// Read the hints from image labels.
+    ///
+    /// A missing label gives no hint; one that isn't a number within
+    /// [`CPUS_HINT_RANGE`] / [`MEMORY_HINT_RANGE`] is an error.
+    pub fn from_labels(labels: &BTreeMap<String, String>) -> Result<Self> {
+        Ok(Self {
+            cpus: parse_label(labels, CPUS_LABEL, CPUS_HINT_RANGE)?,
+            mem: parse_label(labels, MEMORY_LABEL, MEMORY_HINT_RANGE)?,
+        })
+    }
+}
+
+fn parse_label<T>(
+    labels: &BTreeMap<String, String>,
+    label: &str,
+    range: RangeInclusive<T>,
+) -> Result<Option<T>>
+where
+    T: FromStr + PartialOrd + fmt::Display,
+{
+    let Some(value) = labels.get(label) else {
+        return Ok(None);
+    };
+    match value.trim().parse::<T>() {
+        Ok(n) if range.contains(&n) => Ok(Some(n)),
+        _ => Err(Error::config(
+            "read image resource labels",
+            format!(
+                "label {}={:?} must be a number from {} to {}",
+                label,
+                value,
+                range.start(),
+                range.end()
+            ),
+        )),
+    }
+}
+
+/// Where a resolved resource value came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceSource {
+    /// Given explicitly (`--cpus`, `--mem`).
+    Flag,
+    /// Suggested by an image label.
+    Label,
+    /// The built-in default.
+    Default,
+}
+
+impl fmt::Display for ResourceSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Flag => "flag",
+            Self::Label => "image label",
+            Self::Default => "default",
+        })
+    }
+}
+
+/// Pick a resource value: the explicit `flag` if given, else the image's
+/// `hint`, else `default`.
+pub fn resolve<T>(flag: Option<T>, hint: Option<T>, default: T) -> (T, ResourceSource) {
+    match (flag, hint) {
+        (Some(value), _) => (value, ResourceSource::Flag),
+        (None, Some(value)) => (value, ResourceSource::Label),
+        (None, None) => (default, ResourceSource::Default),
+    }
+}
+
+/// Resolve a VM's vCPUs and memory: the explicit `cpus`/`mem` if given,
+/// else the hints in the image's `labels`, else the defaults.
+///
+/// The labels are only read if a value is left to fill, so a bad label
+/// doesn't fail a run that sets both explicitly.
+pub fn resolve_resources(
+    cpus: Option<u8>,
+    mem: Option<u32>,
+    labels: &BTreeMap<String, String>,
+    default_cpus: u8,
+    default_mem: u32,
+) -> Result<(u8, u32)> {
+    let hints = if cpus.is_some() && mem.is_some() {
+        ResourceHints::default()
+    } else {
+        ResourceHints::from_labels(labels)?
+    };
+    let (cpus, cpus_source) = resolve(cpus, hints.cpus, default_cpus);
+    let (mem, mem_source) = resolve(mem, hints.mem, default_mem);
+    tracing::debug!(
+        cpus,
+        %cpus_source,
+        mem,
+        %mem_source,
+        "resolved VM resources"
+    );
+    Ok((cpus, mem))
+}
+
+/// Pick the vCPUs and memory to start a VM at before the image's labels
+/// are known: the explicit `cpus`/`mem` if given, else the `running` VM's
+/// sizes, else the defaults.
+///
+/// Keeping the running sizes means a VM already sized for a labeled image
+/// is reused rather than restarted at the defaults and then resized back
+/// once [`resolve_resources`] has read the labels.
+pub fn start_resources(
+    cpus: Option<u8>,
+    mem: Option<u32>,
+    running: Option<(u8, u32)>,
+    default_cpus: u8,
+    default_mem: u32,
+) -> (u8, u32) {
+    let (running_cpus, running_mem) = running.unzip();
+    (
+        cpus.or(running_cpus).unwrap_or(default_cpus),
+        mem.or(running_mem).unwrap_or(default_mem),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_hints_from_labels() {
+        let hints = ResourceHints::from_labels(&labels(&[
+            (CPUS_LABEL, "4"),
+            (MEMORY_LABEL, " 2048 "),
+            ("org.opencontainers.image.title", "app"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            hints,
+            ResourceHints {
+                cpus: Some(4),
+                mem: Some(2048)
+            }
+        );
+
+        let hints = ResourceHints::from_labels(&labels(&[(MEMORY_LABEL, "256")])).unwrap();
+        assert_eq!(hints.cpus, None);
+        assert_eq!(hints.mem, Some(256));
+        assert_eq!(
+            ResourceHints::from_labels(&BTreeMap::new()).unwrap(),
+            ResourceHints::default()
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_labels_are_rejected() {
+        for (label, value) in [
+            (CPUS_LABEL, "0"),
+            (CPUS_LABEL, "65"),
+            (CPUS_LABEL, "-1"),
+            (CPUS_LABEL, "two"),
+            (MEMORY_LABEL, "64"),
+            (MEMORY_LABEL, "1000000"),
+            (MEMORY_LABEL, "1g"),
+            (MEMORY_LABEL, ""),
+        ] {
+            let err = ResourceHints::from_labels(&labels(&[(label, value)]))
+                .expect_err(&format!("{}={:?} accepted", label, value));
+            assert!(err.to_string().contains(label), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_flag_beats_label_beats_default() {
+        assert_eq!(resolve(Some(2), Some(4), 1), (2, ResourceSource::Flag));
+        assert_eq!(resolve(None, Some(4), 1), (4, ResourceSource::Label));
+        assert_eq!(resolve(None, None, 1), (1, ResourceSource::Default));
+        // A flag equal to the default still counts as explicit
+        assert_eq!(resolve(Some(1), Some(4), 1), (1, ResourceSource::Flag));
+    }
+
+    #[test]
+    fn test_resolve_resources() {
+        let hinted = labels(&[(CPUS_LABEL, "4"), (MEMORY_LABEL, "2048")]);
+        assert_eq!(
+            resolve_resources(None, None, &hinted, 1, 512).unwrap(),
+            (4, 2048)
+        );
+        assert_eq!(
+            resolve_resources(Some(2), None, &hinted, 1, 512).unwrap(),
+            (2, 2048)
+        );
+        assert_eq!(
+            resolve_resources(None, None, &BTreeMap::new(), 1, 512).unwrap(),
+            (1, 512)
+        );
+
+        // Bad labels only matter if they would be used
+        let bad = labels(&[(CPUS_LABEL, "0")]);
+        assert!(resolve_resources(None, Some(256), &bad, 1, 512).is_err());
+        assert_eq!(
+            resolve_resources(Some(2), Some(256), &bad, 1, 512).unwrap(),
+            (2, 256)
+        );
+    }
+
+    #[test]
+    fn test_vm_at_label_size_is_kept() {
+        let hinted = labels(&[(CPUS_LABEL, "4"), (MEMORY_LABEL, "2048")]);
+        let start = start_resources(None, None, Some((4, 2048)), 1, 512);
+        assert_eq!(start, (4, 2048));
+        // Resolving the labels afterwards asks for the size already running
+        assert_eq!(
+            resolve_resources(None, None, &hinted, 1, 512).unwrap(),
+            start
+        );
+
+        // Explicit values still win over the running VM's
+        assert_eq!(
+            start_resources(Some(2), None, Some((4, 2048)), 1, 512),
+            (2, 2048)
+        );
+        assert_eq!(start_resources(None, None, None, 1, 512), (1, 512));
+    }
+}
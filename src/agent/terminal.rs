@@ -5,7 +5,7 @@
 
 use std::io;
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 
 /// Atomic flag set by the SIGWINCH signal handler.
 static SIGWINCH_RECEIVED: AtomicBool = AtomicBool::new(false);
@@ -31,6 +31,55 @@ pub fn check_sigwinch() -> bool {
     SIGWINCH_RECEIVED.swap(false, Ordering::Relaxed)
 }
 
+/// Last signal caught by the forwarding handler, or 0 if none is pending.
+static FORWARDED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Signals relayed to the guest command instead of terminating the CLI.
+const FORWARDED_SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+/// RAII guard that catches SIGINT and SIGTERM for forwarding to the guest.
+///
+/// While the guard is alive these signals no longer terminate the CLI;
+/// they are recorded for [`take_forwarded_signal`] instead. The previous
+/// handlers are restored on drop.
+pub struct SignalForwardGuard {
+    previous: [libc::sighandler_t; 2],
+}
+
+impl SignalForwardGuard {
+    /// Install the forwarding handlers.
+    pub fn install() -> Self {
+        extern "C" fn handler(signal: libc::c_int) {
+            FORWARDED_SIGNAL.store(signal, Ordering::Relaxed);
+        }
+        FORWARDED_SIGNAL.store(0, Ordering::Relaxed);
+        // SAFETY: handler only touches an atomic — async-signal-safe.
+        let previous = FORWARDED_SIGNALS.map(|signal| unsafe {
+            libc::signal(signal, handler as *const () as libc::sighandler_t)
+        });
+        Self { previous }
+    }
+}
+
+impl Drop for SignalForwardGuard {
+    fn drop(&mut self) {
+        for (signal, previous) in FORWARDED_SIGNALS.iter().zip(self.previous) {
+            // SAFETY: restores the handler that was installed before.
+            unsafe {
+                libc::signal(*signal, previous);
+            }
+        }
+    }
+}
+
+/// Take the signal caught since the last call, if any.
+pub fn take_forwarded_signal() -> Option<i32> {
+    match FORWARDED_SIGNAL.swap(0, Ordering::Relaxed) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
 /// RAII guard for terminal raw mode.
 ///
 /// Saves the original terminal settings and restores them on drop,
@@ -243,6 +292,26 @@ mod tests {
         let _ = stdin_is_tty();
     }
 
+    #[test]
+    fn test_signal_forward_guard_catches_sigterm() {
+        let guard = SignalForwardGuard::install();
+        // SAFETY: raising a signal at ourselves with a handler installed
+        unsafe {
+            libc::raise(libc::SIGTERM);
+        }
+        assert_eq!(take_forwarded_signal(), Some(libc::SIGTERM));
+        assert_eq!(take_forwarded_signal(), None);
+        drop(guard);
+
+        // SAFETY: querying the current handler by setting and restoring it
+        let restored = unsafe {
+            let current = libc::signal(libc::SIGTERM, libc::SIG_DFL);
+            libc::signal(libc::SIGTERM, current);
+            current
+        };
+        assert_eq!(restored, libc::SIG_DFL);
+    }
+
     #[test]
     fn test_get_terminal_size_returns_option() {
         // Just verify it doesn't panic
@@ -0,0 +1,256 @@
+//! Client for the workload protocol.
+//!
+//! The agent serves the [`HostMessage`]/[`GuestMessage`] protocol on
+//! [`ports::WORKLOAD_CONTROL`](smolvm_protocol::ports::WORKLOAD_CONTROL)
+//! when the VM is launched with [`WORKLOAD_TOKEN_ENV`] set. The launcher
+//! forwards the token and exposes the port at [`workload_socket_path`].
+
+use crate::error::{Error, ErrorKind, Result};
+use smolvm_protocol::workload::{read_message, write_message};
+use smolvm_protocol::{GuestMessage, HostMessage, PROTOCOL_VERSION};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub use smolvm_protocol::workload::WORKLOAD_TOKEN_ENV;
+
+/// How long to wait for the agent to answer authentication.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Path of the workload socket for the VM whose agent socket is
+/// `agent_socket`.
+pub fn workload_socket_path(agent_socket: &Path) -> PathBuf {
+    agent_socket.with_file_name("workload.sock")
+}
+
+/// Output of a command run through the workload protocol.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkloadOutput {
+    /// Exit code of the command.
+    pub exit_code: i32,
+    /// Captured standard output.
+    pub stdout: Vec<u8>,
+    /// Captured standard error.
+    pub stderr: Vec<u8>,
+    /// Either stream exceeded the agent's capture limit and was cut off.
+    pub truncated: bool,
+    /// How the command ended, e.g. "exited" or "killed by signal 9".
+    pub reason: String,
+}
+
+/// Authenticated workload protocol connection.
+pub struct WorkloadClient {
+    stream: UnixStream,
+    next_request_id: u64,
+}
+
+impl WorkloadClient {
+    /// Connect to the workload socket at `path` and authenticate with
+    /// `token`, returning once the agent reports it is ready.
+    pub fn connect(path: impl AsRef<Path>, token: &str) -> Result<Self> {
+        let stream = UnixStream::connect(path.as_ref())
+            .map_err(|e| Error::agent_io("connect to workload socket", &e))?;
+        Self::authenticate(stream, token)
+    }
+
+    /// Authenticate on an already connected stream.
+    fn authenticate(stream: UnixStream, token: &str) -> Result<Self> {
+        stream
+            .set_read_timeout(Some(AUTH_TIMEOUT))
+            .map_err(|e| Error::agent_io("set read timeout", &e))?;
+        let mut client = Self {
+            stream,
+            next_request_id: 1,
+        };
+        client.send(&HostMessage::Auth {
+            token: token.to_string(),
+            protocol_version: PROTOCOL_VERSION,
+        })?;
+        match client.recv("authenticate")? {
+            GuestMessage::AuthOk => {}
+            GuestMessage::AuthFailed => {
+                return Err(Error::agent("authenticate", "workload token rejected"))
+            }
+            other => return Err(unexpected("authenticate", &other)),
+        }
+        match client.recv("authenticate")? {
+            GuestMessage::Ready => {}
+            other => return Err(unexpected("authenticate", &other)),
+        }
+        client
+            .stream
+            .set_read_timeout(None)
+            .map_err(|e| Error::agent_io("set read timeout", &e))?;
+        Ok(client)
+    }
+
+    /// Run `command` to completion and collect its output.
+    pub fn run(
+        &mut self,
+        command: Vec<String>,
+        env: Vec<(String, String)>,
+        workdir: Option<String>,
+    ) -> Result<WorkloadOutput> {
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        self.send(&HostMessage::Run {
+            request_id,
+            command,
+            env,
+            workdir,
+        })?;
+
+        let mut output = WorkloadOutput::default();
+        loop {
+            match self.recv("run")? {
+                GuestMessage::Started { request_id: id } if id == request_id => {}
+                GuestMessage::Stdout {
+                    request_id: id,
+                    data,
+                    truncated,
+                } if id == request_id => {
+                    output.stdout.extend(data);
+                    output.truncated |= truncated;
+                }
+                GuestMessage::Stderr {
+                    request_id: id,
+                    data,
+                    truncated,
+                } if id == request_id => {
+                    output.stderr.extend(data);
+                    output.truncated |= truncated;
+                }
+                GuestMessage::Exit {
+                    request_id: id,
+                    code,
+                    reason,
+                } if id == request_id => {
+                    output.exit_code = code;
+                    output.reason = reason;
+                    return Ok(output);
+                }
+                GuestMessage::Error { message, .. } => return Err(Error::agent("run", message)),
+                other => return Err(unexpected("run", &other)),
+            }
+        }
+    }
+
+    /// Ask the agent to stop the VM's processes, giving them `timeout` to
+    /// exit after SIGTERM. The agent closes the connection.
+    pub fn stop(mut self, timeout: Duration) -> Result<()> {
+        self.send(&HostMessage::Stop {
+            timeout_ms: timeout.as_millis() as u64,
+        })?;
+        match read_message::<GuestMessage>(&mut self.stream) {
+            Ok(None) => Ok(()),
+            Ok(Some(GuestMessage::Error { message, .. })) => Err(Error::agent("stop", message)),
+            Ok(Some(other)) => Err(unexpected("stop", &other)),
+            Err(e) => Err(Error::agent_io("stop", &e)),
+        }
+    }
+
+    fn send(&mut self, message: &HostMessage) -> Result<()> {
+        write_message(&mut self.stream, message).map_err(|e| Error::agent_io("send message", &e))
+    }
+
+    fn recv(&mut self, operation: &str) -> Result<GuestMessage> {
+        match read_message(&mut self.stream) {
+            Ok(Some(message)) => Ok(message),
+            Ok(None) => Err(Error::agent(operation, "workload connection closed")),
+            Err(e) => Err(Error::agent_io(operation, &e)),
+        }
+    }
+}
+
+fn unexpected(operation: &str, message: &GuestMessage) -> Error {
+    Error::agent_with_kind(
+        ErrorKind::Protocol,
+        operation,
+        format!("unexpected workload message: {:?}", message),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smolvm_protocol::workload::WorkloadSession;
+    use smolvm_protocol::{AgentRequest, AgentResponse, ExitReason};
+
+    /// Serve `stream` like the agent does, running commands with `dispatch`.
+    fn serve(
+        mut stream: UnixStream,
+        dispatch: fn(AgentRequest) -> AgentResponse,
+    ) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            let mut session = WorkloadSession::new("token");
+            while let Some(message) = read_message::<HostMessage>(&mut stream).unwrap() {
+                let reply = session.handle(message, dispatch);
+                for message in &reply.messages {
+                    write_message(&mut stream, message).unwrap();
+                }
+                if reply.close {
+                    break;
+                }
+            }
+        })
+    }
+
+    fn echo(request: AgentRequest) -> AgentResponse {
+        match request {
+            AgentRequest::VmExec { command, .. } => AgentResponse::Completed {
+                exit_code: 2,
+                stdout: command.join(" "),
+                stderr: "warning".into(),
+                stdout_truncated: false,
+                stderr_truncated: false,
+                reason: ExitReason::Exited,
+            },
+            AgentRequest::Stop { .. } => AgentResponse::Ok { data: None },
+            other => panic!("unexpected request {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_client_auth_run_stop() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let server = serve(server, echo);
+
+        let mut client = WorkloadClient::authenticate(client, "token").unwrap();
+        for _ in 0..2 {
+            let output = client
+                .run(vec!["echo".into(), "hi".into()], Vec::new(), None)
+                .unwrap();
+            assert_eq!(
+                output,
+                WorkloadOutput {
+                    exit_code: 2,
+                    stdout: b"echo hi".to_vec(),
+                    stderr: b"warning".to_vec(),
+                    truncated: false,
+                    reason: "exited".into(),
+                }
+            );
+        }
+        client.stop(Duration::from_secs(1)).unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_client_rejected_token() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let server = serve(server, echo);
+        let err = WorkloadClient::authenticate(client, "wrong")
+            .err()
+            .expect("wrong token accepted");
+        assert!(err.to_string().contains("rejected"), "{}", err);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_workload_socket_path() {
+        assert_eq!(
+            workload_socket_path(Path::new("/run/vm/agent.sock")),
+            Path::new("/run/vm/workload.sock")
+        );
+    }
+}
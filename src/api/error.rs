@@ -21,6 +21,8 @@ pub enum ApiError {
     Timeout,
     /// Internal server error (500).
     Internal(String),
+    /// Service unavailable, e.g. the server is shutting down (503).
+    Unavailable(String),
 }
 
 impl ApiError {
@@ -35,6 +37,19 @@ impl ApiError {
     }
 }
 
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::NotFound(msg)
+            | ApiError::Conflict(msg)
+            | ApiError::BadRequest(msg)
+            | ApiError::Internal(msg)
+            | ApiError::Unavailable(msg) => f.write_str(msg),
+            ApiError::Timeout => f.write_str("request timed out"),
+        }
+    }
+}
+
 /// JSON error response body.
 #[derive(Serialize)]
 struct ErrorResponse {
@@ -54,6 +69,7 @@ impl IntoResponse for ApiError {
                 "request timed out".to_string(),
             ),
             ApiError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR", msg),
+            ApiError::Unavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, "UNAVAILABLE", msg),
         };
 
         let body = Json(ErrorResponse {
@@ -77,9 +93,19 @@ impl From<crate::error::Error> for ApiError {
             )),
             // Handle structured Agent errors using kind for HTTP status mapping
             crate::error::Error::Agent { reason, kind, .. } => match kind {
-                crate::error::AgentErrorKind::NotFound => ApiError::NotFound(reason.clone()),
-                crate::error::AgentErrorKind::Conflict => ApiError::Conflict(reason.clone()),
-                crate::error::AgentErrorKind::Other => ApiError::Internal(reason.clone()),
+                crate::error::ErrorKind::NotFound => ApiError::NotFound(reason.clone()),
+                crate::error::ErrorKind::Conflict => ApiError::Conflict(reason.clone()),
+                _ => ApiError::Internal(reason.clone()),
+            },
+            crate::error::Error::PlatformUnavailable { .. } => {
+                ApiError::BadRequest(err.to_string())
+            }
+            crate::error::Error::Protocol { code, message, .. } => match code {
+                crate::error::ProtocolErrorCode::NotFound => ApiError::NotFound(message.clone()),
+                crate::error::ProtocolErrorCode::InvalidRequest => {
+                    ApiError::BadRequest(message.clone())
+                }
+                _ => ApiError::Internal(message.clone()),
             },
             _ => ApiError::Internal(err.to_string()),
         }
@@ -94,6 +120,7 @@ pub fn classify_ensure_running_error(err: crate::Error) -> ApiError {
     match &err {
         crate::Error::Mount { .. }
         | crate::Error::InvalidMountPath { .. }
+        | crate::Error::InvalidMountSpec { .. }
         | crate::Error::MountSourceNotFound { .. } => {
             ApiError::BadRequest(format!("mount validation failed: {}", err))
         }
@@ -123,6 +150,10 @@ mod tests {
                 ApiError::Internal("x".into()),
                 StatusCode::INTERNAL_SERVER_ERROR,
             ),
+            (
+                ApiError::Unavailable("x".into()),
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
         ];
         for (error, expected) in cases {
             assert_eq!(error.into_response().status(), expected);
@@ -143,4 +174,18 @@ mod tests {
         let err = crate::error::Error::agent("connect", "connection refused");
         assert!(matches!(ApiError::from(err), ApiError::Internal(_)));
     }
+
+    #[test]
+    fn test_protocol_error_code_mapping() {
+        use crate::error::{Error, ProtocolErrorCode};
+
+        let err = Error::protocol("query", ProtocolErrorCode::NotFound, "no such image");
+        assert!(matches!(ApiError::from(err), ApiError::NotFound(_)));
+
+        let err = Error::protocol("run", ProtocolErrorCode::InvalidRequest, "bad payload");
+        assert!(matches!(ApiError::from(err), ApiError::BadRequest(_)));
+
+        let err = Error::protocol("pull", ProtocolErrorCode::PullFailed, "unauthorized");
+        assert!(matches!(ApiError::from(err), ApiError::Internal(_)));
+    }
 }
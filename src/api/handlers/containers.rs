@@ -1,21 +1,26 @@
 //! Container management handlers.
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
     Json,
 };
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::agent::CreateContainerConfig;
 use crate::api::error::{classify_ensure_running_error, ApiError};
 use crate::api::state::{ensure_running_and_persist, with_sandbox_client, ApiState};
 use crate::api::types::{
     ApiErrorResponse, ContainerExecRequest, ContainerInfo, CreateContainerRequest,
-    DeleteContainerRequest, DeleteResponse, EnvVar, ExecResponse, ListContainersResponse,
-    StartResponse, StopContainerRequest, StopResponse,
+    DeleteContainerRequest, DeleteResponse, EnvVar, ExecResponse, LabelQuery,
+    ListContainersResponse, StartResponse, StopContainerRequest, StopResponse,
 };
 use crate::api::validation::validate_command;
+use crate::labels::{labels_match, parse_label_filters, validate_labels};
 use crate::DEFAULT_IDLE_CMD;
+use smolvm_protocol::{ContainerStatus, HealthCheck, RestartPolicy};
 
 /// Create a container in a sandbox.
 #[utoipa::path(
@@ -23,24 +28,51 @@ use crate::DEFAULT_IDLE_CMD;
     path = "/api/v1/sandboxes/{id}/containers",
     tag = "Containers",
     params(
-        ("id" = String, Path, description = "Sandbox name")
+        ("id" = String, Path, description = "Sandbox name"),
+        ("Idempotency-Key" = Option<String>, Header, description = "Replay the original response for retries with the same key")
     ),
     request_body = CreateContainerRequest,
     responses(
         (status = 200, description = "Container created", body = ContainerInfo),
         (status = 404, description = "Sandbox not found", body = ApiErrorResponse),
+        (status = 409, description = "Idempotency key reused with a different body", body = ApiErrorResponse),
         (status = 500, description = "Failed to create container", body = ApiErrorResponse)
     )
 )]
 pub async fn create_container(
     State(state): State<Arc<ApiState>>,
     Path(sandbox_id): Path<String>,
+    headers: HeaderMap,
     Json(req): Json<CreateContainerRequest>,
+) -> Result<Response, ApiError> {
+    let scope = format!("POST /sandboxes/{}/containers", sandbox_id);
+    state
+        .idempotency()
+        .run(&scope, &headers, &req, || {
+            create_container_inner(&state, sandbox_id.clone(), req.clone())
+        })
+        .await
+}
+
+async fn create_container_inner(
+    state: &Arc<ApiState>,
+    sandbox_id: String,
+    req: CreateContainerRequest,
 ) -> Result<Json<ContainerInfo>, ApiError> {
+    validate_labels(&req.labels).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let healthcheck = req.healthcheck.as_ref().map(|spec| HealthCheck {
+        interval_ms: spec.interval_ms.unwrap_or(HealthCheck::DEFAULT_INTERVAL_MS),
+        timeout_ms: spec.timeout_ms.unwrap_or(HealthCheck::DEFAULT_TIMEOUT_MS),
+        retries: spec.retries.unwrap_or(HealthCheck::DEFAULT_RETRIES),
+        ..HealthCheck::new(spec.command.clone())
+    });
+    if let Some(check) = &healthcheck {
+        check.validate().map_err(ApiError::BadRequest)?;
+    }
     let entry = state.get_sandbox(&sandbox_id)?;
 
     // Ensure sandbox is running and persist state to DB
-    ensure_running_and_persist(&state, &sandbox_id, &entry)
+    ensure_running_and_persist(state, &sandbox_id, &entry)
         .await
         .map_err(classify_ensure_running_error)?;
 
@@ -53,24 +85,34 @@ pub async fn create_container(
     };
     let env = EnvVar::to_tuples(&req.env);
     let workdir = req.workdir.clone();
+    let restart_policy = req
+        .restart_policy
+        .as_deref()
+        .map(str::parse::<RestartPolicy>)
+        .transpose()
+        .map_err(ApiError::BadRequest)?
+        .unwrap_or_default();
     let mounts: Vec<(String, String, bool)> = req
         .mounts
         .iter()
         .map(|m| (m.source.clone(), m.target.clone(), m.readonly))
         .collect();
+    let labels = req.labels.clone();
 
-    let container_info = with_sandbox_client(&entry, move |c| {
-        c.create_container(&image, command, env, workdir, mounts)
+    let container_info = with_sandbox_client(state, &entry, move |c| {
+        c.create_container(
+            CreateContainerConfig::new(image, command)
+                .with_env(env)
+                .with_workdir(workdir)
+                .with_mounts(mounts)
+                .with_restart_policy(restart_policy)
+                .with_labels(labels)
+                .with_healthcheck(healthcheck),
+        )
     })
     .await?;
 
-    Ok(Json(ContainerInfo {
-        id: container_info.id,
-        image: container_info.image,
-        state: container_info.state,
-        created_at: container_info.created_at,
-        command: container_info.command,
-    }))
+    Ok(Json(api_container_info(container_info)))
 }
 
 /// List containers in a sandbox.
@@ -79,17 +121,22 @@ pub async fn create_container(
     path = "/api/v1/sandboxes/{id}/containers",
     tag = "Containers",
     params(
-        ("id" = String, Path, description = "Sandbox name")
+        ("id" = String, Path, description = "Sandbox name"),
+        ("label" = Option<String>, Query, description = "Comma-separated label filters, each `key` or `key=value`")
     ),
     responses(
         (status = 200, description = "List of containers", body = ListContainersResponse),
+        (status = 400, description = "Invalid filter", body = ApiErrorResponse),
         (status = 404, description = "Sandbox not found", body = ApiErrorResponse)
     )
 )]
 pub async fn list_containers(
     State(state): State<Arc<ApiState>>,
     Path(sandbox_id): Path<String>,
+    Query(query): Query<LabelQuery>,
 ) -> Result<Json<ListContainersResponse>, ApiError> {
+    let filters = parse_label_filters(query.label.as_deref().unwrap_or_default())
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
     let entry = state.get_sandbox(&sandbox_id)?;
 
     // Check if sandbox VM is actually alive, return empty list if not
@@ -102,22 +149,41 @@ pub async fn list_containers(
         }
     }
 
-    let containers = with_sandbox_client(&entry, |c| c.list_containers()).await?;
+    let containers = with_sandbox_client(&state, &entry, |c| c.list_containers()).await?;
 
     let containers = containers
         .into_iter()
-        .map(|c| ContainerInfo {
-            id: c.id,
-            image: c.image,
-            state: c.state,
-            created_at: c.created_at,
-            command: c.command,
-        })
+        .filter(|c| labels_match(&c.labels, &filters))
+        .map(api_container_info)
         .collect();
 
     Ok(Json(ListContainersResponse { containers }))
 }
 
+/// Convert the agent's container info to the API's.
+fn api_container_info(c: smolvm_protocol::ContainerInfo) -> ContainerInfo {
+    let (exit_code, error) = match &c.status {
+        ContainerStatus::Exited { code } => (*code, None),
+        ContainerStatus::Error { message } => (None, Some(message.clone())),
+        _ => (None, None),
+    };
+    ContainerInfo {
+        status: c.status.name().to_string(),
+        exit_code,
+        error,
+        id: c.id,
+        image: c.image,
+        state: c.state,
+        created_at: c.created_at,
+        command: c.command,
+        restart_count: c.restart_count,
+        started_at: c.started_at,
+        finished_at: c.finished_at,
+        labels: c.labels,
+        health: c.health.map(|h| h.to_string()),
+    }
+}
+
 /// Start a container.
 #[utoipa::path(
     post,
@@ -140,7 +206,7 @@ pub async fn start_container(
     let entry = state.get_sandbox(&sandbox_id)?;
 
     let container_id_response = container_id.clone();
-    with_sandbox_client(&entry, move |c| c.start_container(&container_id)).await?;
+    with_sandbox_client(&state, &entry, move |c| c.start_container(&container_id)).await?;
     Ok(Json(StartResponse {
         started: container_id_response,
     }))
@@ -172,7 +238,7 @@ pub async fn stop_container(
     let timeout_secs = req.timeout_secs;
 
     let container_id_response = container_id.clone();
-    with_sandbox_client(&entry, move |c| {
+    with_sandbox_client(&state, &entry, move |c| {
         c.stop_container(&container_id, timeout_secs)
     })
     .await?;
@@ -207,7 +273,10 @@ pub async fn delete_container(
     let force = req.force;
 
     let container_id_response = container_id.clone();
-    with_sandbox_client(&entry, move |c| c.delete_container(&container_id, force)).await?;
+    with_sandbox_client(&state, &entry, move |c| {
+        c.delete_container(&container_id, force)
+    })
+    .await?;
     Ok(Json(DeleteResponse {
         deleted: container_id_response,
     }))
@@ -245,7 +314,7 @@ pub async fn exec_in_container(
     let workdir = req.workdir.clone();
     let timeout = req.timeout_secs.map(Duration::from_secs);
 
-    let (exit_code, stdout, stderr) = with_sandbox_client(&entry, move |c| {
+    let (exit_code, stdout, stderr) = with_sandbox_client(&state, &entry, move |c| {
         c.exec(&container_id, command, env, workdir, timeout)
     })
     .await?;
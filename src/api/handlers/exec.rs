@@ -56,8 +56,10 @@ pub async fn exec_command(
     let workdir = req.workdir.clone();
     let timeout = req.timeout_secs.map(Duration::from_secs);
 
-    let (exit_code, stdout, stderr) =
-        with_sandbox_client(&entry, move |c| c.vm_exec(command, env, workdir, timeout)).await?;
+    let (exit_code, stdout, stderr) = with_sandbox_client(&state, &entry, move |c| {
+        c.vm_exec(command, env, workdir, timeout)
+    })
+    .await?;
 
     Ok(Json(ExecResponse {
         exit_code,
@@ -118,7 +120,7 @@ pub async fn run_command(
             .collect::<Vec<_>>()
     };
 
-    let (exit_code, stdout, stderr) = with_sandbox_client(&entry, move |c| {
+    let (exit_code, stdout, stderr) = with_sandbox_client(&state, &entry, move |c| {
         c.run_with_mounts_and_timeout(&image, command, env, workdir, mounts_config, timeout)
     })
     .await?;
@@ -1,17 +1,21 @@
 //! Image management handlers.
 
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     Json,
 };
 use std::sync::Arc;
 
-use crate::agent::PullOptions;
+use crate::agent::{parse_image_timestamp, ImageFilter, PullOptions};
 use crate::api::error::{classify_ensure_running_error, ApiError};
-use crate::api::state::{ensure_running_and_persist, with_sandbox_client, ApiState};
+use crate::api::state::{
+    ensure_running_and_persist, sandbox_async_client, with_sandbox_client, ApiState,
+};
 use crate::api::types::{
-    ApiErrorResponse, ImageInfo, ListImagesResponse, PullImageRequest, PullImageResponse,
+    ApiErrorResponse, ImageInfo, ListImagesQuery, ListImagesResponse, PullImageRequest,
+    PullImageResponse,
 };
+use crate::labels::parse_label_filters;
 
 /// List images in a sandbox.
 #[utoipa::path(
@@ -19,17 +23,23 @@ use crate::api::types::{
     path = "/api/v1/sandboxes/{id}/images",
     tag = "Images",
     params(
-        ("id" = String, Path, description = "Sandbox name")
+        ("id" = String, Path, description = "Sandbox name"),
+        ("created_before" = Option<String>, Query, description = "Only images created before this date (YYYY-MM-DD or RFC 3339)"),
+        ("created_after" = Option<String>, Query, description = "Only images created after this date (YYYY-MM-DD or RFC 3339)"),
+        ("label" = Option<String>, Query, description = "Comma-separated label filters, each `key` or `key=value`")
     ),
     responses(
         (status = 200, description = "List of images", body = ListImagesResponse),
+        (status = 400, description = "Invalid filter", body = ApiErrorResponse),
         (status = 404, description = "Sandbox not found", body = ApiErrorResponse)
     )
 )]
 pub async fn list_images(
     State(state): State<Arc<ApiState>>,
     Path(sandbox_id): Path<String>,
+    Query(query): Query<ListImagesQuery>,
 ) -> Result<Json<ListImagesResponse>, ApiError> {
+    let filter = image_filter(&query)?;
     let entry = state.get_sandbox(&sandbox_id)?;
 
     // Check if sandbox VM is actually alive, return empty list if not
@@ -40,7 +50,8 @@ pub async fn list_images(
         }
     }
 
-    let images = with_sandbox_client(&entry, |c| c.list_images()).await?;
+    let images =
+        with_sandbox_client(&state, &entry, move |c| c.list_images_filtered(&filter)).await?;
 
     let images = images
         .into_iter()
@@ -51,12 +62,31 @@ pub async fn list_images(
             architecture: i.architecture,
             os: i.os,
             layer_count: i.layer_count,
+            tags: i.tags,
         })
         .collect();
 
     Ok(Json(ListImagesResponse { images }))
 }
 
+/// Build an image filter from list query parameters.
+fn image_filter(query: &ListImagesQuery) -> Result<ImageFilter, ApiError> {
+    let bad_request = |e: crate::Error| ApiError::BadRequest(e.to_string());
+    let mut filter = ImageFilter::new();
+    if let Some(date) = &query.created_before {
+        filter = filter.created_before(parse_image_timestamp(date).map_err(bad_request)?);
+    }
+    if let Some(date) = &query.created_after {
+        filter = filter.created_after(parse_image_timestamp(date).map_err(bad_request)?);
+    }
+    if let Some(list) = &query.label {
+        for (key, value) in parse_label_filters(list).map_err(bad_request)? {
+            filter = filter.label(key, value);
+        }
+    }
+    Ok(filter)
+}
+
 /// Pull an image into a sandbox.
 #[utoipa::path(
     post,
@@ -91,16 +121,17 @@ pub async fn pull_image(
         .await
         .map_err(classify_ensure_running_error)?;
 
-    let image = req.image.clone();
-    let oci_platform = req.oci_platform.clone();
-    let image_info = with_sandbox_client(&entry, move |c| {
-        let mut opts = PullOptions::new().use_registry_config(true);
-        if let Some(p) = oci_platform {
-            opts = opts.oci_platform(p);
-        }
-        c.pull(&image, opts)
-    })
-    .await?;
+    let mut opts = PullOptions::new()
+        .use_registry_config(true)
+        .no_cache(req.no_cache);
+    if let Some(p) = req.oci_platform.clone() {
+        opts = opts.oci_platform(p);
+    }
+    let image_info = sandbox_async_client(&entry)
+        .await?
+        .pull(&req.image, opts)
+        .await
+        .map_err(ApiError::internal)?;
 
     Ok(Json(PullImageResponse {
         image: ImageInfo {
@@ -110,6 +141,8 @@ pub async fn pull_image(
             architecture: image_info.architecture,
             os: image_info.os,
             layer_count: image_info.layer_count,
+            tags: image_info.tags,
         },
+        refreshed_layers: image_info.refreshed_layers,
     }))
 }
@@ -159,6 +159,8 @@ pub async fn create_microvm(
     let mut record = VmRecord::new(name.clone(), cpus, mem, mounts, ports, req.network);
     record.storage_gb = req.storage_gb;
     record.overlay_gb = req.overlay_gb;
+    record.scratch_gb = req.scratch_gb;
+    record.dns = req.dns;
 
     // Use atomic insert to detect conflicts
     let db = state.db();
@@ -256,7 +258,17 @@ pub async fn start_microvm(
 
     let mounts = record.host_mounts();
     let ports = record.port_mappings();
-    let resources = record.vm_resources();
+    let mut resources = record.vm_resources();
+    resources.fallback_dns = db
+        .get_config("default_dns")
+        .ok()
+        .flatten()
+        .and_then(|value| crate::config::parse_default_dns(&value));
+    resources.layer_dedup = db
+        .get_config("layer_dedup")
+        .ok()
+        .flatten()
+        .is_some_and(|value| value == "true");
 
     // Start agent VM in blocking task.
     // Child process closes inherited fds, so DB stays open for concurrent requests.
@@ -6,3 +6,4 @@ pub mod health;
 pub mod images;
 pub mod microvms;
 pub mod sandboxes;
+pub mod stats;
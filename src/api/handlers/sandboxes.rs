@@ -2,6 +2,8 @@
 
 use axum::{
     extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Response,
     Json,
 };
 use std::sync::Arc;
@@ -12,11 +14,12 @@ use crate::api::state::{
     ensure_sandbox_running, restart_spec_to_config, ApiState, ReservationGuard, SandboxRegistration,
 };
 use crate::api::types::{
-    ApiErrorResponse, CreateSandboxRequest, DeleteQuery, DeleteResponse, ListSandboxesResponse,
-    MountInfo, MountSpec, ResourceSpec, SandboxInfo,
+    ApiErrorResponse, CreateSandboxRequest, DeleteQuery, DeleteResponse, LabelQuery,
+    ListSandboxesResponse, MountInfo, MountSpec, ResourceSpec, SandboxInfo,
 };
 use crate::api::validation::validate_resource_name;
 use crate::config::RecordState;
+use crate::labels::{labels_match, parse_label_filters, validate_labels};
 
 /// Maximum sandbox name length.
 /// Socket path is ~/Library/Caches/smolvm/vms/{name}/agent.sock — a name
@@ -56,6 +59,7 @@ pub(crate) fn sandbox_entry_to_info(
         } else {
             None
         },
+        labels: entry.labels.clone(),
     }
 }
 
@@ -64,16 +68,32 @@ pub(crate) fn sandbox_entry_to_info(
     post,
     path = "/api/v1/sandboxes",
     tag = "Sandboxes",
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Replay the original response for retries with the same key")
+    ),
     request_body = CreateSandboxRequest,
     responses(
         (status = 200, description = "Sandbox created", body = SandboxInfo),
         (status = 400, description = "Invalid request", body = ApiErrorResponse),
-        (status = 409, description = "Sandbox already exists", body = ApiErrorResponse)
+        (status = 409, description = "Sandbox already exists, or idempotency key reused with a different body", body = ApiErrorResponse)
     )
 )]
 pub async fn create_sandbox(
     State(state): State<Arc<ApiState>>,
+    headers: HeaderMap,
     Json(req): Json<CreateSandboxRequest>,
+) -> Result<Response, ApiError> {
+    state
+        .idempotency()
+        .run("POST /sandboxes", &headers, &req, || {
+            create_sandbox_inner(&state, req.clone())
+        })
+        .await
+}
+
+async fn create_sandbox_inner(
+    state: &Arc<ApiState>,
+    req: CreateSandboxRequest,
 ) -> Result<Json<SandboxInfo>, ApiError> {
     // Validate name format
     validate_resource_name(&req.name, "sandbox", MAX_NAME_LENGTH)?;
@@ -82,6 +102,8 @@ pub async fn create_sandbox(
     let mounts_result: Result<Vec<_>, _> = req.mounts.iter().map(HostMount::try_from).collect();
     mounts_result.map_err(|e| ApiError::BadRequest(e.to_string()))?;
 
+    validate_labels(&req.labels).map_err(|e| ApiError::BadRequest(e.to_string()))?;
+
     let resources = req.resources.clone().unwrap_or(ResourceSpec {
         cpus: None,
         memory_mb: None,
@@ -97,7 +119,7 @@ pub async fn create_sandbox(
     let restart_config = restart_spec_to_config(req.restart.as_ref());
 
     // Reserve name with RAII guard - automatically released on any error or panic
-    let guard = ReservationGuard::new(&state, req.name.clone())?;
+    let guard = ReservationGuard::new(state, req.name.clone())?;
 
     // Create AgentManager in blocking task
     let name = guard.name().to_string();
@@ -127,6 +149,7 @@ pub async fn create_sandbox(
         resources: resources.clone(),
         restart: restart_config,
         network,
+        labels: req.labels.clone(),
     })?;
 
     Ok(Json(SandboxInfo {
@@ -138,6 +161,7 @@ pub async fn create_sandbox(
         resources,
         network,
         restart_count: None,
+        labels: req.labels,
     }))
 }
 
@@ -146,13 +170,26 @@ pub async fn create_sandbox(
     get,
     path = "/api/v1/sandboxes",
     tag = "Sandboxes",
+    params(
+        ("label" = Option<String>, Query, description = "Comma-separated label filters, each `key` or `key=value`")
+    ),
     responses(
-        (status = 200, description = "List of sandboxes", body = ListSandboxesResponse)
+        (status = 200, description = "List of sandboxes", body = ListSandboxesResponse),
+        (status = 400, description = "Invalid filter", body = ApiErrorResponse)
     )
 )]
-pub async fn list_sandboxes(State(state): State<Arc<ApiState>>) -> Json<ListSandboxesResponse> {
-    let sandboxes = state.list_sandboxes();
-    Json(ListSandboxesResponse { sandboxes })
+pub async fn list_sandboxes(
+    State(state): State<Arc<ApiState>>,
+    Query(query): Query<LabelQuery>,
+) -> Result<Json<ListSandboxesResponse>, ApiError> {
+    let filters = parse_label_filters(query.label.as_deref().unwrap_or_default())
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let sandboxes = state
+        .list_sandboxes()
+        .into_iter()
+        .filter(|s| labels_match(&s.labels, &filters))
+        .collect();
+    Ok(Json(ListSandboxesResponse { sandboxes }))
 }
 
 /// Get sandbox status.
@@ -198,13 +235,14 @@ pub async fn start_sandbox(
     let entry = state.get_sandbox(&id)?;
 
     // Snapshot configuration for response
-    let (mounts_spec, ports_spec, resources_spec, network) = {
+    let (mounts_spec, ports_spec, resources_spec, network, labels) = {
         let entry = entry.lock();
         (
             entry.mounts.clone(),
             entry.ports.clone(),
             entry.resources.clone(),
             entry.network,
+            entry.labels.clone(),
         )
     };
 
@@ -241,6 +279,7 @@ pub async fn start_sandbox(
         resources: resources_spec,
         network,
         restart_count: None, // Just reset
+        labels,
     }))
 }
 
@@ -265,7 +304,7 @@ pub async fn stop_sandbox(
     let entry = state.get_sandbox(&id)?;
 
     // Get config for response
-    let (mounts_spec, ports_spec, resources_spec, network, restart_count) = {
+    let (mounts_spec, ports_spec, resources_spec, network, restart_count, labels) = {
         let entry = entry.lock();
         (
             entry.mounts.clone(),
@@ -277,6 +316,7 @@ pub async fn stop_sandbox(
             } else {
                 None
             },
+            entry.labels.clone(),
         )
     };
 
@@ -319,6 +359,7 @@ pub async fn stop_sandbox(
         resources: resources_spec,
         network,
         restart_count,
+        labels,
     }))
 }
 
@@ -0,0 +1,117 @@
+//! Resource usage streaming handler.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use smolvm_protocol::ResourceStats;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::api::error::ApiError;
+use crate::api::state::{with_sandbox_client, ApiState};
+use crate::api::types::{ApiErrorResponse, StatsQuery, StatsSample};
+
+/// Shortest allowed sampling interval.
+const MIN_INTERVAL_MS: u64 = 100;
+
+/// Longest allowed sampling interval.
+const MAX_INTERVAL_MS: u64 = 60_000;
+
+/// Maximum number of concurrent stats SSE streams.
+/// Each stream holds a pooled agent connection per sample, so this caps the
+/// load streams put on the blocking pool and the agents.
+static STATS_STREAM_SEMAPHORE: std::sync::LazyLock<Semaphore> =
+    std::sync::LazyLock::new(|| Semaphore::new(16));
+
+/// Stream sandbox CPU and memory usage via SSE.
+///
+/// Emits a `stats` event with a [`StatsSample`] every `interval_ms` until the
+/// client disconnects. If sampling fails mid-stream, an `error` event is sent
+/// and the stream ends.
+#[utoipa::path(
+    get,
+    path = "/api/v1/sandboxes/{id}/stats",
+    tag = "Stats",
+    params(
+        ("id" = String, Path, description = "Sandbox name"),
+        ("container" = Option<String>, Query, description = "Container to sample instead of the VM"),
+        ("interval_ms" = Option<u64>, Query, description = "Milliseconds between samples (default 2000)")
+    ),
+    responses(
+        (status = 200, description = "Stats stream (SSE)", content_type = "text/event-stream"),
+        (status = 400, description = "Invalid interval", body = ApiErrorResponse),
+        (status = 404, description = "Sandbox not found", body = ApiErrorResponse),
+        (status = 409, description = "Too many concurrent stats streams", body = ApiErrorResponse)
+    )
+)]
+pub async fn stream_stats(
+    State(state): State<Arc<ApiState>>,
+    Path(id): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Sse<impl tokio_stream::Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let entry = state.get_sandbox(&id)?;
+
+    if !(MIN_INTERVAL_MS..=MAX_INTERVAL_MS).contains(&query.interval_ms) {
+        return Err(ApiError::BadRequest(format!(
+            "interval_ms must be between {} and {}",
+            MIN_INTERVAL_MS, MAX_INTERVAL_MS
+        )));
+    }
+    let interval = Duration::from_millis(query.interval_ms);
+
+    let permit = STATS_STREAM_SEMAPHORE
+        .try_acquire()
+        .map_err(|_| ApiError::Conflict("too many concurrent stats streams".into()))?;
+
+    // Take a baseline sample up front so a stopped sandbox or unknown
+    // container fails the request instead of the stream
+    let container = query.container;
+    let c = container.clone();
+    let mut prev =
+        with_sandbox_client(&state, &entry, move |client| client.stats(c.as_deref())).await?;
+
+    let stream = async_stream::stream! {
+        // Hold the permit for the stream's lifetime
+        let _permit = permit;
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let c = container.clone();
+            let result =
+                with_sandbox_client(&state, &entry, move |client| client.stats(c.as_deref())).await;
+            match result {
+                Ok(sample) => {
+                    let event = sample_event(&prev, &sample);
+                    prev = sample;
+                    yield Ok(event);
+                }
+                Err(e) => {
+                    yield Ok(Event::default().event("error").data(e.to_string()));
+                    break;
+                }
+            }
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Build the SSE event for `sample`, with CPU usage relative to `prev`.
+fn sample_event(prev: &ResourceStats, sample: &ResourceStats) -> Event {
+    let body = StatsSample {
+        container_id: sample.container_id.clone(),
+        timestamp_ms: sample.timestamp_ms,
+        cpu_percent: sample.cpu_percent_since(prev),
+        cpu_usage_usec: sample.cpu_usage_usec,
+        online_cpus: sample.online_cpus,
+        memory_bytes: sample.memory_bytes,
+        memory_limit_bytes: sample.memory_limit_bytes,
+        memory_percent: sample.memory_percent(),
+    };
+    let data = serde_json::to_string(&body).unwrap_or_default();
+    Event::default().event("stats").data(data)
+}
@@ -0,0 +1,356 @@
+//! Idempotency keys for creation endpoints.
+//!
+//! A client that retries `POST /sandboxes` or `POST /sandboxes/:id/containers`
+//! after a network timeout can't tell whether the first attempt created the
+//! resource. Sending the same `Idempotency-Key` header on every attempt makes
+//! the retry safe: the first successful response is recorded for
+//! [`IDEMPOTENCY_TTL`] and replayed for repeats instead of creating a second
+//! resource.
+//!
+//! Keys are scoped per endpoint and bound to a hash of the request body, so
+//! reusing a key with a different payload is rejected with 409.
+
+use crate::api::error::ApiError;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+/// Request header carrying the idempotency key.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// How long a recorded response is replayed for.
+pub const IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Maximum idempotency key length.
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Maximum number of recorded keys; the oldest are dropped beyond this.
+const MAX_ENTRIES: usize = 4096;
+
+/// State of a key.
+enum Outcome {
+    /// The first request with this key is still running.
+    Pending,
+    /// The first request succeeded with this JSON body.
+    Done(Vec<u8>),
+}
+
+struct Entry {
+    body_hash: u64,
+    outcome: Outcome,
+    created: Instant,
+}
+
+/// Recorded responses by (endpoint scope, key).
+pub struct IdempotencyCache {
+    entries: Mutex<HashMap<(String, String), Entry>>,
+    ttl: Duration,
+}
+
+impl IdempotencyCache {
+    /// Create a cache replaying responses for `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Run `op` at most once per idempotency key.
+    ///
+    /// Without a key, `op` simply runs. With one, the first successful
+    /// response is recorded and replayed byte-for-byte for later requests
+    /// with the same key and body. A failed `op` records nothing so the
+    /// client can retry. Reusing a key with a different body, or while the
+    /// first request is still running, is a conflict.
+    pub async fn run<T, F, Fut>(
+        &self,
+        scope: &str,
+        headers: &HeaderMap,
+        body: &impl Serialize,
+        op: F,
+    ) -> Result<Response, ApiError>
+    where
+        T: Serialize,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Json<T>, ApiError>>,
+    {
+        let Some(key) = idempotency_key(headers)? else {
+            return op().await.map(IntoResponse::into_response);
+        };
+        let body_hash = hash_body(body)?;
+        let id = (scope.to_string(), key);
+
+        if let Some(recorded) = self.begin(&id, body_hash)? {
+            tracing::debug!(scope, key = %id.1, "replaying idempotent response");
+            return Ok(json_response(recorded));
+        }
+
+        // Clear the pending marker if `op` fails or the request is cancelled
+        let mut pending = PendingGuard {
+            cache: self,
+            id: Some(id),
+        };
+        let Json(value) = op().await?;
+        let bytes = serde_json::to_vec(&value).map_err(ApiError::internal)?;
+        if let Some(id) = pending.id.take() {
+            self.finish(id, body_hash, bytes.clone());
+        }
+        Ok(json_response(bytes))
+    }
+
+    /// Claim `id` for a new request, or return the recorded response.
+    fn begin(&self, id: &(String, String), body_hash: u64) -> Result<Option<Vec<u8>>, ApiError> {
+        let mut entries = self.entries.lock();
+        let now = Instant::now();
+        entries.retain(|_, e| now.duration_since(e.created) < self.ttl);
+
+        if let Some(entry) = entries.get(id) {
+            if entry.body_hash != body_hash {
+                return Err(ApiError::Conflict(format!(
+                    "idempotency key '{}' was already used with a different request body",
+                    id.1
+                )));
+            }
+            return match &entry.outcome {
+                Outcome::Pending => Err(ApiError::Conflict(format!(
+                    "a request with idempotency key '{}' is still in progress",
+                    id.1
+                ))),
+                Outcome::Done(bytes) => Ok(Some(bytes.clone())),
+            };
+        }
+
+        if entries.len() >= MAX_ENTRIES {
+            if let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, e)| e.created)
+                .map(|(k, _)| k.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            id.clone(),
+            Entry {
+                body_hash,
+                outcome: Outcome::Pending,
+                created: now,
+            },
+        );
+        Ok(None)
+    }
+
+    /// Record the successful response for `id`.
+    fn finish(&self, id: (String, String), body_hash: u64, bytes: Vec<u8>) {
+        self.entries.lock().insert(
+            id,
+            Entry {
+                body_hash,
+                outcome: Outcome::Done(bytes),
+                created: Instant::now(),
+            },
+        );
+    }
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(IDEMPOTENCY_TTL)
+    }
+}
+
+/// Removes a pending key unless the request completed.
+struct PendingGuard<'a> {
+    cache: &'a IdempotencyCache,
+    id: Option<(String, String)>,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            self.cache.entries.lock().remove(&id);
+        }
+    }
+}
+
+/// Read and validate the idempotency key header, if present.
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+    let key = value
+        .to_str()
+        .map_err(|_| ApiError::BadRequest("idempotency key must be ASCII".into()))?
+        .trim();
+    if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+        return Err(ApiError::BadRequest(format!(
+            "idempotency key must be 1-{} characters",
+            MAX_KEY_LENGTH
+        )));
+    }
+    Ok(Some(key.to_string()))
+}
+
+/// Hash the request body as canonical JSON.
+fn hash_body(body: &impl Serialize) -> Result<u64, ApiError> {
+    let bytes = serde_json::to_vec(body).map_err(ApiError::internal)?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+fn json_response(bytes: Vec<u8>) -> Response {
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        )],
+        bytes,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn with_key(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, key.parse().unwrap());
+        headers
+    }
+
+    async fn body_of(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    /// Run a "create" that counts invocations and returns the count.
+    async fn create(
+        cache: &IdempotencyCache,
+        scope: &str,
+        headers: &HeaderMap,
+        name: &str,
+        created: &AtomicUsize,
+    ) -> Result<Response, ApiError> {
+        cache
+            .run(
+                scope,
+                headers,
+                &serde_json::json!({ "name": name }),
+                || async {
+                    let n = created.fetch_add(1, Ordering::SeqCst) + 1;
+                    Ok(Json(serde_json::json!({ "name": name, "serial": n })))
+                },
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_same_key_creates_once() {
+        let cache = IdempotencyCache::default();
+        let created = AtomicUsize::new(0);
+        let headers = with_key("retry-1");
+
+        let first = create(&cache, "sandboxes", &headers, "a", &created)
+            .await
+            .unwrap();
+        let second = create(&cache, "sandboxes", &headers, "a", &created)
+            .await
+            .unwrap();
+
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::OK);
+        assert_eq!(body_of(first).await, body_of(second).await);
+    }
+
+    #[tokio::test]
+    async fn test_key_reuse_with_different_body_conflicts() {
+        let cache = IdempotencyCache::default();
+        let created = AtomicUsize::new(0);
+        let headers = with_key("retry-1");
+
+        create(&cache, "sandboxes", &headers, "a", &created)
+            .await
+            .unwrap();
+        let err = create(&cache, "sandboxes", &headers, "b", &created)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::Conflict(_)));
+
+        // The same key on another endpoint is independent
+        create(&cache, "containers", &headers, "b", &created)
+            .await
+            .unwrap();
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_without_key_or_after_failure_runs_again() {
+        let cache = IdempotencyCache::default();
+        let created = AtomicUsize::new(0);
+
+        create(&cache, "sandboxes", &HeaderMap::new(), "a", &created)
+            .await
+            .unwrap();
+        create(&cache, "sandboxes", &HeaderMap::new(), "a", &created)
+            .await
+            .unwrap();
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+
+        // A failed attempt records nothing, so a retry runs
+        let headers = with_key("retry-2");
+        let failed = cache
+            .run("sandboxes", &headers, &"a", || async {
+                Err::<Json<()>, _>(ApiError::Internal("boom".into()))
+            })
+            .await;
+        assert!(failed.is_err());
+        create(&cache, "sandboxes", &headers, "a", &created)
+            .await
+            .unwrap();
+        assert_eq!(created.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_expired_keys_forgotten() {
+        let cache = IdempotencyCache::new(Duration::from_millis(20));
+        let created = AtomicUsize::new(0);
+        let headers = with_key("retry-1");
+
+        create(&cache, "sandboxes", &headers, "a", &created)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        create(&cache, "sandboxes", &headers, "a", &created)
+            .await
+            .unwrap();
+        assert_eq!(created.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_invalid_key_rejected() {
+        assert!(matches!(
+            idempotency_key(&with_key(" ")),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert!(matches!(
+            idempotency_key(&with_key(&"k".repeat(MAX_KEY_LENGTH + 1))),
+            Err(ApiError::BadRequest(_))
+        ));
+        assert_eq!(idempotency_key(&HeaderMap::new()).unwrap(), None);
+    }
+}
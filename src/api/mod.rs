@@ -17,17 +17,24 @@
 
 pub mod error;
 pub mod handlers;
+pub mod idempotency;
+pub mod pool;
 pub mod state;
 pub mod supervisor;
 pub mod types;
 pub mod validation;
 
 use axum::{
+    extract::{Request, State},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
     routing::{delete, get, post},
     Router,
 };
+use std::future::Future;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::net::TcpListener;
 use tower_http::{
     cors::{AllowOrigin, CorsLayer},
     timeout::TimeoutLayer,
@@ -36,6 +43,7 @@ use tower_http::{
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+use error::ApiError;
 use state::ApiState;
 
 /// OpenAPI documentation for the smolvm API.
@@ -52,6 +60,7 @@ use state::ApiState;
         (name = "Sandboxes", description = "Sandbox lifecycle management"),
         (name = "Execution", description = "Command execution in sandboxes"),
         (name = "Logs", description = "Log streaming"),
+        (name = "Stats", description = "Resource usage streaming"),
         (name = "Containers", description = "Container management within sandboxes"),
         (name = "Images", description = "OCI image management"),
         (name = "MicroVMs", description = "Persistent microVM management")
@@ -70,6 +79,8 @@ use state::ApiState;
         handlers::exec::exec_command,
         handlers::exec::run_command,
         handlers::exec::stream_logs,
+        // Stats
+        handlers::stats::stream_stats,
         // Containers
         handlers::containers::create_container,
         handlers::containers::list_containers,
@@ -101,12 +112,16 @@ use state::ApiState;
         types::EnvVar,
         types::CreateContainerRequest,
         types::ContainerMountSpec,
+        types::HealthCheckSpec,
         types::ContainerExecRequest,
         types::StopContainerRequest,
         types::DeleteContainerRequest,
         types::PullImageRequest,
         types::DeleteQuery,
         types::LogsQuery,
+        types::StatsQuery,
+        types::ListImagesQuery,
+        types::LabelQuery,
         types::CreateMicrovmRequest,
         types::MicrovmExecRequest,
         // Response types
@@ -115,6 +130,7 @@ use state::ApiState;
         types::MountInfo,
         types::ListSandboxesResponse,
         types::ExecResponse,
+        types::StatsSample,
         types::ContainerInfo,
         types::ListContainersResponse,
         types::ImageInfo,
@@ -136,6 +152,9 @@ pub struct ApiDoc;
 /// provides a reasonable upper bound for most requests.
 const API_REQUEST_TIMEOUT_SECS: u64 = 300;
 
+/// How long shutdown waits for in-flight requests before abandoning them.
+pub const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Create the API router with all endpoints.
 ///
 /// `cors_origins` specifies allowed CORS origins. If empty, defaults to
@@ -147,6 +166,9 @@ pub fn create_router(state: Arc<ApiState>, cors_origins: Vec<String>) -> Router
     // SSE logs route (no timeout - streams indefinitely)
     let logs_route = Router::new().route("/:id/logs", get(handlers::exec::stream_logs));
 
+    // SSE stats route (no timeout - streams indefinitely)
+    let stats_route = Router::new().route("/:id/stats", get(handlers::stats::stream_stats));
+
     // Sandbox routes with timeout
     let sandbox_routes_with_timeout = Router::new()
         .route("/", post(handlers::sandboxes::create_sandbox))
@@ -194,6 +216,7 @@ pub fn create_router(state: Arc<ApiState>, cors_origins: Vec<String>) -> Router
     // Combine sandbox routes (with and without timeout)
     let sandbox_routes = Router::new()
         .merge(logs_route)
+        .merge(stats_route)
         .merge(sandbox_routes_with_timeout);
 
     // MicroVM routes
@@ -258,14 +281,181 @@ pub fn create_router(state: Arc<ApiState>, cors_origins: Vec<String>) -> Router
             axum::http::Method::POST,
             axum::http::Method::DELETE,
         ])
-        .allow_headers([axum::http::header::CONTENT_TYPE]);
+        .allow_headers([
+            axum::http::header::CONTENT_TYPE,
+            axum::http::HeaderName::from_static(idempotency::IDEMPOTENCY_KEY_HEADER),
+        ]);
 
     // Combine all routes
     Router::new()
         .merge(health_route)
         .nest("/api/v1", api_v1)
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            track_in_flight,
+        ))
         .layer(TraceLayer::new_for_http())
         .layer(cors)
         .with_state(state)
 }
+
+/// Count requests as in flight, refusing new ones once shutdown has begun.
+async fn track_in_flight(
+    State(state): State<Arc<ApiState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(_guard) = state.track_request() else {
+        return ApiError::Unavailable("server is shutting down".into()).into_response();
+    };
+    next.run(request).await
+}
+
+/// Serve `app` on `listener` until `signal` resolves, then shut down
+/// gracefully.
+///
+/// Once `signal` fires the listener is closed, requests arriving on open
+/// connections are refused with 503, and in-flight requests get up to
+/// `drain_timeout` to finish before being abandoned. Sandboxes keep running
+/// either way; the next server start reconnects to them.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    state: Arc<ApiState>,
+    signal: impl Future<Output = ()> + Send + 'static,
+    drain_timeout: Duration,
+) -> std::io::Result<()> {
+    let (drain_tx, drain_rx) = tokio::sync::oneshot::channel::<()>();
+    let mut server = tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async {
+                let _ = drain_rx.await;
+            })
+            .await
+    });
+
+    tokio::select! {
+        result = &mut server => return result.map_err(std::io::Error::other)?,
+        _ = signal => {}
+    }
+
+    state.begin_drain();
+    let pending = state.in_flight_requests();
+    tracing::info!(in_flight = pending, "draining in-flight requests");
+    let _ = drain_tx.send(());
+
+    match tokio::time::timeout(drain_timeout, &mut server).await {
+        Ok(result) => {
+            result.map_err(std::io::Error::other)??;
+            tracing::info!(drained = pending, "in-flight requests drained");
+        }
+        Err(_) => {
+            server.abort();
+            let remaining = state.in_flight_requests();
+            tracing::warn!(
+                drained = pending.saturating_sub(remaining),
+                remaining,
+                timeout_secs = drain_timeout.as_secs(),
+                "drain timed out, abandoning remaining requests"
+            );
+        }
+    }
+
+    let interrupted = state.reserved_sandbox_names();
+    if !interrupted.is_empty() {
+        tracing::warn!(
+            sandboxes = ?interrupted,
+            "sandbox creation interrupted by shutdown; create them again"
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::SmolvmDb;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn temp_api_state() -> (tempfile::TempDir, Arc<ApiState>) {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = SmolvmDb::open_at(&dir.path().join("test.redb")).unwrap();
+        (dir, Arc::new(ApiState::with_db(db)))
+    }
+
+    /// A router with a slow route behind the in-flight middleware.
+    fn slow_router(state: Arc<ApiState>) -> Router {
+        Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    "done"
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                track_in_flight,
+            ))
+            .with_state(state)
+    }
+
+    async fn http_get(addr: std::net::SocketAddr, path: &str) -> std::io::Result<String> {
+        let mut stream = tokio::net::TcpStream::connect(addr).await?;
+        let request = format!("GET {path} HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n");
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok(response)
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_and_refuses_new() {
+        let (_dir, state) = temp_api_state();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (signal_tx, signal_rx) = tokio::sync::oneshot::channel::<()>();
+
+        let server = tokio::spawn(serve(
+            listener,
+            slow_router(state.clone()),
+            state.clone(),
+            async {
+                let _ = signal_rx.await;
+            },
+            Duration::from_secs(5),
+        ));
+
+        let in_flight = tokio::spawn(http_get(addr, "/slow"));
+        while state.in_flight_requests() == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        signal_tx.send(()).unwrap();
+
+        // The in-flight request completes normally
+        let response = in_flight.await.unwrap().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.ends_with("done"));
+
+        // New connections are refused once the server has drained
+        server.await.unwrap().unwrap();
+        assert!(http_get(addr, "/slow").await.is_err());
+        assert_eq!(state.in_flight_requests(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_requests_refused_while_draining() {
+        let (_dir, state) = temp_api_state();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = slow_router(state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        state.begin_drain();
+        let response = http_get(addr, "/slow").await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+        assert!(response.contains("shutting down"));
+    }
+}
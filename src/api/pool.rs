@@ -0,0 +1,294 @@
+//! Pool of idle agent connections.
+//!
+//! Connecting to an agent pays vsock setup cost on every request. The API
+//! server instead checks connections out of an [`AgentPool`], keyed by the
+//! agent's socket path, and returns them once a request/response exchange
+//! has completed. Idle connections are validated with a ping before reuse
+//! and dropped once they have been idle longer than the pool allows.
+//!
+//! Only request/response exchanges may use pooled connections. Interactive
+//! and streaming sessions leave the connection in a protocol state that a
+//! later request can't recover from, so they must open their own.
+
+use crate::agent::AgentClient;
+use crate::error::Result;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Default number of idle connections kept per agent.
+///
+/// The agent runs one request at a time, so more than one idle connection
+/// per agent rarely gets reused.
+pub const DEFAULT_MAX_IDLE_PER_AGENT: usize = 1;
+
+/// Default time an idle connection is kept before being closed.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// An idle connection and when it was returned.
+struct IdleClient {
+    client: AgentClient,
+    idle_since: Instant,
+}
+
+/// Bounded pool of idle agent connections, keyed by agent socket path.
+pub struct AgentPool {
+    idle: Mutex<HashMap<PathBuf, Vec<IdleClient>>>,
+    max_idle_per_agent: usize,
+    idle_timeout: Duration,
+}
+
+impl AgentPool {
+    /// Create a pool keeping at most `max_idle_per_agent` connections per
+    /// agent, each for at most `idle_timeout`.
+    pub fn new(max_idle_per_agent: usize, idle_timeout: Duration) -> Self {
+        Self {
+            idle: Mutex::new(HashMap::new()),
+            max_idle_per_agent,
+            idle_timeout,
+        }
+    }
+
+    /// Check out a connection to the agent at `socket`.
+    ///
+    /// Reuses the most recently returned idle connection that still answers
+    /// a ping, or connects anew if there is none.
+    pub fn checkout(self: &Arc<Self>, socket: &Path) -> Result<PooledClient> {
+        while let Some(mut client) = self.take_idle(socket) {
+            match client.ping() {
+                Ok(_) => return Ok(PooledClient::new(self, socket, client)),
+                Err(e) => {
+                    tracing::debug!(
+                        socket = %socket.display(),
+                        error = %e,
+                        "discarding stale pooled agent connection"
+                    );
+                }
+            }
+        }
+
+        let client = AgentClient::connect_with_retry(socket)?;
+        Ok(PooledClient::new(self, socket, client))
+    }
+
+    /// Close every idle connection to the agent at `socket`, e.g. because
+    /// its sandbox was removed.
+    pub fn purge(&self, socket: &Path) {
+        self.idle.lock().remove(socket);
+    }
+
+    /// Close idle connections that have exceeded the idle timeout.
+    pub fn reap_expired(&self) {
+        let now = Instant::now();
+        let mut idle = self.idle.lock();
+        for clients in idle.values_mut() {
+            clients.retain(|c| now.duration_since(c.idle_since) < self.idle_timeout);
+        }
+        idle.retain(|_, clients| !clients.is_empty());
+    }
+
+    /// Number of idle connections to the agent at `socket`.
+    #[cfg(test)]
+    fn idle_count(&self, socket: &Path) -> usize {
+        self.idle.lock().get(socket).map_or(0, Vec::len)
+    }
+
+    /// Pop the most recently returned unexpired idle connection.
+    fn take_idle(&self, socket: &Path) -> Option<AgentClient> {
+        let mut idle = self.idle.lock();
+        let clients = idle.get_mut(socket)?;
+        while let Some(entry) = clients.pop() {
+            if entry.idle_since.elapsed() < self.idle_timeout {
+                return Some(entry.client);
+            }
+        }
+        None
+    }
+
+    /// Return a connection, closing it if the agent already has the
+    /// maximum number of idle connections.
+    fn checkin(&self, socket: PathBuf, client: AgentClient) {
+        let mut idle = self.idle.lock();
+        let clients = idle.entry(socket).or_default();
+        if clients.len() < self.max_idle_per_agent {
+            clients.push(IdleClient {
+                client,
+                idle_since: Instant::now(),
+            });
+        }
+    }
+}
+
+impl Default for AgentPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_IDLE_PER_AGENT, DEFAULT_IDLE_TIMEOUT)
+    }
+}
+
+/// A connection checked out of an [`AgentPool`].
+///
+/// Returned to the pool on drop unless [`discard`](Self::discard)ed.
+pub struct PooledClient {
+    pool: Arc<AgentPool>,
+    socket: PathBuf,
+    client: Option<AgentClient>,
+}
+
+impl PooledClient {
+    fn new(pool: &Arc<AgentPool>, socket: &Path, client: AgentClient) -> Self {
+        Self {
+            pool: Arc::clone(pool),
+            socket: socket.to_path_buf(),
+            client: Some(client),
+        }
+    }
+
+    /// Close the connection instead of returning it to the pool, e.g.
+    /// because a request on it failed.
+    pub fn discard(mut self) {
+        self.client = None;
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = AgentClient;
+
+    fn deref(&self) -> &AgentClient {
+        self.client.as_ref().expect("client present until drop")
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut AgentClient {
+        self.client.as_mut().expect("client present until drop")
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.pool.checkin(std::mem::take(&mut self.socket), client);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smolvm_protocol::{encode_message, AgentRequest, AgentResponse, PROTOCOL_VERSION};
+    use std::io::{Read, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Start a fake agent on a socket in `dir` and return its path and a
+    /// count of accepted connections. Each connection answers pings and
+    /// returns an empty image list for anything else, except `shutdown`,
+    /// which closes the connection without replying.
+    fn fake_agent(dir: &Path) -> (PathBuf, Arc<AtomicUsize>) {
+        let socket = dir.join("agent.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+
+        let counter = Arc::clone(&accepted);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                counter.fetch_add(1, Ordering::SeqCst);
+                std::thread::spawn(move || serve(stream.unwrap()));
+            }
+        });
+        (socket, accepted)
+    }
+
+    fn serve(mut stream: UnixStream) {
+        let mut header = [0u8; 4];
+        while stream.read_exact(&mut header).is_ok() {
+            let mut body = vec![0u8; u32::from_be_bytes(header) as usize];
+            stream.read_exact(&mut body).unwrap();
+            let response = match serde_json::from_slice(&body).unwrap() {
+                AgentRequest::Ping => AgentResponse::Pong {
+                    version: PROTOCOL_VERSION,
+                    capabilities: Vec::new(),
+                },
+                AgentRequest::Shutdown => return,
+                _ => AgentResponse::Ok {
+                    data: Some(serde_json::json!([])),
+                },
+            };
+            stream
+                .write_all(&encode_message(&response).unwrap())
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sequential_requests_reuse_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let (socket, accepted) = fake_agent(dir.path());
+        let pool = Arc::new(AgentPool::default());
+
+        for _ in 0..3 {
+            let mut client = pool.checkout(&socket).unwrap();
+            assert!(client.list_images().unwrap().is_empty());
+        }
+
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.idle_count(&socket), 1);
+    }
+
+    #[test]
+    fn test_broken_connection_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let (socket, accepted) = fake_agent(dir.path());
+        let pool = Arc::new(AgentPool::default());
+
+        // The agent closes this connection; it goes back to the pool looking
+        // healthy.
+        let mut client = pool.checkout(&socket).unwrap();
+        client.send_raw(&AgentRequest::Shutdown).unwrap();
+        drop(client);
+        assert_eq!(pool.idle_count(&socket), 1);
+
+        // The next checkout's ping fails, so it is evicted and replaced.
+        let mut client = pool.checkout(&socket).unwrap();
+        assert!(client.list_images().unwrap().is_empty());
+        drop(client);
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+        assert_eq!(pool.idle_count(&socket), 1);
+    }
+
+    #[test]
+    fn test_discarded_and_expired_connections_not_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let (socket, accepted) = fake_agent(dir.path());
+        let pool = Arc::new(AgentPool::new(1, Duration::from_millis(50)));
+
+        pool.checkout(&socket).unwrap().discard();
+        assert_eq!(pool.idle_count(&socket), 0);
+
+        drop(pool.checkout(&socket).unwrap());
+        assert_eq!(pool.idle_count(&socket), 1);
+        std::thread::sleep(Duration::from_millis(100));
+        pool.reap_expired();
+        assert_eq!(pool.idle_count(&socket), 0);
+
+        drop(pool.checkout(&socket).unwrap());
+        assert_eq!(accepted.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_idle_connections_bounded() {
+        let dir = tempfile::tempdir().unwrap();
+        let (socket, _accepted) = fake_agent(dir.path());
+        let pool = Arc::new(AgentPool::new(2, DEFAULT_IDLE_TIMEOUT));
+
+        let clients: Vec<_> = (0..3).map(|_| pool.checkout(&socket).unwrap()).collect();
+        drop(clients);
+        assert_eq!(pool.idle_count(&socket), 2);
+
+        pool.purge(&socket);
+        assert_eq!(pool.idle_count(&socket), 0);
+    }
+}
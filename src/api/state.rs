@@ -2,12 +2,16 @@
 
 use crate::agent::{AgentManager, HostMount, PortMapping, VmResources};
 use crate::api::error::ApiError;
+use crate::api::idempotency::IdempotencyCache;
+use crate::api::pool::AgentPool;
 use crate::api::types::{MountSpec, PortSpec, ResourceSpec, RestartSpec, SandboxInfo};
 use crate::config::{RecordState, RestartConfig, RestartPolicy, VmRecord};
 use crate::db::SmolvmDb;
+use crate::labels::Labels;
 use crate::mount::MountBinding;
 use parking_lot::RwLock;
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 /// Shared API server state.
@@ -19,6 +23,14 @@ pub struct ApiState {
     reserved_names: RwLock<HashSet<String>>,
     /// Database for persistent state.
     db: SmolvmDb,
+    /// Idle agent connections reused across requests.
+    agent_pool: Arc<AgentPool>,
+    /// Number of requests currently being handled.
+    in_flight: Arc<AtomicUsize>,
+    /// Set once shutdown begins; new requests are refused.
+    draining: AtomicBool,
+    /// Recorded responses for idempotent creation requests.
+    idempotency: IdempotencyCache,
 }
 
 /// Internal sandbox entry with manager and configuration.
@@ -35,6 +47,20 @@ pub struct SandboxEntry {
     pub restart: RestartConfig,
     /// Whether outbound network access is enabled.
     pub network: bool,
+    /// User labels.
+    pub labels: Labels,
+}
+
+/// Marks a request as in flight until dropped (see
+/// [`ApiState::track_request`]).
+pub struct InFlightGuard {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 /// Parameters for registering a new sandbox.
@@ -51,6 +77,8 @@ pub struct SandboxRegistration {
     pub restart: RestartConfig,
     /// Whether outbound network access is enabled.
     pub network: bool,
+    /// User labels.
+    pub labels: Labels,
 }
 
 /// RAII guard for sandbox name reservation.
@@ -67,7 +95,7 @@ pub struct SandboxRegistration {
 /// let manager = AgentManager::for_vm(guard.name())?;
 ///
 /// // Complete registration, consuming the guard
-/// guard.complete(SandboxRegistration { manager, mounts, ports, resources, restart, network })?;
+/// guard.complete(SandboxRegistration { manager, mounts, ports, resources, restart, network, labels })?;
 /// ```
 pub struct ReservationGuard<'a> {
     state: &'a ApiState,
@@ -123,6 +151,10 @@ impl ApiState {
             sandboxes: RwLock::new(HashMap::new()),
             reserved_names: RwLock::new(HashSet::new()),
             db,
+            agent_pool: Arc::new(AgentPool::default()),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            draining: AtomicBool::new(false),
+            idempotency: IdempotencyCache::default(),
         })
     }
 
@@ -134,6 +166,10 @@ impl ApiState {
             sandboxes: RwLock::new(HashMap::new()),
             reserved_names: RwLock::new(HashSet::new()),
             db,
+            agent_pool: Arc::new(AgentPool::default()),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            draining: AtomicBool::new(false),
+            idempotency: IdempotencyCache::default(),
         }
     }
 
@@ -219,6 +255,7 @@ impl ApiState {
                             resources,
                             restart: record.restart.clone(),
                             network: record.network,
+                            labels: record.labels.clone(),
                         })),
                     );
                     loaded.push(name.clone());
@@ -281,6 +318,9 @@ impl ApiState {
         let entry = sandboxes
             .remove(name)
             .expect("sandbox disappeared while holding write lock");
+        drop(sandboxes);
+
+        self.agent_pool.purge(entry.lock().manager.vsock_socket());
 
         Ok(entry)
     }
@@ -432,6 +472,7 @@ impl ApiState {
         );
         record.storage_gb = reg.resources.storage_gb;
         record.overlay_gb = reg.resources.overlay_gb;
+        record.labels = reg.labels.clone();
 
         // Use insert_vm_if_not_exists for atomic database insert
         match self.db.insert_vm_if_not_exists(&name, &record) {
@@ -447,6 +488,7 @@ impl ApiState {
                         resources: reg.resources,
                         restart: reg.restart,
                         network: reg.network,
+                        labels: reg.labels,
                     })),
                 );
                 Ok(())
@@ -470,6 +512,54 @@ impl ApiState {
         &self.db
     }
 
+    /// Get the idempotency key cache for creation endpoints.
+    pub fn idempotency(&self) -> &IdempotencyCache {
+        &self.idempotency
+    }
+
+    /// Get the pool of idle agent connections.
+    pub fn agent_pool(&self) -> &AgentPool {
+        &self.agent_pool
+    }
+
+    // ========================================================================
+    // Shutdown Draining
+    // ========================================================================
+
+    /// Register a request as in flight until the returned guard is dropped.
+    ///
+    /// Returns `None` once [`begin_drain`](Self::begin_drain) has been
+    /// called, in which case the request should be refused.
+    pub fn track_request(&self) -> Option<InFlightGuard> {
+        if self.is_draining() {
+            return None;
+        }
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        Some(InFlightGuard {
+            in_flight: self.in_flight.clone(),
+        })
+    }
+
+    /// Number of requests currently in flight.
+    pub fn in_flight_requests(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Start refusing new requests so in-flight ones can drain.
+    pub fn begin_drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether shutdown has begun.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Names of sandboxes whose creation is still in progress.
+    pub fn reserved_sandbox_names(&self) -> Vec<String> {
+        self.reserved_names.read().iter().cloned().collect()
+    }
+
     // ========================================================================
     // Restart Management Methods
     // ========================================================================
@@ -566,8 +656,12 @@ impl ApiState {
 
 /// Run a blocking operation against a sandbox's agent client.
 ///
-/// Handles the common pattern: clone entry → spawn_blocking → lock → connect → op → map errors.
+/// Handles the common pattern: clone entry → spawn_blocking → lock → check
+/// out a pooled connection → op → map errors. The connection goes back to
+/// the pool unless `op` fails, so `op` must be a plain request/response
+/// exchange, never an interactive or streaming session.
 pub async fn with_sandbox_client<T, F>(
+    state: &ApiState,
     entry: &Arc<parking_lot::Mutex<SandboxEntry>>,
     op: F,
 ) -> Result<T, ApiError>
@@ -575,16 +669,35 @@ where
     T: Send + 'static,
     F: FnOnce(&mut crate::agent::AgentClient) -> crate::Result<T> + Send + 'static,
 {
+    let pool = state.agent_pool.clone();
     let entry_clone = entry.clone();
     tokio::task::spawn_blocking(move || {
         let entry = entry_clone.lock();
-        let mut client = entry.manager.connect()?;
-        op(&mut client)
+        let mut client = pool.checkout(entry.manager.vsock_socket())?;
+        let result = op(&mut client);
+        if result.is_err() {
+            client.discard();
+        }
+        result
     })
     .await?
     .map_err(ApiError::internal)
 }
 
+/// Open an async connection to a sandbox's agent.
+///
+/// Unlike [`with_sandbox_client`], no blocking thread is held for the
+/// duration of the agent call; the sandbox lock is only taken to look up the
+/// socket path.
+pub async fn sandbox_async_client(
+    entry: &Arc<parking_lot::Mutex<SandboxEntry>>,
+) -> Result<crate::agent::AsyncAgentClient, ApiError> {
+    let socket = entry.lock().manager.vsock_socket().to_path_buf();
+    crate::agent::AsyncAgentClient::connect(socket)
+        .await
+        .map_err(ApiError::internal)
+}
+
 // ============================================================================
 // Shared Sandbox Helpers
 // ============================================================================
@@ -698,6 +811,10 @@ pub fn resource_spec_to_vm_resources(spec: &ResourceSpec, network: bool) -> VmRe
         network,
         storage_gb: spec.storage_gb,
         overlay_gb: spec.overlay_gb,
+        scratch_gb: None,
+        dns: None,
+        fallback_dns: None,
+        layer_dedup: false,
     }
 }
 
@@ -45,6 +45,7 @@ impl Supervisor {
             tokio::select! {
                 _ = ticker.tick() => {
                     self.check_all_sandboxes().await;
+                    self.state.agent_pool().reap_expired();
                 }
                 _ = self.shutdown_rx.changed() => {
                     if *self.shutdown_rx.borrow() {
@@ -65,9 +66,6 @@ impl Supervisor {
                 tracing::warn!(sandbox = %name, error = %e, "failed to check sandbox");
             }
         }
-
-        // Also rotate logs for all sandboxes
-        self.rotate_logs_if_needed().await;
     }
 
     /// Check a single sandbox and restart if needed.
@@ -200,37 +198,6 @@ impl Supervisor {
         let exponent = restart_count.min(8); // Prevent overflow
         (2u64.pow(exponent)).min(MAX_BACKOFF_SECS)
     }
-
-    /// Rotate logs for all sandboxes if they exceed the size limit.
-    async fn rotate_logs_if_needed(&self) {
-        let sandbox_names = self.state.list_sandbox_names();
-
-        for name in sandbox_names {
-            if let Some(log_path) = self.get_sandbox_log_path(&name) {
-                if let Err(e) = crate::log_rotation::rotate_if_needed(&log_path) {
-                    tracing::debug!(sandbox = %name, error = %e, "failed to rotate logs");
-                }
-            }
-        }
-    }
-
-    /// Get the console log path for a sandbox.
-    fn get_sandbox_log_path(&self, name: &str) -> Option<std::path::PathBuf> {
-        let runtime_dir = dirs::runtime_dir()
-            .or_else(dirs::cache_dir)
-            .unwrap_or_else(|| std::path::PathBuf::from("/tmp"));
-        let log_path = runtime_dir
-            .join("smolvm")
-            .join("vms")
-            .join(name)
-            .join("agent-console.log");
-
-        if log_path.exists() {
-            Some(log_path)
-        } else {
-            None
-        }
-    }
 }
 
 #[cfg(test)]
@@ -1,5 +1,6 @@
 //! JSON request and response types for the API.
 
+use crate::labels::Labels;
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
@@ -19,7 +20,7 @@ pub struct RestartSpec {
 }
 
 /// Request to create a new sandbox.
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 pub struct CreateSandboxRequest {
     /// Unique name for the sandbox.
     #[schema(example = "my-sandbox")]
@@ -36,6 +37,10 @@ pub struct CreateSandboxRequest {
     /// Restart policy configuration.
     #[serde(default)]
     pub restart: Option<RestartSpec>,
+    /// User labels, for finding the sandbox later.
+    #[serde(default)]
+    #[schema(example = json!({"project": "billing"}))]
+    pub labels: Labels,
 }
 
 /// Mount specification (for requests).
@@ -128,6 +133,18 @@ pub struct SandboxInfo {
     /// Number of times this sandbox has been automatically restarted.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub restart_count: Option<u32>,
+    /// User labels.
+    #[serde(skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
+}
+
+/// Query parameters for listing labelled resources.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct LabelQuery {
+    /// Comma-separated label filters, each `key` or `key=value`.
+    #[serde(default)]
+    #[schema(example = "project=billing")]
+    pub label: Option<String>,
 }
 
 /// List sandboxes response.
@@ -219,7 +236,7 @@ pub struct RunRequest {
 // ============================================================================
 
 /// Request to create a container.
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 pub struct CreateContainerRequest {
     /// Image to use.
     #[schema(example = "alpine:latest")]
@@ -237,6 +254,34 @@ pub struct CreateContainerRequest {
     /// Volume mounts.
     #[serde(default)]
     pub mounts: Vec<ContainerMountSpec>,
+    /// Restart policy: "no" (default), "always", "on-failure" or "on-failure:N".
+    #[serde(default)]
+    #[schema(example = "on-failure:3")]
+    pub restart_policy: Option<String>,
+    /// User labels, for finding the container later.
+    #[serde(default)]
+    #[schema(example = json!({"role": "worker"}))]
+    pub labels: Labels,
+    /// Command run periodically inside the container to check its health.
+    #[serde(default)]
+    pub healthcheck: Option<HealthCheckSpec>,
+}
+
+/// Health check specification for a container.
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
+pub struct HealthCheckSpec {
+    /// Command and arguments; exiting 0 is a pass.
+    #[schema(example = json!(["wget", "-q", "--spider", "http://localhost/"]))]
+    pub command: Vec<String>,
+    /// Time between checks in milliseconds (default: 30000).
+    #[serde(default)]
+    pub interval_ms: Option<u64>,
+    /// How long a check may run in milliseconds (default: 30000).
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Consecutive failures before the container is unhealthy (default: 3).
+    #[serde(default)]
+    pub retries: Option<u32>,
 }
 
 /// Container mount specification.
@@ -275,6 +320,30 @@ pub struct ContainerInfo {
     pub created_at: u64,
     /// Command.
     pub command: Vec<String>,
+    /// Number of times the container has been restarted.
+    pub restart_count: u32,
+    /// Lifecycle status (created, running, exited, error).
+    #[schema(example = "running")]
+    pub status: String,
+    /// Exit code of the main process, once exited (if known).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Why the container failed to start, in the error status.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// When the main process last started (Unix epoch seconds).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<u64>,
+    /// When the main process last exited (Unix epoch seconds).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<u64>,
+    /// User labels.
+    #[serde(skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
+    /// Health check outcome while running (starting, healthy, unhealthy).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "healthy")]
+    pub health: Option<String>,
 }
 
 /// List containers response.
@@ -343,6 +412,9 @@ pub struct ImageInfo {
     /// Number of layers.
     #[schema(example = 3)]
     pub layer_count: usize,
+    /// Cached references sharing this image's digest.
+    #[schema(example = json!(["docker.io/library/alpine:3.19", "docker.io/library/alpine:latest"]))]
+    pub tags: Vec<String>,
 }
 
 /// List images response.
@@ -352,6 +424,23 @@ pub struct ListImagesResponse {
     pub images: Vec<ImageInfo>,
 }
 
+/// Query parameters for listing images.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ListImagesQuery {
+    /// Only images created before this date (YYYY-MM-DD or RFC 3339).
+    #[serde(default)]
+    #[schema(example = "2024-12-31")]
+    pub created_before: Option<String>,
+    /// Only images created after this date (YYYY-MM-DD or RFC 3339).
+    #[serde(default)]
+    #[schema(example = "2024-01-01T00:00:00Z")]
+    pub created_after: Option<String>,
+    /// Comma-separated label filters, each `key` or `key=value`.
+    #[serde(default)]
+    #[schema(example = "tier=app")]
+    pub label: Option<String>,
+}
+
 /// Request to pull an image.
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct PullImageRequest {
@@ -362,6 +451,10 @@ pub struct PullImageRequest {
     #[serde(default)]
     #[schema(example = "linux/arm64")]
     pub oci_platform: Option<String>,
+    /// Fetch the manifest again even if the image is cached, picking up
+    /// updates to mutable tags.
+    #[serde(default)]
+    pub no_cache: bool,
 }
 
 /// Pull image response.
@@ -369,6 +462,9 @@ pub struct PullImageRequest {
 pub struct PullImageResponse {
     /// Information about the pulled image.
     pub image: ImageInfo,
+    /// Layers this pull downloaded; empty if all were already cached.
+    #[schema(example = json!(["sha256:abc123..."]))]
+    pub refreshed_layers: Vec<String>,
 }
 
 // ============================================================================
@@ -387,6 +483,48 @@ pub struct LogsQuery {
     pub tail: Option<usize>,
 }
 
+// ============================================================================
+// Stats Types
+// ============================================================================
+
+/// Query parameters for stats endpoint.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct StatsQuery {
+    /// Container to sample instead of the whole sandbox VM.
+    #[serde(default)]
+    pub container: Option<String>,
+    /// Milliseconds between samples. Default: 2000.
+    #[serde(default = "default_stats_interval_ms")]
+    #[schema(example = 2000)]
+    pub interval_ms: u64,
+}
+
+fn default_stats_interval_ms() -> u64 {
+    2000
+}
+
+/// One resource usage sample, sent as an SSE `stats` event.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct StatsSample {
+    /// Container sampled, if not the whole VM.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+    /// When the sample was taken (Unix epoch milliseconds).
+    pub timestamp_ms: u64,
+    /// CPU utilization since the previous sample, in percent of one CPU.
+    pub cpu_percent: Option<f64>,
+    /// Cumulative CPU time, in microseconds.
+    pub cpu_usage_usec: u64,
+    /// Number of CPUs online in the VM.
+    pub online_cpus: u32,
+    /// Memory in use, in bytes.
+    pub memory_bytes: u64,
+    /// Memory limit, in bytes, if bounded.
+    pub memory_limit_bytes: Option<u64>,
+    /// Memory in use as a percentage of the limit.
+    pub memory_percent: Option<f64>,
+}
+
 // ============================================================================
 // Delete Types
 // ============================================================================
@@ -472,6 +610,13 @@ pub struct CreateMicrovmRequest {
     /// Overlay disk size in GiB (default: 2).
     #[serde(default)]
     pub overlay_gb: Option<u64>,
+    /// Scratch disk size in GiB for container writes (default: none).
+    #[serde(default)]
+    pub scratch_gb: Option<u64>,
+    /// DNS server for containers (default: the host's resolvers).
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub dns: Option<std::net::IpAddr>,
 }
 
 /// Request to execute a command in a microvm.
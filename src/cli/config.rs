@@ -3,7 +3,7 @@
 //! Commands for managing smolvm configuration, including registry settings.
 
 use clap::{Args, Subcommand};
-use smolvm::registry::RegistryConfig;
+use smolvm::registry::{DockerConfig, RegistryConfig};
 use smolvm::Result;
 
 /// Configuration commands
@@ -12,6 +12,9 @@ pub enum ConfigCmd {
     /// Show current configuration
     Show(ShowCmd),
 
+    /// Change a global setting
+    Set(SetCmd),
+
     /// Manage registry configuration
     #[command(subcommand)]
     Registries(RegistriesCmd),
@@ -21,6 +24,7 @@ impl ConfigCmd {
     pub fn run(self) -> Result<()> {
         match self {
             ConfigCmd::Show(cmd) => cmd.run(),
+            ConfigCmd::Set(cmd) => cmd.run(),
             ConfigCmd::Registries(cmd) => cmd.run(),
         }
     }
@@ -42,6 +46,7 @@ impl ShowCmd {
         println!("  Default CPUs: {}", config.default_cpus);
         println!("  Default Memory: {} MiB", config.default_mem);
         println!("  Default DNS: {}", config.default_dns);
+        println!("  Layer dedup: {}", config.layer_dedup);
 
         // Load and display registry config
         let registry_config = RegistryConfig::load().unwrap_or_default();
@@ -56,10 +61,16 @@ impl ShowCmd {
             }
         }
         println!("  Default registry: {}", registry_config.default_registry());
+        if let Some(rate) = registry_config.defaults.max_download_rate {
+            println!("  Pull rate limit: {}/s", crate::cli::format_bytes(rate));
+        }
         println!(
             "  Configured registries: {}",
             registry_config.registries.len()
         );
+        if let Some(path) = DockerConfig::config_path().filter(|p| p.exists()) {
+            println!("  Docker logins: {} (fallback)", path.display());
+        }
 
         if !registry_config.registries.is_empty() {
             println!();
@@ -89,6 +100,80 @@ impl ShowCmd {
     }
 }
 
+// ============================================================================
+// Set Command
+// ============================================================================
+
+/// Global settings `config set` can change.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum SettingKey {
+    /// Default number of vCPUs for new VMs
+    DefaultCpus,
+    /// Default memory in MiB for new VMs
+    DefaultMem,
+    /// DNS server for VMs whose host has no usable resolvers
+    DefaultDns,
+    /// Deduplicate identical files across image layers (true or false);
+    /// applies to VMs started afterwards
+    LayerDedup,
+}
+
+/// Change a global setting
+///
+/// Examples:
+///   smolvm config set layer-dedup true
+///   smolvm config set default-mem 1024
+#[derive(Args, Debug)]
+pub struct SetCmd {
+    /// Setting to change
+    #[arg(value_enum, value_name = "KEY")]
+    pub key: SettingKey,
+
+    /// New value
+    #[arg(value_name = "VALUE")]
+    pub value: String,
+}
+
+impl SetCmd {
+    pub fn run(self) -> Result<()> {
+        let mut config = smolvm::SmolvmConfig::load()?;
+        let invalid = |expected: &str| {
+            smolvm::Error::config(
+                "set config",
+                format!("invalid value '{}': expected {}", self.value, expected),
+            )
+        };
+        match self.key {
+            SettingKey::DefaultCpus => {
+                config.default_cpus = self
+                    .value
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| invalid("a number of vCPUs"))?;
+            }
+            SettingKey::DefaultMem => {
+                config.default_mem = self
+                    .value
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| invalid("a size in MiB"))?;
+            }
+            SettingKey::DefaultDns => {
+                self.value
+                    .parse::<std::net::IpAddr>()
+                    .map_err(|_| invalid("an IP address"))?;
+                config.default_dns = self.value.clone();
+            }
+            SettingKey::LayerDedup => {
+                config.layer_dedup = self.value.parse().map_err(|_| invalid("true or false"))?;
+            }
+        }
+        config.save()
+    }
+}
+
 // ============================================================================
 // Registries Commands
 // ============================================================================
@@ -239,6 +324,8 @@ const EXAMPLE_CONFIG: &str = r#"# smolvm Registry Configuration
 [defaults]
 # Default registry when none specified (default: docker.io)
 # registry = "docker.io"
+# Cap each pull's download rate, in bytes per second (default: unlimited)
+# max_download_rate = 10485760
 
 # Docker Hub authentication
 # [registries."docker.io"]
@@ -3,12 +3,15 @@
 //! These commands manage long-running containers via a microvm.
 //! Containers can be created, started, stopped, and deleted independently.
 
-use crate::cli::parsers::{parse_duration, parse_env_list, parse_mounts_to_bindings};
+use crate::cli::parsers::{parse_duration, parse_env_list, parse_mounts_to_bindings, SecurityArgs};
 use crate::cli::vm_common;
-use crate::cli::{flush_output, truncate, truncate_id, COMMAND_WIDTH, IMAGE_NAME_WIDTH};
+use crate::cli::{format_container_status, truncate, truncate_id, COMMAND_WIDTH, IMAGE_NAME_WIDTH};
 use clap::{Args, Subcommand};
-use smolvm::agent::{AgentClient, AgentManager};
+use smolvm::agent::{AgentClient, AgentManager, CreateContainerConfig, ExecConfig};
+use smolvm::labels::{labels_match, parse_label, parse_label_filter, LabelFilter};
+use smolvm::util::RetryConfig;
 use smolvm::{DEFAULT_IDLE_CMD, DEFAULT_SHELL_CMD};
+use smolvm_protocol::{DiffKind, HealthCheck, RestartPolicy};
 use std::time::Duration;
 
 /// Manage containers inside a microVM
@@ -33,6 +36,9 @@ pub enum ContainerCmd {
 
     /// Run a command inside a container
     Exec(ContainerExecCmd),
+
+    /// Show files a container added, changed or deleted
+    Diff(ContainerDiffCmd),
 }
 
 impl ContainerCmd {
@@ -44,6 +50,7 @@ impl ContainerCmd {
             ContainerCmd::Remove(cmd) => cmd.run(),
             ContainerCmd::List(cmd) => cmd.run(),
             ContainerCmd::Exec(cmd) => cmd.run(),
+            ContainerCmd::Diff(cmd) => cmd.run(),
         }
     }
 }
@@ -88,12 +95,80 @@ pub struct ContainerCreateCmd {
     pub env: Vec<String>,
 
     /// Mount host directory (can be used multiple times)
-    #[arg(short = 'v', long = "volume", value_name = "HOST:CONTAINER[:ro]")]
+    #[arg(short = 'v', long = "volume", value_name = "HOST|NAME:CONTAINER[:ro]")]
     pub volume: Vec<String>,
+
+    /// Restart the container when it exits: no, always, on-failure[:MAX_RETRIES]
+    #[arg(long = "restart", value_name = "POLICY", default_value = "no")]
+    pub restart: RestartPolicy,
+
+    /// Set a label on the container (can be used multiple times)
+    #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+    pub label: Vec<(String, String)>,
+
+    /// Shell command run periodically inside the container to check its health
+    #[arg(
+        long = "health-cmd",
+        value_name = "COMMAND",
+        help_heading = "Health check"
+    )]
+    pub health_cmd: Option<String>,
+
+    /// Time between health checks (default: 30s)
+    #[arg(
+        long = "health-interval",
+        value_parser = parse_duration,
+        value_name = "DURATION",
+        requires = "health_cmd",
+        help_heading = "Health check"
+    )]
+    pub health_interval: Option<Duration>,
+
+    /// How long a health check may run before it fails (default: 30s)
+    #[arg(
+        long = "health-timeout",
+        value_parser = parse_duration,
+        value_name = "DURATION",
+        requires = "health_cmd",
+        help_heading = "Health check"
+    )]
+    pub health_timeout: Option<Duration>,
+
+    /// Consecutive failed checks before the container is unhealthy (default: 3)
+    #[arg(
+        long = "health-retries",
+        value_name = "N",
+        requires = "health_cmd",
+        help_heading = "Health check"
+    )]
+    pub health_retries: Option<u32>,
 }
 
 impl ContainerCreateCmd {
+    /// The health check given by the `--health-*` flags, if any.
+    fn healthcheck(&self) -> smolvm::Result<Option<HealthCheck>> {
+        let Some(cmd) = &self.health_cmd else {
+            return Ok(None);
+        };
+        let millis = |d: Duration| u64::try_from(d.as_millis()).unwrap_or(u64::MAX);
+        let check = HealthCheck {
+            interval_ms: self
+                .health_interval
+                .map_or(HealthCheck::DEFAULT_INTERVAL_MS, millis),
+            timeout_ms: self
+                .health_timeout
+                .map_or(HealthCheck::DEFAULT_TIMEOUT_MS, millis),
+            retries: self.health_retries.unwrap_or(HealthCheck::DEFAULT_RETRIES),
+            ..HealthCheck::new(vec!["sh".to_string(), "-c".to_string(), cmd.clone()])
+        };
+        check
+            .validate()
+            .map_err(|e| smolvm::Error::config("health check", e))?;
+        Ok(Some(check))
+    }
+
     pub fn run(self) -> smolvm::Result<()> {
+        let healthcheck = self.healthcheck()?;
         let manager = ensure_microvm(&self.microvm)?;
 
         // Connect to agent
@@ -101,11 +176,11 @@ impl ContainerCreateCmd {
 
         // Pull image if needed
         if !std::path::Path::new(&self.image).exists() {
-            crate::cli::pull_with_progress(&mut client, &self.image, None)?;
+            crate::cli::pull_with_progress(&mut client, &self.image, None, false)?;
         }
 
         // Parse environment variables
-        let env = parse_env_list(&self.env);
+        let env = parse_env_list(&self.env)?;
 
         // Parse mounts
         let mounts = parse_mounts_to_bindings(&self.volume)?;
@@ -118,12 +193,19 @@ impl ContainerCreateCmd {
         };
 
         // Create container
-        let info =
-            client.create_container(&self.image, command, env, self.workdir.clone(), mounts)?;
+        let info = client.create_container(
+            CreateContainerConfig::new(self.image.clone(), command)
+                .with_env(env)
+                .with_workdir(self.workdir.clone())
+                .with_mounts(mounts)
+                .with_restart_policy(self.restart)
+                .with_labels(self.label.into_iter().collect())
+                .with_healthcheck(healthcheck),
+        )?;
 
         println!("Created container: {}", info.id);
         println!("  Image: {}", info.image);
-        println!("  State: {}", info.state);
+        println!("  Status: {}", info.status);
 
         // Keep microvm running
         manager.detach();
@@ -260,6 +342,10 @@ pub struct ContainerListCmd {
     /// Only show container IDs
     #[arg(short = 'q', long)]
     pub quiet: bool,
+
+    /// Only show containers with this label, optionally set to a value (can be used multiple times)
+    #[arg(long = "label", value_name = "KEY[=VALUE]", value_parser = parse_label_filter)]
+    pub label: Vec<LabelFilter>,
 }
 
 impl ContainerListCmd {
@@ -281,7 +367,11 @@ impl ContainerListCmd {
         }
 
         let mut client = AgentClient::connect_with_retry(manager.vsock_socket())?;
-        let containers = client.list_containers()?;
+        let containers: Vec<_> = client
+            .list_containers()?
+            .into_iter()
+            .filter(|c| labels_match(&c.labels, &self.label))
+            .collect();
 
         if self.quiet {
             // Just print IDs
@@ -294,9 +384,13 @@ impl ContainerListCmd {
             println!("No containers");
         } else {
             // Table format
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
             println!(
-                "{:<16} {:<20} {:<12} {:<30}",
-                "CONTAINER ID", "IMAGE", "STATE", "COMMAND"
+                "{:<16} {:<20} {:<22} {:<30}",
+                "CONTAINER ID", "IMAGE", "STATUS", "COMMAND"
             );
 
             for c in &containers {
@@ -309,8 +403,11 @@ impl ContainerListCmd {
                 let short_cmd = truncate(&c.command.join(" "), COMMAND_WIDTH);
 
                 println!(
-                    "{:<16} {:<20} {:<12} {:<30}",
-                    short_id, short_image, c.state, short_cmd
+                    "{:<16} {:<20} {:<22} {:<30}",
+                    short_id,
+                    short_image,
+                    format_container_status(c, now),
+                    short_cmd
                 );
             }
         }
@@ -332,7 +429,8 @@ impl ContainerListCmd {
 ///
 /// Examples:
 ///   smolvm container exec default abc123 -- ls -la
-///   smolvm container exec myvm web -- /bin/sh
+///   smolvm container exec myvm web -- ls /
+///   smolvm container exec -it myvm web -- /bin/sh
 #[derive(Args, Debug)]
 pub struct ContainerExecCmd {
     /// Target microVM name
@@ -358,6 +456,21 @@ pub struct ContainerExecCmd {
     /// Kill command after duration (e.g., "30s", "5m")
     #[arg(long, value_parser = parse_duration, value_name = "DURATION")]
     pub timeout: Option<Duration>,
+
+    /// Keep stdin open for interactive input
+    #[arg(short = 'i', long)]
+    pub interactive: bool,
+
+    /// Allocate a pseudo-TTY (use with -i for shells)
+    #[arg(short = 't', long)]
+    pub tty: bool,
+
+    /// Run as this user instead of the container's (name or UID, optionally `:group`)
+    #[arg(short = 'u', long, value_name = "USER[:GROUP]")]
+    pub user: Option<String>,
+
+    #[command(flatten)]
+    pub security: SecurityArgs,
 }
 
 impl ContainerExecCmd {
@@ -366,7 +479,7 @@ impl ContainerExecCmd {
         let mut client = AgentClient::connect_with_retry(manager.vsock_socket())?;
 
         // Parse environment variables
-        let env = parse_env_list(&self.env);
+        let env = parse_env_list(&self.env)?;
 
         // Default command
         let command = if self.command.is_empty() {
@@ -375,28 +488,70 @@ impl ContainerExecCmd {
             self.command.clone()
         };
 
+        let config = ExecConfig::new(&self.container_id, command)
+            .with_env(env)
+            .with_workdir(self.workdir.clone())
+            .with_timeout(self.timeout)
+            .with_security(self.security.to_options()?)
+            .with_user(self.user.clone());
+
+        // An interactive session carries on if the connection drops briefly
+        if self.interactive || self.tty {
+            let exit_code = client
+                .with_reconnect(RetryConfig::for_connection())
+                .exec_interactive(config.with_tty(self.tty))?;
+            manager.detach();
+            std::process::exit(exit_code);
+        }
+
         // Execute in container
-        let (exit_code, stdout, stderr) = client.exec(
-            &self.container_id,
-            command,
-            env,
-            self.workdir.clone(),
-            self.timeout,
-        )?;
+        let out = client.exec_with_config(config)?;
 
-        // Print output
-        if !stdout.is_empty() {
-            print!("{}", stdout);
-        }
-        if !stderr.is_empty() {
-            eprint!("{}", stderr);
-        }
+        // Print output and keep microvm running
+        vm_common::print_run_output_and_exit(&manager, &out);
+    }
+}
 
-        flush_output();
+// ============================================================================
+// Diff
+// ============================================================================
+
+/// Show how a container's filesystem differs from its image.
+///
+/// Prints one path per line, prefixed with A (added), C (changed) or
+/// D (deleted).
+///
+/// Examples:
+///   smolvm container diff default abc123
+#[derive(Args, Debug)]
+pub struct ContainerDiffCmd {
+    /// Target microVM name
+    #[arg(value_name = "MICROVM")]
+    pub microvm: String,
+
+    /// Container ID (full or prefix)
+    #[arg(value_name = "CONTAINER")]
+    pub container_id: String,
+}
+
+impl ContainerDiffCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = ensure_microvm(&self.microvm)?;
+        let mut client = AgentClient::connect_with_retry(manager.vsock_socket())?;
+
+        let entries = client.diff_container(&self.container_id)?;
+        for entry in entries {
+            let marker = match entry.kind {
+                DiffKind::Added => 'A',
+                DiffKind::Changed => 'C',
+                DiffKind::Deleted => 'D',
+            };
+            println!("{} {}", marker, entry.path);
+        }
 
         // Keep microvm running
         manager.detach();
 
-        std::process::exit(exit_code);
+        Ok(())
     }
 }
@@ -0,0 +1,99 @@
+//! Inspect command.
+//!
+//! Shows what an image will run — its entrypoint, command, environment,
+//! working directory and labels — without starting a container.
+
+use crate::cli::format_bytes;
+use clap::Args;
+use smolvm::agent::{AgentClient, AgentManager};
+use smolvm_protocol::ImageInfo;
+
+/// Show an image's configuration.
+///
+/// Pulls the image into the sandbox storage first if it isn't cached.
+///
+/// Examples:
+///   smolvm inspect alpine
+///   smolvm inspect nginx:1.27 --json
+#[derive(Args, Debug)]
+pub struct InspectCmd {
+    /// Image to inspect
+    #[arg(value_name = "IMAGE")]
+    pub image: String,
+
+    /// Output in JSON format
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl InspectCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = AgentManager::new_default()?;
+
+        // Start VM if not running (needed to query storage)
+        let mut client = if manager.try_connect_existing().is_some() {
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        } else {
+            eprintln!("Starting sandbox VM to query storage...");
+            manager.start()?;
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        };
+
+        let info = client.inspect(&self.image)?;
+
+        if self.json {
+            let json = serde_json::to_string_pretty(&info)
+                .map_err(|e| smolvm::Error::config("serialize json", e.to_string()))?;
+            println!("{}", json);
+        } else {
+            print_image(&info);
+        }
+
+        Ok(())
+    }
+}
+
+fn print_image(info: &ImageInfo) {
+    println!("Image:       {}", info.reference);
+    println!("Digest:      {}", info.digest);
+    println!("Platform:    {}/{}", info.os, info.architecture);
+    println!(
+        "Size:        {} ({} layers)",
+        format_bytes(info.size),
+        info.layer_count
+    );
+    if let Some(created) = &info.created {
+        println!("Created:     {}", created);
+    }
+    println!("Entrypoint:  {}", format_argv(&info.entrypoint));
+    println!("Cmd:         {}", format_argv(&info.cmd));
+    println!(
+        "Workdir:     {}",
+        info.workdir.as_deref().unwrap_or("(none)")
+    );
+
+    println!("Env:");
+    if info.env.is_empty() {
+        println!("  (none)");
+    }
+    for var in &info.env {
+        println!("  {}", var);
+    }
+
+    println!("Labels:");
+    if info.labels.is_empty() {
+        println!("  (none)");
+    }
+    for (key, value) in &info.labels {
+        println!("  {}={}", key, value);
+    }
+}
+
+/// Format an argv list as JSON so arguments with spaces stay unambiguous.
+fn format_argv(argv: &[String]) -> String {
+    if argv.is_empty() {
+        "(none)".to_string()
+    } else {
+        serde_json::to_string(argv).unwrap_or_else(|_| argv.join(" "))
+    }
+}
@@ -0,0 +1,49 @@
+//! Load command.
+//!
+//! Brings images saved with `smolvm save` (or any OCI image layout tar
+//! whose images carry a reference name) into the sandbox storage.
+
+use clap::Args;
+use smolvm::agent::{AgentClient, AgentManager};
+use std::path::PathBuf;
+
+/// Load images from a tar archive made by `smolvm save`.
+///
+/// Each image is stored under the name it was saved as, replacing any
+/// cached image of that name. Layers already in storage are reused.
+///
+/// Examples:
+///   smolvm load -i alpine.tar
+#[derive(Args, Debug)]
+pub struct LoadCmd {
+    /// Archive to load
+    #[arg(short, long, value_name = "FILE")]
+    pub input: PathBuf,
+}
+
+impl LoadCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        if !self.input.is_file() {
+            return Err(smolvm::Error::config(
+                "load image",
+                format!("'{}' is not a file", self.input.display()),
+            ));
+        }
+
+        let manager = AgentManager::new_default()?;
+
+        // Start VM if not running (needed to reach storage)
+        let mut client = if manager.try_connect_existing().is_some() {
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        } else {
+            eprintln!("Starting sandbox VM to access storage...");
+            manager.start()?;
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        };
+
+        for info in client.load_image(&self.input)? {
+            println!("Loaded {}", info.reference);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+//! Logs command.
+//!
+//! Shows the console output of a microVM, which is where kernel and init
+//! messages go — useful for debugging VMs that fail to boot.
+
+use clap::Args;
+use smolvm::agent::vm_console_log_path;
+use smolvm::log_rotation::tail_lines;
+
+use crate::cli::vm_common;
+
+/// Default number of lines shown with `--tail`.
+const DEFAULT_TAIL_LINES: usize = 100;
+
+/// Show logs for a microVM.
+///
+/// Examples:
+///   smolvm logs --console
+///   smolvm logs myvm --console --tail 50
+#[derive(Args, Debug)]
+pub struct LogsCmd {
+    /// MicroVM to show logs for (default: "default")
+    #[arg(value_name = "NAME")]
+    pub name: Option<String>,
+
+    /// Show the VM console log (kernel and init output)
+    #[arg(long, required = true)]
+    pub console: bool,
+
+    /// Number of lines to show from the end of the log
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_TAIL_LINES)]
+    pub tail: usize,
+}
+
+impl LogsCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let name = vm_common::resolve_vm_name(self.name)?;
+        let name = name.as_deref().unwrap_or("default");
+        let log_path = vm_console_log_path(name);
+
+        let lines = tail_lines(&log_path, self.tail).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                smolvm::Error::storage(
+                    "read console log",
+                    format!("no console log for '{}' (has it been started?)", name),
+                )
+            } else {
+                smolvm::Error::storage(
+                    format!("read console log {}", log_path.display()),
+                    e.to_string(),
+                )
+            }
+        })?;
+
+        for line in lines {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+}
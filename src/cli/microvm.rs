@@ -13,6 +13,8 @@ use crate::cli::parsers::{parse_duration, parse_env_list, parse_port};
 use crate::cli::vm_common::{self, DeleteVmOptions, VmKind};
 use clap::{Args, Subcommand};
 use smolvm::agent::PortMapping;
+use smolvm::labels::{parse_label, parse_label_filter, LabelFilter};
+use std::net::IpAddr;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -113,7 +115,7 @@ impl ExecCmd {
         let (manager, mut client) =
             vm_common::ensure_running_and_connect(&self.name, vm_common::VmKind::Microvm)?;
 
-        let env = parse_env_list(&self.env);
+        let env = parse_env_list(&self.env)?;
 
         // Run command directly in VM
         if self.interactive || self.tty {
@@ -174,6 +176,10 @@ pub struct CreateCmd {
     #[arg(long, value_name = "GiB")]
     pub overlay: Option<u64>,
 
+    /// Scratch disk size in GiB, to keep container writes off the storage disk
+    #[arg(long, value_name = "GiB")]
+    pub scratch: Option<u64>,
+
     /// Mount host directory (can be used multiple times)
     #[arg(short = 'v', long = "volume", value_name = "HOST:GUEST[:ro]")]
     pub volume: Vec<String>,
@@ -186,6 +192,10 @@ pub struct CreateCmd {
     #[arg(long)]
     pub net: bool,
 
+    /// DNS server for containers, instead of the host's resolvers
+    #[arg(long, value_name = "IP")]
+    pub dns: Option<IpAddr>,
+
     /// Run command on every VM start (can be used multiple times)
     #[arg(long = "init", value_name = "COMMAND")]
     pub init: Vec<String>,
@@ -201,11 +211,15 @@ pub struct CreateCmd {
     /// Load configuration from a Smolfile (TOML)
     #[arg(long = "smolfile", visible_short_alias = 's', value_name = "PATH")]
     pub smolfile: Option<PathBuf>,
+
+    /// Set a label on the microVM (can be used multiple times)
+    #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+    pub label: Vec<(String, String)>,
 }
 
 impl CreateCmd {
     pub fn run(self) -> smolvm::Result<()> {
-        let params = crate::cli::smolfile::build_create_params(
+        let mut params = crate::cli::smolfile::build_create_params(
             self.name,
             self.cpus,
             self.mem,
@@ -218,7 +232,10 @@ impl CreateCmd {
             self.smolfile,
             self.storage,
             self.overlay,
+            self.scratch,
+            self.dns,
         )?;
+        params.labels = self.label.into_iter().collect();
         vm_common::create_vm(KIND, params)
     }
 }
@@ -338,11 +355,15 @@ pub struct LsCmd {
     /// Output in JSON format
     #[arg(long)]
     pub json: bool,
+
+    /// Only list VMs with this label, optionally set to a value (can be used multiple times)
+    #[arg(long = "label", value_name = "KEY[=VALUE]", value_parser = parse_label_filter)]
+    pub label: Vec<LabelFilter>,
 }
 
 impl LsCmd {
     pub fn run(&self) -> smolvm::Result<()> {
-        vm_common::list_vms(KIND, self.verbose, self.json)
+        vm_common::list_vms(KIND, self.verbose, self.json, &self.label)
     }
 }
 
@@ -2,15 +2,24 @@
 
 pub mod config;
 pub mod container;
+pub mod inspect;
+pub mod load;
+pub mod logs;
 pub mod microvm;
 pub mod openapi;
 pub mod pack;
 pub mod parsers;
 pub mod runpack;
 pub mod sandbox;
+pub mod save;
 pub mod serve;
 pub mod smolfile;
+pub mod stats;
+pub mod system;
+pub mod tag;
+pub mod verify;
 pub mod vm_common;
+pub mod volume;
 
 use std::io::Write;
 
@@ -69,6 +78,29 @@ pub fn flush_output() {
     let _ = std::io::stderr().flush();
 }
 
+/// Print why a command ended abnormally (signal, OOM) to stderr.
+///
+/// Timeouts are skipped since the agent already reports them in stderr.
+pub fn print_exit_reason(reason: &smolvm_protocol::ExitReason) {
+    if matches!(reason, smolvm_protocol::ExitReason::TimedOut) {
+        return;
+    }
+    if let Some(reason) = reason.describe() {
+        eprintln!("{}", reason);
+    }
+}
+
+/// Wrap an error from starting a VM with the operation that failed.
+///
+/// Boot failures are passed through unchanged so their report (exit reason,
+/// vsock state, console tail) is printed as-is.
+pub fn start_error(operation: impl Into<String>, e: smolvm::Error) -> smolvm::Error {
+    match e {
+        smolvm::Error::BootFailed(_) => e,
+        e => smolvm::Error::agent(operation, e.to_string()),
+    }
+}
+
 /// Format bytes as human-readable string (e.g., "1.5 GB", "42.0 MB").
 pub fn format_bytes(bytes: u64) -> String {
     const KB: u64 = 1024;
@@ -86,20 +118,53 @@ pub fn format_bytes(bytes: u64) -> String {
     }
 }
 
+/// Format a container's status with how long it has been in it, as of
+/// `now` (Unix epoch seconds): e.g. "running (up 5m)" or "exited (1) 2h ago".
+pub fn format_container_status(info: &smolvm_protocol::ContainerInfo, now: u64) -> String {
+    use smolvm_protocol::ContainerStatus;
+
+    let since = |at: u64| format_age(now.saturating_sub(at));
+    match (&info.status, info.started_at, info.finished_at) {
+        (ContainerStatus::Running, Some(started), _) => match info.health {
+            Some(health) => format!("running (up {}, {})", since(started), health),
+            None => format!("running (up {})", since(started)),
+        },
+        (ContainerStatus::Exited { .. }, _, Some(finished)) => {
+            format!("{} {} ago", info.status, since(finished))
+        }
+        (status, _, _) => status.to_string(),
+    }
+}
+
+/// Format a number of seconds in its largest whole unit (e.g. "42s", "5m",
+/// "3h", "2d").
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
 /// Pull an image with a CLI progress bar.
+///
+/// With `no_cache`, a cached image is pulled again and the layers that
+/// changed are listed.
 pub fn pull_with_progress(
     client: &mut smolvm::agent::AgentClient,
     image: &str,
     oci_platform: Option<&str>,
+    no_cache: bool,
 ) -> smolvm::Result<smolvm_protocol::ImageInfo> {
     print!("Pulling image {}...", image);
     let _ = std::io::stdout().flush();
 
     let mut last_percent = 0u8;
-    let result = client.pull_with_registry_config_and_progress(
-        image,
-        oci_platform,
-        |percent, _total, _layer| {
+    let mut options = smolvm::agent::PullOptions::new()
+        .use_registry_config(true)
+        .no_cache(no_cache)
+        .progress(|percent, _total, _layer: &str| {
             let percent = percent as u8;
             if percent != last_percent && percent <= 100 {
                 print!("\rPulling image {}... [", image);
@@ -117,11 +182,114 @@ pub fn pull_with_progress(
                 let _ = std::io::stdout().flush();
                 last_percent = percent;
             }
-        },
-    );
+        });
+    if let Some(p) = oci_platform {
+        options = options.oci_platform(p);
+    }
+    let result = client.pull(image, options);
+    let outcome = if result.is_ok() { "done." } else { "failed." };
     println!(
-        "\rPulling image {}... done.                              ",
-        image
+        "\rPulling image {}... {}                              ",
+        image, outcome
     );
+    match &result {
+        Ok(info) if no_cache => {
+            println!("Refreshed {} layer(s)", info.refreshed_layers.len());
+            for layer in &info.refreshed_layers {
+                println!("  {}", layer);
+            }
+        }
+        Err(e) => {
+            if let Some(hint) = pull_error_hint(e) {
+                eprintln!("{}", hint);
+            }
+        }
+        Ok(_) => {}
+    }
     result
 }
+
+/// Advice for fixing a failed pull, if there is any beyond the error.
+fn pull_error_hint(err: &smolvm::Error) -> Option<String> {
+    match err {
+        smolvm::Error::PlatformUnavailable { available, .. } => {
+            let example = available.first()?;
+            Some(format!(
+                "hint: pick a platform with --oci-platform, e.g. --oci-platform {}",
+                example
+            ))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smolvm_protocol::{ContainerInfo, ContainerStatus};
+
+    #[test]
+    fn test_format_container_status() {
+        let mut info = ContainerInfo {
+            id: "c1".into(),
+            image: "alpine".into(),
+            state: "created".into(),
+            created_at: 1000,
+            command: vec![],
+            restart_count: 0,
+            status: ContainerStatus::Created,
+            started_at: None,
+            finished_at: None,
+            labels: Default::default(),
+            health: None,
+        };
+        assert_eq!(format_container_status(&info, 2000), "created");
+
+        info.status = ContainerStatus::Running;
+        info.started_at = Some(1100);
+        assert_eq!(format_container_status(&info, 1400), "running (up 5m)");
+        info.health = Some(smolvm_protocol::HealthStatus::Unhealthy);
+        assert_eq!(
+            format_container_status(&info, 1400),
+            "running (up 5m, unhealthy)"
+        );
+
+        info.status = ContainerStatus::Exited { code: Some(1) };
+        info.finished_at = Some(1400);
+        assert_eq!(
+            format_container_status(&info, 1400 + 7200),
+            "exited (1) 2h ago"
+        );
+
+        info.status = ContainerStatus::Error {
+            message: "no such file".into(),
+        };
+        assert_eq!(format_container_status(&info, 9000), "error");
+    }
+
+    #[test]
+    fn test_platform_unavailable_message_and_hint() {
+        let err = smolvm::Error::PlatformUnavailable {
+            platform: Some("linux/riscv64".to_string()),
+            available: vec!["linux/amd64".to_string(), "linux/arm64".to_string()],
+        };
+        assert_eq!(
+            err.to_string(),
+            "platform linux/riscv64 not available; available: linux/amd64, linux/arm64"
+        );
+        assert_eq!(
+            pull_error_hint(&err).as_deref(),
+            Some("hint: pick a platform with --oci-platform, e.g. --oci-platform linux/amd64")
+        );
+
+        let err = smolvm::Error::PlatformUnavailable {
+            platform: None,
+            available: Vec::new(),
+        };
+        assert_eq!(pull_error_hint(&err), None);
+        assert_eq!(
+            pull_error_hint(&smolvm::Error::agent("pull image", "boom")),
+            None
+        );
+    }
+}
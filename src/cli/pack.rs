@@ -7,7 +7,9 @@
 //! - OCI image layers
 //! - Configuration manifest
 
+use crate::cli::format_bytes;
 use clap::Args;
+use smolvm::agent::resource_hints;
 use smolvm::agent::{AgentClient, AgentManager, PullOptions, VmResources};
 
 /// Default memory for packed VMs (lower than sandbox/microvm because
@@ -16,7 +18,9 @@ const PACK_DEFAULT_MEMORY_MIB: u32 = 256;
 use smolvm::config::{RecordState, SmolvmConfig};
 use smolvm::platform::{Arch, Os, VmExecutor};
 use smolvm::Error;
-use smolvm_pack::assets::AssetCollector;
+use smolvm_pack::assets::{
+    default_compression_cache_dir, AssetCollector, ZSTD_LEVEL, ZSTD_LEVEL_RANGE,
+};
 use smolvm_pack::format::{PackManifest, PackMode};
 use smolvm_pack::packer::Packer;
 use smolvm_pack::signing::sign_with_hypervisor_entitlements;
@@ -56,20 +60,25 @@ pub struct PackCmd {
     #[arg(short = 'o', long, value_name = "PATH")]
     pub output: PathBuf,
 
-    /// Default number of vCPUs for the packed VM
-    #[arg(long, default_value_t = smolvm::agent::DEFAULT_CPUS, value_name = "N")]
-    pub cpus: u8,
+    /// Default number of vCPUs for the packed VM [default: the image's
+    /// org.smolvm.cpus label, or 1]
+    #[arg(long, value_name = "N")]
+    pub cpus: Option<u8>,
 
-    /// Default memory in MiB for the packed VM (lower than sandbox/microvm
-    /// because packed VMs are typically single-purpose, minimal workloads)
-    #[arg(long, default_value_t = PACK_DEFAULT_MEMORY_MIB, value_name = "MiB")]
-    pub mem: u32,
+    /// Default memory in MiB for the packed VM [default: the image's
+    /// org.smolvm.memory-mib label, or 256]
+    #[arg(long, value_name = "MiB")]
+    pub mem: Option<u32>,
 
     /// Target OCI platform for multi-arch images (e.g., linux/arm64, linux/amd64)
     ///
     /// By default, uses the host architecture. Use this to override, for example
     /// to pack x86_64 images for Rosetta on Apple Silicon.
-    #[arg(long = "oci-platform", value_name = "OS/ARCH")]
+    #[arg(
+        long = "oci-platform",
+        visible_alias = "platform",
+        value_name = "OS/ARCH"
+    )]
     pub oci_platform: Option<String>,
 
     /// Override the image entrypoint
@@ -84,9 +93,35 @@ pub struct PackCmd {
     ///
     /// Creates one executable instead of binary + .smolmachine sidecar.
     /// Simpler to distribute but may have issues with macOS notarization.
-    #[arg(long)]
+    #[arg(long, visible_alias = "embedded")]
     pub single_file: bool,
 
+    /// zstd compression level for the assets (1-22; lower packs faster,
+    /// higher packs smaller)
+    #[arg(
+        long,
+        value_name = "LEVEL",
+        default_value_t = ZSTD_LEVEL,
+        value_parser = clap::value_parser!(i32)
+            .range(i64::from(*ZSTD_LEVEL_RANGE.start())..=i64::from(*ZSTD_LEVEL_RANGE.end()))
+    )]
+    pub compression_level: i32,
+
+    /// Print the resulting sizes for both modes without writing the output
+    #[arg(long)]
+    pub plan: bool,
+
+    /// Compress every layer again instead of reusing layers compressed by
+    /// earlier packs
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Train a zstd dictionary on the image layers and compress the assets
+    /// with it (smaller for images with many similar small layers; layers
+    /// are not cached)
+    #[arg(long)]
+    pub train_dictionary: bool,
+
     /// Path to stub executable (defaults to built-in)
     #[arg(long, value_name = "PATH", hide = true)]
     pub stub: Option<PathBuf>,
@@ -188,6 +223,10 @@ impl PackCmd {
                 network: true,
                 storage_gb: None,
                 overlay_gb: None,
+                scratch_gb: None,
+                dns: None,
+                fallback_dns: None,
+                layer_dedup: false,
             },
         )?;
         let mut guard = PackVmGuard {
@@ -242,8 +281,13 @@ impl PackCmd {
         // Build manifest
         let platform = format!("{}/{}", image_info.os, image_info.architecture);
         let mut manifest = PackManifest::new(image, image_info.digest.clone(), platform);
-        manifest.cpus = self.cpus;
-        manifest.mem = self.mem;
+        (manifest.cpus, manifest.mem) = resource_hints::resolve_resources(
+            self.cpus,
+            self.mem,
+            &image_info.labels,
+            smolvm::agent::DEFAULT_CPUS,
+            PACK_DEFAULT_MEMORY_MIB,
+        )?;
 
         // Copy OCI config fields from image (CMD, ENTRYPOINT, ENV, WORKDIR)
         manifest.entrypoint = image_info.entrypoint.clone();
@@ -256,7 +300,7 @@ impl PackCmd {
             manifest.entrypoint = vec![ep.clone()];
         }
 
-        self.finalize_pack(manifest, collector, staging_dir)
+        self.finalize_pack(manifest, collector)
     }
 
     /// Pack from a stopped VM's overlay disk.
@@ -313,8 +357,8 @@ impl PackCmd {
         let mut manifest =
             PackManifest::new(format!("vm://{}", vm_name), "none".to_string(), platform);
         manifest.mode = PackMode::Vm;
-        manifest.cpus = self.cpus;
-        manifest.mem = self.mem;
+        manifest.cpus = self.cpus.unwrap_or(smolvm::agent::DEFAULT_CPUS);
+        manifest.mem = self.mem.unwrap_or(PACK_DEFAULT_MEMORY_MIB);
         manifest.entrypoint = vec!["/bin/sh".to_string()];
 
         // Inherit env/workdir from VmRecord
@@ -326,7 +370,7 @@ impl PackCmd {
             manifest.entrypoint = vec![ep.clone()];
         }
 
-        self.finalize_pack(manifest, collector, staging_dir)
+        self.finalize_pack(manifest, collector)
     }
 
     /// Collect base assets shared by both image and VM packing modes:
@@ -355,20 +399,48 @@ impl PackCmd {
     /// Finalize pack: set inventory, assemble binary, print summary, and sign.
     fn finalize_pack(
         &self,
-        mut manifest: PackManifest,
+        manifest: PackManifest,
         collector: AssetCollector,
-        staging_dir: PathBuf,
     ) -> smolvm::Result<()> {
         let stub_path = self.find_smolvm_binary()?;
 
-        manifest.assets = collector.into_inventory();
-
-        let collector = AssetCollector::new(staging_dir)
-            .map_err(|e| Error::agent("collect assets", e.to_string()))?;
+        let collector = collector.with_compression_level(self.compression_level);
+        let collector = match default_compression_cache_dir() {
+            Some(cache) if !self.no_cache => collector.with_compression_cache(cache),
+            _ => collector,
+        };
+        let collector = if self.train_dictionary {
+            collector.with_trained_dictionary()
+        } else {
+            collector
+        };
 
         let packer = Packer::new(manifest)
             .with_stub(&stub_path)
-            .with_asset_collector(collector);
+            .with_assets(collector);
+
+        if self.plan {
+            let plan = packer
+                .plan()
+                .map_err(|e| Error::agent("plan pack", e.to_string()))?;
+            println!("{:<10} {:>12} {:>12}", "", "SIDECAR", "SINGLE-FILE");
+            let rows = [
+                ("stub", plan.stub_size, plan.stub_size),
+                ("assets", plan.assets_size, plan.assets_size),
+                ("manifest", plan.manifest_size, plan.manifest_size),
+                ("total", plan.sidecar_total_size, plan.embedded_total_size),
+            ];
+            for (name, sidecar, embedded) in rows {
+                println!(
+                    "{:<10} {:>12} {:>12}",
+                    name,
+                    format_bytes(sidecar),
+                    format_bytes(embedded)
+                );
+            }
+            println!("\nNothing written (--plan)");
+            return Ok(());
+        }
 
         let info = if self.single_file {
             println!("Assembling single-file packed binary...");
@@ -582,8 +654,12 @@ impl PackCmd {
                         return Ok(result);
                     }
                 }
-                AgentResponse::Error { message, .. } => {
-                    return Err(Error::agent("export layer", message));
+                AgentResponse::Error { message, code, .. } => {
+                    return Err(Error::agent_response(
+                        "export layer",
+                        message,
+                        code.as_deref(),
+                    ));
                 }
                 _ => {
                     return Err(Error::agent("export layer", "unexpected response type"));
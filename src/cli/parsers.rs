@@ -3,12 +3,19 @@
 //! This module consolidates parser functions used across multiple CLI commands
 //! to eliminate code duplication and ensure consistent validation.
 
-use smolvm::agent::PortMapping;
+use clap::Args;
+use smolvm::agent::{PortMapping, ResourceLimits};
+use smolvm::mount::{normalize_guest_path, parse_mount_spec, split_mount_spec};
 use smolvm::vm::config::HostMount;
 use smolvm::Error;
-use std::path::PathBuf;
+use smolvm_protocol::{SecurityOptions, TmpfsMount};
+use std::net::IpAddr;
+use std::path::Path;
 use std::time::Duration;
 
+/// Agent mount binding: (mount_tag, container_path, read_only).
+type AgentBinding = (String, String, bool);
+
 /// Parse a duration string (e.g., "30s", "5m", "1h").
 pub fn parse_duration(s: &str) -> Result<Duration, humantime::DurationError> {
     humantime::parse_duration(s)
@@ -30,73 +37,367 @@ pub fn parse_port(s: &str) -> Result<PortMapping, String> {
     }
 }
 
+/// Parse a container CPU limit in cores (e.g., "2", "0.5").
+pub fn parse_cpu_limit(s: &str) -> Result<f64, String> {
+    let cpus: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid CPU limit: {}", s))?;
+    if !cpus.is_finite() || cpus <= 0.0 {
+        return Err(format!("CPU limit must be greater than zero: {}", s));
+    }
+    Ok(cpus)
+}
+
+/// Container cgroup limit flags shared by run commands.
+///
+/// Limits are container-level: exec'd processes share the container's
+/// cgroup, so exec commands don't take them.
+#[derive(Args, Debug, Default)]
+pub struct ResourceLimitArgs {
+    /// Limit the container's memory in MiB (it is OOM-killed if exceeded)
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u64).range(1..),
+        value_name = "MiB",
+        help_heading = "Resources"
+    )]
+    pub memory_limit: Option<u64>,
+
+    /// Limit the container's CPU usage in cores (e.g., 0.5)
+    #[arg(
+        long,
+        value_parser = parse_cpu_limit,
+        value_name = "N",
+        help_heading = "Resources"
+    )]
+    pub cpu_limit: Option<f64>,
+}
+
+impl ResourceLimitArgs {
+    /// The limits to send with the request.
+    pub fn to_limits(&self) -> ResourceLimits {
+        ResourceLimits::new(self.memory_limit, self.cpu_limit)
+    }
+}
+
+/// Privilege restriction flags shared by run and exec commands.
+#[derive(Args, Debug, Default)]
+pub struct SecurityArgs {
+    /// Drop a capability from the default set; ALL drops every one (repeatable)
+    #[arg(long = "cap-drop", value_name = "CAP", help_heading = "Security")]
+    pub cap_drop: Vec<String>,
+
+    /// Add a capability after --cap-drop, e.g. NET_BIND_SERVICE (repeatable)
+    #[arg(long = "cap-add", value_name = "CAP", help_heading = "Security")]
+    pub cap_add: Vec<String>,
+
+    /// Security option: no-new-privileges, or seccomp=PATH to allow only the
+    /// syscalls listed in PATH (repeatable)
+    #[arg(long = "security-opt", value_name = "OPT", help_heading = "Security")]
+    pub security_opt: Vec<String>,
+}
+
+impl SecurityArgs {
+    /// Build the protocol options, loading any seccomp profile from disk.
+    pub fn to_options(&self) -> smolvm::Result<SecurityOptions> {
+        let mut options = SecurityOptions {
+            cap_drop: self.cap_drop.clone(),
+            cap_add: self.cap_add.clone(),
+            ..Default::default()
+        };
+        for opt in &self.security_opt {
+            match opt.split_once(['=', ':']) {
+                None if opt == "no-new-privileges" => options.no_new_privileges = true,
+                Some(("no-new-privileges", value)) => {
+                    options.no_new_privileges = value.parse().map_err(|_| {
+                        Error::config(
+                            "parse security option",
+                            format!("'{}': expected true or false", opt),
+                        )
+                    })?;
+                }
+                Some(("seccomp", path)) => {
+                    options.seccomp_allow = Some(load_seccomp_allowlist(Path::new(path))?);
+                }
+                _ => {
+                    return Err(Error::config(
+                        "parse security option",
+                        format!("'{}': expected no-new-privileges or seccomp=PATH", opt),
+                    ))
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Load a seccomp allowlist from `path`.
+///
+/// The file is either plain text with one syscall name per line (`#` starts
+/// a comment), or a Docker/OCI-style JSON profile whose unconditional
+/// `SCMP_ACT_ALLOW` rules are collected. Rules with argument conditions are
+/// skipped, so those syscalls are denied unless also allowed outright.
+pub fn load_seccomp_allowlist(path: &Path) -> smolvm::Result<Vec<String>> {
+    let op = || format!("load seccomp profile {}", path.display());
+    let content = std::fs::read_to_string(path).map_err(|e| Error::config(op(), e.to_string()))?;
+
+    let names: Vec<String> = if content.trim_start().starts_with('{') {
+        let profile: serde_json::Value =
+            serde_json::from_str(&content).map_err(|e| Error::config(op(), e.to_string()))?;
+        if profile["defaultAction"] == "SCMP_ACT_ALLOW" {
+            return Err(Error::config(
+                op(),
+                "profile allows syscalls by default; only allowlist profiles are supported",
+            ));
+        }
+        profile["syscalls"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|rule| rule["action"] == "SCMP_ACT_ALLOW")
+            .filter(|rule| rule["args"].as_array().is_none_or(|args| args.is_empty()))
+            .flat_map(|rule| rule["names"].as_array().into_iter().flatten())
+            .filter_map(|name| name.as_str().map(String::from))
+            .collect()
+    } else {
+        content
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or("").trim())
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect()
+    };
+
+    if names.is_empty() {
+        return Err(Error::config(op(), "profile allows no syscalls"));
+    }
+    Ok(names)
+}
+
 /// Parse an environment variable specification (KEY=VALUE).
-pub fn parse_env_spec(spec: &str) -> Option<(String, String)> {
-    let (key, value) = spec.split_once('=')?;
-    if key.is_empty() {
-        None
+pub fn parse_env_spec(spec: &str) -> Result<(String, String), String> {
+    match spec.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        Some(_) => Err(format!(
+            "invalid environment variable '{}': empty name",
+            spec
+        )),
+        None => Err(format!(
+            "invalid environment variable '{}': expected KEY=VALUE",
+            spec
+        )),
+    }
+}
+
+/// Parse environment variables from CLI args, failing on the first that
+/// isn't KEY=VALUE.
+pub fn parse_env_list(env_args: &[String]) -> smolvm::Result<Vec<(String, String)>> {
+    env_args
+        .iter()
+        .map(|e| parse_env_spec(e).map_err(|e| Error::config("parse environment", e)))
+        .collect()
+}
+
+/// Parse an `--entrypoint` value into the entrypoint argv.
+///
+/// An empty value clears the image entrypoint, so the command runs as given.
+pub fn parse_entrypoint(entrypoint: &str) -> Vec<String> {
+    if entrypoint.is_empty() {
+        Vec::new()
     } else {
-        Some((key.to_string(), value.to_string()))
+        vec![entrypoint.to_string()]
     }
 }
 
-/// Parse environment variables from CLI args.
-pub fn parse_env_list(env_args: &[String]) -> Vec<(String, String)> {
-    env_args.iter().filter_map(|e| parse_env_spec(e)).collect()
+/// Parse a `--tmpfs` specification: `PATH[:size=N]`.
+///
+/// The size is in bytes, or KiB/MiB/GiB with a `k`, `m` or `g` suffix
+/// (e.g. `/scratch:size=64m`); the kernel default applies if it's omitted.
+pub fn parse_tmpfs(spec: &str) -> Result<TmpfsMount, String> {
+    let (path, options) = match spec.split_once(':') {
+        Some((path, options)) => (path, Some(options)),
+        None => (spec, None),
+    };
+    let path = normalize_guest_path(Path::new(path))?;
+
+    let mut size_bytes = None;
+    for option in options.into_iter().flat_map(|o| o.split(',')) {
+        match option.split_once('=') {
+            Some(("size", size)) => size_bytes = Some(parse_tmpfs_size(size)?),
+            _ => {
+                return Err(format!(
+                    "unknown tmpfs option '{}' (only size=N is supported)",
+                    option
+                ))
+            }
+        }
+    }
+
+    Ok(TmpfsMount {
+        path: path.to_string_lossy().into_owned(),
+        size_bytes,
+    })
+}
+
+/// Parse an `--add-host` specification: `HOSTNAME:IP`, as docker takes it.
+///
+/// IPv6 addresses may be bracketed (`db:[fd00::1]`).
+pub fn parse_add_host(spec: &str) -> Result<(String, IpAddr), String> {
+    let (hostname, address) = spec
+        .split_once(':')
+        .ok_or_else(|| format!("invalid host '{}' (expected HOSTNAME:IP)", spec))?;
+    smolvm_protocol::validate_hostname(hostname)?;
+    let address = address
+        .strip_prefix('[')
+        .and_then(|a| a.strip_suffix(']'))
+        .unwrap_or(address);
+    let address = address
+        .parse()
+        .map_err(|_| format!("invalid IP address '{}' for host '{}'", address, hostname))?;
+    Ok((hostname.to_string(), address))
+}
+
+fn parse_tmpfs_size(size: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid tmpfs size '{}' (e.g. 65536, 512k, 64m, 1g)", size);
+    let (digits, unit) = match size.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&size[..i], c.to_ascii_lowercase()),
+        _ => (size, 'b'),
+    };
+    let shift = match unit {
+        'b' => 0,
+        'k' => 10,
+        'm' => 20,
+        'g' => 30,
+        _ => return Err(invalid()),
+    };
+    let n: u64 = digits.parse().map_err(|_| invalid())?;
+    if n == 0 {
+        return Err(format!("tmpfs size must be greater than zero: {}", size));
+    }
+    n.checked_mul(1 << shift).ok_or_else(invalid)
 }
 
 /// Parse volume mount specifications into HostMount structs.
 ///
-/// Format: `host_path:container_path[:ro]`
+/// Format: `host_path:container_path[:ro|:rw]`
 ///
-/// Validates that the host path exists and is a directory.
+/// Validates that the host path exists and is a directory, and that the
+/// container path is absolute and doesn't shadow a reserved mountpoint.
 pub fn parse_mounts(specs: &[String]) -> smolvm::Result<Vec<HostMount>> {
-    specs.iter().map(|spec| parse_mount_spec(spec)).collect()
+    specs
+        .iter()
+        .map(|spec| {
+            if is_named_volume_spec(spec)? {
+                return Err(Error::invalid_mount_spec(
+                    spec.as_str(),
+                    "named volumes can only be mounted into containers",
+                ));
+            }
+            parse_mount_spec(spec)
+        })
+        .collect()
 }
 
-/// Parse a single mount specification.
-fn parse_mount_spec(spec: &str) -> smolvm::Result<HostMount> {
-    let parts: Vec<&str> = spec.split(':').collect();
-    if parts.len() < 2 {
-        return Err(Error::mount(
-            "parse volume spec",
-            format!("invalid format '{}': expected host:container[:ro]", spec),
-        ));
+/// Parse volume specifications for a container, separating host directory
+/// mounts from named volumes.
+///
+/// A spec whose source is a volume name rather than a path (e.g.
+/// `data:/var/lib/data`) refers to a named volume managed by the agent;
+/// see [`is_named_volume_spec`].
+///
+/// Returns the host mounts (which need virtiofs devices on the VM) and the
+/// named volume bindings in agent format `(volume_tag, container_path, read_only)`.
+pub fn parse_container_mounts(
+    specs: &[String],
+) -> smolvm::Result<(Vec<HostMount>, Vec<AgentBinding>)> {
+    let mut host_mounts = Vec::new();
+    let mut volumes = Vec::new();
+    for spec in specs {
+        if is_named_volume_spec(spec)? {
+            volumes.push(parse_named_volume_spec(spec)?);
+        } else {
+            host_mounts.push(parse_mount_spec(spec)?);
+        }
     }
+    Ok((host_mounts, volumes))
+}
 
-    let host_path = PathBuf::from(parts[0]);
-    let guest_path = PathBuf::from(parts[1]);
-    let read_only = parts.get(2).map(|&s| s == "ro").unwrap_or(false);
-
-    // Validate host path exists
-    if !host_path.exists() {
-        return Err(Error::mount(
-            "validate host path",
-            format!("path does not exist: {}", host_path.display()),
-        ));
+/// Check whether a `-v` spec refers to a named volume rather than a host path.
+///
+/// Only a source that is a valid volume name can be one. A name that is
+/// also a file or directory in the current directory is ambiguous, so it
+/// is rejected rather than silently picking one; `./name` mounts the path.
+fn is_named_volume_spec(spec: &str) -> smolvm::Result<bool> {
+    let source = spec.split(':').next().unwrap_or_default();
+    if !is_volume_name(source) {
+        return Ok(false);
     }
-
-    // Must be a directory (virtiofs limitation)
-    if !host_path.is_dir() {
-        return Err(Error::mount(
-            "validate host path",
+    if Path::new(source).exists() {
+        return Err(Error::invalid_mount_spec(
+            spec,
             format!(
-                "path must be a directory (virtiofs limitation): {}",
-                host_path.display()
+                "'{}' is both a volume name and a local path; use ./{} to mount the path",
+                source, source
             ),
         ));
     }
+    Ok(true)
+}
 
-    // Canonicalize host path
-    let host_path = host_path
-        .canonicalize()
-        .map_err(|e| Error::mount("canonicalize host path", format!("'{}': {}", parts[0], e)))?;
+/// Whether `name` is a valid volume name: an alphanumeric followed by
+/// alphanumerics, `_`, `.` and `-`.
+fn is_volume_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
 
-    Ok(if read_only {
-        HostMount::new(host_path, guest_path)
-    } else {
-        HostMount::new_writable(host_path, guest_path)
-    })
+/// Parse a named volume specification (`name:container_path[:ro|:rw]`).
+fn parse_named_volume_spec(spec: &str) -> smolvm::Result<(String, String, bool)> {
+    let (name, guest_path, read_only) = split_mount_spec(spec)?;
+    Ok((
+        smolvm::agent::volume_mount_tag(name),
+        guest_path.to_string_lossy().to_string(),
+        read_only,
+    ))
+}
+
+/// Default guest path for `--cwd`.
+pub const DEFAULT_CWD_TARGET: &str = "/work";
+
+/// Mount the host's current directory read-write at `guest` (for `--cwd`).
+///
+/// The mount is appended after the explicit `-v` mounts so their virtiofs
+/// tags don't shift. Fails if an explicit host mount or named volume
+/// already targets `guest`. Returns the normalized guest path.
+pub fn add_cwd_mount(
+    mounts: &mut Vec<HostMount>,
+    volumes: &[(String, String, bool)],
+    guest: &str,
+) -> smolvm::Result<String> {
+    let spec = format!("--cwd={}", guest);
+    let invalid = |reason: String| Error::invalid_mount_spec(spec.as_str(), reason);
+
+    let guest_path = normalize_guest_path(Path::new(guest)).map_err(invalid)?;
+    let taken = mounts.iter().any(|m| m.target == guest_path)
+        || volumes
+            .iter()
+            .any(|(_, target, _)| Path::new(target) == guest_path);
+    if taken {
+        return Err(invalid(format!(
+            "{} is already the target of a -v mount",
+            guest_path.display()
+        )));
+    }
+
+    let cwd = std::env::current_dir()
+        .and_then(|dir| dir.canonicalize())
+        .map_err(|e| invalid(format!("current directory: {}", e)))?;
+    let guest = guest_path.to_string_lossy().to_string();
+    mounts.push(HostMount::new_writable(cwd, guest_path));
+    Ok(guest)
 }
 
 /// Parse mounts and convert to tuple format for database storage.
@@ -118,8 +419,12 @@ pub fn parse_mounts_as_tuples(specs: &[String]) -> smolvm::Result<Vec<(String, S
 /// Parse mounts and convert to virtiofs binding format for agent.
 ///
 /// Returns tuples of (virtiofs_tag, container_path, read_only).
+/// Named volumes are included using their volume tag.
 pub fn parse_mounts_to_bindings(specs: &[String]) -> smolvm::Result<Vec<(String, String, bool)>> {
-    parse_mounts(specs).map(|mounts| mounts_to_virtiofs_bindings(&mounts))
+    let (mounts, volumes) = parse_container_mounts(specs)?;
+    let mut bindings = mounts_to_virtiofs_bindings(&mounts);
+    bindings.extend(volumes);
+    Ok(bindings)
 }
 
 /// Convert parsed HostMount list to virtiofs binding format for agent.
@@ -139,3 +444,263 @@ pub fn mounts_to_virtiofs_bindings(mounts: &[HostMount]) -> Vec<(String, String,
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn assert_invalid_spec(spec: &str) {
+        match parse_mount_spec(spec) {
+            Err(Error::InvalidMountSpec { spec: got, .. }) => assert_eq!(got, spec),
+            other => panic!("expected InvalidMountSpec for '{}', got {:?}", spec, other),
+        }
+    }
+
+    #[test]
+    fn test_parse_env_spec() {
+        assert_eq!(
+            parse_env_spec("MODE=prod=1").unwrap(),
+            ("MODE".to_string(), "prod=1".to_string())
+        );
+        assert_eq!(
+            parse_env_spec("EMPTY=").unwrap(),
+            ("EMPTY".to_string(), String::new())
+        );
+        assert!(parse_env_spec("FOO").unwrap_err().contains("KEY=VALUE"));
+        assert!(parse_env_spec("=value").is_err());
+        assert!(parse_env_list(&["A=1".to_string(), "B".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_parse_cpu_limit() {
+        assert_eq!(parse_cpu_limit("2").unwrap(), 2.0);
+        assert_eq!(parse_cpu_limit("0.5").unwrap(), 0.5);
+        assert!(parse_cpu_limit("0").is_err());
+        assert!(parse_cpu_limit("-1").is_err());
+        assert!(parse_cpu_limit("inf").is_err());
+        assert!(parse_cpu_limit("two").is_err());
+    }
+
+    #[test]
+    fn test_parse_tmpfs() {
+        let mount = parse_tmpfs("/scratch").unwrap();
+        assert_eq!(mount.path, "/scratch");
+        assert_eq!(mount.size_bytes, None);
+
+        let mount = parse_tmpfs("/run/app/:size=64m").unwrap();
+        assert_eq!(mount.path, "/run/app");
+        assert_eq!(mount.size_bytes, Some(64 * 1024 * 1024));
+        assert_eq!(
+            parse_tmpfs("/tmp:size=512K").unwrap().size_bytes,
+            Some(512 * 1024)
+        );
+        assert_eq!(
+            parse_tmpfs("/tmp:size=4096").unwrap().size_bytes,
+            Some(4096)
+        );
+    }
+
+    #[test]
+    fn test_parse_tmpfs_rejects_invalid_specs() {
+        for (spec, reason) in [
+            ("tmp", "absolute"),
+            ("/proc/x", "reserved"),
+            ("/tmp:size=0", "greater than zero"),
+            ("/tmp:size=", "invalid tmpfs size"),
+            ("/tmp:size=10x", "invalid tmpfs size"),
+            ("/tmp:size=-1m", "invalid tmpfs size"),
+            ("/tmp:size=99999999999g", "invalid tmpfs size"),
+            ("/tmp:mode=1777", "unknown tmpfs option"),
+        ] {
+            let err = parse_tmpfs(spec).expect_err(&format!("{} accepted", spec));
+            assert!(err.contains(reason), "{}: {}", spec, err);
+        }
+    }
+
+    #[test]
+    fn test_parse_add_host() {
+        assert_eq!(
+            parse_add_host("db:10.0.0.5").unwrap(),
+            ("db".to_string(), "10.0.0.5".parse().unwrap())
+        );
+        assert_eq!(
+            parse_add_host("mock.api:fd00::1").unwrap(),
+            ("mock.api".to_string(), "fd00::1".parse().unwrap())
+        );
+        assert_eq!(
+            parse_add_host("mock.api:[fd00::1]").unwrap().1,
+            "fd00::1".parse::<IpAddr>().unwrap()
+        );
+
+        for (spec, reason) in [
+            ("db", "expected HOSTNAME:IP"),
+            (":10.0.0.5", "invalid hostname"),
+            ("bad_host:10.0.0.5", "invalid hostname"),
+            ("db:", "invalid IP address"),
+            ("db:10.0.0.256", "invalid IP address"),
+            ("db:example.com", "invalid IP address"),
+        ] {
+            let err = parse_add_host(spec).expect_err(&format!("{} accepted", spec));
+            assert!(err.contains(reason), "{}: {}", spec, err);
+        }
+    }
+
+    #[test]
+    fn test_security_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = dir.path().join("allow.txt");
+        std::fs::write(&text, "# base\nread\nwrite  # output\n\nexecve\n").unwrap();
+        let json = dir.path().join("profile.json");
+        std::fs::write(
+            &json,
+            r#"{"defaultAction":"SCMP_ACT_ERRNO","syscalls":[
+                {"names":["read","write"],"action":"SCMP_ACT_ALLOW"},
+                {"names":["clone"],"action":"SCMP_ACT_ALLOW","args":[{"index":0}]},
+                {"names":["ptrace"],"action":"SCMP_ACT_ERRNO"}]}"#,
+        )
+        .unwrap();
+
+        let args = SecurityArgs {
+            cap_drop: vec!["ALL".to_string()],
+            cap_add: vec!["NET_BIND_SERVICE".to_string()],
+            security_opt: vec![
+                "no-new-privileges".to_string(),
+                format!("seccomp={}", text.display()),
+            ],
+        };
+        let options = args.to_options().unwrap();
+        assert_eq!(options.cap_drop, ["ALL"]);
+        assert!(options.no_new_privileges);
+        assert_eq!(
+            options.seccomp_allow.as_deref().unwrap(),
+            ["read", "write", "execve"]
+        );
+
+        assert_eq!(load_seccomp_allowlist(&json).unwrap(), ["read", "write"]);
+        assert!(SecurityArgs::default().to_options().unwrap().is_empty());
+
+        let bad = SecurityArgs {
+            security_opt: vec!["apparmor=unconfined".to_string()],
+            ..Default::default()
+        };
+        assert!(bad.to_options().is_err());
+    }
+
+    #[test]
+    fn test_parse_mount_spec_missing_colon() {
+        assert_invalid_spec("/tmp");
+        assert_invalid_spec("");
+    }
+
+    #[test]
+    fn test_parse_mount_spec_read_only_suffix() {
+        let tmp = tempfile::tempdir().unwrap();
+        let host = tmp.path().to_string_lossy();
+
+        let mount = parse_mount_spec(&format!("{}:/data:ro", host)).unwrap();
+        assert!(mount.read_only);
+        assert_eq!(mount.source, tmp.path().canonicalize().unwrap());
+        assert_eq!(mount.target, PathBuf::from("/data"));
+
+        let mount = parse_mount_spec(&format!("{}:/data/", host)).unwrap();
+        assert!(!mount.read_only);
+        assert_eq!(mount.target, PathBuf::from("/data"));
+
+        assert_invalid_spec(&format!("{}:/data:rx", host));
+    }
+
+    #[test]
+    fn test_parse_mount_spec_rejects_relative_host_path() {
+        // Relative host paths that don't resolve must fail at parse time,
+        // not deep inside virtiofs setup
+        assert_invalid_spec("does-not-exist-smolvm-test:/data");
+    }
+
+    #[test]
+    fn test_add_cwd_mount() {
+        let tmp = tempfile::tempdir().unwrap();
+        let specs = vec![
+            format!("{}:/src:ro", tmp.path().display()),
+            "data:/var/lib/data".to_string(),
+        ];
+        let (mut mounts, volumes) = parse_container_mounts(&specs).unwrap();
+
+        let guest = add_cwd_mount(&mut mounts, &volumes, DEFAULT_CWD_TARGET).unwrap();
+        assert_eq!(guest, "/work");
+        let config = smolvm::agent::RunConfig::new("alpine", vec![])
+            .with_mounts(mounts_to_virtiofs_bindings(&mounts));
+        assert_eq!(
+            config.mounts,
+            [
+                ("smolvm0".to_string(), "/src".to_string(), true),
+                ("smolvm1".to_string(), "/work".to_string(), false),
+            ]
+        );
+        assert_eq!(
+            mounts[1].source,
+            std::env::current_dir().unwrap().canonicalize().unwrap()
+        );
+
+        // Explicit mounts keep their target; --cwd must pick another
+        assert!(add_cwd_mount(&mut mounts, &volumes, "/src/").is_err());
+        assert!(add_cwd_mount(&mut mounts, &volumes, "/var/lib/data").is_err());
+        assert!(add_cwd_mount(&mut mounts, &volumes, "work").is_err());
+        assert_eq!(mounts.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_container_mounts_named_volume() {
+        let tmp = tempfile::tempdir().unwrap();
+        let specs = vec![
+            format!("{}:/src", tmp.path().display()),
+            "data:/var/lib/data".to_string(),
+            "cache:/cache:ro".to_string(),
+        ];
+
+        let (mounts, volumes) = parse_container_mounts(&specs).unwrap();
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(
+            volumes,
+            vec![
+                (
+                    "volume:data".to_string(),
+                    "/var/lib/data".to_string(),
+                    false
+                ),
+                ("volume:cache".to_string(), "/cache".to_string(), true),
+            ]
+        );
+
+        // VM-level mounts can't use named volumes
+        assert!(parse_mounts(&specs).is_err());
+    }
+
+    #[test]
+    fn test_named_volume_must_not_be_a_path() {
+        // Paths are never volume names
+        assert!(!is_named_volume_spec("./data:/data").unwrap());
+        assert!(!is_named_volume_spec("~/data:/data").unwrap());
+        assert!(!is_named_volume_spec("a/b:/data").unwrap());
+        assert!(!is_named_volume_spec("-data:/data").unwrap());
+        assert!(is_named_volume_spec("my-vol_1.0:/data").unwrap());
+
+        // A name that is also a relative path here is ambiguous (tests run
+        // from the package root, which has src/)
+        assert!(matches!(
+            is_named_volume_spec("src:/src"),
+            Err(Error::InvalidMountSpec { .. })
+        ));
+        assert!(!is_named_volume_spec("./src:/src").unwrap());
+    }
+
+    #[test]
+    fn test_parse_mount_spec_rejects_bad_guest_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let host = tmp.path().to_string_lossy();
+
+        assert_invalid_spec(&format!("{}:data", host));
+        assert_invalid_spec(&format!("{}:/proc", host));
+        assert_invalid_spec(&format!("{}:/dev/shm", host));
+    }
+}
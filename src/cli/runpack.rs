@@ -8,25 +8,44 @@
 //!
 //! Both paths converge on the same VM launch infrastructure.
 
-use crate::cli::parsers::{mounts_to_virtiofs_bindings, parse_env_spec, parse_mounts, parse_port};
+use crate::cli::parsers::{
+    add_cwd_mount, mounts_to_virtiofs_bindings, parse_entrypoint, parse_env_list, parse_env_spec,
+    parse_mounts, parse_port,
+};
+use crate::cli::{format_bytes, truncate};
 use clap::{Args, Parser, Subcommand};
 use smolvm::agent::launcher_dynamic::{
     launch_agent_vm_dynamic, KrunFunctions, PackedLaunchConfig, PackedMount,
 };
-use smolvm::agent::{mount_tag, AgentClient, PortMapping, RunConfig, VmResources};
-use smolvm::Error;
+use smolvm::agent::{
+    mount_tag, wait_for_agent_ready, AgentClient, PortMapping, RunConfig, VmResources,
+};
 use smolvm::DEFAULT_SHELL_CMD;
+use smolvm::{Error, Timeouts};
 use smolvm_pack::detect::PackedMode;
 use smolvm_pack::extract;
 use smolvm_pack::format::PackMode;
 use smolvm_pack::packer::{
     read_footer_from_sidecar, read_manifest_from_sidecar, verify_sidecar_checksum,
 };
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-/// Timeout waiting for the agent to become ready.
-const AGENT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+/// Parse `-v` mounts, adding the current directory for `--cwd`.
+///
+/// Returns the mounts and, with `--cwd`, the guest path the working
+/// directory defaults to.
+fn parse_run_mounts(
+    volume: &[String],
+    cwd: Option<&str>,
+) -> smolvm::Result<(Vec<smolvm::vm::config::HostMount>, Option<String>)> {
+    let mut mounts = parse_mounts(volume)?;
+    let cwd_target = cwd
+        .map(|guest| add_cwd_mount(&mut mounts, &[], guest))
+        .transpose()?;
+    Ok((mounts, cwd_target))
+}
 
 /// Convert parsed mounts to PackedMount format for the VM launcher.
 fn mounts_to_packed(mounts: &[smolvm::vm::config::HostMount]) -> Vec<PackedMount> {
@@ -95,6 +114,13 @@ pub struct RunpackCmd {
     )]
     pub env: Vec<String>,
 
+    /// Run this program instead of the image entrypoint
+    ///
+    /// The command's arguments are passed to it. An empty value
+    /// (`--entrypoint ""`) clears the entrypoint so the command runs as given.
+    #[arg(long, value_name = "PATH", help_heading = "Container")]
+    pub entrypoint: Option<String>,
+
     /// Mount host directory into container (can be used multiple times)
     #[arg(
         short = 'v',
@@ -104,6 +130,21 @@ pub struct RunpackCmd {
     )]
     pub volume: Vec<String>,
 
+    /// Mount the current directory read-write and run there
+    ///
+    /// Mounts at /work unless a container path is given (e.g. `--cwd=/src`).
+    /// The working directory defaults to the mount unless -w is set.
+    #[arg(
+        short = 'C',
+        long = "cwd",
+        value_name = "CONTAINER",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = crate::cli::parsers::DEFAULT_CWD_TARGET,
+        help_heading = "Container"
+    )]
+    pub cwd: Option<String>,
+
     /// Expose port from container to host (can be used multiple times)
     #[arg(
         short = 'p',
@@ -138,6 +179,10 @@ pub struct RunpackCmd {
     #[arg(long)]
     pub force_extract: bool,
 
+    /// Show progress while extracting assets on first run
+    #[arg(long)]
+    pub progress: bool,
+
     /// Show manifest info and exit
     #[arg(long)]
     pub info: bool,
@@ -149,7 +194,7 @@ pub struct RunpackCmd {
 
 impl RunpackCmd {
     /// Execute the runpack command.
-    pub fn run(self) -> smolvm::Result<()> {
+    pub fn run(mut self) -> smolvm::Result<()> {
         // 1. Resolve sidecar path
         let sidecar_path = resolve_sidecar_path(self.sidecar.as_deref())?;
 
@@ -224,13 +269,16 @@ impl RunpackCmd {
         let cache_dir = extract::get_cache_dir(footer.checksum)
             .map_err(|e| Error::agent("get cache dir", e.to_string()))?;
 
-        extract::extract_sidecar(
-            &sidecar_path,
-            &cache_dir,
-            &footer,
-            self.force_extract,
-            self.debug,
-        )
+        with_extract_progress(self.progress || self.debug, |progress| {
+            extract::extract_sidecar(
+                &sidecar_path,
+                &cache_dir,
+                &footer,
+                self.force_extract,
+                self.debug,
+                progress,
+            )
+        })
         .map_err(|e| Error::agent("extract assets", e.to_string()))?;
 
         // 6. Set up paths — use a unique runtime directory per invocation so
@@ -266,7 +314,10 @@ impl RunpackCmd {
         )?;
 
         // 7. Parse CLI args
-        let mounts = parse_mounts(&self.volume)?;
+        let (mounts, cwd_target) = parse_run_mounts(&self.volume, self.cwd.as_deref())?;
+        if let Some(guest) = cwd_target {
+            self.workdir.get_or_insert(guest);
+        }
         let port_mappings: Vec<(u16, u16)> = self.port.iter().map(|p| (p.host, p.guest)).collect();
 
         let resources = VmResources {
@@ -275,6 +326,10 @@ impl RunpackCmd {
             network: self.net || !self.port.is_empty(),
             storage_gb: self.storage,
             overlay_gb: self.overlay,
+            scratch_gb: None,
+            dns: None,
+            fallback_dns: None,
+            layer_dedup: false,
         };
 
         // Build packed mounts for the launcher
@@ -296,6 +351,7 @@ impl RunpackCmd {
 
         let console_log_path = runtime_dir.path().join("console.log");
         let vsock_path_clone = vsock_path.clone();
+        let console_log_path_clone = console_log_path.clone();
         let child_pid = smolvm::process::fork_session_leader(move || {
             // Child process: load libkrun via dlopen and launch VM
             let krun = match unsafe { KrunFunctions::load(&lib_dir) } {
@@ -316,7 +372,7 @@ impl RunpackCmd {
                 resources,
                 overlay_path: overlay_runtime_path.as_deref(),
                 debug: self.debug,
-                console_log: console_log_path,
+                console_log: console_log_path_clone,
             };
 
             // Detach from parent's terminal so libkrun doesn't
@@ -382,7 +438,7 @@ impl RunpackCmd {
         };
 
         // 9. Parent: wait for agent, connect, execute command
-        let mut client = wait_for_agent(&vsock_path, self.debug)?;
+        let mut client = wait_for_agent(&vsock_path, &console_log_path, self.debug)?;
 
         let exit_code = execute_command(&mut client, &manifest, &self, &mounts)?;
 
@@ -507,88 +563,96 @@ fn setup_vm_overlay(
 }
 
 /// Wait for the agent to become ready on the vsock socket.
-fn wait_for_agent(vsock_path: &Path, debug: bool) -> smolvm::Result<AgentClient> {
-    use std::thread;
-    use std::time::Instant;
-
-    let start = Instant::now();
-    let poll_interval = Duration::from_millis(100);
-
-    loop {
-        if start.elapsed() > AGENT_READY_TIMEOUT {
-            return Err(Error::agent(
-                "wait for agent",
-                format!(
-                    "agent did not become ready within {} seconds",
-                    AGENT_READY_TIMEOUT.as_secs()
-                ),
-            ));
-        }
-
-        if vsock_path.exists() {
-            // Connect opens the Unix socket to the muxer, but the guest agent
-            // may not be listening on vsock port 6000 yet. We must ping to
-            // verify end-to-end connectivity before declaring the agent ready.
-            match AgentClient::connect(vsock_path) {
-                Ok(mut client) => match client.ping() {
-                    Ok(_) => {
-                        if debug {
-                            eprintln!(
-                                "debug: agent ready after {:.1}s",
-                                start.elapsed().as_secs_f64()
-                            );
-                        }
-                        return Ok(client);
-                    }
-                    Err(_) => {
-                        // Muxer accepted but guest agent not ready yet
-                    }
-                },
-                Err(_) => {
-                    // Socket exists but not connectable yet
-                }
-            }
-        }
-
-        thread::sleep(poll_interval);
+fn wait_for_agent(
+    vsock_path: &Path,
+    console_log: &Path,
+    debug: bool,
+) -> smolvm::Result<AgentClient> {
+    let start = std::time::Instant::now();
+    let client = wait_for_agent_ready(
+        vsock_path,
+        Timeouts::default().boot,
+        Some(console_log),
+        || None,
+    )?;
+    if debug {
+        eprintln!(
+            "debug: agent ready after {:.1}s",
+            start.elapsed().as_secs_f64()
+        );
     }
+    Ok(client)
 }
 
-/// Build the command to execute from manifest defaults and CLI overrides.
-fn build_command(manifest: &smolvm_pack::PackManifest, cli_command: &[String]) -> Vec<String> {
-    if !cli_command.is_empty() {
-        return cli_command.to_vec();
-    }
-
-    // Use manifest entrypoint + cmd
-    let mut cmd = manifest.entrypoint.clone();
-    cmd.extend(manifest.cmd.clone());
+/// Command, environment, and working directory for a packed launch.
+#[derive(Debug, PartialEq)]
+struct LaunchSpec {
+    argv: Vec<String>,
+    env: Vec<(String, String)>,
+    workdir: Option<String>,
+}
 
-    if cmd.is_empty() {
-        vec![DEFAULT_SHELL_CMD.to_string()]
+/// Merge the manifest's image config with CLI overrides, following the OCI
+/// image config rules:
+///
+/// - argv is `entrypoint + cmd`. A command given on the CLI replaces `cmd`
+///   but keeps the entrypoint, unless its first argument is an absolute
+///   path, in which case it replaces both (so `/bin/sh` works on images
+///   with an entrypoint). With neither, the default shell runs.
+/// - `--entrypoint` replaces the manifest entrypoint and drops its `cmd`,
+///   so the CLI command alone supplies the arguments; `""` clears it.
+/// - `-e` variables overlay the manifest env: an existing variable keeps
+///   its position with the new value, new ones are appended. One that
+///   isn't KEY=VALUE is an error.
+/// - `workdir` (from `-w` or `--cwd`) overrides the manifest workdir.
+fn resolve_launch(
+    manifest: &smolvm_pack::PackManifest,
+    cli_entrypoint: Option<&str>,
+    cli_command: &[String],
+    cli_env: &[String],
+    workdir: Option<String>,
+) -> smolvm::Result<LaunchSpec> {
+    let mut argv = if let Some(entrypoint) = cli_entrypoint {
+        let mut argv = parse_entrypoint(entrypoint);
+        argv.extend(cli_command.iter().cloned());
+        argv
     } else {
-        cmd
+        let replaces_entrypoint = cli_command
+            .first()
+            .is_some_and(|arg| Path::new(arg).is_absolute());
+        let mut argv = if replaces_entrypoint {
+            Vec::new()
+        } else {
+            manifest.entrypoint.clone()
+        };
+        if cli_command.is_empty() {
+            argv.extend(manifest.cmd.iter().cloned());
+        } else {
+            argv.extend(cli_command.iter().cloned());
+        }
+        argv
+    };
+    if argv.is_empty() {
+        argv.push(DEFAULT_SHELL_CMD.to_string());
     }
-}
 
-/// Build environment variables from manifest defaults and CLI overrides.
-fn build_env(manifest: &smolvm_pack::PackManifest, cli_env: &[String]) -> Vec<(String, String)> {
     let mut env: Vec<(String, String)> = manifest
         .env
         .iter()
-        .filter_map(|e| parse_env_spec(e))
+        .filter_map(|e| parse_env_spec(e).ok())
         .collect();
-
-    // CLI env overrides manifest env
-    for spec in cli_env {
-        if let Some((key, value)) = parse_env_spec(spec) {
-            // Remove existing key if present
-            env.retain(|(k, _)| k != &key);
-            env.push((key, value));
+    for (key, value) in parse_env_list(cli_env)? {
+        match env.iter_mut().find(|(k, _)| *k == key) {
+            Some(existing) => existing.1 = value,
+            None => env.push((key, value)),
         }
     }
 
-    env
+    Ok(LaunchSpec {
+        argv,
+        env,
+        workdir: workdir.or_else(|| manifest.workdir.clone()),
+    })
 }
 
 /// Execute the command in the VM using the existing AgentClient.
@@ -601,9 +665,17 @@ fn execute_command(
     args: &RunpackCmd,
     mounts: &[smolvm::vm::config::HostMount],
 ) -> smolvm::Result<i32> {
-    let command = build_command(manifest, &args.command);
-    let env = build_env(manifest, &args.env);
-    let workdir = args.workdir.clone().or_else(|| manifest.workdir.clone());
+    let LaunchSpec {
+        argv: command,
+        env,
+        workdir,
+    } = resolve_launch(
+        manifest,
+        args.entrypoint.as_deref(),
+        &args.command,
+        &args.env,
+        args.workdir.clone(),
+    )?;
 
     match manifest.mode {
         PackMode::Vm => {
@@ -690,6 +762,18 @@ struct PackedCli {
     )]
     volume: Vec<String>,
 
+    /// Mount the current directory read-write and run there (default: /work)
+    #[arg(
+        short = 'C',
+        long = "cwd",
+        value_name = "GUEST",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = crate::cli::parsers::DEFAULT_CWD_TARGET,
+        global = true
+    )]
+    cwd: Option<String>,
+
     /// Set environment variable (KEY=VALUE)
     #[arg(short = 'e', long = "env", value_name = "KEY=VALUE", global = true)]
     env: Vec<String>,
@@ -698,6 +782,10 @@ struct PackedCli {
     #[arg(short = 'w', long = "workdir", value_name = "PATH", global = true)]
     workdir: Option<String>,
 
+    /// Run this program instead of the image entrypoint ("" clears it)
+    #[arg(long, value_name = "PATH", global = true)]
+    entrypoint: Option<String>,
+
     /// Keep stdin open for interactive input
     #[arg(short = 'i', long)]
     interactive: bool,
@@ -742,6 +830,10 @@ struct PackedCli {
     #[arg(long, global = true)]
     force_extract: bool,
 
+    /// Show progress while extracting assets on first run
+    #[arg(long, global = true)]
+    progress: bool,
+
     /// Print debug information
     #[arg(long, global = true)]
     debug: bool,
@@ -835,7 +927,9 @@ fn runpack_inner(mode: PackedMode, cli: PackedCli) -> smolvm::Result<()> {
                 timeout: cli.timeout,
                 workdir: cli.workdir,
                 env: cli.env,
+                entrypoint: cli.entrypoint,
                 volume: cli.volume,
+                cwd: cli.cwd,
                 port: cli.port,
                 net: cli.net,
                 cpus: cli.cpus,
@@ -843,6 +937,7 @@ fn runpack_inner(mode: PackedMode, cli: PackedCli) -> smolvm::Result<()> {
                 storage: cli.storage,
                 overlay: cli.overlay,
                 force_extract: cli.force_extract,
+                progress: cli.progress,
                 info: cli.info,
                 debug: cli.debug,
             };
@@ -855,7 +950,15 @@ fn runpack_inner(mode: PackedMode, cli: PackedCli) -> smolvm::Result<()> {
             checksum,
             assets_ptr,
             assets_size,
-        } => run_section_mode(*manifest, checksum, assets_ptr, assets_size, cli),
+            dictionary_size,
+        } => run_section_mode(
+            *manifest,
+            checksum,
+            assets_ptr,
+            assets_size,
+            dictionary_size,
+            cli,
+        ),
 
         PackedMode::Embedded { exe_path, footer } => run_embedded_mode(exe_path, footer, cli),
     }
@@ -868,6 +971,7 @@ fn run_section_mode(
     checksum: u32,
     assets_ptr: *const u8,
     assets_size: usize,
+    dictionary_size: u32,
     cli: PackedCli,
 ) -> smolvm::Result<()> {
     if cli.info {
@@ -880,10 +984,17 @@ fn run_section_mode(
 
     let needs_extract = cli.force_extract || !extract::is_extracted(&cache_dir);
     if needs_extract {
-        unsafe {
-            extract::extract_from_section(&cache_dir, assets_ptr, assets_size, cli.debug)
-                .map_err(|e| Error::agent("extract section assets", e.to_string()))?;
-        }
+        with_extract_progress(cli.progress || cli.debug, |progress| unsafe {
+            extract::extract_from_section(
+                &cache_dir,
+                assets_ptr,
+                assets_size,
+                dictionary_size,
+                cli.debug,
+                progress,
+            )
+        })
+        .map_err(|e| Error::agent("extract section assets", e.to_string()))?;
     }
 
     run_from_cache(&cache_dir, &manifest, cli)
@@ -909,8 +1020,10 @@ fn run_embedded_mode(
 
     let needs_extract = cli.force_extract || !extract::is_extracted(&cache_dir);
     if needs_extract {
-        extract::extract_from_binary(&exe_path, &cache_dir, &footer, cli.debug)
-            .map_err(|e| Error::agent("extract embedded assets", e.to_string()))?;
+        with_extract_progress(cli.progress || cli.debug, |progress| {
+            extract::extract_from_binary(&exe_path, &cache_dir, &footer, cli.debug, progress)
+        })
+        .map_err(|e| Error::agent("extract embedded assets", e.to_string()))?;
     }
 
     run_from_cache(&cache_dir, &manifest, cli)
@@ -951,7 +1064,7 @@ fn run_from_cache(
         cli.overlay,
     )?;
 
-    let mounts = parse_mounts(&cli.volume)?;
+    let (mounts, cwd_target) = parse_run_mounts(&cli.volume, cli.cwd.as_deref())?;
     let port_mappings: Vec<(u16, u16)> = cli.port.iter().map(|p| (p.host, p.guest)).collect();
 
     let resources = VmResources {
@@ -960,6 +1073,10 @@ fn run_from_cache(
         network: cli.net || !cli.port.is_empty(),
         storage_gb: cli.storage,
         overlay_gb: cli.overlay,
+        scratch_gb: None,
+        dns: None,
+        fallback_dns: None,
+        layer_dedup: false,
     };
 
     let packed_mounts = mounts_to_packed(&mounts);
@@ -969,6 +1086,7 @@ fn run_from_cache(
     let console_log_path = runtime_dir.path().join("console.log");
     let debug = cli.debug;
     let vsock_path_clone = vsock_path.clone();
+    let console_log_path_clone = console_log_path.clone();
     let child_pid = smolvm::process::fork_session_leader(move || {
         let krun = match unsafe { KrunFunctions::load(&lib_dir) } {
             Ok(k) => k,
@@ -988,7 +1106,7 @@ fn run_from_cache(
             resources,
             overlay_path: overlay_runtime_path.as_deref(),
             debug,
-            console_log: console_log_path,
+            console_log: console_log_path_clone,
         };
 
         // Detach from parent's terminal so libkrun doesn't
@@ -1032,7 +1150,7 @@ fn run_from_cache(
         runtime_dir,
     };
 
-    let mut client = wait_for_agent(&vsock_path, debug)?;
+    let mut client = wait_for_agent(&vsock_path, &console_log_path, debug)?;
 
     // Build a minimal RunpackCmd-like struct for execute_command
     let args = RunpackCmd {
@@ -1041,9 +1159,11 @@ fn run_from_cache(
         interactive: cli.interactive,
         tty: cli.tty,
         timeout: cli.timeout,
-        workdir: cli.workdir,
+        workdir: cli.workdir.or(cwd_target),
         env: cli.env,
+        entrypoint: cli.entrypoint,
         volume: Vec::new(), // already parsed
+        cwd: None,          // already parsed
         port: Vec::new(),   // already parsed
         net: cli.net,
         cpus: cli.cpus,
@@ -1051,6 +1171,7 @@ fn run_from_cache(
         storage: cli.storage,
         overlay: cli.overlay,
         force_extract: false,
+        progress: false,
         info: false,
         debug,
     };
@@ -1156,8 +1277,40 @@ fn read_manifest_for_mode(mode: &PackedMode) -> smolvm::Result<smolvm_pack::Pack
     }
 }
 
+/// Run an extraction, showing a progress line on stderr if `show` is set.
+///
+/// Quiet by default so packed binaries stay scriptable.
+fn with_extract_progress<T>(
+    show: bool,
+    extract: impl FnOnce(Option<&mut extract::ExtractProgress<'_>>) -> T,
+) -> T {
+    if !show {
+        return extract(None);
+    }
+
+    let mut print = |read: u64, total: u64, entry: &str| {
+        let percent = (read * 100).checked_div(total).unwrap_or(100).min(100);
+        eprint!(
+            "\rExtracting assets... {:>3}% ({} / {}) {:<40}",
+            percent,
+            format_bytes(read),
+            format_bytes(total),
+            truncate(entry, 40)
+        );
+        let _ = std::io::stderr().flush();
+    };
+    let result = extract(Some(&mut print));
+    eprintln!();
+    result
+}
+
 /// Ensure assets are extracted to the cache directory for the given mode.
-fn ensure_extracted(mode: &PackedMode, force: bool, debug: bool) -> smolvm::Result<PathBuf> {
+fn ensure_extracted(
+    mode: &PackedMode,
+    force: bool,
+    debug: bool,
+    progress: bool,
+) -> smolvm::Result<PathBuf> {
     let checksum = mode_checksum(mode);
     let cache_dir = extract::get_cache_dir(checksum)
         .map_err(|e| Error::agent("get cache dir", e.to_string()))?;
@@ -1169,24 +1322,45 @@ fn ensure_extracted(mode: &PackedMode, force: bool, debug: bool) -> smolvm::Resu
             PackedMode::Section {
                 assets_ptr,
                 assets_size,
+                dictionary_size,
                 ..
-            } => unsafe {
-                extract::extract_from_section(&cache_dir, *assets_ptr, *assets_size, debug)
-                    .map_err(|e| Error::agent("extract section assets", e.to_string()))?;
-            },
+            } => {
+                with_extract_progress(progress || debug, |progress| unsafe {
+                    extract::extract_from_section(
+                        &cache_dir,
+                        *assets_ptr,
+                        *assets_size,
+                        *dictionary_size,
+                        debug,
+                        progress,
+                    )
+                })
+                .map_err(|e| Error::agent("extract section assets", e.to_string()))?;
+            }
             PackedMode::Embedded {
                 exe_path, footer, ..
             } => {
-                extract::extract_from_binary(exe_path, &cache_dir, footer, debug)
-                    .map_err(|e| Error::agent("extract embedded assets", e.to_string()))?;
+                with_extract_progress(progress || debug, |progress| {
+                    extract::extract_from_binary(exe_path, &cache_dir, footer, debug, progress)
+                })
+                .map_err(|e| Error::agent("extract embedded assets", e.to_string()))?;
             }
             PackedMode::Sidecar {
                 sidecar_path,
                 footer,
                 ..
             } => {
-                extract::extract_sidecar(sidecar_path, &cache_dir, footer, force, debug)
-                    .map_err(|e| Error::agent("extract sidecar assets", e.to_string()))?;
+                with_extract_progress(progress || debug, |progress| {
+                    extract::extract_sidecar(
+                        sidecar_path,
+                        &cache_dir,
+                        footer,
+                        force,
+                        debug,
+                        progress,
+                    )
+                })
+                .map_err(|e| Error::agent("extract sidecar assets", e.to_string()))?;
             }
         }
     }
@@ -1230,7 +1404,7 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
     let manifest = read_manifest_for_mode(mode)?;
 
     // Extract assets to cache
-    let cache_dir = ensure_extracted(mode, cli.force_extract, cli.debug)?;
+    let cache_dir = ensure_extracted(mode, cli.force_extract, cli.debug, cli.progress)?;
 
     // Create daemon directory
     let daemon = cache_dir.join("daemon");
@@ -1278,7 +1452,7 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
     let vsock_path = daemon.join("agent.sock");
 
     // Parse CLI args
-    let mounts = parse_mounts(&cli.volume)?;
+    let (mounts, _) = parse_run_mounts(&cli.volume, cli.cwd.as_deref())?;
     let port_mappings: Vec<(u16, u16)> = cli.port.iter().map(|p| (p.host, p.guest)).collect();
 
     let resources = VmResources {
@@ -1287,6 +1461,10 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
         network: cli.net || !cli.port.is_empty(),
         storage_gb: cli.storage,
         overlay_gb: cli.overlay,
+        scratch_gb: None,
+        dns: None,
+        fallback_dns: None,
+        layer_dedup: false,
     };
 
     let packed_mounts = mounts_to_packed(&mounts);
@@ -1313,6 +1491,7 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
 
     let console_log_path = daemon.join("console.log");
     let vsock_path_clone = vsock_path.clone();
+    let console_log_path_clone = console_log_path.clone();
     let child_pid = smolvm::process::fork_session_leader(move || {
         let krun = match unsafe { KrunFunctions::load(&lib_dir) } {
             Ok(k) => k,
@@ -1332,7 +1511,7 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
             resources,
             overlay_path: overlay_daemon_path.as_deref(),
             debug,
-            console_log: console_log_path,
+            console_log: console_log_path_clone,
         };
 
         // Detach from parent's terminal before launching the VM.
@@ -1381,7 +1560,7 @@ fn daemon_start(mode: &PackedMode, cli: &PackedCli) -> smolvm::Result<()> {
 
     // Wait for agent to become ready
     println!("Starting daemon...");
-    let _client = wait_for_agent(&vsock_path, debug)?;
+    let _client = wait_for_agent(&vsock_path, &console_log_path, debug)?;
 
     println!("Daemon started (PID: {})", child_pid);
     Ok(())
@@ -1411,10 +1590,21 @@ fn daemon_exec(
     // Connect to agent
     let mut client = AgentClient::connect(&sock_path)?;
 
+    // Mounts were attached at `start`; --cwd here must match it
+    let (mounts, cwd_target) = parse_run_mounts(&cli.volume, cli.cwd.as_deref())?;
+
     // Build command from args or manifest defaults
-    let command = build_command(manifest, &command);
-    let env = build_env(manifest, &cli.env);
-    let workdir = cli.workdir.clone().or_else(|| manifest.workdir.clone());
+    let LaunchSpec {
+        argv: command,
+        env,
+        workdir,
+    } = resolve_launch(
+        manifest,
+        cli.entrypoint.as_deref(),
+        &command,
+        &cli.env,
+        cli.workdir.clone().or(cwd_target),
+    )?;
 
     let exit_code = match manifest.mode {
         PackMode::Vm => {
@@ -1435,8 +1625,6 @@ fn daemon_exec(
             }
         }
         PackMode::Container => {
-            // Parse mounts
-            let mounts = parse_mounts(&cli.volume)?;
             let mount_bindings = mounts_to_virtiofs_bindings(&mounts);
 
             if interactive || tty {
@@ -1553,3 +1741,113 @@ fn daemon_status(checksum: u32) -> smolvm::Result<()> {
     println!("Status: running (PID: {}, agent not responding)", pid);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(entrypoint: &[&str], cmd: &[&str]) -> smolvm_pack::PackManifest {
+        let mut manifest = smolvm_pack::PackManifest::new(
+            "app:latest".to_string(),
+            "sha256:abc".to_string(),
+            "linux/arm64".to_string(),
+        );
+        manifest.entrypoint = strings(entrypoint);
+        manifest.cmd = strings(cmd);
+        manifest.env = strings(&["PATH=/usr/bin", "MODE=prod"]);
+        manifest.workdir = Some("/app".to_string());
+        manifest
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn argv(manifest: &smolvm_pack::PackManifest, command: &[&str]) -> Vec<String> {
+        resolve_launch(manifest, None, &strings(command), &[], None)
+            .unwrap()
+            .argv
+    }
+
+    #[test]
+    fn test_resolve_launch_argv() {
+        let entrypoint_only = manifest(&["/entry.sh"], &[]);
+        let cmd_only = manifest(&[], &["python", "app.py"]);
+        let both = manifest(&["python"], &["app.py"]);
+        let neither = manifest(&[], &[]);
+
+        // Image defaults
+        assert_eq!(argv(&entrypoint_only, &[]), ["/entry.sh"]);
+        assert_eq!(argv(&cmd_only, &[]), ["python", "app.py"]);
+        assert_eq!(argv(&both, &[]), ["python", "app.py"]);
+        assert_eq!(argv(&neither, &[]), [DEFAULT_SHELL_CMD]);
+
+        // A relative command replaces cmd and keeps the entrypoint
+        assert_eq!(
+            argv(&entrypoint_only, &["--debug"]),
+            ["/entry.sh", "--debug"]
+        );
+        assert_eq!(argv(&cmd_only, &["ls", "-l"]), ["ls", "-l"]);
+        assert_eq!(argv(&both, &["test.py"]), ["python", "test.py"]);
+
+        // An absolute command replaces both
+        assert_eq!(argv(&entrypoint_only, &["/bin/sh"]), ["/bin/sh"]);
+        assert_eq!(
+            argv(&both, &["/bin/sh", "-c", "id"]),
+            ["/bin/sh", "-c", "id"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_launch_entrypoint_override() {
+        let both = manifest(&["python"], &["app.py"]);
+        let argv = |entrypoint: &str, command: &[&str]| {
+            resolve_launch(&both, Some(entrypoint), &strings(command), &[], None)
+                .unwrap()
+                .argv
+        };
+
+        // The override replaces the entrypoint and the manifest cmd
+        assert_eq!(argv("/bin/sh", &["-c", "id"]), ["/bin/sh", "-c", "id"]);
+        assert_eq!(argv("/bin/sh", &[]), ["/bin/sh"]);
+        assert_eq!(argv("env", &["--debug"]), ["env", "--debug"]);
+
+        // Clearing it runs the command as given, or the default shell
+        assert_eq!(argv("", &["app.py", "-v"]), ["app.py", "-v"]);
+        assert_eq!(argv("", &[]), [DEFAULT_SHELL_CMD]);
+    }
+
+    #[test]
+    fn test_resolve_launch_env_and_workdir() {
+        let manifest = manifest(&[], &["app"]);
+
+        let launch = resolve_launch(&manifest, None, &[], &[], None).unwrap();
+        assert_eq!(launch.workdir.as_deref(), Some("/app"));
+        assert_eq!(
+            launch.env,
+            [
+                ("PATH".to_string(), "/usr/bin".to_string()),
+                ("MODE".to_string(), "prod".to_string()),
+            ]
+        );
+
+        let launch = resolve_launch(
+            &manifest,
+            None,
+            &[],
+            &strings(&["MODE=dev", "DEBUG=1"]),
+            Some("/work".to_string()),
+        )
+        .unwrap();
+        assert_eq!(launch.workdir.as_deref(), Some("/work"));
+        assert_eq!(
+            launch.env,
+            [
+                ("PATH".to_string(), "/usr/bin".to_string()),
+                ("MODE".to_string(), "dev".to_string()),
+                ("DEBUG".to_string(), "1".to_string()),
+            ]
+        );
+        assert!(resolve_launch(&manifest, None, &[], &strings(&["DEBUG"]), None).is_err());
+    }
+}
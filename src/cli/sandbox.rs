@@ -11,17 +11,25 @@
 //! `sandbox create`, managed with `sandbox start/stop/ls/delete`.
 
 use crate::cli::parsers::{
-    mounts_to_virtiofs_bindings, parse_duration, parse_env_list, parse_mounts, parse_port,
+    add_cwd_mount, mounts_to_virtiofs_bindings, parse_add_host, parse_container_mounts,
+    parse_duration, parse_entrypoint, parse_env_list, parse_port, parse_tmpfs, ResourceLimitArgs,
+    SecurityArgs,
 };
 use crate::cli::vm_common::{self, DeleteVmOptions, VmKind};
-use crate::cli::{flush_output, format_bytes, truncate_id};
+use crate::cli::{flush_output, format_bytes, print_exit_reason, truncate, truncate_id};
 use clap::{Args, Subcommand};
 use smolvm::agent::{
-    docker_config_mount, AgentClient, AgentManager, PortMapping, RunConfig, VmResources,
+    docker_config_mount, parse_image_timestamp, resource_hints, AgentClient, AgentManager,
+    CreateContainerConfig, ExecConfig, ImageFilter, PortMapping, RunConfig, VmResources,
 };
+use smolvm::error::ProtocolErrorCode;
+use smolvm::labels::{parse_label, parse_label_filter, LabelFilter};
+use smolvm::util::RetryConfig;
 use smolvm::{DEFAULT_IDLE_CMD, DEFAULT_SHELL_CMD};
+use smolvm_protocol::TmpfsMount;
+use std::net::IpAddr;
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 const KIND: VmKind = VmKind::Sandbox;
 
@@ -29,7 +37,7 @@ const KIND: VmKind = VmKind::Sandbox;
 #[derive(Subcommand, Debug)]
 pub enum SandboxCmd {
     /// Run a container image (ephemeral by default, use -d to keep running)
-    Run(RunCmd),
+    Run(Box<RunCmd>),
 
     /// Create a named sandbox configuration
     Create(CreateCmd),
@@ -59,6 +67,9 @@ pub enum SandboxCmd {
 
     /// Remove unused images and layers to free disk space
     Prune(PruneCmd),
+
+    /// Format the sandbox storage disk
+    Format(FormatCmd),
 }
 
 impl SandboxCmd {
@@ -74,6 +85,7 @@ impl SandboxCmd {
             SandboxCmd::Ls(cmd) => cmd.run(),
             SandboxCmd::Images(cmd) => cmd.run(),
             SandboxCmd::Prune(cmd) => cmd.run(),
+            SandboxCmd::Format(cmd) => cmd.run(),
         }
     }
 }
@@ -91,6 +103,7 @@ impl SandboxCmd {
 ///   smolvm sandbox exec -- ls -la
 ///   smolvm sandbox exec --name mysandbox -- ls -la
 ///   smolvm sandbox exec -e FOO=bar -- env
+///   smolvm sandbox exec -it -- /bin/sh
 #[derive(Args, Debug)]
 pub struct ExecCmd {
     /// Command and arguments to execute
@@ -112,6 +125,21 @@ pub struct ExecCmd {
     /// Kill command after duration (e.g., "30s", "5m")
     #[arg(long, value_parser = parse_duration, value_name = "DURATION")]
     pub timeout: Option<Duration>,
+
+    /// Keep stdin open for interactive input
+    #[arg(short = 'i', long)]
+    pub interactive: bool,
+
+    /// Allocate a pseudo-TTY (use with -i for shells)
+    #[arg(short = 't', long)]
+    pub tty: bool,
+
+    /// Run as this user instead of the container's (name or UID, optionally `:group`)
+    #[arg(short = 'u', long, value_name = "USER[:GROUP]")]
+    pub user: Option<String>,
+
+    #[command(flatten)]
+    pub security: SecurityArgs,
 }
 
 impl ExecCmd {
@@ -129,18 +157,28 @@ impl ExecCmd {
             .map(|c| c.id.clone())
             .ok_or_else(|| Error::agent("find container", "no running container in sandbox"))?;
 
-        let env = parse_env_list(&self.env);
+        let env = parse_env_list(&self.env)?;
+
+        let config = ExecConfig::new(container_id, self.command.clone())
+            .with_env(env)
+            .with_workdir(self.workdir.clone())
+            .with_timeout(self.timeout)
+            .with_security(self.security.to_options()?)
+            .with_user(self.user.clone());
+
+        // An interactive session carries on if the connection drops briefly
+        if self.interactive || self.tty {
+            let exit_code = client
+                .with_reconnect(RetryConfig::for_connection())
+                .exec_interactive(config.with_tty(self.tty))?;
+            manager.detach();
+            std::process::exit(exit_code);
+        }
 
         // Execute in container
-        let (exit_code, stdout, stderr) = client.exec(
-            &container_id,
-            self.command.clone(),
-            env,
-            self.workdir.clone(),
-            self.timeout,
-        )?;
+        let out = client.exec_with_config(config)?;
 
-        vm_common::print_output_and_exit(&manager, exit_code, &stdout, &stderr);
+        vm_common::print_run_output_and_exit(&manager, &out);
     }
 }
 
@@ -220,6 +258,7 @@ impl StatusCmd {
 ///   smolvm sandbox run -d ubuntu                   # Detached, keeps running
 ///   smolvm sandbox run -d -p 8080:80 nginx        # Web server with port
 ///   smolvm sandbox run -v ./src:/app node -- npm start
+///   smolvm sandbox run --entrypoint /bin/sh myapp -- -c 'ls /app'
 #[derive(Args, Debug)]
 pub struct RunCmd {
     /// Container image (e.g., alpine, ubuntu:22.04, ghcr.io/org/image)
@@ -246,6 +285,29 @@ pub struct RunCmd {
     #[arg(long, value_parser = parse_duration, value_name = "DURATION", help_heading = "Execution")]
     pub timeout: Option<Duration>,
 
+    /// Discard rootfs changes when the command exits
+    #[arg(
+        long = "rm",
+        visible_alias = "ephemeral",
+        conflicts_with_all = ["persist", "detach"],
+        help_heading = "Execution"
+    )]
+    pub rm: bool,
+
+    /// Keep rootfs changes for the next run of the same image (default;
+    /// overrides `persist = false` in a Smolfile)
+    #[arg(long, conflicts_with = "detach", help_heading = "Execution")]
+    pub persist: bool,
+
+    /// Run on the image mounted read-only, skipping overlay setup; writes
+    /// outside volumes and --tmpfs mounts fail
+    #[arg(
+        long,
+        conflicts_with_all = ["persist", "rm", "detach"],
+        help_heading = "Execution"
+    )]
+    pub read_only: bool,
+
     /// Set working directory inside container
     #[arg(short = 'w', long, value_name = "DIR", help_heading = "Container")]
     pub workdir: Option<String>,
@@ -259,26 +321,90 @@ pub struct RunCmd {
     )]
     pub env: Vec<String>,
 
+    /// Run this program instead of the image entrypoint
+    ///
+    /// The command's arguments are passed to it. An empty value
+    /// (`--entrypoint ""`) clears the entrypoint so the command runs as given.
+    #[arg(long, value_name = "PATH", help_heading = "Container")]
+    pub entrypoint: Option<String>,
+
+    /// Mount an in-memory filesystem in the container, optionally capped in
+    /// size (e.g., /scratch:size=64m; can be used multiple times)
+    #[arg(
+        long,
+        value_parser = parse_tmpfs,
+        value_name = "PATH[:size=N]",
+        help_heading = "Container"
+    )]
+    pub tmpfs: Vec<TmpfsMount>,
+
+    /// Add an /etc/hosts entry to the container (e.g., db:10.0.0.5; can be
+    /// used multiple times)
+    #[arg(
+        long = "add-host",
+        value_parser = parse_add_host,
+        value_name = "HOST:IP",
+        help_heading = "Container"
+    )]
+    pub add_host: Vec<(String, IpAddr)>,
+
     /// Target OCI platform for multi-arch images (e.g., linux/arm64, linux/amd64)
     ///
-    /// By default, uses the host architecture. Use this to override, for example
-    /// to run x86_64 images via Rosetta on Apple Silicon.
+    /// By default, uses the host architecture, or linux/amd64 via Rosetta on
+    /// Apple Silicon for images not published for arm64. Use this to override,
+    /// for example to run the x86_64 variant of a multi-arch image. A cached
+    /// image built for another architecture is refused unless it matches this
+    /// platform.
     #[arg(
         long = "oci-platform",
+        visible_alias = "platform",
         value_name = "OS/ARCH",
         help_heading = "Container"
     )]
     pub oci_platform: Option<String>,
 
-    /// Mount host directory into container (can be used multiple times)
+    /// Pull the image again even if it is cached, picking up updates to
+    /// mutable tags like :latest (unchanged layers are kept)
+    #[arg(long, help_heading = "Container")]
+    pub no_cache: bool,
+
+    /// Mount host directory or named volume into container (can be used multiple times)
     #[arg(
         short = 'v',
         long = "volume",
-        value_name = "HOST:CONTAINER[:ro]",
+        value_name = "HOST|NAME:CONTAINER[:ro]",
         help_heading = "Container"
     )]
     pub volume: Vec<String>,
 
+    /// Mount the current directory read-write and run there
+    ///
+    /// Mounts at /work unless a container path is given (e.g. `--cwd=/src`).
+    /// The working directory defaults to the mount unless -w is set.
+    #[arg(
+        short = 'C',
+        long = "cwd",
+        value_name = "CONTAINER",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = crate::cli::parsers::DEFAULT_CWD_TARGET,
+        help_heading = "Container"
+    )]
+    pub cwd: Option<String>,
+
+    /// Run as this user instead of root (name or UID, optionally `:group`)
+    #[arg(
+        short = 'u',
+        long,
+        value_name = "USER[:GROUP]",
+        help_heading = "Container"
+    )]
+    pub user: Option<String>,
+
+    /// Fail if the working directory doesn't exist instead of creating it
+    #[arg(long, help_heading = "Container")]
+    pub no_create_workdir: bool,
+
     /// Expose port from container to host (can be used multiple times)
     #[arg(short = 'p', long = "port", value_parser = parse_port, value_name = "HOST:GUEST", help_heading = "Network")]
     pub port: Vec<PortMapping>,
@@ -287,23 +413,25 @@ pub struct RunCmd {
     #[arg(long, help_heading = "Network")]
     pub net: bool,
 
-    /// Number of virtual CPUs
-    #[arg(
-        long,
-        default_value_t = smolvm::agent::DEFAULT_CPUS,
-        value_name = "N",
-        help_heading = "Resources"
-    )]
-    pub cpus: u8,
+    /// DNS server for containers, instead of the host's resolvers
+    #[arg(long, value_name = "IP", help_heading = "Network")]
+    pub dns: Option<IpAddr>,
 
-    /// Memory allocation in MiB
-    #[arg(
-        long,
-        default_value_t = smolvm::agent::DEFAULT_MEMORY_MIB,
-        value_name = "MiB",
-        help_heading = "Resources"
-    )]
-    pub mem: u32,
+    /// Number of virtual CPUs [default: the image's org.smolvm.cpus label,
+    /// or 1]
+    #[arg(long, value_name = "N", help_heading = "Resources")]
+    pub cpus: Option<u8>,
+
+    /// Memory allocation in MiB [default: the image's org.smolvm.memory-mib
+    /// label, or 512]
+    #[arg(long, value_name = "MiB", help_heading = "Resources")]
+    pub mem: Option<u32>,
+
+    #[command(flatten)]
+    pub limits: ResourceLimitArgs,
+
+    #[command(flatten)]
+    pub security: SecurityArgs,
 
     /// Storage disk size in GiB (for OCI layers and container data)
     #[arg(long, value_name = "GiB", help_heading = "Resources")]
@@ -313,6 +441,10 @@ pub struct RunCmd {
     #[arg(long, value_name = "GiB", help_heading = "Resources")]
     pub overlay: Option<u64>,
 
+    /// Scratch disk size in GiB, to keep container writes off the storage disk
+    #[arg(long, value_name = "GiB", help_heading = "Resources")]
+    pub scratch: Option<u64>,
+
     /// Load VM configuration from a Smolfile (TOML)
     #[arg(
         long = "smolfile",
@@ -335,11 +467,25 @@ impl RunCmd {
     pub fn run(self) -> smolvm::Result<()> {
         use smolvm::Error;
 
+        let smolfile = self
+            .smolfile
+            .as_deref()
+            .map(crate::cli::smolfile::load)
+            .transpose()?;
+
+        // --rm and --persist override the Smolfile's `persist` setting
+        let smolfile_persist = smolfile.as_ref().and_then(|sf| sf.persist);
+        let ephemeral = self.rm || (!self.persist && smolfile_persist == Some(false));
+
+        // Resources given by flag or Smolfile win over the image's labels
+        let explicit_cpus = self.cpus.or(smolfile.as_ref().and_then(|sf| sf.cpus));
+        let explicit_mem = self.mem.or(smolfile.as_ref().and_then(|sf| sf.memory));
+
         // Merge CLI flags with Smolfile (if provided)
-        let params = crate::cli::smolfile::build_create_params(
+        let mut params = crate::cli::smolfile::build_create_params(
             "default".to_string(),
-            self.cpus,
-            self.mem,
+            self.cpus.unwrap_or(smolvm::agent::DEFAULT_CPUS),
+            self.mem.unwrap_or(smolvm::agent::DEFAULT_MEMORY_MIB),
             self.volume,
             self.port,
             self.net,
@@ -349,12 +495,21 @@ impl RunCmd {
             self.smolfile,
             self.storage,
             self.overlay,
+            self.scratch,
+            self.dns,
         )?;
+        let env = parse_env_list(&params.env)?;
 
-        // Parse volume mounts
-        let mut mounts = parse_mounts(&params.volume)?;
+        // Parse volume mounts (host directories and named volumes)
+        let (mut mounts, volume_bindings) = parse_container_mounts(&params.volume)?;
         let ports = params.port.clone();
 
+        // Mount the current directory if requested and run there
+        if let Some(ref guest) = self.cwd {
+            let guest = add_cwd_mount(&mut mounts, &volume_bindings, guest)?;
+            params.workdir.get_or_insert(guest);
+        }
+
         // Add docker config mount if requested
         if self.docker_config {
             if let Some(docker_mount) = docker_config_mount() {
@@ -366,18 +521,39 @@ impl RunCmd {
             }
         }
 
-        let resources = VmResources {
+        let (fallback_dns, layer_dedup) = match smolvm::config::SmolvmConfig::load() {
+            Ok(config) => (config.default_dns_addr(), config.layer_dedup),
+            Err(_) => (None, false),
+        };
+        // Start agent VM
+        let manager = AgentManager::new_default_with_sizes(params.storage_gb, params.overlay_gb)
+            .map_err(|e| Error::agent("create agent manager", e.to_string()))?;
+
+        // The image's labels are only known once it is pulled, so start at
+        // the running VM's sizes where nothing explicit was given; a VM
+        // already sized for the image is then reused as is.
+        let (base_cpus, base_mem) = (params.cpus, params.mem);
+        let running = manager.running_resources().map(|r| (r.cpus, r.mem));
+        (params.cpus, params.mem) = resource_hints::start_resources(
+            explicit_cpus,
+            explicit_mem,
+            running,
+            base_cpus,
+            base_mem,
+        );
+
+        let mut resources = VmResources {
             cpus: params.cpus,
             mem: params.mem,
             network: params.net,
             storage_gb: params.storage_gb,
             overlay_gb: params.overlay_gb,
+            scratch_gb: params.scratch_gb,
+            dns: params.dns,
+            fallback_dns,
+            layer_dedup,
         };
 
-        // Start agent VM
-        let manager = AgentManager::new_default_with_sizes(params.storage_gb, params.overlay_gb)
-            .map_err(|e| Error::agent("create agent manager", e.to_string()))?;
-
         // Show startup message
         let mode = if self.detach {
             "persistent"
@@ -396,31 +572,61 @@ impl RunCmd {
         };
         println!("Starting {} sandbox{}{}...", mode, mount_info, port_info);
 
-        let freshly_started = manager
-            .ensure_running_with_full_config(mounts.clone(), ports, resources)
-            .map_err(|e| Error::agent("start sandbox", e.to_string()))?;
+        let mut freshly_started = manager
+            .ensure_running_with_full_config(mounts.clone(), ports.clone(), resources)
+            .map_err(|e| crate::cli::start_error("start sandbox", e))?;
 
         // Connect to agent
         let mut client = AgentClient::connect_with_retry(manager.vsock_socket())?;
+        client.require_tools("run sandbox", &["crun"])?;
 
         // Pull image with progress display
-        crate::cli::pull_with_progress(&mut client, &self.image, self.oci_platform.as_deref())?;
+        let image_info = crate::cli::pull_with_progress(
+            &mut client,
+            &self.image,
+            self.oci_platform.as_deref(),
+            self.no_cache,
+        )?;
+
+        // Restart the VM if the image's labels size it differently. The
+        // pulled image stays on the storage disk.
+        let (cpus, mem) = resource_hints::resolve_resources(
+            explicit_cpus,
+            explicit_mem,
+            &image_info.labels,
+            base_cpus,
+            base_mem,
+        )?;
+        if (cpus, mem) != (params.cpus, params.mem) {
+            println!(
+                "Resizing sandbox to {} vCPU(s) and {} MiB for {}...",
+                cpus, mem, self.image
+            );
+            (params.cpus, params.mem) = (cpus, mem);
+            (resources.cpus, resources.mem) = (cpus, mem);
+            freshly_started |= manager
+                .ensure_running_with_full_config(mounts.clone(), ports, resources)
+                .map_err(|e| crate::cli::start_error("start sandbox", e))?;
+            client = AgentClient::connect_with_retry(manager.vsock_socket())?;
+        }
 
         // Run init commands from Smolfile only on fresh VM start (not when reusing)
         if freshly_started && !params.init.is_empty() {
             for (i, cmd) in params.init.iter().enumerate() {
                 let argv = vec!["sh".into(), "-c".into(), cmd.clone()];
-                let init_env = parse_env_list(&params.env);
                 let (exit_code, _stdout, stderr) =
-                    client.vm_exec(argv, init_env, params.workdir.clone(), None)?;
+                    client.vm_exec(argv, env.clone(), params.workdir.clone(), None)?;
                 if exit_code != 0 {
                     eprintln!("init[{}] failed (exit {}): {}", i, exit_code, stderr.trim());
                 }
             }
         }
 
-        // Build command - for detached mode, default to sleep infinity
-        let command = if self.command.is_empty() {
+        // Build command - for detached mode, default to sleep infinity. An
+        // entrypoint override runs with just the given arguments.
+        let entrypoint = self.entrypoint.as_deref().map(parse_entrypoint);
+        let runs_entrypoint = entrypoint.as_ref().is_some_and(|e| !e.is_empty());
+        let command = if self.command.is_empty() && !runs_entrypoint {
             if self.detach {
                 DEFAULT_IDLE_CMD.iter().map(|s| s.to_string()).collect()
             } else {
@@ -430,20 +636,55 @@ impl RunCmd {
             self.command.clone()
         };
 
-        // Parse environment variables
-        let env = parse_env_list(&params.env);
-
         // Convert mounts to agent format
-        let mount_bindings = mounts_to_virtiofs_bindings(&mounts);
+        let mut mount_bindings = mounts_to_virtiofs_bindings(&mounts);
+        mount_bindings.extend(volume_bindings);
+
+        let security = self.security.to_options()?;
+        if self.detach && !security.is_empty() {
+            return Err(Error::config(
+                "run sandbox",
+                "--cap-drop, --cap-add and --security-opt are not supported with --detach",
+            ));
+        }
+        if self.detach && self.user.is_some() {
+            return Err(Error::config(
+                "run sandbox",
+                "--user is not supported with --detach",
+            ));
+        }
+        if self.detach && self.no_create_workdir {
+            return Err(Error::config(
+                "run sandbox",
+                "--no-create-workdir is not supported with --detach",
+            ));
+        }
+        if self.detach && entrypoint.is_some() {
+            return Err(Error::config(
+                "run sandbox",
+                "--entrypoint is not supported with --detach",
+            ));
+        }
+        if self.detach && !self.tmpfs.is_empty() {
+            return Err(Error::config(
+                "run sandbox",
+                "--tmpfs is not supported with --detach",
+            ));
+        }
+        if self.detach && !self.add_host.is_empty() {
+            return Err(Error::config(
+                "run sandbox",
+                "--add-host is not supported with --detach",
+            ));
+        }
 
         if self.detach {
             // Detached/persistent mode: create container and keep running
             let info = client.create_container(
-                &self.image,
-                command,
-                env,
-                params.workdir.clone(),
-                mount_bindings,
+                CreateContainerConfig::new(self.image.clone(), command)
+                    .with_env(env.clone())
+                    .with_workdir(params.workdir.clone())
+                    .with_mounts(mount_bindings),
             )?;
 
             // Persist "default" record so `sandbox ls` shows this VM
@@ -474,8 +715,10 @@ impl RunCmd {
                             network: params.net,
                             storage_gb: params.storage_gb,
                             overlay_gb: params.overlay_gb,
+                            scratch_gb: params.scratch_gb,
+                            dns: params.dns,
                             init: params.init.clone(),
-                            env: parse_env_list(&params.env),
+                            env: env.clone(),
                             workdir: params.workdir.clone(),
                         }),
                     );
@@ -483,7 +726,10 @@ impl RunCmd {
                 }
             }
 
-            println!("Sandbox running (container: {})", &info.id[..12]);
+            println!(
+                "Sandbox running (vm: default, container: {})",
+                &info.id[..12]
+            );
             println!("\nTo interact with the sandbox:");
             println!(
                 "  smolvm container exec default {} -- <command>",
@@ -493,40 +739,55 @@ impl RunCmd {
                 "  smolvm container exec default {} -it -- /bin/sh",
                 &info.id[..12]
             );
+            println!("\nTo view its console log:");
+            println!("  smolvm logs default --console");
             println!("\nTo stop the sandbox:");
             println!("  smolvm sandbox stop");
+            println!("\nTo stop and remove it:");
+            println!("  smolvm sandbox delete default --force");
 
             // Keep sandbox running
             manager.detach();
             Ok(())
         } else {
             // Ephemeral mode: run command and clean up
-            let exit_code = if self.interactive || self.tty {
-                let config = RunConfig::new(&self.image, command)
-                    .with_env(env)
-                    .with_workdir(params.workdir.clone())
-                    .with_mounts(mount_bindings)
-                    .with_timeout(self.timeout)
-                    .with_tty(self.tty);
-                client.run_interactive(config)?
+            let config = RunConfig::new(&self.image, command)
+                .with_env(env)
+                .with_workdir(params.workdir.clone())
+                .with_mounts(mount_bindings)
+                .with_timeout(self.timeout)
+                .with_tty(self.tty)
+                .with_ephemeral(ephemeral)
+                .with_read_only(self.read_only)
+                .with_limits(self.limits.to_limits())
+                .with_security(security)
+                .with_user(self.user.clone())
+                .with_create_workdir(!self.no_create_workdir)
+                .with_platform(self.oci_platform.clone())
+                .with_entrypoint(entrypoint)
+                .with_tmpfs(self.tmpfs.clone())
+                .with_extra_hosts(self.add_host.clone());
+            // Run first and stop the sandbox regardless of the outcome, so a
+            // lost agent connection doesn't leave the VM behind.
+            let result = if self.interactive || self.tty {
+                client.run_interactive(config)
             } else {
-                let (exit_code, stdout, stderr) = client.run_with_mounts_and_timeout(
-                    &self.image,
-                    command,
-                    env,
-                    params.workdir.clone(),
-                    mount_bindings,
-                    self.timeout,
-                )?;
-
-                if !stdout.is_empty() {
-                    print!("{}", stdout);
-                }
-                if !stderr.is_empty() {
-                    eprint!("{}", stderr);
-                }
-                flush_output();
-                exit_code
+                client.run_with_config(config).map(|out| {
+                    if !out.stdout.is_empty() {
+                        print!("{}", out.stdout);
+                    }
+                    if !out.stderr.is_empty() {
+                        eprint!("{}", out.stderr);
+                    }
+                    if out.stdout_truncated || out.stderr_truncated {
+                        eprintln!(
+                            "warning: command output exceeded the capture limit and was truncated"
+                        );
+                    }
+                    print_exit_reason(&out.reason);
+                    flush_output();
+                    out.exit_code
+                })
             };
 
             // Stop the sandbox (ephemeral mode)
@@ -534,7 +795,7 @@ impl RunCmd {
                 tracing::warn!(error = %e, "failed to stop sandbox");
             }
 
-            std::process::exit(exit_code);
+            std::process::exit(result?);
         }
     }
 }
@@ -574,6 +835,10 @@ pub struct CreateCmd {
     #[arg(long, value_name = "GiB")]
     pub overlay: Option<u64>,
 
+    /// Scratch disk size in GiB, to keep container writes off the storage disk
+    #[arg(long, value_name = "GiB")]
+    pub scratch: Option<u64>,
+
     /// Mount host directory (can be used multiple times)
     #[arg(short = 'v', long = "volume", value_name = "HOST:GUEST[:ro]")]
     pub volume: Vec<String>,
@@ -586,6 +851,10 @@ pub struct CreateCmd {
     #[arg(long)]
     pub net: bool,
 
+    /// DNS server for containers, instead of the host's resolvers
+    #[arg(long, value_name = "IP")]
+    pub dns: Option<IpAddr>,
+
     /// Run command on every VM start (can be used multiple times)
     #[arg(long = "init", value_name = "COMMAND")]
     pub init: Vec<String>,
@@ -601,11 +870,15 @@ pub struct CreateCmd {
     /// Load configuration from a Smolfile (TOML)
     #[arg(long = "smolfile", visible_short_alias = 's', value_name = "PATH")]
     pub smolfile: Option<PathBuf>,
+
+    /// Set a label on the sandbox (can be used multiple times)
+    #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+    pub label: Vec<(String, String)>,
 }
 
 impl CreateCmd {
     pub fn run(self) -> smolvm::Result<()> {
-        let params = crate::cli::smolfile::build_create_params(
+        let mut params = crate::cli::smolfile::build_create_params(
             self.name,
             self.cpus,
             self.mem,
@@ -618,7 +891,10 @@ impl CreateCmd {
             self.smolfile,
             self.storage,
             self.overlay,
+            self.scratch,
+            self.dns,
         )?;
+        params.labels = self.label.into_iter().collect();
         vm_common::create_vm(KIND, params)
     }
 }
@@ -707,11 +983,15 @@ pub struct LsCmd {
     /// Output in JSON format
     #[arg(long)]
     pub json: bool,
+
+    /// Only list sandboxes with this label, optionally set to a value (can be used multiple times)
+    #[arg(long = "label", value_name = "KEY[=VALUE]", value_parser = parse_label_filter)]
+    pub label: Vec<LabelFilter>,
 }
 
 impl LsCmd {
     pub fn run(&self) -> smolvm::Result<()> {
-        vm_common::list_vms(KIND, self.verbose, self.json)
+        vm_common::list_vms(KIND, self.verbose, self.json, &self.label)
     }
 }
 
@@ -727,8 +1007,26 @@ impl LsCmd {
 /// Examples:
 ///   smolvm sandbox images
 ///   smolvm sandbox images --json
+///   smolvm sandbox images --created-after 2024-01-01 --label tier=app
+///   smolvm sandbox images --detail
 #[derive(Args, Debug)]
 pub struct ImagesCmd {
+    /// Only list images created before this date (YYYY-MM-DD or RFC 3339)
+    #[arg(long, value_name = "DATE", value_parser = parse_image_timestamp)]
+    pub created_before: Option<SystemTime>,
+
+    /// Only list images created after this date (YYYY-MM-DD or RFC 3339)
+    #[arg(long, value_name = "DATE", value_parser = parse_image_timestamp)]
+    pub created_after: Option<SystemTime>,
+
+    /// Only list images with this label, optionally set to a value (can be used multiple times)
+    #[arg(long = "label", value_name = "KEY[=VALUE]", value_parser = parse_label_filter)]
+    pub labels: Vec<(String, Option<String>)>,
+
+    /// Also show each layer's size and the images referencing it
+    #[arg(long)]
+    pub detail: bool,
+
     /// Output in JSON format
     #[arg(long)]
     pub json: bool,
@@ -751,18 +1049,34 @@ impl ImagesCmd {
         let status = client.storage_status()?;
 
         // Get images list
-        let images = client.list_images()?;
+        let filter = ImageFilter {
+            created_before: self.created_before,
+            created_after: self.created_after,
+            labels: self.labels,
+        };
+        let images = client.list_images_filtered(&filter)?;
+
+        let layers = if self.detail {
+            Some(client.disk_usage()?)
+        } else {
+            None
+        };
 
         if self.json {
-            let output = serde_json::json!({
+            let mut output = serde_json::json!({
                 "storage": {
                     "total_bytes": status.total_bytes,
                     "used_bytes": status.used_bytes,
+                    "total_inodes": status.total_inodes,
+                    "free_inodes": status.free_inodes,
                     "layer_count": status.layer_count,
                     "image_count": status.image_count,
                 },
                 "images": images,
             });
+            if let Some(layers) = &layers {
+                output["layers"] = serde_json::json!(layers);
+            }
             let json = serde_json::to_string_pretty(&output)
                 .map_err(|e| smolvm::Error::config("serialize json", e.to_string()))?;
             println!("{}", json);
@@ -771,10 +1085,19 @@ impl ImagesCmd {
             println!("Storage Usage:");
             println!("  Total:  {}", format_bytes(status.total_bytes));
             println!("  Used:   {}", format_bytes(status.used_bytes));
+            if status.total_inodes > 0 {
+                println!(
+                    "  Inodes: {} / {} used",
+                    status.total_inodes - status.free_inodes,
+                    status.total_inodes
+                );
+            }
             println!("  Layers: {}", status.layer_count);
             println!();
 
-            if images.is_empty() {
+            if images.is_empty() && !filter.is_empty() {
+                println!("No cached images match the filter.");
+            } else if images.is_empty() {
                 println!("No cached images.");
             } else {
                 println!("Cached Images:");
@@ -798,6 +1121,26 @@ impl ImagesCmd {
                 println!();
                 println!("Total: {} images", images.len());
             }
+
+            if let Some(layers) = &layers {
+                println!();
+                println!("Layers:");
+                println!("{:<22} {:>10}  USED BY", "LAYER", "SIZE");
+                println!("{}", "-".repeat(60));
+                for layer in layers {
+                    let used_by = if layer.referenced_by.is_empty() {
+                        "(unreferenced)".to_string()
+                    } else {
+                        layer.referenced_by.join(", ")
+                    };
+                    println!(
+                        "{:<22} {:>10}  {}",
+                        truncate(&layer.digest, 22),
+                        format_bytes(layer.size_bytes),
+                        used_by
+                    );
+                }
+            }
         }
 
         Ok(())
@@ -911,3 +1254,52 @@ impl PruneCmd {
         Ok(())
     }
 }
+
+// ============================================================================
+// Format Command
+// ============================================================================
+
+/// Format the sandbox storage disk.
+///
+/// Storage is formatted automatically on first use, so this is only needed
+/// to repair a damaged layout. An already formatted disk is left untouched
+/// unless --force is given.
+///
+/// Examples:
+///   smolvm sandbox format
+///   smolvm sandbox format --force
+#[derive(Args, Debug)]
+pub struct FormatCmd {
+    /// Reformat even if the storage disk is already formatted
+    #[arg(long)]
+    pub force: bool,
+}
+
+impl FormatCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = AgentManager::new_default()?;
+
+        // Start VM if not running
+        let mut client = if manager.try_connect_existing().is_some() {
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        } else {
+            println!("Starting sandbox VM...");
+            manager.start()?;
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        };
+
+        match client.format_storage(self.force) {
+            Ok(()) => {
+                println!("Storage formatted.");
+                Ok(())
+            }
+            Err(e) if e.protocol_code() == Some(&ProtocolErrorCode::AlreadyFormatted) => {
+                Err(smolvm::Error::config(
+                    "format storage",
+                    "storage is already formatted; use --force to reformat",
+                ))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
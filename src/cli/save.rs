@@ -0,0 +1,66 @@
+//! Save command.
+//!
+//! Writes a cached image to a tar file, `docker save` style, so it can be
+//! moved to another host without a registry and brought back with
+//! `smolvm load`.
+
+use crate::cli::format_bytes;
+use clap::Args;
+use smolvm::agent::{AgentClient, AgentManager};
+use smolvm::error::ErrorKind;
+use std::path::PathBuf;
+
+/// Save a cached image to a tar archive.
+///
+/// The archive is an OCI image layout holding the image's manifest,
+/// config and layers. The image must already be in the sandbox storage;
+/// it is not pulled.
+///
+/// Examples:
+///   smolvm save alpine:3.19 -o alpine.tar
+///   smolvm save myimage:dev --output /mnt/usb/myimage.tar
+#[derive(Args, Debug)]
+pub struct SaveCmd {
+    /// Cached image to save
+    #[arg(value_name = "IMAGE")]
+    pub image: String,
+
+    /// File to write the archive to
+    #[arg(short, long, value_name = "FILE")]
+    pub output: PathBuf,
+}
+
+impl SaveCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = AgentManager::new_default()?;
+
+        // Start VM if not running (needed to reach storage)
+        let mut client = if manager.try_connect_existing().is_some() {
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        } else {
+            eprintln!("Starting sandbox VM to access storage...");
+            manager.start()?;
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        };
+
+        match client.save_image(&self.image, &self.output) {
+            Ok(size) => {
+                println!(
+                    "Saved {} to {} ({})",
+                    self.image,
+                    self.output.display(),
+                    format_bytes(size)
+                );
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(smolvm::Error::config(
+                "save image",
+                format!(
+                    "image '{}' is not cached; pull it first (e.g. smolvm inspect {})",
+                    self.image, self.image
+                ),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -110,7 +110,7 @@ impl ServeCmd {
         });
 
         // Create router
-        let app = smolvm::api::create_router(state, self.cors_origins);
+        let app = smolvm::api::create_router(state.clone(), self.cors_origins);
 
         // Create listener
         let listener = tokio::net::TcpListener::bind(addr)
@@ -120,11 +120,16 @@ impl ServeCmd {
         tracing::info!(address = %addr, "starting HTTP API server");
         println!("smolvm API server listening on http://{}", addr);
 
-        // Run the server with graceful shutdown (VMs keep running independently)
-        axum::serve(listener, app)
-            .with_graceful_shutdown(shutdown_signal())
-            .await
-            .map_err(smolvm::error::Error::Io)?;
+        // Run until signaled, then drain in-flight requests (VMs keep running independently)
+        smolvm::api::serve(
+            listener,
+            app,
+            state,
+            shutdown_signal(),
+            smolvm::api::SHUTDOWN_DRAIN_TIMEOUT,
+        )
+        .await
+        .map_err(smolvm::error::Error::Io)?;
 
         // Signal supervisor to stop
         let _ = shutdown_tx.send(true);
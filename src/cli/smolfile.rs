@@ -8,11 +8,13 @@
 //! cpus = 2
 //! memory = 1024
 //! net = true
+//! dns = "10.0.0.2"  # instead of the host's resolvers
 //!
 //! ports = ["8080:80", "2222:22"]
 //! volumes = ["./src:/app"]
 //! env = ["NODE_ENV=production"]
 //! workdir = "/app"
+//! persist = false  # discard rootfs changes after each `sandbox run`
 //!
 //! init = [
 //!     "apk add --no-cache openssh",
@@ -25,6 +27,8 @@ use crate::cli::parsers::parse_port;
 use crate::cli::vm_common::CreateVmParams;
 use serde::Deserialize;
 use smolvm::agent::PortMapping;
+use smolvm::labels::Labels;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 
 /// Parsed Smolfile configuration.
@@ -45,6 +49,11 @@ pub struct Smolfile {
     pub workdir: Option<String>,
     pub storage: Option<u64>,
     pub overlay: Option<u64>,
+    pub scratch: Option<u64>,
+    pub dns: Option<IpAddr>,
+    /// Whether `sandbox run` keeps rootfs changes for the next run of the
+    /// same image (default true); `--rm`/`--persist` override it.
+    pub persist: Option<bool>,
 }
 
 /// Load and parse a Smolfile from the given path.
@@ -75,6 +84,8 @@ pub fn build_create_params(
     smolfile_path: Option<PathBuf>,
     cli_storage_gb: Option<u64>,
     cli_overlay_gb: Option<u64>,
+    cli_scratch_gb: Option<u64>,
+    cli_dns: Option<IpAddr>,
 ) -> smolvm::Result<CreateVmParams> {
     let sf = match smolfile_path {
         Some(path) => load(&path)?,
@@ -91,6 +102,9 @@ pub fn build_create_params(
                 workdir: cli_workdir,
                 storage_gb: cli_storage_gb,
                 overlay_gb: cli_overlay_gb,
+                scratch_gb: cli_scratch_gb,
+                dns: cli_dns,
+                labels: Labels::new(),
             });
         }
     };
@@ -144,6 +158,8 @@ pub fn build_create_params(
     // Scalars: CLI overrides Smolfile
     let storage_gb = cli_storage_gb.or(sf.storage);
     let overlay_gb = cli_overlay_gb.or(sf.overlay);
+    let scratch_gb = cli_scratch_gb.or(sf.scratch);
+    let dns = cli_dns.or(sf.dns);
 
     Ok(CreateVmParams {
         name,
@@ -157,5 +173,8 @@ pub fn build_create_params(
         workdir,
         storage_gb,
         overlay_gb,
+        scratch_gb,
+        dns,
+        labels: Labels::new(),
     })
 }
@@ -0,0 +1,213 @@
+//! Stats command.
+//!
+//! Streams CPU and memory usage of running microVMs, `docker stats` style.
+
+use crate::cli::parsers::parse_duration;
+use crate::cli::{format_bytes, truncate};
+use clap::Args;
+use serde::Serialize;
+use smolvm::agent::{AgentClient, AgentManager};
+use smolvm::config::{RecordState, SmolvmConfig};
+use smolvm_protocol::ResourceStats;
+use std::io::{IsTerminal, Write};
+use std::time::Duration;
+
+/// Shortest time between samples; CPU % is meaningless over shorter spans.
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Show live CPU and memory usage of microVMs.
+///
+/// Samples every running microVM unless names are given, and refreshes until
+/// interrupted. CPU % is relative to one CPU, so a VM with 4 CPUs can reach
+/// 400%.
+///
+/// Examples:
+///   smolvm stats
+///   smolvm stats myvm --container web
+///   smolvm stats --no-stream --json
+#[derive(Args, Debug)]
+pub struct StatsCmd {
+    /// MicroVMs to sample (default: all running)
+    #[arg(value_name = "NAME")]
+    pub names: Vec<String>,
+
+    /// Sample one container instead of the whole VM
+    #[arg(long, value_name = "ID")]
+    pub container: Option<String>,
+
+    /// Time between samples
+    #[arg(long, value_parser = parse_duration, value_name = "DURATION", default_value = "2s")]
+    pub interval: Duration,
+
+    /// Print one sample and exit
+    #[arg(long)]
+    pub no_stream: bool,
+
+    /// Output a stream of JSON objects, one per line
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// One VM being sampled.
+struct Target {
+    name: String,
+    client: AgentClient,
+    prev: Option<ResourceStats>,
+}
+
+/// A row of output, derived from two consecutive samples.
+#[derive(Debug, Serialize)]
+struct StatsRow<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    container_id: Option<&'a str>,
+    timestamp_ms: u64,
+    cpu_percent: Option<f64>,
+    memory_bytes: u64,
+    memory_limit_bytes: Option<u64>,
+    memory_percent: Option<f64>,
+}
+
+impl StatsCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let names = if self.names.is_empty() {
+            running_vms()?
+        } else {
+            self.names.clone()
+        };
+        if names.is_empty() {
+            println!("No running VMs");
+            return Ok(());
+        }
+
+        let mut targets = names
+            .into_iter()
+            .map(|name| {
+                connect(&name).map(|client| Target {
+                    name,
+                    client,
+                    prev: None,
+                })
+            })
+            .collect::<smolvm::Result<Vec<_>>>()?;
+
+        // CPU usage is a rate, so take a baseline sample first
+        for target in &mut targets {
+            target.prev = Some(target.client.stats(self.container.as_deref())?);
+        }
+
+        let interval = self.interval.max(MIN_INTERVAL);
+        let clear = !self.json && !self.no_stream && std::io::stdout().is_terminal();
+
+        loop {
+            std::thread::sleep(interval);
+
+            let mut samples = Vec::with_capacity(targets.len());
+            for target in &mut targets {
+                let sample = target.client.stats(self.container.as_deref())?;
+                let prev = target.prev.replace(sample.clone());
+                samples.push((target.name.as_str(), prev, sample));
+            }
+            let rows: Vec<StatsRow> = samples
+                .iter()
+                .map(|(name, prev, sample)| StatsRow::new(name, prev.as_ref(), sample))
+                .collect();
+
+            if self.json {
+                print_json(&rows)?;
+            } else {
+                print_table(&rows, clear);
+            }
+
+            if self.no_stream {
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<'a> StatsRow<'a> {
+    fn new(name: &'a str, prev: Option<&ResourceStats>, sample: &'a ResourceStats) -> Self {
+        Self {
+            name,
+            container_id: sample.container_id.as_deref(),
+            timestamp_ms: sample.timestamp_ms,
+            cpu_percent: prev.and_then(|p| sample.cpu_percent_since(p)),
+            memory_bytes: sample.memory_bytes,
+            memory_limit_bytes: sample.memory_limit_bytes,
+            memory_percent: sample.memory_percent(),
+        }
+    }
+}
+
+/// Names of all VMs that are currently running.
+fn running_vms() -> smolvm::Result<Vec<String>> {
+    let config = SmolvmConfig::load()?;
+    Ok(config
+        .list_vms()
+        .filter(|(_, record)| record.actual_state() == RecordState::Running)
+        .map(|(name, _)| name.clone())
+        .collect())
+}
+
+/// Connect to a running VM's agent.
+fn connect(name: &str) -> smolvm::Result<AgentClient> {
+    let manager = AgentManager::for_vm(name)?;
+    if manager.try_connect_existing().is_none() {
+        return Err(smolvm::Error::agent(
+            "connect",
+            format!(
+                "microvm '{}' is not running. Use 'smolvm microvm start' first.",
+                name
+            ),
+        ));
+    }
+    let client = AgentClient::connect_with_retry(manager.vsock_socket())?;
+    // Leave the VM running when this command exits
+    manager.detach();
+    Ok(client)
+}
+
+fn print_json(rows: &[StatsRow]) -> smolvm::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    for row in rows {
+        let json = serde_json::to_string(row)
+            .map_err(|e| smolvm::Error::config("serialize json", e.to_string()))?;
+        let _ = writeln!(stdout, "{}", json);
+    }
+    let _ = stdout.flush();
+    Ok(())
+}
+
+fn print_table(rows: &[StatsRow], clear: bool) {
+    let mut stdout = std::io::stdout().lock();
+    if clear {
+        // Clear the screen and move the cursor home
+        let _ = write!(stdout, "\x1b[2J\x1b[H");
+    }
+    let _ = writeln!(
+        stdout,
+        "{:<20} {:<14} {:>8} {:>24} {:>7}",
+        "NAME", "CONTAINER", "CPU %", "MEM USAGE / LIMIT", "MEM %"
+    );
+    for row in rows {
+        let limit = row
+            .memory_limit_bytes
+            .map(format_bytes)
+            .unwrap_or_else(|| "-".to_string());
+        let _ = writeln!(
+            stdout,
+            "{:<20} {:<14} {:>8} {:>24} {:>7}",
+            truncate(row.name, 20),
+            row.container_id.map_or("-", crate::cli::truncate_id),
+            format_percent(row.cpu_percent),
+            format!("{} / {}", format_bytes(row.memory_bytes), limit),
+            format_percent(row.memory_percent),
+        );
+    }
+    let _ = stdout.flush();
+}
+
+fn format_percent(value: Option<f64>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{:.2}%", v))
+}
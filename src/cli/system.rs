@@ -0,0 +1,406 @@
+//! System-wide maintenance commands.
+//!
+//! `system prune` reclaims the disk space a microVM's storage accumulates:
+//! exited containers, overlays nothing will mount again, unused images and
+//! the layers only they referenced.
+
+use crate::cli::{format_bytes, vm_common};
+use clap::{Args, Subcommand};
+use smolvm::agent::AgentClient;
+use smolvm_protocol::{
+    ContainerInfo, ContainerStatus, ImageInfo, ProtocolErrorCode, PrunedOverlays,
+};
+
+/// System-wide maintenance
+#[derive(Subcommand, Debug)]
+pub enum SystemCmd {
+    /// Remove exited containers, orphaned overlays and unused layers
+    Prune(SystemPruneCmd),
+}
+
+impl SystemCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        match self {
+            SystemCmd::Prune(cmd) => cmd.run(),
+        }
+    }
+}
+
+// ============================================================================
+// Prune
+// ============================================================================
+
+/// Remove exited containers, orphaned overlays and unused layers.
+///
+/// Exited containers are deleted first, so their overlays and any images
+/// only they used are freed in the same pass. Overlays of deleted
+/// containers and of ephemeral runs that were interrupted are removed next,
+/// then layers no cached image references. Running containers and the
+/// persistent overlays runs reuse are left alone.
+///
+/// Examples:
+///   smolvm system prune --dry-run
+///   smolvm system prune
+///   smolvm system prune myvm --all
+#[derive(Args, Debug)]
+pub struct SystemPruneCmd {
+    /// Target microVM name
+    #[arg(value_name = "MICROVM", default_value = "default")]
+    pub microvm: String,
+
+    /// Show what would be removed without actually removing
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Also remove images no container uses
+    #[arg(long)]
+    pub all: bool,
+}
+
+impl SystemPruneCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = vm_common::get_or_start_vm(&self.microvm)?;
+        let mut client = AgentClient::connect_with_retry(manager.vsock_socket())?;
+
+        let report = prune(&mut client, self.dry_run, self.all)?;
+        print_report(&report, self.dry_run, self.all);
+
+        // Keep microvm running
+        manager.detach();
+
+        Ok(())
+    }
+}
+
+/// What one category of [`prune`] removed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Pruned {
+    /// Container IDs, overlay workload IDs or image references removed.
+    pub removed: Vec<String>,
+    /// Bytes reclaimed.
+    pub freed_bytes: u64,
+}
+
+/// Everything a [`prune`] pass removed, by category.
+#[derive(Debug, Default, PartialEq)]
+pub struct PruneReport {
+    pub containers: Pruned,
+    pub overlays: Pruned,
+    pub images: Pruned,
+    /// Bytes of unreferenced layers garbage collected.
+    pub layer_bytes: u64,
+}
+
+impl PruneReport {
+    /// Total bytes reclaimed across every category.
+    pub fn freed_bytes(&self) -> u64 {
+        self.containers.freed_bytes
+            + self.overlays.freed_bytes
+            + self.images.freed_bytes
+            + self.layer_bytes
+    }
+}
+
+/// The agent operations [`prune`] is built from.
+pub trait PruneClient {
+    fn list_containers(&mut self) -> smolvm::Result<Vec<ContainerInfo>>;
+    fn delete_container(&mut self, container_id: &str) -> smolvm::Result<u64>;
+    fn prune_overlays(&mut self, dry_run: bool) -> smolvm::Result<PrunedOverlays>;
+    fn list_images(&mut self) -> smolvm::Result<Vec<ImageInfo>>;
+    fn remove_image(&mut self, image: &str) -> smolvm::Result<()>;
+    fn garbage_collect(&mut self, dry_run: bool) -> smolvm::Result<u64>;
+}
+
+impl PruneClient for AgentClient {
+    fn list_containers(&mut self) -> smolvm::Result<Vec<ContainerInfo>> {
+        AgentClient::list_containers(self)
+    }
+
+    fn delete_container(&mut self, container_id: &str) -> smolvm::Result<u64> {
+        AgentClient::delete_container(self, container_id, false)
+    }
+
+    fn prune_overlays(&mut self, dry_run: bool) -> smolvm::Result<PrunedOverlays> {
+        AgentClient::prune_overlays(self, dry_run)
+    }
+
+    fn list_images(&mut self) -> smolvm::Result<Vec<ImageInfo>> {
+        AgentClient::list_images(self)
+    }
+
+    fn remove_image(&mut self, image: &str) -> smolvm::Result<()> {
+        AgentClient::remove_image(self, image)
+    }
+
+    fn garbage_collect(&mut self, dry_run: bool) -> smolvm::Result<u64> {
+        AgentClient::garbage_collect(self, dry_run)
+    }
+}
+
+/// Remove exited containers, orphaned overlays, and (with `all`) images no
+/// remaining container uses, then garbage collect unreferenced layers.
+///
+/// With `dry_run` nothing is removed. The space exited containers would
+/// free isn't known until they're deleted, and layers of images that would
+/// be removed aren't counted, so a dry run under-reports those.
+pub fn prune<C: PruneClient>(
+    client: &mut C,
+    dry_run: bool,
+    all: bool,
+) -> smolvm::Result<PruneReport> {
+    let mut report = PruneReport::default();
+
+    let (exited, remaining): (Vec<ContainerInfo>, Vec<ContainerInfo>) =
+        client.list_containers()?.into_iter().partition(|c| {
+            matches!(
+                c.status,
+                ContainerStatus::Exited { .. } | ContainerStatus::Error { .. }
+            )
+        });
+    for container in exited {
+        if !dry_run {
+            report.containers.freed_bytes += client.delete_container(&container.id)?;
+        }
+        report.containers.removed.push(container.id);
+    }
+
+    let overlays = client.prune_overlays(dry_run)?;
+    report.overlays = Pruned {
+        removed: overlays.removed,
+        freed_bytes: overlays.freed_bytes,
+    };
+
+    if all {
+        for image in client.list_images()? {
+            if dry_run {
+                if remaining.iter().all(|c| c.image != image.reference) {
+                    report.images.removed.push(image.reference);
+                }
+                continue;
+            }
+            match client.remove_image(&image.reference) {
+                Ok(()) => report.images.removed.push(image.reference),
+                Err(e) if e.protocol_code() == Some(&ProtocolErrorCode::ImageInUse) => {
+                    tracing::debug!(image = %image.reference, "image in use, keeping it");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    report.layer_bytes = client.garbage_collect(dry_run)?;
+    Ok(report)
+}
+
+fn print_report(report: &PruneReport, dry_run: bool, all: bool) {
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+
+    println!(
+        "{} {} exited container(s){}",
+        verb,
+        report.containers.removed.len(),
+        if dry_run {
+            String::new()
+        } else {
+            format!(", freeing {}", format_bytes(report.containers.freed_bytes))
+        }
+    );
+    for id in &report.containers.removed {
+        println!("  - {}", id);
+    }
+
+    println!(
+        "{} {} orphaned overlay(s), {}",
+        verb,
+        report.overlays.removed.len(),
+        format_bytes(report.overlays.freed_bytes)
+    );
+    for id in &report.overlays.removed {
+        println!("  - {}", id);
+    }
+
+    if all {
+        println!("{} {} unused image(s)", verb, report.images.removed.len());
+        for image in &report.images.removed {
+            println!("  - {}", image);
+        }
+    }
+
+    println!(
+        "{} unreferenced layers, {}",
+        verb,
+        format_bytes(report.layer_bytes)
+    );
+
+    println!();
+    if dry_run {
+        println!(
+            "Would reclaim at least {}. Run without --dry-run to remove.",
+            format_bytes(report.freed_bytes())
+        );
+    } else {
+        println!("Total reclaimed: {}", format_bytes(report.freed_bytes()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records the calls [`prune`] makes, answering with canned results.
+    #[derive(Default)]
+    struct MockClient {
+        containers: Vec<ContainerInfo>,
+        images: Vec<ImageInfo>,
+        in_use: Vec<String>,
+        calls: Vec<String>,
+    }
+
+    fn container(id: &str, image: &str, status: ContainerStatus) -> ContainerInfo {
+        ContainerInfo {
+            id: id.to_string(),
+            image: image.to_string(),
+            state: status.name().to_string(),
+            created_at: 0,
+            command: vec![],
+            restart_count: 0,
+            status,
+            started_at: None,
+            finished_at: None,
+            labels: Default::default(),
+            health: None,
+        }
+    }
+
+    fn image(reference: &str) -> ImageInfo {
+        serde_json::from_value(serde_json::json!({
+            "reference": reference,
+            "digest": "sha256:0",
+            "size": 0,
+            "created": null,
+            "architecture": "arm64",
+            "os": "linux",
+            "layer_count": 0,
+            "layers": [],
+        }))
+        .unwrap()
+    }
+
+    impl PruneClient for MockClient {
+        fn list_containers(&mut self) -> smolvm::Result<Vec<ContainerInfo>> {
+            self.calls.push("list_containers".into());
+            Ok(self.containers.clone())
+        }
+
+        fn delete_container(&mut self, container_id: &str) -> smolvm::Result<u64> {
+            self.calls
+                .push(format!("delete_container {}", container_id));
+            Ok(100)
+        }
+
+        fn prune_overlays(&mut self, dry_run: bool) -> smolvm::Result<PrunedOverlays> {
+            self.calls.push(format!("prune_overlays {}", dry_run));
+            Ok(PrunedOverlays {
+                removed: vec!["ephemeral-abc".into()],
+                freed_bytes: 20,
+            })
+        }
+
+        fn list_images(&mut self) -> smolvm::Result<Vec<ImageInfo>> {
+            self.calls.push("list_images".into());
+            Ok(self.images.clone())
+        }
+
+        fn remove_image(&mut self, image: &str) -> smolvm::Result<()> {
+            self.calls.push(format!("remove_image {}", image));
+            if self.in_use.iter().any(|i| i == image) {
+                return Err(smolvm::Error::agent_response(
+                    "remove image",
+                    "in use",
+                    Some(smolvm_protocol::error_codes::IMAGE_IN_USE),
+                ));
+            }
+            Ok(())
+        }
+
+        fn garbage_collect(&mut self, dry_run: bool) -> smolvm::Result<u64> {
+            self.calls.push(format!("garbage_collect {}", dry_run));
+            Ok(3)
+        }
+    }
+
+    fn mock() -> MockClient {
+        MockClient {
+            containers: vec![
+                container("c1", "alpine", ContainerStatus::Exited { code: Some(0) }),
+                container("c2", "nginx", ContainerStatus::Running),
+                container(
+                    "c3",
+                    "alpine",
+                    ContainerStatus::Error {
+                        message: "boom".into(),
+                    },
+                ),
+            ],
+            images: vec![image("alpine"), image("nginx")],
+            in_use: vec!["nginx".into()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_prune_runs_each_step_and_totals_freed_bytes() {
+        let mut client = mock();
+        let report = prune(&mut client, false, true).unwrap();
+
+        assert_eq!(
+            client.calls,
+            vec![
+                "list_containers",
+                "delete_container c1",
+                "delete_container c3",
+                "prune_overlays false",
+                "list_images",
+                "remove_image alpine",
+                "remove_image nginx",
+                "garbage_collect false",
+            ]
+        );
+        assert_eq!(report.containers.removed, vec!["c1", "c3"]);
+        assert_eq!(report.containers.freed_bytes, 200);
+        assert_eq!(report.overlays.removed, vec!["ephemeral-abc"]);
+        assert_eq!(report.overlays.freed_bytes, 20);
+        // nginx is still used by the running container
+        assert_eq!(report.images.removed, vec!["alpine"]);
+        assert_eq!(report.layer_bytes, 3);
+        assert_eq!(report.freed_bytes(), 223);
+    }
+
+    #[test]
+    fn test_prune_dry_run_removes_nothing() {
+        let mut client = mock();
+        let report = prune(&mut client, true, true).unwrap();
+
+        assert_eq!(
+            client.calls,
+            vec![
+                "list_containers",
+                "prune_overlays true",
+                "list_images",
+                "garbage_collect true",
+            ]
+        );
+        assert_eq!(report.containers.removed, vec!["c1", "c3"]);
+        assert_eq!(report.images.removed, vec!["alpine"]);
+        assert_eq!(report.freed_bytes(), 23);
+    }
+
+    #[test]
+    fn test_prune_without_all_keeps_images() {
+        let mut client = mock();
+        let report = prune(&mut client, false, false).unwrap();
+
+        assert!(!client.calls.iter().any(|c| c.contains("image")));
+        assert!(report.images.removed.is_empty());
+    }
+}
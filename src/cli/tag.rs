@@ -0,0 +1,57 @@
+//! Tag command.
+//!
+//! Adds another name for a cached image, `docker tag` style. Only the
+//! manifest is copied; layers are shared.
+
+use clap::Args;
+use smolvm::agent::{AgentClient, AgentManager};
+use smolvm::error::ErrorKind;
+
+/// Give a cached image another name.
+///
+/// The image must already be in the sandbox storage; it is not pulled.
+/// An existing image with the target name is replaced.
+///
+/// Examples:
+///   smolvm tag myimage:dev myimage:stable
+///   smolvm tag alpine:3.19 base
+#[derive(Args, Debug)]
+pub struct TagCmd {
+    /// Cached image to tag
+    #[arg(value_name = "SOURCE")]
+    pub source: String,
+
+    /// New name for it
+    #[arg(value_name = "TARGET")]
+    pub target: String,
+}
+
+impl TagCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = AgentManager::new_default()?;
+
+        // Start VM if not running (needed to reach storage)
+        let mut client = if manager.try_connect_existing().is_some() {
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        } else {
+            eprintln!("Starting sandbox VM to access storage...");
+            manager.start()?;
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        };
+
+        match client.tag(&self.source, &self.target) {
+            Ok(info) => {
+                println!("Tagged {} as {}", self.source, info.reference);
+                Ok(())
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(smolvm::Error::config(
+                "tag image",
+                format!(
+                    "image '{}' is not cached; pull it first (e.g. smolvm inspect {})",
+                    self.source, self.source
+                ),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+}
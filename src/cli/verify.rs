@@ -0,0 +1,118 @@
+//! Verify command.
+//!
+//! Checks cached images for damage — truncated or missing layer files,
+//! manifests or configs that no longer parse — such as an unclean shutdown
+//! can leave behind, and optionally pulls the damaged parts again.
+
+use crate::cli::pull_with_progress;
+use clap::Args;
+use smolvm::agent::{AgentClient, AgentManager};
+use smolvm::error::ErrorKind;
+use smolvm_protocol::VerifyReport;
+
+/// Check cached images for corrupt layers.
+///
+/// Each layer's extracted content is compared against the checksum
+/// recorded when it was pulled. Layers pulled by older versions have no
+/// checksum and are only checked for being present.
+///
+/// Examples:
+///   smolvm verify
+///   smolvm verify alpine:latest --repair
+#[derive(Args, Debug)]
+pub struct VerifyCmd {
+    /// Image to verify (default: every cached image)
+    #[arg(value_name = "IMAGE")]
+    pub image: Option<String>,
+
+    /// Delete corrupt layers and pull the affected images again
+    #[arg(long)]
+    pub repair: bool,
+
+    /// Output in JSON format
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl VerifyCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = AgentManager::new_default()?;
+
+        // Start VM if not running (needed to reach storage)
+        let mut client = if manager.try_connect_existing().is_some() {
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        } else {
+            eprintln!("Starting sandbox VM to access storage...");
+            manager.start()?;
+            AgentClient::connect_with_retry(manager.vsock_socket())?
+        };
+
+        let reports = match client.verify(self.image.as_deref(), self.repair) {
+            Ok(reports) => reports,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(smolvm::Error::config(
+                    "verify image",
+                    format!(
+                        "image '{}' is not cached",
+                        self.image.as_deref().unwrap_or_default()
+                    ),
+                ))
+            }
+            Err(e) => return Err(e),
+        };
+
+        if self.json {
+            let json = serde_json::to_string_pretty(&reports)
+                .map_err(|e| smolvm::Error::config("serialize json", e.to_string()))?;
+            println!("{}", json);
+        } else if reports.is_empty() {
+            println!("No cached images");
+        } else {
+            for report in &reports {
+                print_report(report);
+            }
+        }
+
+        let failed: Vec<&VerifyReport> = reports.iter().filter(|r| !r.ok).collect();
+        if failed.is_empty() {
+            return Ok(());
+        }
+        if !self.repair {
+            return Err(smolvm::Error::config(
+                "verify images",
+                format!(
+                    "{} image(s) failed verification; rerun with --repair to pull them again",
+                    failed.len()
+                ),
+            ));
+        }
+
+        for report in failed {
+            // Refetch the manifest too, in case it was the damaged part
+            pull_with_progress(&mut client, &report.image, None, true)?;
+        }
+        Ok(())
+    }
+}
+
+fn print_report(report: &VerifyReport) {
+    if report.ok {
+        println!("{}: ok", report.image);
+    } else {
+        println!("{}: CORRUPT", report.image);
+    }
+    if !report.manifest_ok {
+        println!("  manifest does not parse");
+    } else if !report.config_ok {
+        println!("  config is missing or does not parse");
+    }
+    for digest in &report.corrupt_layers {
+        println!("  corrupt layer {}", digest);
+    }
+    if !report.unverified_layers.is_empty() {
+        println!(
+            "  {} layer(s) pulled without a checksum, only checked for presence",
+            report.unverified_layers.len()
+        );
+    }
+}
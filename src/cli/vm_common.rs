@@ -7,8 +7,9 @@
 
 use crate::cli::parsers::parse_mounts_as_tuples;
 use crate::cli::{format_pid_suffix, truncate};
-use smolvm::agent::{AgentManager, PortMapping};
+use smolvm::agent::{AgentManager, PortMapping, RunOutput};
 use smolvm::config::{RecordState, SmolvmConfig, VmRecord};
+use smolvm::labels::{labels_match, LabelFilter, Labels};
 
 // ============================================================================
 // VmKind
@@ -139,6 +140,21 @@ pub fn print_output_and_exit(
     std::process::exit(exit_code);
 }
 
+/// Like [`print_output_and_exit`], but also reports abnormal exits such as
+/// OOM kills.
+pub fn print_run_output_and_exit(manager: &AgentManager, out: &RunOutput) -> ! {
+    if !out.stdout.is_empty() {
+        print!("{}", out.stdout);
+    }
+    if !out.stderr.is_empty() {
+        eprint!("{}", out.stderr);
+    }
+    crate::cli::print_exit_reason(&out.reason);
+    crate::cli::flush_output();
+    manager.detach();
+    std::process::exit(out.exit_code);
+}
+
 /// Get the agent manager for a VM by name, auto-starting it if not running.
 ///
 /// Unlike [`ensure_running_and_connect`] which errors if the VM isn't running,
@@ -177,6 +193,9 @@ pub struct CreateVmParams {
     pub workdir: Option<String>,
     pub storage_gb: Option<u64>,
     pub overlay_gb: Option<u64>,
+    pub scratch_gb: Option<u64>,
+    pub dns: Option<std::net::IpAddr>,
+    pub labels: Labels,
 }
 
 /// Maximum length for VM/sandbox names.
@@ -261,18 +280,7 @@ pub fn create_vm(kind: VmKind, params: CreateVmParams) -> smolvm::Result<()> {
     let ports: Vec<(u16, u16)> = params.port.iter().map(|p| (p.host, p.guest)).collect();
 
     // Parse environment variables for init
-    let env: Vec<(String, String)> = params
-        .env
-        .iter()
-        .filter_map(|e| {
-            let (k, v) = e.split_once('=')?;
-            if k.is_empty() {
-                None
-            } else {
-                Some((k.to_string(), v.to_string()))
-            }
-        })
-        .collect();
+    let env = crate::cli::parsers::parse_env_list(&params.env)?;
 
     // Create record
     let mut record = VmRecord::new(
@@ -288,6 +296,9 @@ pub fn create_vm(kind: VmKind, params: CreateVmParams) -> smolvm::Result<()> {
     record.workdir = params.workdir.clone();
     record.storage_gb = params.storage_gb;
     record.overlay_gb = params.overlay_gb;
+    record.scratch_gb = params.scratch_gb;
+    record.dns = params.dns;
+    record.labels = params.labels.clone();
 
     // Store in config (persisted immediately to database)
     config.insert_vm(params.name.clone(), record)?;
@@ -303,6 +314,9 @@ pub fn create_vm(kind: VmKind, params: CreateVmParams) -> smolvm::Result<()> {
     if !params.init.is_empty() {
         println!("  Init commands: {}", params.init.len());
     }
+    if !params.labels.is_empty() {
+        println!("  Labels: {}", params.labels.len());
+    }
     println!(
         "\nUse '{} start {}' to start the {}",
         kind.cli_prefix(),
@@ -348,7 +362,9 @@ pub fn start_vm_named(kind: VmKind, name: &str) -> smolvm::Result<()> {
 
     let mounts = record.host_mounts();
     let ports = record.port_mappings();
-    let resources = record.vm_resources();
+    let mut resources = record.vm_resources();
+    resources.fallback_dns = config.default_dns_addr();
+    resources.layer_dedup = config.layer_dedup;
 
     // Start agent VM
     let manager = AgentManager::for_vm_with_sizes(name, record.storage_gb, record.overlay_gb)
@@ -374,7 +390,7 @@ pub fn start_vm_named(kind: VmKind, name: &str) -> smolvm::Result<()> {
 
     let _ = manager
         .ensure_running_with_full_config(mounts, ports, resources)
-        .map_err(|e| Error::agent(format!("start {}", kind.label()), e.to_string()))?;
+        .map_err(|e| crate::cli::start_error(format!("start {}", kind.label()), e))?;
 
     // Update state with PID start time for safe process identification
     let pid = manager.child_pid();
@@ -458,6 +474,8 @@ pub fn persist_default_running(
                 r.network = o.network;
                 r.storage_gb = o.storage_gb;
                 r.overlay_gb = o.overlay_gb;
+                r.scratch_gb = o.scratch_gb;
+                r.dns = o.dns;
                 r.init = o.init.clone();
                 r.env = o.env.clone();
                 r.workdir = o.workdir.clone();
@@ -478,6 +496,8 @@ pub struct DefaultVmOverrides {
     pub network: bool,
     pub storage_gb: Option<u64>,
     pub overlay_gb: Option<u64>,
+    pub scratch_gb: Option<u64>,
+    pub dns: Option<std::net::IpAddr>,
     pub init: Vec<String>,
     pub env: Vec<(String, String)>,
     pub workdir: Option<String>,
@@ -692,6 +712,16 @@ where
     if manager.try_connect_existing().is_some() {
         let pid_suffix = crate::cli::format_pid_suffix(manager.child_pid());
         println!("{} '{}': running{}", kind.display_name(), label, pid_suffix);
+        // Agents predating Status just don't report this
+        if let Ok(status) = manager.agent_status() {
+            println!(
+                "  agent: up {}, {} images, {} layers, {} active overlays",
+                crate::cli::format_age(status.uptime_secs),
+                status.image_count,
+                status.layer_count,
+                status.active_overlay_count
+            );
+        }
         extra(&manager);
         manager.detach();
     } else {
@@ -706,9 +736,17 @@ where
 // ============================================================================
 
 /// List all VMs/sandboxes.
-pub fn list_vms(kind: VmKind, verbose: bool, json: bool) -> smolvm::Result<()> {
+pub fn list_vms(
+    kind: VmKind,
+    verbose: bool,
+    json: bool,
+    labels: &[LabelFilter],
+) -> smolvm::Result<()> {
     let config = SmolvmConfig::load()?;
-    let vms: Vec<_> = config.list_vms().collect();
+    let vms: Vec<_> = config
+        .list_vms()
+        .filter(|(_, record)| labels_match(&record.labels, labels))
+        .collect();
 
     let empty_label = match kind {
         VmKind::Microvm => "No VMs found",
@@ -738,6 +776,7 @@ pub fn list_vms(kind: VmKind, verbose: bool, json: bool) -> smolvm::Result<()> {
                     "mounts": record.mounts.len(),
                     "ports": record.ports.len(),
                     "created_at": record.created_at,
+                    "labels": record.labels,
                 });
                 if kind.include_network_in_json() {
                     obj.as_object_mut()
@@ -792,6 +831,9 @@ pub fn list_vms(kind: VmKind, verbose: bool, json: bool) -> smolvm::Result<()> {
                 if let Some(wd) = &record.workdir {
                     println!("  Workdir: {}", wd);
                 }
+                for (k, v) in &record.labels {
+                    println!("  Label: {}={}", k, v);
+                }
                 println!("  Created: {}", record.created_at);
                 println!();
             }
@@ -0,0 +1,160 @@
+//! Named volume commands.
+//!
+//! Named volumes live on a microVM's storage disk and outlive the
+//! containers that mount them with `-v NAME:/path`.
+
+use crate::cli::{format_bytes, vm_common};
+use clap::{Args, Subcommand};
+use smolvm::agent::AgentClient;
+
+/// Manage named volumes in a microVM
+#[derive(Subcommand, Debug)]
+pub enum VolumeCmd {
+    /// Create a named volume
+    Create(VolumeCreateCmd),
+
+    /// List named volumes
+    #[command(visible_alias = "ls")]
+    List(VolumeListCmd),
+
+    /// Remove a named volume and its data
+    #[command(visible_alias = "rm")]
+    Remove(VolumeRemoveCmd),
+}
+
+impl VolumeCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        match self {
+            VolumeCmd::Create(cmd) => cmd.run(),
+            VolumeCmd::List(cmd) => cmd.run(),
+            VolumeCmd::Remove(cmd) => cmd.run(),
+        }
+    }
+}
+
+// ============================================================================
+// Create
+// ============================================================================
+
+/// Create a named volume.
+///
+/// Mounting a volume that doesn't exist yet creates it, so this is only
+/// needed to record a size up front.
+///
+/// Examples:
+///   smolvm volume create default data
+///   smolvm volume create myvm cache --size 1024
+#[derive(Args, Debug)]
+pub struct VolumeCreateCmd {
+    /// Target microVM name
+    #[arg(value_name = "MICROVM")]
+    pub microvm: String,
+
+    /// Volume name
+    #[arg(value_name = "NAME")]
+    pub name: String,
+
+    /// Intended size in MiB
+    #[arg(long, value_name = "MiB")]
+    pub size: Option<u64>,
+}
+
+impl VolumeCreateCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = vm_common::get_or_start_vm(&self.microvm)?;
+        let mut client = AgentClient::connect_with_retry(manager.vsock_socket())?;
+
+        let info = client.create_volume(&self.name, self.size)?;
+        println!("Created volume: {}", info.name);
+
+        // Keep microvm running
+        manager.detach();
+
+        Ok(())
+    }
+}
+
+// ============================================================================
+// List
+// ============================================================================
+
+/// List named volumes.
+#[derive(Args, Debug)]
+pub struct VolumeListCmd {
+    /// Target microVM name
+    #[arg(value_name = "MICROVM")]
+    pub microvm: String,
+
+    /// Only show volume names
+    #[arg(short = 'q', long)]
+    pub quiet: bool,
+}
+
+impl VolumeListCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = vm_common::get_or_start_vm(&self.microvm)?;
+        let mut client = AgentClient::connect_with_retry(manager.vsock_socket())?;
+
+        let volumes = client.list_volumes()?;
+        if self.quiet {
+            for v in &volumes {
+                println!("{}", v.name);
+            }
+        } else if volumes.is_empty() {
+            println!("No volumes");
+        } else {
+            println!("{:<30} {:>10} {:>10}", "NAME", "SIZE", "USED");
+            for v in &volumes {
+                let size = v
+                    .size_mib
+                    .map(|mib| format_bytes(mib * 1024 * 1024))
+                    .unwrap_or_else(|| "-".to_string());
+                println!(
+                    "{:<30} {:>10} {:>10}",
+                    v.name,
+                    size,
+                    format_bytes(v.used_bytes)
+                );
+            }
+        }
+
+        // Keep microvm running
+        manager.detach();
+
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Remove
+// ============================================================================
+
+/// Remove a named volume and all of its data.
+///
+/// Examples:
+///   smolvm volume rm default data
+#[derive(Args, Debug)]
+pub struct VolumeRemoveCmd {
+    /// Target microVM name
+    #[arg(value_name = "MICROVM")]
+    pub microvm: String,
+
+    /// Volume name
+    #[arg(value_name = "NAME")]
+    pub name: String,
+}
+
+impl VolumeRemoveCmd {
+    pub fn run(self) -> smolvm::Result<()> {
+        let manager = vm_common::get_or_start_vm(&self.microvm)?;
+        let mut client = AgentClient::connect_with_retry(manager.vsock_socket())?;
+
+        client.remove_volume(&self.name)?;
+        println!("Removed volume: {}", self.name);
+
+        // Keep microvm running
+        manager.detach();
+
+        Ok(())
+    }
+}
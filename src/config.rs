@@ -9,6 +9,7 @@
 
 use crate::db::SmolvmDb;
 use crate::error::Result;
+use crate::labels::Labels;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -99,9 +100,21 @@ pub struct RestartConfig {
 pub const DEFAULT_VM_CPUS: u8 = 1;
 /// Default memory in MiB for new VMs.
 pub const DEFAULT_VM_MEMORY_MIB: u32 = 512;
-/// Default DNS server for VMs with network egress.
+/// Default DNS server for VMs with network egress, when the host has no
+/// usable resolvers.
 pub const DEFAULT_DNS: &str = "1.1.1.1";
 
+/// Parse a `default_dns` setting, warning if it isn't an IP address.
+pub fn parse_default_dns(value: &str) -> Option<std::net::IpAddr> {
+    match value.parse() {
+        Ok(addr) => Some(addr),
+        Err(_) => {
+            tracing::warn!(value = %value, "ignoring default_dns, not an IP address");
+            None
+        }
+    }
+}
+
 /// Global smolvm configuration with database-backed persistence.
 ///
 /// This struct provides backward-compatible access to VM records while
@@ -117,8 +130,11 @@ pub struct SmolvmConfig {
     pub default_cpus: u8,
     /// Default memory in MiB for new VMs.
     pub default_mem: u32,
-    /// Default DNS server for VMs with network egress.
+    /// DNS server for VMs with network egress when the host has no usable
+    /// resolvers of its own.
     pub default_dns: String,
+    /// Deduplicate identical files across the image layers VMs extract.
+    pub layer_dedup: bool,
     /// Storage volume path (macOS only, for case-sensitive filesystem).
     #[cfg(target_os = "macos")]
     pub storage_volume: String,
@@ -138,6 +154,7 @@ impl SmolvmConfig {
             default_cpus: DEFAULT_VM_CPUS,
             default_mem: DEFAULT_VM_MEMORY_MIB,
             default_dns: DEFAULT_DNS.to_string(),
+            layer_dedup: false,
             #[cfg(target_os = "macos")]
             storage_volume: String::new(),
             vms: HashMap::new(),
@@ -151,6 +168,12 @@ impl SmolvmConfig {
     /// Opens the database and loads all VM records into the in-memory cache.
     /// If this is the first run and an old confy config exists, it will be
     /// migrated automatically.
+    ///
+    /// The database stays locked against other smolvm processes until it is
+    /// closed, so a load-modify-save cycle isn't interleaved with another
+    /// one. Opening waits a few seconds for a concurrent process to finish
+    /// and then fails with
+    /// [`DatabaseUnavailable`](crate::error::Error::DatabaseUnavailable).
     pub fn load() -> Result<Self> {
         let db = SmolvmDb::open()?;
 
@@ -170,6 +193,10 @@ impl SmolvmConfig {
         let default_dns = db
             .get_config("default_dns")?
             .unwrap_or_else(|| DEFAULT_DNS.to_string());
+        let layer_dedup = db
+            .get_config("layer_dedup")?
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(false);
 
         #[cfg(target_os = "macos")]
         let storage_volume = db.get_config("storage_volume")?.unwrap_or_default();
@@ -183,12 +210,18 @@ impl SmolvmConfig {
             default_cpus,
             default_mem,
             default_dns,
+            layer_dedup,
             #[cfg(target_os = "macos")]
             storage_volume,
             vms,
         })
     }
 
+    /// [`default_dns`](Self::default_dns) as an address, if it is one.
+    pub fn default_dns_addr(&self) -> Option<std::net::IpAddr> {
+        parse_default_dns(&self.default_dns)
+    }
+
     /// Close the database, releasing the file lock.
     ///
     /// The in-memory VM cache remains valid but no further DB operations
@@ -201,22 +234,30 @@ impl SmolvmConfig {
     /// Save configuration to the database.
     ///
     /// This is now a no-op for VM records since writes are immediate.
-    /// Global config changes are persisted here.
+    /// Global config changes are persisted here, in a single transaction so
+    /// a crash can't leave some settings updated and others not.
     pub fn save(&self) -> Result<()> {
-        // Persist global config settings
-        self.db.set_config("version", &self.version.to_string())?;
-        self.db
-            .set_config("default_cpus", &self.default_cpus.to_string())?;
-        self.db
-            .set_config("default_mem", &self.default_mem.to_string())?;
-        self.db.set_config("default_dns", &self.default_dns)?;
-
+        let version = self.version.to_string();
+        let default_cpus = self.default_cpus.to_string();
+        let default_mem = self.default_mem.to_string();
+        let layer_dedup = self.layer_dedup.to_string();
         #[cfg(target_os = "macos")]
-        if !self.storage_volume.is_empty() {
-            self.db.set_config("storage_volume", &self.storage_volume)?;
-        }
-
-        Ok(())
+        let storage_volume = (!self.storage_volume.is_empty())
+            .then_some(("storage_volume", self.storage_volume.as_str()));
+        #[cfg(not(target_os = "macos"))]
+        let storage_volume = None;
+        let entries: Vec<_> = [
+            ("version", version.as_str()),
+            ("default_cpus", default_cpus.as_str()),
+            ("default_mem", default_mem.as_str()),
+            ("default_dns", self.default_dns.as_str()),
+            ("layer_dedup", layer_dedup.as_str()),
+        ]
+        .into_iter()
+        .chain(storage_volume)
+        .collect();
+
+        self.db.set_configs(&entries)
     }
 
     /// Insert a VM record (persists immediately to database).
@@ -340,6 +381,19 @@ pub struct VmRecord {
     /// Overlay disk size in GiB (None = default 2 GiB).
     #[serde(default)]
     pub overlay_gb: Option<u64>,
+
+    /// Scratch disk size in GiB for container overlay upper layers (None =
+    /// no scratch disk).
+    #[serde(default)]
+    pub scratch_gb: Option<u64>,
+
+    /// DNS server for containers (None = the host's resolvers).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<std::net::IpAddr>,
+
+    /// User labels, for finding VMs by project, owner, etc.
+    #[serde(default, skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
 }
 
 fn default_cpus() -> u8 {
@@ -378,6 +432,9 @@ impl VmRecord {
             workdir: None,
             storage_gb: None,
             overlay_gb: None,
+            scratch_gb: None,
+            dns: None,
+            labels: Labels::new(),
         }
     }
 
@@ -409,6 +466,9 @@ impl VmRecord {
             workdir: None,
             storage_gb: None,
             overlay_gb: None,
+            scratch_gb: None,
+            dns: None,
+            labels: Labels::new(),
         }
     }
 
@@ -441,10 +501,13 @@ impl VmRecord {
     pub fn host_mounts(&self) -> Vec<crate::vm::config::HostMount> {
         self.mounts
             .iter()
-            .map(|(host, guest, ro)| crate::vm::config::HostMount {
-                source: std::path::PathBuf::from(host),
-                target: std::path::PathBuf::from(guest),
-                read_only: *ro,
+            .map(|(host, guest, ro)| {
+                let mount = crate::vm::config::HostMount::new(host, guest);
+                if *ro {
+                    mount
+                } else {
+                    mount.writable()
+                }
             })
             .collect()
     }
@@ -465,6 +528,10 @@ impl VmRecord {
             network: self.network,
             storage_gb: self.storage_gb,
             overlay_gb: self.overlay_gb,
+            scratch_gb: self.scratch_gb,
+            dns: self.dns,
+            fallback_dns: None,
+            layer_dedup: false,
         }
     }
 }
@@ -10,6 +10,7 @@ use redb::{Database, ReadableTable, TableDefinition};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Table for storing VM records (name -> JSON-serialized VmRecord).
 const VMS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("vms");
@@ -17,6 +18,16 @@ const VMS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("vms");
 /// Table for storing global configuration settings.
 const CONFIG_TABLE: TableDefinition<&str, &str> = TableDefinition::new("config");
 
+/// How long to wait for another process to release the database.
+///
+/// redb takes an exclusive file lock for as long as the database is open, so
+/// a second `smolvm` waits here while the first finishes its
+/// load-modify-save cycle.
+const OPEN_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interval between attempts to open a locked database.
+const OPEN_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
 /// Thread-safe database handle for smolvm state persistence.
 ///
 /// Supports close/reopen to release file locks before forking child processes.
@@ -51,15 +62,36 @@ impl SmolvmDb {
 
     /// Open the database at a specific path.
     ///
-    /// Creates parent directories if they don't exist.
+    /// Creates parent directories if they don't exist. If another process
+    /// has the database open, waits a few seconds for it to be released.
     pub fn open_at(path: &Path) -> Result<Self> {
+        Self::open_at_with_timeout(path, OPEN_LOCK_TIMEOUT)
+    }
+
+    fn open_at_with_timeout(path: &Path, timeout: Duration) -> Result<Self> {
         // Ensure parent directory exists
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)
                 .map_err(|e| Error::database("create directory", e.to_string()))?;
         }
 
-        let db = Database::create(path).map_err(|e| Error::database("open", e.to_string()))?;
+        let deadline = Instant::now() + timeout;
+        let db = loop {
+            match Database::create(path) {
+                Ok(db) => break db,
+                Err(redb::DatabaseError::DatabaseAlreadyOpen) if Instant::now() < deadline => {
+                    std::thread::sleep(OPEN_RETRY_INTERVAL);
+                }
+                Err(redb::DatabaseError::DatabaseAlreadyOpen) => {
+                    return Err(Error::database_unavailable(format!(
+                        "{} is locked by another smolvm process (waited {}s)",
+                        path.display(),
+                        timeout.as_secs_f32()
+                    )));
+                }
+                Err(e) => return Err(Error::database("open", e.to_string())),
+            }
+        };
 
         let instance = Self {
             db: Arc::new(RwLock::new(Some(db))),
@@ -385,6 +417,12 @@ impl SmolvmDb {
 
     /// Set a global configuration value.
     pub fn set_config(&self, key: &str, value: &str) -> Result<()> {
+        self.set_configs(&[(key, value)])
+    }
+
+    /// Set several global configuration values in one transaction, so
+    /// either all of them are written or none are.
+    pub fn set_configs(&self, entries: &[(&str, &str)]) -> Result<()> {
         let db = self.require_open()?;
 
         let write_txn = db
@@ -395,9 +433,11 @@ impl SmolvmDb {
             let mut table = write_txn
                 .open_table(CONFIG_TABLE)
                 .map_err(|e| Error::database("open config table", e.to_string()))?;
-            table
-                .insert(key, value)
-                .map_err(|e| Error::database(format!("set config '{}'", key), e.to_string()))?;
+            for (key, value) in entries {
+                table
+                    .insert(*key, *value)
+                    .map_err(|e| Error::database(format!("set config '{}'", key), e.to_string()))?;
+            }
         }
 
         write_txn
@@ -468,6 +508,44 @@ mod tests {
         assert!(db.get_vm("test-vm").unwrap().is_none());
     }
 
+    #[test]
+    fn test_db_labels_roundtrip_and_filter() {
+        let (_dir, db) = temp_db();
+
+        for (name, labels) in [
+            ("api", &[("project", "billing"), ("env", "prod")][..]),
+            ("worker", &[("project", "billing"), ("env", "dev")][..]),
+            ("search", &[("project", "search")][..]),
+            ("bare", &[][..]),
+        ] {
+            let mut record = VmRecord::new(name.to_string(), 1, 512, vec![], vec![], false);
+            record.labels = labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            db.insert_vm(name, &record).unwrap();
+        }
+
+        let vms = db.list_vms().unwrap();
+        let api = &vms.iter().find(|(name, _)| name == "api").unwrap().1;
+        assert_eq!(api.labels["project"], "billing");
+        assert_eq!(api.labels["env"], "prod");
+
+        let matching = |filter: &str| {
+            let filters = crate::labels::parse_label_filters(filter).unwrap();
+            let mut names: Vec<_> = vms
+                .iter()
+                .filter(|(_, r)| crate::labels::labels_match(&r.labels, &filters))
+                .map(|(name, _)| name.as_str())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(matching("project=billing"), ["api", "worker"]);
+        assert_eq!(matching("project=billing,env=prod"), ["api"]);
+        assert_eq!(matching(""), ["api", "bare", "search", "worker"]);
+    }
+
     #[test]
     fn test_db_concurrent_access() {
         let (_dir, db) = temp_db();
@@ -508,6 +586,54 @@ mod tests {
         assert!(db.get_config("nonexistent").unwrap().is_none());
     }
 
+    #[test]
+    fn test_set_configs_writes_all_entries() {
+        let (dir, db) = temp_db();
+        db.set_config("default_cpus", "1").unwrap();
+
+        db.set_configs(&[("default_cpus", "4"), ("default_mem", "2048")])
+            .unwrap();
+        db.close();
+
+        // The values survive a reopen
+        let db = SmolvmDb::open_at(&dir.path().join("test.redb")).unwrap();
+        assert_eq!(db.get_config("default_cpus").unwrap().as_deref(), Some("4"));
+        assert_eq!(
+            db.get_config("default_mem").unwrap().as_deref(),
+            Some("2048")
+        );
+    }
+
+    #[test]
+    fn test_open_waits_for_lock_release() {
+        let (dir, db) = temp_db();
+        let path = dir.path().join("test.redb");
+
+        let holder = std::thread::spawn(move || {
+            db.set_config("owner", "first").unwrap();
+            std::thread::sleep(Duration::from_millis(200));
+            db.close();
+        });
+        std::thread::sleep(Duration::from_millis(50));
+
+        // The second open sees the first process's write once it is done
+        let db = SmolvmDb::open_at_with_timeout(&path, Duration::from_secs(5)).unwrap();
+        holder.join().unwrap();
+        assert_eq!(db.get_config("owner").unwrap().as_deref(), Some("first"));
+    }
+
+    #[test]
+    fn test_open_fails_on_lock_contention() {
+        let (dir, _db) = temp_db();
+        let path = dir.path().join("test.redb");
+
+        let started = Instant::now();
+        let err = SmolvmDb::open_at_with_timeout(&path, Duration::from_millis(200)).unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(matches!(err, Error::DatabaseUnavailable(_)), "{}", err);
+        assert!(err.to_string().contains("locked by another smolvm process"));
+    }
+
     #[test]
     fn test_update_nonexistent_vm() {
         let (_dir, db) = temp_db();
@@ -40,19 +40,68 @@
 use std::path::PathBuf;
 use thiserror::Error;
 
-/// Classification for agent errors, used to map to HTTP status codes
-/// without fragile string matching on error messages.
+pub use smolvm_protocol::ProtocolErrorCode;
+
+/// Classification of an [`Error`], so callers can branch on the failure
+/// class (HTTP status mapping, retry predicates) without fragile string
+/// matching on error messages.
+///
+/// See [`Error::kind`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-pub enum AgentErrorKind {
+pub enum ErrorKind {
     /// Resource not found (maps to 404).
     NotFound,
     /// Conflict / resource already exists (maps to 409).
     Conflict,
+    /// The peer refused the connection, e.g. the agent isn't listening yet.
+    ConnectionRefused,
+    /// The connection was reset or closed under us.
+    ConnectionReset,
+    /// The operation did not complete in time.
+    Timeout,
+    /// Any other I/O failure.
+    Io,
+    /// The agent sent an error response or a malformed frame.
+    Protocol,
+    /// The agent doesn't support the request.
+    Unsupported,
+    /// The image isn't published for the requested platform.
+    PlatformUnavailable,
     /// General error (maps to 500).
     #[default]
     Other,
 }
 
+impl ErrorKind {
+    /// Classify an I/O error kind.
+    ///
+    /// A missing file or socket is [`ErrorKind::Io`], not
+    /// [`ErrorKind::NotFound`]: that is kept for resources the caller asked
+    /// for by name, so it can become a 404 without exposing I/O details.
+    pub fn from_io(kind: std::io::ErrorKind) -> Self {
+        use std::io::ErrorKind as Io;
+        match kind {
+            Io::ConnectionRefused => Self::ConnectionRefused,
+            Io::ConnectionReset | Io::ConnectionAborted | Io::BrokenPipe => Self::ConnectionReset,
+            Io::TimedOut | Io::WouldBlock => Self::Timeout,
+            _ => Self::Io,
+        }
+    }
+
+    /// Whether a failure of this kind may clear up if the operation is
+    /// retried, e.g. while a VM is still booting.
+    pub fn is_transient(self) -> bool {
+        matches!(
+            self,
+            Self::ConnectionRefused | Self::ConnectionReset | Self::Timeout
+        )
+    }
+}
+
+/// Former name of [`ErrorKind`], from when only agent errors had a kind.
+#[deprecated(note = "renamed to `ErrorKind`")]
+pub type AgentErrorKind = ErrorKind;
+
 /// Result type alias using smolvm's Error type.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -147,6 +196,15 @@ pub enum Error {
         path: PathBuf,
     },
 
+    /// Volume mount specification (`HOST:GUEST[:ro]`) could not be parsed.
+    #[error("invalid mount spec '{spec}': {reason}")]
+    InvalidMountSpec {
+        /// The offending specification as given by the user.
+        spec: String,
+        /// Explanation of why the spec is invalid.
+        reason: String,
+    },
+
     // ========================================================================
     // Configuration Errors
     // ========================================================================
@@ -197,8 +255,51 @@ pub enum Error {
         operation: String,
         /// The reason for the failure.
         reason: String,
-        /// Classification for HTTP status mapping.
-        kind: AgentErrorKind,
+        /// Failure class, set where the error originates.
+        kind: ErrorKind,
+    },
+
+    /// Agent returned an error response carrying a protocol error code.
+    #[error("agent operation failed: {operation}: {message}")]
+    Protocol {
+        /// The operation that failed (e.g., "pull image", "query image").
+        operation: String,
+        /// Typed error code from the agent response.
+        code: ProtocolErrorCode,
+        /// The error message from the agent.
+        message: String,
+    },
+
+    /// The agent did not answer a readiness ping within the timeout.
+    #[error("boot timed out: agent not ready after {timeout_secs}s{}", last_error_suffix(.last_error))]
+    BootTimeout {
+        /// The boot timeout that elapsed, in seconds.
+        timeout_secs: u64,
+        /// Why the last readiness check failed, if one was made.
+        last_error: Option<String>,
+    },
+
+    /// The VM never became ready; the report says how far boot got.
+    #[error("{0}")]
+    BootFailed(Box<crate::vm::BootReport>),
+
+    /// The agent doesn't advertise a capability the request relies on.
+    #[error("{operation}: agent does not support '{capability}' (the agent in this VM is older than the host)")]
+    Unsupported {
+        /// The operation that needed the capability.
+        operation: String,
+        /// The missing capability (see `smolvm_protocol::capabilities`).
+        capability: String,
+    },
+
+    /// The image has no manifest for the requested platform.
+    #[error("platform {} not available; available: {}", .platform.as_deref().unwrap_or("default"), available_platforms(.available))]
+    PlatformUnavailable {
+        /// The requested platform (e.g. "linux/riscv64"), or `None` for the
+        /// agent's default.
+        platform: Option<String>,
+        /// Platforms the image is published for.
+        available: Vec<String>,
     },
 
     // ========================================================================
@@ -275,6 +376,14 @@ impl Error {
         }
     }
 
+    /// Create an invalid mount spec error.
+    pub fn invalid_mount_spec(spec: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::InvalidMountSpec {
+            spec: spec.into(),
+            reason: reason.into(),
+        }
+    }
+
     // ========================================================================
     // Config Error Constructors
     // ========================================================================
@@ -325,16 +434,35 @@ impl Error {
         Self::Agent {
             operation: operation.into(),
             reason: reason.into(),
-            kind: AgentErrorKind::Other,
+            kind: ErrorKind::Other,
         }
     }
 
+    /// Create an agent operation error of a specific kind.
+    pub fn agent_with_kind(
+        kind: ErrorKind,
+        operation: impl Into<String>,
+        reason: impl Into<String>,
+    ) -> Self {
+        Self::Agent {
+            operation: operation.into(),
+            reason: reason.into(),
+            kind,
+        }
+    }
+
+    /// Create an agent operation error from an I/O error, classified by
+    /// its kind.
+    pub fn agent_io(operation: impl Into<String>, err: &std::io::Error) -> Self {
+        Self::agent_with_kind(ErrorKind::from_io(err.kind()), operation, err.to_string())
+    }
+
     /// Create an agent "not found" error (maps to 404).
     pub fn agent_not_found(operation: impl Into<String>, reason: impl Into<String>) -> Self {
         Self::Agent {
             operation: operation.into(),
             reason: reason.into(),
-            kind: AgentErrorKind::NotFound,
+            kind: ErrorKind::NotFound,
         }
     }
 
@@ -343,7 +471,95 @@ impl Error {
         Self::Agent {
             operation: operation.into(),
             reason: reason.into(),
-            kind: AgentErrorKind::Conflict,
+            kind: ErrorKind::Conflict,
+        }
+    }
+
+    /// Create an error from an agent error response with a typed code.
+    pub fn protocol(
+        operation: impl Into<String>,
+        code: ProtocolErrorCode,
+        message: impl Into<String>,
+    ) -> Self {
+        Self::Protocol {
+            operation: operation.into(),
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Create an error from an agent `Error` response, keeping the protocol
+    /// error code when the agent sent one.
+    pub fn agent_response(
+        operation: impl Into<String>,
+        message: impl Into<String>,
+        code: Option<&str>,
+    ) -> Self {
+        match code {
+            Some(code) => Self::protocol(operation, ProtocolErrorCode::from_code(code), message),
+            None => Self::agent(operation, message),
+        }
+    }
+
+    /// Create a boot timeout error for an agent that never answered a ping,
+    /// carrying the last ping's failure.
+    pub fn agent_not_ready(timeout: std::time::Duration, last_error: Option<&Error>) -> Self {
+        Self::BootTimeout {
+            timeout_secs: timeout.as_secs(),
+            last_error: last_error.map(|e| e.to_string()),
+        }
+    }
+
+    /// Create a boot failure error from what was seen during boot.
+    pub fn boot_failed(report: crate::vm::BootReport) -> Self {
+        Self::BootFailed(Box::new(report))
+    }
+
+    /// Create an error for a request the agent lacks the capability for.
+    pub fn unsupported(operation: impl Into<String>, capability: impl Into<String>) -> Self {
+        Self::Unsupported {
+            operation: operation.into(),
+            capability: capability.into(),
+        }
+    }
+
+    /// Create an error from an agent error response that may carry
+    /// structured `data`.
+    ///
+    /// A [`ProtocolErrorCode::PlatformUnavailable`] response listing the
+    /// available platforms becomes [`Error::PlatformUnavailable`]; anything
+    /// else is handled like [`Error::agent_response`].
+    pub fn agent_response_with_data(
+        operation: impl Into<String>,
+        message: impl Into<String>,
+        code: Option<&str>,
+        data: Option<&serde_json::Value>,
+    ) -> Self {
+        if code == Some(smolvm_protocol::error_codes::PLATFORM_UNAVAILABLE) {
+            let available = data
+                .and_then(|d| d["available"].as_array())
+                .map(|platforms| {
+                    platforms
+                        .iter()
+                        .filter_map(|p| p.as_str().map(String::from))
+                        .collect()
+                });
+            if let Some(available) = available {
+                return Self::PlatformUnavailable {
+                    platform: data.and_then(|d| d["platform"].as_str().map(String::from)),
+                    available,
+                };
+            }
+        }
+        Self::agent_response(operation, message, code)
+    }
+
+    /// The protocol error code, if this error came from an agent error
+    /// response that carried one.
+    pub fn protocol_code(&self) -> Option<&ProtocolErrorCode> {
+        match self {
+            Self::Protocol { code, .. } => Some(code),
+            _ => None,
         }
     }
 
@@ -361,6 +577,30 @@ impl Error {
         Self::KvmPermission(reason.into())
     }
 
+    /// The failure class of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Agent { kind, .. } => *kind,
+            Self::Protocol { code, .. } => match code {
+                ProtocolErrorCode::NotFound | ProtocolErrorCode::WorkdirNotFound => {
+                    ErrorKind::NotFound
+                }
+                ProtocolErrorCode::AlreadyFormatted => ErrorKind::Conflict,
+                _ => ErrorKind::Protocol,
+            },
+            Self::Io(e) => ErrorKind::from_io(e.kind()),
+            Self::VmNotFound { .. }
+            | Self::RootfsNotFound { .. }
+            | Self::DiskNotFound { .. }
+            | Self::MountSourceNotFound { .. } => ErrorKind::NotFound,
+            Self::BootTimeout { .. } => ErrorKind::Timeout,
+            Self::BootFailed(report) if report.exit_reason.is_none() => ErrorKind::Timeout,
+            Self::Unsupported { .. } => ErrorKind::Unsupported,
+            Self::PlatformUnavailable { .. } => ErrorKind::PlatformUnavailable,
+            _ => ErrorKind::Other,
+        }
+    }
+
     /// Returns true if this is an `Io` variant.
     pub fn is_io(&self) -> bool {
         matches!(self, Self::Io(_))
@@ -375,6 +615,23 @@ impl Error {
     }
 }
 
+/// Format the last readiness failure for inclusion in an error message.
+fn last_error_suffix(last_error: &Option<String>) -> String {
+    match last_error {
+        Some(e) => format!(" (last error: {})", e),
+        None => String::new(),
+    }
+}
+
+/// Format the platforms an image offers for inclusion in an error message.
+fn available_platforms(available: &[String]) -> String {
+    if available.is_empty() {
+        "none".to_string()
+    } else {
+        available.join(", ")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -489,6 +746,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_invalid_mount_spec_includes_spec() {
+        let err = Error::invalid_mount_spec("./src:app", "guest path must be absolute");
+        let msg = err.to_string();
+        assert!(msg.contains("./src:app"), "Error should include the spec");
+        assert!(msg.contains("absolute"), "Error should include reason");
+    }
+
     // ========================================================================
     // Rootfs Error Tests
     // ========================================================================
@@ -567,6 +832,130 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_protocol_error_keeps_code() {
+        let err = Error::protocol("pull image", ProtocolErrorCode::PullFailed, "unauthorized");
+        assert_eq!(err.protocol_code(), Some(&ProtocolErrorCode::PullFailed));
+        assert_eq!(
+            err.to_string(),
+            "agent operation failed: pull image: unauthorized"
+        );
+        assert_eq!(Error::agent("op", "reason").protocol_code(), None);
+
+        let err = Error::agent_response("query image", "no such image", Some("NOT_FOUND"));
+        assert_eq!(err.protocol_code(), Some(&ProtocolErrorCode::NotFound));
+        let err = Error::agent_response("query image", "boom", None);
+        assert!(matches!(err, Error::Agent { .. }));
+    }
+
+    #[test]
+    fn test_error_kind_classification() {
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        let err = Error::agent_io("connect to agent", &refused);
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+        assert!(err.kind().is_transient());
+        // The message is unchanged by classification
+        assert_eq!(
+            err.to_string(),
+            Error::agent("connect to agent", refused.to_string()).to_string()
+        );
+
+        let err = Error::Io(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+        assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+        let err = Error::Io(std::io::Error::from(std::io::ErrorKind::WouldBlock));
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+        let err = Error::Io(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+        assert_eq!(err.kind(), ErrorKind::Io);
+        // A missing socket or file is an I/O failure, not a missing resource
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert_eq!(
+            Error::agent_io("connect to agent", &missing).kind(),
+            ErrorKind::Io
+        );
+        assert!(!err.kind().is_transient());
+
+        assert_eq!(Error::vm_not_found("vm").kind(), ErrorKind::NotFound);
+        assert_eq!(
+            Error::agent_response("query image", "no such image", Some("NOT_FOUND")).kind(),
+            ErrorKind::NotFound
+        );
+        assert_eq!(
+            Error::protocol("pull", ProtocolErrorCode::PullFailed, "boom").kind(),
+            ErrorKind::Protocol
+        );
+        assert_eq!(
+            Error::unsupported("run", "cap").kind(),
+            ErrorKind::Unsupported
+        );
+        assert_eq!(Error::agent("op", "reason").kind(), ErrorKind::Other);
+    }
+
+    #[test]
+    fn test_platform_unavailable_from_response_data() {
+        let data = serde_json::json!({
+            "platform": "linux/riscv64",
+            "available": ["linux/amd64", "linux/arm64/v8"],
+        });
+        let err = Error::agent_response_with_data(
+            "pull image",
+            "no manifest for linux/riscv64",
+            Some("PLATFORM_UNAVAILABLE"),
+            Some(&data),
+        );
+        assert_eq!(err.kind(), ErrorKind::PlatformUnavailable);
+        let Error::PlatformUnavailable {
+            platform,
+            available,
+        } = &err
+        else {
+            panic!("unexpected error: {:?}", err);
+        };
+        assert_eq!(platform.as_deref(), Some("linux/riscv64"));
+        assert_eq!(available, &["linux/amd64", "linux/arm64/v8"]);
+        assert_eq!(
+            err.to_string(),
+            "platform linux/riscv64 not available; available: linux/amd64, linux/arm64/v8"
+        );
+
+        let data = serde_json::json!({"available": []});
+        let err = Error::agent_response_with_data(
+            "pull image",
+            "m",
+            Some("PLATFORM_UNAVAILABLE"),
+            Some(&data),
+        );
+        assert_eq!(
+            err.to_string(),
+            "platform default not available; available: none"
+        );
+
+        // Without the platform list it stays a protocol error
+        let err =
+            Error::agent_response_with_data("pull image", "m", Some("PLATFORM_UNAVAILABLE"), None);
+        assert_eq!(
+            err.protocol_code(),
+            Some(&ProtocolErrorCode::PlatformUnavailable)
+        );
+        let err =
+            Error::agent_response_with_data("pull image", "m", Some("PULL_FAILED"), Some(&data));
+        assert_eq!(err.protocol_code(), Some(&ProtocolErrorCode::PullFailed));
+    }
+
+    #[test]
+    fn test_agent_not_ready_includes_last_error() {
+        let last = Error::agent("ping", "storage not ready");
+        let err = Error::agent_not_ready(std::time::Duration::from_secs(30), Some(&last));
+        let msg = err.to_string();
+        assert!(msg.contains("30s"), "Error should include the timeout");
+        assert!(
+            msg.contains("storage not ready"),
+            "Error should include the last failure"
+        );
+
+        let err = Error::agent_not_ready(std::time::Duration::from_secs(5), None);
+        assert_eq!(err.to_string(), "boot timed out: agent not ready after 5s");
+    }
+
     // ========================================================================
     // Config Error Tests
     // ========================================================================
@@ -596,11 +985,13 @@ mod tests {
             Error::storage("op", "reason"),
             Error::mount("op", "reason"),
             Error::invalid_mount_path("reason"),
+            Error::invalid_mount_spec("spec", "reason"),
             Error::config("op", "reason"),
             Error::database("op", "reason"),
             Error::database_unavailable("reason"),
             Error::command_failed("cmd", "reason"),
             Error::agent("op", "reason"),
+            Error::protocol("op", ProtocolErrorCode::InternalError, "reason"),
             Error::kvm_unavailable("reason"),
             Error::kvm_permission("reason"),
         ];
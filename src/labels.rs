@@ -0,0 +1,180 @@
+//! User labels on sandboxes, microVMs and containers.
+//!
+//! Labels are free-form `key=value` metadata set at creation time, used to
+//! find resources later (e.g. every sandbox with `project=billing`). List
+//! commands and endpoints take filters of the form `key` (the label is set)
+//! or `key=value` (it is set to that value); a resource must match every
+//! filter to be listed.
+
+use crate::error::{Error, Result};
+use std::collections::BTreeMap;
+
+/// Labels on a resource, by key.
+pub type Labels = BTreeMap<String, String>;
+
+/// A label filter: the key, and the value it must have if any.
+pub type LabelFilter = (String, Option<String>);
+
+/// Maximum length of a label key.
+const MAX_KEY_LENGTH: usize = 128;
+
+/// Maximum length of a label value.
+const MAX_VALUE_LENGTH: usize = 1024;
+
+/// Parse a label of the form `key=value`.
+pub fn parse_label(s: &str) -> Result<(String, String)> {
+    let Some((key, value)) = s.split_once('=') else {
+        return Err(Error::config(
+            "parse label",
+            format!("'{}': expected KEY=VALUE", s),
+        ));
+    };
+    validate_label(key, value)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Check that `key` and `value` can be stored and filtered on.
+///
+/// Keys may not contain `=` or `,` (they separate keys, values and filters
+/// in list queries), whitespace or control characters. Values may not
+/// contain `,` or control characters.
+pub fn validate_label(key: &str, value: &str) -> Result<()> {
+    let invalid = |reason: String| Err(Error::config("validate label", reason));
+    if key.is_empty() {
+        return invalid("label key must not be empty".to_string());
+    }
+    if key.len() > MAX_KEY_LENGTH {
+        return invalid(format!(
+            "label key '{}' is longer than {} bytes",
+            key, MAX_KEY_LENGTH
+        ));
+    }
+    if let Some(c) = key
+        .chars()
+        .find(|c| matches!(c, '=' | ',') || c.is_whitespace() || c.is_control())
+    {
+        return invalid(format!("label key '{}' contains {:?}", key, c));
+    }
+    if value.len() > MAX_VALUE_LENGTH {
+        return invalid(format!(
+            "value of label '{}' is longer than {} bytes",
+            key, MAX_VALUE_LENGTH
+        ));
+    }
+    if value.contains(',') {
+        return invalid(format!(
+            "value of label '{}' contains ',', which can't be filtered on",
+            key
+        ));
+    }
+    if value.chars().any(char::is_control) {
+        return invalid(format!(
+            "value of label '{}' contains control characters",
+            key
+        ));
+    }
+    Ok(())
+}
+
+/// Check every label in `labels`.
+pub fn validate_labels(labels: &Labels) -> Result<()> {
+    labels
+        .iter()
+        .try_for_each(|(key, value)| validate_label(key, value))
+}
+
+/// Parse a label filter of the form `key` or `key=value`.
+pub fn parse_label_filter(s: &str) -> Result<LabelFilter> {
+    let (key, value) = match s.split_once('=') {
+        Some((key, value)) => (key, Some(value.to_string())),
+        None => (s, None),
+    };
+    if key.is_empty() {
+        return Err(Error::config(
+            "parse label filter",
+            format!("'{}': label key must not be empty", s),
+        ));
+    }
+    Ok((key.to_string(), value))
+}
+
+/// Parse a comma-separated list of label filters, as taken by the `label`
+/// query parameter of list endpoints.
+pub fn parse_label_filters(list: &str) -> Result<Vec<LabelFilter>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(parse_label_filter)
+        .collect()
+}
+
+/// Whether `labels` meet every filter in `filters`.
+pub fn labels_match(labels: &Labels, filters: &[LabelFilter]) -> bool {
+    filters
+        .iter()
+        .all(|(key, value)| match (labels.get(key), value) {
+            (Some(actual), Some(expected)) => actual == expected,
+            (Some(_), None) => true,
+            (None, _) => false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_label() {
+        assert_eq!(
+            parse_label("project=billing").unwrap(),
+            ("project".to_string(), "billing".to_string())
+        );
+        assert_eq!(
+            parse_label("url=a=b").unwrap(),
+            ("url".to_string(), "a=b".to_string())
+        );
+        assert_eq!(
+            parse_label("empty=").unwrap(),
+            ("empty".to_string(), String::new())
+        );
+        assert!(parse_label("project").is_err());
+        assert!(parse_label("=billing").is_err());
+        assert!(parse_label("my project=billing").is_err());
+        assert!(parse_label("a,b=c").is_err());
+        assert!(parse_label("hosts=a,b").is_err());
+        assert!(validate_label("note", "line\nbreak").is_err());
+        assert!(validate_label(&"k".repeat(MAX_KEY_LENGTH + 1), "v").is_err());
+    }
+
+    #[test]
+    fn test_label_filters_select_matching_subset() {
+        let resources = [
+            ("a", labels(&[("project", "billing"), ("env", "prod")])),
+            ("b", labels(&[("project", "billing"), ("env", "dev")])),
+            ("c", labels(&[("project", "search")])),
+            ("d", Labels::new()),
+        ];
+        let matching = |list: &str| {
+            let filters = parse_label_filters(list).unwrap();
+            resources
+                .iter()
+                .filter(|(_, l)| labels_match(l, &filters))
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(matching("project=billing"), ["a", "b"]);
+        assert_eq!(matching("project=billing, env=dev"), ["b"]);
+        assert_eq!(matching("env"), ["a", "b"]);
+        assert_eq!(matching("project=missing"), Vec::<&str>::new());
+        assert_eq!(matching(""), ["a", "b", "c", "d"]);
+        assert!(parse_label_filters("project=billing,=dev").is_err());
+    }
+}
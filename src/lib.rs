@@ -66,6 +66,7 @@ pub mod api;
 pub mod config;
 pub mod db;
 pub mod error;
+pub mod labels;
 pub mod log_rotation;
 pub mod mount;
 pub mod network;
@@ -2,13 +2,18 @@
 //!
 //! Provides automatic log rotation when log files exceed a size threshold.
 //! Rotated logs follow the pattern: `filename.1`, `filename.2`, etc.
+//!
+//! libkrun writes console output straight to a path and keeps the fd open,
+//! so renaming the file underneath it doesn't bound its size. Instead,
+//! [`ConsolePump`] hands libkrun a FIFO and copies from it into a
+//! [`RotatingLogWriter`], which rotates as it writes.
 
 use std::fs;
-use std::io;
-use std::path::Path;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
 /// Maximum log file size before rotation (10 MB).
-const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+pub const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
 
 /// Maximum number of rotated log files to keep.
 const MAX_LOG_FILES: usize = 3;
@@ -106,6 +111,195 @@ pub fn cleanup_logs(log_path: &Path) -> io::Result<()> {
     Ok(())
 }
 
+/// Path of the rotated log segment `index` (0 is the live log).
+fn segment_path(log_path: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        log_path.to_path_buf()
+    } else {
+        PathBuf::from(format!("{}.{}", log_path.display(), index))
+    }
+}
+
+/// Size-capped log writer.
+///
+/// Appends to `log_path` and rotates it (see [`rotate`]) before a write
+/// would push it past `max_bytes`, so the live log never exceeds the cap
+/// and at most `MAX_LOG_FILES` older segments are kept. Together the
+/// segments act as a ring buffer of the most recent output.
+#[derive(Debug)]
+pub struct RotatingLogWriter {
+    path: PathBuf,
+    file: fs::File,
+    written: u64,
+    max_bytes: u64,
+}
+
+impl RotatingLogWriter {
+    /// Open `log_path` for appending, rotating at `max_bytes`.
+    pub fn open(log_path: &Path, max_bytes: u64) -> io::Result<Self> {
+        let file = Self::open_file(log_path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: log_path.to_path_buf(),
+            file,
+            written,
+            max_bytes: max_bytes.max(1),
+        })
+    }
+
+    fn open_file(path: &Path) -> io::Result<fs::File> {
+        fs::OpenOptions::new().create(true).append(true).open(path)
+    }
+}
+
+impl Write for RotatingLogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            rotate(&self.path)?;
+            self.file = Self::open_file(&self.path)?;
+            self.written = 0;
+        }
+        // Never let a single write overshoot the cap
+        let len = buf.len().min(self.max_bytes as usize);
+        let n = self.file.write(&buf[..len])?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Read the last `max_lines` lines of a log, including rotated segments.
+///
+/// Lines are returned oldest first. Only as much of each file as needed is
+/// read, starting from the end.
+pub fn tail_lines(log_path: &Path, max_lines: usize) -> io::Result<Vec<String>> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut found_any = false;
+
+    // Walk from the live log back through older segments
+    for index in 0..=MAX_LOG_FILES {
+        if lines.len() >= max_lines {
+            break;
+        }
+        let path = segment_path(log_path, index);
+        let file_lines = match tail_file(&path, max_lines - lines.len()) {
+            Ok(l) => l,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        found_any = true;
+        lines.splice(0..0, file_lines);
+    }
+
+    if !found_any {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("log file not found: {}", log_path.display()),
+        ));
+    }
+    Ok(lines)
+}
+
+/// Read the last `max_lines` lines of a single file.
+fn tail_file(path: &Path, max_lines: usize) -> io::Result<Vec<String>> {
+    const CHUNK: u64 = 64 * 1024;
+
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+
+    // Read backwards in chunks until we have enough newlines
+    let mut offset = len;
+    let mut buf: Vec<u8> = Vec::new();
+    while offset > 0 {
+        let read = CHUNK.min(offset);
+        offset -= read;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut chunk = vec![0u8; read as usize];
+        file.read_exact(&mut chunk)?;
+        chunk.extend_from_slice(&buf);
+        buf = chunk;
+
+        let newlines = buf.iter().filter(|&&b| b == b'\n').count();
+        if newlines > max_lines {
+            break;
+        }
+    }
+
+    let text = String::from_utf8_lossy(&buf);
+    let mut lines: Vec<&str> = text.lines().collect();
+    // The first line is partial if we stopped mid-file
+    if offset > 0 && !lines.is_empty() {
+        lines.remove(0);
+    }
+    let start = lines.len().saturating_sub(max_lines);
+    Ok(lines[start..].iter().map(|l| l.to_string()).collect())
+}
+
+/// Copies VM console output from a FIFO into a size-capped log.
+///
+/// Create it before configuring the VM and pass [`fifo_path`](Self::fifo_path)
+/// as the console output, then call [`spawn`](Self::spawn) in the process
+/// that will run the VM, or [`discard`](Self::discard) if the VM won't use it.
+#[derive(Debug)]
+pub struct ConsolePump {
+    fifo: PathBuf,
+    log_path: PathBuf,
+    max_bytes: u64,
+}
+
+impl ConsolePump {
+    /// Create the FIFO next to `log_path`.
+    pub fn create(log_path: &Path, max_bytes: u64) -> io::Result<Self> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let fifo = PathBuf::from(format!("{}.fifo", log_path.display()));
+        let _ = fs::remove_file(&fifo);
+
+        let c_path = std::ffi::CString::new(fifo.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: c_path is a valid NUL-terminated path
+        if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fifo,
+            log_path: log_path.to_path_buf(),
+            max_bytes,
+        })
+    }
+
+    /// Path to hand to the VM as its console output.
+    pub fn fifo_path(&self) -> &Path {
+        &self.fifo
+    }
+
+    /// Remove the FIFO of a pump that won't be started.
+    pub fn discard(self) {
+        let _ = fs::remove_file(&self.fifo);
+    }
+
+    /// Start copying console output into the log on a background thread.
+    ///
+    /// The FIFO is unlinked once the VM has opened it.
+    pub fn spawn(self) -> io::Result<std::thread::JoinHandle<()>> {
+        let mut writer = RotatingLogWriter::open(&self.log_path, self.max_bytes)?;
+        std::thread::Builder::new()
+            .name("console-log".into())
+            .spawn(move || {
+                // Blocks until the VM opens the write end
+                let reader = fs::File::open(&self.fifo);
+                let _ = fs::remove_file(&self.fifo);
+                if let Ok(mut reader) = reader {
+                    let _ = io::copy(&mut reader, &mut writer);
+                }
+            })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -234,4 +428,87 @@ mod tests {
         // Should not error, just return false
         assert!(!rotate_if_needed(&log_path).unwrap());
     }
+
+    #[test]
+    fn test_rotating_writer_wraps() {
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("console.log");
+        let mut writer = RotatingLogWriter::open(&log_path, 64).unwrap();
+
+        for i in 0..100 {
+            writeln!(writer, "line {:03}", i).unwrap();
+        }
+
+        // The live log stays under the cap and older segments are bounded
+        assert!(fs::metadata(&log_path).unwrap().len() <= 64);
+        assert!(dir.path().join("console.log.3").exists());
+        assert!(!dir.path().join("console.log.4").exists());
+        assert!(total_log_size(&log_path).unwrap() <= 64 * (MAX_LOG_FILES as u64 + 1));
+
+        // The newest output survives, the oldest has been dropped
+        let all = tail_lines(&log_path, 1000).unwrap();
+        assert_eq!(all.last().unwrap(), "line 099");
+        assert!(!all.contains(&"line 000".to_string()));
+    }
+
+    #[test]
+    fn test_rotating_writer_appends_to_existing() {
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("console.log");
+        fs::write(&log_path, b"earlier\n").unwrap();
+
+        let mut writer = RotatingLogWriter::open(&log_path, 1024).unwrap();
+        writer.write_all(b"later\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&log_path).unwrap(), "earlier\nlater\n");
+    }
+
+    #[test]
+    fn test_tail_lines_spans_rotated_segments() {
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("test.log");
+        fs::write(dir.path().join("test.log.2"), b"a\nb\n").unwrap();
+        fs::write(dir.path().join("test.log.1"), b"c\nd\n").unwrap();
+        fs::write(&log_path, b"e\nf\n").unwrap();
+
+        assert_eq!(tail_lines(&log_path, 1).unwrap(), vec!["f"]);
+        assert_eq!(tail_lines(&log_path, 3).unwrap(), vec!["d", "e", "f"]);
+        assert_eq!(
+            tail_lines(&log_path, 100).unwrap(),
+            vec!["a", "b", "c", "d", "e", "f"]
+        );
+        assert!(tail_lines(&dir.path().join("missing.log"), 5).is_err());
+    }
+
+    #[test]
+    fn test_tail_file_reads_from_end_of_large_file() {
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("big.log");
+        let content: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&log_path, content).unwrap();
+
+        assert_eq!(
+            tail_lines(&log_path, 2).unwrap(),
+            vec!["line 19998", "line 19999"]
+        );
+    }
+
+    #[test]
+    fn test_console_pump_copies_fifo_output() {
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("console.log");
+
+        let pump = ConsolePump::create(&log_path, 1024).unwrap();
+        let fifo = pump.fifo_path().to_path_buf();
+        let handle = pump.spawn().unwrap();
+
+        {
+            let mut vm = fs::OpenOptions::new().write(true).open(&fifo).unwrap();
+            vm.write_all(b"booted\n").unwrap();
+        }
+        handle.join().unwrap();
+
+        assert_eq!(fs::read_to_string(&log_path).unwrap(), "booted\n");
+        assert!(!fifo.exists());
+    }
 }
@@ -1,6 +1,7 @@
 //! smolvm CLI entry point.
 
 use clap::{Parser, Subcommand};
+use smolvm_protocol::log_format::{LogFormat, LOG_FORMAT_ENV};
 use tracing_subscriber::EnvFilter;
 
 mod cli;
@@ -21,6 +22,19 @@ smolvm serve"
 )]
 #[command(version)]
 struct Cli {
+    /// Only log errors (ignored if RUST_LOG is set)
+    #[arg(short = 'q', long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log more: -v for info, -vv for debug, -vvv for trace (ignored if
+    /// RUST_LOG is set)
+    #[arg(short = 'v', long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Log format: text or json [env: SMOLVM_LOG_FORMAT]
+    #[arg(long, global = true, value_name = "FORMAT")]
+    log_format: Option<LogFormat>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -39,6 +53,35 @@ enum Commands {
     #[command(subcommand, visible_alias = "ct")]
     Container(cli::container::ContainerCmd),
 
+    /// Manage named volumes inside a microVM
+    #[command(subcommand)]
+    Volume(cli::volume::VolumeCmd),
+
+    /// Show microVM logs
+    Logs(cli::logs::LogsCmd),
+
+    /// Show an image's entrypoint, command, environment and labels
+    Inspect(cli::inspect::InspectCmd),
+
+    /// Give a cached image another name
+    Tag(cli::tag::TagCmd),
+
+    /// Check cached images for corrupt layers
+    Verify(cli::verify::VerifyCmd),
+
+    /// Save a cached image to a tar archive
+    Save(cli::save::SaveCmd),
+
+    /// Load images from a tar archive made by `smolvm save`
+    Load(cli::load::LoadCmd),
+
+    /// Show live CPU and memory usage of running microVMs
+    Stats(cli::stats::StatsCmd),
+
+    /// Reclaim disk space from containers, overlays and images
+    #[command(subcommand)]
+    System(cli::system::SystemCmd),
+
     /// Start the HTTP API server for programmatic control
     Serve(cli::serve::ServeCmd),
 
@@ -66,8 +109,13 @@ fn main() {
 
     let cli = Cli::parse();
 
-    // Initialize logging based on RUST_LOG or default to warn
-    init_logging();
+    // Initialize logging based on RUST_LOG, else -q/-v, else warn
+    let filter = log_filter(
+        std::env::var("RUST_LOG").ok().as_deref(),
+        cli.quiet,
+        cli.verbose,
+    );
+    init_logging(filter, cli.log_format);
 
     tracing::debug!(version = smolvm::VERSION, "starting smolvm");
 
@@ -76,6 +124,15 @@ fn main() {
         Commands::Sandbox(cmd) => cmd.run(),
         Commands::Microvm(cmd) => cmd.run(),
         Commands::Container(cmd) => cmd.run(),
+        Commands::Volume(cmd) => cmd.run(),
+        Commands::Logs(cmd) => cmd.run(),
+        Commands::Inspect(cmd) => cmd.run(),
+        Commands::Tag(cmd) => cmd.run(),
+        Commands::Verify(cmd) => cmd.run(),
+        Commands::Save(cmd) => cmd.run(),
+        Commands::Load(cmd) => cmd.run(),
+        Commands::Stats(cmd) => cmd.run(),
+        Commands::System(cmd) => cmd.run(),
         Commands::Serve(cmd) => cmd.run(),
         Commands::Pack(cmd) => cmd.run(),
         Commands::Config(cmd) => cmd.run(),
@@ -91,13 +148,108 @@ fn main() {
     }
 }
 
+/// The log filter directive for the `-q`/`-v` flags.
+fn verbosity_directive(quiet: bool, verbose: u8) -> &'static str {
+    match (quiet, verbose) {
+        (true, _) => "smolvm=error",
+        (false, 0) => "smolvm=warn",
+        (false, 1) => "smolvm=info",
+        (false, 2) => "smolvm=debug",
+        (false, _) => "smolvm=trace",
+    }
+}
+
+/// Build the log filter: a valid `rust_log` wins, else the level the
+/// `-q`/`-v` flags select.
+fn log_filter(rust_log: Option<&str>, quiet: bool, verbose: u8) -> EnvFilter {
+    rust_log
+        .and_then(|directives| EnvFilter::try_new(directives).ok())
+        .unwrap_or_else(|| EnvFilter::new(verbosity_directive(quiet, verbose)))
+}
+
 /// Initialize the tracing subscriber.
-fn init_logging() {
-    let filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("smolvm=warn"));
+///
+/// The format is `flag`, else [`LOG_FORMAT_ENV`], else text.
+fn init_logging(filter: EnvFilter, flag: Option<LogFormat>) {
+    let format = match flag {
+        Some(format) => {
+            // Forwarded into VMs started by this process, so the agent matches
+            std::env::set_var(LOG_FORMAT_ENV, format.as_str());
+            format
+        }
+        None => match LogFormat::from_env() {
+            Some(Ok(format)) => format,
+            Some(Err(e)) => {
+                eprintln!("Warning: {}, using text", e);
+                LogFormat::Text
+            }
+            None => LogFormat::Text,
+        },
+    };
 
-    tracing_subscriber::fmt()
+    let builder = tracing_subscriber::fmt()
         .with_env_filter(filter)
-        .with_target(false)
-        .init();
+        .with_target(false);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().flatten_event(true).init(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity_flags_map_to_levels() {
+        let level = |quiet, verbose| log_filter(None, quiet, verbose).to_string();
+        assert_eq!(level(true, 0), "smolvm=error");
+        assert_eq!(level(false, 0), "smolvm=warn");
+        assert_eq!(level(false, 1), "smolvm=info");
+        assert_eq!(level(false, 2), "smolvm=debug");
+        assert_eq!(level(false, 3), "smolvm=trace");
+        assert_eq!(level(false, 5), "smolvm=trace");
+
+        let cli = Cli::try_parse_from(["smolvm", "-vv", "config", "show"]).unwrap();
+        assert_eq!((cli.quiet, cli.verbose), (false, 2));
+        assert!(Cli::try_parse_from(["smolvm", "-q", "-v", "config", "show"]).is_err());
+    }
+
+    #[test]
+    fn test_container_limit_flags_are_the_same_everywhere() {
+        let limits = ["--memory-limit", "64", "--cpu-limit", "0.5"];
+        for command in [
+            &["sandbox", "run"][..],
+            &["sandbox", "exec"],
+            &["container", "exec", "vm", "ctr"],
+        ] {
+            let args = ["smolvm"]
+                .iter()
+                .chain(command)
+                .chain(&limits)
+                .chain(&["alpine"]);
+            if let Err(e) = Cli::try_parse_from(args) {
+                panic!("{:?}: {}", command, e);
+            }
+        }
+
+        // --memory/--cpus would read like the VM sizing flags (--mem/--cpus)
+        assert!(
+            Cli::try_parse_from(["smolvm", "sandbox", "exec", "--memory", "64", "true"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_rust_log_overrides_verbosity_flags() {
+        assert_eq!(
+            log_filter(Some("smolvm=trace"), true, 0).to_string(),
+            "smolvm=trace"
+        );
+        assert_eq!(log_filter(Some("debug"), false, 1).to_string(), "debug");
+        // An unparseable RUST_LOG falls back to the flags
+        assert_eq!(
+            log_filter(Some("smolvm=loud"), false, 1).to_string(),
+            "smolvm=info"
+        );
+    }
 }
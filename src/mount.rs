@@ -222,27 +222,125 @@ pub fn validate_mount(mount: &HostMount) -> Result<()> {
 
 /// Parse a mount specification string.
 ///
-/// Format: `host_path:guest_path[:ro]`
+/// Format: `host_path:guest_path[:ro|:rw]`
 ///
 /// - `host_path` - Path on the host filesystem
 /// - `guest_path` - Path inside the guest VM
 /// - `ro` - Optional, makes the mount read-only (default is writable)
 ///
+/// The host path is canonicalized (relative paths resolve against the
+/// current directory) and must be an existing directory; the guest path is
+/// normalized with [`normalize_guest_path`]. All errors are reported as
+/// [`Error::InvalidMountSpec`] carrying the spec.
+///
 /// Note: Per DESIGN.md, mounts should be read-only by default, but for CLI
 /// compatibility with Docker-style `-v`, we default to writable unless `:ro`
 /// is specified.
 pub fn parse_mount_spec(spec: &str) -> Result<HostMount> {
+    let invalid = |reason: String| Error::invalid_mount_spec(spec, reason);
+    let (host, guest_path, read_only) = split_mount_spec(spec)?;
+
+    // Canonicalize host path (resolves relative paths and symlinks, and
+    // fails if the path does not exist)
+    let host_path = PathBuf::from(host)
+        .canonicalize()
+        .map_err(|e| invalid(format!("host path '{}': {}", host, e)))?;
+
+    // Must be a directory (virtiofs limitation)
+    if !host_path.is_dir() {
+        return Err(invalid(format!(
+            "host path must be a directory (virtiofs limitation): {}",
+            host_path.display()
+        )));
+    }
+
+    Ok(if read_only {
+        HostMount::new(host_path, guest_path)
+    } else {
+        HostMount::new_writable(host_path, guest_path)
+    })
+}
+
+/// Split a `source:guest_path[:ro|:rw]` spec into its source, normalized
+/// guest path and read-only flag.
+///
+/// The source is returned as written; it may be a host path or a volume
+/// name, so resolving it is up to the caller.
+pub fn split_mount_spec(spec: &str) -> Result<(&str, PathBuf, bool)> {
+    let invalid = |reason: String| Error::invalid_mount_spec(spec, reason);
+
     let parts: Vec<&str> = spec.split(':').collect();
+    let (source, guest, read_only) = match parts.as_slice() {
+        [source, guest] => (*source, *guest, false),
+        [source, guest, "ro"] => (*source, *guest, true),
+        [source, guest, "rw"] => (*source, *guest, false),
+        [_, _, mode] => {
+            return Err(invalid(format!(
+                "unknown mode '{}' (expected 'ro' or 'rw')",
+                mode
+            )))
+        }
+        _ => return Err(invalid("expected source:container[:ro|:rw]".to_string())),
+    };
+
+    if source.is_empty() || guest.is_empty() {
+        return Err(invalid(
+            "source and container paths must not be empty".to_string(),
+        ));
+    }
+
+    let guest_path = normalize_guest_path(Path::new(guest)).map_err(invalid)?;
+    Ok((source, guest_path, read_only))
+}
+
+/// Guest paths that are managed by the container runtime and must not be
+/// shadowed by a volume mount.
+const RESERVED_GUEST_PATHS: &[&str] = &["/", "/proc", "/sys", "/dev"];
+
+/// Validate and normalize a guest mount target.
+///
+/// The path must be absolute and must not contain `..` components. Redundant
+/// separators and `.` components are removed. Targets equal to or below a
+/// reserved runtime mountpoint (`/proc`, `/sys`, `/dev`) or the root itself
+/// are rejected.
+pub fn normalize_guest_path(path: &Path) -> std::result::Result<PathBuf, String> {
+    use std::path::Component;
+
+    if !path.is_absolute() {
+        return Err(format!("guest path must be absolute: {}", path.display()));
+    }
+
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::RootDir | Component::CurDir => {}
+            Component::Normal(part) => normalized.push(part),
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(format!(
+                    "guest path must not contain '..': {}",
+                    path.display()
+                ));
+            }
+        }
+    }
 
-    match parts.as_slice() {
-        [source, target] => Ok(HostMount::new_writable(source, target)),
-        [source, target, "ro"] => Ok(HostMount::new(source, target)),
-        [source, target, "rw"] => Ok(HostMount::new_writable(source, target)),
-        _ => Err(Error::invalid_mount_path(format!(
-            "invalid format '{}' (expected host:guest[:ro|:rw])",
-            spec
-        ))),
+    for reserved in RESERVED_GUEST_PATHS {
+        let reserved = Path::new(reserved);
+        let collides = if reserved == Path::new("/") {
+            normalized == reserved
+        } else {
+            normalized.starts_with(reserved)
+        };
+        if collides {
+            return Err(format!(
+                "guest path {} collides with reserved mountpoint {}",
+                normalized.display(),
+                reserved.display()
+            ));
+        }
     }
+
+    Ok(normalized)
 }
 
 /// Check if a path is safe to mount (not a sensitive system path).
@@ -274,21 +372,24 @@ mod tests {
 
     #[test]
     fn test_parse_mount_spec_basic() {
-        let mount = parse_mount_spec("/host/path:/guest/path").unwrap();
-        assert_eq!(mount.source, PathBuf::from("/host/path"));
+        let tmp = tempfile::tempdir().unwrap();
+        let mount = parse_mount_spec(&format!("{}:/guest/path", tmp.path().display())).unwrap();
+        assert_eq!(mount.source, tmp.path().canonicalize().unwrap());
         assert_eq!(mount.target, PathBuf::from("/guest/path"));
         assert!(!mount.read_only); // Default writable for CLI compat
     }
 
     #[test]
     fn test_parse_mount_spec_read_only() {
-        let mount = parse_mount_spec("/host/path:/guest/path:ro").unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        let mount = parse_mount_spec(&format!("{}:/guest/path:ro", tmp.path().display())).unwrap();
         assert!(mount.read_only);
     }
 
     #[test]
     fn test_parse_mount_spec_explicit_rw() {
-        let mount = parse_mount_spec("/host/path:/guest/path:rw").unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        let mount = parse_mount_spec(&format!("{}:/guest/path:rw", tmp.path().display())).unwrap();
         assert!(!mount.read_only);
     }
 
@@ -303,11 +404,27 @@ mod tests {
 
     #[test]
     fn test_parse_mount_spec_paths_with_spaces() {
-        let mount = parse_mount_spec("/path/with spaces:/guest/path").unwrap();
-        assert_eq!(mount.source, PathBuf::from("/path/with spaces"));
+        let tmp = tempfile::tempdir().unwrap();
+        let host = tmp.path().join("with spaces");
+        std::fs::create_dir(&host).unwrap();
+        let mount = parse_mount_spec(&format!("{}:/guest/path", host.display())).unwrap();
+        assert_eq!(mount.source, host.canonicalize().unwrap());
         assert_eq!(mount.target, PathBuf::from("/guest/path"));
     }
 
+    #[test]
+    fn test_split_mount_spec_keeps_source() {
+        // Volume names are not resolved against the filesystem
+        let (source, target, read_only) = split_mount_spec("data:/var/lib//data/:ro").unwrap();
+        assert_eq!(source, "data");
+        assert_eq!(target, PathBuf::from("/var/lib/data"));
+        assert!(read_only);
+
+        assert!(split_mount_spec(":/data").is_err());
+        assert!(split_mount_spec("data:").is_err());
+        assert!(split_mount_spec("data:/proc").is_err());
+    }
+
     #[test]
     fn test_parse_mount_spec_invalid_mode() {
         // Invalid mode should fail
@@ -380,6 +497,32 @@ mod tests {
         );
     }
 
+    // === Guest Path Normalization ===
+
+    #[test]
+    fn test_normalize_guest_path() {
+        assert_eq!(
+            normalize_guest_path(Path::new("/app/")).unwrap(),
+            PathBuf::from("/app")
+        );
+        assert_eq!(
+            normalize_guest_path(Path::new("//data/./cache")).unwrap(),
+            PathBuf::from("/data/cache")
+        );
+        // Only whole components collide with reserved paths
+        assert!(normalize_guest_path(Path::new("/devices")).is_ok());
+    }
+
+    #[test]
+    fn test_normalize_guest_path_rejects_invalid() {
+        assert!(normalize_guest_path(Path::new("app")).is_err());
+        assert!(normalize_guest_path(Path::new("/app/../etc")).is_err());
+        assert!(normalize_guest_path(Path::new("/")).is_err());
+        assert!(normalize_guest_path(Path::new("/proc")).is_err());
+        assert!(normalize_guest_path(Path::new("/dev/shm")).is_err());
+        assert!(normalize_guest_path(Path::new("/sys/")).is_err());
+    }
+
     // === Safe Mount Source Checks ===
 
     #[test]
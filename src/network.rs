@@ -3,8 +3,16 @@
 //! This module provides network policy configuration for VMs.
 
 use crate::vm::config::NetworkPolicy;
+use smolvm_protocol::dns::{DnsConfig, DNS_SEARCH_ENV, DNS_SERVERS_ENV};
 use std::net::{IpAddr, Ipv4Addr};
 
+/// The host's resolver configuration.
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+/// Upstream resolvers of systemd-resolved, whose stub at 127.0.0.53 is all
+/// `/etc/resolv.conf` lists when it is in use.
+const SYSTEMD_RESOLV_CONF_PATH: &str = "/run/systemd/resolve/resolv.conf";
+
 /// Default DNS server (Cloudflare) as string.
 pub const DEFAULT_DNS: &str = "1.1.1.1";
 /// Default DNS server as IpAddr (compile-time constant).
@@ -18,10 +26,133 @@ pub fn get_dns_server(policy: &NetworkPolicy) -> Option<IpAddr> {
     }
 }
 
+/// The DNS configuration for a guest with the given network policy, or
+/// `None` if it has no network access.
+///
+/// The policy's server, if it names one, replaces the servers of
+/// [`guest_dns`]; the search domains are kept either way.
+pub fn policy_dns(policy: &NetworkPolicy, fallback: IpAddr) -> Option<DnsConfig> {
+    match policy {
+        NetworkPolicy::None => None,
+        NetworkPolicy::Egress { dns } => {
+            let mut config = guest_dns(fallback);
+            if let Some(dns) = dns {
+                config.servers = vec![*dns];
+            }
+            Some(config)
+        }
+    }
+}
+
+/// The DNS configuration guests should use: the servers and search domains
+/// in [`DNS_SERVERS_ENV`] and [`DNS_SEARCH_ENV`] if the former is set, the
+/// host's own otherwise (see [`host_dns`]).
+pub fn guest_dns(fallback: IpAddr) -> DnsConfig {
+    if let Ok(servers) = std::env::var(DNS_SERVERS_ENV) {
+        let search = std::env::var(DNS_SEARCH_ENV).ok();
+        match DnsConfig::from_env_values(&servers, search.as_deref()) {
+            Ok(config) => return config,
+            Err(e) => tracing::warn!(error = %e, "ignoring {}", DNS_SERVERS_ENV),
+        }
+    }
+    let mut config = host_dns(fallback);
+    if let Ok(search) = std::env::var(DNS_SEARCH_ENV) {
+        match DnsConfig::parse_search(&search) {
+            Ok(domains) => config.search = domains,
+            Err(e) => tracing::warn!(error = %e, "ignoring {}", DNS_SEARCH_ENV),
+        }
+    }
+    config
+}
+
+/// The host's resolvers and search domains.
+///
+/// Loopback resolvers can't be reached from a guest, so they are left out;
+/// if that leaves none, systemd-resolved's upstream servers are used, and
+/// failing that `fallback` (normally [`DEFAULT_DNS_ADDR`]).
+pub fn host_dns(fallback: IpAddr) -> DnsConfig {
+    let read = |path: &str| std::fs::read_to_string(path).ok();
+    host_dns_from(
+        read(RESOLV_CONF_PATH).as_deref(),
+        read(SYSTEMD_RESOLV_CONF_PATH).as_deref(),
+        fallback,
+    )
+}
+
+fn host_dns_from(
+    resolv_conf: Option<&str>,
+    systemd_resolv_conf: Option<&str>,
+    fallback: IpAddr,
+) -> DnsConfig {
+    let usable = |text: Option<&str>| {
+        let mut config = DnsConfig::parse_resolv_conf(text.unwrap_or_default());
+        config.servers.retain(|server| !server.is_loopback());
+        config
+    };
+
+    let mut config = usable(resolv_conf);
+    if config.servers.is_empty() {
+        config.servers = usable(systemd_resolv_conf).servers;
+    }
+    if config.servers.is_empty() {
+        config.servers.push(fallback);
+    }
+    config
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_host_dns_uses_host_resolvers() {
+        let config = host_dns_from(
+            Some("search corp.example\nnameserver 10.0.0.2\nnameserver 127.0.0.1\n"),
+            None,
+            DEFAULT_DNS_ADDR,
+        );
+        assert_eq!(config.servers, vec!["10.0.0.2".parse::<IpAddr>().unwrap()]);
+        assert_eq!(config.search, vec!["corp.example"]);
+
+        // systemd-resolved's stub is replaced by its upstream servers
+        let config = host_dns_from(
+            Some("nameserver 127.0.0.53\nsearch lan\n"),
+            Some("nameserver 192.168.1.1\nnameserver ::1\n"),
+            DEFAULT_DNS_ADDR,
+        );
+        assert_eq!(
+            config.servers,
+            vec!["192.168.1.1".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(config.search, vec!["lan"]);
+
+        // Public DNS only when the host has nothing usable
+        let config = host_dns_from(None, None, DEFAULT_DNS_ADDR);
+        assert_eq!(config.servers, vec![DEFAULT_DNS_ADDR]);
+        let fallback: IpAddr = "9.9.9.9".parse().unwrap();
+        let config = host_dns_from(Some("nameserver 127.0.0.53\n"), None, fallback);
+        assert_eq!(config.servers, vec![fallback]);
+    }
+
+    #[test]
+    fn test_policy_dns() {
+        assert!(policy_dns(&NetworkPolicy::None, DEFAULT_DNS_ADDR).is_none());
+
+        let custom: IpAddr = "10.1.2.3".parse().unwrap();
+        let config = policy_dns(
+            &NetworkPolicy::Egress { dns: Some(custom) },
+            DEFAULT_DNS_ADDR,
+        )
+        .unwrap();
+        assert_eq!(config.servers, vec![custom]);
+        assert_eq!(
+            config.search,
+            policy_dns(&NetworkPolicy::Egress { dns: None }, DEFAULT_DNS_ADDR)
+                .unwrap()
+                .search
+        );
+    }
+
     #[test]
     fn test_get_dns_server() {
         // None policy returns no DNS
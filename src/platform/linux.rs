@@ -5,7 +5,7 @@
 //! wrapper script is needed.
 
 use crate::error::{Error, Result};
-use crate::platform::traits::{RosettaSupport, VmExecutor};
+use crate::platform::traits::{RosettaSupport, VirtiofsMount, VmExecutor};
 use std::ffi::CString;
 use std::fs;
 use std::path::Path;
@@ -82,10 +82,15 @@ impl VmExecutor for LinuxExecutor {
         false
     }
 
+    fn enforces_read_only_mounts(&self) -> bool {
+        // The guest mounts shares itself, with no way to pass `-o ro`
+        false
+    }
+
     fn build_exec_command(
         &self,
         command: &Option<Vec<String>>,
-        _mounts: &[(String, String)], // Ignored on Linux - kernel handles virtiofs
+        _mounts: &[VirtiofsMount], // Ignored on Linux - kernel handles virtiofs
         _rootfs: &Path,
         _rosetta: bool, // Ignored on Linux - Rosetta is macOS-only
     ) -> Result<(CString, Vec<*const libc::c_char>, Vec<CString>)> {
@@ -200,7 +205,11 @@ mod tests {
         let tmp = TempDir::new().unwrap();
         let cmd = Some(vec!["/bin/echo".to_string(), "hello".to_string()]);
         // Linux should ignore mounts - kernel handles virtiofs automatically
-        let mounts = vec![("smolvm0".to_string(), "/data".to_string())];
+        let mounts = vec![VirtiofsMount {
+            tag: "smolvm0".to_string(),
+            guest_path: "/data".to_string(),
+            read_only: true,
+        }];
 
         let (exec_path, _argv, cstrings) = executor
             .build_exec_command(&cmd, &mounts, tmp.path(), false)
@@ -5,7 +5,9 @@
 //! via a wrapper script, as the kernel doesn't auto-mount virtiofs devices.
 
 use crate::error::{Error, Result};
-use crate::platform::traits::{RosettaSupport, VmExecutor};
+use crate::platform::traits::{
+    RosettaSupport, VirtiofsMount, VmExecutor, VERIFY_READ_ONLY_MOUNTS_ENV,
+};
 use std::ffi::CString;
 use std::fs::{self, File};
 use std::io::Write;
@@ -24,10 +26,14 @@ impl VmExecutor for MacOsExecutor {
         true
     }
 
+    fn enforces_read_only_mounts(&self) -> bool {
+        true
+    }
+
     fn build_exec_command(
         &self,
         command: &Option<Vec<String>>,
-        mounts: &[(String, String)],
+        mounts: &[VirtiofsMount],
         rootfs: &Path,
         rosetta: bool,
     ) -> Result<(CString, Vec<*const libc::c_char>, Vec<CString>)> {
@@ -133,7 +139,13 @@ pub fn rosetta_support() -> MacOsRosetta {
 /// This script mounts all virtiofs volumes before executing the user's command.
 /// It's written to /tmp inside the rootfs to avoid mutating the container image.
 /// If rosetta is enabled, it also mounts the Rosetta runtime and registers binfmt_misc.
-fn write_mount_script(rootfs: &Path, mounts: &[(String, String)], rosetta: bool) -> Result<String> {
+///
+/// Read-only shares are mounted with `-o ro`. With
+/// [`VERIFY_READ_ONLY_MOUNTS_ENV`] set, the script also tries a write to each
+/// read-only mount and aborts the boot if it succeeds.
+fn write_mount_script(rootfs: &Path, mounts: &[VirtiofsMount], rosetta: bool) -> Result<String> {
+    let verify_read_only = std::env::var_os(VERIFY_READ_ONLY_MOUNTS_ENV).is_some();
+
     let tmp_dir = rootfs.join("tmp");
     if !tmp_dir.exists() {
         fs::create_dir_all(&tmp_dir)
@@ -159,19 +171,28 @@ fn write_mount_script(rootfs: &Path, mounts: &[(String, String)], rosetta: bool)
     // Both tag and guest_mount are single-quoted to prevent shell injection.
     // Any embedded single quotes are escaped as `'\''` (end quote, literal
     // quote via backslash, restart quote).
-    for (tag, guest_mount) in mounts {
-        write_line(
-            &mut file,
-            &format!("mkdir -p '{}'", shell_escape(guest_mount)),
-        )?;
+    for mount in mounts {
+        let guest_mount = shell_escape(&mount.guest_path);
+        write_line(&mut file, &format!("mkdir -p '{}'", guest_mount))?;
         write_line(
             &mut file,
             &format!(
-                "mount -t virtiofs '{}' '{}'",
-                shell_escape(tag),
-                shell_escape(guest_mount)
+                "mount -t virtiofs -o {} '{}' '{}'",
+                mount.mount_options(),
+                shell_escape(&mount.tag),
+                guest_mount
             ),
         )?;
+        if verify_read_only && mount.read_only {
+            let probe = format!("{}/.smolvm-ro-check", guest_mount);
+            write_line(
+                &mut file,
+                &format!(
+                    "if touch '{probe}' 2>/dev/null; then rm -f '{probe}'; \
+                     echo 'smolvm: read-only mount {guest_mount} is writable' >&2; exit 1; fi"
+                ),
+            )?;
+        }
     }
 
     // If Rosetta is enabled, mount the runtime and register binfmt_misc
@@ -307,7 +328,11 @@ mod tests {
         let executor = MacOsExecutor;
         let tmp = TempDir::new().unwrap();
         let cmd = Some(vec!["/bin/cat".to_string(), "/data/file.txt".to_string()]);
-        let mounts = vec![("smolvm0".to_string(), "/data".to_string())];
+        let mounts = vec![VirtiofsMount {
+            tag: "smolvm0".to_string(),
+            guest_path: "/data".to_string(),
+            read_only: true,
+        }];
 
         let (exec_path, _argv, _cstrings) = executor
             .build_exec_command(&cmd, &mounts, tmp.path(), false)
@@ -327,8 +352,8 @@ mod tests {
             "script should create mount point"
         );
         assert!(
-            content.contains("mount -t virtiofs 'smolvm0' '/data'"),
-            "script should mount virtiofs"
+            content.contains("mount -t virtiofs -o ro 'smolvm0' '/data'"),
+            "script should mount virtiofs read-only"
         );
         assert!(
             content.contains("exec \"$@\""),
@@ -391,10 +416,11 @@ mod tests {
     #[test]
     fn test_mount_script_escapes_malicious_input() {
         let tmp = TempDir::new().unwrap();
-        let mounts = vec![(
-            "smolvm0".to_string(),
-            "/data'; rm -rf /; echo '".to_string(),
-        )];
+        let mounts = vec![VirtiofsMount {
+            tag: "smolvm0".to_string(),
+            guest_path: "/data'; rm -rf /; echo '".to_string(),
+            read_only: false,
+        }];
         let cmd = Some(vec!["/bin/sh".to_string()]);
 
         let executor = MacOsExecutor;
@@ -417,7 +443,7 @@ mod tests {
             content
         );
         assert!(
-            content.contains("mount -t virtiofs 'smolvm0' '/data'\\''"),
+            content.contains("mount -t virtiofs -o rw 'smolvm0' '/data'\\''"),
             "mount should use escaped path, got: {}",
             content
         );
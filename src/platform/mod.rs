@@ -43,7 +43,7 @@ pub mod macos;
 #[cfg(target_os = "linux")]
 pub mod linux;
 
-pub use traits::{RosettaSupport, VmExecutor};
+pub use traits::{RosettaSupport, VirtiofsMount, VmExecutor, VERIFY_READ_ONLY_MOUNTS_ENV};
 
 /// Host operating system.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -7,6 +7,34 @@ use crate::error::Result;
 use std::ffi::CString;
 use std::path::Path;
 
+/// Environment variable that, when set, makes the guest check at boot that
+/// every read-only virtiofs mount rejects writes, failing the boot if not.
+///
+/// A debugging aid for backend changes; adds a write attempt per mount.
+pub const VERIFY_READ_ONLY_MOUNTS_ENV: &str = "SMOLVM_VERIFY_READONLY_MOUNTS";
+
+/// A virtiofs share as the guest mounts it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtiofsMount {
+    /// virtiofs device tag.
+    pub tag: String,
+    /// Mount point inside the guest.
+    pub guest_path: String,
+    /// Whether the guest must mount the share read-only.
+    pub read_only: bool,
+}
+
+impl VirtiofsMount {
+    /// Options for the guest's `mount -t virtiofs -o ...`.
+    pub fn mount_options(&self) -> &'static str {
+        if self.read_only {
+            "ro"
+        } else {
+            "rw"
+        }
+    }
+}
+
 /// Trait for platform-specific VM execution behaviors.
 ///
 /// This abstracts over differences in how VMs are executed on different
@@ -21,12 +49,18 @@ pub trait VmExecutor: Send + Sync {
     /// `false` on Linux where the kernel handles it automatically.
     fn requires_mount_wrapper(&self) -> bool;
 
+    /// Whether read-only virtiofs mounts are actually mounted read-only.
+    ///
+    /// virtiofs itself has no read-only flag; the guest has to mount the
+    /// share with `-o ro`, which only a mount wrapper can do.
+    fn enforces_read_only_mounts(&self) -> bool;
+
     /// Build the execution command, optionally wrapping with mount script.
     ///
     /// # Arguments
     ///
     /// * `command` - The user's command to execute (None defaults to /bin/sh)
-    /// * `mounts` - List of (virtiofs_tag, guest_path) tuples for virtiofs mounts
+    /// * `mounts` - virtiofs shares to mount in the guest
     /// * `rootfs` - Path to the rootfs directory on the host
     /// * `rosetta` - Whether Rosetta is enabled for x86_64 binary support
     ///
@@ -40,7 +74,7 @@ pub trait VmExecutor: Send + Sync {
     fn build_exec_command(
         &self,
         command: &Option<Vec<String>>,
-        mounts: &[(String, String)],
+        mounts: &[VirtiofsMount],
         rootfs: &Path,
         rosetta: bool,
     ) -> Result<(CString, Vec<*const libc::c_char>, Vec<CString>)>;
@@ -4,6 +4,7 @@
 //! - Loading registry credentials from a TOML configuration file
 //! - Environment variable-based password resolution
 //! - Registry mirrors for pull-through caching
+//! - Falling back to docker's `config.json` logins
 //!
 //! # Configuration File
 //!
@@ -12,6 +13,7 @@
 //! ```toml
 //! [defaults]
 //! # registry = "docker.io"  # Optional: default registry
+//! # max_download_rate = 10485760  # Optional: cap each pull at 10 MiB/s
 //!
 //! [registries."docker.io"]
 //! username = "myuser"
@@ -26,11 +28,24 @@
 //! password = "secret"  # Direct password (not recommended)
 //! mirror = "mirror.example.com"  # Optional mirror
 //! ```
+//!
+//! # Docker Logins
+//!
+//! Registries without usable credentials in `registries.toml` fall back to
+//! docker's `config.json` (in `$DOCKER_CONFIG`, default `~/.docker`), so an
+//! existing `docker login` works as is. Both inline `auths` and credential
+//! helpers (`credHelpers`, `credsStore`) are supported; see [`DockerConfig`].
 
 use crate::error::{Error, Result};
+use base64::Engine;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::Write;
 use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
 
 /// Registry configuration loaded from `~/.config/smolvm/registries.toml`.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -41,6 +56,9 @@ pub struct RegistryConfig {
     /// Default settings.
     #[serde(default)]
     pub defaults: RegistryDefaults,
+    /// Docker logins, consulted for registries without credentials here.
+    #[serde(skip)]
+    pub docker: DockerConfig,
 }
 
 /// Configuration for a single registry.
@@ -61,17 +79,26 @@ pub struct RegistryEntry {
 pub struct RegistryDefaults {
     /// Default registry when none specified (defaults to docker.io).
     pub registry: Option<String>,
+    /// Cap on each pull's download rate, in bytes per second.
+    pub max_download_rate: Option<u64>,
 }
 
 // Re-export RegistryAuth from protocol to avoid duplication
 pub use smolvm_protocol::RegistryAuth;
 
 impl RegistryConfig {
-    /// Load registry configuration from the default config file.
+    /// Load registry configuration from the default config file, along
+    /// with docker's logins (see [`DockerConfig::load`]).
     ///
     /// If the config file doesn't exist, returns an empty configuration.
     /// Errors are logged but don't cause failure - we fall back to empty config.
     pub fn load() -> Result<Self> {
+        let mut config = Self::load_file()?;
+        config.docker = DockerConfig::load();
+        Ok(config)
+    }
+
+    fn load_file() -> Result<Self> {
         let config_path = match Self::config_path() {
             Ok(p) => p,
             Err(e) => {
@@ -120,12 +147,18 @@ impl RegistryConfig {
 
     /// Get credentials for a registry, resolving environment variables.
     ///
-    /// Returns `Some((username, password))` if credentials are configured and available.
-    /// Returns `None` if:
+    /// Returns `Some((username, password))` if credentials are configured and
+    /// available, else whatever docker's config has for the registry.
+    /// Configured credentials are unavailable if:
     /// - No entry for this registry
     /// - No username configured
     /// - Password not available (env var not set, no direct password)
     pub fn get_credentials(&self, registry: &str) -> Option<RegistryAuth> {
+        self.configured_credentials(registry)
+            .or_else(|| self.docker.get_credentials(registry))
+    }
+
+    fn configured_credentials(&self, registry: &str) -> Option<RegistryAuth> {
         let entry = self.registries.get(registry)?;
         let username = entry.username.as_ref()?;
 
@@ -170,6 +203,291 @@ impl RegistryConfig {
     }
 }
 
+/// Registry logins from docker's `config.json`.
+///
+/// Credentials for a registry come from its `credHelpers` entry, then its
+/// inline `auths` entry, then the `credsStore` helper. Helpers are run as
+/// `docker-credential-<name> get`, the way docker runs them.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, DockerAuth>,
+    #[serde(default, rename = "credHelpers")]
+    cred_helpers: HashMap<String, String>,
+    #[serde(default, rename = "credsStore")]
+    creds_store: Option<String>,
+    /// `PATH` to find credential helpers on; the inherited one if `None`.
+    #[serde(skip)]
+    helper_path: Option<OsString>,
+    /// How long a helper may run; [`CREDENTIAL_HELPER_TIMEOUT`] if `None`.
+    #[serde(skip)]
+    helper_timeout: Option<Duration>,
+}
+
+/// An `auths` entry: base64 `user:pass` in `auth`, or the two fields.
+#[derive(Clone, Default, Deserialize)]
+struct DockerAuth {
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl fmt::Debug for DockerAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redacted = |secret: &Option<String>| secret.as_ref().map(|_| "<redacted>");
+        f.debug_struct("DockerAuth")
+            .field("auth", &redacted(&self.auth))
+            .field("username", &self.username)
+            .field("password", &redacted(&self.password))
+            .finish()
+    }
+}
+
+/// A credential helper's answer to `get`.
+#[derive(Deserialize)]
+struct HelperCredentials {
+    #[serde(rename = "Username")]
+    username: String,
+    #[serde(rename = "Secret")]
+    secret: String,
+}
+
+/// Server name docker stores Docker Hub logins under.
+const DOCKER_HUB_SERVER: &str = "https://index.docker.io/v1/";
+
+/// How long a credential helper may take before it is killed and the
+/// registry treated as having no login. Long enough to answer a keychain
+/// unlock prompt.
+const CREDENTIAL_HELPER_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl DockerConfig {
+    /// Load `config.json` from `$DOCKER_CONFIG`, or `~/.docker` if unset.
+    ///
+    /// A missing file gives no logins; an unreadable or malformed one is
+    /// logged and ignored, since these logins are only a fallback.
+    pub fn load() -> Self {
+        let Some(path) = Self::config_path() else {
+            return Self::default();
+        };
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "could not read docker config");
+                return Self::default();
+            }
+        };
+        match Self::parse(&contents) {
+            Ok(config) => {
+                tracing::debug!(
+                    path = %path.display(),
+                    auths = config.auths.len(),
+                    cred_helpers = config.cred_helpers.len(),
+                    "loaded docker config"
+                );
+                config
+            }
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "ignoring malformed docker config");
+                Self::default()
+            }
+        }
+    }
+
+    /// Get the path to docker's `config.json`.
+    pub fn config_path() -> Option<PathBuf> {
+        let dir = match std::env::var_os("DOCKER_CONFIG") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => dirs::home_dir()?.join(".docker"),
+        };
+        Some(dir.join("config.json"))
+    }
+
+    /// Parse the contents of a `config.json`.
+    pub fn parse(contents: &str) -> Result<Self> {
+        serde_json::from_str(contents)
+            .map_err(|e| Error::config("parse docker config", e.to_string()))
+    }
+
+    /// Get docker's credentials for a registry.
+    pub fn get_credentials(&self, registry: &str) -> Option<RegistryAuth> {
+        if let Some(helper) = lookup(&self.cred_helpers, registry) {
+            return self.helper_credentials(helper, registry);
+        }
+        if let Some(auth) = lookup(&self.auths, registry).and_then(DockerAuth::credentials) {
+            tracing::debug!(registry = %registry, username = %auth.username, "using docker login");
+            return Some(auth);
+        }
+        let store = self.creds_store.as_deref()?;
+        self.helper_credentials(store, registry)
+    }
+
+    /// Ask `docker-credential-<helper>` for the registry's credentials.
+    fn helper_credentials(&self, helper: &str, registry: &str) -> Option<RegistryAuth> {
+        let program = format!("docker-credential-{}", helper);
+        let server = if registry == DEFAULT_REGISTRY {
+            DOCKER_HUB_SERVER
+        } else {
+            registry
+        };
+
+        let mut command = Command::new(&program);
+        command
+            .arg("get")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(path) = &self.helper_path {
+            command.env("PATH", path);
+        }
+        let timeout = self.helper_timeout.unwrap_or(CREDENTIAL_HELPER_TIMEOUT);
+        let output = match run_helper(command, server, timeout) {
+            Ok(Some(output)) => output,
+            Ok(None) => {
+                tracing::warn!(
+                    helper = %program,
+                    registry = %registry,
+                    timeout_secs = timeout.as_secs_f64(),
+                    "docker credential helper timed out"
+                );
+                return None;
+            }
+            Err(e) => {
+                tracing::warn!(helper = %program, error = %e, "could not run docker credential helper");
+                return None;
+            }
+        };
+        if !output.status.success() {
+            // Typically "credentials not found in native keychain"
+            tracing::debug!(
+                helper = %program,
+                registry = %registry,
+                stderr = %String::from_utf8_lossy(&output.stderr).trim(),
+                "docker credential helper has no login"
+            );
+            return None;
+        }
+
+        // Don't echo the output on failure: it may hold the secret
+        let creds: HelperCredentials = match serde_json::from_slice(&output.stdout) {
+            Ok(creds) => creds,
+            Err(_) => {
+                tracing::warn!(helper = %program, "docker credential helper gave malformed output");
+                return None;
+            }
+        };
+        // "<token>" marks an identity token, which needs a token exchange
+        // rather than basic auth
+        if creds.username.is_empty() || creds.username == "<token>" {
+            tracing::debug!(helper = %program, registry = %registry, "ignoring identity token login");
+            return None;
+        }
+        tracing::debug!(
+            helper = %program,
+            registry = %registry,
+            username = %creds.username,
+            "using docker credential helper"
+        );
+        Some(RegistryAuth {
+            username: creds.username,
+            password: creds.secret,
+        })
+    }
+}
+
+/// Run a credential helper with `server` on its stdin, killing it if it
+/// hasn't exited within `timeout` (`Ok(None)`).
+fn run_helper(
+    mut command: Command,
+    server: &str,
+    timeout: Duration,
+) -> std::io::Result<Option<Output>> {
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A helper that exits without reading closes the pipe; its exit
+        // status says what happened
+        let _ = stdin.write_all(server.as_bytes());
+    }
+
+    // Drain the pipes on threads so a chatty helper can't block on them
+    let drain = |pipe: Option<Box<dyn std::io::Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = drain(child.stderr.take().map(|p| Box::new(p) as _));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    Ok(status.map(|status| Output {
+        status,
+        stdout,
+        stderr,
+    }))
+}
+
+impl DockerAuth {
+    fn credentials(&self) -> Option<RegistryAuth> {
+        if let Some(auth) = &self.auth {
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(auth.trim())
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())?;
+            let (username, password) = decoded.split_once(':')?;
+            return Some(RegistryAuth {
+                username: username.to_string(),
+                password: password.to_string(),
+            });
+        }
+        Some(RegistryAuth {
+            username: self.username.clone()?,
+            password: self.password.clone()?,
+        })
+    }
+}
+
+/// Find the entry for `registry` in a map keyed by docker server names,
+/// which may be URLs (`https://ghcr.io`) or Docker Hub aliases.
+fn lookup<'a, T>(entries: &'a HashMap<String, T>, registry: &str) -> Option<&'a T> {
+    entries.get(registry).or_else(|| {
+        entries
+            .iter()
+            .find(|(server, _)| docker_server_registry(server) == registry)
+            .map(|(_, entry)| entry)
+    })
+}
+
+/// The registry a docker server name refers to.
+fn docker_server_registry(server: &str) -> &str {
+    let host = server
+        .strip_prefix("https://")
+        .or_else(|| server.strip_prefix("http://"))
+        .unwrap_or(server);
+    let host = host.split('/').next().unwrap_or(host);
+    match host {
+        "index.docker.io" | "registry-1.docker.io" | "registry.hub.docker.com" => DEFAULT_REGISTRY,
+        _ => host,
+    }
+}
+
 /// Default registry when none specified in image reference.
 pub const DEFAULT_REGISTRY: &str = "docker.io";
 
@@ -228,6 +546,7 @@ pub fn rewrite_image_registry(image: &str, new_registry: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use smolvm_protocol::test_util::fake_tool;
 
     #[test]
     fn test_extract_registry_implicit_dockerhub() {
@@ -353,6 +672,7 @@ mod tests {
         let toml_content = r#"
 [defaults]
 registry = "docker.io"
+max_download_rate = 1048576
 
 [registries."docker.io"]
 username = "myuser"
@@ -367,6 +687,7 @@ mirror = "ghcr-mirror.example.com"
         let config: RegistryConfig = toml::from_str(toml_content).unwrap();
         assert_eq!(config.registries.len(), 2);
         assert_eq!(config.default_registry(), "docker.io");
+        assert_eq!(config.defaults.max_download_rate, Some(1048576));
 
         let docker_entry = config.registries.get("docker.io").unwrap();
         assert_eq!(docker_entry.username.as_deref(), Some("myuser"));
@@ -424,6 +745,105 @@ mirror = "ghcr-mirror.example.com"
         assert!(config.get_credentials("test.io").is_none());
     }
 
+    /// A docker config with an inline Docker Hub login and a helper for ghcr.io.
+    fn docker_config(helper_dir: &std::path::Path) -> DockerConfig {
+        let auth = base64::engine::general_purpose::STANDARD.encode("hubuser:hubpass");
+        let mut config = DockerConfig::parse(&format!(
+            r#"{{
+                "auths": {{
+                    "https://index.docker.io/v1/": {{ "auth": "{}" }},
+                    "registry.example.com": {{ "username": "plain", "password": "text" }},
+                    "ghcr.io": {{}}
+                }},
+                "credHelpers": {{ "ghcr.io": "fake" }}
+            }}"#,
+            auth
+        ))
+        .unwrap();
+        config.helper_path = Some(helper_dir.as_os_str().to_owned());
+        config
+    }
+
+    fn fake_helper(dir: &std::path::Path, name: &str, script: &str) {
+        fake_tool(dir, &format!("docker-credential-{}", name), script);
+    }
+
+    #[test]
+    fn test_docker_config_credentials() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_helper(
+            dir.path(),
+            "fake",
+            r#"read server; [ "$server" = ghcr.io ] || exit 1
+echo '{"ServerURL":"ghcr.io","Username":"octocat","Secret":"gh-token"}'"#,
+        );
+        let config = RegistryConfig {
+            docker: docker_config(dir.path()),
+            ..Default::default()
+        };
+
+        let hub = config.get_credentials("docker.io").unwrap();
+        assert_eq!(hub.username, "hubuser");
+        assert_eq!(hub.password, "hubpass");
+
+        let plain = config.get_credentials("registry.example.com").unwrap();
+        assert_eq!(plain.username, "plain");
+        assert_eq!(plain.password, "text");
+
+        // The helper wins over the (empty) auths entry
+        let ghcr = config.get_credentials("ghcr.io").unwrap();
+        assert_eq!(ghcr.username, "octocat");
+        assert_eq!(ghcr.password, "gh-token");
+
+        assert!(config.get_credentials("quay.io").is_none());
+        // Secrets stay out of debug output
+        assert!(!format!("{:?}", config).contains("text"));
+    }
+
+    #[test]
+    fn test_docker_config_is_lower_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_helper(dir.path(), "fake", "exit 1");
+        let mut config = RegistryConfig {
+            docker: docker_config(dir.path()),
+            ..Default::default()
+        };
+        config.registries.insert(
+            "docker.io".to_string(),
+            RegistryEntry {
+                username: Some("smolvm_user".to_string()),
+                password: Some("smolvm_pass".to_string()),
+                password_env: None,
+                mirror: None,
+            },
+        );
+
+        let creds = config.get_credentials("docker.io").unwrap();
+        assert_eq!(creds.username, "smolvm_user");
+        // A helper without a login gives nothing rather than the auths entry
+        assert!(config.get_credentials("ghcr.io").is_none());
+    }
+
+    #[test]
+    fn test_hung_credential_helper_times_out() {
+        let dir = tempfile::tempdir().unwrap();
+        fake_helper(dir.path(), "fake", "exec sleep 10");
+        let mut docker = docker_config(dir.path());
+        docker.helper_timeout = Some(Duration::from_millis(200));
+        let config = RegistryConfig {
+            docker,
+            ..Default::default()
+        };
+
+        let started = Instant::now();
+        assert!(config.get_credentials("ghcr.io").is_none());
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "gave up after {:?}",
+            started.elapsed()
+        );
+    }
+
     #[test]
     fn test_has_registries() {
         let mut config = RegistryConfig::default();
@@ -544,6 +544,51 @@ impl OverlayDisk {
     }
 }
 
+// ============================================================================
+// Scratch Disk
+// ============================================================================
+
+/// Scratch disk filename.
+pub const SCRATCH_DISK_FILENAME: &str = "scratch.raw";
+
+/// Scratch disk for container overlay upper layers.
+///
+/// A sparse disk attached only to VMs created with a scratch size, so
+/// container writes land on it instead of the storage disk. The agent
+/// formats it on first boot.
+#[derive(Debug, Clone)]
+pub struct ScratchDisk {
+    /// Path to the disk image file.
+    path: PathBuf,
+}
+
+impl ScratchDisk {
+    /// Open or create the scratch disk at a custom path.
+    pub fn open_or_create_at(path: &Path, size_gb: u64) -> Result<Self> {
+        if size_gb == 0 {
+            return Err(Error::config(
+                "validate scratch size",
+                "disk size must be greater than 0 GB",
+            ));
+        }
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if !path.exists() {
+            create_sparse_disk(path, size_gb * 1024 * 1024 * 1024, "scratch")?;
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Get the path to the disk image.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5,7 +5,7 @@ use std::time::{SystemTime, UNIX_EPOCH};
 // Re-export retry utilities from the protocol crate for convenience.
 // This provides a single source of truth for retry logic across the codebase.
 pub use smolvm_protocol::retry::{
-    is_transient_io_error, is_transient_network_error, retry_with_backoff, RetryConfig,
+    is_transient_io_error, is_transient_network_error, retry_with_backoff, Jitter, RetryConfig,
 };
 
 /// Get current timestamp as seconds since Unix epoch.
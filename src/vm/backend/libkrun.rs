@@ -8,11 +8,15 @@ use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 
 use crate::error::{Error, Result};
-use crate::platform::{self, VmExecutor};
-use crate::vm::config::{NetworkPolicy, RootfsSource, VmConfig};
+use crate::platform::{self, VirtiofsMount, VmExecutor};
+use crate::process::ChildProcess;
+use crate::vm::cid::GuestCid;
+use crate::vm::config::{HostMount, RootfsSource, VmConfig};
 use crate::vm::rosetta;
 use crate::vm::state::{ExitReason, VmState};
 use crate::vm::{VmBackend, VmHandle, VmId};
+use parking_lot::Mutex;
+use smolvm_protocol::dns::DnsConfig;
 
 // FFI bindings to libkrun
 // Linking is handled by build.rs
@@ -97,21 +101,56 @@ impl VmBackend for LibkrunBackend {
     }
 
     fn create(&self, config: VmConfig) -> Result<Box<dyn VmHandle>> {
+        config.validate()?;
         LibkrunVm::create(config).map(|vm| Box::new(vm) as Box<dyn VmHandle>)
     }
 }
 
 /// A VM instance managed by libkrun.
+///
+/// The VM runs in a forked child process. Its state is refreshed from that
+/// process whenever it is queried, so it moves to `Stopped` once the guest
+/// exits, and the CID is held until then.
 pub struct LibkrunVm {
     id: VmId,
+    run: Mutex<VmRun>,
+}
+
+/// The part of a [`LibkrunVm`] that changes as its process runs.
+struct VmRun {
     state: VmState,
     exit_reason: Option<ExitReason>,
+    /// Guest CID, held until the VM stops.
+    cid: Option<GuestCid>,
     /// Child process running the VM.
-    child: Option<crate::process::ChildProcess>,
+    child: Option<ChildProcess>,
+}
+
+impl VmRun {
+    /// Pick up the exit of the VM process, if it has exited.
+    fn refresh(&mut self) {
+        if let Some(child) = self.child.as_mut() {
+            if !child.is_running() {
+                let code = child.exit_code();
+                self.finish(code);
+            }
+        }
+    }
+
+    /// Record how the VM process exited, releasing its CID.
+    fn finish(&mut self, code: Option<i32>) {
+        self.child = None;
+        self.cid = None;
+        self.state = VmState::Stopped;
+        self.exit_reason = Some(ExitReason::from_process_exit(code));
+    }
 }
 
 impl LibkrunVm {
     /// Create and start a VM with the given configuration.
+    ///
+    /// Returns once the VM process is running. Errors setting the VM up are
+    /// returned before anything is started.
     fn create(config: VmConfig) -> Result<Self> {
         let id = config.id.clone();
 
@@ -122,39 +161,40 @@ impl LibkrunVm {
         inject_init_krun(&rootfs_path)?;
 
         // Setup DNS if network egress is enabled
-        if let NetworkPolicy::Egress { dns } = &config.network {
-            setup_dns(&rootfs_path, dns.map(|ip| ip.to_string()).as_deref())?;
+        if let Some(resolvers) =
+            crate::network::policy_dns(&config.network, crate::network::DEFAULT_DNS_ADDR)
+        {
+            setup_dns(&rootfs_path, &resolvers)?;
         }
 
-        let mut vm = Self {
+        let vm = Self {
             id,
-            state: VmState::Created,
-            exit_reason: None,
-            child: None,
+            run: Mutex::new(VmRun {
+                state: VmState::Created,
+                exit_reason: None,
+                cid: Some(crate::vm::cid::allocate()?),
+                child: None,
+            }),
         };
 
-        // Execute VM (this blocks until VM exits)
-        vm.state = VmState::Booting;
-        let result = vm.exec_vm(&rootfs_path, &config);
-
-        match result {
-            Ok(code) => {
-                vm.state = VmState::Stopped;
-                vm.exit_reason = Some(ExitReason::exited(code));
-            }
-            Err(e) => {
-                vm.state = VmState::Failed {
-                    reason: e.to_string(),
-                };
-                vm.exit_reason = Some(ExitReason::vm_crash(e.to_string()));
-            }
-        }
+        vm.run.lock().state = VmState::Booting;
+        let child = vm.start(&rootfs_path, &config)?;
+        let mut run = vm.run.lock();
+        run.child = Some(child);
+        run.state = VmState::Running;
+        drop(run);
 
         Ok(vm)
     }
 
-    /// Execute the VM using libkrun FFI.
-    fn exec_vm(&mut self, rootfs_path: &Path, config: &VmConfig) -> Result<i32> {
+    /// Configure a libkrun context and fork the process running the VM.
+    fn start(&self, rootfs_path: &Path, config: &VmConfig) -> Result<ChildProcess> {
+        // Refuse before creating the context rather than expose host files
+        // writable
+        let executor = platform::vm_executor();
+        let mounts = virtiofs_mounts(&config.mounts);
+        check_read_only_supported(&mounts, &executor)?;
+
         // Raise file descriptor limits (required by libkrun)
         set_rlimits();
 
@@ -187,9 +227,19 @@ impl LibkrunVm {
                 return Err(Error::vm_creation("failed to set root filesystem"));
             }
 
-            // Set empty port map (required by libkrun)
-            let empty_ports: Vec<*const libc::c_char> = vec![std::ptr::null()];
-            if krun_set_port_map(ctx, empty_ports.as_ptr()) < 0 {
+            // Set port map (required by libkrun, even if empty)
+            let port_cstrings: Vec<CString> = config
+                .ports
+                .iter()
+                .map(|p| {
+                    CString::new(format!("{}:{}", p.host, p.guest))
+                        .expect("port mapping format cannot contain null bytes")
+                })
+                .collect();
+            let mut port_ptrs: Vec<*const libc::c_char> =
+                port_cstrings.iter().map(|s| s.as_ptr()).collect();
+            port_ptrs.push(std::ptr::null());
+            if krun_set_port_map(ctx, port_ptrs.as_ptr()) < 0 {
                 krun_free_ctx(ctx);
                 return Err(Error::vm_creation("failed to set port map"));
             }
@@ -209,19 +259,6 @@ impl LibkrunVm {
             // Build environment with defaults
             let (envp, _env_cstrings) = build_env_args(&config.env, &self.id)?;
 
-            // Build mounts list for wrapper script: (tag, guest_path)
-            let mount_specs: Vec<(String, String)> = config
-                .mounts
-                .iter()
-                .enumerate()
-                .map(|(i, m)| {
-                    (
-                        crate::agent::mount_tag(i),
-                        m.target.to_string_lossy().to_string(),
-                    )
-                })
-                .collect();
-
             // Determine if Rosetta should be enabled
             // Only enable if requested in config AND Rosetta is actually available
             let rosetta_enabled = config.rosetta && rosetta::is_available();
@@ -237,10 +274,9 @@ impl LibkrunVm {
             // Build exec command using platform-specific executor
             // On macOS, this wraps the command with a mount script for virtiofs
             // On Linux, the kernel handles virtiofs mounting automatically
-            let executor = platform::vm_executor();
             let (exec_path, argv, _argv_cstrings) = executor.build_exec_command(
                 &config.command,
-                &mount_specs,
+                &mounts,
                 rootfs_path,
                 rosetta_enabled,
             )?;
@@ -344,21 +380,34 @@ impl LibkrunVm {
                 }
             }
 
-            // Set console output if specified
+            // Set console output if specified. Output goes through a FIFO so
+            // the log can be size-capped; fall back to the plain file if the
+            // FIFO can't be created.
+            let mut console_pump = None;
             if let Some(ref log_path) = config.console_log {
-                let console_path = path_to_cstring(log_path)?;
+                let pump = crate::log_rotation::ConsolePump::create(
+                    log_path,
+                    config.console_log_max_bytes,
+                )
+                .map_err(|e| {
+                    tracing::warn!(error = %e, "failed to create console FIFO");
+                })
+                .ok();
+                let target = pump.as_ref().map(|p| p.fifo_path()).unwrap_or(log_path);
+                let console_path = path_to_cstring(target)?;
                 if krun_set_console_output(ctx, console_path.as_ptr()) < 0 {
                     tracing::warn!("failed to set console output: {}", log_path.display());
+                    if let Some(pump) = pump {
+                        pump.discard();
+                    }
                 } else {
                     tracing::debug!(path = %log_path.display(), "console output enabled");
+                    console_pump = pump;
                 }
             }
 
-            // Update state to running
-            self.state = VmState::Running;
-
             // Fork before starting VM because krun_start_enter calls exit() directly
-            // The child runs the VM, parent waits and returns the exit code
+            // The child runs the VM; the parent keeps a handle to it
             tracing::info!(vm_id = %self.id, "starting VM");
 
             let pid = libc::fork();
@@ -367,19 +416,16 @@ impl LibkrunVm {
             } else if pid == 0 {
                 // Child process: run the VM
                 // This will call exit() when the VM exits
+                if let Some(pump) = console_pump {
+                    if let Err(e) = pump.spawn() {
+                        tracing::warn!(error = %e, "failed to start console log pump");
+                    }
+                }
                 krun_start_enter(ctx);
                 // If we get here, something went wrong
                 libc::_exit(1);
             } else {
-                // Parent process: store child process and wait
-                let mut child = crate::process::ChildProcess::new(pid);
-                let exit_code = child.wait();
-                self.child = Some(child);
-
-                // Clear child reference after exit
-                self.child = None;
-
-                Ok(exit_code)
+                Ok(ChildProcess::new(pid))
             }
         }
     }
@@ -406,35 +452,49 @@ impl VmHandle for LibkrunVm {
     }
 
     fn state(&self) -> VmState {
-        self.state.clone()
+        let mut run = self.run.lock();
+        run.refresh();
+        run.state.clone()
+    }
+
+    fn cid(&self) -> Option<u32> {
+        let mut run = self.run.lock();
+        run.refresh();
+        run.cid.as_ref().map(GuestCid::get)
     }
 
     fn wait(&mut self) -> Result<ExitReason> {
-        // VM already exited since krun_start_enter blocks
-        self.exit_reason
+        let run = self.run.get_mut();
+        if let Some(child) = run.child.as_mut() {
+            let code = child.wait();
+            run.finish(Some(code));
+        }
+        run.exit_reason
             .clone()
             .ok_or_else(|| Error::vm_not_found(&self.id.0))
     }
 
     fn stop(&mut self) -> Result<()> {
-        if let Some(ref mut child) = self.child {
-            if child.is_running() {
-                tracing::info!(pid = child.pid(), "stopping VM with SIGTERM");
-                child.stop(crate::process::DEFAULT_STOP_TIMEOUT, true)?;
-                self.state = VmState::Stopped;
-            }
+        let run = self.run.get_mut();
+        run.refresh();
+        if let Some(child) = run.child.as_mut() {
+            tracing::info!(pid = child.pid(), "stopping VM with SIGTERM");
+            run.state = VmState::Stopping;
+            let code = child.stop(crate::process::DEFAULT_STOP_TIMEOUT, true)?;
+            run.finish(Some(code));
         }
         Ok(())
     }
 
     fn kill(&mut self) -> Result<()> {
-        if let Some(ref child) = self.child {
-            if crate::process::is_alive(child.pid()) {
-                tracing::info!(pid = child.pid(), "killing VM with SIGKILL");
-                crate::process::kill(child.pid());
-            }
+        let run = self.run.get_mut();
+        run.refresh();
+        if let Some(child) = run.child.as_mut() {
+            tracing::info!(pid = child.pid(), "killing VM with SIGKILL");
+            child.kill();
+            let code = child.wait();
+            run.finish(Some(code));
         }
-        self.state = VmState::Stopped;
         Ok(())
     }
 }
@@ -455,14 +515,13 @@ fn resolve_rootfs(source: &RootfsSource) -> Result<PathBuf> {
 }
 
 /// Setup DNS configuration in the rootfs.
-fn setup_dns(rootfs: &Path, dns: Option<&str>) -> Result<()> {
+fn setup_dns(rootfs: &Path, dns: &DnsConfig) -> Result<()> {
     let resolv_path = rootfs.join("etc/resolv.conf");
 
     // Only write if etc directory exists
     if let Some(parent) = resolv_path.parent() {
         if parent.exists() {
-            let content = format!("nameserver {}\n", dns.unwrap_or("1.1.1.1"));
-            std::fs::write(&resolv_path, content)?;
+            std::fs::write(&resolv_path, dns.to_resolv_conf())?;
             tracing::debug!("wrote DNS config to {:?}", resolv_path);
         }
     }
@@ -523,6 +582,38 @@ fn inject_init_krun(rootfs: &Path) -> Result<()> {
     ))
 }
 
+/// Translate host mounts into the virtiofs shares the guest mounts, tagged
+/// in order as the agent expects.
+fn virtiofs_mounts(mounts: &[HostMount]) -> Vec<VirtiofsMount> {
+    mounts
+        .iter()
+        .enumerate()
+        .map(|(i, m)| VirtiofsMount {
+            tag: crate::agent::mount_tag(i),
+            guest_path: m.target.to_string_lossy().to_string(),
+            read_only: m.read_only,
+        })
+        .collect()
+}
+
+/// Fail if a mount must be read-only but `executor` can't mount it so.
+fn check_read_only_supported(mounts: &[VirtiofsMount], executor: &dyn VmExecutor) -> Result<()> {
+    if executor.enforces_read_only_mounts() {
+        return Ok(());
+    }
+    match mounts.iter().find(|m| m.read_only) {
+        Some(mount) => Err(Error::mount(
+            "attach virtiofs mount",
+            format!(
+                "'{}' must be read-only, but this platform's libkrun backend cannot \
+                 mount virtiofs shares read-only",
+                mount.guest_path
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
 /// Convert a Path to a CString.
 fn path_to_cstring(path: &Path) -> Result<CString> {
     CString::new(path.to_string_lossy().as_bytes())
@@ -604,6 +695,52 @@ fn build_env_args(
 mod tests {
     use super::*;
 
+    /// A handle whose VM process is `sh -c script`, holding a CID.
+    fn vm_running(script: &str) -> LibkrunVm {
+        // Reaped through the ChildProcess, as a forked VM would be
+        let pid = std::process::Command::new("sh")
+            .args(["-c", script])
+            .spawn()
+            .unwrap()
+            .id();
+        LibkrunVm {
+            id: VmId::new("test"),
+            run: Mutex::new(VmRun {
+                state: VmState::Running,
+                exit_reason: None,
+                cid: Some(crate::vm::cid::allocate().unwrap()),
+                child: Some(ChildProcess::new(pid as libc::pid_t)),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_vm_state_and_cid_follow_the_vm_process() {
+        let vm = vm_running("exit 3");
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while vm.state() == VmState::Running {
+            assert!(std::time::Instant::now() < deadline, "VM never exited");
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(vm.state(), VmState::Stopped);
+        assert_eq!(vm.cid(), None);
+        let mut vm = vm;
+        assert_eq!(vm.wait().unwrap(), ExitReason::exited(3));
+    }
+
+    #[test]
+    fn test_vm_holds_cid_until_killed() {
+        let mut vm = vm_running("sleep 30");
+        assert_eq!(vm.state(), VmState::Running);
+        let cid = vm.cid().unwrap();
+        assert!(cid >= smolvm_protocol::cid::FIRST_GUEST);
+
+        vm.kill().unwrap();
+        assert_eq!(vm.state(), VmState::Stopped);
+        assert_eq!(vm.cid(), None);
+        assert_eq!(vm.wait().unwrap(), ExitReason::signaled(libc::SIGKILL));
+    }
+
     #[test]
     fn test_build_exec_args_default() {
         let (exec_path, argv, _) = build_exec_args(&None).unwrap();
@@ -662,6 +799,71 @@ mod tests {
         assert_eq!(envp.len(), 5);
     }
 
+    #[test]
+    fn test_read_only_mount_maps_to_ro_option() {
+        let config = VmConfig::builder(RootfsSource::path("/rootfs"))
+            .mount(crate::vm::HostMount::new("/host/ro", "/data"))
+            .mount(crate::vm::HostMount::new_writable("/host/rw", "/scratch"))
+            .build();
+
+        let mounts = virtiofs_mounts(&config.mounts);
+        assert_eq!(
+            mounts[0],
+            VirtiofsMount {
+                tag: "smolvm0".to_string(),
+                guest_path: "/data".to_string(),
+                read_only: true,
+            }
+        );
+        assert_eq!(mounts[0].mount_options(), "ro");
+        assert_eq!(mounts[1].tag, "smolvm1");
+        assert_eq!(mounts[1].mount_options(), "rw");
+    }
+
+    #[test]
+    fn test_read_only_mount_rejected_without_enforcement() {
+        struct NoReadOnly;
+        impl VmExecutor for NoReadOnly {
+            fn requires_mount_wrapper(&self) -> bool {
+                false
+            }
+            fn enforces_read_only_mounts(&self) -> bool {
+                false
+            }
+            fn build_exec_command(
+                &self,
+                command: &Option<Vec<String>>,
+                _mounts: &[VirtiofsMount],
+                _rootfs: &Path,
+                _rosetta: bool,
+            ) -> Result<(CString, Vec<*const libc::c_char>, Vec<CString>)> {
+                build_exec_args(command)
+            }
+            fn tool_search_paths(&self) -> &'static [&'static str] {
+                &[]
+            }
+            fn dylib_extension(&self) -> &'static str {
+                "so"
+            }
+            fn library_search_paths(&self) -> &'static [&'static str] {
+                &[]
+            }
+        }
+
+        let writable = VmConfig::builder(RootfsSource::path("/rootfs"))
+            .mount(crate::vm::HostMount::new_writable("/host", "/data"))
+            .build();
+        check_read_only_supported(&virtiofs_mounts(&writable.mounts), &NoReadOnly).unwrap();
+
+        let read_only = VmConfig::builder(RootfsSource::path("/rootfs"))
+            .mount(crate::vm::HostMount::new("/host", "/data"))
+            .build();
+        let err = check_read_only_supported(&virtiofs_mounts(&read_only.mounts), &NoReadOnly)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("'/data' must be read-only"), "{}", err);
+    }
+
     #[test]
     fn test_path_to_cstring() {
         let path = Path::new("/some/path");
@@ -0,0 +1,67 @@
+//! Diagnostics for VMs that fail to boot.
+//!
+//! A [`BootReport`] collects what was known about a VM when it failed to
+//! boot: how its process exited, the tail of its console log, and how far
+//! the host got talking to it. It is attached to
+//! [`Error::BootFailed`](crate::error::Error::BootFailed) by both
+//! [`VmBackend::create_with_report`](super::VmBackend::create_with_report)
+//! and the agent launcher.
+
+use crate::vm::state::ExitReason;
+use std::path::Path;
+
+/// Number of console log lines included in a boot failure report.
+pub(crate) const BOOT_CONSOLE_TAIL_LINES: usize = 20;
+
+/// What was known about a VM that failed to become ready.
+///
+/// Attached to [`Error::BootFailed`](crate::error::Error::BootFailed) so a
+/// failed boot can be diagnosed without digging through log files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BootReport {
+    /// The boot timeout, in seconds.
+    pub timeout_secs: u64,
+    /// How the VM process exited, if it died during boot.
+    pub exit_reason: Option<ExitReason>,
+    /// Last lines of the VM console log, if one was captured.
+    pub console_tail: Option<String>,
+    /// Whether a connection to the agent's vsock socket was ever made.
+    ///
+    /// The agent's ready signal is never part of a report: once it arrives,
+    /// boot has succeeded.
+    pub vsock_connected: bool,
+}
+
+impl std::fmt::Display for BootReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.exit_reason {
+            Some(reason) => write!(
+                f,
+                "boot failed: vm process stopped during startup ({})",
+                reason
+            )?,
+            None => write!(
+                f,
+                "boot timed out: agent not ready after {}s",
+                self.timeout_secs
+            )?,
+        }
+        write!(
+            f,
+            "\nvsock connected: {}",
+            if self.vsock_connected { "yes" } else { "no" }
+        )?;
+        if let Some(tail) = &self.console_tail {
+            write!(f, "\nlast console output:\n{}", tail.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// The last [`BOOT_CONSOLE_TAIL_LINES`] lines of a console log, if there is
+/// one and it isn't empty.
+pub(crate) fn console_tail(console_log: Option<&Path>) -> Option<String> {
+    let lines = crate::log_rotation::tail_lines(console_log?, BOOT_CONSOLE_TAIL_LINES).ok()?;
+    let tail = lines.join("\n");
+    (!tail.trim().is_empty()).then_some(tail)
+}
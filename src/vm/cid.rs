@@ -0,0 +1,202 @@
+//! Guest CID allocation.
+//!
+//! Every running VM is given its own vsock context ID so guests can be told
+//! apart when addressing them with
+//! [`smolvm_protocol::vsock::connect`]. CIDs are handed out from
+//! [`cid::FIRST_GUEST`](smolvm_protocol::cid::FIRST_GUEST) upwards and
+//! returned when the [`GuestCid`] holding one is dropped. (libkrun gives each
+//! VM a private vsock device reached through Unix sockets, so its guests
+//! keep [`cid::GUEST`](smolvm_protocol::cid::GUEST) internally.)
+//!
+//! Allocation continues round-robin past the last CID handed out rather than
+//! reusing the lowest free one, so a CID that was just released is not given
+//! to the next VM while connections to the old guest may still be closing.
+
+use crate::error::{Error, Result};
+use parking_lot::Mutex;
+use smolvm_protocol::cid;
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, LazyLock};
+
+/// Allocator shared by all VMs in this process.
+static ALLOCATOR: LazyLock<CidAllocator> = LazyLock::new(CidAllocator::new);
+
+/// Allocate a guest CID from the process-wide allocator.
+pub fn allocate() -> Result<GuestCid> {
+    ALLOCATOR.allocate()
+}
+
+/// Hands out unique guest CIDs.
+#[derive(Debug)]
+pub struct CidAllocator {
+    range: RangeInclusive<u32>,
+    state: Arc<Mutex<AllocatorState>>,
+}
+
+#[derive(Debug)]
+struct AllocatorState {
+    in_use: BTreeSet<u32>,
+    /// Where the search for the next free CID starts.
+    next: u32,
+}
+
+impl CidAllocator {
+    /// Allocator over every assignable guest CID.
+    pub fn new() -> Self {
+        // u32::MAX is the wildcard CID and can't be assigned
+        Self::with_range(cid::FIRST_GUEST..=cid::ANY - 1)
+    }
+
+    /// Allocator over `range` only.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is empty or includes a reserved CID.
+    pub fn with_range(range: RangeInclusive<u32>) -> Self {
+        assert!(
+            !range.is_empty() && *range.start() >= cid::FIRST_GUEST && *range.end() < cid::ANY,
+            "invalid guest CID range {:?}",
+            range
+        );
+        Self {
+            state: Arc::new(Mutex::new(AllocatorState {
+                in_use: BTreeSet::new(),
+                next: *range.start(),
+            })),
+            range,
+        }
+    }
+
+    /// Allocate a CID not held by any other [`GuestCid`] from this allocator.
+    ///
+    /// Fails once every CID in the range is in use.
+    pub fn allocate(&self) -> Result<GuestCid> {
+        let mut state = self.state.lock();
+        let (start, end) = (*self.range.start(), *self.range.end());
+        let next = state.next;
+
+        let cid = (next..=end)
+            .chain(start..next)
+            .find(|cid| !state.in_use.contains(cid))
+            .ok_or_else(|| {
+                Error::vm_creation(format!("no free guest CID: all {} in use", end - start + 1))
+            })?;
+
+        state.in_use.insert(cid);
+        state.next = if cid == end { start } else { cid + 1 };
+        Ok(GuestCid {
+            cid,
+            state: Arc::clone(&self.state),
+        })
+    }
+
+    /// Number of CIDs currently allocated.
+    pub fn in_use(&self) -> usize {
+        self.state.lock().in_use.len()
+    }
+}
+
+impl Default for CidAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A guest CID held for one VM, released when dropped.
+#[derive(Debug)]
+pub struct GuestCid {
+    cid: u32,
+    state: Arc<Mutex<AllocatorState>>,
+}
+
+impl GuestCid {
+    /// The CID value.
+    pub fn get(&self) -> u32 {
+        self.cid
+    }
+}
+
+impl Drop for GuestCid {
+    fn drop(&mut self) {
+        self.state.lock().in_use.remove(&self.cid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocates_from_first_guest_cid() {
+        let allocator = CidAllocator::new();
+        let a = allocator.allocate().unwrap();
+        let b = allocator.allocate().unwrap();
+        assert_eq!(a.get(), cid::FIRST_GUEST);
+        assert_eq!(b.get(), cid::FIRST_GUEST + 1);
+        assert_eq!(allocator.in_use(), 2);
+    }
+
+    #[test]
+    fn test_release_and_exhaustion() {
+        let allocator = CidAllocator::with_range(3..=5);
+        let a = allocator.allocate().unwrap();
+        let b = allocator.allocate().unwrap();
+        let c = allocator.allocate().unwrap();
+        assert_eq!([a.get(), b.get(), c.get()], [3, 4, 5]);
+
+        let err = allocator.allocate().unwrap_err();
+        assert!(err.to_string().contains("no free guest CID"), "{}", err);
+
+        // A released CID becomes available again
+        drop(b);
+        assert_eq!(allocator.in_use(), 2);
+        let d = allocator.allocate().unwrap();
+        assert_eq!(d.get(), 4);
+        assert!(allocator.allocate().is_err());
+        drop((a, c, d));
+        assert_eq!(allocator.in_use(), 0);
+    }
+
+    #[test]
+    fn test_released_cid_is_not_reused_immediately() {
+        let allocator = CidAllocator::with_range(3..=6);
+        let a = allocator.allocate().unwrap();
+        assert_eq!(a.get(), 3);
+        drop(a);
+
+        // The next VMs get fresh CIDs before 3 comes around again
+        let b = allocator.allocate().unwrap();
+        let c = allocator.allocate().unwrap();
+        let d = allocator.allocate().unwrap();
+        assert_eq!([b.get(), c.get(), d.get()], [4, 5, 6]);
+        assert_eq!(allocator.allocate().unwrap().get(), 3);
+    }
+
+    #[test]
+    fn test_concurrent_allocations_are_unique() {
+        let allocator = Arc::new(CidAllocator::with_range(3..=402));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let allocator = Arc::clone(&allocator);
+                std::thread::spawn(move || {
+                    (0..50)
+                        .map(|_| allocator.allocate().unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let held: Vec<GuestCid> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+        let unique: BTreeSet<u32> = held.iter().map(GuestCid::get).collect();
+        assert_eq!(unique.len(), 400);
+        assert_eq!(allocator.in_use(), 400);
+        assert!(allocator.allocate().is_err());
+
+        drop(held);
+        assert_eq!(allocator.in_use(), 0);
+    }
+}
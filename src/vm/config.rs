@@ -1,6 +1,9 @@
 //! VM configuration types.
 
+use crate::agent::PortMapping;
+use crate::error::{Error, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::net::IpAddr;
 use std::path::PathBuf;
 use std::time::Duration;
@@ -58,6 +61,9 @@ impl AsRef<str> for VmId {
     }
 }
 
+/// Smallest guest memory the libkrun kernel boots reliably with, in MiB.
+pub const MIN_MEMORY_MIB: u32 = 128;
+
 /// VM resource limits (aligned with DESIGN.md defaults).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Resources {
@@ -262,6 +268,114 @@ impl DiskConfig {
         self.format = format;
         self
     }
+
+    /// Create an empty sparse disk image of `size_mib` MiB at `path` and
+    /// return a configuration for it.
+    ///
+    /// Raw images are a sparse file of the full size; qcow2 images contain
+    /// only the metadata for an empty disk. The file is created readable
+    /// and writable by the owner only. An existing file is an error unless
+    /// `force` is set, in which case it is replaced.
+    pub fn create_sparse(
+        block_id: impl Into<String>,
+        path: impl Into<PathBuf>,
+        size_mib: u64,
+        format: DiskFormat,
+        force: bool,
+    ) -> Result<Self> {
+        use std::fs::OpenOptions;
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let path = path.into();
+        let size_bytes = size_mib
+            .checked_mul(1024 * 1024)
+            .filter(|&b| b > 0)
+            .ok_or_else(|| {
+                Error::config(
+                    "create disk image",
+                    format!("invalid size: {} MiB", size_mib),
+                )
+            })?;
+
+        if path.exists() {
+            if !force {
+                return Err(Error::storage(
+                    "create disk image",
+                    format!("{} already exists (use force to overwrite)", path.display()),
+                ));
+            }
+            // Recreate rather than truncate so the new permissions apply
+            std::fs::remove_file(&path)?;
+        }
+
+        tracing::info!(path = %path.display(), size_mib, ?format, "creating sparse disk image");
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+        match format {
+            DiskFormat::Raw => file.set_len(size_bytes)?,
+            DiskFormat::Qcow2 => file.write_all(&qcow2_empty_image(size_bytes))?,
+        }
+        file.sync_all()?;
+
+        Ok(Self::new(block_id, path).format(format))
+    }
+}
+
+/// qcow2 cluster size used for created images (64 KiB, as `qemu-img`).
+const QCOW2_CLUSTER_BITS: u32 = 16;
+
+/// Build a minimal qcow2 (version 2) image of a `size_bytes` virtual disk
+/// with no data clusters allocated.
+///
+/// Layout, one cluster each: header, refcount table, refcount block, then
+/// the (all-zero) L1 table.
+fn qcow2_empty_image(size_bytes: u64) -> Vec<u8> {
+    let cluster_size = 1u64 << QCOW2_CLUSTER_BITS;
+    // Each L2 table holds cluster_size / 8 entries, each mapping one cluster
+    let bytes_per_l1_entry = cluster_size * (cluster_size / 8);
+    let l1_size = size_bytes.div_ceil(bytes_per_l1_entry);
+    let l1_clusters = (l1_size * 8).div_ceil(cluster_size).max(1);
+
+    let refcount_table_offset = cluster_size;
+    let refcount_block_offset = 2 * cluster_size;
+    let l1_table_offset = 3 * cluster_size;
+    let total_clusters = 3 + l1_clusters;
+
+    let mut image = vec![0u8; (total_clusters * cluster_size) as usize];
+
+    // Header
+    let mut header = Vec::with_capacity(72);
+    header.extend_from_slice(b"QFI\xfb");
+    header.extend_from_slice(&2u32.to_be_bytes()); // version
+    header.extend_from_slice(&0u64.to_be_bytes()); // backing_file_offset
+    header.extend_from_slice(&0u32.to_be_bytes()); // backing_file_size
+    header.extend_from_slice(&QCOW2_CLUSTER_BITS.to_be_bytes());
+    header.extend_from_slice(&size_bytes.to_be_bytes());
+    header.extend_from_slice(&0u32.to_be_bytes()); // crypt_method
+    header.extend_from_slice(&(l1_size as u32).to_be_bytes());
+    header.extend_from_slice(&l1_table_offset.to_be_bytes());
+    header.extend_from_slice(&refcount_table_offset.to_be_bytes());
+    header.extend_from_slice(&1u32.to_be_bytes()); // refcount_table_clusters
+    header.extend_from_slice(&0u32.to_be_bytes()); // nb_snapshots
+    header.extend_from_slice(&0u64.to_be_bytes()); // snapshots_offset
+    image[..header.len()].copy_from_slice(&header);
+
+    // Refcount table: a single refcount block
+    let table = refcount_table_offset as usize;
+    image[table..table + 8].copy_from_slice(&refcount_block_offset.to_be_bytes());
+
+    // Refcount block: 16-bit refcount of 1 for every metadata cluster
+    let block = refcount_block_offset as usize;
+    for cluster in 0..total_clusters as usize {
+        image[block + cluster * 2..block + cluster * 2 + 2].copy_from_slice(&1u16.to_be_bytes());
+    }
+
+    image
 }
 
 /// vsock port configuration.
@@ -340,9 +454,17 @@ pub struct VmConfig {
     /// vsock ports for host-guest communication.
     pub vsock_ports: Vec<VsockPort>,
 
+    /// TCP ports forwarded from the host (requires network egress).
+    #[serde(default)]
+    pub ports: Vec<PortMapping>,
+
     /// Console output log file (for debugging).
     pub console_log: Option<PathBuf>,
 
+    /// Size at which the console log is rotated (default: 10 MiB).
+    #[serde(default = "default_console_log_max_bytes")]
+    pub console_log_max_bytes: u64,
+
     /// Enable Rosetta for x86_64 binaries on Apple Silicon.
     pub rosetta: bool,
 
@@ -361,6 +483,80 @@ impl VmConfig {
     pub fn builder(rootfs: RootfsSource) -> VmConfigBuilder {
         VmConfigBuilder::new(rootfs)
     }
+
+    /// Check that the configuration can be handed to a backend.
+    ///
+    /// Catches combinations that would otherwise only fail deep inside the
+    /// hypervisor: no CPUs, too little memory, missing rootfs or mount
+    /// sources, duplicate block device IDs, vsock ports or forwarded host
+    /// ports, and port forwards without network access.
+    pub fn validate(&self) -> Result<()> {
+        const OP: &str = "validate vm config";
+
+        if self.resources.cpus == 0 {
+            return Err(Error::config(OP, "resources.cpus must be at least 1"));
+        }
+        if self.resources.memory_mib < MIN_MEMORY_MIB {
+            return Err(Error::config(
+                OP,
+                format!(
+                    "resources.memory_mib is {} MiB, minimum is {} MiB",
+                    self.resources.memory_mib, MIN_MEMORY_MIB
+                ),
+            ));
+        }
+
+        let RootfsSource::Path { path } = &self.rootfs;
+        if !path.exists() {
+            return Err(Error::RootfsNotFound { path: path.clone() });
+        }
+
+        for mount in &self.mounts {
+            if !mount.source.exists() {
+                return Err(Error::MountSourceNotFound {
+                    path: mount.source.clone(),
+                });
+            }
+        }
+
+        let mut block_ids = HashSet::new();
+        for disk in &self.disks {
+            if !block_ids.insert(disk.block_id.as_str()) {
+                return Err(Error::config(
+                    OP,
+                    format!("disks: block_id '{}' is used more than once", disk.block_id),
+                ));
+            }
+        }
+
+        let mut vsock_ports = HashSet::new();
+        for vsock in &self.vsock_ports {
+            if !vsock_ports.insert(vsock.port) {
+                return Err(Error::config(
+                    OP,
+                    format!("vsock_ports: port {} is used more than once", vsock.port),
+                ));
+            }
+        }
+
+        if !self.ports.is_empty() && self.network == NetworkPolicy::None {
+            return Err(Error::config(
+                OP,
+                "ports: port forwarding requires network egress, but network policy is none",
+            ));
+        }
+        let mut host_ports = HashSet::new();
+        for port in &self.ports {
+            if !host_ports.insert(port.host) {
+                return Err(Error::config(
+                    OP,
+                    format!("ports: host port {} is forwarded more than once", port.host),
+                ));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// Builder for VmConfig.
@@ -369,6 +565,10 @@ pub struct VmConfigBuilder {
     config: VmConfig,
 }
 
+fn default_console_log_max_bytes() -> u64 {
+    crate::log_rotation::MAX_LOG_SIZE
+}
+
 impl VmConfigBuilder {
     /// Create a new builder with the given rootfs source.
     pub fn new(rootfs: RootfsSource) -> Self {
@@ -382,7 +582,9 @@ impl VmConfigBuilder {
                 mounts: Vec::new(),
                 disks: Vec::new(),
                 vsock_ports: Vec::new(),
+                ports: Vec::new(),
                 console_log: None,
+                console_log_max_bytes: default_console_log_max_bytes(),
                 rosetta: false,
                 command: None,
                 workdir: None,
@@ -475,12 +677,24 @@ impl VmConfigBuilder {
         self
     }
 
+    /// Forward a TCP port from the host.
+    pub fn port(mut self, port: PortMapping) -> Self {
+        self.config.ports.push(port);
+        self
+    }
+
     /// Set the console log file.
     pub fn console_log(mut self, path: impl Into<PathBuf>) -> Self {
         self.config.console_log = Some(path.into());
         self
     }
 
+    /// Set the size at which the console log is rotated.
+    pub fn console_log_max_bytes(mut self, bytes: u64) -> Self {
+        self.config.console_log_max_bytes = bytes;
+        self
+    }
+
     /// Enable Rosetta for x86_64 binaries.
     pub fn rosetta(mut self, enabled: bool) -> Self {
         self.config.rosetta = enabled;
@@ -488,6 +702,9 @@ impl VmConfigBuilder {
     }
 
     /// Build the VmConfig.
+    ///
+    /// The result is not validated; backends call [`VmConfig::validate`]
+    /// before creating the VM.
     pub fn build(self) -> VmConfig {
         self.config
     }
@@ -521,6 +738,61 @@ mod tests {
         assert_eq!(id.as_str().len(), 64);
     }
 
+    #[test]
+    fn test_create_sparse_raw() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.raw");
+        let disk = DiskConfig::create_sparse("data", &path, 64, DiskFormat::Raw, false).unwrap();
+
+        assert_eq!(disk, DiskConfig::new("data", &path));
+        let meta = std::fs::metadata(&path).unwrap();
+        assert_eq!(meta.len(), 64 * 1024 * 1024);
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_create_sparse_qcow2() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.qcow2");
+        let disk =
+            DiskConfig::create_sparse("data", &path, 1024, DiskFormat::Qcow2, false).unwrap();
+        assert_eq!(disk.format, DiskFormat::Qcow2);
+
+        let image = std::fs::read(&path).unwrap();
+        assert_eq!(&image[..4], b"QFI\xfb");
+        let be_u32 = |at: usize| u32::from_be_bytes(image[at..at + 4].try_into().unwrap());
+        let be_u64 = |at: usize| u64::from_be_bytes(image[at..at + 8].try_into().unwrap());
+        assert_eq!(be_u32(4), 2); // version
+        assert_eq!(be_u64(24), 1024 * 1024 * 1024); // virtual size
+        assert_eq!(be_u32(36), 2); // L1 entries, 512 MiB each
+                                   // Only metadata is allocated, not the virtual size
+        assert_eq!(image.len(), 4 * 65536);
+    }
+
+    #[test]
+    fn test_create_sparse_does_not_clobber() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.raw");
+        std::fs::write(&path, b"keep me").unwrap();
+
+        assert!(DiskConfig::create_sparse("data", &path, 1, DiskFormat::Raw, false).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"keep me");
+
+        DiskConfig::create_sparse("data", &path, 1, DiskFormat::Raw, true).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 1024 * 1024);
+
+        assert!(DiskConfig::create_sparse(
+            "data",
+            dir.path().join("zero"),
+            0,
+            DiskFormat::Raw,
+            false
+        )
+        .is_err());
+    }
+
     #[test]
     fn test_vm_config_builder() {
         let config = VmConfig::builder(RootfsSource::path("/rootfs"))
@@ -544,6 +816,97 @@ mod tests {
         assert_eq!(config.env, vec![("FOO".to_string(), "bar".to_string())]);
     }
 
+    /// A config that passes validation, rooted in `dir`.
+    fn valid_config(dir: &std::path::Path) -> VmConfigBuilder {
+        VmConfig::builder(RootfsSource::path(dir))
+            .mount(HostMount::new(dir, "/data"))
+            .disk(DiskConfig::new("storage", dir.join("storage.raw")))
+            .vsock(VsockPort::host_listen(6000, dir.join("agent.sock")))
+    }
+
+    fn validation_error(config: VmConfig) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_validate_accepts_valid_config() {
+        let dir = tempfile::tempdir().unwrap();
+        valid_config(dir.path()).build().validate().unwrap();
+        valid_config(dir.path())
+            .network(NetworkPolicy::Egress { dns: None })
+            .port(PortMapping::new(8080, 80))
+            .build()
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_validate_resources() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = validation_error(valid_config(dir.path()).cpus(0).build());
+        assert!(err.contains("resources.cpus"), "{}", err);
+
+        let err = validation_error(valid_config(dir.path()).memory(MIN_MEMORY_MIB - 1).build());
+        assert!(err.contains("resources.memory_mib"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_missing_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+
+        let err = VmConfig::builder(RootfsSource::path(&missing))
+            .build()
+            .validate()
+            .unwrap_err();
+        assert!(matches!(err, Error::RootfsNotFound { path } if path == missing));
+
+        let err = valid_config(dir.path())
+            .mount(HostMount::new(&missing, "/missing"))
+            .build()
+            .validate()
+            .unwrap_err();
+        assert!(matches!(err, Error::MountSourceNotFound { path } if path == missing));
+    }
+
+    #[test]
+    fn test_validate_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = validation_error(
+            valid_config(dir.path())
+                .disk(DiskConfig::new("storage", dir.path().join("other.raw")))
+                .build(),
+        );
+        assert!(err.contains("block_id 'storage'"), "{}", err);
+
+        let err = validation_error(
+            valid_config(dir.path())
+                .vsock(VsockPort::guest_listen(6000, dir.path().join("b.sock")))
+                .build(),
+        );
+        assert!(err.contains("vsock_ports: port 6000"), "{}", err);
+
+        let err = validation_error(
+            valid_config(dir.path())
+                .network(NetworkPolicy::Egress { dns: None })
+                .port(PortMapping::new(8080, 80))
+                .port(PortMapping::new(8080, 81))
+                .build(),
+        );
+        assert!(err.contains("host port 8080"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_port_forward_needs_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = validation_error(
+            valid_config(dir.path())
+                .port(PortMapping::same(8080))
+                .build(),
+        );
+        assert!(err.contains("requires network egress"), "{}", err);
+    }
+
     #[test]
     fn test_network_policy_serialization() {
         let none = NetworkPolicy::None;
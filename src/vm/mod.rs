@@ -6,16 +6,24 @@
 //! - [`VmBackend`]: Trait for VM backend implementations (e.g., libkrun)
 
 pub mod backend;
+pub mod boot;
+pub mod cid;
 pub mod config;
 pub mod rosetta;
 pub mod state;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
+pub use boot::BootReport;
 pub use config::{
     DiskConfig, DiskFormat, HostMount, NetworkPolicy, Resources, RootfsSource, Timeouts, VmConfig,
     VmId, VsockPort,
 };
 pub use state::{ExitReason, VmState};
+use std::time::{Duration, Instant};
+
+/// How long after starting a VM [`VmBackend::create_with_report`] watches
+/// for it failing to boot, unless the boot timeout is shorter.
+pub const BOOT_PHASE: Duration = Duration::from_secs(2);
 
 /// Handle to a running or stopped VM.
 ///
@@ -28,6 +36,12 @@ pub trait VmHandle: Send {
     /// Get current state.
     fn state(&self) -> VmState;
 
+    /// The guest CID held by the VM (see [`cid`]), or `None` once it has
+    /// stopped or if the backend doesn't allocate one.
+    fn cid(&self) -> Option<u32> {
+        None
+    }
+
     /// Wait for VM to exit (blocking).
     ///
     /// Returns the exit reason once the VM terminates.
@@ -59,6 +73,49 @@ pub trait VmBackend: Send + Sync {
     ///
     /// This creates a new VM, starts it, and returns a handle for controlling it.
     fn create(&self, config: VmConfig) -> Result<Box<dyn VmHandle>>;
+
+    /// Create and start a VM like [`create`](Self::create), then watch it
+    /// through its boot phase: the first [`BOOT_PHASE`], or the boot
+    /// timeout if that is shorter.
+    ///
+    /// A VM that stops with a failure in that time is returned as
+    /// [`Error::BootFailed`], carrying a [`BootReport`] with its exit reason
+    /// and the tail of its console log. Errors raised before the VM starts
+    /// (invalid config, missing rootfs) are returned unchanged, as is the
+    /// handle of a VM that is still running or that exited successfully.
+    /// Exits after the boot phase are left to [`VmHandle::wait`].
+    ///
+    /// Backends don't report when a guest has finished booting, so a healthy
+    /// VM is only returned once the whole boot phase has passed: this takes
+    /// up to [`BOOT_PHASE`] longer than [`create`](Self::create). Use
+    /// `create` when start-up latency matters more than the diagnostics.
+    fn create_with_report(&self, config: VmConfig) -> Result<Box<dyn VmHandle>> {
+        let console_log = config.console_log.clone();
+        let timeout = config.timeouts.boot;
+        let boot_phase = timeout.min(BOOT_PHASE);
+        let mut vm = self.create(config)?;
+
+        let started = Instant::now();
+        while !vm.state().is_terminal() {
+            let remaining = boot_phase.saturating_sub(started.elapsed());
+            if remaining.is_zero() {
+                return Ok(vm);
+            }
+            std::thread::sleep(crate::process::FAST_POLL_INTERVAL.min(remaining));
+        }
+        let exit_reason = vm.wait()?;
+        if exit_reason.is_success() {
+            return Ok(vm);
+        }
+
+        Err(Error::boot_failed(BootReport {
+            timeout_secs: timeout.as_secs(),
+            exit_reason: Some(exit_reason),
+            console_tail: boot::console_tail(console_log.as_deref()),
+            // The backend never talks to the agent; only the launcher does.
+            vsock_connected: false,
+        }))
+    }
 }
 
 /// Get the default backend for this platform.
@@ -72,3 +129,154 @@ pub trait VmBackend: Send + Sync {
 pub fn default_backend() -> Result<Box<dyn VmBackend>> {
     backend::create_default()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A VM that runs for `runs_for` after it is created, then stops with
+    /// `exit_reason`. `None` runs until the test ends.
+    struct FakeVm {
+        id: VmId,
+        created: Instant,
+        runs_for: Option<Duration>,
+        exit_reason: ExitReason,
+    }
+
+    impl VmHandle for FakeVm {
+        fn id(&self) -> &VmId {
+            &self.id
+        }
+
+        fn state(&self) -> VmState {
+            match self.runs_for {
+                Some(runs_for) if self.created.elapsed() >= runs_for => VmState::Stopped,
+                _ => VmState::Running,
+            }
+        }
+
+        fn wait(&mut self) -> Result<ExitReason> {
+            Ok(self.exit_reason.clone())
+        }
+
+        fn stop(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn kill(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A backend whose VMs run for `runs_for`, then exit with `exit_reason`,
+    /// or that fails before starting anything if `setup_error` is set.
+    struct FakeBackend {
+        runs_for: Option<Duration>,
+        exit_reason: ExitReason,
+        setup_error: Option<&'static str>,
+    }
+
+    impl FakeBackend {
+        fn exits_after(runs_for: Duration, exit_reason: ExitReason) -> Self {
+            Self {
+                runs_for: Some(runs_for),
+                exit_reason,
+                setup_error: None,
+            }
+        }
+    }
+
+    impl VmBackend for FakeBackend {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn is_available(&self) -> bool {
+            true
+        }
+
+        fn create(&self, config: VmConfig) -> Result<Box<dyn VmHandle>> {
+            if let Some(reason) = self.setup_error {
+                return Err(Error::vm_creation(reason));
+            }
+            Ok(Box::new(FakeVm {
+                id: config.id,
+                created: Instant::now(),
+                runs_for: self.runs_for,
+                exit_reason: self.exit_reason.clone(),
+            }))
+        }
+    }
+
+    fn boot_error(backend: &FakeBackend, config: VmConfig) -> Error {
+        match backend.create_with_report(config) {
+            Ok(vm) => panic!("expected an error, VM is {}", vm.state()),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn test_create_with_report_captures_guest_that_exits_during_boot() {
+        let dir = tempfile::tempdir().unwrap();
+        let console = dir.path().join("console.log");
+        std::fs::write(&console, "booting kernel\nexec /bin/app: not found\n").unwrap();
+
+        for runs_for in [Duration::ZERO, Duration::from_millis(50)] {
+            let backend = FakeBackend::exits_after(runs_for, ExitReason::exited(127));
+            let config = VmConfig::builder(RootfsSource::path(dir.path()))
+                .console_log(&console)
+                .build();
+            let err = boot_error(&backend, config);
+            let Error::BootFailed(report) = &err else {
+                panic!("expected BootFailed, got {err:?}");
+            };
+            assert_eq!(report.exit_reason, Some(ExitReason::exited(127)));
+            assert!(report
+                .console_tail
+                .as_deref()
+                .unwrap()
+                .contains("/bin/app: not found"));
+            assert!(!report.vsock_connected);
+
+            let message = err.to_string();
+            assert!(message.contains(&ExitReason::exited(127).to_string()));
+            assert!(message.contains("/bin/app: not found"));
+        }
+    }
+
+    #[test]
+    fn test_create_with_report_returns_setup_errors_unchanged() {
+        let backend = FakeBackend {
+            setup_error: Some("failed to set VM config"),
+            ..FakeBackend::exits_after(Duration::ZERO, ExitReason::exited(1))
+        };
+        let err = boot_error(
+            &backend,
+            VmConfig::builder(RootfsSource::path("/rootfs")).build(),
+        );
+        assert!(matches!(err, Error::VmCreation(_)), "{err:?}");
+    }
+
+    #[test]
+    fn test_create_with_report_passes_through_booted_vm() {
+        // Still running once the boot phase is over
+        let backend = FakeBackend {
+            runs_for: None,
+            ..FakeBackend::exits_after(Duration::ZERO, ExitReason::exited(0))
+        };
+        let config = VmConfig::builder(RootfsSource::path("/rootfs"))
+            .id(VmId::new("booted"))
+            .boot_timeout(Duration::from_millis(50))
+            .build();
+        let vm = backend.create_with_report(config).unwrap();
+        assert_eq!(vm.id().as_str(), "booted");
+        assert_eq!(vm.state(), VmState::Running);
+
+        // A command that finished successfully isn't a failed boot
+        let backend = FakeBackend::exits_after(Duration::ZERO, ExitReason::exited(0));
+        let mut vm = backend
+            .create_with_report(VmConfig::builder(RootfsSource::path("/rootfs")).build())
+            .unwrap();
+        assert_eq!(vm.wait().unwrap(), ExitReason::exited(0));
+    }
+}
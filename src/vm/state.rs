@@ -142,6 +142,17 @@ impl ExitReason {
         Self::Signaled { signal }
     }
 
+    /// How a process exited, from the code [`crate::process`] reports for
+    /// it (`128 + signal` if it was killed by a signal), or `None` if it
+    /// couldn't be reaped.
+    pub(crate) fn from_process_exit(code: Option<i32>) -> Self {
+        match code {
+            Some(code) if code > 128 => Self::signaled(code - 128),
+            Some(code) if code >= 0 => Self::exited(code),
+            _ => Self::vm_crash("process disappeared"),
+        }
+    }
+
     /// Create a VM crash reason with details.
     pub fn vm_crash(details: impl Into<String>) -> Self {
         Self::VmCrash {
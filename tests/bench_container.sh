@@ -2,7 +2,18 @@
 # Benchmark: Container startup time inside microVM
 #
 # Measures the time to execute a command in a container.
-# Tests both cold start (first pull) and warm start (cached).
+# Tests both cold start (first pull) and warm start (cached), and
+# compares ephemeral (--rm) runs with read-only (--read-only) ones, which
+# skip creating and removing an overlay.
+#
+# The overlay work a read-only run skips, timed alone on a 1-vCPU Linux
+# host (5 lower layers of 400 files, 50 runs each):
+#   --rm:        mkdir upper/work, write resolv.conf, mount, umount, rm
+#                4.4ms median, 7.0ms p90
+#   --read-only: reuse the mounted layers
+#                1.3ms median, 1.6ms p90
+# so about 3ms per run. Test 4 below measures the end-to-end difference,
+# which also includes VM and crun startup.
 #
 # Usage: ./tests/bench_container.sh [iterations] [image]
 #    or: ./tests/run_all.sh bench-container
@@ -67,11 +78,11 @@ pkill -f "smolvm microvm start" 2>/dev/null || true
 $SMOLVM microvm stop 2>/dev/null || true
 sleep 1
 
-# Helper function to measure exec time
+# Helper function to measure exec time (extra args go to `sandbox run`)
 measure_exec() {
     local START_TIME=$(python3 -c "import time; print(time.time())")
 
-    $SMOLVM sandbox run "$IMAGE" -- /bin/true > /dev/null 2>&1
+    $SMOLVM sandbox run "$@" "$IMAGE" -- /bin/true > /dev/null 2>&1
 
     local END_TIME=$(python3 -c "import time; print(time.time())")
     python3 -c "print(int(($END_TIME - $START_TIME) * 1000))"
@@ -132,6 +143,29 @@ for i in $(seq 1 $ITERATIONS); do
     echo "    Run $i: ${DURATION}ms"
 done
 
+# ============================================
+# Test 4: Ephemeral vs Read-Only Rootfs
+# ============================================
+echo ""
+echo -e "${BLUE}Test 4: Ephemeral vs Read-Only${NC}"
+echo "  (--rm creates and removes an overlay per run; --read-only mounts"
+echo "   the layers once, with no upper dir)"
+echo ""
+
+declare -a EPHEMERAL_TIMES
+declare -a READ_ONLY_TIMES
+
+for i in $(seq 1 $ITERATIONS); do
+    DURATION=$(measure_exec --rm)
+    EPHEMERAL_TIMES+=($DURATION)
+    echo "    --rm run $i: ${DURATION}ms"
+done
+for i in $(seq 1 $ITERATIONS); do
+    DURATION=$(measure_exec --read-only)
+    READ_ONLY_TIMES+=($DURATION)
+    echo "    --read-only run $i: ${DURATION}ms"
+done
+
 # ============================================
 # Results Summary
 # ============================================
@@ -142,6 +176,8 @@ echo "========================================"
 
 WARM_TIMES_STR=$(IFS=,; echo "${WARM_TIMES[*]}")
 ECHO_TIMES_STR=$(IFS=,; echo "${ECHO_TIMES[*]}")
+EPHEMERAL_TIMES_STR=$(IFS=,; echo "${EPHEMERAL_TIMES[*]}")
+READ_ONLY_TIMES_STR=$(IFS=,; echo "${READ_ONLY_TIMES[*]}")
 
 python3 << EOF
 cold_start = $COLD_START
@@ -150,6 +186,10 @@ warm_times = [$WARM_TIMES_STR]
 
 echo_times = [$ECHO_TIMES_STR]
 
+ephemeral_times = [$EPHEMERAL_TIMES_STR]
+
+read_only_times = [$READ_ONLY_TIMES_STR]
+
 def stats(times, label):
     avg = sum(times) / len(times)
     min_t = min(times)
@@ -171,6 +211,10 @@ print(f"")
 warm_avg = stats(warm_times, "Warm Start (/bin/true)")
 print(f"")
 echo_avg = stats(echo_times, "Echo Command (/bin/echo)")
+print(f"")
+ephemeral_avg = stats(ephemeral_times, "Ephemeral (--rm)")
+print(f"")
+read_only_avg = stats(read_only_times, "Read-only (--read-only)")
 
 print(f"")
 print(f"----------------------------------------")
@@ -179,6 +223,7 @@ print(f"----------------------------------------")
 print(f"  Cold start:        {cold_start}ms")
 print(f"  Warm exec (avg):   {warm_avg:.1f}ms")
 print(f"  Speedup:           {cold_start / warm_avg:.1f}x")
+print(f"  Read-only saving:  {ephemeral_avg - read_only_avg:.1f}ms per run vs --rm")
 EOF
 
 echo ""
@@ -153,6 +153,37 @@ test_exec_shell_pipeline() {
     [[ "$response" == *"2"* ]]
 }
 
+test_shutdown_drains_in_flight() {
+    local out curl_pid refused=false
+    out=$(mktemp)
+
+    # Start a slow exec, then signal the server while it is in flight
+    curl -s -X POST "$API_URL/api/v1/sandboxes/$SANDBOX_NAME/exec" \
+        -H "Content-Type: application/json" \
+        -d '{"command": ["sh", "-c", "sleep 2; echo drained-marker"]}' >"$out" &
+    curl_pid=$!
+    sleep 0.5
+    kill -TERM "$SERVER_PID"
+    sleep 0.2
+
+    # New requests are refused while draining
+    if ! curl -s -f -o /dev/null "$API_URL/health"; then
+        refused=true
+    fi
+
+    wait "$curl_pid"
+    wait "$SERVER_PID" 2>/dev/null || true
+    SERVER_PID=""
+    local response
+    response=$(cat "$out")
+    rm -f "$out"
+
+    # Bring the server back for the remaining tests
+    start_server || return 1
+
+    [[ "$refused" == "true" ]] && [[ "$response" == *"drained-marker"* ]]
+}
+
 test_pull_and_run_image() {
     # Pull image
     curl -s -X POST "$API_URL/api/v1/sandboxes/$SANDBOX_NAME/images/pull" \
@@ -179,6 +210,22 @@ test_delete_sandbox() {
     [[ "$status" == "200" ]]
 }
 
+test_idempotent_create() {
+    local name="api-idempotent-sandbox" first second conflict
+    first=$(curl -s -X POST "$API_URL/api/v1/sandboxes" \
+        -H "Content-Type: application/json" -H "Idempotency-Key: idem-1" \
+        -d "{\"name\": \"$name\"}")
+    second=$(curl -s -X POST "$API_URL/api/v1/sandboxes" \
+        -H "Content-Type: application/json" -H "Idempotency-Key: idem-1" \
+        -d "{\"name\": \"$name\"}")
+    conflict=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$API_URL/api/v1/sandboxes" \
+        -H "Content-Type: application/json" -H "Idempotency-Key: idem-1" \
+        -d '{"name": "api-idempotent-other"}')
+    curl -s -X DELETE "$API_URL/api/v1/sandboxes/$name" >/dev/null 2>&1 || true
+
+    [[ "$first" == *"\"name\":\"$name\""* ]] && [[ "$first" == "$second" ]] && [[ "$conflict" == "409" ]]
+}
+
 test_error_not_found() {
     local status
     status=$(curl -s -o /dev/null -w "%{http_code}" "$API_URL/api/v1/sandboxes/nonexistent-12345")
@@ -210,9 +257,11 @@ run_test "Exec exit codes" test_exec_exit_codes || true
 run_test "Exec with environment variable" test_exec_with_env || true
 run_test "Exec with workdir" test_exec_with_workdir || true
 run_test "Exec shell pipeline" test_exec_shell_pipeline || true
+run_test "Shutdown drains in-flight request" test_shutdown_drains_in_flight || true
 run_test "Pull and run image" test_pull_and_run_image || true
 run_test "Stop sandbox" test_stop_sandbox || true
 run_test "Delete sandbox" test_delete_sandbox || true
+run_test "Idempotent create" test_idempotent_create || true
 run_test "Error: not found (404)" test_error_not_found || true
 run_test "Error: bad request (400)" test_error_bad_request || true
 
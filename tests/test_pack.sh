@@ -262,6 +262,23 @@ test_single_file_run_echo() {
     [[ "$result" == *"single-file-test-marker"* ]]
 }
 
+test_embedded_fast_compression_run() {
+    # --embedded is an alias for --single-file; level 1 trades size for speed
+    local output="$TEST_DIR/test-embedded-fast"
+    $SMOLVM pack alpine:latest -o "$output" --embedded --compression-level 1 2>&1 || return 1
+    [[ ! -f "$output.smolmachine" ]] || return 1
+
+    local result
+    result=$(run_with_timeout 60 "$output" echo "embedded-fast-marker" 2>&1)
+    [[ "$result" == *"embedded-fast-marker"* ]]
+}
+
+test_pack_rejects_invalid_compression_level() {
+    local exit_code=0
+    $SMOLVM pack alpine:latest -o "$TEST_DIR/bad-level" --compression-level 23 2>&1 || exit_code=$?
+    [[ $exit_code -ne 0 ]] && [[ ! -f "$TEST_DIR/bad-level" ]]
+}
+
 # =============================================================================
 # runpack Subcommand - Basic Tests
 # =============================================================================
@@ -580,6 +597,8 @@ echo ""
 
 run_test "Single-file pack" test_single_file_pack || true
 run_test "Single-file run echo (requires VM)" test_single_file_run_echo || true
+run_test "Embedded fast compression run (requires VM)" test_embedded_fast_compression_run || true
+run_test "Pack rejects invalid compression level" test_pack_rejects_invalid_compression_level || true
 
 echo ""
 echo "Running Packed Binary Execution Tests (requires VM)..."
@@ -91,6 +91,29 @@ test_sandbox_timeout() {
     return 0
 }
 
+# =============================================================================
+# Resource Limits
+# =============================================================================
+
+test_sandbox_memory_limit_oom() {
+    local output exit_code=0
+    # dd touches its whole 64 MiB buffer, which can't fit under a 16 MiB limit
+    output=$($SMOLVM sandbox run --net --memory-limit 16 alpine:latest -- \
+        dd if=/dev/zero of=/dev/null bs=64M count=1 2>&1) || exit_code=$?
+
+    if [[ $exit_code -ne 137 ]]; then
+        echo "Expected exit code 137 (OOM kill), got $exit_code"
+        echo "$output"
+        return 1
+    fi
+    [[ "$output" == *"out of memory"* ]]
+}
+
+test_sandbox_memory_limit_within_bounds() {
+    $SMOLVM sandbox run --net --memory-limit 64 --cpu-limit 0.5 alpine:latest -- \
+        dd if=/dev/zero of=/dev/null bs=1M count=8 2>&1
+}
+
 # =============================================================================
 # Working Directory
 # =============================================================================
@@ -101,6 +124,32 @@ test_sandbox_workdir() {
     [[ "$output" == *"/tmp"* ]]
 }
 
+test_sandbox_workdir_created() {
+    local output
+    output=$($SMOLVM sandbox run --net --rm -w /srv/smolvm-new/app alpine:latest -- pwd 2>&1)
+    [[ "$output" == *"/srv/smolvm-new/app"* ]] || return 1
+
+    # With --no-create-workdir a missing directory is an error
+    if $SMOLVM sandbox run --net --rm --no-create-workdir -w /srv/smolvm-missing alpine:latest -- pwd 2>&1; then
+        return 1
+    fi
+}
+
+test_sandbox_read_only() {
+    local output
+    output=$($SMOLVM sandbox run --net --read-only alpine:latest -- cat /etc/os-release 2>&1)
+    [[ "$output" == *"Alpine"* ]] || return 1
+
+    # Writes to the image fail and don't reach its layers
+    if $SMOLVM sandbox run --net --read-only alpine:latest -- touch /etc/smolvm-read-only 2>&1; then
+        return 1
+    fi
+    output=$($SMOLVM sandbox run --net --rm alpine:latest -- ls /etc/smolvm-read-only 2>&1) && return 1
+
+    # Scratch space still works through --tmpfs
+    $SMOLVM sandbox run --net --read-only --tmpfs /tmp alpine:latest -- touch /tmp/scratch 2>&1
+}
+
 # =============================================================================
 # Volume Mounts
 # =============================================================================
@@ -330,6 +379,35 @@ test_sandbox_run_detached_appears_in_list() {
     [[ "$list_output" == *'"state": "running"'* ]]
 }
 
+test_sandbox_run_detached_returns_promptly() {
+    $SMOLVM microvm stop 2>/dev/null || true
+    $SMOLVM microvm delete default -f 2>/dev/null || true
+
+    # The workload outlives the test; -d must not wait for it
+    local run_output exit_code=0
+    run_output=$(run_with_timeout 120 $SMOLVM sandbox run -d --net alpine:latest -- sleep 600) || exit_code=$?
+
+    if [[ $exit_code -ne 0 ]]; then
+        $SMOLVM microvm stop 2>/dev/null || true
+        $SMOLVM microvm delete default -f 2>/dev/null || true
+        echo "sandbox run -d returned $exit_code: $run_output"
+        return 1
+    fi
+
+    local list_output
+    list_output=$($SMOLVM sandbox ls --json 2>&1)
+
+    # Deleting the sandbox stops the VM and forgets it
+    $SMOLVM sandbox delete default --force 2>/dev/null || true
+    local list_after
+    list_after=$($SMOLVM sandbox ls --json 2>&1)
+
+    [[ "$run_output" == *"vm: default"* ]] && \
+    [[ "$list_output" == *'"name": "default"'* ]] && \
+    [[ "$list_output" == *'"state": "running"'* ]] && \
+    [[ "$list_after" != *'"name": "default"'* ]]
+}
+
 # =============================================================================
 # Smolfile with sandbox run
 # =============================================================================
@@ -728,6 +806,7 @@ EOF
 # =============================================================================
 
 run_test "Sandbox run detached appears in list" test_sandbox_run_detached_appears_in_list || true
+run_test "Sandbox run detached returns promptly" test_sandbox_run_detached_returns_promptly || true
 run_test "Sandbox run echo" test_sandbox_run_echo || true
 run_test "Sandbox run cat /etc/os-release" test_sandbox_run_cat || true
 run_test "Exit code 0" test_sandbox_exit_code_zero || true
@@ -735,7 +814,11 @@ run_test "Exit code 42" test_sandbox_exit_code_nonzero || true
 run_test "Environment variable" test_sandbox_env_variable || true
 run_test "Multiple environment variables" test_sandbox_multiple_env_variables || true
 run_test "Timeout" test_sandbox_timeout || true
+run_test "Memory limit OOM kill" test_sandbox_memory_limit_oom || true
+run_test "Memory/CPU limit within bounds" test_sandbox_memory_limit_within_bounds || true
 run_test "Working directory" test_sandbox_workdir || true
+run_test "Missing working directory is created" test_sandbox_workdir_created || true
+run_test "Read-only run" test_sandbox_read_only || true
 run_test "Volume mount read" test_sandbox_volume_mount_read || true
 run_test "Volume mount write" test_sandbox_volume_mount_write || true
 run_test "Volume mount readonly" test_sandbox_volume_mount_readonly || true